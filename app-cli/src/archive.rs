@@ -0,0 +1,373 @@
+//! Archive nodes (`daemon --role`) and `p2p-games archive`.
+//!
+//! See [`p2p_core::archive`] for the roles. Every client signs up for the
+//! mailbox when it starts listening, so archive nodes know whose inbox to
+//! follow; `archive fetch` and `archive deposit` use the replay role.
+
+use anyhow::{Result, bail};
+use p2p_core::{
+    archive::{ARCHIVE_TOPIC_NAME, ArchiveBody, Mailbox, ReplayArchive, Roles},
+    digest::{DigestSync, Digestible, SyncTable, TableDigest},
+    discovery::{DISCOVERY_TOPIC_NAME, Discovery, ResponderShield, RoomCache},
+    dm::inbox_topic_name,
+    game::GameRegistry,
+    history::{HistoryQuery, HistoryScope, HistoryStore},
+    matchlog::{MatchLog, MatchRecord},
+    middleware::RecentIds,
+    protocol::{DiscoveryBody, Envelope, Kind, Scope, make_envelope, now_ms},
+    session::{SessionState, data_dir},
+    signing, t,
+    timeouts::collect,
+    validation,
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle};
+
+use crate::{Ctx, Node, connect_host, sender_id};
+
+/// How often old rooms and held messages are dropped and the mailbox saved.
+const UPKEEP_EVERY: Duration = Duration::from_secs(60);
+
+/// `body` from `me`, for the archive topic.
+fn envelope(me: &str, body: ArchiveBody) -> Envelope<ArchiveBody> {
+    make_envelope(
+        Kind::Discovery,
+        Scope::Global,
+        None,
+        me.to_string(),
+        now_ms(),
+        body,
+    )
+}
+
+fn frame(me: &str, body: ArchiveBody) -> Vec<u8> {
+    signing::to_signed_vec(&envelope(me, body))
+}
+
+async fn join_archive(transport: &dyn GossipTransport) -> Result<Box<dyn TopicHandle>> {
+    transport
+        .join_topic(transport.topic_from_name(ARCHIVE_TOPIC_NAME))
+        .await
+}
+
+/// Ask archive nodes to hold our direct messages from now on and to send
+/// the ones that came while we were offline.
+pub(crate) async fn sign_up(transport: &dyn GossipTransport, me: &str) -> Result<()> {
+    let newest = HistoryQuery {
+        scope: Some(HistoryScope::Dm),
+        room: Some(me.to_string()),
+        limit: 1,
+        ..Default::default()
+    };
+    let since = HistoryStore::open()
+        .query(&newest)?
+        .first()
+        .map_or(0, |e| e.ts);
+    let th = join_archive(transport).await?;
+    th.publish(&frame(me, ArchiveBody::Mailbox { since })).await
+}
+
+/// The inbox of a signed-up peer: frames arriving there go to the archive
+/// loop, held ones are published through `publish`.
+struct FollowedInbox {
+    publish: mpsc::Sender<Vec<u8>>,
+    task: JoinHandle<()>,
+}
+
+impl FollowedInbox {
+    fn start(
+        peer_id: String,
+        mut th: Box<dyn TopicHandle>,
+        incoming: mpsc::Sender<(String, Vec<u8>)>,
+    ) -> Self {
+        let (publish, mut outgoing) = mpsc::channel::<Vec<u8>>(64);
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    b = th.next() => match b {
+                        Ok(b) => {
+                            if incoming.send((peer_id.clone(), b)).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            tracing::debug!("inbox of {peer_id}: {e:#}");
+                            break;
+                        }
+                    },
+                    Some(frame) = outgoing.recv() => {
+                        if let Err(e) = th.publish(&frame).await {
+                            tracing::debug!("inbox of {peer_id}: {e:#}");
+                        }
+                    }
+                }
+            }
+        });
+        Self { publish, task }
+    }
+}
+
+impl Drop for FollowedInbox {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn follow_inbox(
+    transport: &Node,
+    peer_id: &str,
+    incoming: &mpsc::Sender<(String, Vec<u8>)>,
+) -> Result<FollowedInbox> {
+    let th = transport
+        .join_topic(transport.topic_from_name(&inbox_topic_name(peer_id)))
+        .await?;
+    Ok(FollowedInbox::start(
+        peer_id.to_string(),
+        th,
+        incoming.clone(),
+    ))
+}
+
+/// Serve `roles` to other peers. Returns only when a topic fails.
+pub(crate) async fn serve(transport: &Node, ctx: &Ctx, me: &str, roles: Roles) -> Result<()> {
+    let cfg = &ctx.config.archive;
+    let discovery = Discovery::new(transport, ctx.config.timeouts);
+    let mut announcements = transport
+        .join_topic(transport.topic_from_name(DISCOVERY_TOPIC_NAME))
+        .await?;
+    let mut requests = join_archive(transport).await?;
+    let mut rooms = RoomCache::default();
+    let mut shield = ResponderShield::default();
+    let mut sync = DigestSync::default();
+    let mut recent = RecentIds::default();
+    let replays = ReplayArchive::open();
+    let registry = GameRegistry::builtin();
+    let mut mailbox = if roles.mailbox {
+        Mailbox::load().unwrap_or_default()
+    } else {
+        Mailbox::default()
+    };
+    let (incoming, mut held) = mpsc::channel(64);
+    let mut inboxes = HashMap::new();
+    for peer_id in mailbox.peers() {
+        inboxes.insert(
+            peer_id.to_string(),
+            follow_inbox(transport, peer_id, &incoming).await?,
+        );
+    }
+    let mut upkeep = tokio::time::interval(UPKEEP_EVERY);
+    let digest_every = ctx.config.sync.digest_every().filter(|_| roles.snapshots);
+    let mut digests = tokio::time::interval(digest_every.unwrap_or(UPKEEP_EVERY));
+    loop {
+        // Nothing scheduled: wake up now and then anyway.
+        let wake = shield
+            .next_due()
+            .unwrap_or_else(|| Instant::now() + UPKEEP_EVERY);
+        tokio::select! {
+            b = announcements.next() => {
+                let Some(env) = signing::open::<DiscoveryBody>(&b?)
+                    .filter(|e| e.sender_id != me && recent.insert(&e.msg_id))
+                else {
+                    continue;
+                };
+                rooms.apply(&env);
+                let now = Instant::now();
+                match &env.body {
+                    DiscoveryBody::ListRoomsReq if roles.rooms => {
+                        shield.request(&env, now);
+                    }
+                    DiscoveryBody::ListRoomsRes {
+                        rooms: listed,
+                        req_id: Some(req_id),
+                    } => shield.answered(req_id, listed),
+                    DiscoveryBody::Digest {
+                        table: SyncTable::Rooms,
+                        digest,
+                    } if roles.snapshots => {
+                        let ours = rooms.digest();
+                        let peer = &env.sender_id;
+                        if let Some(buckets) =
+                            sync.on_digest(peer, SyncTable::Rooms, digest, &ours, now)
+                        {
+                            discovery
+                                .sync_request(me, peer, SyncTable::Rooms, buckets)
+                                .await?;
+                        }
+                    }
+                    DiscoveryBody::SyncReq {
+                        peer_id,
+                        table: SyncTable::Rooms,
+                        buckets,
+                    } if roles.snapshots
+                        && peer_id == me
+                        && sync.on_request(&env.sender_id, SyncTable::Rooms, now) =>
+                    {
+                        let found = rooms.in_buckets(buckets);
+                        if !found.is_empty() {
+                            discovery.sync_rooms(me, &env.msg_id, found).await?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ = tokio::time::sleep_until(wake.into()) => {
+                for (req_id, listed) in shield.due(Instant::now(), &rooms.list()) {
+                    discovery.sync_rooms(me, &req_id, listed).await?;
+                }
+            }
+            b = requests.next() => {
+                let Some(env) = signing::open::<ArchiveBody>(&b?)
+                    .filter(|e| e.sig.is_some() && e.sender_id != me && recent.insert(&e.msg_id))
+                else {
+                    continue;
+                };
+                let peer = &env.sender_id;
+                match env.body {
+                    ArchiveBody::Mailbox { since } if roles.mailbox => {
+                        if !mailbox.sign_up(peer, cfg) {
+                            continue;
+                        }
+                        if !inboxes.contains_key(peer) {
+                            connect_host(transport, ctx, peer).await?;
+                            inboxes.insert(peer.clone(), follow_inbox(transport, peer, &incoming).await?);
+                        }
+                        let frames = mailbox.collect(peer, since);
+                        tracing::debug!("{peer} signed up, {} held messages", frames.len());
+                        for frame in frames {
+                            inboxes[peer].publish.send(frame).await?;
+                        }
+                        let _ = mailbox.save();
+                    }
+                    ArchiveBody::Fetch { game_id } if roles.replays => {
+                        if let Some(record) = replays.find(&game_id) {
+                            let body = ArchiveBody::Record {
+                                req_id: env.msg_id,
+                                record,
+                            };
+                            requests.publish(&frame(me, body)).await?;
+                        }
+                    }
+                    ArchiveBody::Deposit { record } if roles.replays => {
+                        if matches!(replays.deposit(peer, &record, &registry), Ok(true)) {
+                            tracing::debug!("{peer} deposited match {}", record.game_id);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Some((owner, b)) = held.recv() => {
+                let Some(env) = ctx.wire.decode::<Value>(&b) else {
+                    continue;
+                };
+                if matches!(env.kind, Kind::Direct)
+                    && env.sig.is_some()
+                    && env.sender_id != owner
+                    && env.room_id.as_deref() == Some(owner.as_str())
+                    && mailbox.hold(&owner, &env.msg_id, env.ts, &b, cfg)
+                {
+                    let _ = mailbox.save();
+                }
+            }
+            _ = upkeep.tick() => {
+                rooms.prune(cfg.room_ttl_ms());
+                rooms.keep_newest(cfg.max_rooms);
+                for peer_id in mailbox.expire(cfg) {
+                    inboxes.remove(&peer_id);
+                }
+                if roles.mailbox {
+                    let _ = mailbox.save();
+                }
+            }
+            _ = digests.tick(), if digest_every.is_some() => {
+                let entries = rooms.entries();
+                if !entries.is_empty() {
+                    let digest = TableDigest::of(entries);
+                    discovery.digest(me, SyncTable::Rooms, digest).await?;
+                }
+            }
+        }
+    }
+}
+
+/// `archive fetch`: ask archive nodes for the record of `game_id` and add
+/// it to `fetched.jsonl`.
+pub(crate) async fn fetch(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &SessionState,
+    game_id: &str,
+) -> Result<()> {
+    let me = sender_id(transport, session);
+    let mut th = join_archive(transport).await?;
+    let request = envelope(
+        &me,
+        ArchiveBody::Fetch {
+            game_id: game_id.to_string(),
+        },
+    );
+    th.publish(&signing::to_signed_vec(&request)).await?;
+    let registry = GameRegistry::builtin();
+    let mut found: Option<MatchRecord> = None;
+    let timeouts = &ctx.config.timeouts;
+    let window = timeouts.window(timeouts.list_rooms(), &*th);
+    collect(&mut th, window, |b| {
+        match signing::open::<ArchiveBody>(b).map(|e| e.body) {
+            // Archive nodes are not trusted with the outcome either.
+            Some(ArchiveBody::Record { req_id, record })
+                if req_id == request.msg_id
+                    && record.game_id == game_id
+                    && found.is_none()
+                    && validation::validate(&record, &registry).is_ok() =>
+            {
+                found = Some(record);
+                true
+            }
+            _ => false,
+        }
+    })
+    .await;
+    let Some(record) = found else {
+        bail!(t!("archive.not_found", game_id = game_id));
+    };
+    let path = data_dir().join("fetched.jsonl");
+    MatchLog::at(path.clone()).append(&record)?;
+    let text = t!(
+        "archive.fetched",
+        game_id = game_id,
+        game = record.game,
+        path = path.display()
+    );
+    println!("{}", ctx.out.success(&text));
+    Ok(())
+}
+
+/// `archive deposit`: hand the record of `game_id` from our match log to
+/// the archive nodes online.
+pub(crate) async fn deposit(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &SessionState,
+    game_id: &str,
+) -> Result<()> {
+    let Some(record) = MatchLog::open()
+        .load()?
+        .into_iter()
+        .find(|r| r.game_id == game_id)
+    else {
+        bail!(t!("archive.unknown_match", game_id = game_id));
+    };
+    let me = sender_id(transport, session);
+    let th = join_archive(transport).await?;
+    th.publish(&frame(&me, ArchiveBody::Deposit { record }))
+        .await?;
+    println!(
+        "{}",
+        ctx.out.success(&t!("archive.deposited", game_id = game_id))
+    );
+    Ok(())
+}
@@ -0,0 +1,146 @@
+//! `p2p-games bridge`: mirror chat between the rooms in `[[bridges]]`.
+
+use anyhow::{Result, bail};
+use p2p_core::{
+    bridge::{Bridge, BridgeEnd},
+    error::ProtocolError,
+    protocol::{ChatMsg, GLOBAL_CHAT_TOPIC_NAME, Kind, Scope, make_envelope, now_ms},
+    rooms::{RoomRef, TopicFeed},
+    session::SessionState,
+    t,
+};
+use std::collections::BTreeMap;
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle, TopicId};
+
+use crate::{Ctx, Node, connect_host, restore, sender_id, shutdown::Goodbye};
+
+/// Publishing side of one bridge end.
+struct End {
+    /// `room_id` of mirrored envelopes; `None` for a global chat.
+    room_id: Option<String>,
+    th: Box<dyn TopicHandle>,
+}
+
+/// Run every configured bridge until Ctrl-C.
+pub(crate) async fn run(transport: &Node, ctx: &Ctx, session: &mut SessionState) -> Result<()> {
+    let out = &ctx.out;
+    if ctx.config.bridges.is_empty() {
+        bail!(t!("bridge.none"));
+    }
+    restore::restore(transport, ctx, session).await?;
+    let me = sender_id(transport, session);
+
+    let mut feed = TopicFeed::default();
+    let mut ends: BTreeMap<String, End> = BTreeMap::new();
+    let mut rooms = Vec::new();
+    for cfg in &ctx.config.bridges {
+        for label in [&cfg.a, &cfg.b] {
+            if ends.contains_key(label) {
+                continue;
+            }
+            let (topic, room) = resolve(transport, ctx, session, label).await?;
+            // Receiving blocks its handle, so mirrors go out on a second one.
+            feed.add(Some(label.clone()), transport.join_topic(topic).await?);
+            let th = transport.join_topic(topic).await?;
+            th.publish(&ctx.wire.capabilities(me.clone())).await?;
+            let room_id = match (&room, BridgeEnd::parse(label)) {
+                (Some(r), _) => Some(r.topic_hex.clone()),
+                (None, BridgeEnd::Topic(hex)) => Some(hex),
+                (None, _) => None,
+            };
+            rooms.extend(room);
+            ends.insert(label.clone(), End { room_id, th });
+        }
+        println!(
+            "{}",
+            out.success(&t!("bridge.linked", a = cfg.a, b = cfg.b, ttl = cfg.ttl))
+        );
+    }
+    let mut bridges: Vec<Bridge> = ctx
+        .config
+        .bridges
+        .iter()
+        .cloned()
+        .map(Bridge::new)
+        .collect();
+
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        let (label, bytes) = tokio::select! {
+            b = feed.next() => b?,
+            _ = &mut interrupted => break,
+        };
+        let Some(label) = label else {
+            continue;
+        };
+        let Some(env) = ctx.wire.decode::<ChatMsg>(&bytes) else {
+            continue;
+        };
+        for bridge in &mut bridges {
+            let Some((to, msg)) = bridge.forward(&env, &label, &me) else {
+                continue;
+            };
+            let end = &ends[to];
+            let scope = if end.room_id.is_some() {
+                Scope::Room
+            } else {
+                Scope::Global
+            };
+            let mirror = make_envelope(
+                Kind::Chat,
+                scope,
+                end.room_id.clone(),
+                me.clone(),
+                now_ms(),
+                msg,
+            );
+            for frame in ctx.wire.encode(&mirror) {
+                end.th.publish(&frame).await?;
+            }
+        }
+    }
+
+    let goodbye = Goodbye {
+        rooms,
+        hosted: None,
+    };
+    goodbye.send(transport, ctx, &me).await;
+    Ok(())
+}
+
+/// Topic of a bridge end, plus the joined room it names, if any.
+async fn resolve(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &SessionState,
+    label: &str,
+) -> Result<(TopicId, Option<RoomRef>)> {
+    let end = BridgeEnd::parse(label);
+    if matches!(end, BridgeEnd::Global { .. }) && ctx.safe.hides_global_chat() {
+        bail!(t!("safe.global_hidden"));
+    }
+    Ok(match end {
+        BridgeEnd::Global { namespace: None } => {
+            (transport.topic_from_name(GLOBAL_CHAT_TOPIC_NAME), None)
+        }
+        BridgeEnd::Global {
+            namespace: Some(ns),
+        } => (
+            transport.inner().topic_in(&ns, GLOBAL_CHAT_TOPIC_NAME),
+            None,
+        ),
+        BridgeEnd::Topic(hex) => (transport.topic_from_hex(&hex)?, None),
+        BridgeEnd::Room(name) => {
+            let room =
+                session.rooms.get(&name).cloned().ok_or_else(|| {
+                    ProtocolError::NotFound(t!("bridge.unknown_room", room = name))
+                })?;
+            let me = sender_id(transport, session);
+            if let Some(addr) = room.host_addr.as_ref().filter(|a| **a != me) {
+                connect_host(transport, ctx, addr).await?;
+            }
+            (transport.topic_from_hex(&room.topic_hex)?, Some(room))
+        }
+    })
+}
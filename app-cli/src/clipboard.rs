@@ -0,0 +1,36 @@
+//! System clipboard access for long strings (tickets, node addresses).
+
+use anyhow::{Context, Result};
+use p2p_core::{output::Output, t};
+
+/// Put `text` on the system clipboard.
+///
+/// On X11 the selection is served by this process, so after a short-lived
+/// command exits the text survives only if a clipboard manager picked it up;
+/// we therefore keep serving it for a moment before returning.
+pub fn copy(text: &str) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new().context("clipboard unavailable")?;
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        use arboard::SetExtLinux;
+        use std::time::{Duration, Instant};
+        clipboard
+            .set()
+            .wait_until(Instant::now() + Duration::from_millis(500))
+            .text(text)
+            .context("could not copy to the clipboard")?;
+    }
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    clipboard
+        .set_text(text)
+        .context("could not copy to the clipboard")?;
+    Ok(())
+}
+
+/// Copy and report the outcome; failures are warnings, never fatal.
+pub fn copy_and_report(out: &Output, text: &str) {
+    match copy(text) {
+        Ok(()) => println!("{}", out.success(&t!("clipboard.copied"))),
+        Err(e) => println!("{}", out.warn(&t!("clipboard.failed", error = e))),
+    }
+}
@@ -0,0 +1,110 @@
+//! `p2p-games daemon`.
+
+use anyhow::{Result, bail};
+use p2p_core::{
+    archive::{Role, Roles},
+    config::{ConfigWatcher, Reload},
+    daemon::{self, DaemonTransport},
+    protocol::GLOBAL_CHAT_TOPIC_NAME,
+    session::SessionState,
+    t,
+};
+use std::time::Duration;
+use transport_iroh::transport_iroh::GossipTransport;
+
+use crate::{
+    Ctx, archive, listen_chat, restore, sender_id, shutdown::Goodbye, subscribe_rooms, subscribed,
+};
+
+/// How often `config.toml` is checked for edits.
+const CONFIG_POLL: Duration = Duration::from_secs(2);
+
+/// Apply edits of `config.toml` while the daemon runs; never returns.
+async fn follow_config(ctx: &Ctx) {
+    let out = &ctx.out;
+    let mut watcher = ConfigWatcher::default();
+    let mut poll = tokio::time::interval(CONFIG_POLL);
+    loop {
+        poll.tick().await;
+        match watcher.poll() {
+            Some(Reload::Applied { config, restart }) => {
+                ctx.reconfigure(&config);
+                println!("{}", out.info(&t!("daemon.config_reloaded")));
+                if !restart.is_empty() {
+                    let keys = restart.join(", ");
+                    println!("{}", out.warn(&t!("daemon.config_restart", keys = keys)));
+                }
+            }
+            Some(Reload::Rejected(e)) => {
+                let error = format!("{e:#}");
+                println!("{}", out.warn(&t!("daemon.config_rejected", error = error)));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Serve our endpoint to other commands and follow the joined rooms (and
+/// global chat) like `global listen` until Ctrl-C. Edits of `config.toml`
+/// are applied as they are saved. With `roles` (or `[archive] roles`) the
+/// node also serves others as an archive node.
+pub(crate) async fn run(ctx: &Ctx, session: &mut SessionState, roles: &[Role]) -> Result<()> {
+    if ctx.guest {
+        bail!(t!("daemon.guest"));
+    }
+    if DaemonTransport::attach().await.is_some() {
+        bail!(t!("daemon.running"));
+    }
+    let listener = daemon::listen().await?;
+    let endpoint = ctx.bind().await?;
+    let transport = ctx.node(endpoint.clone());
+    ctx.bootstrap(&transport).await;
+    let server = tokio::spawn(daemon::serve(listener, endpoint));
+    let me = sender_id(&transport, session);
+    println!(
+        "{}",
+        ctx.out.heading(&t!(
+            "daemon.started",
+            peer_id = me,
+            socket = daemon::socket_path().display()
+        ))
+    );
+
+    let roles = if roles.is_empty() {
+        Roles::of(&ctx.config.archive.roles)
+    } else {
+        Roles::of(roles)
+    };
+    if roles.any() {
+        let roles = roles.names().join(", ");
+        println!("{}", ctx.out.info(&t!("daemon.roles", roles = roles)));
+    }
+
+    restore::restore(&transport, ctx, session).await?;
+    let mut subs = subscribe_rooms(&transport, ctx, session).await?;
+    if !ctx.safe.hides_global_chat() {
+        let topic = transport.topic_from_name(GLOBAL_CHAT_TOPIC_NAME);
+        subs.insert(0, (None, transport.join_topic(topic).await?));
+    }
+    let goodbye = Goodbye {
+        rooms: subscribed(&subs, session),
+        hosted: None,
+    };
+    let archive = async {
+        if roles.any() {
+            archive::serve(&transport, ctx, &me, roles).await
+        } else {
+            std::future::pending().await
+        }
+    };
+    // Rooms and games stay up while edited settings are applied.
+    let res = tokio::select! {
+        res = listen_chat(&transport, subs, ctx, session, &me) => res,
+        () = follow_config(ctx) => Ok(()),
+        res = archive => res,
+    };
+    server.abort();
+    let _ = std::fs::remove_file(daemon::socket_path());
+    goodbye.send(&transport, ctx, &me).await;
+    res
+}
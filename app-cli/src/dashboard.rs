@@ -0,0 +1,132 @@
+//! `p2p-games stats <game> [--vs <nick>]`: results, openings and streaks
+//! in one game, from the match log (see [`p2p_core::dashboard`]).
+
+use anyhow::{Result, bail};
+use p2p_core::{
+    dashboard::{GameStats, Tally},
+    directory::PeerDirectory,
+    dispute::DisputeBook,
+    game::GameRegistry,
+    matchlog::{MatchLog, MatchRecord},
+    output::Output,
+    session::SessionState,
+    t, validation,
+    widgets::{ScoreRow, ScoreTable},
+};
+
+use crate::{moderation::resolve_peer, print_json};
+
+/// Statistics of `game` for our identity, against `vs` only if given.
+pub(crate) fn load(session: &SessionState, game: &str, vs: Option<&str>) -> Result<GameStats> {
+    let registry = GameRegistry::builtin();
+    if !registry.knows(game) {
+        bail!(t!("game.unknown", game = game));
+    }
+    let matches = MatchLog::open().load()?;
+    let disputes = DisputeBook::load().unwrap_or_default();
+    let opponent = vs.map(resolve_peer);
+    Ok(GameStats::build(
+        game,
+        &session.peer_id,
+        opponent.as_deref(),
+        &matches,
+        &registry,
+        |m: &MatchRecord| validation::counts(m, &registry, &disputes),
+    ))
+}
+
+/// Title of the statistics, e.g. `tictactoe against alice`.
+pub(crate) fn title(stats: &GameStats, directory: &PeerDirectory) -> String {
+    match &stats.opponent {
+        Some(peer_id) => t!(
+            "dashboard.title_vs",
+            game = stats.game,
+            player = name(directory, peer_id)
+        ),
+        None => t!("dashboard.title", game = stats.game),
+    }
+}
+
+/// One row per opponent and a highlighted total.
+pub(crate) fn table(stats: &GameStats, directory: &PeerDirectory) -> ScoreTable {
+    let columns = ["wins", "losses", "draws", "aborted", "games", "avg_moves"]
+        .iter()
+        .map(|c| t!(&format!("dashboard.col.{c}")))
+        .collect();
+    let row = |player: String, tally: &Tally, highlight| ScoreRow {
+        player,
+        values: vec![
+            tally.wins.to_string(),
+            tally.losses.to_string(),
+            tally.draws.to_string(),
+            tally.aborted.to_string(),
+            tally.games().to_string(),
+            format!("{:.1}", tally.avg_moves()),
+        ],
+        highlight,
+    };
+    let mut rows: Vec<ScoreRow> = stats
+        .opponents
+        .iter()
+        .map(|(peer_id, tally)| row(name(directory, peer_id), tally, false))
+        .collect();
+    if stats.opponents.len() > 1 {
+        rows.push(row(t!("dashboard.total"), &stats.total, true));
+    }
+    ScoreTable { columns, rows }
+}
+
+/// The streak lines below the table.
+pub(crate) fn streaks(stats: &GameStats) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(s) = stats.streak {
+        let finish = t!(&format!("dashboard.finish.{}", s.finish.key()));
+        lines.push(t!("dashboard.streak", count = s.len, finish = finish));
+    }
+    lines.push(t!(
+        "dashboard.longest_win_streak",
+        count = stats.longest_win_streak
+    ));
+    lines
+}
+
+pub(crate) fn show(
+    out: &Output,
+    session: &SessionState,
+    game: &str,
+    vs: Option<&str>,
+) -> Result<()> {
+    let stats = load(session, game, vs)?;
+    if out.is_json() {
+        return print_json(&stats);
+    }
+    let directory = PeerDirectory::load().unwrap_or_default();
+    println!("{}", out.heading(&title(&stats, &directory)));
+    if stats.total.games() == 0 {
+        println!("{}", out.info(&t!("dashboard.none")));
+        return Ok(());
+    }
+    println!("{}", out.score_table(&table(&stats, &directory)));
+    for line in streaks(&stats) {
+        println!("{line}");
+    }
+    if !stats.openings.is_empty() {
+        println!("{}", t!("dashboard.openings"));
+        let rows: Vec<(String, u64)> = stats
+            .openings
+            .iter()
+            .map(|(mv, n)| (mv.clone(), u64::from(*n)))
+            .collect();
+        println!("{}", out.bar_chart(&rows));
+    }
+    Ok(())
+}
+
+/// Nickname of `peer_id` from the directory, or a short form of the id.
+pub(crate) fn name(directory: &PeerDirectory, peer_id: &str) -> String {
+    directory
+        .peers
+        .get(peer_id)
+        .and_then(|p| p.nickname.clone())
+        .unwrap_or_else(|| peer_id.chars().take(8).collect())
+}
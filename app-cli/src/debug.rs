@@ -0,0 +1,217 @@
+//! `p2p-games debug tail <topic>` and `p2p-games debug replay`.
+
+use anyhow::{Result, bail};
+use p2p_core::{
+    dispute::{DisputeBook, DisputeStatus},
+    game::GameRegistry,
+    matchlog::MatchLog,
+    middleware::{Compress, Middleware},
+    protocol::Envelope,
+    signing, t,
+    wire::parse_frame,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{fmt::Write, path::PathBuf};
+use transport_iroh::transport_iroh::GossipTransport;
+
+use crate::{Ctx, Node, print_json};
+
+/// Bytes of an undecodable frame shown in the hexdump.
+const HEXDUMP_MAX: usize = 512;
+
+/// Subscribe to `topic` (a topic name, or 64 hex digits) and print every
+/// frame until Ctrl-C.
+pub(crate) async fn tail(transport: &Node, ctx: &Ctx, topic: &str) -> Result<()> {
+    let id = if topic.len() == 64 && topic.chars().all(|c| c.is_ascii_hexdigit()) {
+        transport.topic_from_hex(topic)?
+    } else {
+        transport.topic_from_name(topic)
+    };
+    let mut th = transport.join_topic(id).await?;
+    println!(
+        "{}",
+        ctx.out.heading(&t!(
+            "debug.tailing",
+            topic = topic,
+            hex = transport.topic_to_hex(&id)
+        ))
+    );
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        let bytes = tokio::select! {
+            b = th.next() => b?,
+            _ = &mut interrupted => return Ok(()),
+        };
+        if ctx.out.is_json() {
+            print_json(&frame_json(&bytes))?;
+        } else {
+            println!("{}", describe(&bytes));
+        }
+    }
+}
+
+/// Check every recorded replay in `file` (default: our match log) against
+/// the current game engines; fails if any of them no longer matches.
+pub(crate) fn replay(ctx: &Ctx, file: Option<PathBuf>) -> Result<()> {
+    // Disputes are about our own matches only.
+    let mut disputes = file
+        .is_none()
+        .then(|| DisputeBook::load().unwrap_or_default());
+    let log = file.map_or_else(MatchLog::open, MatchLog::at);
+    let registry = GameRegistry::builtin();
+    let (mut checked, mut failed, mut skipped) = (0, 0, 0);
+    for record in log.load()? {
+        if let Some(status) = disputes
+            .as_mut()
+            .and_then(|d| d.revalidate(&record, &registry))
+        {
+            let key = match status {
+                DisputeStatus::Open => "debug.dispute_open",
+                DisputeStatus::Revalidated => "debug.dispute_revalidated",
+                DisputeStatus::Invalid => "debug.dispute_invalid",
+            };
+            println!("{}", ctx.out.info(&t!(key, game_id = record.game_id)));
+        }
+        let Some(replay) = &record.replay else {
+            skipped += 1;
+            continue;
+        };
+        checked += 1;
+        let result = replay.verify(&registry, &record.game, record.players.len());
+        if result.is_err() {
+            failed += 1;
+        }
+        if ctx.out.is_json() {
+            print_json(&json!({
+                "game_id": record.game_id,
+                "game": record.game,
+                "moves": replay.moves.len(),
+                "ok": result.is_ok(),
+                "error": result.as_ref().err().map(|e| format!("{e:#}")),
+            }))?;
+            continue;
+        }
+        let line = match result {
+            Ok(()) => ctx.out.success(&t!(
+                "debug.replay_ok",
+                game = record.game,
+                game_id = record.game_id,
+                moves = replay.moves.len()
+            )),
+            Err(e) => ctx.out.error(&t!(
+                "debug.replay_failed",
+                game = record.game,
+                game_id = record.game_id,
+                error = format!("{e:#}")
+            )),
+        };
+        println!("{line}");
+    }
+    println!(
+        "{}",
+        ctx.out.info(&t!(
+            "debug.replay_summary",
+            checked = checked,
+            failed = failed,
+            skipped = skipped
+        ))
+    );
+    if let Some(disputes) = disputes {
+        disputes.save()?;
+    }
+    if failed > 0 {
+        bail!(t!("debug.replay_mismatch", count = failed));
+    }
+    Ok(())
+}
+
+/// Summary line plus pretty JSON of an envelope, or a hexdump.
+fn describe(bytes: &[u8]) -> String {
+    let Some((ver, json)) = parse_frame(bytes) else {
+        return hexdump(bytes);
+    };
+    let Ok(mut v) = serde_json::from_slice::<Value>(json) else {
+        return hexdump(bytes);
+    };
+    // Inflate compressed bodies so they can be read.
+    let inflated = Compress.receive(&mut v);
+    let signature = match signature(&v) {
+        "unsigned" => t!("debug.unsigned"),
+        "valid" => t!("debug.signed"),
+        _ => t!("debug.bad_signature"),
+    };
+    let mut out = match Envelope::<Value>::deserialize(&v) {
+        Ok(env) => t!(
+            "debug.envelope",
+            ver = ver,
+            kind = format!("{:?}", env.kind),
+            sender = env.sender_id,
+            ts = env.ts,
+            size = bytes.len(),
+            signature = signature
+        ),
+        Err(_) => t!("debug.json", ver = ver, size = bytes.len()),
+    };
+    if !inflated {
+        out.push('\n');
+        out.push_str(&t!("debug.bad_compression"));
+    }
+    out.push('\n');
+    out.push_str(&serde_json::to_string_pretty(&v).unwrap_or_default());
+    out
+}
+
+/// `unsigned`, `valid` or `invalid`.
+fn signature(v: &Value) -> &'static str {
+    if v.get("sig").is_none() {
+        "unsigned"
+    } else if signing::accept(v) {
+        "valid"
+    } else {
+        "invalid"
+    }
+}
+
+/// The frame as one JSON object, for `--json`.
+fn frame_json(bytes: &[u8]) -> Value {
+    let decoded = parse_frame(bytes)
+        .and_then(|(ver, json)| Some((ver, serde_json::from_slice::<Value>(json).ok()?)));
+    let Some((ver, mut v)) = decoded else {
+        let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        return json!({ "size": bytes.len(), "hex": hex });
+    };
+    let inflated = Compress.receive(&mut v);
+    json!({
+        "size": bytes.len(),
+        "ver": ver,
+        "signature": signature(&v),
+        "inflated": inflated,
+        "envelope": v,
+    })
+}
+
+/// Offset, hex and printable bytes, 16 per line.
+fn hexdump(bytes: &[u8]) -> String {
+    let mut out = t!("debug.undecodable", size = bytes.len());
+    for (i, chunk) in bytes.chunks(16).take(HEXDUMP_MAX / 16).enumerate() {
+        let _ = write!(out, "\n{:08x}  ", i * 16);
+        for b in chunk {
+            let _ = write!(out, "{b:02x} ");
+        }
+        out.push_str(&"   ".repeat(16 - chunk.len()));
+        out.push(' ');
+        out.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+    }
+    if bytes.len() > HEXDUMP_MAX {
+        out.push_str("\n…");
+    }
+    out
+}
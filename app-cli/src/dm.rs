@@ -0,0 +1,149 @@
+//! `p2p-games dm <peer> <text>` and `room game invite <peer> <game>`.
+
+use anyhow::{Result, bail};
+use p2p_core::{
+    dm::{INVITE_TTL, InviteBody, inbox_topic_name, make_direct, make_invite},
+    error::ProtocolError,
+    game::{GameKind, GameRegistry},
+    protocol::now_ms,
+    rooms::RoomTicket,
+    session::SessionState,
+    t,
+    turn::TurnOrder,
+};
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle};
+
+use crate::{
+    Ctx, Node, connect_host,
+    matchmaking::{Paired, play_paired},
+    moderation::resolve_peer,
+    remember_own, restore, sender_id,
+};
+
+/// Peer id of `peer` (nickname or peer id), if we can send to it.
+fn recipient(transport: &Node, session: &SessionState, peer: &str) -> Result<String> {
+    let peer_id = resolve_peer(peer);
+    if transport.parse_node_id_addr(&peer_id).is_err() {
+        bail!(ProtocolError::NotFound(t!("dm.unknown_peer", peer = peer)));
+    }
+    if peer_id == sender_id(transport, session) {
+        bail!(t!("dm.self"));
+    }
+    Ok(peer_id)
+}
+
+/// Dial `peer_id` and join its inbox.
+async fn open_inbox(transport: &Node, ctx: &Ctx, peer_id: &str) -> Result<Box<dyn TopicHandle>> {
+    connect_host(transport, ctx, peer_id).await?;
+    transport
+        .join_topic(transport.topic_from_name(&inbox_topic_name(peer_id)))
+        .await
+}
+
+/// Send `text` to `peer` (nickname or peer id) through its inbox.
+pub(crate) async fn send(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &SessionState,
+    peer: &str,
+    text: String,
+) -> Result<()> {
+    let peer_id = recipient(transport, session, peer)?;
+    let me = sender_id(transport, session);
+    let th = open_inbox(transport, ctx, &peer_id).await?;
+    let env = make_direct(me, &peer_id, text);
+    for frame in ctx.wire.encode(&env) {
+        th.publish(&frame).await?;
+    }
+    remember_own(&env, session);
+    println!("{}", ctx.out.success(&t!("dm.sent", peer = peer)));
+    Ok(())
+}
+
+/// Invite `peer` to `game` in a new private room and host the match there
+/// once it accepts.
+pub(crate) async fn invite(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &mut SessionState,
+    peer: &str,
+    game: GameKind,
+    order: TurnOrder,
+) -> Result<()> {
+    let out = &ctx.out;
+    // Fail on games that cannot be played by two before inviting anyone.
+    GameRegistry::builtin().create(&game.name, 2)?;
+    let peer_id = recipient(transport, session, peer)?;
+    restore::restore(transport, ctx, session).await?;
+    let me = sender_id(transport, session);
+    let topic = transport.topic_from_name(&format!("invite-{}", uuid::Uuid::new_v4()));
+    let ticket = RoomTicket {
+        topic_hex: transport.topic_to_hex(&topic),
+        host_addr: me.clone(),
+    };
+    let invite_id = uuid::Uuid::new_v4().to_string();
+    let expires = now_ms() + INVITE_TTL.as_millis() as u64;
+    let body = InviteBody::Invite {
+        invite_id: invite_id.clone(),
+        game: game.clone(),
+        order,
+        ticket: ticket.to_string(),
+        expires,
+    };
+    let mut inbox = transport
+        .join_topic(transport.topic_from_name(&inbox_topic_name(&me)))
+        .await?;
+    let theirs = open_inbox(transport, ctx, &peer_id).await?;
+    for frame in ctx.wire.encode(&make_invite(me.clone(), &peer_id, body)) {
+        theirs.publish(&frame).await?;
+    }
+    let text = t!(
+        "invite.sent",
+        peer = peer,
+        game = game,
+        seconds = INVITE_TTL.as_secs()
+    );
+    println!("{}", out.info(&text));
+    let lapse = tokio::time::sleep(INVITE_TTL);
+    tokio::pin!(lapse);
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        tokio::select! {
+            b = inbox.next() => {
+                let Some(env) = ctx.wire.decode::<InviteBody>(&b?) else {
+                    continue;
+                };
+                if env.sender_id != peer_id
+                    || env.sig.is_none()
+                    || env.body.invite_id() != invite_id
+                {
+                    continue;
+                }
+                match env.body {
+                    InviteBody::Accept { .. } => break,
+                    InviteBody::Decline { .. } => {
+                        println!("{}", out.warn(&t!("invite.declined", peer = peer)));
+                        return Ok(());
+                    }
+                    InviteBody::Invite { .. } => {}
+                }
+            }
+            _ = &mut lapse => {
+                println!("{}", out.warn(&t!("invite.expired", peer = peer)));
+                return Ok(());
+            }
+            _ = &mut interrupted => return Ok(()),
+        }
+    }
+    drop((inbox, theirs));
+    let short: String = peer_id.chars().take(8).collect();
+    let name = format!("{}-{short}", game.name);
+    let paired = Paired {
+        partner: peer_id,
+        topic_hex: ticket.topic_hex,
+        host: true,
+        order,
+    };
+    play_paired(transport, ctx, session, &game.name, name, paired, &me).await
+}
@@ -1,41 +1,2402 @@
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Result, anyhow, bail};
+use clap::Parser;
+use members::{Notice, RoomMembers};
+#[cfg(unix)]
+use p2p_core::daemon::DaemonTransport;
+use p2p_core::{
+    announce::{self, AnnouncementKind},
+    bandwidth::{BandwidthLog, BandwidthMeter, MeteredTransport, current_hour},
+    bootstrap::{self, BootstrapPeer},
+    browser::RoomBrowser,
+    config::{Config, TransportKind},
+    connectivity::{ConnectivityChange, ConnectivityReport, PartitionDetector},
+    digest::{DigestSync, Digestible, SyncTable, TableDigest},
+    directory::PeerDirectory,
+    discovery::{DISCOVERY_TOPIC_NAME, Discovery, ROOM_TTL_MS, RoomCache},
+    dm::{InviteBody, inbox_topic_name},
+    error::{ErrorReport, ProtocolError},
+    game::{GameKind, GameRegistry},
+    history::{COMPACT_EVERY, HistoryEntry, HistoryQuery, HistoryScope, HistoryStore, find_ranges},
+    hlc::{self, OrderBuffer},
+    i18n,
+    keymap::{Action, Keymap},
+    language,
+    middleware::{RecentIds, Stage},
+    namespace::{DEFAULT_NAMESPACE, NamespacedTransport},
+    notify::{Notifier, NotifyEvent},
+    output::{Output, UiEvent},
+    power::{BatchedTransport, PowerProfile},
+    profanity::ProfanityFilter,
+    protocol::{
+        AppCli, ArchiveCmd, ChatMsg, Command, DebugCmd, DiscoveryBody, Envelope, ErrorFormat,
+        GLOBAL_CHAT_TOPIC_NAME, GameCmd, GlobalCmd, HistoryCmd, MemberRole,
+        NAME_REGISTRY_TOPIC_NAME, NameClaim, NameRelease, PROTOCOL_VER, RoomCmd, RoomSummary,
+        Scope, StorageCmd, make_chat_global, make_chat_room, now_ms,
+    },
+    rating::RatingBook,
+    registry::{ClaimCache, NameRegistry, NickConflictLost, NickGuard},
+    rooms::{DEFAULT_IDLE_MINUTES, IdleTimer, RoomPresence, RoomRef, RoomTicket, TopicFeed},
+    safe_mode::SafeMode,
+    scheduler::{Budget, Scheduler},
+    session::{self, SessionState},
+    signing,
+    stats::LocalStats,
+    storage,
+    supervisor::{Health, HealthReport, RestartPolicy, Supervisor},
+    sybil::{MAX_POW_BITS, Proof, SybilConfig},
+    t,
+    timeouts::{Timeouts, retry},
+    titles,
+    update::{self, ReleaseManifest, UpdateStatus},
+    vouch::VouchBook,
+    wire::Wire,
+};
+use play::Role;
+use serde::Serialize;
+use serde_json::{Value, json};
+use shutdown::Goodbye;
+use std::{
+    collections::BTreeMap,
+    io::IsTerminal,
+    net::SocketAddr,
+    process::ExitCode,
+    sync::{
+        Arc, OnceLock, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
 use tracing_subscriber::EnvFilter;
+use transport_iroh::transport_iroh::{GossipTransport, IrohTransport, NeighborCount, TopicHandle};
+use transport_libp2p::transport_libp2p::{self as libp2p_t, Libp2pTransport};
+use transport_tcp::transport_tcp::{DEFAULT_PORT, TcpTransport, format_addr};
 
-#[derive(Parser, Debug)]
-#[command(name = "p2p-games")]
-#[command(about = "P2P Games: global chat & lobbies", long_about = None)]
-struct Args {
-    #[arg(short, long, default_value = "Player")]
-    nickname: String,
+mod archive;
+mod bridge;
+mod clipboard;
+#[cfg(unix)]
+mod daemon;
+mod dashboard;
+mod debug;
+mod dm;
+mod matchmaking;
+mod members;
+mod moderation;
+mod onboarding;
+mod play;
+mod rating;
+mod repl;
+mod restore;
+mod shutdown;
+mod summary;
+mod tournament;
+mod tui;
+mod websocket;
 
-    #[command(subcommand)]
-    command: Command,
+/// Version of this client, compared against the release feed.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Peers seen in a room within this window count as present.
+const PRESENCE_WINDOW: Duration = Duration::from_secs(300);
+
+/// The transport every command runs on.
+pub(crate) type Node =
+    MeteredTransport<NamespacedTransport<BatchedTransport<Arc<dyn GossipTransport>>>>;
+
+/// Configuration and UI helpers shared by all command handlers.
+pub(crate) struct Ctx {
+    pub config: Config,
+    pub out: Output,
+    pub notifier: Notifier,
+    pub meter: BandwidthMeter,
+    pub power: PowerProfile,
+    /// Periodic publishes of every loop of this process, see [`Scheduler`].
+    pub budget: Budget,
+    pub wire: Wire,
+    pub safe: SafeMode,
+    /// Applied to displayed chat text when enabled.
+    pub filter: RwLock<Option<ProfanityFilter>>,
+    /// Who was recently seen in which room (for player counts).
+    pub presence: RoomPresence,
+    /// `--guest`: the node key is generated per run and not persisted.
+    pub guest: bool,
+    /// Endpoint kept up for every command of a `repl`.
+    kept: OnceLock<Arc<dyn GossipTransport>>,
 }
 
-#[derive(Subcommand, Debug)]
-enum Command {
-    Whoami,
-    Say { text: String },
+impl Ctx {
+    /// Bring up the node: the running `daemon` if it has our identity,
+    /// otherwise a fresh endpoint (see [`Ctx::bind`]).
+    pub async fn start_node(&self) -> Result<Node> {
+        if let Some(endpoint) = self.kept.get() {
+            return Ok(self.node(endpoint.clone()));
+        }
+        #[cfg(unix)]
+        if let Some(daemon) = self.attach_daemon().await {
+            return Ok(self.node(Arc::new(daemon)));
+        }
+        let node = self.node(self.bind().await?);
+        self.bootstrap(&node).await;
+        Ok(node)
+    }
+
+    /// Bring the node up once for all following commands (`repl`).
+    pub async fn keep_node(&self) -> Result<()> {
+        #[cfg(unix)]
+        if let Some(daemon) = self.attach_daemon().await {
+            let _ = self.kept.set(Arc::new(daemon));
+            return Ok(());
+        }
+        let endpoint = self.bind().await?;
+        self.bootstrap(&self.node(endpoint.clone())).await;
+        let _ = self.kept.set(endpoint);
+        Ok(())
+    }
+
+    /// Whether commands share a node kept up by [`Ctx::keep_node`]; they
+    /// must not shut it down then.
+    pub fn keeps_node(&self) -> bool {
+        self.kept.get().is_some()
+    }
+
+    /// Shut the kept node down.
+    pub async fn release_node(&self) {
+        if let Some(endpoint) = self.kept.get() {
+            self.meter.flush();
+            let _ = endpoint.shutdown().await;
+        }
+    }
+
+    /// Dial the general `[[bootstrap]]` peers (see [`bootstrap`]). A daemon
+    /// did this when it started.
+    pub async fn bootstrap(&self, node: &Node) {
+        dial_bootstrap(node, self, &bootstrap::general(&self.config.bootstrap)).await;
+    }
+
+    /// Bind our own endpoint on the configured transport with the persisted
+    /// key; envelopes are signed with that key from here on. Guests get a
+    /// new key.
+    pub async fn bind(&self) -> Result<Arc<dyn GossipTransport>> {
+        let offline = |e: anyhow::Error| {
+            ProtocolError::NoConnectivity(t!("connectivity.bind_failed", error = format!("{e:#}")))
+        };
+        let identity = if self.guest {
+            None
+        } else {
+            session::load_identity().map_err(|e| anyhow!(t!("identity.unreadable", error = e)))?
+        };
+        if self.config.transport == TransportKind::Tcp {
+            let listen = self
+                .config
+                .tcp_listen
+                .unwrap_or(SocketAddr::from(([0, 0, 0, 0], DEFAULT_PORT)));
+            let tcp = match identity {
+                Some(secret) if !self.guest => TcpTransport::with_secret_key(secret, listen).await,
+                _ => TcpTransport::new(listen).await,
+            }
+            .map_err(offline)?;
+            if identity.is_none() && !self.guest {
+                session::save_identity(&tcp.secret_key())?;
+            }
+            signing::init(&tcp.secret_key(), self.config.protocol.require_signatures);
+            return Ok(Arc::new(tcp));
+        }
+        if self.config.transport == TransportKind::Libp2p {
+            let listen = self
+                .config
+                .libp2p_listen
+                .unwrap_or(SocketAddr::from(([0, 0, 0, 0], libp2p_t::DEFAULT_PORT)));
+            let libp2p = match identity {
+                Some(secret) if !self.guest => {
+                    Libp2pTransport::with_secret_key(secret, listen).await
+                }
+                _ => Libp2pTransport::new(listen).await,
+            }
+            .map_err(offline)?;
+            if identity.is_none() && !self.guest {
+                session::save_identity(&libp2p.secret_key())?;
+            }
+            signing::init(
+                &libp2p.secret_key(),
+                self.config.protocol.require_signatures,
+            );
+            return Ok(Arc::new(libp2p));
+        }
+        let options = self.config.iroh_options();
+        options.check()?;
+        let iroh = match identity {
+            _ if self.guest => IrohTransport::new(&options).await.map_err(offline)?,
+            Some(secret) => IrohTransport::with_secret_key(secret, &options)
+                .await
+                .map_err(offline)?,
+            None => {
+                let iroh = IrohTransport::new(&options).await.map_err(offline)?;
+                session::save_identity(&iroh.secret_key())?;
+                iroh
+            }
+        };
+        signing::init(&iroh.secret_key(), self.config.protocol.require_signatures);
+        Ok(Arc::new(iroh))
+    }
+
+    /// The running daemon, if it runs the persisted identity.
+    #[cfg(unix)]
+    async fn attach_daemon(&self) -> Option<DaemonTransport> {
+        if self.guest {
+            return None;
+        }
+        let secret = session::load_identity().ok().flatten()?;
+        let daemon = DaemonTransport::attach().await?;
+        signing::init(&secret, self.config.protocol.require_signatures);
+        (signing::public_key()? == daemon.node_addr().node_id.to_string()).then_some(daemon)
+    }
+
+    /// Wrap an endpoint in the power, namespace and metering layers.
+    pub fn node(&self, endpoint: Arc<dyn GossipTransport>) -> Node {
+        let batched = BatchedTransport::new(endpoint, self.power);
+        MeteredTransport::new(
+            NamespacedTransport::new(batched, self.config.namespace())
+                .with_epochs(self.config.topics.clone()),
+            self.meter.clone(),
+        )
+    }
+
+    /// `text` as it is displayed, masked if the profanity filter is on.
+    pub fn clean(&self, text: &str) -> String {
+        match &*self.filter.read().unwrap() {
+            Some(f) => f.clean(text),
+            None => text.to_string(),
+        }
+    }
+
+    /// Apply the settings of an edited `config.toml` that a running node
+    /// can take over: chat filter, alerts and rate limits.
+    pub fn reconfigure(&self, config: &Config) {
+        *self.filter.write().unwrap() = profanity_filter(config, &self.safe);
+        self.notifier.reconfigure(config.notifications.clone());
+        self.budget.reconfigure(&config.scheduler);
+        self.meter.reconfigure(&config.bandwidth);
+    }
+}
+
+fn profanity_filter(config: &Config, safe: &SafeMode) -> Option<ProfanityFilter> {
+    (config.profanity_filter || safe.forces_profanity_filter()).then(|| {
+        ProfanityFilter::new(&[config.blocked_words.clone(), safe.blocked_words.clone()].concat())
+    })
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .init();
 
-    let args = Args::parse();
-    tracing::info!("Starting CLI as {}", args.nickname);
+    let cli = AppCli::parse();
+    let errors = cli.errors;
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let report = ErrorReport::new(&e);
+            match errors {
+                ErrorFormat::Text => eprintln!("Error: {e:?}"),
+                ErrorFormat::Json => {
+                    eprintln!("{}", serde_json::to_string(&report).unwrap_or_default())
+                }
+            }
+            ExitCode::from(report.exit_code)
+        }
+    }
+}
+
+async fn run(cli: AppCli) -> Result<()> {
+    let mut config = Config::load().unwrap_or_default();
+    // Flags win over `config.toml`.
+    if !cli.relay_url.is_empty() {
+        config.relay_url = None;
+        config.relays.urls = cli.relay_url;
+    }
+    config.relays.relay_only |= cli.relay_only;
+    config.relays.disabled |= cli.no_relay;
+    config.relays.n0_discovery &= !cli.no_n0_discovery;
+    config.relays.local_discovery &= !cli.no_local_discovery;
+    if let Some(transport) = cli.transport {
+        config.transport = transport;
+    }
+    if let Some(color) = cli.color {
+        config.color = color;
+    }
+    i18n::init(&config);
+    if let Some(name) = &cli.profile {
+        session::set_profile(name)
+            .map_err(|e| anyhow!(t!("profile.invalid", name = name, error = e)))?;
+    }
+    let safe = SafeMode::load();
+    let filter = RwLock::new(profanity_filter(&config, &safe));
+    let ctx = Ctx {
+        out: Output::new(cli.plain || config.plain)
+            .with_colors(config.color.enabled())
+            .with_json(cli.json),
+        notifier: Notifier::new(config.notifications.clone()),
+        meter: BandwidthMeter::new(&config.bandwidth),
+        power: PowerProfile::from_flag(cli.low_power || config.low_power),
+        budget: Budget::new(&config.scheduler),
+        wire: Wire::new(&config.protocol),
+        safe,
+        filter,
+        presence: RoomPresence::default(),
+        guest: cli.guest,
+        kept: OnceLock::new(),
+        config,
+    };
+    let (config, out) = (&ctx.config, &ctx.out);
+    let mut session = if ctx.guest {
+        SessionState::guest()
+    } else {
+        SessionState::load()?
+    };
+
+    if !out.is_json()
+        && update::due(&config.update)
+        && let Ok(UpdateStatus::Available(m)) = update::check(&config.update, VERSION).await
+    {
+        print_update(out, &m);
+    }
+
+    if session.nickname.is_empty() && onboarding::should_run(&cli.command) {
+        onboarding::run(&ctx, &mut session, false).await?;
+    }
+    dispatch(cli.command, &ctx, &mut session).await
+}
+
+/// Run one command; `repl` runs many in a row on one node.
+async fn dispatch(command: Command, ctx: &Ctx, session: &mut SessionState) -> Result<()> {
+    let (config, out) = (&ctx.config, &ctx.out);
+    if session.guest
+        && matches!(
+            command,
+            Command::Setup | Command::Login { .. } | Command::Logout
+        )
+    {
+        bail!(t!("guest.no_login"));
+    }
+    match command {
+        Command::Setup => onboarding::run(ctx, session, true).await?,
+        Command::Repl => repl::run(ctx, session).await?,
+        Command::Login {
+            name,
+            no_auto,
+            wait_ms,
+        } => {
+            let mut timeouts = config.timeouts;
+            if let Some(ms) = wait_ms {
+                timeouts.claim_wait_ms = ms;
+            }
+            let Some(name) = name.or_else(|| config.nickname.clone()) else {
+                bail!(t!("login.no_name"));
+            };
+            let transport = ctx.start_node().await?;
+            login(
+                &transport,
+                out,
+                session,
+                &name,
+                no_auto,
+                timeouts,
+                &config.sybil,
+            )
+            .await?;
+        }
+        Command::Logout => {
+            let transport = ctx.start_node().await?;
+            logout(&transport, out, session, config.timeouts).await?;
+        }
+        Command::Addr { copy } => {
+            let transport = ctx.start_node().await?;
+            // Over TCP and libp2p a node id alone cannot be dialed.
+            let addr = match ctx.config.transport {
+                TransportKind::Iroh => transport.node_addr().node_id.to_string(),
+                TransportKind::Tcp | TransportKind::Libp2p => format_addr(transport.node_addr()),
+            };
+            if out.is_json() {
+                print_json(&json!({ "node_id": addr }))?;
+            } else {
+                println!("{addr}");
+            }
+            if copy {
+                clipboard::copy_and_report(out, &addr);
+            }
+        }
+        Command::Dm { peer, text } => {
+            let transport = ctx.start_node().await?;
+            dm::send(&transport, ctx, session, &peer, text).await?;
+        }
+        Command::Announce { text, kind, hours } => {
+            announce(ctx, session, text, kind, hours).await?
+        }
+        Command::Whoami => whoami(out, session)?,
+        Command::Stats { game: None, .. } => show_stats(out)?,
+        Command::Stats {
+            game: Some(game),
+            vs,
+        } => dashboard::show(out, session, &game, vs.as_deref())?,
+        Command::Who => who(ctx, session).await?,
+        Command::Trust { nickname } => trust(out, &nickname)?,
+        Command::Vouch { peer, revoke } => vouch(ctx, session, &peer, revoke).await?,
+        Command::Whois { peer } => whois(ctx, &peer)?,
+        Command::Match { game, range } => {
+            let transport = ctx.start_node().await?;
+            matchmaking::run(&transport, ctx, session, &game, range).await?
+        }
+        Command::Tournament { sub } => tournament::run(sub, ctx, session).await?,
+        Command::Rating { game, publish } => {
+            rating::show(ctx, session, game.as_deref(), publish).await?
+        }
+        Command::Title { id, clear } => title(out, session, id, clear)?,
+        Command::Languages { tags, clear } => languages(out, session, tags, clear)?,
+        Command::Keys => show_keys(out, config)?,
+        #[cfg(unix)]
+        Command::Daemon { roles } => daemon::run(ctx, session, &roles).await?,
+        #[cfg(not(unix))]
+        Command::Daemon { .. } => bail!(t!("daemon.unsupported")),
+        Command::Archive { sub } => {
+            let transport = ctx.start_node().await?;
+            match sub {
+                ArchiveCmd::Fetch { game_id } => {
+                    archive::fetch(&transport, ctx, session, &game_id).await?
+                }
+                ArchiveCmd::Deposit { game_id } => {
+                    archive::deposit(&transport, ctx, session, &game_id).await?
+                }
+            }
+        }
+        Command::Tui => {
+            let transport = ctx.start_node().await?;
+            tui::run(&transport, ctx, session).await?
+        }
+        Command::Bridge => {
+            let transport = ctx.start_node().await?;
+            bridge::run(&transport, ctx, session).await?
+        }
+        Command::Websocket { listen } => {
+            let transport = ctx.start_node().await?;
+            websocket::run(&transport, ctx, session, listen).await?
+        }
+        Command::Status => show_status(ctx, session)?,
+        Command::History { sub } => history(sub, ctx)?,
+        Command::Storage { sub } => storage(sub, ctx)?,
+        Command::Debug {
+            sub: DebugCmd::Tail { topic },
+        } => {
+            let transport = ctx.start_node().await?;
+            debug::tail(&transport, ctx, &topic).await?
+        }
+        Command::Debug {
+            sub: DebugCmd::Replay { file },
+        } => debug::replay(ctx, file)?,
+        Command::Update => match update::check(&config.update, VERSION).await? {
+            UpdateStatus::UpToDate if out.is_json() => {
+                print_json(&json!({ "status": "up_to_date", "version": VERSION }))?
+            }
+            UpdateStatus::Available(m) if out.is_json() => print_json(&json!({
+                "status": "available",
+                "version": VERSION,
+                "release": m,
+            }))?,
+            UpdateStatus::UpToDate => println!("{}", t!("update.up_to_date", version = VERSION)),
+            UpdateStatus::Available(m) => print_update(out, &m),
+        },
+        Command::Global { sub } => {
+            if ctx.safe.hides_global_chat() {
+                bail!(t!("safe.global_hidden"));
+            }
+            if let GlobalCmd::History { last } = sub {
+                return global_history(ctx, last);
+            }
+            let transport = ctx.start_node().await?;
+            match sub {
+                GlobalCmd::Listen => {
+                    restore::restore(&transport, ctx, session).await?;
+                    global_listen(&transport, ctx, session).await?
+                }
+                GlobalCmd::Say { text } => {
+                    let text = config.canned.expand(&text, None).unwrap_or(text);
+                    let topic = transport.topic_from_name(GLOBAL_CHAT_TOPIC_NAME);
+                    let th = transport.join_topic(topic).await?;
+                    let env = make_chat_global(sender_id(&transport, session), text);
+                    remember_own(&env, session);
+                    for frame in ctx.wire.encode(&env) {
+                        th.publish(&frame).await?;
+                    }
+                }
+                GlobalCmd::History { .. } => unreachable!("handled above"),
+            }
+        }
+        Command::Room { sub } => room(sub, ctx, session).await?,
+    }
+
+    Ok(())
+}
+
+/// Peer id to put into outgoing envelopes.
+fn sender_id(transport: &dyn GossipTransport, session: &SessionState) -> String {
+    if session.peer_id.is_empty() {
+        transport.node_addr().node_id.to_string()
+    } else {
+        session.peer_id.clone()
+    }
+}
+
+/// Print `value` as one line of JSON (`--json`).
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string(value)?);
+    Ok(())
+}
+
+/// Our proof of work for `[sybil] pow_bits`, showing progress while a new
+/// one is searched for; claims and seeks then send the stored one.
+pub(crate) async fn proof_of_work(out: &Output, sybil: &SybilConfig, peer_id: &str) -> Option<u64> {
+    let expected = 1u64 << sybil.pow_bits.min(MAX_POW_BITS);
+    let shown = Arc::new(AtomicBool::new(false));
+    let report = (!out.is_json()).then(|| shown.clone());
+    let nonce = Proof::ensure(peer_id, sybil.pow_bits, move |tried| {
+        if let Some(shown) = &report {
+            shown.store(true, Ordering::Relaxed);
+            let text = t!("login.proof_of_work", tried = tried, expected = expected);
+            eprint!("\r{text}");
+        }
+    })
+    .await;
+    if shown.load(Ordering::Relaxed) {
+        eprintln!();
+    }
+    nonce
+}
+
+async fn login(
+    transport: &dyn GossipTransport,
+    out: &Output,
+    session: &mut SessionState,
+    name: &str,
+    no_auto: bool,
+    timeouts: Timeouts,
+    sybil: &SybilConfig,
+) -> Result<()> {
+    let peer_id = transport.node_addr().node_id.to_string();
+    proof_of_work(out, sybil, &peer_id).await;
+    let registry = NameRegistry::new(transport, timeouts).with_sybil(sybil);
+    let since = now_ms();
+    let (nickname, granted) = {
+        let _spinner = out.waiting(&t!("login.claiming", name = name));
+        registry.claim_unique_at(name, &peer_id, since).await?
+    };
+    if !granted && no_auto {
+        bail!(ProtocolError::NameTaken(t!("login.taken", name = name)));
+    }
+
+    session.peer_id = peer_id;
+    session.nickname = nickname.clone();
+    session.nick_since = if granted { since } else { 0 };
+    session.save()?;
+
+    if out.is_json() {
+        print_json(&json!({ "nickname": nickname, "granted": granted }))?;
+    } else if granted {
+        println!("{}", out.success(&t!("login.ok", nickname = nickname)));
+    } else {
+        println!(
+            "{}",
+            out.warn(&t!("login.renamed", name = name, nickname = nickname))
+        );
+    }
+    Ok(())
+}
+
+/// Release our nickname on the network and forget it locally.
+async fn logout(
+    transport: &dyn GossipTransport,
+    out: &Output,
+    session: &mut SessionState,
+    timeouts: Timeouts,
+) -> Result<()> {
+    if session.nickname.is_empty() {
+        bail!(t!("logout.none"));
+    }
+    // A fallback name we never won has no claim to release.
+    if let Some(claim) = session.nick_claim() {
+        NameRegistry::new(transport, timeouts)
+            .publish_release(&claim.release())
+            .await?;
+    }
+    let nickname = std::mem::take(&mut session.nickname);
+    session.nick_since = 0;
+    session.save()?;
+
+    if out.is_json() {
+        print_json(&json!({ "released": nickname }))?;
+    } else {
+        println!("{}", out.success(&t!("logout.ok", nickname = nickname)));
+    }
+    Ok(())
+}
+
+fn print_update(out: &Output, m: &ReleaseManifest) {
+    println!(
+        "{}",
+        out.warn(&t!(
+            "update.available",
+            version = m.version,
+            current = VERSION,
+            url = m.url
+        ))
+    );
+    if m.protocol_ver > PROTOCOL_VER {
+        println!("{}", out.warn(&t!("update.protocol_changed")));
+    }
+    if let Some(notes) = &m.notes {
+        println!("{notes}");
+    }
+}
+
+fn show_stats(out: &Output) -> Result<()> {
+    let stats = LocalStats::load()?;
+    if out.is_json() {
+        return print_json(&json!({
+            "since": stats.since,
+            "chat_ms": stats.chat_ms,
+            "peers_met": stats.peers_met.len(),
+            "games_total": stats.total_games(),
+            "games_played": stats.games_played,
+        }));
+    }
+    println!("{}", out.heading(&t!("stats.title")));
+    let days = now_ms().saturating_sub(stats.since) / 86_400_000;
+    println!("{}", t!("stats.since", days = days));
+    println!(
+        "{}",
+        t!("stats.chat_time", minutes = stats.chat_ms / 60_000)
+    );
+    println!("{}", t!("stats.peers_met", count = stats.peers_met.len()));
+    println!("{}", t!("stats.games_total", count = stats.total_games()));
+    let rows: Vec<(String, u64)> = stats
+        .games_played
+        .iter()
+        .map(|(game, n)| (game.clone(), *n))
+        .collect();
+    if !rows.is_empty() {
+        println!("{}", out.bar_chart(&rows));
+    }
+    Ok(())
+}
+
+/// Approximate online list from the peer directory, refreshed with the
+/// hosts of currently listed rooms.
+async fn who(ctx: &Ctx, session: &SessionState) -> Result<()> {
+    let out = &ctx.out;
+    let transport = ctx.start_node().await?;
+    let rooms = {
+        let _spinner = out.waiting(&t!("who.probing"));
+        Discovery::new(&transport, ctx.config.timeouts)
+            .list_rooms()
+            .await?
+    };
+    let mut directory = PeerDirectory::load().unwrap_or_default();
+    directory.hosts(&rooms);
+    let _ = directory.save();
+
+    let me = sender_id(&transport, session);
+    let online: Vec<_> = directory
+        .online()
+        .into_iter()
+        .filter(|(id, _)| **id != me)
+        .collect();
+    if out.is_json() {
+        let online: Vec<_> = online
+            .iter()
+            .map(|(id, p)| {
+                json!({
+                    "peer_id": id,
+                    "nickname": p.nickname,
+                    "room": p.room,
+                    "last_seen": p.last_seen,
+                })
+            })
+            .collect();
+        return print_json(&json!({ "online": online, "known": directory.peers.len() }));
+    }
+    println!("{}", out.heading(&t!("who.title")));
+    let now = now_ms();
+    for (id, p) in &online {
+        let name = match &p.nickname {
+            Some(nick) => format!("{nick} ({})", &id[..8.min(id.len())]),
+            None => id.to_string(),
+        };
+        let minutes = now.saturating_sub(p.last_seen) / 60_000;
+        let line = match &p.room {
+            Some(room) => t!(
+                "who.peer_in_room",
+                name = name,
+                room = room,
+                minutes = minutes
+            ),
+            None => t!("who.peer", name = name, minutes = minutes),
+        };
+        println!("  {line}");
+    }
+    println!(
+        "{}",
+        t!(
+            "who.count",
+            online = online.len(),
+            known = directory.peers.len()
+        )
+    );
+    Ok(())
+}
+
+fn show_keys(out: &Output, config: &Config) -> Result<()> {
+    let keymap = Keymap::from_config(&config.keymap)?;
+    if out.is_json() {
+        let bindings: BTreeMap<_, Vec<String>> = Action::ALL
+            .iter()
+            .map(|&a| {
+                (
+                    a.name(),
+                    keymap.keys(a).iter().map(|k| k.to_string()).collect(),
+                )
+            })
+            .collect();
+        return print_json(&bindings);
+    }
+    println!("{}", out.heading(&t!("keys.title")));
+    for &action in Action::ALL {
+        let keys: Vec<String> = keymap.keys(action).iter().map(|k| k.to_string()).collect();
+        let keys = if keys.is_empty() {
+            t!("keys.unbound")
+        } else {
+            keys.join(", ")
+        };
+        println!("{}", t!("keys.entry", action = action.name(), keys = keys));
+    }
+    Ok(())
+}
+
+/// Everything `status` shows, for `--json`.
+fn status_json(ctx: &Ctx, session: &SessionState) -> Value {
+    let protocol = ctx.wire.config();
+    let health = HealthReport::load_live();
+    ctx.meter.flush();
+    let log = BandwidthLog::load().unwrap_or_default();
+    let hour = current_hour();
+    let mut status = identity_json(session);
+    status["namespace"] = json!(ctx.config.namespace());
+    status["topic_epochs"] = json!(topic_epochs(ctx));
+    status["low_power"] = json!(ctx.power == PowerProfile::LowPower);
+    status["safe_mode"] = json!(ctx.safe.enabled);
+    status["middleware"] = json!(ctx.wire.pipeline().names());
+    status["dual_emit_until"] = json!(
+        protocol
+            .dual_emit_until
+            .filter(|_| protocol.in_transition())
+    );
+    status["connectivity"] = json!(
+        ConnectivityReport::load_live()
+            .into_iter()
+            .map(|r| {
+                let state = json!({ "isolated": r.isolated, "neighbors": r.neighbors });
+                (r.pid.to_string(), state)
+            })
+            .collect::<BTreeMap<_, _>>()
+    );
+    status["subsystems"] = json!(
+        health
+            .iter()
+            .map(|r| (r.pid.to_string(), &r.subsystems))
+            .collect::<BTreeMap<_, _>>()
+    );
+    status["bandwidth"] = json!({
+        "hour": log.hour(hour),
+        "day": log.total_since(hour, 24),
+        "soft_cap_bytes": ctx.config.bandwidth.soft_cap_mb_per_hour.map(|cap| cap * 1024 * 1024),
+    });
+    status
+}
+
+/// Epochs of the rotated topics, by name.
+fn topic_epochs(ctx: &Ctx) -> BTreeMap<String, u32> {
+    let topics = &ctx.config.topics;
+    topics
+        .rotate
+        .iter()
+        .map(|r| (r.name.clone(), topics.epoch(&r.name, now_ms())))
+        .collect()
+}
+
+fn show_status(ctx: &Ctx, session: &SessionState) -> Result<()> {
+    let out = &ctx.out;
+    if out.is_json() {
+        return print_json(&status_json(ctx, session));
+    }
+    println!("{}", out.heading(&t!("status.title")));
+    whoami(out, session)?;
+    let ns = ctx.config.namespace();
+    if ns != DEFAULT_NAMESPACE {
+        println!("{}", t!("status.namespace", namespace = ns));
+    }
+    for (name, epoch) in topic_epochs(ctx) {
+        println!("{}", t!("status.topic_epoch", topic = name, epoch = epoch));
+    }
+    if ctx.power == PowerProfile::LowPower {
+        println!("{}", t!("status.low_power"));
+    }
+    if ctx.safe.enabled {
+        println!("{}", t!("status.safe_mode"));
+    }
+    let protocol = ctx.wire.config();
+    if protocol.middleware != Stage::defaults() {
+        let stages = ctx.wire.pipeline().names().join(" → ");
+        println!("{}", t!("status.middleware", stages = stages));
+    }
+    if let Some(until) = protocol.dual_emit_until
+        && protocol.in_transition()
+    {
+        println!(
+            "{}",
+            t!(
+                "status.dual_emit",
+                target = protocol.target_ver,
+                until = until
+            )
+        );
+    }
+
+    for report in ConnectivityReport::load_live() {
+        let line = if report.isolated {
+            out.warn(&t!("status.isolated", pid = report.pid))
+        } else {
+            let neighbors = report.neighbors;
+            t!("status.connected", neighbors = neighbors, pid = report.pid)
+        };
+        println!("{line}");
+    }
+
+    for report in HealthReport::load_live() {
+        if report.subsystems.is_empty() {
+            continue;
+        }
+        println!();
+        println!("{}", t!("status.subsystems", pid = report.pid));
+        for (name, h) in &report.subsystems {
+            let line = match &h.health {
+                Health::Running => t!(
+                    "status.subsystem_running",
+                    name = name,
+                    restarts = h.restarts
+                ),
+                Health::Restarting { reason } => {
+                    t!("status.subsystem_restarting", name = name, reason = reason)
+                }
+                Health::Failed { reason } => t!(
+                    "status.subsystem_failed",
+                    name = name,
+                    restarts = h.restarts,
+                    reason = reason
+                ),
+                Health::Stopped => t!("status.subsystem_stopped", name = name),
+            };
+            let line = match h.health {
+                Health::Failed { .. } => out.error(&line),
+                Health::Restarting { .. } => out.warn(&line),
+                _ => line,
+            };
+            println!("  {line}");
+        }
+    }
+
+    // Flush our own counters first so the log on disk is current.
+    ctx.meter.flush();
+    let log = BandwidthLog::load().unwrap_or_default();
+    let hour = current_hour();
+    println!();
+    println!("{}", t!("status.bandwidth_hour"));
+    let topics = log.hour(hour);
+    if topics.is_empty() {
+        println!("  {}", t!("status.no_traffic"));
+    }
+    for (topic, u) in &topics {
+        println!(
+            "  {}",
+            t!(
+                "status.topic_usage",
+                topic = topic,
+                sent = fmt_bytes(u.sent),
+                received = fmt_bytes(u.received)
+            )
+        );
+    }
+    let day = log.total_since(hour, 24);
+    println!(
+        "{}",
+        t!(
+            "status.bandwidth_day",
+            sent = fmt_bytes(day.sent),
+            received = fmt_bytes(day.received)
+        )
+    );
+    match ctx.config.bandwidth.soft_cap_mb_per_hour {
+        Some(cap) => {
+            let used = log.total_since(hour, 1).total();
+            let line = t!(
+                "status.soft_cap",
+                used = fmt_bytes(used),
+                cap = fmt_bytes(cap * 1024 * 1024)
+            );
+            if used > cap * 1024 * 1024 {
+                println!("{}", out.warn(&t!("status.throttled", usage = line)));
+            } else {
+                println!("{line}");
+            }
+        }
+        None => println!("{}", t!("status.no_cap")),
+    }
+    Ok(())
+}
+
+/// Human-readable byte count (`1.5 KiB`).
+fn fmt_bytes(n: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB"];
+    let mut v = n as f64;
+    let mut unit = 0;
+    while v >= 1024.0 && unit + 1 < UNITS.len() {
+        v /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{n} B")
+    } else {
+        format!("{v:.1} {}", UNITS[unit])
+    }
+}
+
+/// Re-pin `nickname` to the key it is currently claimed with.
+fn trust(out: &Output, nickname: &str) -> Result<()> {
+    let mut directory = PeerDirectory::load()?;
+    let Some(peer) = directory.trust(nickname) else {
+        bail!(ProtocolError::NotFound(t!(
+            "trust.unknown",
+            nickname = nickname
+        )));
+    };
+    directory.save()?;
+    if out.is_json() {
+        return print_json(&json!({ "nickname": nickname, "peer_id": peer }));
+    }
+    println!(
+        "{}",
+        out.success(&t!("trust.done", nickname = nickname, peer = peer))
+    );
+    Ok(())
+}
+
+/// Vouch for `peer` (or take the vouch back) in public.
+async fn vouch(ctx: &Ctx, session: &SessionState, peer: &str, revoke: bool) -> Result<()> {
+    let out = &ctx.out;
+    let peer_id = moderation::resolve_peer(peer);
+    let transport = ctx.start_node().await?;
+    let me = sender_id(&transport, session);
+    if peer_id == me {
+        bail!(t!("vouch.self"));
+    }
+    Discovery::new(&transport, ctx.config.timeouts)
+        .vouch(&me, &peer_id, revoke)
+        .await?;
+    let mut vouches = VouchBook::load().unwrap_or_default();
+    vouches.record(&me, &peer_id, now_ms(), !revoke);
+    vouches.save()?;
+    if out.is_json() {
+        return print_json(&json!({ "peer_id": peer_id, "vouched": !revoke }));
+    }
+    let key = if revoke {
+        "vouch.revoked"
+    } else {
+        "vouch.done"
+    };
+    println!("{}", out.success(&t!(key, peer = peer)));
+    Ok(())
+}
+
+/// Publish a signed banner on the announcements topic.
+async fn announce(
+    ctx: &Ctx,
+    session: &SessionState,
+    text: String,
+    kind: AnnouncementKind,
+    hours: u64,
+) -> Result<()> {
+    let out = &ctx.out;
+    let transport = ctx.start_node().await?;
+    let me = sender_id(&transport, session);
+    if !ctx.config.announcements.is_operator(&me) {
+        eprintln!("{}", out.warn(&t!("announce.not_operator")));
+    }
+    let env = announce::make_announcement(me, kind, text, hours);
+    announce::publish(&transport, &ctx.config.timeouts, &env).await?;
+    if out.is_json() {
+        return print_json(&json!({ "msg_id": env.msg_id, "expires_at": env.body.expires_at }));
+    }
+    println!("{}", out.success(&t!("announce.sent", hours = hours)));
+    Ok(())
+}
+
+/// What the peer directory and the vouches we heard say about `peer`.
+fn whois(ctx: &Ctx, peer: &str) -> Result<()> {
+    let out = &ctx.out;
+    let peer_id = moderation::resolve_peer(peer);
+    let directory = PeerDirectory::load().unwrap_or_default();
+    let vouches = VouchBook::load().unwrap_or_default();
+    let ratings = RatingBook::load().unwrap_or_default();
+    let published = ratings.published(&peer_id);
+    let entry = directory.peers.get(&peer_id);
+    let vouchers = vouches.vouchers(&peer_id);
+    let friends = vouches.from_friends(&peer_id, &ctx.config.vouch, &directory);
+    if out.is_json() {
+        return print_json(&json!({
+            "peer_id": peer_id,
+            "nickname": entry.and_then(|p| p.nickname.as_ref()),
+            "last_seen": entry.map(|p| p.last_seen),
+            "room": entry.and_then(|p| p.room.as_ref()),
+            "online": entry.is_some_and(|p| p.is_online(now_ms())),
+            "vouchers": vouchers,
+            "friend_vouches": friends,
+            "ratings": published,
+        }));
+    }
+    println!("{}", out.heading(&t!("whois.title", peer = peer_id)));
+    match entry {
+        Some(p) => {
+            if let Some(nick) = &p.nickname {
+                println!("{}", t!("whois.nickname", nickname = nick));
+            }
+            let minutes = now_ms().saturating_sub(p.last_seen) / 60_000;
+            let line = match &p.room {
+                Some(room) => t!("whois.seen_in_room", minutes = minutes, room = room),
+                None => t!("whois.seen", minutes = minutes),
+            };
+            println!("{line}");
+        }
+        None => println!("{}", t!("whois.unknown")),
+    }
+    println!(
+        "{}",
+        t!("whois.vouches", count = vouchers.len(), friends = friends)
+    );
+    for v in vouchers {
+        let name = directory
+            .peers
+            .get(v)
+            .and_then(|p| p.nickname.as_deref())
+            .unwrap_or(v);
+        let marker = if ctx.config.vouch.is_friend(v, &directory) {
+            t!("whois.friend")
+        } else {
+            String::new()
+        };
+        println!("  {name}{marker}");
+    }
+    for (game, p) in published.into_iter().flatten() {
+        let line = t!(
+            "whois.rating",
+            game = game,
+            rating = p.rating,
+            games = p.games
+        );
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// List the titles of all achievements, or pick the one to show.
+fn title(out: &Output, session: &mut SessionState, id: Option<String>, clear: bool) -> Result<()> {
+    let stats = LocalStats::load().unwrap_or_default();
+    if clear {
+        session.title = None;
+        session.save()?;
+        println!("{}", out.success(&t!("titles.cleared")));
+        return Ok(());
+    }
+    let Some(id) = id else {
+        if out.is_json() {
+            let titles: Vec<_> = titles::ACHIEVEMENTS
+                .iter()
+                .map(|a| {
+                    json!({
+                        "id": a.id,
+                        "name": a.name(),
+                        "unlocked": a.is_unlocked(&stats),
+                        "shown": session.title.as_deref() == Some(a.id),
+                    })
+                })
+                .collect();
+            return print_json(&titles);
+        }
+        println!("{}", out.heading(&t!("titles.heading")));
+        for a in titles::ACHIEVEMENTS {
+            let line = if !a.is_unlocked(&stats) {
+                t!("titles.locked", id = a.id, goal = a.goal())
+            } else if session.title.as_deref() == Some(a.id) {
+                t!("titles.shown", id = a.id, name = a.name())
+            } else {
+                t!("titles.unlocked", id = a.id, name = a.name())
+            };
+            println!("  {line}");
+        }
+        return Ok(());
+    };
+    let Some(a) = titles::find(&id) else {
+        bail!(ProtocolError::NotFound(t!("titles.unknown", id = id)));
+    };
+    if !a.is_unlocked(&stats) {
+        bail!(t!("titles.not_unlocked", id = id, goal = a.goal()));
+    }
+    session.title = Some(a.id.to_string());
+    session.save()?;
+    println!("{}", out.success(&t!("titles.set", name = a.name())));
+    Ok(())
+}
+
+fn languages(
+    out: &Output,
+    session: &mut SessionState,
+    tags: Vec<String>,
+    clear: bool,
+) -> Result<()> {
+    if clear {
+        session.languages.clear();
+        session.save()?;
+        println!("{}", out.success(&t!("languages.cleared")));
+        return Ok(());
+    }
+    if tags.is_empty() {
+        if out.is_json() {
+            return print_json(&session.languages);
+        }
+        if session.languages.is_empty() {
+            println!("{}", t!("languages.none"));
+        } else {
+            let languages = session.languages.join(", ");
+            println!("{}", t!("languages.shown", languages = languages));
+        }
+        return Ok(());
+    }
+    let mut languages: Vec<String> = Vec::new();
+    for tag in &tags {
+        let tag = language::normalize(tag)?;
+        if !languages.contains(&tag) {
+            languages.push(tag);
+        }
+    }
+    session.languages = languages;
+    session.save()?;
+    let languages = session.languages.join(", ");
+    println!(
+        "{}",
+        out.success(&t!("languages.set", languages = languages))
+    );
+    Ok(())
+}
+
+/// `whoami --json`; also the start of `status --json`.
+fn identity_json(session: &SessionState) -> Value {
+    json!({
+        "nickname": (!session.nickname.is_empty()).then_some(&session.nickname),
+        "peer_id": session.peer_id,
+        "guest": session.guest,
+        "profile": session::profile(),
+        "active_room": session.rooms.active().map(|r| &r.name),
+        "rooms": session.rooms.rooms(),
+    })
+}
+
+fn whoami(out: &Output, session: &SessionState) -> Result<()> {
+    if out.is_json() {
+        return print_json(&identity_json(session));
+    }
+    if session.nickname.is_empty() {
+        println!("{}", out.warn(&t!("whoami.logged_out")));
+        return Ok(());
+    }
+    println!("{}", t!("whoami.nickname", nickname = session.nickname));
+    if session.guest {
+        println!("{}", t!("whoami.guest"));
+    }
+    if let Some(name) = session::profile() {
+        println!("{}", t!("whoami.profile", name = name));
+    }
+    println!("{}", t!("whoami.peer_id", peer_id = session.peer_id));
+    let Some(active) = session.rooms.active() else {
+        println!("{}", t!("whoami.no_room"));
+        return Ok(());
+    };
+    println!(
+        "{}",
+        t!("whoami.room", name = active.name, topic = active.topic_hex)
+    );
+    for r in session.rooms.rooms() {
+        if r.name != active.name {
+            println!(
+                "{}",
+                t!("whoami.other_room", name = r.name, topic = r.topic_hex)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn history(sub: HistoryCmd, ctx: &Ctx) -> Result<()> {
+    let store = HistoryStore::open();
+    match sub {
+        HistoryCmd::Purge { peer } => {
+            let removed = store.purge_peer(&ctx.config.history, &peer)?;
+            if ctx.out.is_json() {
+                return print_json(&json!({ "purged": removed, "peer": peer }));
+            }
+            println!("{}", t!("history.purged", count = removed, peer = peer));
+        }
+        HistoryCmd::Search {
+            query,
+            room,
+            peer,
+            limit,
+        } => {
+            let hits = store.query(&HistoryQuery {
+                text: query.clone(),
+                room,
+                peer,
+                limit,
+                ..HistoryQuery::default()
+            })?;
+            if ctx.out.is_json() {
+                return print_json(&hits);
+            }
+            for e in &hits {
+                let text = ctx.out.highlight(&e.text, &find_ranges(&e.text, &query));
+                let from = e.sender_nick.as_deref().unwrap_or(&e.sender_id);
+                println!(
+                    "{}",
+                    ctx.out.event(&UiEvent::Chat {
+                        from,
+                        text: &text,
+                        room: e.room.as_deref(),
+                    })
+                );
+            }
+            println!(
+                "{}",
+                t!("history.results", count = hits.len(), query = query)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn storage(sub: StorageCmd, ctx: &Ctx) -> Result<()> {
+    match sub {
+        StorageCmd::Status => {
+            let usage = storage::usage(&ctx.config.storage)?;
+            if ctx.out.is_json() {
+                return print_json(&usage);
+            }
+            println!("{}", ctx.out.heading(&t!("storage.title")));
+            for u in &usage {
+                let name = t!(&format!("storage.{}", u.category.key()));
+                let line = match u.quota {
+                    Some(quota) => t!(
+                        "storage.usage",
+                        category = name,
+                        used = fmt_bytes(u.bytes),
+                        quota = fmt_bytes(quota)
+                    ),
+                    None => t!(
+                        "storage.usage_unlimited",
+                        category = name,
+                        used = fmt_bytes(u.bytes)
+                    ),
+                };
+                if u.over_quota() {
+                    println!("{}", ctx.out.warn(&line));
+                } else {
+                    println!("{line}");
+                }
+            }
+        }
+        StorageCmd::Gc => {
+            let evicted = storage::gc(&ctx.config.storage)?;
+            if ctx.out.is_json() {
+                return print_json(&evicted);
+            }
+            if evicted.is_empty() {
+                println!("{}", t!("storage.gc_nothing"));
+            }
+            for e in &evicted {
+                println!(
+                    "{}",
+                    t!(
+                        &format!("storage.gc_{}", e.category.key()),
+                        count = e.items,
+                        freed = fmt_bytes(e.freed)
+                    )
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print the newest `last` stored global chat lines, oldest first.
+fn global_history(ctx: &Ctx, last: usize) -> Result<()> {
+    let now = now_ms();
+    let mut lines: Vec<_> = HistoryStore::open()
+        .query(&HistoryQuery {
+            scope: Some(HistoryScope::Global),
+            ..HistoryQuery::default()
+        })?
+        .into_iter()
+        .filter(|e| !ctx.config.history.is_expired(e, now))
+        .collect();
+    lines.drain(..lines.len().saturating_sub(last));
+    if ctx.out.is_json() {
+        return print_json(&lines);
+    }
+    if lines.is_empty() {
+        println!("{}", t!("history.empty"));
+    }
+    for e in &lines {
+        let from = e.sender_nick.as_deref().unwrap_or(&e.sender_id);
+        println!(
+            "{}",
+            ctx.out.event(&UiEvent::Chat {
+                from,
+                text: &e.text,
+                room: None,
+            })
+        );
+    }
+    Ok(())
+}
+
+/// Tell the user a remote claim won their nickname and let them take the
+/// suggested name or dispute; non-interactive sessions rename. Returns the
+/// guard for the nickname now in use.
+async fn nick_conflict(
+    transport: &dyn GossipTransport,
+    ctx: &Ctx,
+    session: &mut SessionState,
+    guard: &NickGuard,
+    lost: &NickConflictLost,
+) -> Result<Option<NickGuard>> {
+    let out = &ctx.out;
+    println!(
+        "{}",
+        out.event(&UiEvent::NickConflictLost {
+            nickname: &lost.nickname,
+            winner: &lost.winner_peer_id,
+        })
+    );
+    proof_of_work(out, &ctx.config.sybil, &session.peer_id).await;
+    let registry = NameRegistry::new(transport, ctx.config.timeouts).with_sybil(&ctx.config.sybil);
+    let rename = !std::io::stdin().is_terminal()
+        || onboarding::confirm(
+            &t!(
+                "nick.conflict_rename",
+                suggestion = lost.suggestion,
+                nickname = lost.nickname
+            ),
+            true,
+        )?;
+    if !rename {
+        registry.publish_claim(guard.claim()).await?;
+        println!("{}", t!("nick.disputed", nickname = lost.nickname));
+        return Ok(None);
+    }
+
+    let since = now_ms();
+    let (nickname, granted) = registry
+        .claim_unique_at(&lost.suggestion, &session.peer_id, since)
+        .await?;
+    session.nickname = nickname.clone();
+    session.nick_since = if granted { since } else { 0 };
+    session.save()?;
+    println!(
+        "{}",
+        out.warn(&t!(
+            "login.renamed",
+            name = lost.nickname,
+            nickname = nickname
+        ))
+    );
+    Ok(session.nick_claim().map(NickGuard::new))
+}
+
+/// Store a line we sent ourselves in the local history.
+fn remember_own(env: &Envelope<ChatMsg>, session: &SessionState) {
+    let nick = (!session.nickname.is_empty()).then(|| session.nickname.clone());
+    let _ = HistoryStore::open().append(&HistoryEntry::chat(env, nick));
+}
+
+pub(crate) async fn global_listen(
+    transport: &dyn GossipTransport,
+    ctx: &Ctx,
+    session: &mut SessionState,
+) -> Result<()> {
+    let topic = transport.topic_from_name(GLOBAL_CHAT_TOPIC_NAME);
+    let th = transport.join_topic(topic).await?;
+    println!("{}", ctx.out.heading(&t!("global.listening")));
+    let me = sender_id(transport, session);
+    listen_chat(transport, vec![(None, th)], ctx, session, &me).await?;
+    Goodbye::default().send(transport, ctx, &me).await;
+    Ok(())
+}
+
+/// Dial a room host, retrying per `[timeouts.retry]`; gossip may still reach
+/// the room through other peers, so failure is only logged.
+async fn connect_host(transport: &Node, ctx: &Ctx, addr: &str) -> Result<()> {
+    let host = &transport.parse_node_id_addr(addr)?;
+    if let Err(e) = retry(&ctx.config.timeouts.retry, || transport.connect(host)).await {
+        tracing::warn!("could not connect to host {addr}: {e}");
+    }
+    Ok(())
+}
+
+/// Dial `peers` in order; unreachable ones are only logged.
+async fn dial_bootstrap(transport: &Node, ctx: &Ctx, peers: &[&BootstrapPeer]) {
+    for peer in bootstrap::dial(transport, peers, ctx.config.timeouts.bootstrap()).await {
+        tracing::warn!("could not reach bootstrap peer {}", peer.name());
+    }
+}
+
+/// Subscribe to the joined rooms (only the active one in low-power mode).
+async fn subscribe_rooms(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &SessionState,
+) -> Result<Vec<(Option<String>, Box<dyn TopicHandle>)>> {
+    let me = sender_id(transport, session);
+    let active = session.rooms.active().map(|r| r.name.clone());
+    let mut subs = Vec::new();
+    for r in session.rooms.rooms() {
+        if !ctx.power.keep_inactive_rooms() && active.as_ref() != Some(&r.name) {
+            continue;
+        }
+        let rendezvous = bootstrap::rendezvous(&ctx.config.bootstrap, &r.name, &r.topic_hex);
+        dial_bootstrap(transport, ctx, &rendezvous).await;
+        if let Some(addr) = r.host_addr.as_ref().filter(|a| **a != me) {
+            connect_host(transport, ctx, addr).await?;
+        }
+        let th = transport
+            .join_topic(transport.topic_from_hex(&r.topic_hex)?)
+            .await?;
+        restore::rejoin(&*th, ctx, session, r, &me).await?;
+        subs.push((Some(r.name.clone()), th));
+    }
+    Ok(subs)
+}
+
+/// Rooms behind the labelled handles returned by [`subscribe_rooms`].
+fn subscribed(
+    subs: &[(Option<String>, Box<dyn TopicHandle>)],
+    session: &SessionState,
+) -> Vec<RoomRef> {
+    subs.iter()
+        .filter_map(|(label, _)| session.rooms.get(label.as_deref()?))
+        .cloned()
+        .collect()
+}
+
+/// Periodic broadcasts of an event loop, run by a [`Scheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Periodic {
+    /// Our heartbeat in every followed room.
+    Heartbeat,
+    /// Renewal of our nickname claim.
+    Renewal,
+    /// Digests of the tables we keep, see [`p2p_core::digest`].
+    Digest,
+}
+
+/// Heartbeats in `rooms` rooms and the claim renewal, on the periods of the
+/// power profile and within the budget of the node.
+pub(crate) fn schedule(ctx: &Ctx, rooms: usize) -> Scheduler<Periodic> {
+    let intervals = ctx.power.intervals();
+    let mut scheduler = Scheduler::new(ctx.budget.clone(), &ctx.config.scheduler);
+    scheduler.every(Periodic::Heartbeat, intervals.heartbeat, rooms as u32);
+    scheduler.every(Periodic::Renewal, intervals.claim_renewal, 1);
+    if let Some(every) = ctx.config.sync.digest_every() {
+        // One digest per table.
+        scheduler.every(Periodic::Digest, every, 2);
+    }
+    scheduler
+}
+
+/// Our digest of `table`, if we keep it and it has entries.
+fn our_digest(
+    table: SyncTable,
+    claims: &ClaimCache,
+    rooms: Option<&RoomCache>,
+) -> Option<TableDigest> {
+    let entries = match table {
+        SyncTable::Names => claims.entries(),
+        SyncTable::Rooms => rooms?.entries(),
+    };
+    (!entries.is_empty()).then(|| TableDigest::of(entries))
+}
+
+/// Publish digests of the name claims and, if given, the rooms we know.
+pub(crate) async fn publish_digests(
+    transport: &dyn GossipTransport,
+    ctx: &Ctx,
+    me: &str,
+    claims: &ClaimCache,
+    rooms: Option<&RoomCache>,
+) -> Result<()> {
+    let discovery = Discovery::new(transport, ctx.config.timeouts);
+    for table in [SyncTable::Names, SyncTable::Rooms] {
+        if let Some(digest) = our_digest(table, claims, rooms) {
+            discovery.digest(me, table, digest).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Ask for what a peer's digest has that ours lacks, and answer requests
+/// for our entries: claims go out again on the name registry topic
+/// (`names`), rooms as a room list.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn sync_tables(
+    transport: &dyn GossipTransport,
+    ctx: &Ctx,
+    me: &str,
+    env: &Envelope<DiscoveryBody>,
+    sync: &mut DigestSync,
+    names: &dyn TopicHandle,
+    claims: &ClaimCache,
+    rooms: Option<&RoomCache>,
+) -> Result<()> {
+    if env.sender_id == me {
+        return Ok(());
+    }
+    let discovery = Discovery::new(transport, ctx.config.timeouts);
+    match &env.body {
+        DiscoveryBody::Digest { table, digest } => {
+            let ours = match table {
+                SyncTable::Names => claims.digest(),
+                SyncTable::Rooms => match rooms {
+                    Some(rooms) => rooms.digest(),
+                    None => return Ok(()),
+                },
+            };
+            if let Some(buckets) =
+                sync.on_digest(&env.sender_id, *table, digest, &ours, Instant::now())
+            {
+                discovery
+                    .sync_request(me, &env.sender_id, *table, buckets)
+                    .await?;
+            }
+        }
+        DiscoveryBody::SyncReq {
+            peer_id,
+            table,
+            buckets,
+        } if peer_id == me && sync.on_request(&env.sender_id, *table, Instant::now()) => {
+            match table {
+                SyncTable::Names => {
+                    for frame in claims.in_buckets(buckets) {
+                        names.publish(&frame).await?;
+                    }
+                }
+                SyncTable::Rooms => {
+                    if let Some(rooms) = rooms
+                        .map(|r| r.in_buckets(buckets))
+                        .filter(|r| !r.is_empty())
+                    {
+                        discovery.sync_rooms(me, &env.msg_id, rooms).await?;
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Print chat lines from all `subs` (labelled by room) until one closes or
+/// the user presses Ctrl-C.
+///
+/// Advertises our wire [`Capabilities`](p2p_core::protocol::Capabilities)
+/// first so peers migrating the format know what we understand.
+async fn listen_chat(
+    transport: &dyn GossipTransport,
+    subs: Vec<(Option<String>, Box<dyn TopicHandle>)>,
+    ctx: &Ctx,
+    session: &mut SessionState,
+    me: &str,
+) -> Result<()> {
+    let (out, notifier) = (&ctx.out, &ctx.notifier);
+    let labels = subs.iter().filter_map(|(label, _)| label.as_deref());
+    let mut scheduler = schedule(ctx, labels.clone().count());
+    let mut members = RoomMembers::join(transport, ctx, session, labels).await?;
+    let mut feed = TopicFeed::default();
+    for (label, th) in subs {
+        th.publish(&ctx.wire.capabilities(me.to_string())).await?;
+        feed.add(label, th);
+    }
+    let history = HistoryStore::open();
+    let _ = history.enforce(&ctx.config.history);
+    let _ = storage::gc(&ctx.config.storage);
+    let mut compacted = Instant::now();
+    let mut stats = LocalStats::load().unwrap_or_default();
+    let mut last = Instant::now();
+    let mut detector = PartitionDetector::default();
+    for n in feed.neighbors() {
+        detector.watch(n.clone());
+    }
+    let names_topic = transport.topic_from_name(NAME_REGISTRY_TOPIC_NAME);
+    let mut names = transport.join_topic(names_topic).await?;
+    let mut guard = session.nick_claim().map(NickGuard::new);
+    let mut claims = ClaimCache::new(ctx.config.timeouts.nick_grace());
+    // Name claims and announcements skip the wire pipeline; drop repeats here.
+    let mut recent = RecentIds::default();
+    let discovery_topic = transport.topic_from_name(DISCOVERY_TOPIC_NAME);
+    let mut announcements = transport.join_topic(discovery_topic).await?;
+    let mut directory = PeerDirectory::load().unwrap_or_default();
+    let mut vouches = VouchBook::load().unwrap_or_default();
+    let mut ratings = RatingBook::load().unwrap_or_default();
+    let mut sync = DigestSync::default();
+    let inbox_topic = transport.topic_from_name(&inbox_topic_name(me));
+    let mut inbox = transport.join_topic(inbox_topic).await?;
+    if let Err(e) = archive::sign_up(transport, me).await {
+        tracing::debug!("mailbox sign-up: {e:#}");
+    }
+    let mut check = tokio::time::interval(Duration::from_secs(5));
+    // Chat lines, shown in causal order once held for a moment.
+    let mut order = OrderBuffer::default();
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        let flush = order
+            .next_due()
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(60));
+        let (room, bytes) = tokio::select! {
+            b = feed.next() => b?,
+            _ = &mut interrupted => {
+                for line in order.drain() {
+                    println!("{line}");
+                }
+                let _ = directory.save();
+                return Ok(());
+            }
+            _ = tokio::time::sleep_until(flush.into()) => {
+                for line in order.due(Instant::now()) {
+                    println!("{line}");
+                }
+                continue;
+            }
+            b = names.next() => {
+                let b = b?;
+                if let Some(env) =
+                    signing::open::<NameRelease>(&b).filter(|e| recent.insert(&e.msg_id))
+                {
+                    claims.release(&env);
+                    continue;
+                }
+                let Some(env) = signing::open::<NameClaim>(&b).filter(|e| recent.insert(&e.msg_id))
+                else {
+                    continue;
+                };
+                if let Some(pinned) =
+                    directory.set_nickname(&env.body.owner_peer_id, &env.body.nickname)
+                {
+                    println!(
+                        "{}",
+                        ctx.out.event(&UiEvent::KeyMismatch {
+                            nickname: &env.body.nickname,
+                            pinned: &pinned,
+                            seen: &env.body.owner_peer_id,
+                        })
+                    );
+                }
+                let owner = &env.body.owner_peer_id;
+                directory.proof(owner, env.body.pow);
+                if !ctx.config.sybil.established(owner, &directory, &vouches) {
+                    continue;
+                }
+                if let Some(defence) = claims.observe(&env, &b) {
+                    names.publish(&defence).await?;
+                }
+                if let Some(g) = guard.as_mut() {
+                    if g.defends(&env.body) {
+                        NameRegistry::new(transport, ctx.config.timeouts)
+                            .publish_claim(g.renew(ctx.config.timeouts.nick_lease()))
+                            .await?;
+                    } else if let Some(lost) = g.observe(&env.body) {
+                        guard = nick_conflict(transport, ctx, session, g, &lost).await?;
+                    }
+                }
+                continue;
+            }
+            b = inbox.next() => {
+                let b = b?;
+                if let Some(env) = ctx.wire.decode::<InviteBody>(&b) {
+                    if let InviteBody::Invite { game, .. } = &env.body
+                        && env.sig.is_some()
+                        && env.room_id.as_deref() == Some(me)
+                    {
+                        let from = directory
+                            .peers
+                            .get(&env.sender_id)
+                            .and_then(|p| p.nickname.clone())
+                            .unwrap_or_else(|| env.sender_id.clone());
+                        notifier.notify(NotifyEvent::DirectMessage);
+                        println!("{}", out.info(&t!("invite.received", peer = from, game = game)));
+                    }
+                    continue;
+                }
+                let Some(env) = ctx.wire.decode::<ChatMsg>(&b) else {
+                    continue;
+                };
+                if !matches!(env.scope, Scope::Direct) || env.room_id.as_deref() != Some(me) {
+                    continue;
+                }
+                directory.seen(&env.sender_id, None);
+                claims.owner_seen(&env.sender_id);
+                let _ = history.append(&HistoryEntry::chat(&env, None));
+                notifier.notify(NotifyEvent::DirectMessage);
+                let from = directory
+                    .peers
+                    .get(&env.sender_id)
+                    .and_then(|p| p.nickname.clone())
+                    .unwrap_or_else(|| env.sender_id.clone());
+                let text = ctx.clean(&env.body.text);
+                println!("{}", out.event(&UiEvent::Direct { from: &from, text: &text }));
+                continue;
+            }
+            b = announcements.next() => {
+                if let Some(env) = signing::open::<DiscoveryBody>(&b?)
+                    .filter(|e| recent.insert(&e.msg_id))
+                {
+                    directory.apply(&env.sender_id, &env.body);
+                    claims.owner_seen(&env.sender_id);
+                    if vouches.apply(&env) {
+                        let _ = vouches.save();
+                    }
+                    if ratings.apply(&env) {
+                        let _ = ratings.save();
+                    }
+                    sync_tables(transport, ctx, me, &env, &mut sync, &*names, &claims, None).await?;
+                }
+                continue;
+            }
+            task = scheduler.next() => {
+                match task {
+                    Periodic::Heartbeat => {
+                        members.beat(ctx, me, session).await?;
+                        print_notices(out, members.take_notices());
+                    }
+                    Periodic::Renewal => {
+                        if let Some(g) = guard.as_mut() {
+                            NameRegistry::new(transport, ctx.config.timeouts)
+                                .publish_claim(g.renew(ctx.config.timeouts.nick_lease()))
+                                .await?;
+                        }
+                    }
+                    Periodic::Digest => publish_digests(transport, ctx, me, &claims, None).await?,
+                }
+                continue;
+            }
+            _ = check.tick() => {
+                let _ = directory.save();
+                claims.expire();
+                if compacted.elapsed() >= COMPACT_EVERY {
+                    let _ = history.enforce(&ctx.config.history);
+                    let _ = storage::gc(&ctx.config.storage);
+                    compacted = Instant::now();
+                }
+                match detector.poll() {
+                    Some(ConnectivityChange::Isolated) => {
+                        println!("{}", out.warn(&t!("connectivity.isolated")));
+                    }
+                    Some(ConnectivityChange::Recovered) => {
+                        println!("{}", out.success(&t!("connectivity.recovered")));
+                    }
+                    None => {}
+                }
+                detector.publish();
+                continue;
+            }
+        };
+        if let Some(change) = ctx.wire.decode_change(&bytes) {
+            let from = change.sender_id();
+            if room.as_ref().is_some_and(|r| members.ignores(r, from)) {
+                continue;
+            }
+            let _ = history.apply(&change);
+            let line = match (change.new_text(), room.as_deref()) {
+                (Some(text), _) => {
+                    let text = ctx.clean(text);
+                    out.event(&UiEvent::Chat {
+                        from,
+                        text: &t!("chat.edited", text = text),
+                        room: room.as_deref(),
+                    })
+                }
+                (None, Some(room)) => out.info(&t!("chat.deleted_in", from = from, room = room)),
+                (None, None) => out.info(&t!("chat.deleted", from = from)),
+            };
+            order.push(change.stamp(), change.msg_id(), line, Instant::now());
+            continue;
+        }
+        let Some(env) = ctx.wire.decode::<ChatMsg>(&bytes) else {
+            if let Some(room) = &room
+                && members.handle(ctx, me, room, &bytes).await?
+            {
+                session.rooms.leave(room);
+                session.save()?;
+            }
+            print_notices(out, members.take_notices());
+            continue;
+        };
+        if let Some(room) = &room
+            && members.ignores(room, &env.sender_id)
+        {
+            continue;
+        }
+        stats.add_chat_time(last.elapsed().as_millis() as u64);
+        last = Instant::now();
+        stats.meet_peer(&env.sender_id);
+        claims.owner_seen(&env.sender_id);
+        directory.seen(&env.sender_id, room.as_deref());
+        if let Some(room) = &room {
+            ctx.presence.seen(room, &env.sender_id);
+            members.touch(room, &env.sender_id);
+        }
+        let _ = stats.save();
+        let _ = history.append(&HistoryEntry::chat(&env, None));
+        notifier.check_mention(&env.body.text, &session.nickname);
+        let text = ctx.clean(&env.body.text);
+        let from = match &env.body.relay {
+            Some(r) => t!("bridge.from", origin = r.origin_id, room = r.origin_room),
+            None => env.sender_id.clone(),
+        };
+        let line = out.event(&UiEvent::Chat {
+            from: &from,
+            text: &text,
+            room: room.as_deref(),
+        });
+        order.push(hlc::stamp(&env), &env.msg_id, line, Instant::now());
+    }
+}
+
+/// Resolves once the room `name` we host has had nobody else in it for
+/// `minutes`: no gossip neighbors on its topic and no one seen there. Never
+/// resolves for 0 minutes.
+async fn idle_for(ctx: &Ctx, name: &str, neighbors: Option<NeighborCount>, minutes: u64) {
+    if minutes == 0 {
+        return std::future::pending().await;
+    }
+    // Members heartbeat; a few missed beats mean they are gone.
+    let window = ctx.power.intervals().heartbeat * 3;
+    let mut timer = IdleTimer::new(Duration::from_secs(minutes * 60), Instant::now());
+    let mut check = tokio::time::interval(ctx.power.intervals().heartbeat);
+    loop {
+        check.tick().await;
+        let others =
+            neighbors.as_ref().map_or(0, |n| n.get() as u32) + ctx.presence.count(name, window);
+        if timer.observe(others, Instant::now()) {
+            return;
+        }
+    }
+}
+
+/// Print what [`RoomMembers`] collected.
+fn print_notices(out: &Output, notices: Vec<Notice>) {
+    for n in notices {
+        if n.warn {
+            println!("{}", out.warn(&n.text));
+        } else {
+            println!("{}", out.info(&n.text));
+        }
+    }
+}
+
+async fn room(sub: RoomCmd, ctx: &Ctx, session: &mut SessionState) -> Result<()> {
+    let out = &ctx.out;
+    match sub {
+        RoomCmd::Open {
+            name,
+            game,
+            settings,
+            copy,
+            max_players,
+            admit,
+            lang,
+            idle_close,
+        } => {
+            let game = game.map(|g| game_kind(g, settings)).transpose()?;
+            let idle_minutes = idle_close
+                .or(ctx.config.room_idle_minutes)
+                .unwrap_or(DEFAULT_IDLE_MINUTES);
+            let language = lang.as_deref().map(language::normalize).transpose()?;
+            let transport = Arc::new(ctx.start_node().await?);
+            restore::restore(&*transport, ctx, session).await?;
+            let peer_id = sender_id(&*transport, session);
+            let discovery = Discovery::new(&*transport, ctx.config.timeouts);
+            let (room_id, owned) = {
+                let _spinner = out.waiting(&t!("room.claiming", name = name));
+                discovery.claim_room_name(&name, &peer_id).await?
+            };
+            if !owned {
+                bail!(ProtocolError::NameTaken(t!("room.name_taken", name = name)));
+            }
+            let topic = transport.topic_from_name(&room_id);
+            let topic_hex = transport.topic_to_hex(&topic);
+            let ticket = RoomTicket {
+                topic_hex: topic_hex.clone(),
+                host_addr: peer_id.clone(),
+            };
+            let summary = RoomSummary {
+                room_id: room_id.clone(),
+                title: name.clone(),
+                host_id: peer_id.clone(),
+                last_seen: now_ms(),
+                ticket: Some(ticket.to_string()),
+                current_players: Some(1),
+                max_players,
+                game,
+                language,
+            };
+            let created_at = now_ms();
+            discovery.announce_room(&summary, created_at).await?;
+
+            session.rooms.join(RoomRef {
+                name: name.clone(),
+                topic_hex,
+                host_addr: Some(peer_id.clone()),
+                max_players,
+                admission: admit,
+                role: MemberRole::Player,
+            });
+            session.save()?;
+
+            if out.is_json() {
+                print_json(&json!({
+                    "type": "room_opened",
+                    "name": name,
+                    "topic": ticket.topic_hex,
+                    "ticket": ticket.to_string(),
+                }))?;
+            } else {
+                println!("{}", out.success(&t!("room.opened", name = name)));
+                println!("{}", t!("room.share", ticket = ticket));
+            }
+            if copy {
+                clipboard::copy_and_report(out, &ticket.to_string());
+            }
 
-    match args.command {
-        Command::Whoami => {
-            println!("(stub) node: not connected yet");
+            let presence = ctx.presence.clone();
+            let known_rooms = Arc::new(move || {
+                vec![RoomSummary {
+                    last_seen: now_ms(),
+                    current_players: Some(presence.count(&summary.title, PRESENCE_WINDOW) + 1),
+                    ..summary.clone()
+                }]
+            });
+            // The responder and announcer run supervised so a crash in them
+            // restarts them instead of closing the room.
+            let mut supervisor = Supervisor::new();
+            let node = transport.clone();
+            let timeouts = ctx.config.timeouts;
+            let rooms = known_rooms.clone();
+            supervisor.spawn("discovery responder", RestartPolicy::default(), move || {
+                let node = node.clone();
+                let known_rooms = rooms.clone();
+                async move {
+                    Discovery::new(&*node, timeouts)
+                        .serve_discovery(move || known_rooms())
+                        .await
+                }
+            });
+            let node = transport.clone();
+            let every = ctx.power.intervals().reannounce;
+            let (budget, scheduling) = (ctx.budget.clone(), ctx.config.scheduler);
+            let meter = ctx.meter.clone();
+            supervisor.spawn("room announcer", RestartPolicy::default(), move || {
+                let node = node.clone();
+                let known_rooms = known_rooms.clone();
+                let meter = meter.clone();
+                let mut scheduler = Scheduler::new(budget.clone(), &scheduling);
+                async move {
+                    let discovery = Discovery::new(&*node, timeouts);
+                    scheduler.every_from((), every, 1, Instant::now() + every);
+                    loop {
+                        scheduler.next().await;
+                        // Over the bandwidth cap the room stays listed only
+                        // through answers to list requests.
+                        if !meter.allow_background() {
+                            continue;
+                        }
+                        for room in known_rooms() {
+                            discovery.announce_room(&room, created_at).await?;
+                        }
+                    }
+                }
+            });
+            let subs = subscribe_rooms(&transport, ctx, session).await?;
+            let goodbye = Goodbye {
+                rooms: subscribed(&subs, session),
+                hosted: Some((room_id, name.clone())),
+            };
+            let neighbors = subs
+                .iter()
+                .find(|(label, _)| label.as_deref() == Some(name.as_str()))
+                .and_then(|(_, th)| th.neighbors());
+            let idle = idle_for(ctx, &name, neighbors, idle_minutes);
+            let since = now_ms();
+            tokio::select! {
+                res = listen_chat(&*transport, subs, ctx, session, &peer_id) => res?,
+                _ = idle => {
+                    let text = t!("room.idle_closed", name = name, minutes = idle_minutes);
+                    println!("{}", out.info(&text));
+                }
+            }
+            drop(supervisor);
+            let room = session.rooms.get(&name).cloned();
+            if let Some(room) = room
+                && let Err(e) =
+                    summary::game_night(&transport, ctx, &room, since, &peer_id, true).await
+            {
+                println!("{}", out.warn(&e.to_string()));
+            }
+            goodbye.send(&*transport, ctx, &peer_id).await;
+        }
+        RoomCmd::Join {
+            ticket,
+            addr,
+            topic,
+            name,
+            spectate,
+        } => {
+            let ticket = match (ticket, addr, topic) {
+                (Some(t), _, _) => t.parse()?,
+                (None, Some(host_addr), Some(topic_hex)) => RoomTicket {
+                    topic_hex,
+                    host_addr,
+                },
+                _ => unreachable!("clap requires --ticket or --addr and --topic"),
+            };
+            let transport = ctx.start_node().await?;
+            let role = if spectate {
+                MemberRole::Spectator
+            } else {
+                MemberRole::Player
+            };
+            join_room(&transport, ctx, session, ticket, name, role).await?;
+        }
+        RoomCmd::Leave { name } => {
+            let name = match name {
+                Some(n) => n,
+                None => session
+                    .rooms
+                    .active()
+                    .map(|r| r.name.clone())
+                    .ok_or_else(|| ProtocolError::NoActiveRoom(t!("room.none_active")))?,
+            };
+            let room = session
+                .rooms
+                .leave(&name)
+                .ok_or_else(|| ProtocolError::NotFound(t!("room.unknown", name = name)))?;
+            session.save()?;
+            println!("{}", out.info(&t!("room.left", name = room.name)));
         }
-        Command::Say { text } => {
-            println!("(stub) would send to global: {}", text);
+        RoomCmd::Switch { name } => {
+            let room = session
+                .rooms
+                .switch(&name)
+                .map_err(|_| ProtocolError::NotFound(t!("room.unknown", name = name)))?
+                .name
+                .clone();
+            session.save()?;
+            println!("{}", out.success(&t!("room.switched", name = room)));
+        }
+        RoomCmd::Say { text } => {
+            let active = session
+                .rooms
+                .active()
+                .cloned()
+                .ok_or_else(|| ProtocolError::NoActiveRoom(t!("room.none_active")))?;
+            let text = ctx
+                .config
+                .canned
+                .expand(&text, Some(&active.name))
+                .unwrap_or(text);
+            let topic = active.topic_hex;
+            let transport = ctx.start_node().await?;
+            if let Some(addr) = &active.host_addr {
+                connect_host(&transport, ctx, addr).await?;
+            }
+            let th = transport
+                .join_topic(transport.topic_from_hex(&topic)?)
+                .await?;
+            let env = make_chat_room(topic, sender_id(&transport, session), text);
+            remember_own(&env, session);
+            for frame in ctx.wire.encode(&env) {
+                th.publish(&frame).await?;
+            }
+        }
+        RoomCmd::Kick { peer, reason, ban } => {
+            let transport = ctx.start_node().await?;
+            moderation::kick(&transport, ctx, session, &peer, reason, ban).await?;
+        }
+        RoomCmd::Ban { peer, reason } => {
+            let transport = ctx.start_node().await?;
+            moderation::kick(&transport, ctx, session, &peer, reason, true).await?;
+        }
+        RoomCmd::Unban { peer } => {
+            let transport = ctx.start_node().await?;
+            moderation::unban(&transport, ctx, session, &peer)?;
+        }
+        RoomCmd::Bans => moderation::list(ctx, session)?,
+        RoomCmd::List { lang } => {
+            let lang = lang.as_deref().map(language::normalize).transpose()?;
+            let transport = ctx.start_node().await?;
+            let mut rooms = {
+                let _spinner = out.waiting(&t!("room.listing"));
+                Discovery::new(&transport, ctx.config.timeouts)
+                    .list_rooms()
+                    .await?
+            };
+            if let Some(lang) = &lang {
+                rooms.retain(|r| {
+                    r.language
+                        .as_deref()
+                        .is_some_and(|l| language::same(l, lang))
+                });
+            }
+            if out.is_json() {
+                return print_json(&rooms);
+            }
+            if rooms.is_empty() {
+                println!("{}", t!("room.list_empty"));
+            }
+            for r in rooms {
+                println!(
+                    "{}",
+                    t!(
+                        "room.list_entry",
+                        title = r.title,
+                        details = room_details(&r),
+                        room_id = r.room_id,
+                        host = r.host_id
+                    )
+                );
+            }
+        }
+        RoomCmd::Game { sub } => match sub {
+            GameCmd::List => play::list(out)?,
+            GameCmd::Start {
+                game,
+                vs,
+                players,
+                order,
+                seed,
+            } => {
+                let transport = ctx.start_node().await?;
+                let role = Role::Host {
+                    game,
+                    vs,
+                    seats: players,
+                    order,
+                    seed,
+                };
+                play::run(&transport, ctx, session, role).await?;
+            }
+            GameCmd::Invite {
+                peer,
+                game,
+                settings,
+                order,
+            } => {
+                let game = game_kind(game, settings)?;
+                let transport = ctx.start_node().await?;
+                dm::invite(&transport, ctx, session, &peer, game, order).await?;
+            }
+            GameCmd::Play => {
+                let transport = ctx.start_node().await?;
+                play::run(&transport, ctx, session, Role::Guest).await?;
+            }
+            GameCmd::Watch => {
+                let transport = ctx.start_node().await?;
+                play::run(&transport, ctx, session, Role::Watch).await?;
+            }
+        },
+        RoomCmd::Browse => {
+            let transport = ctx.start_node().await?;
+            if let Some((ticket, title)) = browse_rooms(&transport, ctx).await? {
+                let role = MemberRole::Player;
+                join_room(&transport, ctx, session, ticket, Some(title), role).await?;
+            }
         }
     }
+    Ok(())
+}
 
+/// Remember the room, make it active and follow all joined rooms.
+async fn join_room(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &mut SessionState,
+    ticket: RoomTicket,
+    name: Option<String>,
+    role: MemberRole,
+) -> Result<()> {
+    let topic = ticket.topic_hex;
+    if !ctx.safe.allows_room(&topic, name.as_deref()) {
+        bail!(ProtocolError::JoinRejected(t!(
+            "safe.room_blocked",
+            room = topic
+        )));
+    }
+    restore::restore(transport, ctx, session).await?;
+    transport.topic_from_hex(&topic)?;
+    let name = name.unwrap_or_else(|| topic.chars().take(8).collect());
+    session.rooms.join(RoomRef {
+        name: name.clone(),
+        topic_hex: topic.clone(),
+        host_addr: Some(ticket.host_addr),
+        max_players: None,
+        admission: None,
+        role,
+    });
+    session.save()?;
+
+    let key = match role {
+        MemberRole::Player => "room.joined",
+        MemberRole::Spectator => "room.spectating",
+    };
+    println!("{}", ctx.out.success(&t!(key, name = name, topic = topic)));
+    let subs = subscribe_rooms(transport, ctx, session).await?;
+    let me = sender_id(transport, session);
+    let goodbye = Goodbye {
+        rooms: subscribed(&subs, session),
+        hosted: None,
+    };
+    let since = now_ms();
+    listen_chat(transport, subs, ctx, session, &me).await?;
+    if let Some(room) = session.rooms.get(&name).cloned()
+        && let Err(e) = summary::game_night(transport, ctx, &room, since, &me, false).await
+    {
+        println!("{}", ctx.out.warn(&e.to_string()));
+    }
+    goodbye.send(transport, ctx, &me).await;
     Ok(())
 }
+
+/// The `--game` of `room open`, checked against the games we can play.
+fn game_kind(name: String, settings: Option<String>) -> Result<GameKind> {
+    if !GameRegistry::builtin().knows(&name) {
+        bail!(t!("game.unknown", game = name));
+    }
+    let mut game = GameKind::new(name);
+    if let Some(s) = settings {
+        game.settings = match serde_json::from_str(&s) {
+            Ok(v @ Value::Object(_)) => v,
+            Ok(_) => bail!(t!("game.bad_settings", error = s)),
+            Err(e) => bail!(t!("game.bad_settings", error = e)),
+        };
+    }
+    Ok(game)
+}
+
+/// Game and player count of a listed room, e.g. "tictactoe, 1/2 player(s)".
+fn room_details(r: &RoomSummary) -> String {
+    let players = match (r.current_players, r.max_players) {
+        (Some(n), Some(max)) => t!("room.players_of", count = n, max = max),
+        (Some(n), None) => t!("room.players", count = n),
+        (None, _) => t!("room.players_unknown"),
+    };
+    let details = match &r.game {
+        Some(game) => t!("room.details_game", game = game, players = players),
+        None => players,
+    };
+    match &r.language {
+        Some(language) => t!(
+            "room.details_language",
+            details = details,
+            language = language
+        ),
+        None => details,
+    }
+}
+
+/// Numbered room list; pick a number to join, Enter to refresh, `q` to quit.
+async fn browse_rooms(transport: &Node, ctx: &Ctx) -> Result<Option<(RoomTicket, String)>> {
+    let out = &ctx.out;
+    let mut cache = RoomCache::default();
+    let mut browser = RoomBrowser::default();
+    let registry = GameRegistry::builtin();
+    loop {
+        let rooms = {
+            let _spinner = out.waiting(&t!("room.listing"));
+            Discovery::new(transport, ctx.config.timeouts)
+                .list_rooms()
+                .await?
+        };
+        for r in rooms {
+            cache.insert(r);
+        }
+        cache.prune(ROOM_TTL_MS);
+        browser.refresh(&cache);
+
+        println!("{}", out.heading(&t!("room.browse_title")));
+        if browser.rooms().is_empty() {
+            println!("{}", t!("room.list_empty"));
+        }
+        for (i, r) in browser.rooms().iter().enumerate() {
+            let mut line = t!(
+                "room.browse_entry",
+                index = i + 1,
+                title = r.title,
+                details = room_details(r)
+            );
+            if r.ticket.is_none() {
+                line = format!("{line} {}", t!("room.no_ticket"));
+            }
+            println!("{line}");
+        }
+
+        let answer = onboarding::prompt(&t!("room.browse_prompt"))?;
+        match answer.as_str() {
+            "" => continue,
+            "q" | "Q" => return Ok(None),
+            n => {
+                let picked = n
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|i| browser.select(i).cloned());
+                let Some(room) = picked else {
+                    println!("{}", out.warn(&t!("room.browse_invalid", answer = n)));
+                    continue;
+                };
+                if let Some(game) = &room.game
+                    && !registry.knows(&game.name)
+                {
+                    let text = t!("room.game_unsupported", game = game);
+                    println!("{}", out.warn(&text));
+                    continue;
+                }
+                match browser.selected_ticket() {
+                    Some(ticket) => return Ok(Some((ticket, room.title))),
+                    None => println!("{}", out.warn(&t!("room.no_ticket"))),
+                }
+            }
+        }
+    }
+}
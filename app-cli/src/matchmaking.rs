@@ -0,0 +1,264 @@
+//! `p2p-games match <game>`: queue for an opponent, then play them in a
+//! room of two (see [`p2p_core::matchmaking`]).
+
+use anyhow::Result;
+use p2p_core::{
+    directory::PeerDirectory,
+    game::GameRegistry,
+    matchmaking::{
+        MATCHMAKING_TOPIC_NAME, MatchBody, Queue, SEEK_REFRESH, Seeker, hosts, make_match,
+    },
+    protocol::{MemberRole, now_ms},
+    rating::RatingBook,
+    rooms::RoomRef,
+    scheduler::Scheduler,
+    session::SessionState,
+    signing, t,
+    turn::TurnOrder,
+    vouch::VouchBook,
+};
+use serde_json::json;
+use std::time::Duration;
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle};
+
+use crate::{
+    Ctx, Node,
+    play::{self, Role},
+    print_json, restore, sender_id,
+};
+
+/// How often the host of a pair repeats its offer.
+const OFFER_EVERY: Duration = Duration::from_secs(2);
+
+/// How long the guest waits after accepting, so the host is in the room
+/// before the guest asks for its seat.
+const JOIN_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Clone, PartialEq)]
+enum Task {
+    Seek,
+    Pair,
+}
+
+/// A pair agreed on: the room and who hosts it.
+pub(crate) struct Paired {
+    pub(crate) partner: String,
+    pub(crate) topic_hex: String,
+    pub(crate) host: bool,
+    /// Seat order if we host.
+    pub(crate) order: TurnOrder,
+}
+
+async fn publish(th: &dyn TopicHandle, me: &str, body: MatchBody) -> Result<()> {
+    th.publish(&signing::to_signed_vec(&make_match(me.to_string(), body)))
+        .await
+}
+
+/// Wait in the queue for `game` until paired, then play the match.
+pub(crate) async fn run(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &mut SessionState,
+    game: &str,
+    range: u32,
+) -> Result<()> {
+    let out = &ctx.out;
+    // Fail on unknown games before queueing for one.
+    GameRegistry::builtin().create(game, 2)?;
+    restore::restore(transport, ctx, session).await?;
+    let me = sender_id(transport, session);
+    let rating = RatingBook::load()
+        .unwrap_or_default()
+        .rating(&me, game)
+        .round() as u32;
+    let sybil = &ctx.config.sybil;
+    let since = now_ms();
+    let seek = MatchBody::Seek {
+        game: game.to_string(),
+        rating,
+        range,
+        since,
+        languages: session.languages.clone(),
+        pow: crate::proof_of_work(out, sybil, &me).await,
+    };
+    let mut seeker = Seeker {
+        game: game.to_string(),
+        rating,
+        range,
+        since,
+        languages: session.languages.clone(),
+        seen: since,
+    };
+    let mut th = transport
+        .join_topic(transport.topic_from_name(MATCHMAKING_TOPIC_NAME))
+        .await?;
+    println!(
+        "{}",
+        t!(
+            "matchmaking.seeking",
+            game = game,
+            rating = rating,
+            range = range
+        )
+    );
+    let mut queue = Queue::default();
+    // Seekers we do not consider established are left out of our queue.
+    let mut directory = PeerDirectory::load().unwrap_or_default();
+    let vouches = VouchBook::load().unwrap_or_default();
+    let mut scheduler = Scheduler::new(ctx.budget.clone(), &ctx.config.scheduler);
+    scheduler.every(Task::Seek, SEEK_REFRESH, 1);
+    scheduler.every(Task::Pair, OFFER_EVERY, 1);
+    // Our open offer as host: partner and room topic.
+    let mut offered: Option<(String, String)> = None;
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    let paired = loop {
+        tokio::select! {
+            b = th.next() => {
+                let Some(env) = signing::open::<MatchBody>(&b?) else {
+                    continue;
+                };
+                if let MatchBody::Seek { pow, .. } = &env.body
+                    && env.sender_id != me
+                {
+                    directory.seen(&env.sender_id, None);
+                    directory.proof(&env.sender_id, *pow);
+                    if !sybil.established(&env.sender_id, &directory, &vouches) {
+                        continue;
+                    }
+                }
+                queue.apply(&env);
+                match env.body {
+                    MatchBody::Offer { peer_id, game: offered_game, topic_hex }
+                        if peer_id == me
+                            && offered_game == game
+                            && env.sig.is_some()
+                            && queue.partner(&me).as_deref() == Some(&env.sender_id) =>
+                    {
+                        let body = MatchBody::Accept {
+                            peer_id: env.sender_id.clone(),
+                            topic_hex: topic_hex.clone(),
+                        };
+                        publish(&*th, &me, body).await?;
+                        break Paired {
+                            partner: env.sender_id,
+                            topic_hex,
+                            host: false,
+                            order: TurnOrder::Random,
+                        };
+                    }
+                    MatchBody::Accept { peer_id, topic_hex }
+                        if peer_id == me
+                            && env.sig.is_some()
+                            && offered
+                                .as_ref()
+                                .is_some_and(|(p, t)| *p == env.sender_id && *t == topic_hex) =>
+                    {
+                        break Paired {
+                            partner: env.sender_id,
+                            topic_hex,
+                            host: true,
+                            order: TurnOrder::Random,
+                        };
+                    }
+                    _ => {}
+                }
+            }
+            task = scheduler.next() => match task {
+                Task::Seek => {
+                    seeker.seen = now_ms();
+                    queue.insert(&me, seeker.clone());
+                    publish(&*th, &me, seek.clone()).await?;
+                }
+                Task::Pair => {
+                    queue.expire(now_ms());
+                    let partner = queue.partner(&me).filter(|p| hosts(&me, p));
+                    let Some(partner) = partner else {
+                        offered = None;
+                        continue;
+                    };
+                    if offered.as_ref().is_none_or(|(p, _)| *p != partner) {
+                        let topic = transport
+                            .topic_from_name(&format!("match-{}", uuid::Uuid::new_v4()));
+                        offered = Some((partner.clone(), transport.topic_to_hex(&topic)));
+                    }
+                    if let Some((peer_id, topic_hex)) = offered.clone() {
+                        let body = MatchBody::Offer {
+                            peer_id,
+                            game: game.to_string(),
+                            topic_hex,
+                        };
+                        publish(&*th, &me, body).await?;
+                    }
+                }
+            },
+            _ = &mut interrupted => {
+                publish(&*th, &me, MatchBody::Cancel).await?;
+                println!("{}", out.info(&t!("matchmaking.cancelled")));
+                return Ok(());
+            }
+        }
+    };
+    if paired.host {
+        // The guest's accept took it out of every queue; take us out too.
+        publish(&*th, &me, MatchBody::Cancel).await?;
+    }
+    drop(th);
+    let short: String = paired.partner.chars().take(8).collect();
+    let name = format!("{game}-{short}");
+    play_paired(transport, ctx, session, game, name, paired, &me).await
+}
+
+/// Remember the room of the pair as `name`, make it active and play the
+/// match there.
+pub(crate) async fn play_paired(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &mut SessionState,
+    game: &str,
+    name: String,
+    paired: Paired,
+    me: &str,
+) -> Result<()> {
+    let short: String = paired.partner.chars().take(8).collect();
+    let host_addr = if paired.host {
+        me.to_string()
+    } else {
+        paired.partner.clone()
+    };
+    session.rooms.join(RoomRef {
+        name: name.clone(),
+        topic_hex: paired.topic_hex.clone(),
+        host_addr: Some(host_addr),
+        max_players: Some(2),
+        admission: None,
+        role: MemberRole::Player,
+    });
+    session.save()?;
+    if ctx.out.is_json() {
+        print_json(&json!({
+            "type": "match_found",
+            "peer_id": paired.partner,
+            "room": name,
+            "topic": paired.topic_hex,
+        }))?;
+    } else {
+        let text = t!("matchmaking.paired", player = short, room = name);
+        println!("{}", ctx.out.success(&text));
+    }
+    if !paired.host {
+        tokio::time::sleep(JOIN_GRACE).await;
+    }
+    let role = if paired.host {
+        Role::Host {
+            game: game.to_string(),
+            vs: vec![paired.partner],
+            seats: 2,
+            order: paired.order,
+            seed: None,
+        }
+    } else {
+        Role::Guest
+    };
+    play::run(transport, ctx, session, role).await
+}
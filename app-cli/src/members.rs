@@ -0,0 +1,449 @@
+//! Heartbeats, member lists and bans of the rooms a listener follows, and
+//! the join requests of rooms we host (see [`p2p_core::admission`]).
+
+use anyhow::Result;
+use p2p_core::{
+    admission::{Admission, AdmissionPolicy},
+    bans::BanList,
+    directory::PeerDirectory,
+    membership::MemberTracker,
+    protocol::{Kind, Member, MemberRole, RoomBody, Scope, make_envelope, now_ms},
+    rooms::RoomRef,
+    session::SessionState,
+    t, titles,
+    vouch::VouchBook,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle};
+
+use crate::Ctx;
+
+struct Room {
+    room: RoomRef,
+    /// Publishing handle; the feed holds the receiving one.
+    th: Box<dyn TopicHandle>,
+    tracker: MemberTracker,
+}
+
+impl Room {
+    fn hosted_by(&self, peer_id: &str) -> bool {
+        self.room.host_addr.as_deref() == Some(peer_id)
+    }
+
+    fn is_member(&self, peer_id: &str) -> bool {
+        self.tracker.members().iter().any(|m| m.peer_id == peer_id)
+    }
+
+    /// Whether the room is at its cap for a newcomer `peer_id`; the host
+    /// counts as a player but is not in its own member list. Spectators
+    /// neither count nor are turned away.
+    fn full_for(&self, peer_id: &str, role: MemberRole) -> bool {
+        let players = self
+            .tracker
+            .members()
+            .iter()
+            .filter(|m| m.role.is_player())
+            .count() as u32;
+        role.is_player()
+            && self
+                .room
+                .max_players
+                .is_some_and(|max| !self.is_member(peer_id) && players + 1 >= max)
+    }
+}
+
+/// Something a front end shows the user.
+pub(crate) struct Notice {
+    pub text: String,
+    /// We were removed from a room.
+    pub warn: bool,
+}
+
+impl Notice {
+    fn info(text: String) -> Self {
+        Self { text, warn: false }
+    }
+
+    fn warn(text: String) -> Self {
+        Self { text, warn: true }
+    }
+}
+
+/// Membership of every followed room, keyed by room label.
+pub(crate) struct RoomMembers {
+    rooms: BTreeMap<String, Room>,
+    bans: BanList,
+    /// Rooms we were removed from; their traffic is ignored.
+    removed: BTreeSet<String>,
+    /// Lines for the user, collected until [`Self::take_notices`].
+    notices: Vec<Notice>,
+    /// Whether the front end asks the host about joins ([`Self::prompt_host`]).
+    prompts: bool,
+    /// Joins waiting for the host, collected until [`Self::take_requests`].
+    requests: Vec<JoinRequest>,
+}
+
+impl RoomMembers {
+    /// Track the rooms behind `labels`.
+    pub async fn join(
+        transport: &dyn GossipTransport,
+        ctx: &Ctx,
+        session: &SessionState,
+        labels: impl Iterator<Item = &str>,
+    ) -> Result<Self> {
+        let mut members = Self {
+            rooms: BTreeMap::new(),
+            bans: BanList::load().unwrap_or_default(),
+            removed: BTreeSet::new(),
+            notices: Vec::new(),
+            prompts: false,
+            requests: Vec::new(),
+        };
+        for label in labels {
+            members.add(transport, ctx, session, label).await?;
+        }
+        Ok(members)
+    }
+
+    /// Also track the room behind `label`, e.g. one joined after the start.
+    pub async fn add(
+        &mut self,
+        transport: &dyn GossipTransport,
+        ctx: &Ctx,
+        session: &SessionState,
+        label: &str,
+    ) -> Result<()> {
+        let Some(room) = session.rooms.get(label).cloned() else {
+            return Ok(());
+        };
+        // A member is only dropped after it missed a few heartbeats, even
+        // if the configured timeout is shorter than the (low-power) period.
+        let stale_after = ctx
+            .config
+            .timeouts
+            .member_stale()
+            .max(ctx.power.intervals().heartbeat * 3);
+        let th = transport
+            .join_topic(transport.topic_from_hex(&room.topic_hex)?)
+            .await?;
+        self.rooms.insert(
+            label.to_string(),
+            Room {
+                room,
+                th,
+                tracker: MemberTracker::new(stale_after),
+            },
+        );
+        Ok(())
+    }
+
+    /// Send our heartbeat, drop silent members and, in rooms we host,
+    /// broadcast the member list if it changed.
+    pub async fn beat(&mut self, ctx: &Ctx, me: &str, session: &SessionState) -> Result<()> {
+        // `room ban` and `room unban` run as separate processes.
+        self.bans = BanList::load().unwrap_or_default();
+        let background = ctx.meter.allow_background();
+        let now = now_ms();
+        for (label, r) in &mut self.rooms {
+            if self.removed.contains(label) {
+                continue;
+            }
+            let room_id = &r.room.topic_hex;
+            if background {
+                let body = RoomBody::Heartbeat {
+                    room_id: room_id.clone(),
+                    nickname: session.nickname.clone(),
+                    title: session.title.clone(),
+                    role: r.room.role,
+                };
+                publish(ctx, &*r.th, room_id, me, body).await?;
+            }
+            for nick in r.tracker.sweep(now) {
+                let text = t!("members.stale", nickname = nick, room = label);
+                self.notices.push(Notice::info(text));
+            }
+            let changed = r.tracker.take_changed();
+            if changed && r.hosted_by(me) {
+                let body = r.tracker.update(room_id, me);
+                publish(ctx, &*r.th, room_id, me, body).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle `bytes` from `label` if it is a room control message; returns
+    /// whether we were banned from that room or turned away.
+    pub async fn handle(&mut self, ctx: &Ctx, me: &str, label: &str, bytes: &[u8]) -> Result<bool> {
+        let Some(r) = self.rooms.get_mut(label) else {
+            return Ok(false);
+        };
+        let Some(env) = ctx.wire.decode::<RoomBody>(bytes) else {
+            return Ok(false);
+        };
+        let room_id = &r.room.topic_hex;
+        let sender = env.sender_id.as_str();
+        if self.removed.contains(label) || self.bans.is_banned(room_id, sender) {
+            if r.hosted_by(me) && matches!(env.body, RoomBody::JoinReq { .. }) {
+                let reason = self
+                    .bans
+                    .get(room_id, sender)
+                    .and_then(|b| b.reason.clone());
+                let ack = RoomBody::JoinAck {
+                    room_id: room_id.clone(),
+                    accept: false,
+                    reason,
+                    peer_id: Some(sender.to_string()),
+                    full: false,
+                    declined: false,
+                };
+                publish(ctx, &*r.th, room_id, me, ack).await?;
+            }
+            return Ok(false);
+        }
+        if let RoomBody::JoinReq { role, .. } = env.body
+            && r.hosted_by(me)
+            && r.full_for(sender, role)
+        {
+            let ack = RoomBody::JoinAck {
+                room_id: room_id.clone(),
+                accept: false,
+                reason: Some(t!("room.full_reason")),
+                peer_id: Some(sender.to_string()),
+                full: true,
+                declined: false,
+            };
+            publish(ctx, &*r.th, room_id, me, ack).await?;
+            return Ok(false);
+        }
+        if let RoomBody::JoinReq { nickname, .. } = &env.body
+            && r.hosted_by(me)
+            && !r.is_member(sender)
+        {
+            let policy = AdmissionPolicy::effective(
+                r.room.admission.or(ctx.config.admission),
+                &ctx.config.vouch,
+            );
+            let directory = PeerDirectory::load().unwrap_or_default();
+            let vouches = VouchBook::load().unwrap_or_default();
+            let request = JoinRequest {
+                label: label.to_string(),
+                peer_id: sender.to_string(),
+                nickname: nickname.clone(),
+                vouches: vouches.from_friends(sender, &ctx.config.vouch, &directory),
+            };
+            match policy.decide(sender, &ctx.config.vouch, &vouches, &directory) {
+                Admission::Accept => {
+                    publish(ctx, &*r.th, room_id, me, request.ack(room_id, true)).await?;
+                    if policy != AdmissionPolicy::Open {
+                        self.notices
+                            .push(Notice::info(request.text("room.auto_admitted")));
+                    }
+                }
+                Admission::Decline => {
+                    publish(ctx, &*r.th, room_id, me, request.ack(room_id, false)).await?;
+                    let text = t!(
+                        "room.declined_notice",
+                        peer = request.nickname,
+                        room = label,
+                        policy = t!(&format!("admission.{}", policy.key()))
+                    );
+                    self.notices.push(Notice::info(text));
+                }
+                // Without a front end to ask, the peer is in until the
+                // host kicks it.
+                Admission::Ask => {
+                    self.notices
+                        .push(Notice::info(request.text("room.join_request")));
+                    if self.prompts {
+                        self.requests.push(request);
+                    }
+                }
+            }
+        }
+        match env.body {
+            RoomBody::Heartbeat {
+                nickname,
+                title,
+                role,
+                ..
+            } => {
+                let title = title.as_deref().filter(|t| titles::find(t).is_some());
+                r.tracker.heartbeat(sender, &nickname, title, role);
+            }
+            RoomBody::Kick {
+                peer_id,
+                reason,
+                ban,
+                ..
+            } if r.hosted_by(sender) => {
+                let reason = reason.unwrap_or_default();
+                if peer_id == me {
+                    let key = if ban { "room.banned" } else { "room.kicked" };
+                    let text = t!(key, room = label, reason = reason);
+                    self.notices.push(Notice::warn(text));
+                    self.removed.insert(label.to_string());
+                    return Ok(ban);
+                }
+                r.tracker.left(&peer_id);
+                if ban {
+                    self.bans.ban(room_id, &peer_id, Some(reason.clone()));
+                    let _ = self.bans.save();
+                }
+                let who = short(&peer_id);
+                self.notices.push(Notice::info(t!(
+                    "room.kick_notice",
+                    peer = who,
+                    room = label,
+                    reason = reason
+                )));
+            }
+            RoomBody::JoinAck {
+                accept: false,
+                reason,
+                peer_id: Some(peer_id),
+                full,
+                declined,
+                ..
+            } if peer_id == me && r.hosted_by(sender) => {
+                let reason = reason.unwrap_or_default();
+                let key = if full {
+                    "room.full"
+                } else if declined {
+                    "room.declined"
+                } else {
+                    "room.banned"
+                };
+                let text = t!(key, room = label, reason = reason);
+                self.notices.push(Notice::warn(text));
+                self.removed.insert(label.to_string());
+                return Ok(!full);
+            }
+            RoomBody::Leave { .. } | RoomBody::Close { .. } => {
+                r.tracker.left(sender);
+            }
+            _ => r.tracker.touch(sender),
+        }
+        Ok(false)
+    }
+
+    /// Whether traffic from `peer_id` in `label` is to be dropped: the peer
+    /// is banned there, or we were removed from the room.
+    pub fn ignores(&self, label: &str, peer_id: &str) -> bool {
+        self.removed.contains(label)
+            || self
+                .rooms
+                .get(label)
+                .is_some_and(|r| self.bans.is_banned(&r.room.topic_hex, peer_id))
+    }
+
+    /// Live members of `label`.
+    pub fn members(&self, label: &str) -> Vec<Member> {
+        self.rooms
+            .get(label)
+            .map(|r| r.tracker.members())
+            .unwrap_or_default()
+    }
+
+    /// Hand joins the admission policy leaves to the host to
+    /// [`Self::take_requests`] instead of letting them in.
+    pub fn prompt_host(&mut self) {
+        self.prompts = true;
+    }
+
+    /// Joins waiting for the host since the last call.
+    pub fn take_requests(&mut self) -> Vec<JoinRequest> {
+        std::mem::take(&mut self.requests)
+    }
+
+    /// The host let `request` in or turned it away.
+    pub async fn answer(
+        &mut self,
+        ctx: &Ctx,
+        me: &str,
+        request: &JoinRequest,
+        accept: bool,
+    ) -> Result<()> {
+        let Some(r) = self.rooms.get(&request.label) else {
+            return Ok(());
+        };
+        let room_id = &r.room.topic_hex;
+        publish(ctx, &*r.th, room_id, me, request.ack(room_id, accept)).await?;
+        let key = if accept {
+            "room.admitted"
+        } else {
+            "room.turned_away"
+        };
+        self.notices.push(Notice::info(request.text(key)));
+        Ok(())
+    }
+
+    /// Stale members, kicks and bans seen since the last call.
+    pub fn take_notices(&mut self) -> Vec<Notice> {
+        std::mem::take(&mut self.notices)
+    }
+
+    /// Other traffic from `peer_id` in `label`.
+    pub fn touch(&mut self, label: &str, peer_id: &str) {
+        if let Some(r) = self.rooms.get_mut(label) {
+            r.tracker.touch(peer_id);
+        }
+    }
+}
+
+/// A join to a room we host that waits for the host.
+pub(crate) struct JoinRequest {
+    pub label: String,
+    pub peer_id: String,
+    pub nickname: String,
+    /// Friends vouching for the peer.
+    pub vouches: usize,
+}
+
+impl JoinRequest {
+    fn ack(&self, room_id: &str, accept: bool) -> RoomBody {
+        RoomBody::JoinAck {
+            room_id: room_id.to_string(),
+            accept,
+            reason: (!accept).then(|| t!("room.declined_reason")),
+            peer_id: Some(self.peer_id.clone()),
+            full: false,
+            declined: !accept,
+        }
+    }
+
+    /// Message `key` about this request.
+    pub fn text(&self, key: &str) -> String {
+        t!(
+            key,
+            peer = self.nickname,
+            room = self.label,
+            count = self.vouches
+        )
+    }
+}
+
+/// Short form of a peer id for notices.
+fn short(peer_id: &str) -> &str {
+    &peer_id[..8.min(peer_id.len())]
+}
+
+async fn publish(
+    ctx: &Ctx,
+    th: &dyn TopicHandle,
+    room_id: &str,
+    me: &str,
+    body: RoomBody,
+) -> Result<()> {
+    let env = make_envelope(
+        Kind::Room,
+        Scope::Room,
+        Some(room_id.to_string()),
+        me.to_string(),
+        now_ms(),
+        body,
+    );
+    for frame in ctx.wire.encode(&env) {
+        th.publish(&frame).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,158 @@
+//! `room kick`, `room ban`, `room unban` and `room bans`.
+
+use anyhow::{Result, bail};
+use p2p_core::{
+    bans::BanList,
+    directory::PeerDirectory,
+    error::ProtocolError,
+    protocol::{Kind, RoomBody, Scope, make_envelope, now_ms},
+    rooms::RoomRef,
+    session::SessionState,
+    t,
+};
+use serde_json::json;
+use transport_iroh::transport_iroh::GossipTransport;
+
+use crate::{Ctx, Node, print_json, sender_id};
+
+/// The active room, which we must host.
+fn hosted_room(transport: &Node, session: &SessionState) -> Result<RoomRef> {
+    let room = session
+        .rooms
+        .active()
+        .cloned()
+        .ok_or_else(|| ProtocolError::NoActiveRoom(t!("room.none_active")))?;
+    if room.host_addr.as_deref() != Some(sender_id(transport, session).as_str()) {
+        bail!(ProtocolError::NotHost(t!(
+            "room.not_host",
+            name = room.name
+        )));
+    }
+    Ok(room)
+}
+
+/// Peer id for a nickname known from the peer directory; anything else is
+/// taken to be a peer id already.
+pub(crate) fn resolve_peer(peer: &str) -> String {
+    let directory = PeerDirectory::load().unwrap_or_default();
+    directory.find_nickname(peer).unwrap_or(peer).to_string()
+}
+
+/// Remove `peer` from the active room, and ban it if `ban` is set.
+pub(crate) async fn kick(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &SessionState,
+    peer: &str,
+    reason: Option<String>,
+    ban: bool,
+) -> Result<()> {
+    let room = hosted_room(transport, session)?;
+    let me = sender_id(transport, session);
+    let peer_id = resolve_peer(peer);
+    if peer_id == me {
+        bail!(t!("room.kick_self"));
+    }
+    if ban {
+        let mut bans = BanList::load().unwrap_or_default();
+        bans.ban(&room.topic_hex, &peer_id, reason.clone());
+        bans.save()?;
+    }
+    let th = transport
+        .join_topic(transport.topic_from_hex(&room.topic_hex)?)
+        .await?;
+    let env = make_envelope(
+        Kind::Room,
+        Scope::Room,
+        Some(room.topic_hex.clone()),
+        me,
+        now_ms(),
+        RoomBody::Kick {
+            room_id: room.topic_hex.clone(),
+            peer_id: peer_id.clone(),
+            reason,
+            ban,
+        },
+    );
+    for frame in ctx.wire.encode(&env) {
+        th.publish(&frame).await?;
+    }
+    let key = if ban {
+        "room.ban_done"
+    } else {
+        "room.kick_done"
+    };
+    println!(
+        "{}",
+        ctx.out.success(&t!(key, peer = peer_id, room = room.name))
+    );
+    Ok(())
+}
+
+/// Lift the ban of `peer` in the active room.
+pub(crate) fn unban(transport: &Node, ctx: &Ctx, session: &SessionState, peer: &str) -> Result<()> {
+    let room = hosted_room(transport, session)?;
+    let peer_id = resolve_peer(peer);
+    let mut bans = BanList::load().unwrap_or_default();
+    if !bans.unban(&room.topic_hex, &peer_id) {
+        bail!(t!("room.not_banned", peer = peer_id, room = room.name));
+    }
+    bans.save()?;
+    println!(
+        "{}",
+        ctx.out
+            .success(&t!("room.unban_done", peer = peer_id, room = room.name))
+    );
+    Ok(())
+}
+
+/// Print the bans of the active room.
+pub(crate) fn list(ctx: &Ctx, session: &SessionState) -> Result<()> {
+    let room = session
+        .rooms
+        .active()
+        .ok_or_else(|| ProtocolError::NoActiveRoom(t!("room.none_active")))?;
+    let bans = BanList::load()?;
+    let directory = PeerDirectory::load().unwrap_or_default();
+    let nickname = |peer_id: &str| {
+        directory
+            .peers
+            .get(peer_id)
+            .and_then(|p| p.nickname.as_deref())
+    };
+    if ctx.out.is_json() {
+        let bans: Vec<_> = bans
+            .in_room(&room.topic_hex)
+            .map(|(peer_id, ban)| {
+                json!({
+                    "peer_id": peer_id,
+                    "nickname": nickname(peer_id),
+                    "reason": ban.reason,
+                })
+            })
+            .collect();
+        return print_json(&bans);
+    }
+    println!(
+        "{}",
+        ctx.out.heading(&t!("room.bans_title", room = room.name))
+    );
+    let mut any = false;
+    for (peer_id, ban) in bans.in_room(&room.topic_hex) {
+        any = true;
+        let nickname = nickname(peer_id).unwrap_or("?");
+        println!(
+            "  {}",
+            t!(
+                "room.bans_entry",
+                nickname = nickname,
+                peer = peer_id,
+                reason = ban.reason.as_deref().unwrap_or("-")
+            )
+        );
+    }
+    if !any {
+        println!("  {}", t!("room.bans_empty"));
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! First-run setup wizard.
+//!
+//! Walks a new user through what used to be separate manual steps
+//! (`addr`, `login`, `global listen`): start a node and show its identity,
+//! check connectivity, pick a nickname with a live availability check, and
+//! optionally drop straight into the global chat.
+
+use std::io::{BufRead, IsTerminal, Write};
+
+use anyhow::Result;
+use p2p_core::{
+    protocol::{Command, now_ms},
+    registry::NameRegistry,
+    session::SessionState,
+    t,
+};
+use transport_iroh::transport_iroh::GossipTransport;
+
+use crate::Ctx;
+
+/// Whether the wizard should run automatically before `cmd` on first launch.
+pub fn should_run(cmd: &Command) -> bool {
+    !matches!(
+        cmd,
+        Command::Login { .. } | Command::Logout | Command::Setup
+    ) && std::io::stdin().is_terminal()
+}
+
+pub async fn run(ctx: &Ctx, session: &mut SessionState, offer_global_chat: bool) -> Result<()> {
+    let out = &ctx.out;
+    println!("{}", out.heading(&t!("setup.title")));
+    println!("{}", t!("setup.intro"));
+
+    let transport = {
+        let _spinner = out.waiting(&t!("setup.starting_node"));
+        ctx.start_node().await?
+    };
+    let addr = transport.node_addr();
+    let peer_id = addr.node_id.to_string();
+    println!("{}", t!("setup.identity", peer_id = peer_id));
+
+    match &addr.relay_url {
+        Some(relay) => println!(
+            "{}",
+            out.success(&t!("setup.connectivity_ok", relay = relay))
+        ),
+        None if !addr.direct_addresses.is_empty() => {
+            println!("{}", out.warn(&t!("setup.connectivity_direct_only")))
+        }
+        None => println!("{}", out.warn(&t!("setup.connectivity_failed"))),
+    }
+
+    crate::proof_of_work(out, &ctx.config.sybil, &peer_id).await;
+    let registry = NameRegistry::new(&transport, ctx.config.timeouts).with_sybil(&ctx.config.sybil);
+    let mut next: Option<String> = None;
+    let (nickname, since) = loop {
+        let desired = match next.take() {
+            Some(n) => n,
+            None => match &ctx.config.nickname {
+                Some(default) => {
+                    let answer = prompt(&t!("setup.ask_nickname_default", nickname = default))?;
+                    if answer.is_empty() {
+                        default.clone()
+                    } else {
+                        answer
+                    }
+                }
+                None => prompt(&t!("setup.ask_nickname"))?,
+            },
+        };
+        if desired.is_empty() {
+            continue;
+        }
+        let since = now_ms();
+        let (nick, granted) = {
+            let _spinner = out.waiting(&t!("login.claiming", name = desired));
+            registry.claim_unique_at(&desired, &peer_id, since).await?
+        };
+        if granted {
+            break (nick, since);
+        }
+        println!("{}", out.warn(&t!("setup.nickname_taken", name = desired)));
+        if confirm(&t!("setup.accept_suggestion", suggestion = nick), true)? {
+            next = Some(nick);
+        }
+    };
+
+    session.peer_id = peer_id;
+    session.nickname = nickname.clone();
+    session.nick_since = since;
+    session.save()?;
+    println!("{}", out.success(&t!("setup.done", nickname = nickname)));
+
+    if offer_global_chat
+        && !ctx.safe.hides_global_chat()
+        && confirm(&t!("setup.join_global"), false)?
+    {
+        crate::global_listen(&transport, ctx, session).await?;
+    } else {
+        println!("{}", t!("setup.hint"));
+    }
+    Ok(())
+}
+
+/// Ask a question and return the trimmed answer.
+pub(crate) fn prompt(question: &str) -> Result<String> {
+    print!("{question} ");
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// Yes/no question; an empty answer picks `default`.
+pub(crate) fn confirm(question: &str, default: bool) -> Result<bool> {
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    let answer = prompt(&format!("{question} {hint}"))?.to_lowercase();
+    Ok(match answer.as_str() {
+        "" => default,
+        a => a.starts_with('y'),
+    })
+}
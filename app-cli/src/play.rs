@@ -0,0 +1,838 @@
+//! Playing a game in the active room.
+//!
+//! The player who runs `room game start` hosts the match: peers announcing
+//! themselves in the room (or those given with `--vs`) take the other seats
+//! until `--players` are seated, and the host sends `Start` with the seats
+//! in `--order`. The players then exchange `Move`s on the room topic; a
+//! [`TurnEngine`] checks each one against the turn order and the rules
+//! before it is applied, the first seat announces every turn transition and
+//! what becomes of the seats of players who leave (moving for them when a
+//! bot takes over), and whoever completes the game sends `End`. The players
+//! can then dispute the result for a moment (see [`p2p_core::dispute`]).
+//! Meanwhile the two players of a rated match attest the result to each
+//! other; the attestation is kept once the window is over and moves the
+//! rating if the result counts (see [`p2p_core::rating`]).
+//!
+//! `room game watch` follows a match without a seat: it joins as a
+//! spectator, so the host brings it up to date but never seats it, and it
+//! only shows the board.
+
+use anyhow::{Result, bail};
+use p2p_core::{
+    dispute::{DISPUTE_WINDOW, DisputeBook, Evidence},
+    error::ProtocolError,
+    game::{GameBody, GameRegistry, Outcome, SavedGames, Seat, make_game},
+    matchlog::{MatchLog, MatchRecord},
+    notify::NotifyEvent,
+    output::{Output, UiEvent},
+    plugin,
+    protocol::{ChatMsg, Envelope, Kind, MemberRole, RoomBody, now_ms},
+    rating::{self, GameResult, RatingBook},
+    replay::SeatChange,
+    rooms::RoomRef,
+    session::SessionState,
+    stats::LocalStats,
+    t,
+    turn::{TurnEngine, TurnOrder, Verdict},
+    validation,
+};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle};
+
+use crate::{Ctx, Node, connect_host, print_json, restore, sender_id};
+
+/// A running match as seen by this peer.
+struct Match {
+    engine: TurnEngine,
+    /// Our seat; `None` while watching.
+    seat: Option<Seat>,
+    /// Signed move envelopes received, kept as dispute evidence.
+    signed_moves: Vec<Value>,
+}
+
+impl Match {
+    fn save(&self, room: &RoomRef) {
+        if self.seat.is_none() {
+            return;
+        }
+        let mut saved = SavedGames::load().unwrap_or_default();
+        saved.upsert(self.engine.saved(&room.name));
+        let _ = saved.save();
+    }
+}
+
+/// Who set the match up.
+pub(crate) enum Role {
+    /// Started the game; optionally waits for specific opponents.
+    Host {
+        game: String,
+        vs: Vec<String>,
+        seats: usize,
+        order: TurnOrder,
+        seed: Option<u64>,
+    },
+    /// Takes a seat in a game started by someone else.
+    Guest,
+    /// Follows the game as a spectator.
+    Watch,
+}
+
+/// Print the games this client knows.
+pub(crate) fn list(out: &Output) -> Result<()> {
+    if out.is_json() {
+        return print_json(&GameRegistry::builtin().names().collect::<Vec<_>>());
+    }
+    println!("{}", out.heading(&t!("game.list_title")));
+    for name in GameRegistry::builtin().names() {
+        println!("  {name}");
+    }
+    for (path, error) in &plugin::loaded().errors {
+        let text = t!("game.plugin_failed", path = path.display(), error = error);
+        println!("{}", out.warn(&text));
+    }
+    Ok(())
+}
+
+/// Play one match in the active room until it ends or the user quits.
+pub(crate) async fn run(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &mut SessionState,
+    role: Role,
+) -> Result<()> {
+    let out = &ctx.out;
+    let registry = GameRegistry::builtin();
+    if let Role::Host { game, seats, .. } = &role {
+        // Fail early on unknown names and seat counts instead of after the
+        // opponents show up.
+        registry.create(game, *seats)?;
+    }
+    let mut room = session
+        .rooms
+        .active()
+        .cloned()
+        .ok_or_else(|| ProtocolError::NoActiveRoom(t!("room.none_active")))?;
+    match role {
+        Role::Watch => room.role = MemberRole::Spectator,
+        _ if room.role == MemberRole::Spectator => {
+            bail!(t!("game.spectator", room = room.name))
+        }
+        _ => {}
+    }
+    restore::restore(transport, ctx, session).await?;
+    let me = sender_id(transport, session);
+    if let Some(addr) = room.host_addr.as_ref().filter(|a| **a != me) {
+        connect_host(transport, ctx, addr).await?;
+    }
+    let mut th = transport
+        .join_topic(transport.topic_from_hex(&room.topic_hex)?)
+        .await?;
+    th.publish(&ctx.wire.capabilities(me.clone())).await?;
+    restore::rejoin(&*th, ctx, session, &room, &me).await?;
+
+    let mut current = resume(&registry, &role, &room, &me);
+    match (&current, &role) {
+        (Some(m), _) => show(out, m),
+        (None, Role::Host { seats: 2, .. }) => {
+            println!("{}", t!("game.waiting_opponent", room = room.name))
+        }
+        (None, Role::Host { seats, .. }) => println!(
+            "{}",
+            t!("game.waiting_players", count = seats - 1, room = room.name)
+        ),
+        (None, Role::Guest | Role::Watch) => {
+            println!("{}", t!("game.waiting_start", room = room.name))
+        }
+    }
+    // Peers seated so far while the host waits for the match to fill up.
+    let mut lobby = Vec::new();
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        tokio::select! {
+            bytes = th.next() => {
+                let bytes = bytes?;
+                let Some(env) = ctx.wire.decode::<Value>(&bytes) else {
+                    continue;
+                };
+                if env.sender_id == me {
+                    continue;
+                }
+                let table = Table { th: &*th, ctx, room: &room, me: &me };
+                if on_envelope(env, &mut current, &mut lobby, &role, &registry, &table).await? {
+                    break;
+                }
+            }
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    return Ok(());
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let Some(m) = current.as_mut() else {
+                    println!("{}", out.warn(&t!("game.not_started")));
+                    continue;
+                };
+                let Some(seat) = m.seat else {
+                    println!("{}", out.warn(&t!("game.watching")));
+                    continue;
+                };
+                let body = match m.engine.play(seat, &line) {
+                    Ok(body) => body,
+                    Err(e) => {
+                        println!("{}", out.warn(&e.to_string()));
+                        continue;
+                    }
+                };
+                m.save(&room);
+                let table = Table { th: &*th, ctx, room: &room, me: &me };
+                table.publish(body).await?;
+                table.announce_turn(m).await?;
+                play_bots(m, &table).await?;
+                show(out, m);
+                if let Some(outcome) = m.engine.outcome() {
+                    let game_id = m.engine.game_id().to_string();
+                    table.publish(GameBody::End { game_id, outcome: outcome.clone() }).await?;
+                    finish(out, m, &room, &outcome);
+                    break;
+                }
+            }
+            _ = &mut interrupted => return Ok(()),
+        }
+    }
+
+    // The match is over; give the players a moment to dispute the result.
+    let Some(m) = current.filter(|m| m.seat.is_some()) else {
+        return Ok(());
+    };
+    println!("{}", t!("dispute.hint", seconds = DISPUTE_WINDOW.as_secs()));
+    // Rated with our rating before the match, also when sent again below.
+    let result = rated_result(&m);
+    let ours = result.as_ref().map(|r| attest(r, &me));
+    if let Some(body) = &ours {
+        let table = Table {
+            th: &*th,
+            ctx,
+            room: &room,
+            me: &me,
+        };
+        table.publish(body.clone()).await?;
+    }
+    // The opponent's attestation, kept once the window is over.
+    let mut theirs: Option<Value> = None;
+    let window = tokio::time::sleep(DISPUTE_WINDOW);
+    tokio::pin!(window);
+    loop {
+        tokio::select! {
+            bytes = th.next() => {
+                let Some(env) = ctx.wire.decode::<GameBody>(&bytes?) else {
+                    continue;
+                };
+                if let (Some(result), Some(body)) = (&result, &ours)
+                    && matches!(env.body, GameBody::Attest { .. })
+                    && let Ok(signed) = serde_json::to_value(&env)
+                {
+                    let agrees = rating::open(&signed)
+                        .is_some_and(|a| a.signer != me && a.result == *result);
+                    if agrees && theirs.is_none() {
+                        theirs = Some(signed);
+                        // Ours may have gone out before they were listening.
+                        let table = Table { th: &*th, ctx, room: &room, me: &me };
+                        table.publish(body.clone()).await?;
+                    }
+                    continue;
+                }
+                if let GameBody::Dispute { game_id, reason, evidence } = env.body
+                    && game_id == m.engine.game_id()
+                    && env.sig.is_some()
+                    && env.sender_id != me
+                    && m.engine.seat_of(&env.sender_id).is_some()
+                {
+                    let mut book = DisputeBook::load().unwrap_or_default();
+                    if book.open(&game_id, &env.sender_id, &reason, evidence) {
+                        let _ = book.save();
+                        let text = t!(
+                            "dispute.received",
+                            player = short(&env.sender_id),
+                            reason = reason
+                        );
+                        println!("{}", out.warn(&text));
+                    }
+                }
+            }
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    return Ok(());
+                };
+                let Some(reason) = line.trim().strip_prefix("dispute") else {
+                    continue;
+                };
+                let game_id = m.engine.game_id().to_string();
+                let reason = reason.trim().to_string();
+                let evidence = Evidence {
+                    replay: m.engine.replay(),
+                    signed_moves: m.signed_moves.clone(),
+                };
+                let mut book = DisputeBook::load().unwrap_or_default();
+                book.open(&game_id, &me, &reason, evidence.clone());
+                book.save()?;
+                let table = Table { th: &*th, ctx, room: &room, me: &me };
+                table.publish(GameBody::Dispute { game_id, reason, evidence }).await?;
+                println!("{}", out.success(&t!("dispute.sent")));
+                return rate(out, result.as_ref(), theirs, &me);
+            }
+            _ = &mut window => return rate(out, result.as_ref(), theirs, &me),
+            _ = &mut interrupted => return Ok(()),
+        }
+    }
+}
+
+/// A saved, unfinished match of ours in `room`, if any.
+fn resume(registry: &GameRegistry, role: &Role, room: &RoomRef, me: &str) -> Option<Match> {
+    let saved = SavedGames::load().unwrap_or_default();
+    let s = saved.in_room(&room.name).find(|g| match role {
+        Role::Host { game, .. } => g.game == *game && g.players.iter().any(|p| p == me),
+        Role::Guest => g.players.iter().any(|p| p == me),
+        Role::Watch => false,
+    })?;
+    Some(Match {
+        seat: Some(s.players.iter().position(|p| p == me)?),
+        engine: TurnEngine::resume(registry, s).ok()?,
+        signed_moves: Vec::new(),
+    })
+}
+
+/// Handle one envelope from the room; returns whether the match is over.
+async fn on_envelope(
+    env: Envelope<Value>,
+    current: &mut Option<Match>,
+    lobby: &mut Vec<String>,
+    role: &Role,
+    registry: &GameRegistry,
+    table: &Table<'_>,
+) -> Result<bool> {
+    let Table { ctx, room, me, .. } = *table;
+    let out = &ctx.out;
+    let sender = env.sender_id.clone();
+    match env.kind {
+        Kind::Chat => {
+            if let Ok(msg) = serde_json::from_value::<ChatMsg>(env.body) {
+                let text = ctx.clean(&msg.text);
+                println!(
+                    "{}",
+                    out.event(&UiEvent::Chat {
+                        from: &sender,
+                        text: &text,
+                        room: Some(&room.name),
+                    })
+                );
+            }
+        }
+        Kind::Room => {
+            let joined_as = match serde_json::from_value(env.body) {
+                Ok(RoomBody::JoinReq { role, .. }) => {
+                    rejoin(current, &sender, table).await?;
+                    role
+                }
+                Ok(RoomBody::JoinAck {
+                    accept: false,
+                    reason,
+                    peer_id: Some(peer_id),
+                    full,
+                    declined,
+                    ..
+                }) if peer_id == me && room.host_addr.as_deref() == Some(sender.as_str()) => {
+                    let reason = reason.unwrap_or_default();
+                    if full {
+                        let text = t!("room.full", room = room.name, reason = reason);
+                        bail!(ProtocolError::RoomFull(text));
+                    }
+                    let key = if declined {
+                        "room.declined"
+                    } else {
+                        "room.banned"
+                    };
+                    let text = t!(key, room = room.name, reason = reason);
+                    bail!(ProtocolError::JoinRejected(text));
+                }
+                Ok(RoomBody::Leave { .. }) => {
+                    lobby.retain(|p| *p != sender);
+                    return eliminate(current, &sender, table).await;
+                }
+                _ => return Ok(false),
+            };
+            let Role::Host {
+                game,
+                vs,
+                seats,
+                order,
+                seed,
+            } = role
+            else {
+                return Ok(false);
+            };
+            match current {
+                // A player reconnected or a spectator came: bring them up to
+                // date.
+                Some(m) if m.engine.seat_of(&sender).is_some() || !joined_as.is_player() => {
+                    table.publish(m.engine.start()).await?;
+                    table.publish(m.engine.state_sync()).await?;
+                }
+                Some(_) => {}
+                None if joined_as.is_player()
+                    && (vs.is_empty() || vs.contains(&sender))
+                    && !lobby.contains(&sender) =>
+                {
+                    // Old or different clients would only get moves they
+                    // cannot read.
+                    let version = registry.version(game).unwrap_or_default();
+                    let support = ctx.wire.game_support(&sender, game, version);
+                    if let Some(problem) = support.problem(short(&sender), game, version) {
+                        println!("{}", out.warn(&problem));
+                        return Ok(false);
+                    }
+                    println!(
+                        "{}",
+                        t!(
+                            "game.seat_taken",
+                            player = short(&sender),
+                            taken = lobby.len() + 2,
+                            seats = seats
+                        )
+                    );
+                    lobby.push(sender);
+                    if lobby.len() + 1 < *seats {
+                        return Ok(false);
+                    }
+                    let mut players = vec![me.to_string()];
+                    players.append(lobby);
+                    let players = order.arrange(players, *seed);
+                    let m = Match {
+                        seat: Some(players.iter().position(|p| p == me).unwrap_or_default()),
+                        engine: TurnEngine::new(
+                            p2p_core::game::new_game_id(),
+                            players,
+                            registry.create(game, *seats)?,
+                        ),
+                        signed_moves: Vec::new(),
+                    };
+                    table.publish(m.engine.start()).await?;
+                    m.save(room);
+                    show(out, &m);
+                    *current = Some(m);
+                }
+                None => {}
+            }
+        }
+        Kind::Game => {
+            let Ok(body) = serde_json::from_value::<GameBody>(env.body.clone()) else {
+                return Ok(false);
+            };
+            match body {
+                GameBody::Start {
+                    game_id,
+                    game,
+                    players,
+                } => {
+                    let seat = players.iter().position(|p| p == me);
+                    match role {
+                        // Someone else got the seats we were waiting for.
+                        Role::Guest if seat.is_none() && current.is_none() => {
+                            bail!(ProtocolError::RoomFull(t!("game.full", room = room.name)));
+                        }
+                        Role::Watch => {}
+                        _ if seat.is_none() => return Ok(false),
+                        _ => {}
+                    }
+                    if current
+                        .as_ref()
+                        .is_some_and(|m| m.engine.game_id() == game_id)
+                    {
+                        return Ok(false);
+                    }
+                    let game = registry.create(&game, players.len())?;
+                    let m = Match {
+                        engine: TurnEngine::new(game_id, players, game),
+                        seat,
+                        signed_moves: Vec::new(),
+                    };
+                    m.save(room);
+                    show(out, &m);
+                    alert_turn(ctx, &m);
+                    *current = Some(m);
+                }
+                GameBody::StateSync {
+                    game_id,
+                    seq,
+                    state,
+                } => {
+                    let Some(m) = current.as_mut().filter(|m| m.engine.game_id() == game_id) else {
+                        return Ok(false);
+                    };
+                    if m.engine.load_sync(seq, state)? {
+                        m.save(room);
+                        show(out, m);
+                    } else if seq < m.engine.seq() {
+                        // They are behind (e.g. resumed from an older save).
+                        table.publish(m.engine.state_sync()).await?;
+                    }
+                }
+                GameBody::Move { game_id, seq, mv } => {
+                    let Some(m) = current.as_mut().filter(|m| m.engine.game_id() == game_id) else {
+                        return Ok(false);
+                    };
+                    match m.engine.receive(&sender, seq, &mv) {
+                        Verdict::Applied => {}
+                        Verdict::Stale => return Ok(false),
+                        Verdict::Resync => {
+                            // We missed something or they are confused.
+                            table.publish(m.engine.state_sync()).await?;
+                            return Ok(false);
+                        }
+                        Verdict::Rejected(e) => {
+                            tracing::warn!("rejected move from {sender}: {e}");
+                            return Ok(false);
+                        }
+                    }
+                    if env.sig.is_some()
+                        && let Ok(signed) = serde_json::to_value(&env)
+                    {
+                        m.signed_moves.push(signed);
+                    }
+                    m.save(room);
+                    table.announce_turn(m).await?;
+                    play_bots(m, table).await?;
+                    show(out, m);
+                    if m.seat.is_some() {
+                        ctx.notifier.notify(NotifyEvent::GameMove);
+                    }
+                    alert_turn(ctx, m);
+                    if let Some(outcome) = m.engine.outcome() {
+                        finish(out, m, room, &outcome);
+                        return Ok(true);
+                    }
+                }
+                GameBody::Eliminate { game_id, seq, seat } => {
+                    let change = (seq, seat, SeatChange::Dropped);
+                    return on_seat_change(current, &sender, &game_id, change, table).await;
+                }
+                GameBody::Rejoin { game_id, seq, seat } => {
+                    let change = (seq, seat, SeatChange::Rejoined);
+                    return on_seat_change(current, &sender, &game_id, change, table).await;
+                }
+                GameBody::Turn {
+                    game_id,
+                    seq,
+                    to_move,
+                } => {
+                    let Some(m) = current.as_ref().filter(|m| m.engine.game_id() == game_id) else {
+                        return Ok(false);
+                    };
+                    if m.engine.is_authority(&sender) && !m.engine.agrees(seq, to_move) {
+                        table.publish(m.engine.state_sync()).await?;
+                    }
+                }
+                // Only after the match; see the dispute window in `run`.
+                GameBody::Dispute { .. } | GameBody::Attest { .. } => {}
+                // Read by `fair_random::draw` of the game that asked for it.
+                GameBody::RandomCommit { .. } | GameBody::RandomReveal { .. } => {}
+                // Read by the game's `deck::Deck`.
+                GameBody::DeckPass { .. } | GameBody::DeckKeys { .. } => {}
+                GameBody::End { game_id, outcome } => {
+                    let Some(m) = current.as_ref().filter(|m| m.engine.game_id() == game_id) else {
+                        return Ok(false);
+                    };
+                    finish(out, m, room, &outcome);
+                    return Ok(true);
+                }
+            }
+        }
+        Kind::Discovery
+        | Kind::Direct
+        | Kind::Edit
+        | Kind::Delete
+        | Kind::Announcement
+        | Kind::Matchmaking
+        | Kind::Tournament
+        | Kind::Invite => {}
+    }
+    Ok(false)
+}
+
+/// A [`GameBody::Eliminate`] or [`GameBody::Rejoin`] of the first seat,
+/// as `(seq, seat, change)`; returns whether the match is over.
+async fn on_seat_change(
+    current: &mut Option<Match>,
+    sender: &str,
+    game_id: &str,
+    (seq, seat, change): (u64, Seat, SeatChange),
+    table: &Table<'_>,
+) -> Result<bool> {
+    let Some(m) = current.as_mut().filter(|m| m.engine.game_id() == game_id) else {
+        return Ok(false);
+    };
+    let to_move = m.engine.game().to_move();
+    match m.engine.receive_seat_change(sender, seq, seat, change) {
+        Verdict::Applied => {}
+        Verdict::Stale => return Ok(false),
+        Verdict::Resync => {
+            table.publish(m.engine.state_sync()).await?;
+            return Ok(false);
+        }
+        Verdict::Rejected(e) => {
+            tracing::warn!("rejected seat change from {sender}: {e}");
+            return Ok(false);
+        }
+    }
+    m.save(table.room);
+    let out = &table.ctx.out;
+    show_seat_change(out, m, seat, change);
+    if m.engine.game().to_move() != to_move {
+        alert_turn(table.ctx, m);
+    }
+    if let Some(outcome) = m.engine.outcome() {
+        finish(out, m, table.room, &outcome);
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// `peer_id` left the room. As the first seat, handle it as the game's
+/// drop policy says; returns whether the match is over.
+async fn eliminate(current: &mut Option<Match>, peer_id: &str, table: &Table<'_>) -> Result<bool> {
+    let Some(m) = current.as_mut().filter(|m| m.engine.is_authority(table.me)) else {
+        return Ok(false);
+    };
+    let Some(seat) = m.engine.seat_of(peer_id) else {
+        return Ok(false);
+    };
+    let body = match m.engine.eliminate(seat) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::debug!("{peer_id} left, seat kept: {e}");
+            return Ok(false);
+        }
+    };
+    m.save(table.room);
+    table.publish(body).await?;
+    table.announce_turn(m).await?;
+    play_bots(m, table).await?;
+    let out = &table.ctx.out;
+    show_seat_change(out, m, seat, SeatChange::Dropped);
+    if let Some(outcome) = m.engine.outcome() {
+        let game_id = m.engine.game_id().to_string();
+        table
+            .publish(GameBody::End {
+                game_id,
+                outcome: outcome.clone(),
+            })
+            .await?;
+        finish(out, m, table.room, &outcome);
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// `peer_id` asked to join again. As the first seat, give them back their
+/// seat if a bot plays it.
+async fn rejoin(current: &mut Option<Match>, peer_id: &str, table: &Table<'_>) -> Result<()> {
+    let Some(m) = current.as_mut().filter(|m| m.engine.is_authority(table.me)) else {
+        return Ok(());
+    };
+    let Some(seat) = m.engine.seat_of(peer_id).filter(|s| m.engine.is_bot(*s)) else {
+        return Ok(());
+    };
+    let body = m.engine.rejoin(seat)?;
+    m.save(table.room);
+    table.publish(body).await?;
+    show_seat_change(&table.ctx.out, m, seat, SeatChange::Rejoined);
+    Ok(())
+}
+
+/// As the first seat, move for bot seats until a player is up.
+async fn play_bots(m: &mut Match, table: &Table<'_>) -> Result<()> {
+    if !m.engine.is_authority(table.me) {
+        return Ok(());
+    }
+    while let Some(body) = m.engine.play_bot() {
+        m.save(table.room);
+        table.publish(body).await?;
+        table.announce_turn(m).await?;
+    }
+    Ok(())
+}
+
+/// Where our match messages go.
+struct Table<'a> {
+    th: &'a dyn TopicHandle,
+    ctx: &'a Ctx,
+    room: &'a RoomRef,
+    me: &'a str,
+}
+
+impl Table<'_> {
+    async fn publish(&self, body: GameBody) -> Result<()> {
+        let env = make_game(self.room.topic_hex.clone(), self.me.to_string(), body);
+        for frame in self.ctx.wire.encode(&env) {
+            self.th.publish(&frame).await?;
+        }
+        Ok(())
+    }
+
+    /// The host tells the room whose turn it is after each move.
+    async fn announce_turn(&self, m: &Match) -> Result<()> {
+        if m.engine.is_authority(self.me) {
+            self.publish(m.engine.turn()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of a finished match of ours that moves ratings.
+fn rated_result(m: &Match) -> Option<GameResult> {
+    let result = GameResult {
+        game_id: m.engine.game_id().to_string(),
+        game: m.engine.game().name().to_string(),
+        players: m.engine.players().to_vec(),
+        outcome: m.engine.outcome()?,
+    };
+    (m.seat.is_some() && result.rated()).then_some(result)
+}
+
+/// Keep the opponent's attestation of `result` after the dispute window.
+/// The match only moves our rating if its result counts, so a disputed one
+/// waits for revalidation.
+fn rate(out: &Output, result: Option<&GameResult>, theirs: Option<Value>, me: &str) -> Result<()> {
+    let (Some(result), Some(signed)) = (result, theirs) else {
+        return Ok(());
+    };
+    let mut book = RatingBook::load().unwrap_or_default();
+    let before = book.rating(me, &result.game);
+    if !book.record(signed, me, result) {
+        return Ok(());
+    }
+    book.save()?;
+    let after = book.rating(me, &result.game);
+    if after != before {
+        let text = t!(
+            "rating.updated",
+            game = result.game,
+            before = before.round(),
+            after = after.round()
+        );
+        println!("{}", out.success(&text));
+    }
+    Ok(())
+}
+
+/// Our attestation of `result`, with our rating before it.
+fn attest(result: &GameResult, me: &str) -> GameBody {
+    let rating = RatingBook::load()
+        .unwrap_or_default()
+        .rating(me, &result.game);
+    GameBody::Attest {
+        result: result.clone(),
+        rating,
+    }
+}
+
+/// Alert the player of `m` if it is their turn, e.g. after the opponent
+/// moved.
+fn alert_turn(ctx: &Ctx, m: &Match) {
+    if m.seat.is_some() && m.engine.outcome().is_none() && m.engine.game().to_move() == m.seat {
+        ctx.notifier.notify(NotifyEvent::YourTurn);
+    }
+}
+
+/// Short form of a peer id for prompts.
+fn short(peer_id: &str) -> &str {
+    &peer_id[..8.min(peer_id.len())]
+}
+
+/// Board plus whose turn it is.
+fn show(out: &Output, m: &Match) {
+    let engine = &m.engine;
+    if engine.seq() == 0 {
+        let players: Vec<&str> = engine.players().iter().map(|p| short(p)).collect();
+        println!(
+            "{}",
+            out.success(&t!(
+                "game.started",
+                game = engine.game().name(),
+                players = players.join(", ")
+            ))
+        );
+    }
+    println!("{}", out.render(&engine.game().render(m.seat)));
+    match engine.game().to_move() {
+        Some(s) if Some(s) == m.seat => println!("{}", t!("game.your_turn")),
+        Some(_) => println!(
+            "{}",
+            t!(
+                "game.their_turn",
+                player = short(engine.to_move().unwrap_or("?"))
+            )
+        ),
+        None => {}
+    }
+}
+
+/// Who left or came back, then the board as usual.
+fn show_seat_change(out: &Output, m: &Match, seat: Seat, change: SeatChange) {
+    let player = m.engine.players().get(seat).map_or("?", |p| short(p));
+    let text = match change {
+        SeatChange::Dropped => {
+            let policy = m.engine.game().on_drop().key();
+            t!(&format!("game.dropped.{policy}"), player = player)
+        }
+        SeatChange::Rejoined => t!("game.rejoined", player = player),
+    };
+    println!("{}", out.warn(&text));
+    show(out, m);
+}
+
+/// Report the result, log and count the game and drop the save.
+fn finish(out: &Output, m: &Match, room: &RoomRef, outcome: &Outcome) {
+    let line = match outcome {
+        Outcome::Winner { seat } if Some(*seat) == m.seat => out.success(&t!("game.won")),
+        Outcome::Winner { seat } => t!(
+            "game.lost",
+            player = m.engine.players().get(*seat).map_or("?", |p| short(p))
+        ),
+        Outcome::Draw => t!("game.draw"),
+        Outcome::Aborted { reason } => out.warn(&t!("game.aborted", reason = reason)),
+    };
+    println!("{line}");
+    // Spectators keep no record of other people's matches.
+    if m.seat.is_none() {
+        return;
+    }
+    let record = MatchRecord {
+        game_id: m.engine.game_id().to_string(),
+        game: m.engine.game().name().to_string(),
+        room: room.topic_hex.clone(),
+        players: m.engine.players().to_vec(),
+        outcome: outcome.clone(),
+        moves: m.engine.seq(),
+        ended_at: now_ms(),
+        replay: m.engine.replay(),
+        signed_moves: m.signed_moves.clone(),
+        validated: false,
+    };
+    if let Err(e) = validation::validate(&record, &GameRegistry::builtin()) {
+        println!("{}", out.warn(&t!("validation.not_counted", error = e)));
+    }
+    if let Err(e) = MatchLog::open().append(&record) {
+        tracing::warn!("could not log match: {e}");
+    }
+    let mut stats = LocalStats::load().unwrap_or_default();
+    stats.record_game(m.engine.game().name());
+    let _ = stats.save();
+    let mut saved = SavedGames::load().unwrap_or_default();
+    saved.remove(m.engine.game_id());
+    let _ = saved.save();
+}
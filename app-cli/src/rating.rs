@@ -0,0 +1,159 @@
+//! `p2p-games rating [<game>] [--publish]`: Elo ratings from attested
+//! results (see [`p2p_core::rating`]).
+
+use anyhow::{Result, bail};
+use p2p_core::{
+    directory::PeerDirectory,
+    discovery::Discovery,
+    game::GameRegistry,
+    output::Output,
+    rating::{RatingBook, RatingChange},
+    session::SessionState,
+    t,
+    widgets::{ScoreRow, ScoreTable},
+};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::{Ctx, dashboard::name, print_json, sender_id};
+
+/// Matches of the history shown below the table.
+const RECENT: usize = 5;
+
+/// Won, drawn and lost matches and the net change against one opponent.
+#[derive(Default)]
+struct Against {
+    won: u32,
+    drawn: u32,
+    lost: u32,
+    net: f64,
+}
+
+fn by_opponent(changes: &[RatingChange]) -> BTreeMap<&str, Against> {
+    let mut opponents: BTreeMap<&str, Against> = BTreeMap::new();
+    for c in changes {
+        let a = opponents.entry(&c.opponent).or_default();
+        match c.score {
+            s if s >= 1.0 => a.won += 1,
+            s if s <= 0.0 => a.lost += 1,
+            _ => a.drawn += 1,
+        }
+        a.net += c.after - c.before;
+    }
+    opponents
+}
+
+/// Our rating in every game we have rated matches in.
+fn list(out: &Output, session: &SessionState, book: &RatingBook) -> Result<()> {
+    let me = &session.peer_id;
+    let ratings: BTreeMap<String, (f64, usize)> = book
+        .games()
+        .into_iter()
+        .map(|g| {
+            let changes = book.changes(me, &g);
+            (g, (p2p_core::rating::current(&changes), changes.len()))
+        })
+        .collect();
+    if out.is_json() {
+        let games: BTreeMap<_, _> = ratings
+            .iter()
+            .map(|(g, (r, n))| (g, json!({ "rating": r.round(), "games": n })))
+            .collect();
+        return print_json(&games);
+    }
+    println!("{}", out.heading(&t!("rating.list_title")));
+    if ratings.is_empty() {
+        println!("{}", out.info(&t!("rating.none")));
+    }
+    for (game, (rating, games)) in ratings {
+        println!(
+            "{}",
+            t!(
+                "rating.line",
+                game = game,
+                rating = rating.round(),
+                games = games
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Our rating in `game`: a row per opponent and the latest changes;
+/// `publish` also sends it with its attestations to the network.
+pub(crate) async fn show(
+    ctx: &Ctx,
+    session: &SessionState,
+    game: Option<&str>,
+    publish: bool,
+) -> Result<()> {
+    let out = &ctx.out;
+    let book = RatingBook::load().unwrap_or_default();
+    let Some(game) = game else {
+        return list(out, session, &book);
+    };
+    if !GameRegistry::builtin().knows(game) {
+        bail!(t!("game.unknown", game = game));
+    }
+    if publish {
+        let transport = ctx.start_node().await?;
+        let me = sender_id(&transport, session);
+        let parts = book.summary(&me, game);
+        Discovery::new(&transport, ctx.config.timeouts)
+            .rating(&me, parts)
+            .await?;
+    }
+    let changes = book.changes(&session.peer_id, game);
+    let rating = p2p_core::rating::current(&changes).round();
+    if out.is_json() {
+        return print_json(&json!({
+            "game": game,
+            "rating": rating,
+            "published": publish,
+            "changes": changes,
+        }));
+    }
+    let directory = PeerDirectory::load().unwrap_or_default();
+    println!(
+        "{}",
+        out.heading(&t!("rating.title", game = game, rating = rating))
+    );
+    if changes.is_empty() {
+        println!("{}", out.info(&t!("rating.none")));
+    } else {
+        let columns = ["won", "drawn", "lost", "net"]
+            .iter()
+            .map(|c| t!(&format!("rating.col.{c}")))
+            .collect();
+        let rows = by_opponent(&changes)
+            .into_iter()
+            .map(|(peer_id, a)| ScoreRow {
+                player: name(&directory, peer_id),
+                values: vec![
+                    a.won.to_string(),
+                    a.drawn.to_string(),
+                    a.lost.to_string(),
+                    format!("{:+.0}", a.net),
+                ],
+                highlight: false,
+            })
+            .collect();
+        println!("{}", out.score_table(&ScoreTable { columns, rows }));
+        println!("{}", t!("rating.recent"));
+        for c in changes.iter().rev().take(RECENT) {
+            let line = t!(
+                "rating.change",
+                before = c.before.round(),
+                after = c.after.round(),
+                player = name(&directory, &c.opponent),
+                opponent_rating = c.opponent_rating.round()
+            );
+            println!("  {line}");
+        }
+    }
+    if publish {
+        let text = t!("rating.published", game = game, games = changes.len());
+        println!("{}", out.success(&text));
+    }
+    Ok(())
+}
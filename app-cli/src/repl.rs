@@ -0,0 +1,95 @@
+//! `p2p-games repl`: many commands on one node.
+//!
+//! Every one-shot command binds an endpoint and waits for discovery before
+//! it can do anything. The REPL does that once and then reads commands line
+//! by line, all run on the same node:
+//!
+//! - `/join <room or ticket>`: switch to a joined room, or join by ticket
+//! - `/say <text>` (or just the text): into the active room, or global chat
+//!   without one
+//! - `/list`: rooms on the network
+//! - `/gg`, `/brb`, …: canned messages (see [`p2p_core::canned`])
+//! - `/<command> [args]`: any other command as on the command line, e.g.
+//!   `/room browse` or `/stats tictactoe --vs alice`
+//! - `/quit`
+//!
+//! Running commands (`/room listen`, …) end with Ctrl-C and return to the
+//! prompt.
+
+use anyhow::{Result, bail};
+use clap::Parser;
+use p2p_core::{canned::CannedConfig, protocol::AppCli, session::SessionState, t};
+use std::io::Write;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::{Ctx, dispatch};
+
+pub(crate) async fn run(ctx: &Ctx, session: &mut SessionState) -> Result<()> {
+    let out = &ctx.out;
+    if ctx.keeps_node() {
+        bail!(t!("repl.nested"));
+    }
+    ctx.keep_node().await?;
+    println!("{}", out.info(&t!("repl.ready")));
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("> ");
+        let _ = std::io::stdout().flush();
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            _ = tokio::signal::ctrl_c() => None,
+        };
+        let Some(line) = line else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some(words) = words(line, session, &ctx.config.canned) else {
+            break;
+        };
+        let command = match AppCli::try_parse_from(std::iter::once("p2p-games".into()).chain(words))
+        {
+            Ok(cli) => cli.command,
+            Err(e) => {
+                println!("{e}");
+                continue;
+            }
+        };
+        // `dispatch` runs this very command too; box the cycle.
+        if let Err(e) = Box::pin(dispatch(command, ctx, session)).await {
+            println!("{}", out.error(&format!("{e:#}")));
+        }
+    }
+    ctx.release_node().await;
+    Ok(())
+}
+
+/// Command-line words for a REPL line; `None` to quit.
+fn words(line: &str, session: &SessionState, canned: &CannedConfig) -> Option<Vec<String>> {
+    let room = session.rooms.active().map(|r| r.name.as_str());
+    let (cmd, rest) = match line.strip_prefix('/') {
+        Some(l) => l
+            .split_once(char::is_whitespace)
+            .map_or((l, ""), |(c, r)| (c, r.trim())),
+        None => ("say", line),
+    };
+    // `say` expands it.
+    if canned.text(cmd, room).is_some() && rest.is_empty() {
+        return words(&format!("/say {line}"), session, canned);
+    }
+    let words = |w: &[&str]| w.iter().map(|s| s.to_string()).collect();
+    Some(match cmd {
+        "quit" | "exit" => return None,
+        "say" if room.is_some() => words(&["room", "say", rest]),
+        "say" => words(&["global", "say", rest]),
+        "list" => words(&["room", "list"]),
+        "join" if session.rooms.get(rest).is_some() => words(&["room", "switch", rest]),
+        "join" => words(&["room", "join", "--ticket", rest]),
+        _ => std::iter::once(cmd)
+            .chain(rest.split_whitespace())
+            .map(str::to_string)
+            .collect(),
+    })
+}
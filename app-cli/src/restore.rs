@@ -0,0 +1,119 @@
+//! Picking up where the previous run left off.
+//!
+//! Interactive sessions start with [`restore`]: the persisted node key keeps
+//! the peer id, the nickname is claimed again, and every persisted room is
+//! re-entered with a fresh join handshake and the state of saved games.
+//! Guest sessions skip all of that: there is nothing to pick up.
+
+use anyhow::Result;
+use p2p_core::{
+    game::{SavedGames, make_game},
+    protocol::{Kind, RoomBody, Scope, make_envelope, now_ms},
+    registry::NameRegistry,
+    rooms::RoomRef,
+    session::SessionState,
+    t,
+};
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle};
+
+use crate::Ctx;
+
+/// Re-login with the persisted identity and re-claim the nickname.
+pub(crate) async fn restore(
+    transport: &dyn GossipTransport,
+    ctx: &Ctx,
+    session: &mut SessionState,
+) -> Result<()> {
+    let out = &ctx.out;
+    let peer_id = transport.node_addr().node_id.to_string();
+    // Sessions from before the node key was persisted carry a stale id.
+    if session.peer_id != peer_id {
+        session.peer_id = peer_id.clone();
+        session.save()?;
+    }
+    if session.nickname.is_empty() {
+        return Ok(());
+    }
+    // Guest nicknames are random and only live as long as the process.
+    if session.guest {
+        println!("{}", t!("guest.welcome", nickname = session.nickname));
+        return Ok(());
+    }
+    let desired = session.nickname.clone();
+    // Keep the original claim time: it is what wins conflicts for us.
+    let since = match session.nick_since {
+        0 => now_ms(),
+        since => since,
+    };
+    crate::proof_of_work(out, &ctx.config.sybil, &peer_id).await;
+    let (nickname, granted) = {
+        let _spinner = out.waiting(&t!("login.claiming", name = desired));
+        NameRegistry::new(transport, ctx.config.timeouts)
+            .with_sybil(&ctx.config.sybil)
+            .claim_unique_at(&desired, &peer_id, since)
+            .await?
+    };
+    if granted {
+        println!("{}", t!("restore.welcome_back", nickname = nickname));
+    } else {
+        println!(
+            "{}",
+            out.warn(&t!("restore.renamed", name = desired, nickname = nickname))
+        );
+    }
+    session.nickname = nickname;
+    session.nick_since = if granted { since } else { 0 };
+    session.save()?;
+
+    if !session.rooms.is_empty() {
+        println!(
+            "{}",
+            t!("restore.rooms", count = session.rooms.rooms().len())
+        );
+    }
+    let games = SavedGames::load().unwrap_or_default();
+    let resumable = session
+        .rooms
+        .rooms()
+        .iter()
+        .map(|r| games.in_room(&r.name).count())
+        .sum::<usize>();
+    if resumable > 0 {
+        println!("{}", t!("restore.games", count = resumable));
+    }
+    Ok(())
+}
+
+/// Announce ourselves in a room just subscribed to: a join request so the
+/// host re-adds us to the member list, then the state of our saved games.
+pub(crate) async fn rejoin(
+    th: &dyn TopicHandle,
+    ctx: &Ctx,
+    session: &SessionState,
+    room: &RoomRef,
+    me: &str,
+) -> Result<()> {
+    let req = make_envelope(
+        Kind::Room,
+        Scope::Room,
+        Some(room.topic_hex.clone()),
+        me.to_string(),
+        now_ms(),
+        RoomBody::JoinReq {
+            room_id: room.topic_hex.clone(),
+            nickname: session.nickname.clone(),
+            role: room.role,
+        },
+    );
+    for frame in ctx.wire.encode(&req) {
+        th.publish(&frame).await?;
+    }
+    let games = SavedGames::load().unwrap_or_default();
+    for saved in games.in_room(&room.name) {
+        let env = make_game(room.topic_hex.clone(), me.to_string(), saved.state_sync());
+        for frame in ctx.wire.encode(&env) {
+            th.publish(&frame).await?;
+        }
+    }
+    Ok(())
+}
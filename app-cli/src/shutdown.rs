@@ -0,0 +1,90 @@
+//! Leaving the network cleanly on Ctrl-C.
+
+use anyhow::Result;
+use p2p_core::{
+    discovery::Discovery,
+    history::HistoryStore,
+    protocol::{Kind, RoomBody, Scope, make_envelope, now_ms},
+    rooms::RoomRef,
+    t,
+};
+use std::time::Duration;
+use transport_iroh::transport_iroh::GossipTransport;
+
+use crate::Ctx;
+
+/// Upper bound for the goodbye; a second Ctrl-C is not needed to get out.
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What this process holds on the network and gives back on exit.
+#[derive(Default)]
+pub(crate) struct Goodbye {
+    /// Rooms subscribed to; members get a `Leave` (or `Close` if hosted).
+    pub rooms: Vec<RoomRef>,
+    /// Room hosted by this process: `(room id, name)`.
+    pub hosted: Option<(String, String)>,
+}
+
+impl Goodbye {
+    /// Send goodbyes, flush local state and shut the transport down (unless
+    /// a `repl` keeps it for the next command).
+    ///
+    /// Network failures are reported but never keep us from exiting.
+    pub async fn send(self, transport: &dyn GossipTransport, ctx: &Ctx, me: &str) {
+        let out = &ctx.out;
+        let res = {
+            let _spinner = out.waiting(&t!("shutdown.leaving"));
+            tokio::time::timeout(GOODBYE_TIMEOUT, self.announce(transport, ctx, me))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|r| r)
+        };
+        if let Err(e) = res {
+            println!("{}", out.warn(&t!("shutdown.incomplete", error = e)));
+        }
+        let _ = HistoryStore::open().sync();
+        ctx.meter.flush();
+        if !ctx.keeps_node() {
+            let _ = tokio::time::timeout(GOODBYE_TIMEOUT, transport.shutdown()).await;
+        }
+    }
+
+    async fn announce(&self, transport: &dyn GossipTransport, ctx: &Ctx, me: &str) -> Result<()> {
+        for r in &self.rooms {
+            let (room_id, body) = match &self.hosted {
+                Some((room_id, name)) if *name == r.name => (
+                    room_id.clone(),
+                    RoomBody::Close {
+                        room_id: room_id.clone(),
+                    },
+                ),
+                _ => (
+                    r.topic_hex.clone(),
+                    RoomBody::Leave {
+                        room_id: r.topic_hex.clone(),
+                    },
+                ),
+            };
+            let th = transport
+                .join_topic(transport.topic_from_hex(&r.topic_hex)?)
+                .await?;
+            let env = make_envelope(
+                Kind::Room,
+                Scope::Room,
+                Some(room_id),
+                me.to_string(),
+                now_ms(),
+                body,
+            );
+            for frame in ctx.wire.encode(&env) {
+                th.publish(&frame).await?;
+            }
+        }
+        let discovery = Discovery::new(transport, ctx.config.timeouts);
+        if let Some((room_id, name)) = &self.hosted {
+            discovery.close_room(room_id, me).await?;
+            discovery.release_room_name(name, room_id, me).await?;
+        }
+        discovery.announce_presence(me, false).await
+    }
+}
@@ -0,0 +1,71 @@
+//! Game-night summary at the end of a room session.
+
+use anyhow::Result;
+use p2p_core::{
+    directory::PeerDirectory,
+    dispute::DisputeBook,
+    game::GameRegistry,
+    history::HistoryStore,
+    matchlog::{MatchLog, MatchRecord},
+    protocol::make_chat_room,
+    rooms::RoomRef,
+    summary::GameNight,
+    t, validation,
+};
+use transport_iroh::transport_iroh::GossipTransport;
+
+use crate::{Ctx, Node};
+
+/// Summarize `room` since `since`, save the report and, when `post` is set
+/// (we host the room), share the short version in the room.
+pub(crate) async fn game_night(
+    transport: &Node,
+    ctx: &Ctx,
+    room: &RoomRef,
+    since: u64,
+    me: &str,
+    post: bool,
+) -> Result<()> {
+    let matches = MatchLog::open().load()?;
+    let disputes = DisputeBook::load().unwrap_or_default();
+    let registry = GameRegistry::builtin();
+    let counts = |m: &MatchRecord| validation::counts(m, &registry, &disputes);
+    let history = HistoryStore::open().load()?;
+    let directory = PeerDirectory::load().unwrap_or_default();
+    let name = |peer_id: &str| {
+        directory
+            .peers
+            .get(peer_id)
+            .and_then(|p| p.nickname.clone())
+            .unwrap_or_else(|| peer_id.chars().take(8).collect())
+    };
+    let night = GameNight::build(
+        &room.name,
+        &room.topic_hex,
+        since,
+        &matches,
+        counts,
+        &history,
+        name,
+    );
+    if night.is_empty() {
+        return Ok(());
+    }
+    let path = night.save()?;
+    println!(
+        "{}",
+        ctx.out.success(&t!("summary.saved", path = path.display()))
+    );
+    if post {
+        let th = transport
+            .join_topic(transport.topic_from_hex(&room.topic_hex)?)
+            .await?;
+        for line in night.chat_lines() {
+            let env = make_chat_room(room.topic_hex.clone(), me.to_string(), line);
+            for frame in ctx.wire.encode(&env) {
+                th.publish(&frame).await?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,512 @@
+//! `p2p-games tournament create/join/status`: single-elimination
+//! tournaments (see [`p2p_core::tournament`]).
+
+use anyhow::{Result, bail};
+use p2p_core::{
+    directory::PeerDirectory,
+    dispute::Evidence,
+    game::{GameRegistry, Outcome},
+    matchlog::MatchLog,
+    output::Output,
+    protocol::{TournamentCmd, now_ms},
+    rating::{self, RatingBook},
+    scheduler::Scheduler,
+    session::SessionState,
+    signing, t,
+    tournament::{
+        BRACKET_EVERY, Bracket, MAX_PLAYERS, TournamentBody, TournamentTicket, Tournaments,
+        make_tournament, room_topic_name,
+    },
+    turn::TurnOrder,
+};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle};
+
+use crate::{
+    Ctx, Node,
+    dashboard::name,
+    matchmaking::{Paired, play_paired},
+    print_json, restore, sender_id,
+};
+
+pub(crate) async fn run(sub: TournamentCmd, ctx: &Ctx, session: &mut SessionState) -> Result<()> {
+    match sub {
+        TournamentCmd::Create { game, players } => {
+            let transport = ctx.start_node().await?;
+            create(&transport, ctx, session, &game, players).await
+        }
+        TournamentCmd::Join { ticket } => {
+            let transport = ctx.start_node().await?;
+            join(&transport, ctx, session, ticket.parse()?).await
+        }
+        TournamentCmd::Status { ticket: None } => list(&ctx.out),
+        TournamentCmd::Status {
+            ticket: Some(ticket),
+        } => status(ctx, ticket.parse()?).await,
+    }
+}
+
+async fn publish(th: &dyn TopicHandle, me: &str, body: TournamentBody) -> Result<()> {
+    th.publish(&signing::to_signed_vec(&make_tournament(
+        me.to_string(),
+        body,
+    )))
+    .await
+}
+
+/// The bracket in `bytes`, if the organizer of `ticket` signed it.
+fn bracket_from(bytes: &[u8], ticket: &TournamentTicket) -> Option<Bracket> {
+    let env = signing::open::<TournamentBody>(bytes)?;
+    let TournamentBody::Bracket { bracket } = env.body else {
+        return None;
+    };
+    (env.sig.is_some() && env.sender_id == ticket.organizer && bracket.id == ticket.id)
+        .then_some(bracket)
+}
+
+fn save(bracket: &Bracket) {
+    let mut saved = Tournaments::load().unwrap_or_default();
+    saved.upsert(bracket);
+    let _ = saved.save();
+}
+
+/// Run a tournament of `game` for up to `players` entrants until it has a
+/// champion.
+async fn create(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &mut SessionState,
+    game: &str,
+    players: usize,
+) -> Result<()> {
+    let out = &ctx.out;
+    GameRegistry::builtin().create(game, 2)?;
+    if !(2..=MAX_PLAYERS).contains(&players) {
+        bail!(t!("tournament.size", max = MAX_PLAYERS));
+    }
+    restore::restore(transport, ctx, session).await?;
+    let me = sender_id(transport, session);
+    let ticket = TournamentTicket {
+        id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+        organizer: me.clone(),
+    };
+    let mut bracket = Bracket::new(&ticket, game);
+    let mut th = transport
+        .join_topic(transport.topic_from_name(&ticket.topic_name()))
+        .await?;
+    if out.is_json() {
+        print_json(&json!({
+            "type": "tournament_created",
+            "id": ticket.id,
+            "game": game,
+            "ticket": ticket.to_string(),
+        }))?;
+    } else {
+        let text = t!(
+            "tournament.created",
+            id = ticket.id,
+            game = game,
+            ticket = ticket
+        );
+        println!("{}", out.success(&text));
+        println!("{}", t!("tournament.start_hint", players = players));
+    }
+    let directory = PeerDirectory::load().unwrap_or_default();
+    let registry = GameRegistry::builtin();
+    // Peer id → the rating it joined with.
+    let mut entrants: BTreeMap<String, u32> = BTreeMap::new();
+    let mut scheduler = Scheduler::new(ctx.budget.clone(), &ctx.config.scheduler);
+    scheduler.every((), BRACKET_EVERY, 1);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    // Without stdin the tournament only starts once full.
+    let mut stdin_open = true;
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        let mut start = false;
+        tokio::select! {
+            b = th.next() => {
+                let Some(env) = signing::open::<TournamentBody>(&b?) else {
+                    continue;
+                };
+                if env.sig.is_none() {
+                    continue;
+                }
+                match env.body {
+                    TournamentBody::Join { rating }
+                        if !bracket.started()
+                            && entrants.len() < players
+                            && !entrants.contains_key(&env.sender_id) =>
+                    {
+                        entrants.insert(env.sender_id.clone(), rating);
+                        bracket.players = entrants.keys().cloned().collect();
+                        let text = t!(
+                            "tournament.entered",
+                            player = name(&directory, &env.sender_id),
+                            count = entrants.len(),
+                            max = players
+                        );
+                        println!("{}", out.info(&text));
+                        start = entrants.len() == players;
+                        if !start {
+                            let body = TournamentBody::Bracket { bracket: bracket.clone() };
+                            publish(&*th, &me, body).await?;
+                        }
+                    }
+                    TournamentBody::Result { attestation, evidence } if bracket.started() => {
+                        let Some(attestation) = rating::open(&attestation) else {
+                            continue;
+                        };
+                        if !bracket.report(&attestation, &evidence, &registry, now_ms()) {
+                            continue;
+                        }
+                        save(&bracket);
+                        let body = TournamentBody::Bracket { bracket: bracket.clone() };
+                        publish(&*th, &me, body).await?;
+                        let result = &attestation.result;
+                        show_result(out, &directory, &result.players, &result.outcome);
+                        if let Some(champion) = bracket.champion() {
+                            let champion = name(&directory, champion);
+                            let text = t!("tournament.champion", player = champion);
+                            println!("{}", out.success(&text));
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            () = scheduler.next() => {
+                let body = TournamentBody::Bracket { bracket: bracket.clone() };
+                publish(&*th, &me, body).await?;
+            }
+            line = lines.next_line(), if stdin_open => {
+                if line?.is_none() {
+                    stdin_open = false;
+                    continue;
+                }
+                if bracket.started() {
+                    continue;
+                }
+                if entrants.len() < 2 {
+                    println!("{}", out.warn(&t!("tournament.too_few")));
+                    continue;
+                }
+                start = true;
+            }
+            _ = &mut interrupted => return Ok(()),
+        }
+        if start {
+            bracket.start(&entrants, now_ms());
+            save(&bracket);
+            let body = TournamentBody::Bracket {
+                bracket: bracket.clone(),
+            };
+            publish(&*th, &me, body).await?;
+            let text = t!("tournament.started", players = entrants.len());
+            println!("{}", out.success(&text));
+            show(out, &directory, &bracket)?;
+        }
+    }
+    // Once more for players that missed the final bracket.
+    tokio::time::sleep(BRACKET_EVERY).await;
+    publish(&*th, &me, TournamentBody::Bracket { bracket }).await
+}
+
+fn show_result(out: &Output, directory: &PeerDirectory, players: &[String], outcome: &Outcome) {
+    let Outcome::Winner { seat } = outcome else {
+        return;
+    };
+    let text = t!(
+        "tournament.decided",
+        winner = name(directory, &players[*seat]),
+        loser = name(directory, &players[1 - seat])
+    );
+    println!("{}", out.info(&text));
+}
+
+/// What a player does next, as the bracket says.
+enum Next {
+    /// Play pairing `index` of `round` against `opponent`.
+    Play {
+        round: usize,
+        index: usize,
+        opponent: String,
+        host: bool,
+        started: u64,
+    },
+    Done,
+}
+
+/// Enter the tournament of `ticket` and play every match of ours until we
+/// are out or won it.
+async fn join(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &mut SessionState,
+    ticket: TournamentTicket,
+) -> Result<()> {
+    let out = &ctx.out;
+    restore::restore(transport, ctx, session).await?;
+    let me = sender_id(transport, session);
+    let directory = PeerDirectory::load().unwrap_or_default();
+    println!("{}", t!("tournament.waiting", id = ticket.id));
+    let mut entered = false;
+    // The round, signed attestation and moves of our last decided match.
+    let mut reported: Option<(usize, Value, Evidence)> = None;
+    loop {
+        let mut th = transport
+            .join_topic(transport.topic_from_name(&ticket.topic_name()))
+            .await?;
+        let mut scheduler = Scheduler::new(ctx.budget.clone(), &ctx.config.scheduler);
+        scheduler.every((), BRACKET_EVERY, 1);
+        let mut last: Option<Bracket> = None;
+        let interrupted = tokio::signal::ctrl_c();
+        tokio::pin!(interrupted);
+        let next = loop {
+            tokio::select! {
+                b = th.next() => {
+                    let Some(bracket) = bracket_from(&b?, &ticket) else {
+                        continue;
+                    };
+                    if last.as_ref() == Some(&bracket) {
+                        continue;
+                    }
+                    save(&bracket);
+                    last = Some(bracket.clone());
+                    if let Some(champion) = bracket.champion() {
+                        let text = t!("tournament.champion", player = name(&directory, champion));
+                        println!("{}", out.success(&text));
+                        break Next::Done;
+                    }
+                    let listed = bracket.players.contains(&me);
+                    if !bracket.started() {
+                        if listed && !entered {
+                            entered = true;
+                            let text = t!("tournament.joined", count = bracket.players.len());
+                            println!("{}", out.success(&text));
+                        }
+                        continue;
+                    }
+                    if !listed {
+                        println!("{}", out.warn(&t!("tournament.not_entered")));
+                        break Next::Done;
+                    }
+                    if bracket.eliminated(&me) {
+                        println!("{}", out.info(&t!("tournament.eliminated")));
+                        break Next::Done;
+                    }
+                    let Some((round, index, pairing)) = bracket.pairing_of(&me) else {
+                        continue;
+                    };
+                    let Some(opponent) = pairing.opponent(&me) else {
+                        continue;
+                    };
+                    let decided = reported.as_ref().is_some_and(|(r, ..)| *r == round);
+                    if pairing.winner.is_none() && !decided {
+                        break Next::Play {
+                            round,
+                            index,
+                            opponent: opponent.to_string(),
+                            host: pairing.home == me,
+                            started: bracket.current().map_or(0, |(_, r)| r.started),
+                        };
+                    }
+                }
+                () = scheduler.next() => {
+                    let Some(bracket) = &last else {
+                        continue;
+                    };
+                    if !bracket.started() && !bracket.players.contains(&me) {
+                        let rating = RatingBook::load()
+                            .unwrap_or_default()
+                            .rating(&me, &bracket.game)
+                            .round() as u32;
+                        publish(&*th, &me, TournamentBody::Join { rating }).await?;
+                    }
+                    if let Some((_, attestation, evidence)) = &reported {
+                        let body = TournamentBody::Result {
+                            attestation: attestation.clone(),
+                            evidence: evidence.clone(),
+                        };
+                        publish(&*th, &me, body).await?;
+                    }
+                }
+                _ = &mut interrupted => return Ok(()),
+            }
+        };
+        let Next::Play {
+            round,
+            index,
+            opponent,
+            host,
+            started,
+        } = next
+        else {
+            return Ok(());
+        };
+        drop(th);
+        let game = last.map(|b| b.game).unwrap_or_default();
+        let topic = transport.topic_from_name(&room_topic_name(&ticket.id, round, index));
+        let paired = Paired {
+            partner: opponent.clone(),
+            topic_hex: transport.topic_to_hex(&topic),
+            host,
+            order: TurnOrder::Random,
+        };
+        let room = format!("{game}-{}-{}", ticket.id, round + 1);
+        play_paired(transport, ctx, session, &game, room, paired, &me).await?;
+        let book = RatingBook::load().unwrap_or_default();
+        let Some(signed) = book.latest_from(&opponent, &game, started).cloned() else {
+            println!("{}", out.warn(&t!("tournament.left")));
+            return Ok(());
+        };
+        // A draw is played again; anything else decides the pairing.
+        let Some(attestation) = rating::open(&signed) else {
+            continue;
+        };
+        if matches!(attestation.result.outcome, Outcome::Winner { .. }) {
+            let evidence = MatchLog::open()
+                .load()
+                .unwrap_or_default()
+                .into_iter()
+                .find(|r| r.game_id == attestation.result.game_id)
+                .map(|r| Evidence {
+                    replay: r.replay,
+                    signed_moves: r.signed_moves,
+                })
+                .unwrap_or_default();
+            reported = Some((round, signed, evidence));
+        }
+    }
+}
+
+/// Tournaments we took part in, one line each.
+fn list(out: &Output) -> Result<()> {
+    let saved = Tournaments::load().unwrap_or_default();
+    if out.is_json() {
+        return print_json(&saved.all().collect::<Vec<_>>());
+    }
+    let directory = PeerDirectory::load().unwrap_or_default();
+    let mut any = false;
+    for bracket in saved.all() {
+        any = true;
+        let line = match (bracket.champion(), bracket.current()) {
+            (Some(champion), _) => t!(
+                "tournament.state.won",
+                id = bracket.id,
+                game = bracket.game,
+                player = name(&directory, champion)
+            ),
+            (None, Some((round, _))) => t!(
+                "tournament.state.round",
+                id = bracket.id,
+                game = bracket.game,
+                round = round + 1,
+                rounds = rounds(bracket)
+            ),
+            (None, None) => t!(
+                "tournament.state.registering",
+                id = bracket.id,
+                game = bracket.game,
+                count = bracket.players.len()
+            ),
+        };
+        println!("{line}");
+    }
+    if !any {
+        println!("{}", out.info(&t!("tournament.none")));
+    }
+    Ok(())
+}
+
+/// Rounds the tournament takes.
+fn rounds(bracket: &Bracket) -> u32 {
+    bracket.players.len().next_power_of_two().trailing_zeros()
+}
+
+/// The bracket of `ticket`, fresh from the organizer if it answers in time.
+async fn status(ctx: &Ctx, ticket: TournamentTicket) -> Result<()> {
+    let transport = ctx.start_node().await?;
+    let mut th = transport
+        .join_topic(transport.topic_from_name(&ticket.topic_name()))
+        .await?;
+    let wait = tokio::time::sleep(BRACKET_EVERY * 2);
+    tokio::pin!(wait);
+    let fresh = loop {
+        tokio::select! {
+            b = th.next() => {
+                if let Some(bracket) = bracket_from(&b?, &ticket) {
+                    break Some(bracket);
+                }
+            }
+            _ = &mut wait => break None,
+        }
+    };
+    let bracket = match fresh {
+        Some(bracket) => {
+            save(&bracket);
+            bracket
+        }
+        None => match Tournaments::load().unwrap_or_default().get(&ticket.id) {
+            Some(bracket) => bracket.clone(),
+            None => bail!(t!("tournament.unknown", id = ticket.id)),
+        },
+    };
+    show(
+        &ctx.out,
+        &PeerDirectory::load().unwrap_or_default(),
+        &bracket,
+    )
+}
+
+fn show(out: &Output, directory: &PeerDirectory, bracket: &Bracket) -> Result<()> {
+    if out.is_json() {
+        return print_json(bracket);
+    }
+    let title = t!("tournament.title", game = bracket.game, id = bracket.id);
+    println!("{}", out.heading(&title));
+    if !bracket.started() {
+        let players: Vec<String> = bracket.players.iter().map(|p| name(directory, p)).collect();
+        let text = t!(
+            "tournament.state.registering",
+            id = bracket.id,
+            game = bracket.game,
+            count = players.len()
+        );
+        println!("{text}");
+        for player in players {
+            println!("  {player}");
+        }
+        return Ok(());
+    }
+    for (i, round) in bracket.rounds.iter().enumerate() {
+        println!("{}", t!("tournament.round", round = i + 1));
+        for pairing in &round.pairings {
+            let home = name(directory, &pairing.home);
+            let line = match (&pairing.away, &pairing.winner) {
+                (None, _) => t!("tournament.bye", player = home),
+                (Some(away), None) => {
+                    t!(
+                        "tournament.pairing",
+                        home = home,
+                        away = name(directory, away)
+                    )
+                }
+                (Some(away), Some(winner)) => t!(
+                    "tournament.pairing_won",
+                    home = home,
+                    away = name(directory, away),
+                    winner = name(directory, winner)
+                ),
+            };
+            println!("  {line}");
+        }
+    }
+    if let Some(champion) = bracket.champion() {
+        let text = t!("tournament.champion", player = name(directory, champion));
+        println!("{}", out.success(&text));
+    }
+    Ok(())
+}
@@ -0,0 +1,2225 @@
+//! `p2p-games tui`: a full-screen client.
+//!
+//! The left column lists the chat views (global chat, then the joined rooms)
+//! and the public rooms heard of on the discovery topic, with their game,
+//! player count and language; Enter on a public room joins it with the
+//! ticket it announces (see [`RoomBrowser`]). The chat of the
+//! selected view fills the middle, its members (or the peers online, for
+//! global chat) the right, and an input line the bottom. Keys follow the
+//! `[keymap]` of the config file and are reloaded when it changes.
+//!
+//! Unsent input is kept per view in the [`Drafts`]. `/edit <text>` replaces
+//! our last message of the view (`/edit` alone puts it into the input line),
+//! `/delete` retracts it and `/resend` sends it again. `/stats <game>
+//! [nick]` charts our results in a game (see [`crate::dashboard`]). Canned
+//! messages (`/gg`, see [`p2p_core::canned`]) are expanded, and `[canned.keys]`
+//! send them with one key. Joins our rooms' admission policy leaves to us
+//! pop up one at a time; `y` lets the peer in, `n` turns it away. Game
+//! invitations pop up the same way: `y` joins the private room of the
+//! invitation, `n` declines it.
+//!
+//! In the chat pane the cursor keys select a line; the message actions of
+//! the keymap reply to it, copy it, or edit or delete it if it is ours. `/`
+//! searches the lines of the pane, history loaded at start included, as
+//! the query is typed; matches are highlighted and `n`/`N` jump between
+//! them (see [`ScrollbackSearch`]).
+//!
+//! The command palette offers every command of the command line: the chosen
+//! one lands in the input line as `/run <command>`. `/run` runs a command in
+//! a process of its own, on the same profile, and shows its output in the
+//! chat pane.
+//!
+//! Direct messages stay out of the room views: the conversations pane lists
+//! them per peer, newest first, with the number of unread messages (see
+//! [`ReadMarks`]). Opening one shows it in the chat pane, and the input
+//! then goes to that peer; `/dm <peer>` starts a new conversation.
+//!
+//! The newest announcement of a configured operator (see
+//! [`p2p_core::announce`]) runs as a banner across the top until it expires.
+
+use anyhow::Result;
+use p2p_core::{
+    announce::{self, ANNOUNCEMENTS_TOPIC_NAME, Announcement, AnnouncementKind, AnnouncementStore},
+    browser::RoomBrowser,
+    canned::CannedConfig,
+    connectivity::{ConnectivityChange, PartitionDetector},
+    dashboard::GameStats,
+    digest::DigestSync,
+    directory::PeerDirectory,
+    discovery::{DISCOVERY_TOPIC_NAME, Discovery, ROOM_TTL_MS, RoomCache},
+    dm::{InviteBody, ReadMarks, conversation_peer, inbox_topic_name, make_direct, make_invite},
+    drafts::{Drafts, dm_scope_key, scope_key},
+    game::{GameKind, GameRegistry},
+    history::{COMPACT_EVERY, HistoryEntry, HistoryScope, HistoryStore},
+    hlc::{self, OrderBuffer},
+    keymap::{Action, Key, KeyChord, Keymap, KeymapReloader},
+    middleware::RecentIds,
+    notify::NotifyEvent,
+    output::{Output, UiEvent},
+    palette::{CommandPalette, help_entries},
+    protocol::MemberRole,
+    protocol::{
+        ChatChange, ChatMsg, DiscoveryBody, Envelope, GLOBAL_CHAT_TOPIC_NAME,
+        NAME_REGISTRY_TOPIC_NAME, NameClaim, NameRelease, RoomSummary, Scope, make_chat_delete,
+        make_chat_edit, make_chat_global, make_chat_room, now_ms,
+    },
+    rating::RatingBook,
+    registry::{ClaimCache, NameRegistry, NickGuard},
+    rooms::{RoomRef, RoomTicket, TopicFeed},
+    search::ScrollbackSearch,
+    session::{self, SessionState},
+    signing, storage, t, titles,
+    vouch::VouchBook,
+};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Bar, BarChart, BarGroup, Block, Clear, List, ListItem, ListState, Paragraph, Wrap},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, VecDeque},
+    process::Stdio,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle};
+
+use crate::{
+    Ctx, Node, Periodic, archive, clipboard, connect_host, dashboard,
+    members::{JoinRequest, Notice, RoomMembers},
+    publish_digests, remember_own, restore, schedule, sender_id,
+    shutdown::Goodbye,
+    subscribe_rooms, subscribed, sync_tables,
+};
+
+/// Chat lines kept in memory.
+const SCROLLBACK: usize = 1000;
+
+/// Lines moved by [`Action::PageUp`] and [`Action::PageDown`].
+const PAGE: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Rooms,
+    Dms,
+    Public,
+    Chat,
+    Input,
+}
+
+impl Pane {
+    fn next(self) -> Self {
+        match self {
+            Pane::Rooms => Pane::Dms,
+            Pane::Dms => Pane::Public,
+            Pane::Public => Pane::Chat,
+            Pane::Chat => Pane::Input,
+            Pane::Input => Pane::Rooms,
+        }
+    }
+
+    fn prev(self) -> Self {
+        match self {
+            Pane::Rooms => Pane::Input,
+            Pane::Dms => Pane::Rooms,
+            Pane::Public => Pane::Dms,
+            Pane::Chat => Pane::Public,
+            Pane::Input => Pane::Chat,
+        }
+    }
+}
+
+/// One line of the chat log.
+#[derive(Clone)]
+enum Entry {
+    /// `room` is the view label, `None` for global chat.
+    Chat {
+        room: Option<String>,
+        from: String,
+        text: String,
+        msg_id: String,
+        sender_id: String,
+        edited: bool,
+        /// Retracted by the sender; `text` is empty.
+        deleted: bool,
+    },
+    /// A direct message; `peer` is the other side of the conversation.
+    Direct {
+        peer: String,
+        from: String,
+        text: String,
+    },
+    /// Shown in every view.
+    Notice { text: String, color: Color },
+}
+
+impl Entry {
+    fn chat(room: Option<String>, from: String, text: String, env: &Envelope<ChatMsg>) -> Self {
+        Self::Chat {
+            room,
+            from,
+            text,
+            msg_id: env.msg_id.clone(),
+            sender_id: env.sender_id.clone(),
+            edited: false,
+            deleted: false,
+        }
+    }
+
+    /// What search looks at.
+    fn text(&self) -> &str {
+        match self {
+            Entry::Chat { text, .. } | Entry::Direct { text, .. } | Entry::Notice { text, .. } => {
+                text
+            }
+        }
+    }
+}
+
+/// Received chat waiting in the [`OrderBuffer`].
+enum Incoming {
+    Line(Entry),
+    Change(ChatChange),
+}
+
+/// What the event loop has to do after a key press.
+enum Request {
+    Send(String),
+    /// Replace our last message of the view.
+    Edit(String),
+    /// Retract our last message of the view.
+    Delete,
+    /// Put the text on the clipboard.
+    Copy(String),
+    /// Send our last message of the view again.
+    Resend,
+    /// Make the room the active one.
+    Switch(String),
+    /// Show our statistics in `game`, against `vs` only if given.
+    Stats {
+        game: String,
+        vs: Option<String>,
+    },
+    /// Answer the oldest join request.
+    Admit(bool),
+    /// Answer the oldest game invitation.
+    Answer(bool),
+    /// Join a public room from the list.
+    Join(RoomSummary),
+    /// Open the conversation with a peer (nickname or peer id).
+    OpenDm(String),
+    /// Run a command line in a process of its own.
+    Run(Vec<String>),
+}
+
+/// A game invitation waiting for `y` or `n`.
+struct Invitation {
+    /// Peer id of the inviter, who hosts the room.
+    from: String,
+    name: String,
+    invite_id: String,
+    game: GameKind,
+    ticket: RoomTicket,
+    /// Unix millis.
+    expires: u64,
+}
+
+impl Invitation {
+    /// The invitation in `env`, if it is one for `me` that can still be
+    /// answered.
+    fn new(env: Envelope<InviteBody>, me: &str, directory: &PeerDirectory) -> Option<Self> {
+        let InviteBody::Invite {
+            invite_id,
+            game,
+            ticket,
+            expires,
+            ..
+        } = env.body
+        else {
+            return None;
+        };
+        let ticket: RoomTicket = ticket.parse().ok()?;
+        let valid = env.sig.is_some()
+            && env.room_id.as_deref() == Some(me)
+            && ticket.host_addr == env.sender_id
+            && expires > now_ms();
+        valid.then(|| Self {
+            name: nickname(directory, &env.sender_id),
+            from: env.sender_id,
+            invite_id,
+            game,
+            ticket,
+            expires,
+        })
+    }
+}
+
+/// A direct message conversation, for the conversations pane.
+struct Conversation {
+    peer_id: String,
+    name: String,
+    unread: usize,
+}
+
+/// The `/stats` overlay, ready to draw.
+struct StatsView {
+    title: String,
+    /// Opponent → wins, losses and draws.
+    results: Vec<(String, [u64; 3])>,
+    openings: Vec<(String, u64)>,
+    /// Average length and streaks.
+    lines: Vec<String>,
+}
+
+impl StatsView {
+    fn new(stats: &GameStats, directory: &PeerDirectory) -> Self {
+        let results = stats
+            .opponents
+            .iter()
+            .map(|(peer_id, t)| {
+                let counts = [t.wins, t.losses, t.draws].map(u64::from);
+                (dashboard::name(directory, peer_id), counts)
+            })
+            .collect();
+        let avg = format!("{:.1}", stats.total.avg_moves());
+        let mut lines = vec![t!("tui.stats_avg_moves", moves = avg)];
+        lines.extend(dashboard::streaks(stats));
+        Self {
+            title: dashboard::title(stats, directory),
+            results,
+            openings: stats
+                .openings
+                .iter()
+                .map(|(mv, n)| (mv.clone(), u64::from(*n)))
+                .collect(),
+            lines,
+        }
+    }
+}
+
+struct App {
+    /// Our peer id.
+    me: String,
+    keymap: Keymap,
+    canned: CannedConfig,
+    /// Keys that send a canned message, by name.
+    canned_keys: Vec<(KeyChord, String)>,
+    /// Chat views: `None` is global chat (absent in safe mode), then rooms.
+    views: Vec<Option<String>>,
+    /// Index of the view shown.
+    view: usize,
+    /// Cursor in the rooms pane.
+    selected: usize,
+    /// Public rooms heard of.
+    cache: RoomCache,
+    /// The public rooms pane, fed by `cache`.
+    browser: RoomBrowser,
+    entries: VecDeque<Entry>,
+    /// Lines scrolled back from the newest.
+    scroll: usize,
+    /// Line selected in the chat pane, counted back from the newest.
+    picked: Option<usize>,
+    /// Message id of our message `/edit` and `/delete` act on instead of
+    /// the last one.
+    target: Option<String>,
+    /// Height of the chat pane when last drawn.
+    chat_height: Cell<usize>,
+    search: ScrollbackSearch,
+    /// The search query is being typed.
+    searching: bool,
+    input: String,
+    drafts: Drafts,
+    focus: Pane,
+    help: bool,
+    stats: Option<StatsView>,
+    /// Joins waiting for us, oldest first.
+    joins: VecDeque<JoinRequest>,
+    /// Game invitations waiting for us, oldest first.
+    invites: VecDeque<Invitation>,
+    /// Direct message conversations, newest first.
+    dms: Vec<Conversation>,
+    /// Cursor in the conversations pane.
+    dm_selected: usize,
+    /// Peer id of the conversation shown instead of the view, if any.
+    dm: Option<String>,
+    marks: ReadMarks,
+    /// Operator announcements to show, newest first.
+    banners: Vec<Envelope<Announcement>>,
+    /// The Ctrl-P overlay, while open.
+    palette: Option<CommandPalette>,
+    /// Neighbors on the subscribed topics, shown under the input.
+    detector: PartitionDetector,
+    quit: bool,
+}
+
+impl App {
+    fn current(&self) -> Option<&str> {
+        self.views.get(self.view).and_then(|v| v.as_deref())
+    }
+
+    fn scope(&self) -> String {
+        match &self.dm {
+            Some(peer_id) => dm_scope_key(peer_id),
+            None => scope_key(self.current()),
+        }
+    }
+
+    /// A direct message of the conversation with `peer_id` (called `name`)
+    /// came in or went out; the conversation moves to the top.
+    fn direct(&mut self, peer_id: &str, name: &str, from: String, text: String, unread: bool) {
+        let mut conversation = match self.dms.iter().position(|c| c.peer_id == peer_id) {
+            Some(i) => self.dms.remove(i),
+            None => Conversation {
+                peer_id: peer_id.to_string(),
+                name: name.to_string(),
+                unread: 0,
+            },
+        };
+        if unread {
+            conversation.unread += 1;
+        }
+        self.dms.insert(0, conversation);
+        self.push(Entry::Direct {
+            peer: peer_id.to_string(),
+            from,
+            text,
+        });
+    }
+
+    /// Show the conversation with `peer_id` and mark it read.
+    fn open_dm(&mut self, peer_id: &str, name: &str) {
+        let scope = self.scope();
+        self.drafts.set_draft(&scope, &self.input);
+        let _ = self.drafts.save();
+        match self.dms.iter_mut().find(|c| c.peer_id == peer_id) {
+            Some(c) => c.unread = 0,
+            None => self.dms.insert(
+                0,
+                Conversation {
+                    peer_id: peer_id.to_string(),
+                    name: name.to_string(),
+                    unread: 0,
+                },
+            ),
+        }
+        self.marks.read(peer_id, now_ms());
+        let _ = self.marks.save();
+        self.dm = Some(peer_id.to_string());
+        self.input = self.drafts.draft(&self.scope()).to_string();
+        self.reset_chat();
+        self.focus = Pane::Input;
+    }
+
+    /// Show view `i` in the chat pane.
+    fn switch_view(&mut self, i: usize) -> Option<Request> {
+        let scope = self.scope();
+        self.drafts.set_draft(&scope, &self.input);
+        let _ = self.drafts.save();
+        self.dm = None;
+        self.view = i;
+        self.selected = i;
+        self.input = self.drafts.draft(&self.scope()).to_string();
+        self.reset_chat();
+        self.focus = Pane::Input;
+        self.current().map(|room| Request::Switch(room.to_string()))
+    }
+
+    /// The chat pane shows another view or conversation.
+    fn reset_chat(&mut self) {
+        self.scroll = 0;
+        self.picked = None;
+        self.target = None;
+        self.reset_search();
+    }
+
+    fn push(&mut self, entry: Entry) {
+        if self.entries.len() == SCROLLBACK {
+            self.entries.pop_front();
+        }
+        // The selection stays on its line.
+        if self.visible(&entry) {
+            self.picked = self.picked.map(|p| p + 1);
+        }
+        self.entries.push_back(entry);
+        if self.search.is_active() {
+            self.search(|search, lines| search.rescan(lines));
+        }
+    }
+
+    /// Update the search over the chat pane's lines with `f`.
+    fn search(&mut self, f: impl FnOnce(&mut ScrollbackSearch, &[&str])) {
+        let mut search = std::mem::take(&mut self.search);
+        let lines: Vec<&str> = self.shown().into_iter().map(Entry::text).collect();
+        f(&mut search, &lines);
+        self.search = search;
+    }
+
+    /// Select `line` of the chat pane, a search match counted from the
+    /// oldest line.
+    fn jump(&mut self, line: Option<usize>) {
+        if let Some(p) = line.and_then(|l| self.shown().len().checked_sub(l + 1)) {
+            self.pick(p);
+        }
+    }
+
+    /// `text` in `style`, with the search matches highlighted.
+    fn marked<'a>(&self, text: &'a str, style: Style) -> Vec<Span<'a>> {
+        let mut spans = Vec::new();
+        let mut at = 0;
+        for range in self.search.highlights(text) {
+            spans.push(Span::styled(&text[at..range.start], style));
+            spans.push(Span::styled(
+                &text[range.clone()],
+                style.black().on_yellow(),
+            ));
+            at = range.end;
+        }
+        spans.push(Span::styled(&text[at..], style));
+        spans
+    }
+
+    /// Whether the chat pane shows `entry`.
+    fn visible(&self, entry: &Entry) -> bool {
+        match entry {
+            Entry::Chat { room, .. } => self.dm.is_none() && room.as_deref() == self.current(),
+            Entry::Direct { peer, .. } => self.dm.as_ref() == Some(peer),
+            Entry::Notice { .. } => true,
+        }
+    }
+
+    /// Lines of the chat pane, oldest first.
+    fn shown(&self) -> Vec<&Entry> {
+        self.entries.iter().filter(|e| self.visible(e)).collect()
+    }
+
+    fn picked_entry(&self) -> Option<&Entry> {
+        let shown = self.shown();
+        let i = shown.len().checked_sub(self.picked? + 1)?;
+        Some(shown[i])
+    }
+
+    /// Select line `p` of the chat pane and scroll it into sight.
+    fn pick(&mut self, p: usize) {
+        let height = self.chat_height.get().max(1);
+        self.picked = Some(p);
+        self.scroll = self.scroll.clamp((p + 1).saturating_sub(height), p);
+    }
+
+    /// Reply to, copy, edit or delete the selected line.
+    fn message_action(&mut self, action: Action) -> Option<Request> {
+        let Some(entry) = self.picked_entry().cloned() else {
+            self.notice(t!("tui.nothing_picked"), Color::DarkGray);
+            return None;
+        };
+        match (action, entry) {
+            (
+                Action::CopyMessage,
+                Entry::Chat { text, .. } | Entry::Direct { text, .. } | Entry::Notice { text, .. },
+            ) => return Some(Request::Copy(text)),
+            (Action::Reply, Entry::Chat { from, .. } | Entry::Direct { from, .. }) => {
+                self.input = format!("@{from} ");
+                self.focus = Pane::Input;
+            }
+            (Action::EditMessage | Action::DeleteMessage, Entry::Direct { .. }) => {
+                self.notice(t!("tui.dm_no_edit"), Color::DarkGray);
+            }
+            (
+                Action::EditMessage | Action::DeleteMessage,
+                Entry::Chat {
+                    msg_id,
+                    sender_id,
+                    text,
+                    deleted: false,
+                    ..
+                },
+            ) if sender_id == self.me => {
+                self.target = Some(msg_id);
+                if action == Action::DeleteMessage {
+                    return Some(Request::Delete);
+                }
+                self.input = format!("/edit {text}");
+                self.focus = Pane::Input;
+            }
+            (Action::EditMessage | Action::DeleteMessage, _) => {
+                self.notice(t!("tui.not_yours"), Color::DarkGray);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn reset_search(&mut self) {
+        self.search.clear();
+        self.searching = false;
+    }
+
+    /// Whether `env` is our last message of the view, the one `/resend`
+    /// sends.
+    fn is_last(&self, env: &Envelope<ChatMsg>) -> bool {
+        self.drafts
+            .last(&self.scope())
+            .is_some_and(|last| last.msg_id == env.msg_id)
+    }
+
+    /// The message `/edit` or `/delete` acts on: the one picked with the
+    /// message actions, else our last one of the view.
+    fn original(&mut self, session: &SessionState) -> Option<Envelope<ChatMsg>> {
+        let Some(msg_id) = self.target.take() else {
+            return self.drafts.last(&self.scope()).cloned();
+        };
+        let me = self.me.clone();
+        let mut env = match self.current() {
+            Some(room) => make_chat_room(
+                session.rooms.get(room)?.topic_hex.clone(),
+                me,
+                String::new(),
+            ),
+            None => make_chat_global(me, String::new()),
+        };
+        // Edits and retractions name their target by id alone.
+        env.msg_id = msg_id;
+        Some(env)
+    }
+
+    /// Edit or tombstone the line `change` targets, if it is still in the
+    /// log and was written by the same peer.
+    fn apply(&mut self, change: &ChatChange) {
+        let line = self.entries.iter_mut().rev().find_map(|e| match e {
+            Entry::Chat {
+                msg_id,
+                sender_id,
+                text,
+                edited,
+                deleted,
+                ..
+            } if msg_id == change.target_msg_id() && sender_id == change.sender_id() => {
+                Some((text, edited, deleted))
+            }
+            _ => None,
+        });
+        let Some((text, edited, deleted)) = line else {
+            return;
+        };
+        match change.new_text() {
+            Some(new) => {
+                *text = new.to_string();
+                *edited = true;
+            }
+            None => {
+                text.clear();
+                *deleted = true;
+            }
+        }
+        if self.search.is_active() {
+            self.search(|search, lines| search.rescan(lines));
+        }
+    }
+
+    /// The banner line of `banner`, with the number of other announcements.
+    fn banner(&self, banner: &Envelope<Announcement>) -> Line<'static> {
+        let color = match banner.body.kind {
+            AnnouncementKind::Info => Color::Blue,
+            AnnouncementKind::Maintenance => Color::Yellow,
+            AnnouncementKind::Release => Color::Green,
+            AnnouncementKind::Tournament => Color::Magenta,
+        };
+        let kind = t!(&format!("announce.{}", banner.body.kind.key()));
+        let mut spans = vec![
+            Span::styled(
+                format!(" {kind} "),
+                Style::new().fg(Color::Black).bg(color).bold(),
+            ),
+            Span::raw(format!(" {}", banner.body.text)),
+        ];
+        if self.banners.len() > 1 {
+            let more = t!("announce.more", count = self.banners.len() - 1);
+            spans.push(Span::styled(more, Style::new().fg(Color::DarkGray)));
+        }
+        Line::from(spans)
+    }
+
+    fn notice(&mut self, text: String, color: Color) {
+        self.push(Entry::Notice { text, color });
+    }
+
+    fn notices(&mut self, notices: Vec<Notice>) {
+        for n in notices {
+            let color = if n.warn {
+                Color::Yellow
+            } else {
+                Color::DarkGray
+            };
+            self.notice(n.text, color);
+        }
+    }
+
+    /// Fill the log with the unexpired history of the views and
+    /// conversations.
+    fn preload(&mut self, ctx: &Ctx, session: &SessionState, directory: &PeerDirectory, me: &str) {
+        let now = now_ms();
+        let lines = HistoryStore::open().load().unwrap_or_default();
+        for e in lines
+            .iter()
+            .filter(|e| !ctx.config.history.is_expired(e, now))
+        {
+            let room = match e.scope {
+                HistoryScope::Global => None,
+                HistoryScope::Room => match session
+                    .rooms
+                    .rooms()
+                    .iter()
+                    .find(|r| e.room.as_deref() == Some(r.topic_hex.as_str()))
+                {
+                    Some(r) => Some(r.name.clone()),
+                    None => continue,
+                },
+                HistoryScope::Dm => {
+                    if let Some(peer_id) = conversation_peer(e, me) {
+                        let from = e
+                            .sender_nick
+                            .clone()
+                            .unwrap_or_else(|| nickname(directory, &e.sender_id));
+                        let unread = e.sender_id != me && self.marks.is_unread(peer_id, e.ts);
+                        let name = nickname(directory, peer_id);
+                        self.direct(peer_id, &name, from, e.text.clone(), unread);
+                    }
+                    continue;
+                }
+            };
+            if !self.views.contains(&room) {
+                continue;
+            }
+            let from = e
+                .sender_nick
+                .clone()
+                .unwrap_or_else(|| nickname(directory, &e.sender_id));
+            self.push(Entry::Chat {
+                room,
+                from,
+                text: e.text.clone(),
+                msg_id: e.msg_id.clone(),
+                sender_id: e.sender_id.clone(),
+                edited: e.edited,
+                deleted: e.deleted,
+            });
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) -> Option<Request> {
+        let chord = chord(key)?;
+        let actions = self.keymap.actions(&chord);
+        let bound = |a: Action| actions.contains(&a);
+        if bound(Action::Quit) {
+            self.quit = true;
+            return None;
+        }
+        if self.help {
+            if bound(Action::Help) || bound(Action::Cancel) || chord.key == Key::Enter {
+                self.help = false;
+            }
+            return None;
+        }
+        if self.stats.is_some() {
+            if bound(Action::Cancel) || chord.key == Key::Enter {
+                self.stats = None;
+            }
+            return None;
+        }
+        if let Some(palette) = &mut self.palette {
+            match chord.key {
+                Key::Char(c) if !chord.ctrl && !chord.alt => palette.push_char(c),
+                Key::Backspace => palette.pop_char(),
+                Key::Enter => {
+                    if let Some(command) = palette.selected() {
+                        let (title, params) = (command.title(), command.params.join(" "));
+                        self.input = format!("/run {title} ");
+                        self.focus = Pane::Input;
+                        if !params.is_empty() {
+                            let text = t!("tui.palette_params", command = title, params = params);
+                            self.notice(text, Color::DarkGray);
+                        }
+                    }
+                    self.palette = None;
+                }
+                _ if bound(Action::CursorUp) => palette.select_prev(),
+                _ if bound(Action::CursorDown) => palette.select_next(),
+                _ if bound(Action::Cancel) || bound(Action::CommandPalette) => self.palette = None,
+                _ => {}
+            }
+            return None;
+        }
+        if !self.joins.is_empty() {
+            return match chord.key {
+                Key::Char('y') => Some(Request::Admit(true)),
+                Key::Char('n') => Some(Request::Admit(false)),
+                _ if bound(Action::Cancel) => Some(Request::Admit(false)),
+                _ => None,
+            };
+        }
+        if !self.invites.is_empty() {
+            return match chord.key {
+                Key::Char('y') => Some(Request::Answer(true)),
+                Key::Char('n') => Some(Request::Answer(false)),
+                _ if bound(Action::Cancel) => Some(Request::Answer(false)),
+                _ => None,
+            };
+        }
+        if self.searching {
+            match chord.key {
+                Key::Char(c) if !chord.ctrl && !chord.alt => {
+                    self.search(|search, lines| search.push_char(c, lines));
+                    self.jump(self.search.current_line());
+                }
+                Key::Backspace => {
+                    self.search(|search, lines| search.pop_char(lines));
+                    self.jump(self.search.current_line());
+                }
+                Key::Enter => self.searching = false,
+                _ if bound(Action::Cancel) => self.reset_search(),
+                _ => {}
+            }
+            return None;
+        }
+        if let Some((_, name)) = self.canned_keys.iter().find(|(k, _)| *k == chord)
+            && let Some(text) = self.canned.text(name, self.current())
+        {
+            return Some(Request::Send(text));
+        }
+        if bound(Action::CommandPalette) {
+            self.palette = Some(CommandPalette::default());
+            return None;
+        }
+        if bound(Action::NextPane) {
+            self.focus = self.focus.next();
+            return None;
+        }
+        if bound(Action::PrevPane) {
+            self.focus = self.focus.prev();
+            return None;
+        }
+        if self.focus == Pane::Input {
+            match chord.key {
+                Key::Char(c) if !chord.ctrl && !chord.alt => {
+                    self.input.push(c);
+                    return None;
+                }
+                Key::Backspace => {
+                    self.input.pop();
+                    return None;
+                }
+                Key::Enter => {
+                    let text = std::mem::take(&mut self.input);
+                    self.scroll = 0;
+                    return self.request(text);
+                }
+                _ if bound(Action::Cancel) => {
+                    self.input.clear();
+                    self.target = None;
+                    return None;
+                }
+                _ => {}
+            }
+        }
+        if self.focus == Pane::Rooms {
+            if bound(Action::CursorUp) {
+                self.selected = self.selected.saturating_sub(1);
+                return None;
+            }
+            if bound(Action::CursorDown) {
+                self.selected = (self.selected + 1).min(self.views.len().saturating_sub(1));
+                return None;
+            }
+            if chord.key == Key::Enter && self.selected < self.views.len() {
+                return self.switch_view(self.selected);
+            }
+        }
+        if self.focus == Pane::Public {
+            if bound(Action::CursorUp) {
+                self.browser.up();
+                return None;
+            }
+            if bound(Action::CursorDown) {
+                self.browser.down();
+                return None;
+            }
+            if chord.key == Key::Enter
+                && let Some(room) = self.browser.selected().cloned()
+            {
+                // Rooms we are in already are only switched to.
+                return match self
+                    .views
+                    .iter()
+                    .position(|v| *v == Some(room.title.clone()))
+                {
+                    Some(i) => self.switch_view(i),
+                    None => Some(Request::Join(room)),
+                };
+            }
+        }
+        if self.focus == Pane::Dms {
+            if bound(Action::CursorUp) {
+                self.dm_selected = self.dm_selected.saturating_sub(1);
+                return None;
+            }
+            if bound(Action::CursorDown) {
+                self.dm_selected = (self.dm_selected + 1).min(self.dms.len().saturating_sub(1));
+                return None;
+            }
+            if chord.key == Key::Enter
+                && let Some(c) = self.dms.get(self.dm_selected)
+            {
+                let (peer_id, name) = (c.peer_id.clone(), c.name.clone());
+                self.open_dm(&peer_id, &name);
+                return None;
+            }
+        }
+        if self.focus == Pane::Chat {
+            let len = self.shown().len();
+            if bound(Action::CursorUp) && len > 0 {
+                self.pick(self.picked.map_or(0, |p| (p + 1).min(len - 1)));
+                return None;
+            }
+            if bound(Action::CursorDown) {
+                // Past the newest line the selection goes away.
+                match self.picked {
+                    Some(0) | None => self.picked = None,
+                    Some(p) => self.pick(p - 1),
+                }
+                return None;
+            }
+            if bound(Action::Search) {
+                self.reset_search();
+                self.searching = true;
+                return None;
+            }
+            if bound(Action::NextMatch) {
+                let line = self.search.older();
+                self.jump(line);
+                return None;
+            }
+            if bound(Action::PrevMatch) {
+                let line = self.search.newer();
+                self.jump(line);
+                return None;
+            }
+            if bound(Action::Cancel) {
+                self.picked = None;
+                self.reset_search();
+                return None;
+            }
+        }
+        let message = [
+            Action::Reply,
+            Action::CopyMessage,
+            Action::EditMessage,
+            Action::DeleteMessage,
+        ];
+        if let Some(&action) = message.iter().find(|a| bound(**a)) {
+            return self.message_action(action);
+        }
+        if bound(Action::ScrollUp) {
+            self.scroll += 1;
+        } else if bound(Action::ScrollDown) {
+            self.scroll = self.scroll.saturating_sub(1);
+        } else if bound(Action::PageUp) {
+            self.scroll += PAGE;
+        } else if bound(Action::PageDown) {
+            self.scroll = self.scroll.saturating_sub(PAGE);
+        } else if bound(Action::ScrollTop) {
+            self.scroll = self.entries.len();
+        } else if bound(Action::ScrollBottom) {
+            self.scroll = 0;
+        } else if bound(Action::Help) {
+            self.help = true;
+        }
+        None
+    }
+
+    /// What the entered `text` asks for: a message, or a `/` command.
+    fn request(&mut self, text: String) -> Option<Request> {
+        let target = self.target.take();
+        match text.trim() {
+            "" => None,
+            "/resend" => Some(Request::Resend),
+            "/delete" => Some(Request::Delete),
+            "/edit" => {
+                if let Some(last) = self.drafts.last(&self.scope()) {
+                    self.input = format!("/edit {}", last.body.text);
+                }
+                None
+            }
+            "/run" => {
+                self.notice(t!("tui.run_usage"), Color::DarkGray);
+                None
+            }
+            t if t.starts_with("/run ") => Some(Request::Run(command_words(&t[5..]))),
+            "/dm" => {
+                self.notice(t!("tui.dm_usage"), Color::DarkGray);
+                None
+            }
+            t if t.starts_with("/dm ") => Some(Request::OpenDm(t[4..].trim().to_string())),
+            t if t == "/stats" || t.starts_with("/stats ") => {
+                let mut args = t.split_whitespace().skip(1);
+                let Some(game) = args.next() else {
+                    self.notice(t!("tui.stats_usage"), Color::DarkGray);
+                    return None;
+                };
+                Some(Request::Stats {
+                    game: game.to_string(),
+                    vs: args.next().map(str::to_string),
+                })
+            }
+            t => match t.strip_prefix("/edit ") {
+                Some(new) => {
+                    self.target = target;
+                    Some(Request::Edit(new.trim().to_string()))
+                }
+                None => Some(Request::Send(
+                    self.canned.expand(t, self.current()).unwrap_or(text),
+                )),
+            },
+        }
+    }
+
+    fn view_name(&self, view: &Option<String>) -> String {
+        match view {
+            Some(room) => room.clone(),
+            None => t!("tui.global"),
+        }
+    }
+
+    /// Name of what the chat pane shows: the conversation, else the view.
+    fn title(&self) -> String {
+        match &self.dm {
+            Some(peer_id) => self
+                .dms
+                .iter()
+                .find(|c| c.peer_id == *peer_id)
+                .map_or_else(|| peer_id.clone(), |c| c.name.clone()),
+            None => self.view_name(&self.views.get(self.view).cloned().flatten()),
+        }
+    }
+
+    fn render(&self, frame: &mut Frame, members: &[String]) {
+        let area = match self.banners.first() {
+            Some(banner) => {
+                let [top, rest] = Layout::vertical([Constraint::Length(1), Constraint::Min(4)])
+                    .areas(frame.area());
+                frame.render_widget(Paragraph::new(self.banner(banner)), top);
+                rest
+            }
+            None => frame.area(),
+        };
+        let [main, input] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(3)]).areas(area);
+        let [left, chat, right] = Layout::horizontal([
+            Constraint::Length(24),
+            Constraint::Min(20),
+            Constraint::Length(22),
+        ])
+        .areas(main);
+        let [views, dms, public] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Percentage(30),
+            Constraint::Percentage(30),
+        ])
+        .areas(left);
+
+        let items: Vec<ListItem> = self
+            .views
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let style = if i == self.view && self.dm.is_none() {
+                    Style::new().add_modifier(Modifier::BOLD)
+                } else {
+                    Style::new()
+                };
+                ListItem::new(Line::styled(self.view_name(v), style))
+            })
+            .collect();
+        let mut state = ListState::default();
+        if self.focus == Pane::Rooms {
+            state.select(Some(self.selected));
+        }
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(self.block(t!("tui.rooms"), Pane::Rooms))
+                .highlight_style(Style::new().reversed()),
+            views,
+            &mut state,
+        );
+
+        let conversations: Vec<ListItem> = self
+            .dms
+            .iter()
+            .map(|c| {
+                let style = if self.dm.as_ref() == Some(&c.peer_id) {
+                    Style::new().add_modifier(Modifier::BOLD)
+                } else {
+                    Style::new()
+                };
+                let mut spans = vec![Span::styled(c.name.as_str(), style)];
+                if c.unread > 0 {
+                    spans.push(Span::styled(
+                        format!(" ({})", c.unread),
+                        Style::new().fg(Color::Magenta).bold(),
+                    ));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let mut state = ListState::default();
+        if self.focus == Pane::Dms {
+            state.select(Some(self.dm_selected));
+        }
+        frame.render_stateful_widget(
+            List::new(conversations)
+                .block(self.block(t!("tui.dms"), Pane::Dms))
+                .highlight_style(Style::new().reversed()),
+            dms,
+            &mut state,
+        );
+
+        let public_rooms: Vec<ListItem> = self
+            .browser
+            .rooms()
+            .iter()
+            .map(|r| {
+                // Rooms without a ticket cannot be joined from here.
+                let style = match &r.ticket {
+                    Some(_) => Style::new(),
+                    None => Style::new().fg(Color::DarkGray),
+                };
+                let mut spans = Vec::new();
+                if self.views.contains(&Some(r.title.clone())) {
+                    spans.push(Span::styled("✓ ", Style::new().fg(Color::Green)));
+                }
+                spans.push(Span::styled(r.title.as_str(), style));
+                if let Some(game) = &r.game {
+                    spans.push(Span::raw(" "));
+                    let badge = Style::new().fg(Color::Black).bg(Color::Magenta);
+                    spans.push(Span::styled(format!(" {} ", game.name), badge));
+                }
+                let full =
+                    matches!((r.current_players, r.max_players), (Some(n), Some(max)) if n >= max);
+                let players = match (r.current_players, r.max_players) {
+                    (Some(n), Some(max)) => Some(format!(" {n}/{max}")),
+                    (Some(n), None) => Some(format!(" {n}")),
+                    (None, _) => None,
+                };
+                if let Some(players) = players {
+                    let color = if full { Color::Red } else { Color::Green };
+                    spans.push(Span::styled(players, Style::new().fg(color)));
+                }
+                if let Some(language) = &r.language {
+                    spans.push(Span::styled(
+                        format!(" {language}"),
+                        Style::new().fg(Color::DarkGray),
+                    ));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let mut state = ListState::default();
+        if self.focus == Pane::Public {
+            state.select(Some(self.browser.selected_index()));
+        }
+        frame.render_stateful_widget(
+            List::new(public_rooms)
+                .block(self.block(t!("tui.public_rooms"), Pane::Public))
+                .highlight_style(Style::new().reversed()),
+            public,
+            &mut state,
+        );
+
+        self.render_chat(frame, chat);
+
+        let members_title = match (&self.dm, self.current()) {
+            (None, None) => t!("tui.online"),
+            _ => t!("tui.members"),
+        };
+        let members: Vec<ListItem> = members.iter().map(|m| ListItem::new(m.as_str())).collect();
+        frame.render_widget(
+            List::new(members).block(Block::bordered().title(members_title)),
+            right,
+        );
+
+        frame.render_widget(
+            Paragraph::new(self.input.as_str()).block(
+                self.block(t!("tui.input", view = self.title()), Pane::Input)
+                    .title_bottom(self.connectivity().right_aligned()),
+            ),
+            input,
+        );
+        if self.focus == Pane::Input
+            && !self.help
+            && self.stats.is_none()
+            && self.palette.is_none()
+            && self.joins.is_empty()
+            && self.invites.is_empty()
+        {
+            let x = input.x + 1 + self.input.chars().count() as u16;
+            frame.set_cursor_position((x.min(input.right().saturating_sub(2)), input.y + 1));
+        }
+
+        if self.help {
+            self.render_help(frame);
+        } else if let Some(stats) = &self.stats {
+            render_stats(frame, stats);
+        } else if let Some(palette) = &self.palette {
+            render_palette(frame, palette);
+        } else if let Some(request) = self.joins.front() {
+            render_join(frame, request);
+        } else if let Some(invite) = self.invites.front() {
+            render_invite(frame, invite);
+        }
+    }
+
+    fn render_chat(&self, frame: &mut Frame, area: Rect) {
+        let mut lines: Vec<Line> = self
+            .shown()
+            .into_iter()
+            .map(|e| match e {
+                Entry::Chat {
+                    from,
+                    text,
+                    edited,
+                    deleted,
+                    ..
+                } => {
+                    let mut spans = vec![
+                        Span::styled(from.as_str(), Style::new().fg(Color::Cyan).bold()),
+                        Span::raw(": "),
+                    ];
+                    if *deleted {
+                        spans.push(Span::styled(
+                            t!("tui.deleted"),
+                            Style::new().fg(Color::DarkGray).italic(),
+                        ));
+                    } else {
+                        spans.extend(self.marked(text, Style::new()));
+                    }
+                    if *edited && !*deleted {
+                        spans.push(Span::styled(
+                            t!("tui.edited"),
+                            Style::new().fg(Color::DarkGray),
+                        ));
+                    }
+                    Line::from(spans)
+                }
+                Entry::Direct { from, text, .. } => {
+                    let mut spans = vec![
+                        Span::styled(from.as_str(), Style::new().fg(Color::Magenta).bold()),
+                        Span::raw(": "),
+                    ];
+                    spans.extend(self.marked(text, Style::new()));
+                    Line::from(spans)
+                }
+                Entry::Notice { text, color } => {
+                    Line::from(self.marked(text, Style::new().fg(*color)))
+                }
+            })
+            .collect();
+        if let Some(i) = self.picked.and_then(|p| lines.len().checked_sub(p + 1)) {
+            lines[i] = std::mem::take(&mut lines[i]).reversed();
+        }
+        let height = area.height.saturating_sub(2) as usize;
+        self.chat_height.set(height);
+        let scroll = self.scroll.min(lines.len().saturating_sub(height));
+        let end = lines.len() - scroll;
+        let shown = lines[end.saturating_sub(height)..end].to_vec();
+        let hint = if self.searching || self.search.is_active() {
+            let (current, total) = self.search.position();
+            let query = self.search.query();
+            t!(
+                "tui.search",
+                query = query,
+                current = current,
+                total = total
+            )
+        } else {
+            t!(
+                "tui.hint",
+                help = first_key(&self.keymap, Action::Help),
+                quit = first_key(&self.keymap, Action::Quit)
+            )
+        };
+        frame.render_widget(
+            Paragraph::new(shown).block(self.block(self.title(), Pane::Chat).title_bottom(hint)),
+            area,
+        );
+    }
+
+    fn render_help(&self, frame: &mut Frame) {
+        let area = centered(frame.area(), 70, 80);
+        let lines: Vec<Line> = help_entries(&self.keymap)
+            .into_iter()
+            .map(|e| {
+                Line::from(vec![
+                    Span::styled(format!("{:<20}", e.keys), Style::new().bold()),
+                    Span::raw(e.description),
+                ])
+            })
+            .collect();
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(t!("tui.help_title"))),
+            area,
+        );
+    }
+
+    /// Whether we have peers, for the bottom of the input box.
+    fn connectivity(&self) -> Line<'static> {
+        if self.detector.is_isolated() {
+            Line::styled(t!("tui.isolated"), Style::new().fg(Color::Red))
+        } else {
+            let count = self.detector.neighbor_total();
+            Line::styled(
+                t!("tui.neighbors", count = count),
+                Style::new().fg(Color::DarkGray),
+            )
+        }
+    }
+
+    fn block(&self, title: String, pane: Pane) -> Block<'static> {
+        let block = Block::bordered().title(title);
+        if self.focus == pane {
+            block.border_style(Style::new().fg(Color::Yellow))
+        } else {
+            block
+        }
+    }
+}
+
+/// The oldest join request, waiting for `y` or `n`.
+fn render_join(frame: &mut Frame, request: &JoinRequest) {
+    let area = centered(frame.area(), 50, 20);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(request.text("tui.join_prompt"))
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title(t!("tui.join_title"))),
+        area,
+    );
+}
+
+/// The oldest game invitation, waiting for `y` or `n`.
+fn render_invite(frame: &mut Frame, invite: &Invitation) {
+    let area = centered(frame.area(), 50, 20);
+    let game = match &invite.game.settings {
+        Value::Null => invite.game.name.clone(),
+        settings => format!("{} {settings}", invite.game.name),
+    };
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(t!("tui.invite_prompt", peer = invite.name, game = game))
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title(t!("tui.invite_title"))),
+        area,
+    );
+}
+
+/// The commands matching the palette query, best first.
+fn render_palette(frame: &mut Frame, palette: &CommandPalette) {
+    let area = centered(frame.area(), 70, 60);
+    frame.render_widget(Clear, area);
+    let block = Block::bordered().title(t!("tui.palette_title"));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let [query, list] = Layout::vertical([Constraint::Length(1), Constraint::Min(1)]).areas(inner);
+    frame.render_widget(Paragraph::new(format!("> {}", palette.query())), query);
+    frame.set_cursor_position((
+        query.x + 2 + palette.query().chars().count() as u16,
+        query.y,
+    ));
+    let items: Vec<ListItem> = palette
+        .matches()
+        .map(|c| {
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<22}", c.title()), Style::new().bold()),
+                Span::styled(c.about.clone(), Style::new().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+    let mut state = ListState::default().with_selected(Some(palette.selected_index()));
+    frame.render_stateful_widget(
+        List::new(items).highlight_style(Style::new().reversed()),
+        list,
+        &mut state,
+    );
+}
+
+/// Results per opponent and favorite openings as bar charts, streaks below.
+fn render_stats(frame: &mut Frame, stats: &StatsView) {
+    let area = centered(frame.area(), 80, 80);
+    frame.render_widget(Clear, area);
+    let block = Block::bordered().title(stats.title.clone());
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let [results, openings, summary] = Layout::vertical([
+        Constraint::Min(6),
+        Constraint::Percentage(35),
+        Constraint::Length(stats.lines.len() as u16),
+    ])
+    .areas(inner);
+
+    let styles = [Color::Green, Color::Red, Color::Yellow];
+    let mut chart = BarChart::default()
+        .block(Block::new().title(t!("tui.stats_results")))
+        .bar_width(3)
+        .group_gap(2);
+    for (opponent, counts) in &stats.results {
+        let bars: Vec<Bar> = counts
+            .iter()
+            .zip(styles)
+            .map(|(n, color)| Bar::default().value(*n).style(Style::new().fg(color)))
+            .collect();
+        chart = chart.data(
+            BarGroup::default()
+                .label(Line::from(opponent.clone()))
+                .bars(&bars),
+        );
+    }
+    frame.render_widget(chart, results);
+
+    let bars: Vec<Bar> = stats
+        .openings
+        .iter()
+        .map(|(mv, n)| Bar::default().value(*n).label(Line::from(mv.clone())))
+        .collect();
+    frame.render_widget(
+        BarChart::default()
+            .block(Block::new().title(t!("dashboard.openings")))
+            .bar_width(4)
+            .data(BarGroup::default().bars(&bars)),
+        openings,
+    );
+
+    let lines: Vec<Line> = stats.lines.iter().map(|l| Line::raw(l.clone())).collect();
+    frame.render_widget(Paragraph::new(lines), summary);
+}
+
+/// The keymap chord of a terminal key press.
+fn chord(key: KeyEvent) -> Option<KeyChord> {
+    let k = match key.code {
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Esc => Key::Esc,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::BackTab => Key::BackTab,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::F(n) => Key::F(n),
+        _ => return None,
+    };
+    let m = key.modifiers;
+    Some(KeyChord {
+        key: k,
+        ctrl: m.contains(KeyModifiers::CONTROL),
+        alt: m.contains(KeyModifiers::ALT),
+        // Shift is already in the character (`G`) and in BackTab.
+        shift: m.contains(KeyModifiers::SHIFT) && !matches!(k, Key::Char(_) | Key::BackTab),
+    })
+}
+
+fn first_key(keymap: &Keymap, action: Action) -> String {
+    keymap
+        .keys(action)
+        .first()
+        .map(|k| k.to_string())
+        .unwrap_or_default()
+}
+
+/// `percent_x` by `percent_y` of `area`, centered.
+fn centered(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+    let [_, area, _] = Layout::vertical([
+        Constraint::Percentage((100 - percent_y) / 2),
+        Constraint::Percentage(percent_y),
+        Constraint::Percentage((100 - percent_y) / 2),
+    ])
+    .areas(area);
+    let [_, area, _] = Layout::horizontal([
+        Constraint::Percentage((100 - percent_x) / 2),
+        Constraint::Percentage(percent_x),
+        Constraint::Percentage((100 - percent_x) / 2),
+    ])
+    .areas(area);
+    area
+}
+
+/// Short form of a peer id.
+fn short(peer_id: &str) -> &str {
+    &peer_id[..8.min(peer_id.len())]
+}
+
+/// Nickname of `peer_id` from the directory, or a short form of the id.
+fn nickname(directory: &PeerDirectory, peer_id: &str) -> String {
+    directory
+        .peers
+        .get(peer_id)
+        .and_then(|p| p.nickname.clone())
+        .unwrap_or_else(|| short(peer_id).to_string())
+}
+
+/// Publish `env` on the topic of `view`.
+async fn publish<T: Serialize>(
+    senders: &BTreeMap<Option<String>, Box<dyn TopicHandle>>,
+    view: &Option<String>,
+    ctx: &Ctx,
+    env: &Envelope<T>,
+) -> Result<()> {
+    if let Some(th) = senders.get(view) {
+        for frame in ctx.wire.encode(env) {
+            th.publish(&frame).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Publish the direct message `env` on the inbox of `peer_id`, dialing the
+/// peer and joining its inbox on first use.
+async fn send_direct<T: Serialize>(
+    transport: &Node,
+    ctx: &Ctx,
+    inboxes: &mut BTreeMap<String, Box<dyn TopicHandle>>,
+    peer_id: &str,
+    env: &Envelope<T>,
+) -> Result<()> {
+    if !inboxes.contains_key(peer_id) {
+        connect_host(transport, ctx, peer_id).await?;
+        let topic = transport.topic_from_name(&inbox_topic_name(peer_id));
+        inboxes.insert(peer_id.to_string(), transport.join_topic(topic).await?);
+    }
+    for frame in ctx.wire.encode(env) {
+        inboxes[peer_id].publish(&frame).await?;
+    }
+    Ok(())
+}
+
+/// Show and store an edit or deletion of our own.
+fn own_change(app: &mut App, change: &ChatChange) {
+    let _ = HistoryStore::open().apply(change);
+    app.apply(change);
+}
+
+/// Words of a `/run` line; double quotes keep spaces in one word.
+fn command_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_default();
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Run the command line `args` in a process of its own, on our profile.
+/// Its output, then how it ended, comes back line by line on `tx`.
+fn run_command(ctx: &Ctx, args: Vec<String>, tx: mpsc::UnboundedSender<Entry>) -> Result<()> {
+    let mut command = tokio::process::Command::new(std::env::current_exe()?);
+    if let Some(profile) = session::profile() {
+        command.arg("--profile").arg(profile);
+    }
+    if ctx.guest {
+        command.arg("--guest");
+    }
+    let mut child = command
+        .arg("--plain")
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let output = [
+        forward(child.stdout.take(), Color::Gray, tx.clone()),
+        forward(child.stderr.take(), Color::Yellow, tx.clone()),
+    ];
+    let name = args.join(" ");
+    tokio::spawn(async move {
+        let status = child.wait().await;
+        // Lines still in the pipes come before the verdict.
+        for task in output {
+            let _ = task.await;
+        }
+        let notice = match status {
+            Ok(s) if s.success() => Entry::Notice {
+                text: t!("tui.run_done", command = name),
+                color: Color::DarkGray,
+            },
+            Ok(s) => Entry::Notice {
+                text: t!("tui.run_failed", command = name, status = s),
+                color: Color::Red,
+            },
+            Err(e) => Entry::Notice {
+                text: t!("tui.run_failed", command = name, status = e),
+                color: Color::Red,
+            },
+        };
+        let _ = tx.send(notice);
+    });
+    Ok(())
+}
+
+/// Send the lines of `pipe` as notices in `color`.
+fn forward(
+    pipe: Option<impl AsyncRead + Unpin + Send + 'static>,
+    color: Color,
+    tx: mpsc::UnboundedSender<Entry>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let Some(pipe) = pipe else {
+            return;
+        };
+        let mut lines = BufReader::new(pipe).lines();
+        while let Ok(Some(text)) = lines.next_line().await {
+            let _ = tx.send(Entry::Notice { text, color });
+        }
+    })
+}
+
+/// Forward terminal events from a blocking reader thread.
+fn read_events(tx: mpsc::UnboundedSender<Event>) {
+    std::thread::spawn(move || {
+        while let Ok(ev) = event::read() {
+            if tx.send(ev).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Run the full-screen client until the user quits.
+pub(crate) async fn run(transport: &Node, ctx: &Ctx, session: &mut SessionState) -> Result<()> {
+    restore::restore(transport, ctx, session).await?;
+    let me = sender_id(transport, session);
+    let keymap = Keymap::from_config(&ctx.config.keymap)?;
+    let mut cache = RoomCache::default();
+    {
+        let _spinner = ctx.out.waiting(&t!("room.listing"));
+        let rooms = Discovery::new(transport, ctx.config.timeouts)
+            .list_rooms()
+            .await
+            .unwrap_or_default();
+        for r in rooms {
+            cache.insert(r);
+        }
+    }
+    let mut subs = subscribe_rooms(transport, ctx, session).await?;
+    if !ctx.safe.hides_global_chat() {
+        let topic = transport.topic_from_name(GLOBAL_CHAT_TOPIC_NAME);
+        subs.insert(0, (None, transport.join_topic(topic).await?));
+    }
+    let goodbye = Goodbye {
+        rooms: subscribed(&subs, session),
+        hosted: None,
+    };
+
+    let views: Vec<Option<String>> = subs.iter().map(|(label, _)| label.clone()).collect();
+    let active = session.rooms.active().map(|r| r.name.clone());
+    let view = views.iter().position(|v| *v == active).unwrap_or(0);
+    let drafts = Drafts::load().unwrap_or_default();
+    let input = scope_key(views.get(view).cloned().flatten().as_deref());
+    let input = drafts.draft(&input).to_string();
+    let mut app = App {
+        me: me.clone(),
+        keymap,
+        canned: ctx.config.canned.clone(),
+        canned_keys: ctx.config.canned.key_bindings()?,
+        views,
+        view,
+        selected: view,
+        cache,
+        browser: RoomBrowser::default(),
+        entries: VecDeque::new(),
+        scroll: 0,
+        picked: None,
+        target: None,
+        chat_height: Cell::new(0),
+        search: ScrollbackSearch::default(),
+        searching: false,
+        input,
+        drafts,
+        focus: Pane::Input,
+        help: false,
+        stats: None,
+        joins: VecDeque::new(),
+        invites: VecDeque::new(),
+        dms: Vec::new(),
+        dm_selected: 0,
+        dm: None,
+        marks: ReadMarks::load().unwrap_or_default(),
+        banners: Vec::new(),
+        palette: None,
+        detector: PartitionDetector::default(),
+        quit: false,
+    };
+    app.browser.refresh(&app.cache);
+    let directory = PeerDirectory::load().unwrap_or_default();
+    app.preload(ctx, session, &directory, &me);
+
+    let mut terminal = ratatui::init();
+    let res = event_loop(&mut terminal, transport, ctx, session, app, subs, &me).await;
+    ratatui::restore();
+    goodbye.send(transport, ctx, &me).await;
+    res
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    transport: &Node,
+    ctx: &Ctx,
+    session: &mut SessionState,
+    mut app: App,
+    subs: Vec<(Option<String>, Box<dyn TopicHandle>)>,
+    me: &str,
+) -> Result<()> {
+    // Escape sequences would show up as text on the screen.
+    let out = Output::new(true);
+    let labels = subs.iter().filter_map(|(label, _)| label.as_deref());
+    let mut scheduler = schedule(ctx, labels.clone().count());
+    let mut members = RoomMembers::join(transport, ctx, session, labels).await?;
+    members.prompt_host();
+    let mut senders: BTreeMap<Option<String>, Box<dyn TopicHandle>> = BTreeMap::new();
+    let mut feed = TopicFeed::default();
+    for (label, th) in subs {
+        th.publish(&ctx.wire.capabilities(me.to_string())).await?;
+        let topic = match &label {
+            Some(room) => match session.rooms.get(room) {
+                Some(r) => transport.topic_from_hex(&r.topic_hex)?,
+                None => continue,
+            },
+            None => transport.topic_from_name(GLOBAL_CHAT_TOPIC_NAME),
+        };
+        senders.insert(label.clone(), transport.join_topic(topic).await?);
+        feed.add(label, th);
+    }
+    let history = HistoryStore::open();
+    let mut compacted = Instant::now();
+    let names_topic = transport.topic_from_name(NAME_REGISTRY_TOPIC_NAME);
+    let mut names = transport.join_topic(names_topic).await?;
+    let mut guard = session.nick_claim().map(NickGuard::new);
+    let mut claims = ClaimCache::new(ctx.config.timeouts.nick_grace());
+    // Name claims and announcements skip the wire pipeline; drop repeats here.
+    let mut recent = RecentIds::default();
+    let discovery_topic = transport.topic_from_name(DISCOVERY_TOPIC_NAME);
+    let mut announcements = transport.join_topic(discovery_topic).await?;
+    let mut directory = PeerDirectory::load().unwrap_or_default();
+    let mut vouches = VouchBook::load().unwrap_or_default();
+    let mut ratings = RatingBook::load().unwrap_or_default();
+    let mut sync = DigestSync::default();
+    let inbox_topic = transport.topic_from_name(&inbox_topic_name(me));
+    let mut inbox = transport.join_topic(inbox_topic).await?;
+    if let Err(e) = archive::sign_up(transport, me).await {
+        tracing::debug!("mailbox sign-up: {e:#}");
+    }
+    // Announcements we keep are passed on to peers that missed them.
+    let mut banners = AnnouncementStore::load().unwrap_or_default();
+    let operators = &ctx.config.announcements;
+    let mut operator_topic = transport
+        .join_topic(transport.topic_from_name(ANNOUNCEMENTS_TOPIC_NAME))
+        .await?;
+    for frame in banners.frames(operators) {
+        operator_topic.publish(&frame).await?;
+    }
+    app.banners = banners.current(operators);
+    let registry = GameRegistry::builtin();
+    // Inboxes of the peers we wrote to, joined on first use.
+    let mut inboxes = BTreeMap::new();
+    let mut check = tokio::time::interval(Duration::from_secs(5));
+    let mut reloader = KeymapReloader::default();
+    // Received chat, shown in causal order once held for a moment.
+    let mut order = OrderBuffer::default();
+    // Output of the commands run with `/run`.
+    let (ran, mut output) = mpsc::unbounded_channel();
+    let (tx, mut events) = mpsc::unbounded_channel();
+    read_events(tx);
+    loop {
+        let shown = match app.current() {
+            _ if app.dm.is_some() => vec![
+                titles::decorate(&session.nickname, session.title.as_deref()),
+                app.title(),
+            ],
+            Some(room) => {
+                let mut shown = vec![titles::decorate(
+                    &session.nickname,
+                    session.title.as_deref(),
+                )];
+                // Members whose client cannot play the room's game are marked.
+                let game = app
+                    .cache
+                    .by_title(room)
+                    .and_then(|r| r.game.as_ref())
+                    .and_then(|g| Some((g.name.as_str(), registry.version(&g.name)?)));
+                shown.extend(members.members(room).into_iter().map(|m| {
+                    let name = titles::decorate(&m.nickname, m.title.as_deref());
+                    match game {
+                        _ if !m.role.is_player() => {
+                            t!("tui.member_spectating", member = name)
+                        }
+                        Some((game, version))
+                            if ctx
+                                .wire
+                                .game_support(&m.peer_id, game, version)
+                                .problem(&m.nickname, game, version)
+                                .is_some() =>
+                        {
+                            t!("tui.member_cannot_play", member = name, game = game)
+                        }
+                        _ => name,
+                    }
+                }));
+                shown
+            }
+            None => directory
+                .online()
+                .into_iter()
+                .filter(|(id, _)| **id != me)
+                .map(|(id, _)| nickname(&directory, id))
+                .collect(),
+        };
+        terminal.draw(|frame| app.render(frame, &shown))?;
+        let flush = order
+            .next_due()
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(60));
+        tokio::select! {
+            ev = events.recv() => {
+                let Some(Event::Key(key)) = ev else {
+                    continue;
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match app.key(key) {
+                    Some(Request::Send(text)) if app.dm.is_some() => {
+                        let peer_id = app.dm.clone().unwrap_or_default();
+                        let env = make_direct(me.to_string(), &peer_id, text);
+                        send_direct(transport, ctx, &mut inboxes, &peer_id, &env).await?;
+                        remember_own(&env, session);
+                        app.drafts.sent(&app.scope(), &env);
+                        let (name, text) = (app.title(), env.body.text.clone());
+                        app.direct(&peer_id, &name, session.nickname.clone(), text, false);
+                    }
+                    Some(Request::Edit(_) | Request::Delete) if app.dm.is_some() => {
+                        app.target = None;
+                        app.notice(t!("tui.dm_no_edit"), Color::DarkGray);
+                    }
+                    Some(Request::Send(text)) => {
+                        let view = app.current().map(str::to_string);
+                        let env = match &view {
+                            Some(room) => {
+                                let Some(r) = session.rooms.get(room) else {
+                                    continue;
+                                };
+                                make_chat_room(r.topic_hex.clone(), me.to_string(), text)
+                            }
+                            None => make_chat_global(me.to_string(), text),
+                        };
+                        remember_own(&env, session);
+                        publish(&senders, &view, ctx, &env).await?;
+                        app.drafts.sent(&app.scope(), &env);
+                        let text = env.body.text.clone();
+                        app.push(Entry::chat(view, session.nickname.clone(), text, &env));
+                    }
+                    Some(Request::Edit(text)) => {
+                        let view = app.current().map(str::to_string);
+                        let Some(original) = app.original(session) else {
+                            app.notice(t!("tui.nothing_sent"), Color::DarkGray);
+                            continue;
+                        };
+                        let env = make_chat_edit(&original, text);
+                        publish(&senders, &view, ctx, &env).await?;
+                        if app.is_last(&original) {
+                            app.drafts.edited(&app.scope(), &env.body.new_text);
+                        }
+                        own_change(&mut app, &ChatChange::Edit(env));
+                    }
+                    Some(Request::Delete) => {
+                        let view = app.current().map(str::to_string);
+                        let Some(original) = app.original(session) else {
+                            app.notice(t!("tui.nothing_sent"), Color::DarkGray);
+                            continue;
+                        };
+                        let env = make_chat_delete(&original);
+                        publish(&senders, &view, ctx, &env).await?;
+                        if app.is_last(&original) {
+                            app.drafts.deleted(&app.scope());
+                        }
+                        own_change(&mut app, &ChatChange::Delete(env));
+                    }
+                    Some(Request::Copy(text)) => match clipboard::copy(&text) {
+                        Ok(()) => app.notice(t!("clipboard.copied"), Color::DarkGray),
+                        Err(e) => app.notice(t!("clipboard.failed", error = e), Color::Yellow),
+                    },
+                    Some(Request::Resend) => {
+                        let view = app.current().map(str::to_string);
+                        let Some(last) = app.drafts.last(&app.scope()) else {
+                            app.notice(t!("tui.nothing_sent"), Color::DarkGray);
+                            continue;
+                        };
+                        match &app.dm {
+                            Some(peer_id) => {
+                                send_direct(transport, ctx, &mut inboxes, peer_id, last).await?
+                            }
+                            None => publish(&senders, &view, ctx, last).await?,
+                        }
+                        app.notice(t!("tui.resent"), Color::DarkGray);
+                    }
+                    Some(Request::Switch(room)) => {
+                        session.rooms.switch(&room)?;
+                        session.save()?;
+                    }
+                    Some(Request::Stats { game, vs }) => {
+                        match dashboard::load(session, &game, vs.as_deref()) {
+                            Ok(stats) => app.stats = Some(StatsView::new(&stats, &directory)),
+                            Err(e) => app.notice(e.to_string(), Color::Red),
+                        }
+                    }
+                    Some(Request::Run(args)) => {
+                        let name = args.join(" ");
+                        if matches!(args[0].as_str(), "tui" | "repl" | "setup") {
+                            app.notice(t!("tui.run_terminal", command = name), Color::Red);
+                        } else {
+                            let (text, color) = match run_command(ctx, args, ran.clone()) {
+                                Ok(()) => (t!("tui.running", command = name), Color::DarkGray),
+                                Err(e) => (e.to_string(), Color::Red),
+                            };
+                            app.notice(text, color);
+                        }
+                    }
+                    Some(Request::Join(listed)) => {
+                        let ticket = listed.ticket.as_deref().map(str::parse::<RoomTicket>);
+                        let Some(Ok(ticket)) = ticket else {
+                            app.notice(t!("tui.no_ticket", room = listed.title), Color::Yellow);
+                            continue;
+                        };
+                        if let Some(game) = &listed.game
+                            && !registry.knows(&game.name)
+                        {
+                            app.notice(t!("room.game_unsupported", game = game), Color::Yellow);
+                            continue;
+                        }
+                        if !ctx.safe.allows_room(&ticket.topic_hex, Some(&listed.title)) {
+                            app.notice(t!("safe.room_blocked", room = listed.title), Color::Red);
+                            continue;
+                        }
+                        if ticket.host_addr != me {
+                            connect_host(transport, ctx, &ticket.host_addr).await?;
+                        }
+                        let room = RoomRef {
+                            name: listed.title,
+                            topic_hex: ticket.topic_hex,
+                            host_addr: Some(ticket.host_addr),
+                            max_players: None,
+                            admission: None,
+                            role: MemberRole::Player,
+                        };
+                        let topic = transport.topic_from_hex(&room.topic_hex)?;
+                        let th = transport.join_topic(topic).await?;
+                        restore::rejoin(&*th, ctx, session, &room, me).await?;
+                        th.publish(&ctx.wire.capabilities(me.to_string())).await?;
+                        let label = Some(room.name.clone());
+                        senders.insert(label.clone(), transport.join_topic(topic).await?);
+                        feed.add(label.clone(), th);
+                        let text = t!("room.joined", name = room.name, topic = room.topic_hex);
+                        session.rooms.join(room);
+                        session.save()?;
+                        if let Some(name) = &label {
+                            members.add(transport, ctx, session, name).await?;
+                        }
+                        app.views.push(label);
+                        app.switch_view(app.views.len() - 1);
+                        app.notice(text, Color::Green);
+                    }
+                    Some(Request::OpenDm(peer)) => {
+                        let peer_id = directory.find_nickname(&peer).unwrap_or(&peer).to_string();
+                        if peer_id == me {
+                            app.notice(t!("dm.self"), Color::Red);
+                        } else if transport.parse_node_id_addr(&peer_id).is_err() {
+                            app.notice(t!("dm.unknown_peer", peer = peer), Color::Red);
+                        } else {
+                            let name = nickname(&directory, &peer_id);
+                            app.open_dm(&peer_id, &name);
+                        }
+                    }
+                    Some(Request::Admit(accept)) => {
+                        if let Some(request) = app.joins.pop_front() {
+                            members.answer(ctx, me, &request, accept).await?;
+                            app.notices(members.take_notices());
+                        }
+                    }
+                    Some(Request::Answer(accept)) => {
+                        if let Some(invite) = app.invites.pop_front() {
+                            let invite_id = invite.invite_id.clone();
+                            let body = if accept {
+                                InviteBody::Accept { invite_id }
+                            } else {
+                                InviteBody::Decline { invite_id }
+                            };
+                            let env = make_invite(me.to_string(), &invite.from, body);
+                            send_direct(transport, ctx, &mut inboxes, &invite.from, &env).await?;
+                            if accept {
+                                let room = RoomRef {
+                                    name: format!("{}-{}", invite.game.name, short(&invite.from)),
+                                    topic_hex: invite.ticket.topic_hex,
+                                    host_addr: Some(invite.ticket.host_addr),
+                                    max_players: Some(2),
+                                    admission: None,
+                                    role: MemberRole::Player,
+                                };
+                                let topic = transport.topic_from_hex(&room.topic_hex)?;
+                                let th = transport.join_topic(topic).await?;
+                                th.publish(&ctx.wire.capabilities(me.to_string())).await?;
+                                let label = Some(room.name.clone());
+                                senders.insert(label.clone(), transport.join_topic(topic).await?);
+                                feed.add(label.clone(), th);
+                                if !app.views.contains(&label) {
+                                    app.views.push(label);
+                                }
+                                let text = t!("invite.joined", room = room.name);
+                                session.rooms.join(room);
+                                session.save()?;
+                                app.notice(text, Color::Green);
+                            }
+                        }
+                    }
+                    None => {}
+                }
+                // Unsent input survives a crash.
+                if app.drafts.set_draft(&app.scope(), &app.input) {
+                    let _ = app.drafts.save();
+                }
+                if app.quit {
+                    let _ = directory.save();
+                    return Ok(());
+                }
+            }
+            Some(entry) = output.recv() => app.push(entry),
+            b = feed.next() => {
+                let (room, bytes) = b?;
+                if let Some(change) = ctx.wire.decode_change(&bytes) {
+                    if room.as_ref().is_some_and(|r| members.ignores(r, change.sender_id())) {
+                        continue;
+                    }
+                    let _ = history.apply(&change);
+                    let (stamp, id) = (change.stamp(), change.msg_id().to_string());
+                    order.push(stamp, &id, Incoming::Change(change), Instant::now());
+                    continue;
+                }
+                let Some(env) = ctx.wire.decode::<ChatMsg>(&bytes) else {
+                    if let Some(room) = &room
+                        && members.handle(ctx, me, room, &bytes).await?
+                    {
+                        session.rooms.leave(room);
+                        session.save()?;
+                    }
+                    app.notices(members.take_notices());
+                    app.joins.extend(members.take_requests());
+                    continue;
+                };
+                if let Some(room) = &room
+                    && members.ignores(room, &env.sender_id)
+                {
+                    continue;
+                }
+                claims.owner_seen(&env.sender_id);
+                directory.seen(&env.sender_id, room.as_deref());
+                if let Some(room) = &room {
+                    ctx.presence.seen(room, &env.sender_id);
+                    members.touch(room, &env.sender_id);
+                }
+                let _ = history.append(&HistoryEntry::chat(&env, None));
+                ctx.notifier.check_mention(&env.body.text, &session.nickname);
+                let from = match &env.body.relay {
+                    Some(r) => t!("bridge.from", origin = r.origin_id, room = r.origin_room),
+                    None => nickname(&directory, &env.sender_id),
+                };
+                let text = ctx.clean(&env.body.text);
+                let line = Incoming::Line(Entry::chat(room, from, text, &env));
+                order.push(hlc::stamp(&env), &env.msg_id, line, Instant::now());
+            }
+            _ = tokio::time::sleep_until(flush.into()) => {
+                for incoming in order.due(Instant::now()) {
+                    match incoming {
+                        Incoming::Line(entry) => app.push(entry),
+                        Incoming::Change(change) => app.apply(&change),
+                    }
+                }
+            }
+            b = names.next() => {
+                let b = b?;
+                if let Some(env) =
+                    signing::open::<NameRelease>(&b).filter(|e| recent.insert(&e.msg_id))
+                {
+                    claims.release(&env);
+                    continue;
+                }
+                let Some(env) = signing::open::<NameClaim>(&b).filter(|e| recent.insert(&e.msg_id))
+                else {
+                    continue;
+                };
+                if let Some(pinned) =
+                    directory.set_nickname(&env.body.owner_peer_id, &env.body.nickname)
+                {
+                    let text = out.event(&UiEvent::KeyMismatch {
+                        nickname: &env.body.nickname,
+                        pinned: &pinned,
+                        seen: &env.body.owner_peer_id,
+                    });
+                    app.notice(text, Color::Red);
+                }
+                let owner = &env.body.owner_peer_id;
+                directory.proof(owner, env.body.pow);
+                if !ctx.config.sybil.established(owner, &directory, &vouches) {
+                    continue;
+                }
+                if let Some(defence) = claims.observe(&env, &b) {
+                    names.publish(&defence).await?;
+                }
+                if let Some(g) = guard.as_mut() {
+                    if g.defends(&env.body) {
+                        NameRegistry::new(transport, ctx.config.timeouts)
+                            .publish_claim(g.renew(ctx.config.timeouts.nick_lease()))
+                            .await?;
+                    } else if let Some(lost) = g.observe(&env.body) {
+                        let text = out.event(&UiEvent::NickConflictLost {
+                            nickname: &lost.nickname,
+                            winner: &lost.winner_peer_id,
+                        });
+                        app.notice(text, Color::Red);
+                        guard = None;
+                    }
+                }
+            }
+            b = inbox.next() => {
+                let b = b?;
+                if let Some(env) = ctx.wire.decode::<InviteBody>(&b) {
+                    if let Some(invite) = Invitation::new(env, me, &directory) {
+                        ctx.notifier.notify(NotifyEvent::DirectMessage);
+                        app.invites.push_back(invite);
+                    }
+                    continue;
+                }
+                let Some(env) = ctx.wire.decode::<ChatMsg>(&b) else {
+                    continue;
+                };
+                if !matches!(env.scope, Scope::Direct) || env.room_id.as_deref() != Some(me) {
+                    continue;
+                }
+                directory.seen(&env.sender_id, None);
+                claims.owner_seen(&env.sender_id);
+                let _ = history.append(&HistoryEntry::chat(&env, None));
+                ctx.notifier.notify(NotifyEvent::DirectMessage);
+                let from = nickname(&directory, &env.sender_id);
+                let text = ctx.clean(&env.body.text);
+                let open = app.dm.as_ref() == Some(&env.sender_id);
+                if open {
+                    app.marks.read(&env.sender_id, env.ts);
+                    let _ = app.marks.save();
+                }
+                app.direct(&env.sender_id, &from, from.clone(), text, !open);
+            }
+            b = announcements.next() => {
+                if let Some(env) = signing::open::<DiscoveryBody>(&b?)
+                    .filter(|e| recent.insert(&e.msg_id))
+                {
+                    directory.apply(&env.sender_id, &env.body);
+                    claims.owner_seen(&env.sender_id);
+                    app.cache.apply(&env);
+                    app.browser.refresh(&app.cache);
+                    if vouches.apply(&env) {
+                        let _ = vouches.save();
+                    }
+                    if ratings.apply(&env) {
+                        let _ = ratings.save();
+                    }
+                    let rooms = Some(&app.cache);
+                    sync_tables(transport, ctx, me, &env, &mut sync, &*names, &claims, rooms)
+                        .await?;
+                }
+            }
+            b = operator_topic.next() => {
+                let b = b?;
+                if let Some(env) =
+                    announce::open(&b, operators).filter(|e| recent.insert(&e.msg_id))
+                    && banners.insert(&b, &env)
+                {
+                    let _ = banners.save();
+                    app.banners = banners.current(operators);
+                }
+            }
+            task = scheduler.next() => match task {
+                Periodic::Heartbeat => {
+                    members.beat(ctx, me, session).await?;
+                    app.notices(members.take_notices());
+                }
+                Periodic::Renewal => {
+                    if let Some(g) = guard.as_mut() {
+                        NameRegistry::new(transport, ctx.config.timeouts)
+                            .publish_claim(g.renew(ctx.config.timeouts.nick_lease()))
+                            .await?;
+                    }
+                }
+                Periodic::Digest => {
+                    publish_digests(transport, ctx, me, &claims, Some(&app.cache)).await?;
+                }
+            },
+            _ = check.tick() => {
+                let _ = directory.save();
+                claims.expire();
+                app.cache.prune(ROOM_TTL_MS);
+                app.browser.refresh(&app.cache);
+                app.invites.retain(|i| i.expires > now_ms());
+                app.banners = banners.current(operators);
+                if compacted.elapsed() >= COMPACT_EVERY {
+                    let _ = history.enforce(&ctx.config.history);
+                    let _ = storage::gc(&ctx.config.storage);
+                    compacted = Instant::now();
+                }
+                match reloader.poll() {
+                    Some(Ok(keymap)) => app.keymap = keymap,
+                    Some(Err(e)) => app.notice(e.to_string(), Color::Yellow),
+                    None => {}
+                }
+                app.detector.watch_only(feed.neighbors());
+                match app.detector.poll() {
+                    Some(ConnectivityChange::Isolated) => {
+                        app.notice(t!("connectivity.isolated"), Color::Red);
+                    }
+                    Some(ConnectivityChange::Recovered) => {
+                        app.notice(t!("connectivity.recovered"), Color::Green);
+                    }
+                    None => {}
+                }
+                app.detector.publish();
+            }
+        }
+    }
+}
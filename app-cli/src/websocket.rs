@@ -0,0 +1,324 @@
+//! `p2p-games websocket`: the node as a WebSocket server for browser front
+//! ends.
+//!
+//! A page on the same machine connects to the URL printed at startup, which
+//! carries a token: any site the browser visits could reach a port on
+//! localhost, only the page given the token may use the node. Frames are
+//! JSON text tagged by `op`, like the daemon's control socket.
+//!
+//! The page [`subscribe`](ClientOp::Subscribe)s to topics by name (e.g.
+//! `global-chat`) or by the hex id of a room ticket, and gets every envelope
+//! received there as a [`message`](Event::Message) event. It
+//! [`publish`](ClientOp::Publish)es a kind, scope and body; the node fills in
+//! sender, id and time and signs it with its key, so the page takes part in
+//! chat and games as this node.
+
+use anyhow::{Result, anyhow, bail};
+use futures_util::{SinkExt, StreamExt, stream::FuturesUnordered};
+use p2p_core::{
+    hlc,
+    middleware::RecentIds,
+    protocol::{Envelope, Kind, Scope, make_envelope, now_ms},
+    session::SessionState,
+    t,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use tokio_websockets::{Message, ServerBuilder};
+use transport_iroh::transport_iroh::{GossipTransport, NeighborCount, TopicHandle};
+
+use crate::{Ctx, Node, sender_id};
+
+/// How often changed neighbor counts are reported.
+const NEIGHBORS_EVERY: Duration = Duration::from_secs(1);
+
+/// A topic by the name the client uses or by its hex id.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TopicRef {
+    Name(String),
+    Hex(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ClientOp {
+    /// Follow a topic; answered with [`Event::Subscribed`].
+    Subscribe {
+        topic: TopicRef,
+    },
+    Unsubscribe {
+        hex: String,
+    },
+    /// Sign and broadcast an envelope with this body on a subscribed topic.
+    Publish {
+        hex: String,
+        kind: Kind,
+        scope: Scope,
+        #[serde(default)]
+        room_id: Option<String>,
+        body: Value,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Event<'a> {
+    /// Sent once the client is let in.
+    Hello {
+        peer_id: &'a str,
+        nickname: &'a str,
+    },
+    Subscribed {
+        hex: String,
+    },
+    Unsubscribed {
+        hex: String,
+    },
+    Message {
+        hex: String,
+        envelope: Envelope<Value>,
+    },
+    Published {
+        hex: String,
+        msg_id: String,
+    },
+    Neighbors {
+        hex: String,
+        count: usize,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// A frame the client publishes, and who to tell how it went.
+type Outgoing = (Vec<u8>, oneshot::Sender<Result<()>>);
+
+/// A topic a client follows. The handle lives in a task that forwards what
+/// arrives and publishes what the client sends.
+struct Subscription {
+    publish: mpsc::Sender<Outgoing>,
+    neighbors: Option<NeighborCount>,
+    reported: Option<usize>,
+    task: JoinHandle<()>,
+}
+
+impl Subscription {
+    fn start(
+        hex: String,
+        mut th: Box<dyn TopicHandle>,
+        incoming: mpsc::Sender<(String, Result<Vec<u8>>)>,
+    ) -> Self {
+        let neighbors = th.neighbors();
+        let (publish, mut outgoing) = mpsc::channel::<Outgoing>(64);
+        let task = tokio::spawn(async move {
+            loop {
+                let res = tokio::select! {
+                    b = th.next() => b,
+                    Some((frame, done)) = outgoing.recv() => {
+                        let _ = done.send(th.publish(&frame).await);
+                        continue;
+                    }
+                };
+                let failed = res.is_err();
+                if incoming.send((hex.clone(), res)).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Self {
+            publish,
+            neighbors,
+            reported: None,
+            task,
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+type Socket = tokio_websockets::WebSocketStream<TcpStream>;
+
+async fn send(ws: &mut Socket, event: &Event<'_>) -> Result<()> {
+    ws.send(Message::text(serde_json::to_string(event)?))
+        .await?;
+    Ok(())
+}
+
+/// Serve `transport` to browser clients on `listen` until Ctrl-C.
+pub(crate) async fn run(
+    transport: &Node,
+    ctx: &Ctx,
+    session: &SessionState,
+    listen: SocketAddr,
+) -> Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let me = sender_id(transport, session);
+    let url = format!("ws://{}/?token={token}", listener.local_addr()?);
+    println!("{}", ctx.out.heading(&t!("websocket.started", url = url)));
+    let mut clients = FuturesUnordered::new();
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                clients.push(client(stream, transport, ctx, session, &me, &token));
+            }
+            Some(res) = clients.next() => {
+                if let Err(e) = res {
+                    tracing::debug!("websocket client: {e:#}");
+                }
+            }
+            _ = &mut interrupted => return Ok(()),
+        }
+    }
+}
+
+async fn client(
+    stream: TcpStream,
+    transport: &Node,
+    ctx: &Ctx,
+    session: &SessionState,
+    me: &str,
+    token: &str,
+) -> Result<()> {
+    let (request, mut ws) = ServerBuilder::new().accept(stream).await?;
+    let authorized = request
+        .uri()
+        .query()
+        .is_some_and(|q| q.split('&').any(|pair| pair == format!("token={token}")));
+    if !authorized {
+        let message = "missing or wrong token".to_string();
+        send(&mut ws, &Event::Error { message }).await?;
+        ws.close().await?;
+        return Ok(());
+    }
+    let hello = Event::Hello {
+        peer_id: me,
+        nickname: &session.nickname,
+    };
+    send(&mut ws, &hello).await?;
+    let mut subs: HashMap<String, Subscription> = HashMap::new();
+    // Other clients may have been shown a message already; each drops
+    // repeats on its own.
+    let mut recent = RecentIds::default();
+    let (incoming, mut frames) = mpsc::channel(64);
+    let mut tick = tokio::time::interval(NEIGHBORS_EVERY);
+    loop {
+        tokio::select! {
+            msg = ws.next() => {
+                let Some(msg) = msg else {
+                    return Ok(());
+                };
+                let msg = msg?;
+                if msg.is_close() {
+                    return Ok(());
+                }
+                let Some(text) = msg.as_text() else {
+                    continue;
+                };
+                let reply = match serde_json::from_str::<ClientOp>(text) {
+                    Ok(op) => apply(op, transport, ctx, me, &mut subs, &incoming).await,
+                    Err(e) => Err(e.into()),
+                };
+                let event = reply.unwrap_or_else(|e| Event::Error {
+                    message: format!("{e:#}"),
+                });
+                send(&mut ws, &event).await?;
+            }
+            Some((hex, res)) = frames.recv() => match res {
+                Ok(bytes) => {
+                    if let Some(envelope) = ctx.wire.decode_own::<Value>(&bytes, &mut recent) {
+                        send(&mut ws, &Event::Message { hex, envelope }).await?;
+                    }
+                }
+                Err(e) => {
+                    subs.remove(&hex);
+                    let message = format!("{hex}: {e:#}");
+                    send(&mut ws, &Event::Error { message }).await?;
+                }
+            },
+            _ = tick.tick() => {
+                for (hex, sub) in &mut subs {
+                    let count = sub.neighbors.as_ref().map(NeighborCount::get);
+                    if count != sub.reported
+                        && let Some(count) = count
+                    {
+                        sub.reported = Some(count);
+                        let hex = hex.clone();
+                        send(&mut ws, &Event::Neighbors { hex, count }).await?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Carry out one request of a client; the event answers it.
+async fn apply<'a>(
+    op: ClientOp,
+    transport: &Node,
+    ctx: &Ctx,
+    me: &str,
+    subs: &mut HashMap<String, Subscription>,
+    incoming: &mpsc::Sender<(String, Result<Vec<u8>>)>,
+) -> Result<Event<'a>> {
+    match op {
+        ClientOp::Subscribe { topic } => {
+            let topic = match topic {
+                TopicRef::Name(name) => transport.topic_from_name(&name),
+                TopicRef::Hex(hex) => transport.topic_from_hex(&hex)?,
+            };
+            let hex = transport.topic_to_hex(&topic);
+            if !subs.contains_key(&hex) {
+                let th = transport.join_topic(topic).await?;
+                th.publish(&ctx.wire.capabilities(me.to_string())).await?;
+                let sub = Subscription::start(hex.clone(), th, incoming.clone());
+                subs.insert(hex.clone(), sub);
+            }
+            Ok(Event::Subscribed { hex })
+        }
+        ClientOp::Unsubscribe { hex } => {
+            subs.remove(&hex);
+            Ok(Event::Unsubscribed { hex })
+        }
+        ClientOp::Publish {
+            hex,
+            kind,
+            scope,
+            room_id,
+            body,
+        } => {
+            let Some(sub) = subs.get(&hex) else {
+                bail!("not subscribed to {hex}");
+            };
+            let mut env = make_envelope(kind, scope, room_id, me.to_string(), now_ms(), body);
+            if matches!(kind, Kind::Chat) {
+                env.hlc = Some(hlc::now());
+            }
+            for frame in ctx.wire.encode(&env) {
+                let (done, published) = oneshot::channel();
+                let gone = || anyhow!("subscription to {hex} ended");
+                sub.publish.send((frame, done)).await.map_err(|_| gone())?;
+                published.await.map_err(|_| gone())??;
+            }
+            Ok(Event::Published {
+                hex,
+                msg_id: env.msg_id,
+            })
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Who may join the rooms we host.
+//!
+//! The host decides every `JoinReq` by an [`AdmissionPolicy`]: the one
+//! given to `room open --admit`, else `admission` in `config.toml`:
+//!
+//! - `open`: everyone is accepted;
+//! - `friends`: only our `[vouch] friends`;
+//! - `vouched`: peers that `[vouch] auto_admit` friends (one if unset)
+//!   vouch for;
+//! - `manual`: the host is asked, in the TUI with a prompt; peers vouched
+//!   for by `[vouch] auto_admit` friends are accepted without asking.
+//!
+//! Without either setting, rooms are `manual` when `[vouch] auto_admit` is
+//! set and `open` otherwise. Bans and the player cap are checked before the
+//! policy.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    directory::PeerDirectory,
+    vouch::{VouchBook, VouchConfig},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AdmissionPolicy {
+    Open,
+    Friends,
+    Vouched,
+    Manual,
+}
+
+/// What becomes of one join request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Accept,
+    Decline,
+    /// Up to the host.
+    Ask,
+}
+
+impl AdmissionPolicy {
+    /// The policy of a room: `chosen` for it or in the config, else the
+    /// default described in the [module docs](self).
+    pub fn effective(chosen: Option<Self>, vouch: &VouchConfig) -> Self {
+        chosen.unwrap_or(if vouch.auto_admit.is_some() {
+            AdmissionPolicy::Manual
+        } else {
+            AdmissionPolicy::Open
+        })
+    }
+
+    /// Key of the policy in `admission.<key>` messages.
+    pub fn key(self) -> &'static str {
+        match self {
+            AdmissionPolicy::Open => "open",
+            AdmissionPolicy::Friends => "friends",
+            AdmissionPolicy::Vouched => "vouched",
+            AdmissionPolicy::Manual => "manual",
+        }
+    }
+
+    pub fn decide(
+        self,
+        peer_id: &str,
+        config: &VouchConfig,
+        vouches: &VouchBook,
+        directory: &PeerDirectory,
+    ) -> Admission {
+        let accept_if = |ok: bool| {
+            if ok {
+                Admission::Accept
+            } else {
+                Admission::Decline
+            }
+        };
+        match self {
+            AdmissionPolicy::Open => Admission::Accept,
+            AdmissionPolicy::Friends => accept_if(config.is_friend(peer_id, directory)),
+            AdmissionPolicy::Vouched => accept_if(
+                vouches.from_friends(peer_id, config, directory) >= config.auto_admit.unwrap_or(1),
+            ),
+            AdmissionPolicy::Manual if vouches.admits(peer_id, config, directory) => {
+                Admission::Accept
+            }
+            AdmissionPolicy::Manual => Admission::Ask,
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Network-wide announcements by operators.
+//!
+//! Operators (the maintainers of a community, a tournament organizer…)
+//! publish [`Announcement`]s on [`ANNOUNCEMENTS_TOPIC_NAME`] with
+//! `announce <text>`. A client shows one as a banner only if it is signed by
+//! a key listed in `config.toml`; unsigned announcements and those of other
+//! keys are dropped:
+//!
+//! ```toml
+//! [announcements]
+//! operators = ["3ac1…"]   # peer ids (hex node keys) of the operators
+//! ```
+//!
+//! Accepted announcements are kept in `<data dir>/p2p-games/announcements.json`
+//! until they expire, and front ends publish them again when they come up,
+//! so peers that were offline still see them; the operator's signature
+//! travels with the copy.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fs, path::PathBuf};
+use transport_iroh::transport_iroh::GossipTransport;
+
+use crate::{
+    protocol::{Envelope, Kind, Scope, make_envelope, now_ms},
+    session::data_dir,
+    signing,
+    timeouts::{Timeouts, retry},
+};
+
+pub const ANNOUNCEMENTS_TOPIC_NAME: &str = "p2p-announcements";
+
+/// `[announcements]` section of `config.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnnounceConfig {
+    /// Peer ids whose signed announcements are shown.
+    pub operators: Vec<String>,
+}
+
+impl AnnounceConfig {
+    pub fn is_operator(&self, peer_id: &str) -> bool {
+        self.operators
+            .iter()
+            .any(|o| o.eq_ignore_ascii_case(peer_id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncementKind {
+    Info,
+    Maintenance,
+    Release,
+    Tournament,
+}
+
+impl AnnouncementKind {
+    /// Key of the kind in `announce.<key>` messages.
+    pub fn key(self) -> &'static str {
+        match self {
+            AnnouncementKind::Info => "info",
+            AnnouncementKind::Maintenance => "maintenance",
+            AnnouncementKind::Release => "release",
+            AnnouncementKind::Tournament => "tournament",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Announcement {
+    pub kind: AnnouncementKind,
+    pub text: String,
+    /// Unix millis after which the banner is no longer shown.
+    pub expires_at: u64,
+}
+
+/// Build an announcement of ours that is shown for `hours`.
+pub fn make_announcement(
+    sender_id: String,
+    kind: AnnouncementKind,
+    text: String,
+    hours: u64,
+) -> Envelope<Announcement> {
+    let now = now_ms();
+    make_envelope(
+        Kind::Announcement,
+        Scope::Global,
+        None,
+        sender_id,
+        now,
+        Announcement {
+            kind,
+            text,
+            expires_at: now + hours * 3_600_000,
+        },
+    )
+}
+
+/// Sign `env` and publish it on the announcements topic.
+pub async fn publish(
+    transport: &dyn GossipTransport,
+    timeouts: &Timeouts,
+    env: &Envelope<Announcement>,
+) -> Result<()> {
+    let topic = transport.topic_from_name(ANNOUNCEMENTS_TOPIC_NAME);
+    let bytes = &signing::to_signed_vec(env);
+    retry(&timeouts.retry, || async move {
+        transport.join_topic(topic).await?.publish(bytes).await
+    })
+    .await
+}
+
+/// An announcement to show: signed by an operator and not expired.
+pub fn open(bytes: &[u8], config: &AnnounceConfig) -> Option<Envelope<Announcement>> {
+    signing::open::<Announcement>(bytes).filter(|env| {
+        env.sig.is_some() && config.is_operator(&env.sender_id) && env.body.expires_at > now_ms()
+    })
+}
+
+/// Announcements heard so far, as received, by `msg_id`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AnnouncementStore {
+    frames: BTreeMap<String, Value>,
+}
+
+impl AnnouncementStore {
+    fn storage_path() -> PathBuf {
+        data_dir().join("announcements.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// Keep the accepted announcement in `bytes`; returns whether it is new.
+    pub fn insert(&mut self, bytes: &[u8], env: &Envelope<Announcement>) -> bool {
+        let Ok(v) = serde_json::from_slice(bytes) else {
+            return false;
+        };
+        self.frames.insert(env.msg_id.clone(), v).is_none()
+    }
+
+    /// The frames of announcements still to be shown, to publish again.
+    pub fn frames(&self, config: &AnnounceConfig) -> Vec<Vec<u8>> {
+        self.frames
+            .values()
+            .map(|v| serde_json::to_vec(v).expect("serialize envelope"))
+            .filter(|b| open(b, config).is_some())
+            .collect()
+    }
+
+    /// Announcements to show, newest first; expired ones are forgotten.
+    pub fn current(&mut self, config: &AnnounceConfig) -> Vec<Envelope<Announcement>> {
+        let mut shown = Vec::new();
+        self.frames.retain(|_, v| {
+            let b = serde_json::to_vec(v).expect("serialize envelope");
+            match open(&b, config) {
+                Some(env) => {
+                    shown.push(env);
+                    true
+                }
+                None => false,
+            }
+        });
+        shown.sort_by_key(|env| std::cmp::Reverse(env.ts));
+        shown
+    }
+}
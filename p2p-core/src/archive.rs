@@ -0,0 +1,313 @@
+//! Archive nodes: always-on community peers that remember for the others.
+//!
+//! `daemon --role archive` takes on every [`Role`]; `--role` can be given
+//! several times to pick single ones, as can `roles` in the `[archive]`
+//! section of `config.toml`:
+//!
+//! - `rooms`: announced rooms are kept for [`ArchiveConfig::room_ttl_hours`]
+//!   instead of [`ROOM_TTL_MS`](crate::discovery::ROOM_TTL_MS), and room list
+//!   requests are answered from that cache.
+//! - `snapshots`: digests of the room cache are published and sync requests
+//!   answered, so peers that come online catch up on rooms from the archive
+//!   (name claims are synced by every daemon).
+//! - `mailbox`: a peer coming online signs up with [`ArchiveBody::Mailbox`]
+//!   on [`ARCHIVE_TOPIC_NAME`]. The archive then follows its inbox, keeps the
+//!   direct messages sent there for [`ArchiveConfig::mailbox_days`] and
+//!   publishes those the peer has not seen on its next sign-up.
+//! - `replays`: [`ArchiveBody::Fetch`] is answered with the match record from
+//!   the archive's own log or from those players deposited with
+//!   [`ArchiveBody::Deposit`]; deposits are checked by [`crate::validation`]
+//!   first.
+//!
+//! Held messages are kept in `<data dir>/p2p-games/mailbox.json`, deposited
+//! records in `archive.jsonl` next to it.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{
+    game::GameRegistry,
+    matchlog::{MatchLog, MatchRecord},
+    protocol::now_ms,
+    session::data_dir,
+    validation,
+};
+
+/// Topic where peers talk to archive nodes.
+pub const ARCHIVE_TOPIC_NAME: &str = "p2p-games/archive";
+
+const HOUR_MS: u64 = 3_600_000;
+
+/// A service of an archive node (`daemon --role`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// All of the below.
+    Archive,
+    /// Keep a large room cache and answer room lists from it.
+    Rooms,
+    /// Publish digests of the room cache and answer sync requests.
+    Snapshots,
+    /// Hold direct messages for offline peers.
+    Mailbox,
+    /// Serve match records and accept deposited ones.
+    Replays,
+}
+
+/// The roles a daemon serves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Roles {
+    pub rooms: bool,
+    pub snapshots: bool,
+    pub mailbox: bool,
+    pub replays: bool,
+}
+
+impl Roles {
+    pub fn of(roles: &[Role]) -> Self {
+        let all = roles.contains(&Role::Archive);
+        Self {
+            rooms: all || roles.contains(&Role::Rooms),
+            snapshots: all || roles.contains(&Role::Snapshots),
+            mailbox: all || roles.contains(&Role::Mailbox),
+            replays: all || roles.contains(&Role::Replays),
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.rooms || self.snapshots || self.mailbox || self.replays
+    }
+
+    /// Names of the roles served, for the startup line.
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.rooms, "rooms"),
+            (self.snapshots, "snapshots"),
+            (self.mailbox, "mailbox"),
+            (self.replays, "replays"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect()
+    }
+}
+
+/// `[archive]` section of `config.toml`.
+///
+/// ```toml
+/// [archive]
+/// roles = ["rooms", "mailbox"]    # `daemon --role`
+/// room_ttl_hours = 24
+/// max_rooms = 5000
+/// mailbox_days = 7
+/// mailbox_per_peer = 200
+/// max_mailboxes = 1000
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    /// Roles of `daemon` when no `--role` is given.
+    pub roles: Vec<Role>,
+    /// Hours a room stays listed after its last announcement.
+    pub room_ttl_hours: u64,
+    /// Rooms kept at most; the longest unseen go first.
+    pub max_rooms: usize,
+    /// Days a held message waits for its recipient.
+    pub mailbox_days: u64,
+    /// Messages held per recipient; the oldest go first.
+    pub mailbox_per_peer: usize,
+    /// Peers whose inboxes are followed at most.
+    pub max_mailboxes: usize,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            roles: Vec::new(),
+            room_ttl_hours: 24,
+            max_rooms: 5000,
+            mailbox_days: 7,
+            mailbox_per_peer: 200,
+            max_mailboxes: 1000,
+        }
+    }
+}
+
+impl ArchiveConfig {
+    pub fn room_ttl_ms(&self) -> u64 {
+        self.room_ttl_hours * HOUR_MS
+    }
+}
+
+/// Messages on [`ARCHIVE_TOPIC_NAME`]. All are signed by their sender.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ArchiveBody {
+    /// The sender is online: follow its inbox from now on and send again
+    /// the held messages newer than `since` (unix millis), the newest
+    /// direct message it has.
+    Mailbox { since: u64 },
+    /// Ask for the record of match `game_id`.
+    Fetch { game_id: String },
+    /// Answer to the [`Self::Fetch`] with msg id `req_id`.
+    Record { req_id: String, record: MatchRecord },
+    /// Keep the record of a match the sender played in.
+    Deposit { record: MatchRecord },
+}
+
+/// A direct message waiting for its recipient.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Held {
+    msg_id: String,
+    /// Unix millis, as sent.
+    ts: u64,
+    /// The frame as received, hex encoded.
+    frame: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Inbox {
+    /// Unix millis of the peer's last sign-up.
+    seen: u64,
+    held: Vec<Held>,
+}
+
+/// Messages held for offline peers, by recipient peer id.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Mailbox {
+    peers: BTreeMap<String, Inbox>,
+}
+
+impl Mailbox {
+    fn storage_path() -> PathBuf {
+        data_dir().join("mailbox.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// Peers whose inboxes are followed.
+    pub fn peers(&self) -> impl Iterator<Item = &str> {
+        self.peers.keys().map(String::as_str)
+    }
+
+    /// Hold messages for `peer_id` (again); returns whether it is held for.
+    /// Nobody new is taken once [`ArchiveConfig::max_mailboxes`] are full.
+    pub fn sign_up(&mut self, peer_id: &str, cfg: &ArchiveConfig) -> bool {
+        if !self.peers.contains_key(peer_id) && self.peers.len() >= cfg.max_mailboxes {
+            return false;
+        }
+        self.peers.entry(peer_id.to_string()).or_default().seen = now_ms();
+        true
+    }
+
+    /// Hold the direct message `frame` for `peer_id`; returns whether it
+    /// was new.
+    pub fn hold(
+        &mut self,
+        peer_id: &str,
+        msg_id: &str,
+        ts: u64,
+        frame: &[u8],
+        cfg: &ArchiveConfig,
+    ) -> bool {
+        let Some(Inbox { held, .. }) = self.peers.get_mut(peer_id) else {
+            return false;
+        };
+        if held.iter().any(|h| h.msg_id == msg_id) {
+            return false;
+        }
+        held.push(Held {
+            msg_id: msg_id.to_string(),
+            ts,
+            frame: hex::encode(frame),
+        });
+        held.sort_by_key(|h| h.ts);
+        let excess = held.len().saturating_sub(cfg.mailbox_per_peer);
+        held.drain(..excess);
+        true
+    }
+
+    /// Take the frames held for `peer_id` that are newer than `since`; the
+    /// older ones it already has are dropped as well.
+    pub fn collect(&mut self, peer_id: &str, since: u64) -> Vec<Vec<u8>> {
+        let Some(Inbox { held, .. }) = self.peers.get_mut(peer_id) else {
+            return Vec::new();
+        };
+        std::mem::take(held)
+            .into_iter()
+            .filter(|h| h.ts > since)
+            .filter_map(|h| hex::decode(h.frame).ok())
+            .collect()
+    }
+
+    /// Forget messages older than [`ArchiveConfig::mailbox_days`], and
+    /// peers that have not signed up for that long; returns those peers.
+    pub fn expire(&mut self, cfg: &ArchiveConfig) -> Vec<String> {
+        let oldest = now_ms().saturating_sub(cfg.mailbox_days * 24 * HOUR_MS);
+        let mut gone = Vec::new();
+        self.peers.retain(|peer_id, inbox| {
+            inbox.held.retain(|h| h.ts >= oldest);
+            let keep = inbox.seen >= oldest;
+            if !keep {
+                gone.push(peer_id.clone());
+            }
+            keep
+        });
+        gone
+    }
+}
+
+/// Match records kept for other peers.
+pub struct ReplayArchive {
+    deposited: MatchLog,
+}
+
+impl ReplayArchive {
+    pub fn open() -> Self {
+        Self {
+            deposited: MatchLog::at(data_dir().join("archive.jsonl")),
+        }
+    }
+
+    /// The record of `game_id`, from our own matches or deposited ones.
+    pub fn find(&self, game_id: &str) -> Option<MatchRecord> {
+        [&MatchLog::open(), &self.deposited]
+            .into_iter()
+            .filter_map(|log| log.load().ok())
+            .flatten()
+            .find(|r| r.game_id == game_id)
+    }
+
+    /// Keep `record`, deposited by `sender_id`, if the sender played in it
+    /// and it passes validation; returns whether it was kept.
+    pub fn deposit(
+        &self,
+        sender_id: &str,
+        record: &MatchRecord,
+        registry: &GameRegistry,
+    ) -> std::io::Result<bool> {
+        if !record.players.iter().any(|p| p == sender_id)
+            || self.find(&record.game_id).is_some()
+            || validation::validate(record, registry).is_err()
+        {
+            return Ok(false);
+        }
+        self.deposited.append(record)?;
+        Ok(true)
+    }
+}
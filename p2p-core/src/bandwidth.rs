@@ -0,0 +1,310 @@
+//! Bandwidth accounting and soft caps.
+//!
+//! [`MeteredTransport`] wraps any [`GossipTransport`] and counts the payload
+//! bytes sent and received per topic, bucketed by hour. The log is persisted
+//! to `<data dir>/p2p-games/bandwidth.json` so `status` can show it from a
+//! separate invocation.
+//!
+//! When the configured soft cap for the current hour is exceeded,
+//! [`BandwidthMeter::allow_background`] returns `false`. Periodic,
+//! non-essential broadcasters (presence, re-announces) must check it before
+//! publishing; user-initiated traffic is never blocked.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use transport_iroh::transport_iroh::{
+    GossipTransport, NeighborCount, NodeAddr, TopicHandle, TopicId,
+};
+
+use crate::{protocol::now_ms, session::data_dir};
+
+/// How many hourly buckets are kept on disk.
+const KEEP_HOURS: u64 = 48;
+
+/// Minimum time between writes of the log while traffic is flowing.
+const FLUSH_EVERY: Duration = Duration::from_secs(5);
+
+/// `[bandwidth]` section of `config.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandwidthConfig {
+    /// Soft cap in MiB per hour (sent + received); `None` disables throttling.
+    pub soft_cap_mb_per_hour: Option<u64>,
+}
+
+/// Byte counters for one topic in one hour.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub sent: u64,
+    pub received: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.sent + self.received
+    }
+}
+
+/// Persisted usage: hour index (unix millis / 1h) → topic label → counters.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BandwidthLog {
+    pub hours: BTreeMap<u64, BTreeMap<String, Usage>>,
+}
+
+impl BandwidthLog {
+    fn storage_path() -> PathBuf {
+        data_dir().join("bandwidth.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// Per-topic usage for the given hour.
+    pub fn hour(&self, hour: u64) -> BTreeMap<String, Usage> {
+        self.hours.get(&hour).cloned().unwrap_or_default()
+    }
+
+    /// Sum over all topics for the last `hours` hours (including the current one).
+    pub fn total_since(&self, hour: u64, hours: u64) -> Usage {
+        self.hours
+            .range(hour.saturating_sub(hours - 1)..=hour)
+            .flat_map(|(_, topics)| topics.values())
+            .fold(Usage::default(), |acc, u| Usage {
+                sent: acc.sent + u.sent,
+                received: acc.received + u.received,
+            })
+    }
+
+    fn prune(&mut self, hour: u64) {
+        let oldest = hour.saturating_sub(KEEP_HOURS - 1);
+        self.hours.retain(|h, _| *h >= oldest);
+    }
+}
+
+/// Current hour index.
+pub fn current_hour() -> u64 {
+    now_ms() / 3_600_000
+}
+
+struct MeterState {
+    log: BandwidthLog,
+    names: HashMap<String, String>,
+    soft_cap: Option<u64>,
+    last_flush: Instant,
+    dirty: bool,
+}
+
+impl MeterState {
+    fn record(&mut self, topic_hex: &str, sent: u64, received: u64) {
+        let label = self
+            .names
+            .get(topic_hex)
+            .cloned()
+            .unwrap_or_else(|| topic_hex.to_string());
+        let hour = current_hour();
+        let usage = self
+            .log
+            .hours
+            .entry(hour)
+            .or_default()
+            .entry(label)
+            .or_default();
+        usage.sent += sent;
+        usage.received += received;
+        self.dirty = true;
+        if self.last_flush.elapsed() >= FLUSH_EVERY {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.log.prune(current_hour());
+        let _ = self.log.save();
+        self.last_flush = Instant::now();
+        self.dirty = false;
+    }
+}
+
+impl Drop for MeterState {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Shared byte counter; cheap to clone.
+#[derive(Clone)]
+pub struct BandwidthMeter {
+    state: Arc<Mutex<MeterState>>,
+}
+
+impl BandwidthMeter {
+    pub fn new(cfg: &BandwidthConfig) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MeterState {
+                log: BandwidthLog::load().unwrap_or_default(),
+                names: HashMap::new(),
+                soft_cap: cfg.soft_cap_mb_per_hour.map(|mb| mb * 1024 * 1024),
+                last_flush: Instant::now(),
+                dirty: false,
+            })),
+        }
+    }
+
+    /// Apply a new soft cap.
+    pub fn reconfigure(&self, cfg: &BandwidthConfig) {
+        self.state.lock().unwrap().soft_cap = cfg.soft_cap_mb_per_hour.map(|mb| mb * 1024 * 1024);
+    }
+
+    /// Label traffic on `topic_hex` with a human-readable topic name.
+    pub fn name_topic(&self, topic_hex: &str, name: &str) {
+        let mut st = self.state.lock().unwrap();
+        st.names.insert(topic_hex.to_string(), name.to_string());
+    }
+
+    pub fn record_sent(&self, topic_hex: &str, bytes: usize) {
+        self.state
+            .lock()
+            .unwrap()
+            .record(topic_hex, bytes as u64, 0);
+    }
+
+    pub fn record_received(&self, topic_hex: &str, bytes: usize) {
+        self.state
+            .lock()
+            .unwrap()
+            .record(topic_hex, 0, bytes as u64);
+    }
+
+    /// Bytes sent + received in the current hour.
+    pub fn hour_total(&self) -> u64 {
+        let st = self.state.lock().unwrap();
+        st.log.total_since(current_hour(), 1).total()
+    }
+
+    pub fn over_soft_cap(&self) -> bool {
+        let cap = self.state.lock().unwrap().soft_cap;
+        cap.is_some_and(|cap| self.hour_total() > cap)
+    }
+
+    /// Whether non-essential periodic traffic may be sent right now.
+    pub fn allow_background(&self) -> bool {
+        !self.over_soft_cap()
+    }
+
+    /// Write the log to disk now.
+    pub fn flush(&self) {
+        self.state.lock().unwrap().flush();
+    }
+}
+
+/// [`GossipTransport`] decorator that feeds a [`BandwidthMeter`].
+pub struct MeteredTransport<T> {
+    inner: T,
+    meter: BandwidthMeter,
+}
+
+impl<T: GossipTransport> MeteredTransport<T> {
+    pub fn new(inner: T, meter: BandwidthMeter) -> Self {
+        Self { inner, meter }
+    }
+
+    pub fn meter(&self) -> &BandwidthMeter {
+        &self.meter
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<T: GossipTransport> GossipTransport for MeteredTransport<T> {
+    fn node_addr(&self) -> &NodeAddr {
+        self.inner.node_addr()
+    }
+
+    async fn connect(&self, peer: &NodeAddr) -> Result<()> {
+        self.inner.connect(peer).await
+    }
+
+    async fn join_topic(&self, topic: TopicId) -> Result<Box<dyn TopicHandle>> {
+        let inner = self.inner.join_topic(topic).await?;
+        Ok(Box::new(MeteredTopic {
+            inner,
+            topic_hex: self.inner.topic_to_hex(&topic),
+            meter: self.meter.clone(),
+        }))
+    }
+
+    fn topic_from_name(&self, name: &str) -> TopicId {
+        let topic = self.inner.topic_from_name(name);
+        self.meter
+            .name_topic(&self.inner.topic_to_hex(&topic), name);
+        topic
+    }
+
+    fn topic_from_hex(&self, hex: &str) -> Result<TopicId> {
+        self.inner.topic_from_hex(hex)
+    }
+
+    fn topic_to_hex(&self, topic: &TopicId) -> String {
+        self.inner.topic_to_hex(topic)
+    }
+
+    fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr> {
+        self.inner.parse_node_id_addr(s)
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+struct MeteredTopic {
+    inner: Box<dyn TopicHandle>,
+    topic_hex: String,
+    meter: BandwidthMeter,
+}
+
+#[async_trait]
+impl TopicHandle for MeteredTopic {
+    async fn publish(&self, bytes: &[u8]) -> Result<()> {
+        self.inner.publish(bytes).await?;
+        self.meter.record_sent(&self.topic_hex, bytes.len());
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Result<Vec<u8>> {
+        let b = self.inner.next().await?;
+        self.meter.record_received(&self.topic_hex, b.len());
+        Ok(b)
+    }
+
+    fn neighbors(&self) -> Option<NeighborCount> {
+        self.inner.neighbors()
+    }
+}
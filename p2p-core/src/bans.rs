@@ -0,0 +1,83 @@
+//! Per-room ban lists.
+//!
+//! The host of a room bans a peer with `room kick --ban` (or `room ban`),
+//! which publishes [`RoomBody::Kick`](crate::protocol::RoomBody::Kick) with
+//! `ban = true`. The host rejects further join requests of banned peers;
+//! members that see the kick record the ban as well and ignore the peer's
+//! messages in that room. Bans are kept in `<data dir>/p2p-games/bans.json`,
+//! keyed by room topic hex.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{protocol::now_ms, session::data_dir};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    pub reason: Option<String>,
+    /// Unix millis.
+    pub since: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BanList {
+    /// Room topic hex → banned peer id → ban.
+    rooms: BTreeMap<String, BTreeMap<String, Ban>>,
+}
+
+impl BanList {
+    fn storage_path() -> PathBuf {
+        data_dir().join("bans.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    pub fn ban(&mut self, room: &str, peer_id: &str, reason: Option<String>) {
+        self.rooms.entry(room.to_string()).or_default().insert(
+            peer_id.to_string(),
+            Ban {
+                reason,
+                since: now_ms(),
+            },
+        );
+    }
+
+    /// Lift a ban; returns whether there was one.
+    pub fn unban(&mut self, room: &str, peer_id: &str) -> bool {
+        let Some(bans) = self.rooms.get_mut(room) else {
+            return false;
+        };
+        let was = bans.remove(peer_id).is_some();
+        if bans.is_empty() {
+            self.rooms.remove(room);
+        }
+        was
+    }
+
+    pub fn get(&self, room: &str, peer_id: &str) -> Option<&Ban> {
+        self.rooms.get(room)?.get(peer_id)
+    }
+
+    pub fn is_banned(&self, room: &str, peer_id: &str) -> bool {
+        self.get(room, peer_id).is_some()
+    }
+
+    /// Bans in `room`, by peer id.
+    pub fn in_room(&self, room: &str) -> impl Iterator<Item = (&String, &Ban)> {
+        self.rooms.get(room).into_iter().flatten()
+    }
+}
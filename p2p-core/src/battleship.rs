@@ -0,0 +1,432 @@
+//! Battleship with committed boards.
+//!
+//! Each player places a fleet of five ships (5, 4, 3, 3 and 2 cells) on a
+//! 10×10 board only they can see, and commits to the layout before the
+//! first shot (see [`crate::commitment`]). A turn then answers the
+//! opponent's last shot, hit or miss as our own layout says, and fires at a
+//! cell of theirs. Once a board is sunk or shot through, both players
+//! reveal their layout: a player whose opening does not match the
+//! commitment, or whose answers do not match the layout, loses, so a board
+//! cannot be moved or lied about mid-game. If both cheated it is a draw. A
+//! player who leaves forfeits.
+//!
+//! A layout is typed as `random`, or as the bow cell and direction of each
+//! ship in fleet order (`a1h c3v e5h g2v i7h`; `h` runs along the row, `v`
+//! down the column). Shots are typed as cells (`a1` … `j10`); the answer
+//! is added from the layout kept in [`Openings`]. The opponent's board is
+//! drawn on top, ours below.
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::{
+    commitment::{Opening, Openings},
+    game::{DropPolicy, Game, Outcome, Seat},
+    output::cell_name,
+    t,
+    widgets::{GridModel, RenderModel},
+};
+
+const SIZE: usize = 10;
+
+/// Ship lengths, in the order ships are placed.
+const FLEET: [usize; 5] = [5, 4, 3, 3, 2];
+
+/// Cells covered by the whole fleet.
+const FLEET_CELLS: usize = 17;
+
+/// Where a ship lies: its bow cell (row-major index) and direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ship {
+    pub cell: usize,
+    pub vertical: bool,
+}
+
+/// The secret layout of one board, ships in [`FLEET`] order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Layout {
+    pub ships: Vec<Ship>,
+}
+
+impl Layout {
+    /// Cells covered by the fleet, or `None` if ships are missing, stick
+    /// out of the board or overlap.
+    pub fn cells(&self) -> Option<BTreeSet<usize>> {
+        if self.ships.len() != FLEET.len() {
+            return None;
+        }
+        covered(&self.ships)
+    }
+
+    fn random() -> Self {
+        let mut ships = Vec::new();
+        while ships.len() < FLEET.len() {
+            let r = uuid::Uuid::new_v4().as_u64_pair().0;
+            ships.push(Ship {
+                cell: (r % (SIZE * SIZE) as u64) as usize,
+                vertical: r >> 32 & 1 == 1,
+            });
+            if covered(&ships).is_none() {
+                ships.pop();
+            }
+        }
+        Self { ships }
+    }
+
+    fn parse(input: &str) -> Result<Self> {
+        let ships = input
+            .split_whitespace()
+            .map(|word| {
+                let (cell, dir) = word.split_at(word.len() - 1);
+                Some(Ship {
+                    cell: parse_cell(cell)?,
+                    vertical: match dir {
+                        "h" => false,
+                        "v" => true,
+                        _ => return None,
+                    },
+                })
+            })
+            .collect::<Option<Vec<_>>>();
+        match ships.map(|ships| Self { ships }) {
+            Some(layout) if layout.cells().is_some() => Ok(layout),
+            _ => bail!(t!("battleship.bad_layout")),
+        }
+    }
+}
+
+/// Cells covered by the first `ships.len()` ships of the fleet, if they
+/// fit on the board without overlapping.
+fn covered(ships: &[Ship]) -> Option<BTreeSet<usize>> {
+    let mut cells = BTreeSet::new();
+    for (ship, len) in ships.iter().zip(FLEET) {
+        let (row, col) = (ship.cell / SIZE, ship.cell % SIZE);
+        let end = if ship.vertical { row + len } else { col + len };
+        if row >= SIZE || end > SIZE {
+            return None;
+        }
+        let step = if ship.vertical { SIZE } else { 1 };
+        for i in 0..len {
+            if !cells.insert(ship.cell + i * step) {
+                return None;
+            }
+        }
+    }
+    Some(cells)
+}
+
+/// `a1` … `j10`, column letter first, as on the drawn board.
+fn parse_cell(input: &str) -> Option<usize> {
+    let col = input.chars().next().filter(|c| ('a'..='j').contains(c))?;
+    let row: usize = input[1..].parse().ok().filter(|r| (1..=SIZE).contains(r))?;
+    Some((row - 1) * SIZE + (col as usize - 'a' as usize))
+}
+
+/// A shot at a board; `hit` once the board's owner answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shot {
+    pub cell: usize,
+    pub hit: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BattleshipMove {
+    /// Commit to our layout.
+    Commit { commitment: String },
+    /// Answer the shot at our board, if one is pending, and fire at
+    /// `target` on theirs.
+    Fire { answer: Option<bool>, target: usize },
+    /// Answer the pending shot, if any, and open our layout; once a board
+    /// is sunk or shot through.
+    Reveal {
+        answer: Option<bool>,
+        opening: Opening,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Setup,
+    Battle,
+    Reveal,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Battleship {
+    /// Commitment to each seat's layout.
+    commitments: [Option<String>; 2],
+    /// Shots at each seat's board, oldest first.
+    shots: [Vec<Shot>; 2],
+    /// Layouts opened at the end.
+    revealed: [Option<Opening>; 2],
+    turn: Seat,
+    /// Seat that left the game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    forfeit: Option<Seat>,
+}
+
+impl Battleship {
+    fn phase(&self) -> Phase {
+        if self.commitments.iter().any(Option::is_none) {
+            Phase::Setup
+        } else if self.finished(0) || self.finished(1) {
+            Phase::Reveal
+        } else {
+            Phase::Battle
+        }
+    }
+
+    /// The last shot at the board of `seat`, if it is not answered yet.
+    fn pending(&self, seat: Seat) -> Option<&Shot> {
+        self.shots[seat].last().filter(|s| s.hit.is_none())
+    }
+
+    fn hits(&self, seat: Seat) -> usize {
+        self.shots[seat]
+            .iter()
+            .filter(|s| s.hit == Some(true))
+            .count()
+    }
+
+    /// Whether the board of `seat` is sunk, or every cell of it was shot
+    /// at and answered.
+    fn finished(&self, seat: Seat) -> bool {
+        self.hits(seat) >= FLEET_CELLS
+            || (self.shots[seat].len() == SIZE * SIZE && self.pending(seat).is_none())
+    }
+
+    fn answer(&mut self, seat: Seat, answer: Option<bool>) -> Result<()> {
+        match (self.shots[seat].last_mut(), answer) {
+            (Some(shot), Some(hit)) if shot.hit.is_none() => shot.hit = Some(hit),
+            (Some(shot), None) if shot.hit.is_none() => bail!(t!("battleship.answer_missing")),
+            (_, Some(_)) => bail!(t!("battleship.nothing_to_answer")),
+            (_, None) => {}
+        }
+        Ok(())
+    }
+
+    /// Whether `seat` opened the layout it committed to and answered every
+    /// shot as that layout says.
+    fn honest(&self, seat: Seat) -> bool {
+        let (Some(commitment), Some(opening)) = (&self.commitments[seat], &self.revealed[seat])
+        else {
+            return false;
+        };
+        let cells = opening.value::<Layout>().and_then(|l| l.cells());
+        opening.verify(commitment)
+            && cells.is_some_and(|cells| {
+                self.shots[seat]
+                    .iter()
+                    .all(|s| s.hit.is_none_or(|hit| hit == cells.contains(&s.cell)))
+            })
+    }
+
+    /// Our opening and the cells of our fleet, from the local store.
+    fn own_layout(&self, seat: Seat) -> Result<(Opening, BTreeSet<usize>)> {
+        let missing = || anyhow!(t!("battleship.no_layout"));
+        let commitment = self.commitments[seat].as_deref().ok_or_else(missing)?;
+        let opening = Openings::load()?
+            .get(commitment)
+            .cloned()
+            .ok_or_else(missing)?;
+        let cells = opening
+            .value::<Layout>()
+            .and_then(|l| l.cells())
+            .ok_or_else(missing)?;
+        Ok((opening, cells))
+    }
+
+    /// The move `seat` makes with our own layout: the answer to the
+    /// pending shot, and a reveal if the battle is over after it.
+    fn own_move(&self, seat: Seat, target: Option<usize>) -> Result<BattleshipMove> {
+        let (opening, cells) = self.own_layout(seat)?;
+        let answer = self.pending(seat).map(|s| cells.contains(&s.cell));
+        let mut next = self.clone();
+        next.answer(seat, answer)?;
+        if next.phase() == Phase::Reveal {
+            return Ok(BattleshipMove::Reveal { answer, opening });
+        }
+        let target = target.ok_or_else(|| anyhow!(t!("battleship.target_missing")))?;
+        Ok(BattleshipMove::Fire { answer, target })
+    }
+
+    fn board(&self, seat: Seat, viewer: Option<Seat>) -> GridModel {
+        let ships = match &self.revealed[seat] {
+            Some(opening) => opening.value::<Layout>().and_then(|l| l.cells()),
+            None if viewer == Some(seat) => self.own_layout(seat).ok().map(|(_, cells)| cells),
+            None => None,
+        }
+        .unwrap_or_default();
+        let mut grid = GridModel::empty(SIZE, SIZE);
+        for &cell in &ships {
+            grid.cells[cell / SIZE][cell % SIZE] = Some('S');
+        }
+        for shot in &self.shots[seat] {
+            grid.cells[shot.cell / SIZE][shot.cell % SIZE] = Some(match shot.hit {
+                Some(true) => 'X',
+                Some(false) => 'o',
+                None => '?',
+            });
+        }
+        grid.highlight = self.shots[seat]
+            .last()
+            .map(|s| (s.cell / SIZE, s.cell % SIZE));
+        grid
+    }
+}
+
+impl Game for Battleship {
+    type Move = BattleshipMove;
+
+    const NAME: &'static str = "battleship";
+
+    const ON_DROP: DropPolicy = DropPolicy::EndGame;
+
+    fn new(players: usize) -> Result<Self> {
+        if players != 2 {
+            bail!(t!("game.player_count", game = Self::NAME, count = 2));
+        }
+        Ok(Self::default())
+    }
+
+    fn to_move(&self) -> Option<Seat> {
+        if self.outcome().is_some() {
+            return None;
+        }
+        match self.phase() {
+            Phase::Setup => self.commitments.iter().position(Option::is_none),
+            Phase::Battle | Phase::Reveal => Some(self.turn),
+        }
+    }
+
+    /// Empty during setup: committing needs a new secret layout.
+    fn legal_moves(&self, seat: Seat) -> Vec<BattleshipMove> {
+        if Game::to_move(self) != Some(seat) || self.phase() == Phase::Setup {
+            return Vec::new();
+        }
+        let shot: BTreeSet<usize> = self.shots[1 - seat].iter().map(|s| s.cell).collect();
+        let mut targets = (0..SIZE * SIZE).filter(|c| !shot.contains(c));
+        match self.own_move(seat, targets.next()) {
+            Ok(BattleshipMove::Fire { answer, target }) => std::iter::once(target)
+                .chain(targets)
+                .map(|target| BattleshipMove::Fire { answer, target })
+                .collect(),
+            Ok(mv) => vec![mv],
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, seat: Seat, mv: &BattleshipMove) -> Result<()> {
+        match Game::to_move(self) {
+            None => bail!(t!("game.over")),
+            Some(s) if s != seat => bail!(t!("game.not_your_turn")),
+            Some(_) => {}
+        }
+        let mut next = self.clone();
+        match (self.phase(), mv) {
+            (Phase::Setup, BattleshipMove::Commit { commitment }) => {
+                next.commitments[seat] = Some(commitment.clone());
+            }
+            (Phase::Battle, BattleshipMove::Fire { answer, target }) => {
+                next.answer(seat, *answer)?;
+                if next.phase() == Phase::Reveal {
+                    bail!(t!("battleship.must_reveal"));
+                }
+                let foe = 1 - seat;
+                if *target >= SIZE * SIZE {
+                    bail!(t!("game.bad_move", input = target));
+                }
+                if next.shots[foe].iter().any(|s| s.cell == *target) {
+                    bail!(t!("battleship.already_shot"));
+                }
+                next.shots[foe].push(Shot {
+                    cell: *target,
+                    hit: None,
+                });
+                next.turn = foe;
+            }
+            (Phase::Battle | Phase::Reveal, BattleshipMove::Reveal { answer, opening }) => {
+                next.answer(seat, *answer)?;
+                if next.phase() != Phase::Reveal {
+                    bail!(t!("battleship.not_over"));
+                }
+                next.revealed[seat] = Some(opening.clone());
+                next.turn = 1 - seat;
+            }
+            _ => bail!(t!("battleship.wrong_phase")),
+        }
+        *self = next;
+        Ok(())
+    }
+
+    /// Committing to a layout keeps its opening in [`Openings`].
+    fn parse_move(&self, input: &str) -> Result<BattleshipMove> {
+        let Some(seat) = Game::to_move(self) else {
+            bail!(t!("game.over"));
+        };
+        let input = input.trim().to_ascii_lowercase();
+        match self.phase() {
+            Phase::Setup => {
+                let layout = if input == "random" {
+                    Layout::random()
+                } else {
+                    Layout::parse(&input)?
+                };
+                let mut openings = Openings::load()?;
+                let commitment = openings.commit(&layout);
+                openings.save()?;
+                Ok(BattleshipMove::Commit { commitment })
+            }
+            Phase::Battle => {
+                let target = parse_cell(&input)
+                    .ok_or_else(|| anyhow!(t!("game.bad_move", input = input)))?;
+                self.own_move(seat, Some(target))
+            }
+            Phase::Reveal => self.own_move(seat, None),
+        }
+    }
+
+    fn describe_move(&self, mv: &BattleshipMove) -> Option<String> {
+        match mv {
+            BattleshipMove::Fire { target, .. } => Some(cell_name(target / SIZE, target % SIZE)),
+            _ => None,
+        }
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        if let Some(seat) = self.forfeit {
+            return Some(Outcome::Winner { seat: 1 - seat });
+        }
+        if self.revealed.iter().any(Option::is_none) {
+            return None;
+        }
+        match (self.honest(0), self.honest(1)) {
+            (true, true) => Some(Outcome::Winner {
+                seat: if self.finished(0) { 1 } else { 0 },
+            }),
+            (true, false) => Some(Outcome::Winner { seat: 0 }),
+            (false, true) => Some(Outcome::Winner { seat: 1 }),
+            (false, false) => Some(Outcome::Draw),
+        }
+    }
+
+    fn render(&self, viewer: Option<Seat>) -> RenderModel {
+        let seats = match viewer {
+            Some(me) => [1 - me, me],
+            None => [0, 1],
+        };
+        RenderModel::Stack(
+            seats
+                .iter()
+                .map(|&seat| RenderModel::Grid(self.board(seat, viewer)))
+                .collect(),
+        )
+    }
+
+    fn eliminate(&mut self, seat: Seat) -> Result<()> {
+        self.forfeit = Some(seat);
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+//! Well-known peers to join the network through.
+//!
+//! Discovery through n0 can take a while to find anyone. Long-running peers
+//! listed in `config.toml` (a friend's daemon, a community hub) are dialed
+//! directly instead, so gossip on the global topics and in rooms has
+//! neighbors right away:
+//!
+//! ```toml
+//! [[bootstrap]]
+//! node_id = "3ac1…"
+//! label = "club hub"
+//! priority = 10        # dialed before lower priorities
+//!
+//! [[bootstrap]]
+//! node_id = "9f0e…"
+//! rooms = ["chess"]    # rendezvous for these rooms only
+//! ```
+//!
+//! Peers without `rooms` are dialed when the node comes up and so serve every
+//! topic; rendezvous peers are dialed when one of their rooms is subscribed.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::timeout;
+use transport_iroh::transport_iroh::GossipTransport;
+
+/// Connected bootstrap peers after which the rest of the list is skipped.
+const ENOUGH_PEERS: usize = 3;
+
+/// One `[[bootstrap]]` entry of `config.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootstrapPeer {
+    pub node_id: String,
+    /// Shown in logs instead of the node id.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Higher is dialed first; equal priorities keep the file order.
+    #[serde(default)]
+    pub priority: i32,
+    /// Rooms (names or topic hex) this peer is a rendezvous for; empty for a
+    /// general bootstrap peer.
+    #[serde(default)]
+    pub rooms: Vec<String>,
+}
+
+impl BootstrapPeer {
+    pub fn name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.node_id)
+    }
+
+    fn serves(&self, room: &str, topic_hex: &str) -> bool {
+        self.rooms
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(room) || r.eq_ignore_ascii_case(topic_hex))
+    }
+}
+
+/// General bootstrap peers, highest priority first.
+pub fn general(peers: &[BootstrapPeer]) -> Vec<&BootstrapPeer> {
+    by_priority(peers.iter().filter(|p| p.rooms.is_empty()))
+}
+
+/// Rendezvous peers of a room, highest priority first.
+pub fn rendezvous<'a>(
+    peers: &'a [BootstrapPeer],
+    room: &str,
+    topic_hex: &str,
+) -> Vec<&'a BootstrapPeer> {
+    by_priority(peers.iter().filter(|p| p.serves(room, topic_hex)))
+}
+
+fn by_priority<'a>(peers: impl Iterator<Item = &'a BootstrapPeer>) -> Vec<&'a BootstrapPeer> {
+    let mut peers: Vec<_> = peers.collect();
+    peers.sort_by_key(|p| std::cmp::Reverse(p.priority));
+    peers
+}
+
+/// Dial `peers` in order, each for at most `wait`, until [`ENOUGH_PEERS`]
+/// answered. Returns the peers that could not be reached.
+pub async fn dial<'a>(
+    transport: &impl GossipTransport,
+    peers: &[&'a BootstrapPeer],
+    wait: Duration,
+) -> Vec<&'a BootstrapPeer> {
+    let mut connected = 0;
+    let mut failed = Vec::new();
+    for &peer in peers {
+        if connected == ENOUGH_PEERS {
+            break;
+        }
+        let Ok(addr) = transport.parse_node_id_addr(&peer.node_id) else {
+            failed.push(peer);
+            continue;
+        };
+        match timeout(wait, transport.connect(&addr)).await {
+            Ok(Ok(())) => connected += 1,
+            _ => failed.push(peer),
+        }
+    }
+    failed
+}
@@ -0,0 +1,135 @@
+//! Chat bridges between rooms and namespaces.
+//!
+//! A node that is in two rooms can mirror chat between them, e.g. to link a
+//! private friends room with a public lobby. Bridges are configured in
+//! `config.toml` and run with `p2p-games bridge`:
+//!
+//! ```toml
+//! [[bridges]]
+//! a = "friends"        # a joined room (name or topic hex)
+//! b = "global@public"  # the global chat of a namespace
+//! ttl = 2
+//! ```
+//!
+//! Mirrored messages carry a [`Relay`] tag with the original sender, room
+//! and message id. A message travels at most `ttl` bridges, each bridge
+//! forwards an original message only once, and a bridge ignores its own
+//! mirrors, so chains and cycles of bridges cannot loop.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::protocol::{ChatMsg, Envelope, Relay};
+
+/// How many origin message ids a bridge remembers.
+const RECENT_IDS: usize = 512;
+
+fn default_ttl() -> u8 {
+    2
+}
+
+/// One `[[bridges]]` entry of `config.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeConfig {
+    /// First end, see [`BridgeEnd::parse`].
+    pub a: String,
+    /// Second end.
+    pub b: String,
+    /// Bridges a message may cross in total.
+    #[serde(default = "default_ttl")]
+    pub ttl: u8,
+}
+
+/// Where one side of a bridge is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeEnd {
+    /// Global chat, of our own namespace unless given.
+    Global { namespace: Option<String> },
+    /// A topic given as hex.
+    Topic(String),
+    /// A joined room, by name.
+    Room(String),
+}
+
+impl BridgeEnd {
+    /// `global`, `global@<namespace>`, a 64-digit topic hex, or a room name.
+    pub fn parse(s: &str) -> Self {
+        match s.split_once('@') {
+            _ if s == "global" => Self::Global { namespace: None },
+            Some(("global", ns)) => Self::Global {
+                namespace: Some(ns.to_string()),
+            },
+            _ if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) => {
+                Self::Topic(s.to_lowercase())
+            }
+            _ => Self::Room(s.to_string()),
+        }
+    }
+}
+
+/// Mirrors chat between the two ends of a [`BridgeConfig`].
+pub struct Bridge {
+    cfg: BridgeConfig,
+    seen: VecDeque<String>,
+}
+
+impl Bridge {
+    pub fn new(cfg: BridgeConfig) -> Self {
+        Self {
+            cfg,
+            seen: VecDeque::new(),
+        }
+    }
+
+    pub fn config(&self) -> &BridgeConfig {
+        &self.cfg
+    }
+
+    /// The message to post on the other end when `env` arrived on the end
+    /// labelled `from`, with `me` being this node; `None` if it must not
+    /// cross (other bridge, own mirror, TTL spent, already forwarded).
+    pub fn forward(
+        &mut self,
+        env: &Envelope<ChatMsg>,
+        from: &str,
+        me: &str,
+    ) -> Option<(&str, ChatMsg)> {
+        let to = if from == self.cfg.a {
+            &self.cfg.b
+        } else if from == self.cfg.b {
+            &self.cfg.a
+        } else {
+            return None;
+        };
+        let relay = match &env.body.relay {
+            Some(_) if env.sender_id == me => return None,
+            Some(r) => Relay {
+                hops: r.hops.saturating_add(1),
+                ..r.clone()
+            },
+            None => Relay {
+                origin_id: env.sender_id.clone(),
+                origin_room: from.to_string(),
+                origin_msg_id: env.msg_id.clone(),
+                hops: 1,
+            },
+        };
+        if relay.hops > self.cfg.ttl
+            || relay.origin_room == *to
+            || self.seen.contains(&relay.origin_msg_id)
+        {
+            return None;
+        }
+        if self.seen.len() == RECENT_IDS {
+            self.seen.pop_front();
+        }
+        self.seen.push_back(relay.origin_msg_id.clone());
+        Some((
+            to,
+            ChatMsg {
+                text: env.body.text.clone(),
+                relay: Some(relay),
+            },
+        ))
+    }
+}
@@ -0,0 +1,59 @@
+//! Room browser state.
+//!
+//! Front-end independent model behind "pick a room from a list and join":
+//! the list comes from a [`RoomCache`], the selection survives refreshes
+//! (it follows the room, not the row), and the selected room yields the
+//! [`RoomTicket`] to join with.
+
+use crate::{discovery::RoomCache, protocol::RoomSummary, rooms::RoomTicket};
+
+#[derive(Debug, Clone, Default)]
+pub struct RoomBrowser {
+    rooms: Vec<RoomSummary>,
+    selected: usize,
+}
+
+impl RoomBrowser {
+    /// Replace the list, keeping the selected room selected if it is still there.
+    pub fn refresh(&mut self, cache: &RoomCache) {
+        let current = self.selected().map(|r| r.room_id.clone());
+        self.rooms = cache.list();
+        self.selected = current
+            .and_then(|id| self.rooms.iter().position(|r| r.room_id == id))
+            .unwrap_or(0);
+    }
+
+    pub fn rooms(&self) -> &[RoomSummary] {
+        &self.rooms
+    }
+
+    pub fn selected(&self) -> Option<&RoomSummary> {
+        self.rooms.get(self.selected)
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, index: usize) -> Option<&RoomSummary> {
+        if index < self.rooms.len() {
+            self.selected = index;
+        }
+        self.rooms.get(index)
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn down(&mut self) {
+        if self.selected + 1 < self.rooms.len() {
+            self.selected += 1;
+        }
+    }
+
+    /// Ticket of the selected room; `None` if the host did not publish one.
+    pub fn selected_ticket(&self) -> Option<RoomTicket> {
+        self.selected()?.ticket.as_deref()?.parse().ok()
+    }
+}
@@ -0,0 +1,77 @@
+//! Quick messages typed as `/gg`, `/brb` or `/rematch?`.
+//!
+//! A chat line that is exactly `/<name>` of a canned message is sent as its
+//! text instead (`say`, the REPL and the TUI input all expand it). Rooms can
+//! say it differently, and the TUI sends a canned message on a key of its
+//! own. Everything comes from `[canned]` in `config.toml`:
+//!
+//! ```toml
+//! [canned.messages]
+//! gg = "gg, well played!"
+//! gl = "good luck, have fun"
+//!
+//! [canned.rooms.chess]       # per room, by local room name
+//! gg = "Good game. Analysis?"
+//!
+//! [canned.keys]              # TUI keys, see `[keymap]` for the syntax
+//! f2 = "gg"
+//! ```
+//!
+//! `gg`, `brb` and `rematch?` exist without any configuration.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{keymap::KeyChord, t};
+
+/// Canned messages available without configuration; `canned.<name>` is
+/// their i18n key.
+const BUILTIN: &[&str] = &["gg", "brb", "rematch?"];
+
+/// `[canned]` section of `config.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CannedConfig {
+    /// Name → text; replaces a built-in message of the same name.
+    pub messages: BTreeMap<String, String>,
+    /// Room name → name → text, used in that room before `messages`.
+    pub rooms: BTreeMap<String, BTreeMap<String, String>>,
+    /// TUI key → name of the message it sends.
+    pub keys: BTreeMap<String, String>,
+}
+
+impl CannedConfig {
+    /// Text of the message `name` in `room` (`None` for global chat).
+    pub fn text(&self, name: &str, room: Option<&str>) -> Option<String> {
+        let in_room = room
+            .and_then(|r| self.rooms.get(r))
+            .and_then(|m| m.get(name));
+        match in_room.or_else(|| self.messages.get(name)) {
+            Some(text) => Some(text.clone()),
+            None => BUILTIN
+                .contains(&name)
+                .then(|| t!(&format!("canned.{name}"))),
+        }
+    }
+
+    /// The text to send for a typed `line`: the canned message if the line
+    /// is `/<name>` of one, `None` otherwise.
+    pub fn expand(&self, line: &str, room: Option<&str>) -> Option<String> {
+        let name = line.trim().strip_prefix('/')?;
+        self.text(name, room)
+    }
+
+    /// `keys` parsed; bad keys are errors.
+    pub fn key_bindings(&self) -> Result<Vec<(KeyChord, String)>> {
+        self.keys
+            .iter()
+            .map(|(key, name)| {
+                let chord = key
+                    .parse()
+                    .map_err(|e| anyhow!("canned message key '{key}': {e}"))?;
+                Ok((chord, name.clone()))
+            })
+            .collect()
+    }
+}
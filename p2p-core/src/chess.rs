@@ -0,0 +1,189 @@
+//! Chess, with the rules of [`shakmaty`].
+//!
+//! Seat 0 plays white and moves first, seat 1 plays black. Moves are typed
+//! in SAN (`Nf3`, `exd6`, `O-O`, `e8=Q`) or UCI (`g1f3`, `e7e8q`) and travel
+//! as UCI, so every peer checks them against its own copy of the position:
+//! castling, en passant, promotion, check, checkmate, stalemate and
+//! insufficient material all come from the library. The 75-move rule ends the
+//! game in a draw; repetitions are not tracked. A player who leaves forfeits.
+//!
+//! The board is drawn like every [`GridModel`], first row on top, so rank 1
+//! is the top row and the labels read as square names.
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use shakmaty::{
+    CastlingMode, Color, EnPassantMode, File, KnownOutcome, Position, Rank, Square, fen::Fen,
+    san::San, uci::UciMove,
+};
+
+use crate::{
+    game::{DropPolicy, Game, Outcome, Seat},
+    t,
+    widgets::{GridModel, RenderModel},
+};
+
+const SIZE: u32 = 8;
+
+/// Half-moves without a capture or pawn move after which the game is drawn.
+const SEVENTY_FIVE_MOVES: u32 = 150;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Chess {
+    /// The position, as FEN.
+    #[serde(serialize_with = "to_fen", deserialize_with = "from_fen")]
+    position: shakmaty::Chess,
+    /// Rank and file of the square the last move went to.
+    last: Option<(usize, usize)>,
+    /// Seat that left the game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    forfeit: Option<Seat>,
+}
+
+/// A move in UCI notation (`e2e4`, `e1g1` for castling, `e7e8q`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChessMove {
+    pub uci: String,
+}
+
+fn to_fen<S: Serializer>(position: &shakmaty::Chess, s: S) -> Result<S::Ok, S::Error> {
+    Fen::from_position(position, EnPassantMode::Legal)
+        .to_string()
+        .serialize(s)
+}
+
+fn from_fen<'de, D: Deserializer<'de>>(d: D) -> Result<shakmaty::Chess, D::Error> {
+    let fen: Fen = String::deserialize(d)?.parse().map_err(de::Error::custom)?;
+    fen.into_position(CastlingMode::Standard)
+        .map_err(de::Error::custom)
+}
+
+fn seat(color: Color) -> Seat {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+impl Chess {
+    fn to_move(&self, m: shakmaty::Move) -> ChessMove {
+        ChessMove {
+            uci: UciMove::from_move(m, CastlingMode::Standard).to_string(),
+        }
+    }
+
+    /// The library's move for `mv`, if it is legal in this position.
+    fn resolve(&self, mv: &ChessMove) -> Result<shakmaty::Move> {
+        let uci: UciMove = mv
+            .uci
+            .parse()
+            .map_err(|_| anyhow!(t!("game.bad_move", input = mv.uci)))?;
+        uci.to_move(&self.position)
+            .map_err(|_| anyhow!(t!("chess.illegal", input = mv.uci)))
+    }
+}
+
+impl Game for Chess {
+    type Move = ChessMove;
+
+    const NAME: &'static str = "chess";
+
+    const ON_DROP: DropPolicy = DropPolicy::EndGame;
+
+    fn new(players: usize) -> Result<Self> {
+        if players != 2 {
+            bail!(t!("game.player_count", game = Self::NAME, count = 2));
+        }
+        Ok(Self::default())
+    }
+
+    fn to_move(&self) -> Option<Seat> {
+        self.outcome().is_none().then(|| seat(self.position.turn()))
+    }
+
+    fn legal_moves(&self, seat: Seat) -> Vec<ChessMove> {
+        if Game::to_move(self) != Some(seat) {
+            return Vec::new();
+        }
+        self.position
+            .legal_moves()
+            .into_iter()
+            .map(|m| self.to_move(m))
+            .collect()
+    }
+
+    fn apply(&mut self, seat: Seat, mv: &ChessMove) -> Result<()> {
+        match Game::to_move(self) {
+            None => bail!(t!("game.over")),
+            Some(s) if s != seat => bail!(t!("game.not_your_turn")),
+            Some(_) => {}
+        }
+        let m = self.resolve(mv)?;
+        self.position = self
+            .position
+            .clone()
+            .play(m)
+            .map_err(|_| anyhow!(t!("chess.illegal", input = mv.uci)))?;
+        let to = m.to();
+        self.last = Some((to.rank().to_usize(), to.file().to_usize()));
+        Ok(())
+    }
+
+    fn parse_move(&self, input: &str) -> Result<ChessMove> {
+        let input = input.trim();
+        if let Ok(uci) = input.to_ascii_lowercase().parse::<UciMove>() {
+            return match uci.to_move(&self.position) {
+                Ok(m) => Ok(self.to_move(m)),
+                Err(_) => bail!(t!("chess.illegal", input = input)),
+            };
+        }
+        let san: San = input
+            .trim_end_matches(['+', '#'])
+            .parse()
+            .map_err(|_| anyhow!(t!("game.bad_move", input = input)))?;
+        let m = san
+            .to_move(&self.position)
+            .map_err(|_| anyhow!(t!("chess.illegal", input = input)))?;
+        Ok(self.to_move(m))
+    }
+
+    /// The UCI move, which reads the same whatever the position.
+    fn describe_move(&self, mv: &ChessMove) -> Option<String> {
+        Some(mv.uci.clone())
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        if let Some(seat) = self.forfeit {
+            return Some(Outcome::Winner { seat: 1 - seat });
+        }
+        match self.position.outcome().known() {
+            Some(KnownOutcome::Decisive { winner }) => Some(Outcome::Winner { seat: seat(winner) }),
+            Some(KnownOutcome::Draw) => Some(Outcome::Draw),
+            None if self.position.halfmoves() >= SEVENTY_FIVE_MOVES => Some(Outcome::Draw),
+            None => None,
+        }
+    }
+
+    fn render(&self, _viewer: Option<Seat>) -> RenderModel {
+        let board = self.position.board();
+        let cells = (0..SIZE)
+            .map(|rank| {
+                (0..SIZE)
+                    .map(|file| {
+                        let square = Square::from_coords(File::new(file), Rank::new(rank));
+                        board.piece_at(square).map(|p| p.char())
+                    })
+                    .collect()
+            })
+            .collect();
+        RenderModel::Grid(GridModel {
+            cells,
+            highlight: self.last,
+        })
+    }
+
+    fn eliminate(&mut self, seat: Seat) -> Result<()> {
+        self.forfeit = Some(seat);
+        Ok(())
+    }
+}
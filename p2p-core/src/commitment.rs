@@ -0,0 +1,101 @@
+//! Commit-reveal: promise a value now, prove it later.
+//!
+//! A player who must not change a secret mid-game (a battleship layout, a
+//! sealed bid…) publishes the commitment of an [`Opening`] when the game
+//! starts and keeps the opening to itself. Revealing it at the end lets
+//! everyone check with [`Opening::verify`] that the value is the one
+//! committed to. The random salt keeps small secrets from being found by
+//! hashing every candidate.
+//!
+//! Openings are secrets of this node: [`Openings`] keeps them in
+//! `<data dir>/p2p-games/openings.json` by commitment, so a restarted client
+//! can still play and reveal.
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::session::data_dir;
+
+/// A committed value and the salt that hides it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Opening {
+    pub value: Value,
+    pub salt: String,
+}
+
+impl Opening {
+    /// Opening of `value` with a fresh salt.
+    pub fn new(value: &impl Serialize) -> Self {
+        let salt = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        Self {
+            value: serde_json::to_value(value).expect("serialize committed value"),
+            salt,
+        }
+    }
+
+    /// Hex SHA-256 of the salt and the value's JSON, to be published.
+    pub fn commitment(&self) -> String {
+        let mut hash = Sha256::new();
+        hash.update(self.salt.as_bytes());
+        hash.update(b":");
+        // `serde_json::Map` keeps keys sorted, so equal values hash alike.
+        hash.update(serde_json::to_vec(&self.value).expect("serialize committed value"));
+        hex::encode(hash.finalize())
+    }
+
+    /// Whether this opening is the one behind `commitment`.
+    pub fn verify(&self, commitment: &str) -> bool {
+        self.commitment() == commitment
+    }
+
+    /// The committed value, if it has the expected type.
+    pub fn value<T: DeserializeOwned>(&self) -> Option<T> {
+        serde_json::from_value(self.value.clone()).ok()
+    }
+}
+
+/// Our own openings, by commitment.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Openings {
+    openings: BTreeMap<String, Opening>,
+}
+
+impl Openings {
+    fn storage_path() -> PathBuf {
+        data_dir().join("openings.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// Commit to `value`: keep its opening and return the commitment.
+    pub fn commit(&mut self, value: &impl Serialize) -> String {
+        let opening = Opening::new(value);
+        let commitment = opening.commitment();
+        self.openings.insert(commitment.clone(), opening);
+        commitment
+    }
+
+    pub fn get(&self, commitment: &str) -> Option<&Opening> {
+        self.openings.get(commitment)
+    }
+}
@@ -0,0 +1,291 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use clap::ValueEnum;
+use std::{fs, net::SocketAddr, path::PathBuf, time::SystemTime};
+use transport_iroh::transport_iroh::{IrohOptions, Relays};
+
+use crate::{
+    admission::AdmissionPolicy, announce::AnnounceConfig, archive::ArchiveConfig,
+    bandwidth::BandwidthConfig,
+    bootstrap::BootstrapPeer, bridge::BridgeConfig, canned::CannedConfig, digest::SyncConfig,
+    history::HistoryConfig, keymap::{Keymap, KeymapConfig},
+    namespace::{DEFAULT_NAMESPACE, TopicEpochs},
+    notify::NotifyConfig, output::ColorChoice, scheduler::SchedulerConfig,
+    storage::StorageConfig, sybil::SybilConfig, timeouts::Timeouts, update::UpdateConfig,
+    vouch::VouchConfig,
+    wire::ProtocolConfig,
+};
+
+/// User configuration read from `<config dir>/p2p-games/config.toml`.
+///
+/// Every field is optional in the file; missing keys fall back to defaults.
+/// Command-line flags win over the file:
+///
+/// ```toml
+/// nickname = "alice"                          # `login` without `--name`
+/// relay_url = "https://relay.example.org"     # `--relay-url`
+/// transport = "tcp"                           # iroh, tcp or libp2p; `--transport`
+/// tcp_listen = "0.0.0.0:7447"
+/// libp2p_listen = "0.0.0.0:4001"
+/// color = "never"                             # auto, always or never; `--color`
+///
+/// [timeouts]
+/// claim_wait_ms = 2000                        # `login --wait-ms`
+///
+/// [[bootstrap]]
+/// node_id = "3ac1…"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    /// UI locale (e.g. `"de"`, `"fr-CH"`). `None` falls back to `LANG`, then English.
+    pub locale: Option<String>,
+    /// Network namespace all named topics are derived from (default `"public"`).
+    pub namespace: Option<String>,
+    /// Epochs of rotated well-known topics (`[topics]`).
+    pub topics: TopicEpochs,
+    /// Nickname `login` claims when no `--name` is given; offered by `setup`.
+    pub nickname: Option<String>,
+    /// Relay server used instead of n0's (same as `--relay-url`).
+    pub relay_url: Option<String>,
+    /// Relay servers and discovery of the iroh transport (`[relays]`).
+    pub relays: RelayConfig,
+    /// Network transport (same as `--transport`).
+    pub transport: TransportKind,
+    /// Address the TCP transport listens on (default `0.0.0.0:7447`).
+    pub tcp_listen: Option<SocketAddr>,
+    /// Address the libp2p transport listens on (default `0.0.0.0:4001`).
+    pub libp2p_listen: Option<SocketAddr>,
+    /// Always use the plain, screen-reader friendly output (same as `--plain`).
+    pub plain: bool,
+    /// When rich output is colored (same as `--color`).
+    pub color: ColorChoice,
+    /// Mask profanity in displayed chat (always on in safe mode).
+    pub profanity_filter: bool,
+    /// Extra words for the profanity filter.
+    pub blocked_words: Vec<String>,
+    /// Always run in low-power mode (same as `--low-power`).
+    pub low_power: bool,
+    /// Audible alerts per event type (`[notifications]`).
+    pub notifications: NotifyConfig,
+    /// Opt-in release feed check (`[update]`).
+    pub update: UpdateConfig,
+    /// Traffic soft cap (`[bandwidth]`).
+    pub bandwidth: BandwidthConfig,
+    /// Local history retention per scope (`[history]`).
+    pub history: HistoryConfig,
+    /// Disk quotas for history, replays and summaries (`[storage]`).
+    pub storage: StorageConfig,
+    /// Key bindings for interactive front ends (`[keymap]`).
+    pub keymap: KeymapConfig,
+    /// Quick messages like `/gg` (`[canned]`).
+    pub canned: CannedConfig,
+    /// Wire format migration (`[protocol]`).
+    pub protocol: ProtocolConfig,
+    /// Network wait times and retries (`[timeouts]`).
+    pub timeouts: Timeouts,
+    /// Rate budget and jitter of periodic broadcasts (`[scheduler]`).
+    pub scheduler: SchedulerConfig,
+    /// Digests of shared tables, to catch up on missed entries (`[sync]`).
+    pub sync: SyncConfig,
+    /// Chat bridges between rooms (`[[bridges]]`).
+    pub bridges: Vec<BridgeConfig>,
+    /// Well-known peers to join the network through (`[[bootstrap]]`).
+    pub bootstrap: Vec<BootstrapPeer>,
+    /// Friends and vouch-based room admission (`[vouch]`).
+    pub vouch: VouchConfig,
+    /// Who may join rooms we host (same as `room open --admit`).
+    pub admission: Option<AdmissionPolicy>,
+    /// Minutes a room we host may stay empty before it is closed (same as
+    /// `room open --idle-close`; default 60, 0 for never).
+    pub room_idle_minutes: Option<u64>,
+    /// Operator keys whose announcements are shown (`[announcements]`).
+    pub announcements: AnnounceConfig,
+    /// What `daemon` serves as an archive node (`[archive]`).
+    pub archive: ArchiveConfig,
+    /// What peers must show before their claims and seeks count (`[sybil]`).
+    pub sybil: SybilConfig,
+}
+
+/// How the node reaches the network (`transport` in `config.toml`,
+/// `--transport`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    /// iroh gossip over QUIC, with relays and hole punching.
+    #[default]
+    Iroh,
+    /// Plain TCP, for networks that block UDP; peers are dialed at
+    /// `<node id>@<host:port>`.
+    Tcp,
+    /// libp2p gossipsub over TCP, to take part in libp2p networks; peers
+    /// are dialed like over `tcp` or at `<node id>@<multiaddr>`.
+    Libp2p,
+}
+
+/// `[relays]`: how the iroh transport reaches other nodes, for
+/// self-hosted deployments.
+///
+/// ```toml
+/// [relays]
+/// urls = ["https://relay1.example.org", "https://relay2.example.org"]
+/// relay_only = true        # `--relay-only`
+/// disabled = false         # `--no-relay`
+/// n0_discovery = false     # `--no-n0-discovery`
+/// local_discovery = false  # `--no-local-discovery`
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayConfig {
+    /// Relay servers used instead of n0's, next to `relay_url`.
+    pub urls: Vec<String>,
+    /// Send all traffic through the relays, never directly.
+    pub relay_only: bool,
+    /// Use no relays at all; only direct connections work.
+    pub disabled: bool,
+    /// Publish our address to n0's DNS discovery and look nodes up there.
+    pub n0_discovery: bool,
+    /// Find nodes on the same subnet over mDNS, e.g. at LAN parties.
+    pub local_discovery: bool,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            urls: Vec::new(),
+            relay_only: false,
+            disabled: false,
+            n0_discovery: true,
+            local_discovery: true,
+        }
+    }
+}
+
+impl Config {
+    /// Directory holding `config.toml` and user-supplied resources (e.g. locales).
+    pub fn dir() -> PathBuf {
+        let mut path = dirs::config_dir().unwrap_or(std::env::temp_dir());
+        path.push("p2p-games");
+        path
+    }
+
+    pub fn namespace(&self) -> &str {
+        self.namespace
+            .as_deref()
+            .filter(|ns| !ns.is_empty())
+            .unwrap_or(DEFAULT_NAMESPACE)
+    }
+
+    fn storage_path() -> PathBuf {
+        Self::dir().join("config.toml")
+    }
+
+    /// Last modification time of `config.toml`, if it exists.
+    pub fn modified() -> Option<SystemTime> {
+        fs::metadata(Self::storage_path()).ok()?.modified().ok()
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let s = fs::read_to_string(path)?;
+            toml::from_str(&s).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Options of the iroh transport from `relay_url` and `[relays]`.
+    pub fn iroh_options(&self) -> IrohOptions {
+        let urls: Vec<String> = self.relay_url.iter().chain(&self.relays.urls).cloned().collect();
+        let relays = if self.relays.disabled {
+            Relays::Disabled
+        } else if urls.is_empty() {
+            Relays::Default
+        } else {
+            Relays::Custom(urls)
+        };
+        IrohOptions {
+            relays,
+            relay_only: self.relays.relay_only,
+            n0_discovery: self.relays.n0_discovery,
+            local_discovery: self.relays.local_discovery,
+        }
+    }
+
+    /// Catch mistakes that parse but cannot be used.
+    pub fn validate(&self) -> Result<()> {
+        Keymap::from_config(&self.keymap)?;
+        self.iroh_options().check()?;
+        Ok(())
+    }
+}
+
+/// What an edit of `config.toml` means for a running node.
+pub enum Reload {
+    /// The new settings; `restart` names changed keys that only take effect
+    /// once the node is restarted.
+    Applied {
+        config: Box<Config>,
+        restart: Vec<&'static str>,
+    },
+    /// The edit does not parse or validate; the previous settings stay.
+    Rejected(anyhow::Error),
+}
+
+/// Re-reads `config.toml` when it changes on disk, for long-running nodes.
+pub struct ConfigWatcher {
+    modified: Option<SystemTime>,
+    current: Config,
+}
+
+impl Default for ConfigWatcher {
+    fn default() -> Self {
+        Self {
+            modified: Config::modified(),
+            current: Config::load().unwrap_or_default(),
+        }
+    }
+}
+
+impl ConfigWatcher {
+    /// How the file changed since the last call, if it did.
+    ///
+    /// A broken edit is reported once; later polls compare with the last
+    /// settings that were applied.
+    pub fn poll(&mut self) -> Option<Reload> {
+        let modified = Config::modified();
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        let config = match Config::load().map_err(anyhow::Error::from) {
+            Ok(config) => config,
+            Err(e) => return Some(Reload::Rejected(e)),
+        };
+        if let Err(e) = config.validate() {
+            return Some(Reload::Rejected(e));
+        }
+        let old = &self.current;
+        // The endpoint and the topic names are fixed when the node binds.
+        let restart = [
+            ("locale", old.locale != config.locale),
+            ("namespace", old.namespace() != config.namespace()),
+            ("relay_url", old.relay_url != config.relay_url),
+            ("relays", old.relays != config.relays),
+            ("transport", old.transport != config.transport),
+            ("tcp_listen", old.tcp_listen != config.tcp_listen),
+            ("libp2p_listen", old.libp2p_listen != config.libp2p_listen),
+            ("archive", old.archive != config.archive),
+            ("sybil", old.sybil != config.sybil),
+        ]
+        .into_iter()
+        .filter_map(|(key, changed)| changed.then_some(key))
+        .collect();
+        self.current = config.clone();
+        Some(Reload::Applied {
+            config: Box::new(config),
+            restart,
+        })
+    }
+}
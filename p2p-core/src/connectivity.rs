@@ -0,0 +1,173 @@
+//! Detection of network isolation.
+//!
+//! An empty chat or room list looks the same whether nobody is online or we
+//! are cut off. [`PartitionDetector`] watches the neighbor counts of the
+//! subscribed topics and reports isolation once *all* of them have had zero
+//! neighbors for longer than a grace period, so the UI can show a banner
+//! instead of silently presenting an empty world.
+//!
+//! A front end that runs a detector also [publishes](PartitionDetector::publish)
+//! what it sees to `<data dir>/p2p-games/connectivity/<pid>.json`, so
+//! `status`, run from another process, can show it.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use transport_iroh::transport_iroh::NeighborCount;
+
+use crate::{protocol::now_ms, session::data_dir};
+
+/// How long all topics must be without neighbors before we call it isolation.
+pub const DEFAULT_ISOLATION_GRACE: Duration = Duration::from_secs(30);
+
+/// A report older than this is from a front end that is no longer running.
+pub const REPORT_STALE_MS: u64 = 90_000;
+
+/// Transition reported by [`PartitionDetector::poll`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectivityChange {
+    /// No neighbors on any watched topic for the whole grace period.
+    Isolated,
+    /// At least one neighbor is back after an isolation was reported.
+    Recovered,
+}
+
+/// What the detector of one running front end last saw.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectivityReport {
+    pub pid: u32,
+    /// Unix millis of the last write.
+    pub updated_at: u64,
+    pub isolated: bool,
+    /// See [`PartitionDetector::neighbor_total`].
+    pub neighbors: usize,
+}
+
+impl ConnectivityReport {
+    fn storage_dir() -> PathBuf {
+        data_dir().join("connectivity")
+    }
+
+    fn storage_path(pid: u32) -> PathBuf {
+        Self::storage_dir().join(format!("{pid}.json"))
+    }
+
+    /// Reports of the front ends still running, by pid. Stale ones, left by
+    /// front ends that did not exit cleanly, are removed.
+    pub fn load_live() -> Vec<Self> {
+        let Ok(entries) = fs::read_dir(Self::storage_dir()) else {
+            return Vec::new();
+        };
+        let mut live = Vec::new();
+        for path in entries.flatten().map(|e| e.path()) {
+            // A report being rewritten may not parse; it is skipped this time.
+            let Some(report) = fs::read(&path)
+                .ok()
+                .and_then(|b| serde_json::from_slice::<Self>(&b).ok())
+            else {
+                continue;
+            };
+            if now_ms().saturating_sub(report.updated_at) < REPORT_STALE_MS {
+                live.push(report);
+            } else {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        live.sort_by_key(|r| r.pid);
+        live
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::create_dir_all(Self::storage_dir())?;
+        fs::write(Self::storage_path(self.pid), json)
+    }
+}
+
+pub struct PartitionDetector {
+    grace: Duration,
+    topics: Vec<NeighborCount>,
+    empty_since: Option<Instant>,
+    isolated: bool,
+    /// Whether [`Self::publish`] wrote a report, removed again on drop.
+    published: bool,
+}
+
+impl PartitionDetector {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            topics: Vec::new(),
+            empty_since: Some(Instant::now()),
+            isolated: false,
+            published: false,
+        }
+    }
+
+    /// Watch another topic's neighbor counter.
+    pub fn watch(&mut self, neighbors: NeighborCount) {
+        self.topics.push(neighbors);
+    }
+
+    /// Watch exactly `topics`, e.g. after rooms were joined or left.
+    pub fn watch_only(&mut self, topics: &[NeighborCount]) {
+        self.topics = topics.to_vec();
+    }
+
+    pub fn is_isolated(&self) -> bool {
+        self.isolated
+    }
+
+    /// Total neighbors across watched topics (a peer on two topics counts twice).
+    pub fn neighbor_total(&self) -> usize {
+        self.topics.iter().map(NeighborCount::get).sum()
+    }
+
+    /// Re-evaluate; returns a change only on transitions.
+    ///
+    /// Without any watched topic the detector never reports isolation.
+    pub fn poll(&mut self) -> Option<ConnectivityChange> {
+        if self.topics.is_empty() || self.neighbor_total() > 0 {
+            self.empty_since = None;
+            if self.isolated {
+                self.isolated = false;
+                return Some(ConnectivityChange::Recovered);
+            }
+            return None;
+        }
+        let since = *self.empty_since.get_or_insert_with(Instant::now);
+        if !self.isolated && since.elapsed() >= self.grace {
+            self.isolated = true;
+            return Some(ConnectivityChange::Isolated);
+        }
+        None
+    }
+
+    /// Write what we see for `status`; call it after every [`Self::poll`].
+    pub fn publish(&mut self) {
+        let report = ConnectivityReport {
+            pid: std::process::id(),
+            updated_at: now_ms(),
+            isolated: self.isolated,
+            neighbors: self.neighbor_total(),
+        };
+        self.published |= report.save().is_ok();
+    }
+}
+
+impl Drop for PartitionDetector {
+    fn drop(&mut self) {
+        if self.published {
+            let _ = fs::remove_file(ConnectivityReport::storage_path(std::process::id()));
+        }
+    }
+}
+
+impl Default for PartitionDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_ISOLATION_GRACE)
+    }
+}
@@ -0,0 +1,392 @@
+//! Daemon mode: one long-running node shared by every command.
+//!
+//! `p2p-games daemon` binds the iroh endpoint once, keeps the registry
+//! listener and room subscriptions alive, and [`serve`]s the endpoint on a
+//! unix socket at `<data dir>/p2p-games/daemon.sock`. Other commands
+//! [`attach`](DaemonTransport::attach) to it and use it as their transport
+//! instead of binding a fresh node. Namespacing, batching and metering stay
+//! in the command; the daemon only dials peers and moves frames.
+//!
+//! Each operation is a connection carrying JSON lines: one [`Request`], then
+//! a [`Reply`]. A [`Request::Join`] turns the connection into the topic:
+//! the client sends [`Request::Publish`] lines, each answered in order with
+//! [`Reply::Ok`] or [`Reply::Error`], and the daemon sends [`Reply::Frame`]
+//! and [`Reply::Neighbors`] lines in between, until either side hangs up.
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{collections::VecDeque, fs, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    net::{
+        UnixListener, UnixStream,
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::{mpsc, oneshot},
+};
+use transport_iroh::transport_iroh::{
+    self as iroh, GossipTransport, NeighborCount, NodeAddr, TopicHandle, TopicId,
+};
+
+use crate::session::data_dir;
+
+/// How often the daemon reports changed neighbor counts of a topic.
+const NEIGHBORS_EVERY: Duration = Duration::from_secs(1);
+
+pub fn socket_path() -> PathBuf {
+    data_dir().join("daemon.sock")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    /// Which node the daemon runs.
+    Hello,
+    Connect {
+        node_id: String,
+    },
+    /// Subscribe; the connection carries the topic from now on.
+    Join {
+        topic: String,
+    },
+    /// Base64 frame to broadcast on the joined topic.
+    Publish {
+        frame: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Reply {
+    Hello {
+        node_id: String,
+    },
+    Ok,
+    Error {
+        message: String,
+    },
+    /// Base64 frame received on the joined topic.
+    Frame {
+        frame: String,
+    },
+    Neighbors {
+        count: usize,
+    },
+}
+
+type LineReader = Lines<BufReader<OwnedReadHalf>>;
+
+async fn send_line<T: Serialize>(w: &mut OwnedWriteHalf, msg: &T) -> Result<()> {
+    let mut line = serde_json::to_vec(msg)?;
+    line.push(b'\n');
+    w.write_all(&line).await?;
+    Ok(())
+}
+
+async fn read_line<T: DeserializeOwned>(lines: &mut LineReader) -> Result<Option<T>> {
+    match lines.next_line().await? {
+        Some(line) => Ok(Some(serde_json::from_str(&line)?)),
+        None => Ok(None),
+    }
+}
+
+/// Open a connection and send `req`.
+async fn request(req: &Request) -> Result<(LineReader, OwnedWriteHalf)> {
+    let (r, mut w) = UnixStream::connect(socket_path()).await?.into_split();
+    send_line(&mut w, req).await?;
+    Ok((BufReader::new(r).lines(), w))
+}
+
+/// The endpoint of a running daemon.
+pub struct DaemonTransport {
+    addr: NodeAddr,
+}
+
+impl DaemonTransport {
+    /// Attach to the daemon of this data directory, if one answers.
+    pub async fn attach() -> Option<Self> {
+        let (mut lines, _w) = request(&Request::Hello).await.ok()?;
+        let Ok(Some(Reply::Hello { node_id })) = read_line(&mut lines).await else {
+            return None;
+        };
+        let addr = iroh::parse_node_id_addr(&node_id).ok()?;
+        Some(Self { addr })
+    }
+}
+
+#[async_trait]
+impl GossipTransport for DaemonTransport {
+    fn node_addr(&self) -> &NodeAddr {
+        &self.addr
+    }
+
+    async fn connect(&self, peer: &NodeAddr) -> Result<()> {
+        let (mut lines, _w) = request(&Request::Connect {
+            node_id: peer.node_id.to_string(),
+        })
+        .await?;
+        match read_line(&mut lines).await? {
+            Some(Reply::Ok) => Ok(()),
+            Some(Reply::Error { message }) => Err(anyhow!(message)),
+            _ => bail!("daemon hung up"),
+        }
+    }
+
+    async fn join_topic(&self, topic: TopicId) -> Result<Box<dyn TopicHandle>> {
+        let (mut lines, w) = request(&Request::Join {
+            topic: iroh::topic_to_hex(&topic),
+        })
+        .await?;
+        match read_line(&mut lines).await? {
+            Some(Reply::Ok) => {}
+            Some(Reply::Error { message }) => bail!(message),
+            _ => bail!("daemon hung up"),
+        }
+        Ok(Box::new(DaemonTopic::new(lines, w)))
+    }
+
+    fn topic_from_name(&self, name: &str) -> TopicId {
+        iroh::topic_from_name(name)
+    }
+
+    fn topic_from_hex(&self, hex: &str) -> Result<TopicId> {
+        iroh::topic_from_hex(hex)
+    }
+
+    fn topic_to_hex(&self, topic: &TopicId) -> String {
+        iroh::topic_to_hex(topic)
+    }
+
+    fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr> {
+        iroh::parse_node_id_addr(s)
+    }
+
+    /// The daemon outlives the command; topics close with their handles.
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A frame to publish, and who to tell the daemon's answer.
+type Queued = (Vec<u8>, oneshot::Sender<Result<()>>);
+
+/// Topic joined through the daemon. One task owns the connection: it sends
+/// queued publishes, hands their replies back in order and passes received
+/// frames on; it ends when the handle is dropped.
+struct DaemonTopic {
+    queue: mpsc::UnboundedSender<Queued>,
+    received: mpsc::UnboundedReceiver<Result<Vec<u8>>>,
+    neighbors: NeighborCount,
+}
+
+impl DaemonTopic {
+    fn new(lines: LineReader, w: OwnedWriteHalf) -> Self {
+        let (queue, queued) = mpsc::unbounded_channel();
+        let (deliver, received) = mpsc::unbounded_channel();
+        let neighbors = NeighborCount::default();
+        tokio::spawn(relay(lines, w, queued, deliver, neighbors.clone()));
+        Self {
+            queue,
+            received,
+            neighbors,
+        }
+    }
+}
+
+async fn relay(
+    mut lines: LineReader,
+    mut w: OwnedWriteHalf,
+    mut queued: mpsc::UnboundedReceiver<Queued>,
+    deliver: mpsc::UnboundedSender<Result<Vec<u8>>>,
+    neighbors: NeighborCount,
+) {
+    let mut waiting = VecDeque::new();
+    loop {
+        tokio::select! {
+            frame = queued.recv() => match frame {
+                Some((bytes, done)) => {
+                    let req = Request::Publish {
+                        frame: BASE64.encode(bytes),
+                    };
+                    match send_line(&mut w, &req).await {
+                        Ok(()) => waiting.push_back(done),
+                        Err(e) => {
+                            let _ = done.send(Err(e));
+                        }
+                    }
+                }
+                None => break,
+            },
+            reply = read_line(&mut lines) => {
+                let frame = match reply {
+                    Ok(Some(Reply::Frame { frame })) => BASE64.decode(frame).map_err(Into::into),
+                    Ok(Some(Reply::Neighbors { count })) => {
+                        neighbors.set(count);
+                        continue;
+                    }
+                    Ok(Some(Reply::Ok)) => {
+                        if let Some(done) = waiting.pop_front() {
+                            let _ = done.send(Ok(()));
+                        }
+                        continue;
+                    }
+                    Ok(Some(Reply::Error { message })) => {
+                        if let Some(done) = waiting.pop_front() {
+                            let _ = done.send(Err(anyhow!(message)));
+                        }
+                        continue;
+                    }
+                    Ok(Some(Reply::Hello { .. })) => continue,
+                    Ok(None) => Err(anyhow!("daemon closed the topic")),
+                    Err(e) => Err(e),
+                };
+                let closed = frame.is_err();
+                if deliver.send(frame).is_err() || closed {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl TopicHandle for DaemonTopic {
+    /// Returns once the daemon has published the frame, or with its error.
+    async fn publish(&self, bytes: &[u8]) -> Result<()> {
+        let (done, published) = oneshot::channel();
+        self.queue
+            .send((bytes.to_vec(), done))
+            .map_err(|_| anyhow!("daemon closed the topic"))?;
+        published
+            .await
+            .map_err(|_| anyhow!("daemon closed the topic"))?
+    }
+
+    async fn next(&mut self) -> Result<Vec<u8>> {
+        self.received
+            .recv()
+            .await
+            .unwrap_or_else(|| Err(anyhow!("daemon closed the topic")))
+    }
+
+    fn neighbors(&self) -> Option<NeighborCount> {
+        Some(self.neighbors.clone())
+    }
+}
+
+/// Bind the control socket; fails if another daemon already listens there.
+pub async fn listen() -> Result<UnixListener> {
+    let path = socket_path();
+    if UnixStream::connect(&path).await.is_ok() {
+        bail!("a daemon is already running");
+    }
+    // Left behind by a daemon that did not exit cleanly.
+    let _ = fs::remove_file(&path);
+    Ok(UnixListener::bind(&path)?)
+}
+
+/// Serve `transport` to commands on `listener` until the task is dropped.
+pub async fn serve(listener: UnixListener, transport: Arc<dyn GossipTransport>) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let transport = transport.clone();
+        // A command that goes away mid-request only ends its connection.
+        tokio::spawn(async move {
+            let _ = handle(stream, &*transport).await;
+        });
+    }
+}
+
+async fn handle(stream: UnixStream, transport: &dyn GossipTransport) -> Result<()> {
+    let (r, mut w) = stream.into_split();
+    let mut lines = BufReader::new(r).lines();
+    let Some(req) = read_line::<Request>(&mut lines).await? else {
+        return Ok(());
+    };
+    match req {
+        Request::Hello => {
+            let node_id = transport.node_addr().node_id.to_string();
+            send_line(&mut w, &Reply::Hello { node_id }).await
+        }
+        Request::Connect { node_id } => {
+            let res = match transport.parse_node_id_addr(&node_id) {
+                Ok(peer) => transport.connect(&peer).await,
+                Err(e) => Err(e),
+            };
+            let reply = match res {
+                Ok(()) => Reply::Ok,
+                Err(e) => Reply::Error {
+                    message: e.to_string(),
+                },
+            };
+            send_line(&mut w, &reply).await
+        }
+        Request::Join { topic } => {
+            let th = match transport.topic_from_hex(&topic) {
+                Ok(topic) => transport.join_topic(topic).await,
+                Err(e) => Err(e),
+            };
+            match th {
+                Ok(th) => {
+                    send_line(&mut w, &Reply::Ok).await?;
+                    pump(th, lines, w).await
+                }
+                Err(e) => {
+                    let message = e.to_string();
+                    send_line(&mut w, &Reply::Error { message }).await
+                }
+            }
+        }
+        Request::Publish { .. } => {
+            let message = "publish before join".to_string();
+            send_line(&mut w, &Reply::Error { message }).await
+        }
+    }
+}
+
+/// Move frames between a joined topic and its client.
+async fn pump(
+    mut th: Box<dyn TopicHandle>,
+    mut lines: LineReader,
+    mut w: OwnedWriteHalf,
+) -> Result<()> {
+    let neighbors = th.neighbors();
+    let mut reported = None;
+    let mut tick = tokio::time::interval(NEIGHBORS_EVERY);
+    loop {
+        tokio::select! {
+            b = th.next() => {
+                let frame = BASE64.encode(b?);
+                send_line(&mut w, &Reply::Frame { frame }).await?;
+            }
+            req = read_line::<Request>(&mut lines) => match req? {
+                Some(Request::Publish { frame }) => {
+                    let res = match BASE64.decode(frame) {
+                        Ok(bytes) => th.publish(&bytes).await,
+                        Err(e) => Err(e.into()),
+                    };
+                    let reply = match res {
+                        Ok(()) => Reply::Ok,
+                        Err(e) => Reply::Error {
+                            message: e.to_string(),
+                        },
+                    };
+                    send_line(&mut w, &reply).await?;
+                }
+                Some(_) => continue,
+                None => return Ok(()),
+            },
+            _ = tick.tick() => {
+                let count = neighbors.as_ref().map(NeighborCount::get);
+                if count != reported
+                    && let Some(count) = count
+                {
+                    send_line(&mut w, &Reply::Neighbors { count }).await?;
+                    reported = Some(count);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Per-game and per-opponent statistics (`stats <game> [--vs <nick>]`).
+//!
+//! [`GameStats::build`] goes through our [`MatchRecord`]s of one game,
+//! optionally only those against one opponent: wins, losses and draws per
+//! opponent, average length, favorite openings and streaks. An opening is
+//! our first move as the game names it ([`Game::describe_move`]); games
+//! that do not name moves have none. Results that do not count (see
+//! [`crate::validation::counts`]) are left out.
+//!
+//! [`Game::describe_move`]: crate::game::Game::describe_move
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::{
+    game::{GameRegistry, Outcome},
+    matchlog::MatchRecord,
+};
+
+/// Openings listed, most played first.
+const TOP_OPENINGS: usize = 5;
+
+/// How a match ended for us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Finish {
+    Won,
+    Lost,
+    Drew,
+}
+
+impl Finish {
+    /// Key of the finish in `stats.finish.<key>` messages.
+    pub fn key(self) -> &'static str {
+        match self {
+            Finish::Won => "won",
+            Finish::Lost => "lost",
+            Finish::Drew => "drew",
+        }
+    }
+}
+
+/// Results against one opponent, or overall.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Tally {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    pub aborted: u32,
+    /// Moves over all these matches.
+    pub moves: u64,
+}
+
+impl Tally {
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws + self.aborted
+    }
+
+    /// Moves per match.
+    pub fn avg_moves(&self) -> f64 {
+        match self.games() {
+            0 => 0.0,
+            n => self.moves as f64 / f64::from(n),
+        }
+    }
+
+    fn add(&mut self, finish: Option<Finish>, moves: u64) {
+        match finish {
+            Some(Finish::Won) => self.wins += 1,
+            Some(Finish::Lost) => self.losses += 1,
+            Some(Finish::Drew) => self.draws += 1,
+            None => self.aborted += 1,
+        }
+        self.moves += moves;
+    }
+}
+
+/// The same finish several matches in a row; aborted matches do not
+/// break it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Streak {
+    pub finish: Finish,
+    pub len: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GameStats {
+    pub game: String,
+    /// Peer id the matches were filtered by.
+    pub opponent: Option<String>,
+    pub total: Tally,
+    /// Opponent peer id → results against them.
+    pub opponents: BTreeMap<String, Tally>,
+    /// Our first moves and how often we played them, most played first.
+    pub openings: Vec<(String, u32)>,
+    /// Streak the latest match is part of.
+    pub streak: Option<Streak>,
+    pub longest_win_streak: u32,
+}
+
+impl GameStats {
+    /// Statistics of `me` in `game` over `matches` (oldest first), against
+    /// `opponent` only if given; `counts` says whether a result counts.
+    pub fn build(
+        game: &str,
+        me: &str,
+        opponent: Option<&str>,
+        matches: &[MatchRecord],
+        registry: &GameRegistry,
+        counts: impl Fn(&MatchRecord) -> bool,
+    ) -> Self {
+        let mut stats = GameStats {
+            game: game.to_string(),
+            opponent: opponent.map(str::to_string),
+            ..Self::default()
+        };
+        let mut openings: BTreeMap<String, u32> = BTreeMap::new();
+        let mut current_wins = 0;
+        for m in matches {
+            let Some(seat) = m.players.iter().position(|p| p == me) else {
+                continue;
+            };
+            if m.game != game
+                || opponent.is_some_and(|o| o == me || !m.players.iter().any(|p| p == o))
+                || !counts(m)
+            {
+                continue;
+            }
+            let finish = match m.outcome {
+                Outcome::Winner { seat: w } if w == seat => Some(Finish::Won),
+                Outcome::Winner { .. } => Some(Finish::Lost),
+                Outcome::Draw => Some(Finish::Drew),
+                Outcome::Aborted { .. } => None,
+            };
+            stats.total.add(finish, m.moves);
+            for p in m.players.iter().filter(|p| *p != me) {
+                stats
+                    .opponents
+                    .entry(p.clone())
+                    .or_default()
+                    .add(finish, m.moves);
+            }
+            if let Some(name) = opening(m, seat, registry) {
+                *openings.entry(name).or_default() += 1;
+            }
+            let Some(finish) = finish else {
+                continue;
+            };
+            stats.streak = match stats.streak {
+                Some(s) if s.finish == finish => Some(Streak {
+                    finish,
+                    len: s.len + 1,
+                }),
+                _ => Some(Streak { finish, len: 1 }),
+            };
+            current_wins = if finish == Finish::Won {
+                current_wins + 1
+            } else {
+                0
+            };
+            stats.longest_win_streak = stats.longest_win_streak.max(current_wins);
+        }
+        let mut openings: Vec<_> = openings.into_iter().collect();
+        openings.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        openings.truncate(TOP_OPENINGS);
+        stats.openings = openings;
+        stats
+    }
+}
+
+/// Name of our first move in `m`, if it was logged and the game names it.
+fn opening(m: &MatchRecord, seat: usize, registry: &GameRegistry) -> Option<String> {
+    let first = m
+        .replay
+        .as_ref()?
+        .moves
+        .iter()
+        .find(|mv| mv.seat == seat && mv.change.is_none())?;
+    registry
+        .create(&m.game, m.players.len())
+        .ok()?
+        .describe_move(&first.mv)
+}
@@ -0,0 +1,243 @@
+//! A shuffled deck of cards that no single player controls.
+//!
+//! Mental poker with commutative encryption: a card is a point of the
+//! Ristretto group, and "encrypting" it with a key is multiplying it by a
+//! secret scalar. Multiplications commute, so the players can lock a card
+//! in any order and unlock it in any other.
+//!
+//! 1. **Shuffle.** In seat order, every player locks all cards with one key
+//!    of its own and shuffles them ([`GameBody::DeckPass`]). After the last
+//!    seat nobody knows which card lies where.
+//! 2. **Lock.** In seat order again, every player takes its shuffle key off
+//!    and locks each position with a key of its own for that position.
+//! 3. **Deal.** A card at a position is dealt to a player when everybody
+//!    else publishes its key for that position ([`GameBody::DeckKeys`]);
+//!    only the owner also holds the last key and can [`Deck::open`] it. It
+//!    is turned face up when the owner publishes its key as well.
+//!
+//! The passes carry no proofs: a player who locks or shuffles wrongly is
+//! only caught when a card fails to open, and [`Deck::open`] reports that
+//! as an error.
+
+use anyhow::{Result, bail};
+use curve25519_dalek::{
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+};
+use sha2::{Digest, Sha512};
+use std::collections::BTreeMap;
+
+use crate::{
+    game::{GameBody, Seat},
+    t,
+};
+
+/// Cards in a deck: four suits of thirteen ranks.
+pub const DECK_SIZE: usize = 52;
+
+const RANKS: &[u8; 13] = b"23456789TJQKA";
+const SUITS: &[u8; 4] = b"cdhs";
+
+/// A card, `0..DECK_SIZE`: suit `card / 13`, rank `card % 13`.
+pub type Card = u8;
+
+/// Short name of a card, rank then suit (`"Ts"` is the ten of spades).
+pub fn card_name(card: Card) -> String {
+    let (suit, rank) = (card as usize / 13, card as usize % 13);
+    format!("{}{}", RANKS[rank] as char, SUITS[suit] as char)
+}
+
+/// The card called `name`, see [`card_name`].
+pub fn parse_card(name: &str) -> Option<Card> {
+    let &[rank, suit] = name.as_bytes() else {
+        return None;
+    };
+    let rank = RANKS.iter().position(|r| *r == rank.to_ascii_uppercase())?;
+    let suit = SUITS.iter().position(|s| *s == suit.to_ascii_lowercase())?;
+    Some((suit * 13 + rank) as Card)
+}
+
+/// The point of an unlocked card. Hashed, so nobody knows how the points of
+/// two cards relate.
+fn card_point(card: Card) -> RistrettoPoint {
+    let mut bytes = [0; 64];
+    bytes.copy_from_slice(&Sha512::digest(format!("p2p-games/deck/card/{card}")));
+    RistrettoPoint::from_uniform_bytes(&bytes)
+}
+
+fn fresh_key() -> Scalar {
+    let mut bytes = [0; 64];
+    for chunk in bytes.chunks_mut(16) {
+        chunk.copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+    }
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+fn encode_point(p: &RistrettoPoint) -> String {
+    hex::encode(p.compress().as_bytes())
+}
+
+fn decode_point(s: &str) -> Option<RistrettoPoint> {
+    CompressedRistretto::from_slice(&hex::decode(s).ok()?)
+        .ok()?
+        .decompress()
+}
+
+fn decode_key(s: &str) -> Option<Scalar> {
+    let bytes: [u8; 32] = hex::decode(s).ok()?.try_into().ok()?;
+    Option::from(Scalar::from_canonical_bytes(bytes))
+}
+
+/// One deck of a match, seen from one of its players.
+pub struct Deck {
+    game_id: String,
+    name: String,
+    players: Vec<String>,
+    seat: Seat,
+    shuffle_key: Scalar,
+    /// Our key of each position, after the lock passes.
+    card_keys: Vec<Scalar>,
+    /// The deck as the last pass left it.
+    cards: Vec<RistrettoPoint>,
+    /// Passes applied: shuffle passes first, one per seat, then lock passes.
+    passes: usize,
+    /// Position → seat → published key.
+    keys: BTreeMap<usize, BTreeMap<Seat, Scalar>>,
+}
+
+impl Deck {
+    /// Deck `name` of `game_id` among `players` (peer ids in seat order).
+    pub fn new(game_id: &str, name: &str, players: &[String], me: &str) -> Result<Self> {
+        let Some(seat) = players.iter().position(|p| p == me) else {
+            bail!(t!("deck.not_seated"));
+        };
+        Ok(Self {
+            game_id: game_id.to_string(),
+            name: name.to_string(),
+            players: players.to_vec(),
+            seat,
+            shuffle_key: fresh_key(),
+            card_keys: (0..DECK_SIZE).map(|_| fresh_key()).collect(),
+            cards: (0..DECK_SIZE as Card).map(card_point).collect(),
+            passes: 0,
+            keys: BTreeMap::new(),
+        })
+    }
+
+    /// Whether both rounds are done and cards can be dealt.
+    pub fn ready(&self) -> bool {
+        self.passes == 2 * self.players.len()
+    }
+
+    fn our_pass(&self) -> bool {
+        !self.ready() && self.passes % self.players.len() == self.seat
+    }
+
+    /// The first pass, if it is ours; the first seat sends it to begin.
+    pub fn start(&mut self) -> Option<GameBody> {
+        (self.passes == 0 && self.our_pass()).then(|| self.pass())
+    }
+
+    /// Apply our pass to the deck and describe it for the others.
+    fn pass(&mut self) -> GameBody {
+        if self.passes < self.players.len() {
+            for c in &mut self.cards {
+                *c *= self.shuffle_key;
+            }
+            for i in (1..DECK_SIZE).rev() {
+                let j = (uuid::Uuid::new_v4().as_u64_pair().0 % (i as u64 + 1)) as usize;
+                self.cards.swap(i, j);
+            }
+        } else {
+            let unlock = self.shuffle_key.invert();
+            for (c, key) in self.cards.iter_mut().zip(&self.card_keys) {
+                *c *= unlock * key;
+            }
+        }
+        self.passes += 1;
+        GameBody::DeckPass {
+            game_id: self.game_id.clone(),
+            deck: self.name.clone(),
+            pass: self.passes - 1,
+            cards: self.cards.iter().map(encode_point).collect(),
+        }
+    }
+
+    /// Take in `body` from `sender`. Returns our next pass when it is our
+    /// turn; fails on a malformed pass.
+    pub fn handle(&mut self, sender: &str, body: &GameBody) -> Result<Option<GameBody>> {
+        let Some(from) = self.players.iter().position(|p| p == sender) else {
+            return Ok(None);
+        };
+        match body {
+            GameBody::DeckPass {
+                game_id,
+                deck,
+                pass,
+                cards,
+            } if *game_id == self.game_id
+                && *deck == self.name
+                && *pass == self.passes
+                && from == pass % self.players.len()
+                && from != self.seat =>
+            {
+                let cards: Option<Vec<_>> = cards.iter().map(|c| decode_point(c)).collect();
+                let Some(cards) = cards.filter(|c| c.len() == DECK_SIZE) else {
+                    bail!(t!("deck.bad_pass", player = from + 1));
+                };
+                self.cards = cards;
+                self.passes += 1;
+                Ok(self.our_pass().then(|| self.pass()))
+            }
+            GameBody::DeckKeys {
+                game_id,
+                deck,
+                keys,
+            } if *game_id == self.game_id && *deck == self.name && from != self.seat => {
+                for (pos, key) in keys {
+                    let Some(key) = decode_key(key).filter(|_| *pos < DECK_SIZE) else {
+                        bail!(t!("deck.bad_pass", player = from + 1));
+                    };
+                    self.keys.entry(*pos).or_default().insert(from, key);
+                }
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Our keys of `positions`. Sent by everybody but the owner, they deal
+    /// those cards to the owner; sent by the owner, they turn them face up.
+    pub fn keys(&self, positions: &[usize]) -> GameBody {
+        GameBody::DeckKeys {
+            game_id: self.game_id.clone(),
+            deck: self.name.clone(),
+            keys: positions
+                .iter()
+                .filter(|p| **p < DECK_SIZE)
+                .map(|p| (*p, hex::encode(self.card_keys[*p].as_bytes())))
+                .collect(),
+        }
+    }
+
+    /// The card at `pos` once every other player published its key for it:
+    /// a card dealt to us, or one face up. `None` while keys are missing,
+    /// an error when the keys do not open a card.
+    pub fn open(&self, pos: usize) -> Result<Option<Card>> {
+        if !self.ready() || pos >= DECK_SIZE {
+            return Ok(None);
+        }
+        let mut unlock = self.card_keys[pos].invert();
+        for seat in (0..self.players.len()).filter(|s| *s != self.seat) {
+            let Some(key) = self.keys.get(&pos).and_then(|k| k.get(&seat)) else {
+                return Ok(None);
+            };
+            unlock *= key.invert();
+        }
+        let point = self.cards[pos] * unlock;
+        match (0..DECK_SIZE as Card).find(|c| card_point(*c) == point) {
+            Some(card) => Ok(Some(card)),
+            None => bail!(t!("deck.corrupt", position = pos)),
+        }
+    }
+}
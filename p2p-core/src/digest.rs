@@ -0,0 +1,174 @@
+//! Cheap divergence checks for tables every peer keeps.
+//!
+//! Peers learn the room list and who owns which nickname from gossip, and
+//! miss whatever was published while they were away. Instead of sending
+//! whole tables now and then, front ends periodically publish a
+//! [`TableDigest`] of each table on the discovery topic
+//! ([`DiscoveryBody::Digest`]): the entries are spread over [`BUCKETS`]
+//! buckets by a hash of their key, and the digest carries one short hash per
+//! bucket. A peer whose own digest differs asks the sender for the buckets
+//! that differ only ([`DiscoveryBody::SyncReq`]), and the sender answers
+//! with the entries in them, in the messages that carry them anyway: signed
+//! name claims on the name registry topic, a room list for rooms.
+//!
+//! Both sides hold back for [`SYNC_COOLDOWN`] per peer and table, so a
+//! table that keeps differing (a room about to expire on one side) costs a
+//! request now and then, not one per digest.
+//!
+//! Published ratings are left out: one only counts with the attestations
+//! behind it, which only its owner sends (`rating <game> --publish`).
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+/// Buckets per table; a digest is about this many short hashes.
+pub const BUCKETS: usize = 16;
+
+/// How long we wait before asking the same peer for the same table again,
+/// or answering it again.
+pub const SYNC_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// `[sync]` section of `config.toml`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Seconds between digests of our tables; 0 turns digests off.
+    pub digest_every_secs: u64,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            digest_every_secs: 120,
+        }
+    }
+}
+
+impl SyncConfig {
+    pub fn digest_every(&self) -> Option<Duration> {
+        (self.digest_every_secs > 0).then(|| Duration::from_secs(self.digest_every_secs))
+    }
+}
+
+/// The tables peers compare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncTable {
+    /// Nickname owners, see [`crate::registry::ClaimCache`].
+    Names,
+    /// Announced rooms, see [`crate::discovery::RoomCache`].
+    Rooms,
+}
+
+/// A table that can be digested.
+pub trait Digestible {
+    /// `(key, value)` of every entry; peers that agree on an entry give the
+    /// same value for it, so nothing seen only locally (like when it was
+    /// last heard of) belongs in it.
+    fn entries(&self) -> Vec<(String, String)>;
+
+    fn digest(&self) -> TableDigest {
+        TableDigest::of(self.entries())
+    }
+}
+
+/// The bucket of the entry with `key`.
+pub fn bucket_of(key: &str) -> u8 {
+    (Sha256::digest(key.as_bytes())[0] as usize % BUCKETS) as u8
+}
+
+/// Hashes of a table's buckets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableDigest {
+    /// Hash of the bucket hashes; equal tables have equal roots.
+    pub root: String,
+    /// One hash per bucket, [`BUCKETS`] of them.
+    pub buckets: Vec<String>,
+}
+
+impl TableDigest {
+    pub fn of(entries: Vec<(String, String)>) -> Self {
+        let mut sorted: Vec<Vec<(String, String)>> = vec![Vec::new(); BUCKETS];
+        for (key, value) in entries {
+            sorted[bucket_of(&key) as usize].push((key, value));
+        }
+        let buckets: Vec<String> = sorted
+            .into_iter()
+            .map(|mut bucket| {
+                bucket.sort();
+                let mut hash = Sha256::new();
+                for (key, value) in bucket {
+                    hash.update(key.as_bytes());
+                    hash.update([0]);
+                    hash.update(value.as_bytes());
+                    hash.update([b'\n']);
+                }
+                hex::encode(&hash.finalize()[..8])
+            })
+            .collect();
+        let root = hex::encode(&Sha256::digest(buckets.concat().as_bytes())[..8]);
+        Self { root, buckets }
+    }
+
+    /// Buckets in which `other` differs from this digest; all of them if
+    /// `other` is malformed.
+    pub fn diverging(&self, other: &TableDigest) -> Vec<u8> {
+        if self.root == other.root && self.buckets == other.buckets {
+            return Vec::new();
+        }
+        if other.buckets.len() != BUCKETS {
+            return (0..BUCKETS as u8).collect();
+        }
+        (0..BUCKETS)
+            .filter(|i| self.buckets[*i] != other.buckets[*i])
+            .map(|i| i as u8)
+            .collect()
+    }
+}
+
+/// Who we asked and answered lately, per peer and table.
+#[derive(Debug, Default)]
+pub struct DigestSync {
+    asked: BTreeMap<(String, SyncTable), Instant>,
+    answered: BTreeMap<(String, SyncTable), Instant>,
+}
+
+fn due(
+    times: &mut BTreeMap<(String, SyncTable), Instant>,
+    peer: &str,
+    table: SyncTable,
+    now: Instant,
+) -> bool {
+    times.retain(|_, t| now.duration_since(*t) < SYNC_COOLDOWN);
+    let key = (peer.to_string(), table);
+    if times.contains_key(&key) {
+        return false;
+    }
+    times.insert(key, now);
+    true
+}
+
+impl DigestSync {
+    /// `peer` published `theirs` of a table we hold as `ours`; returns the
+    /// buckets to ask it for, if any and not asked lately.
+    pub fn on_digest(
+        &mut self,
+        peer: &str,
+        table: SyncTable,
+        theirs: &TableDigest,
+        ours: &TableDigest,
+        now: Instant,
+    ) -> Option<Vec<u8>> {
+        let buckets = ours.diverging(theirs);
+        (!buckets.is_empty() && due(&mut self.asked, peer, table, now)).then_some(buckets)
+    }
+
+    /// `peer` asked us for entries of `table`; returns whether to answer.
+    pub fn on_request(&mut self, peer: &str, table: SyncTable, now: Instant) -> bool {
+        due(&mut self.answered, peer, table, now)
+    }
+}
@@ -0,0 +1,208 @@
+//! Directory of peers observed on the network.
+//!
+//! Every peer we hear from — chatting, claiming a nickname, announcing
+//! presence or hosting a public room — is recorded in
+//! `<data dir>/p2p-games/peers.json` with its nickname, when it was last
+//! active and the room it was last seen in. There is no membership list in a
+//! gossip network, so the `who` command derives an approximate online list
+//! from this: peers active within [`ONLINE_WINDOW_MS`] that did not say
+//! goodbye.
+//!
+//! The directory doubles as an address book of nickname keys: the peer id
+//! (public key) first seen claiming a nickname is pinned, and a later claim
+//! of that nickname by another key is reported as a possible impersonation
+//! until the user accepts the new key with `trust`.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::PathBuf,
+};
+
+use crate::{
+    protocol::{DiscoveryBody, RoomSummary, now_ms},
+    session::data_dir,
+};
+
+/// Peers active more recently than this count as online.
+pub const ONLINE_WINDOW_MS: u64 = 10 * 60_000;
+
+/// Entries not seen for this long are dropped on save.
+const FORGET_AFTER_MS: u64 = 30 * 86_400_000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeerEntry {
+    /// Last nickname the peer claimed.
+    pub nickname: Option<String>,
+    /// Last activity (unix millis).
+    pub last_seen: u64,
+    /// Room the peer was last active in: one of ours, or a public room it
+    /// hosts.
+    pub room: Option<String>,
+    /// Cleared when the peer announces it goes offline.
+    pub online: bool,
+    /// When we first heard of the peer (unix millis, our clock); 0 for
+    /// entries from before this was kept.
+    #[serde(default)]
+    pub first_seen: u64,
+    /// Proof of work the peer sent for its key (see [`crate::sybil`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pow: Option<u64>,
+}
+
+impl PeerEntry {
+    pub fn is_online(&self, now: u64) -> bool {
+        self.online && now.saturating_sub(self.last_seen) < ONLINE_WINDOW_MS
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeerDirectory {
+    pub peers: BTreeMap<String, PeerEntry>,
+    /// Lowercase nickname → peer id first seen claiming it. Pins outlive
+    /// forgotten peers, so impersonation after claim expiry is noticed.
+    #[serde(default)]
+    pub pins: BTreeMap<String, String>,
+    /// Mismatches (nickname, peer id) already reported by this process.
+    #[serde(skip)]
+    warned: BTreeSet<(String, String)>,
+}
+
+impl PeerDirectory {
+    fn storage_path() -> PathBuf {
+        data_dir().join("peers.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&mut self) -> std::io::Result<()> {
+        let now = now_ms();
+        self.peers
+            .retain(|_, p| now.saturating_sub(p.last_seen) < FORGET_AFTER_MS);
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// `peer_id` was active just now, in `room` if given.
+    pub fn seen(&mut self, peer_id: &str, room: Option<&str>) -> &mut PeerEntry {
+        self.seen_at(peer_id, room, now_ms())
+    }
+
+    /// Like [`seen`](Self::seen) for activity reported later (e.g. a cached
+    /// room list); older reports don't override newer ones.
+    pub fn seen_at(&mut self, peer_id: &str, room: Option<&str>, at: u64) -> &mut PeerEntry {
+        let entry = self.peers.entry(peer_id.to_string()).or_default();
+        // Reports can be dated back at will; only our own clock says since
+        // when we know a peer.
+        if entry.first_seen == 0 {
+            entry.first_seen = now_ms();
+        }
+        if at >= entry.last_seen {
+            entry.last_seen = at;
+            entry.online = true;
+            if let Some(room) = room {
+                entry.room = Some(room.to_string());
+            }
+        }
+        entry
+    }
+
+    /// Record a discovery message sent by `sender_id`.
+    pub fn apply(&mut self, sender_id: &str, body: &DiscoveryBody) {
+        match body {
+            DiscoveryBody::Presence { peer_id, online } if peer_id == sender_id => {
+                if *online {
+                    self.seen(peer_id, None);
+                } else {
+                    self.left(peer_id);
+                }
+            }
+            DiscoveryBody::AnnounceRoom { host_id, title, .. } if host_id == sender_id => {
+                self.seen(host_id, Some(title));
+            }
+            DiscoveryBody::ListRoomsRes { rooms, .. } => self.hosts(rooms),
+            _ => {}
+        }
+    }
+
+    /// Remember the proof of work `peer_id` sent with a claim or seek.
+    pub fn proof(&mut self, peer_id: &str, nonce: Option<u64>) {
+        if let (Some(entry), Some(nonce)) = (self.peers.get_mut(peer_id), nonce) {
+            entry.pow = Some(nonce);
+        }
+    }
+
+    /// Hosts of public rooms are online in their room.
+    pub fn hosts(&mut self, rooms: &[RoomSummary]) {
+        for r in rooms {
+            self.seen_at(&r.host_id, Some(&r.title), r.last_seen);
+        }
+    }
+
+    /// Peer id last seen with `nickname` (case-insensitive).
+    pub fn find_nickname(&self, nickname: &str) -> Option<&str> {
+        self.peers
+            .iter()
+            .filter(|(_, p)| {
+                p.nickname
+                    .as_deref()
+                    .is_some_and(|n| n.eq_ignore_ascii_case(nickname))
+            })
+            .max_by_key(|(_, p)| p.last_seen)
+            .map(|(id, _)| id.as_str())
+    }
+
+    /// Record that `peer_id` claims `nickname`, pinning the nickname to it
+    /// if it is new. Returns the pinned peer id when the nickname is pinned
+    /// to another key; each such key is reported once per process.
+    pub fn set_nickname(&mut self, peer_id: &str, nickname: &str) -> Option<String> {
+        self.seen(peer_id, None).nickname = Some(nickname.to_string());
+        let key = nickname.to_lowercase();
+        let pinned = self
+            .pins
+            .entry(key.clone())
+            .or_insert_with(|| peer_id.to_string());
+        if pinned == peer_id || !self.warned.insert((key, peer_id.to_string())) {
+            return None;
+        }
+        Some(pinned.clone())
+    }
+
+    /// Re-pin `nickname` to the peer last seen with it; returns that peer.
+    pub fn trust(&mut self, nickname: &str) -> Option<String> {
+        let peer_id = self.find_nickname(nickname)?.to_string();
+        self.pins.insert(nickname.to_lowercase(), peer_id.clone());
+        Some(peer_id)
+    }
+
+    /// The peer said goodbye.
+    pub fn left(&mut self, peer_id: &str) {
+        if let Some(entry) = self.peers.get_mut(peer_id) {
+            entry.online = false;
+            entry.room = None;
+        }
+    }
+
+    /// Peers that currently count as online, most recently active first.
+    pub fn online(&self) -> Vec<(&String, &PeerEntry)> {
+        let now = now_ms();
+        let mut online: Vec<_> = self
+            .peers
+            .iter()
+            .filter(|(_, p)| p.is_online(now))
+            .collect();
+        online.sort_by_key(|(_, p)| Reverse(p.last_seen));
+        online
+    }
+}
@@ -1,13 +1,30 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use tokio::time::{Duration, timeout};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::time::{Duration, Instant};
 
-use crate::protocol::{DiscoveryBody, Envelope, PROTOCOL_VER, RoomSummary, now_ms};
-use transport_iroh::transport_iroh::GossipTransport;
+use crate::digest::{Digestible, SyncTable, TableDigest, bucket_of};
+use crate::protocol::{
+    DiscoveryBody, Envelope, Kind, PROTOCOL_VER, RoomSummary, Scope, make_envelope, now_ms,
+};
+use crate::middleware::RecentIds;
+use crate::signing;
+use crate::timeouts::{Timeouts, collect, retry};
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle, TopicId};
 
 const ROOM_REGISTRY_TOPIC_NAME: &str = "p2p-room-registry";
-const DISCOVERY_TOPIC_NAME: &str = "p2p-discovery";
+pub const DISCOVERY_TOPIC_NAME: &str = "p2p-discovery";
+
+/// Room list requests answered per peer and [`QUOTA_WINDOW`].
+const REQUESTS_PER_PEER: usize = 5;
+/// Replies waiting for their delay at most, however many peers ask.
+const MAX_PENDING_REPLIES: usize = 16;
+const QUOTA_WINDOW: Duration = Duration::from_secs(10);
+/// Upper bound of the random delay before a room list reply.
+const MAX_REPLY_JITTER_MS: u64 = 400;
+/// Rooms not announced or listed for this long are dropped from listings;
+/// covers a couple of missed low-power re-announcements.
+pub const ROOM_TTL_MS: u64 = 5 * 60_000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoomClaim {
@@ -16,6 +33,9 @@ pub struct RoomClaim {
     pub owner_peer_id: String,
     pub since_ts: u64,
     pub room_id: String,
+    /// Owner gives the name up (room closed); older peers ignore this field.
+    #[serde(default)]
+    pub released: bool,
 }
 
 fn room_claim_wins(a_owner: &str, a_ts: u64, b_owner: &str, b_ts: u64) -> bool {
@@ -32,6 +52,16 @@ pub struct RoomTable {
 }
 impl RoomTable {
     pub fn apply_claim(&mut self, c: &RoomClaim) {
+        if c.released {
+            if self
+                .names
+                .get(&c.name_lower)
+                .is_some_and(|(owner, ..)| *owner == c.owner_peer_id)
+            {
+                self.names.remove(&c.name_lower);
+            }
+            return;
+        }
         match self.names.get(&c.name_lower) {
             None => {
                 self.names.insert(
@@ -81,23 +111,222 @@ impl RoomTable {
     }
 }
 
+/// Rooms learned from announcements and list responses, newest data wins.
+#[derive(Default)]
+pub struct RoomCache {
+    rooms: BTreeMap<String, RoomSummary>,
+}
+
+impl RoomCache {
+    /// Hosts repeat their announcements, so a room is seen as of the latest
+    /// one (`ts`), not when it was created.
+    pub fn apply(&mut self, env: &Envelope<DiscoveryBody>) {
+        match &env.body {
+            DiscoveryBody::AnnounceRoom {
+                room_id,
+                title,
+                host_id,
+                max_players,
+                current_players,
+                language,
+                ticket,
+                game,
+                ..
+            } => self.insert(RoomSummary {
+                room_id: room_id.clone(),
+                title: title.clone(),
+                host_id: host_id.clone(),
+                last_seen: env.ts,
+                ticket: ticket.clone(),
+                current_players: *current_players,
+                max_players: *max_players,
+                game: game.clone(),
+                language: language.clone(),
+            }),
+            DiscoveryBody::ListRoomsRes { rooms, .. } => {
+                for r in rooms {
+                    self.insert(r.clone());
+                }
+            }
+            DiscoveryBody::CloseRoom { room_id } => {
+                self.rooms.remove(room_id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Keep the newest summary, but do not lose details an announcement lacks.
+    pub fn insert(&mut self, mut room: RoomSummary) {
+        if let Some(old) = self.rooms.get(&room.room_id) {
+            if old.last_seen > room.last_seen {
+                return;
+            }
+            room.ticket = room.ticket.or(old.ticket.clone());
+            room.game = room.game.or(old.game.clone());
+            room.current_players = room.current_players.or(old.current_players);
+            room.max_players = room.max_players.or(old.max_players);
+            room.language = room.language.or(old.language.clone());
+        }
+        self.rooms.insert(room.room_id.clone(), room);
+    }
+
+    /// Drop rooms not seen for `max_age_ms`, e.g. [`ROOM_TTL_MS`].
+    pub fn prune(&mut self, max_age_ms: u64) {
+        let oldest = now_ms().saturating_sub(max_age_ms);
+        self.rooms.retain(|_, r| r.last_seen >= oldest);
+    }
+
+    /// Keep only the `max` rooms seen most recently.
+    pub fn keep_newest(&mut self, max: usize) {
+        let mut by_age: Vec<(u64, String)> =
+            self.rooms.values().map(|r| (r.last_seen, r.room_id.clone())).collect();
+        by_age.sort_unstable();
+        let excess = by_age.len().saturating_sub(max);
+        for (_, room_id) in &by_age[..excess] {
+            self.rooms.remove(room_id);
+        }
+    }
+
+    /// The room called `title`, if it was announced.
+    pub fn by_title(&self, title: &str) -> Option<&RoomSummary> {
+        self.rooms.values().find(|r| r.title == title)
+    }
+
+    /// Rooms ordered by title.
+    pub fn list(&self) -> Vec<RoomSummary> {
+        let mut rooms: Vec<_> = self.rooms.values().cloned().collect();
+        rooms.sort_by_key(|r| r.title.to_lowercase());
+        rooms
+    }
+
+    /// Rooms in digest `buckets`, to answer a [`DiscoveryBody::SyncReq`].
+    pub fn in_buckets(&self, buckets: &[u8]) -> Vec<RoomSummary> {
+        self.rooms
+            .values()
+            .filter(|r| buckets.contains(&bucket_of(&r.room_id)))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Digestible for RoomCache {
+    fn entries(&self) -> Vec<(String, String)> {
+        self.rooms
+            .values()
+            .map(|r| (r.room_id.clone(), format!("{}\0{}", r.host_id, r.title)))
+            .collect()
+    }
+}
+
+/// Keeps the discovery responder cheap when many peers share the topic.
+///
+/// Each signing key gets a small request quota; unsigned requests carry no
+/// identity we can trust, so they all share one. Replies wait a random delay
+/// so the hosts answering one request do not all publish at once, and rooms
+/// that another peer already listed in an answer to the same request during
+/// that delay are left out; if nothing is left, there is no reply at all. At
+/// most [`MAX_PENDING_REPLIES`] replies wait at a time.
+#[derive(Default)]
+pub struct ResponderShield {
+    requests: BTreeMap<String, VecDeque<Instant>>,
+    pending: BTreeMap<String, PendingReply>,
+}
+
+struct PendingReply {
+    due: Instant,
+    /// Room ids someone else already answered with.
+    covered: BTreeSet<String>,
+}
+
+impl ResponderShield {
+    /// Schedule a reply to the request `env` (checked by [`signing::open`]),
+    /// unless its key is over its quota or too many replies wait already;
+    /// returns whether a reply was scheduled.
+    pub fn request<T>(&mut self, env: &Envelope<T>, now: Instant) -> bool {
+        // A verified key, or the bucket of all unsigned requests.
+        let key = match (&env.sig, &env.pubkey) {
+            (Some(_), Some(pubkey)) => pubkey.as_str(),
+            _ => "",
+        };
+        if self.pending.len() >= MAX_PENDING_REPLIES || self.pending.contains_key(&env.msg_id) {
+            return false;
+        }
+        let times = self.requests.entry(key.to_string()).or_default();
+        while times.front().is_some_and(|t| now.duration_since(*t) >= QUOTA_WINDOW) {
+            times.pop_front();
+        }
+        if times.len() >= REQUESTS_PER_PEER {
+            return false;
+        }
+        times.push_back(now);
+        let jitter = (uuid::Uuid::new_v4().as_u128() % u128::from(MAX_REPLY_JITTER_MS)) as u64;
+        let reply = PendingReply {
+            due: now + Duration::from_millis(jitter),
+            covered: BTreeSet::new(),
+        };
+        self.pending.insert(env.msg_id.clone(), reply);
+        true
+    }
+
+    /// Another responder answered `req_id` with `rooms`.
+    pub fn answered(&mut self, req_id: &str, rooms: &[RoomSummary]) {
+        if let Some(p) = self.pending.get_mut(req_id) {
+            p.covered.extend(rooms.iter().map(|r| r.room_id.clone()));
+        }
+    }
+
+    /// When the next scheduled reply is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.values().map(|p| p.due).min()
+    }
+
+    /// Replies due at `now`, each with the `rooms` nobody listed yet.
+    pub fn due(&mut self, now: Instant, rooms: &[RoomSummary]) -> Vec<(String, Vec<RoomSummary>)> {
+        let (ready, waiting): (BTreeMap<_, _>, BTreeMap<_, _>) =
+            std::mem::take(&mut self.pending).into_iter().partition(|(_, p)| p.due <= now);
+        self.pending = waiting;
+        self.requests
+            .retain(|_, t| t.back().is_some_and(|t| now.duration_since(*t) < QUOTA_WINDOW));
+        ready
+            .into_iter()
+            .map(|(req_id, p)| {
+                let rooms = rooms.iter().filter(|r| !p.covered.contains(&r.room_id)).cloned();
+                (req_id, rooms.collect::<Vec<_>>())
+            })
+            .filter(|(_, rooms)| !rooms.is_empty())
+            .collect()
+    }
+}
+
 pub struct Discovery<'a> {
     transport: &'a dyn GossipTransport,
+    timeouts: Timeouts,
 }
 
 impl<'a> Discovery<'a> {
-    pub fn new(transport: &'a dyn GossipTransport) -> Self {
-        Self { transport }
+    pub fn new(transport: &'a dyn GossipTransport, timeouts: Timeouts) -> Self {
+        Self {
+            transport,
+            timeouts,
+        }
+    }
+
+    /// Join `topic` and publish `bytes`, retrying per the retry policy.
+    async fn send(&self, topic: TopicId, bytes: &[u8]) -> Result<Box<dyn TopicHandle>> {
+        retry(&self.timeouts.retry, || async move {
+            let th = self.transport.join_topic(topic).await?;
+            th.publish(bytes).await?;
+            Ok(th)
+        })
+        .await
     }
 
     pub async fn claim_room_name(
         &self,
         desired_name: &str,
         my_peer_id: &str,
-        wait_ms: u64,
     ) -> Result<(String, bool)> {
         let reg_topic = self.transport.topic_from_name(ROOM_REGISTRY_TOPIC_NAME);
-        let mut th = self.transport.join_topic(reg_topic).await?;
 
         let room_id = format!("lobby-{}", uuid::Uuid::new_v4());
         let claim = RoomClaim {
@@ -106,6 +335,7 @@ impl<'a> Discovery<'a> {
             owner_peer_id: my_peer_id.to_string(),
             since_ts: now_ms(),
             room_id: room_id.clone(),
+            released: false,
         };
 
         let env = Envelope {
@@ -116,43 +346,52 @@ impl<'a> Discovery<'a> {
             sender_id: my_peer_id.to_string(),
             msg_id: uuid::Uuid::new_v4().to_string(),
             ts: claim.since_ts,
+            hlc: None,
             body: claim.clone(),
+            sig: None,
+            pubkey: None,
         };
-        th.publish(&serde_json::to_vec(&env)?).await?;
+        let mut th = self.send(reg_topic, &signing::to_signed_vec(&env)).await?;
 
         let mut table = RoomTable::default();
         table.apply_claim(&claim);
 
-        let _ = timeout(Duration::from_millis(wait_ms), async {
-            loop {
-                match th.next().await {
-                    Ok(b) => {
-                        if let Ok(env) = serde_json::from_slice::<Envelope<RoomClaim>>(&b) {
-                            table.apply_claim(&env.body);
-                        }
-                    }
-                    Err(_) => break,
-                }
-            }
+        let mut recent = RecentIds::default();
+        let window = self.timeouts.window(self.timeouts.claim_wait(), &*th);
+        collect(&mut th, window, |b| {
+            let Some(env) = signing::open::<RoomClaim>(b).filter(|e| recent.insert(&e.msg_id))
+            else {
+                return false;
+            };
+            table.apply_claim(&env.body);
+            true
         })
         .await;
 
-        if let Some((owner, _ts, _name, rid)) = table.owner_of(&desired_name.to_lowercase()) {
-            if owner == my_peer_id {
-                return Ok((rid.clone(), true));
-            }
+        if let Some((owner, _ts, _name, rid)) = table.owner_of(&desired_name.to_lowercase())
+            && owner == my_peer_id
+        {
+            return Ok((rid.clone(), true));
         }
         Ok((room_id, false))
     }
 
-    pub async fn announce_room(&self, room_id: &str, title: &str, host_id: &str) -> Result<()> {
+    /// Announce a room we host, opened at `created_at`. Hosts repeat this
+    /// every [`Intervals::reannounce`](crate::power::Intervals) so listings
+    /// keep the room until [`ROOM_TTL_MS`] after the host is gone.
+    pub async fn announce_room(&self, room: &RoomSummary, created_at: u64) -> Result<()> {
         let topic = self.transport.topic_from_name(DISCOVERY_TOPIC_NAME);
-        let th = self.transport.join_topic(topic).await?;
+        let host_id = &room.host_id;
         let body = DiscoveryBody::AnnounceRoom {
-            room_id: room_id.to_string(),
-            title: title.to_string(),
-            host_id: host_id.to_string(),
-            created_at: now_ms(),
+            room_id: room.room_id.clone(),
+            title: room.title.clone(),
+            host_id: host_id.clone(),
+            created_at,
+            max_players: room.max_players,
+            current_players: room.current_players,
+            language: room.language.clone(),
+            ticket: room.ticket.clone(),
+            game: room.game.clone(),
         };
         let env = Envelope {
             ver: PROTOCOL_VER,
@@ -162,47 +401,167 @@ impl<'a> Discovery<'a> {
             sender_id: host_id.to_string(),
             msg_id: uuid::Uuid::new_v4().to_string(),
             ts: now_ms(),
+            hlc: None,
             body,
+            sig: None,
+            pubkey: None,
         };
-        th.publish(&serde_json::to_vec(&env)?).await?;
+        self.send(topic, &signing::to_signed_vec(&env)).await?;
         Ok(())
     }
 
-    pub async fn list_rooms(&self, wait_ms: u64) -> Result<Vec<RoomSummary>> {
+    /// Give up a room name claimed with [`Self::claim_room_name`].
+    pub async fn release_room_name(
+        &self,
+        name: &str,
+        room_id: &str,
+        my_peer_id: &str,
+    ) -> Result<()> {
+        let reg_topic = self.transport.topic_from_name(ROOM_REGISTRY_TOPIC_NAME);
+        let claim = RoomClaim {
+            name_lower: name.to_lowercase(),
+            name: name.to_string(),
+            owner_peer_id: my_peer_id.to_string(),
+            since_ts: now_ms(),
+            room_id: room_id.to_string(),
+            released: true,
+        };
+        let env = make_envelope(
+            Kind::Discovery,
+            Scope::Global,
+            None,
+            my_peer_id.to_string(),
+            now_ms(),
+            claim,
+        );
+        self.send(reg_topic, &signing::to_signed_vec(&env)).await?;
+        Ok(())
+    }
+
+    /// Tell room browsers that a hosted room is gone.
+    pub async fn close_room(&self, room_id: &str, host_id: &str) -> Result<()> {
+        let body = DiscoveryBody::CloseRoom {
+            room_id: room_id.to_string(),
+        };
+        self.publish(host_id, body).await
+    }
+
+    /// Announce that this peer is online or going offline.
+    pub async fn announce_presence(&self, peer_id: &str, online: bool) -> Result<()> {
+        let body = DiscoveryBody::Presence {
+            peer_id: peer_id.to_string(),
+            online,
+        };
+        self.publish(peer_id, body).await
+    }
+
+    /// Vouch for `peer_id`, or take the vouch back.
+    pub async fn vouch(&self, sender_id: &str, peer_id: &str, revoke: bool) -> Result<()> {
+        let body = DiscoveryBody::Vouch {
+            peer_id: peer_id.to_string(),
+            revoke,
+        };
+        self.publish(sender_id, body).await
+    }
+
+    /// Publish our digest of `table`.
+    pub async fn digest(
+        &self,
+        sender_id: &str,
+        table: SyncTable,
+        digest: TableDigest,
+    ) -> Result<()> {
+        self.publish(sender_id, DiscoveryBody::Digest { table, digest }).await
+    }
+
+    /// Ask `peer_id` for its entries of `table` in `buckets`.
+    pub async fn sync_request(
+        &self,
+        sender_id: &str,
+        peer_id: &str,
+        table: SyncTable,
+        buckets: Vec<u8>,
+    ) -> Result<()> {
+        let body = DiscoveryBody::SyncReq {
+            peer_id: peer_id.to_string(),
+            table,
+            buckets,
+        };
+        self.publish(sender_id, body).await
+    }
+
+    /// Answer sync request `req_id` with the `rooms` it asked for.
+    pub async fn sync_rooms(
+        &self,
+        sender_id: &str,
+        req_id: &str,
+        rooms: Vec<RoomSummary>,
+    ) -> Result<()> {
+        let body = DiscoveryBody::ListRoomsRes {
+            rooms,
+            req_id: Some(req_id.to_string()),
+        };
+        self.publish(sender_id, body).await
+    }
+
+    /// Publish our rating summary, see [`crate::rating::RatingBook::summary`].
+    pub async fn rating(&self, sender_id: &str, parts: Vec<DiscoveryBody>) -> Result<()> {
+        for body in parts {
+            self.publish(sender_id, body).await?;
+        }
+        Ok(())
+    }
+
+    async fn publish(&self, sender_id: &str, body: DiscoveryBody) -> Result<()> {
+        let topic = self.transport.topic_from_name(DISCOVERY_TOPIC_NAME);
+        let env = make_envelope(
+            Kind::Discovery,
+            Scope::Global,
+            None,
+            sender_id.to_string(),
+            now_ms(),
+            body,
+        );
+        self.send(topic, &signing::to_signed_vec(&env)).await?;
+        Ok(())
+    }
+
+    /// Our node id; requests and replies are signed with it so responders
+    /// can hold each peer to its quota.
+    fn node_id(&self) -> String {
+        self.transport.node_addr().node_id.to_string()
+    }
+
+    pub async fn list_rooms(&self) -> Result<Vec<RoomSummary>> {
         let topic = self.transport.topic_from_name(DISCOVERY_TOPIC_NAME);
-        let mut th = self.transport.join_topic(topic).await?;
 
         let req = DiscoveryBody::ListRoomsReq;
-        let env = Envelope {
-            ver: PROTOCOL_VER,
-            kind: crate::protocol::Kind::Discovery,
-            scope: crate::protocol::Scope::Global,
-            room_id: None,
-            sender_id: "client".into(),
-            msg_id: uuid::Uuid::new_v4().to_string(),
-            ts: now_ms(),
-            body: req,
-        };
-        th.publish(&serde_json::to_vec(&env)?).await?;
-
-        let mut out: Vec<RoomSummary> = Vec::new();
-        let _ = timeout(Duration::from_millis(wait_ms), async {
-            loop {
-                match th.next().await {
-                    Ok(b) => {
-                        if let Ok(env) = serde_json::from_slice::<Envelope<DiscoveryBody>>(&b) {
-                            if let DiscoveryBody::ListRoomsRes { rooms } = env.body {
-                                out.extend(rooms);
-                            }
-                        }
-                    }
-                    Err(_) => break,
-                }
-            }
+        let env = make_envelope(
+            Kind::Discovery,
+            Scope::Global,
+            None,
+            self.node_id(),
+            now_ms(),
+            req,
+        );
+        let mut th = self.send(topic, &signing::to_signed_vec(&env)).await?;
+
+        let mut cache = RoomCache::default();
+        let mut recent = RecentIds::default();
+        let window = self.timeouts.window(self.timeouts.list_rooms(), &*th);
+        collect(&mut th, window, |b| {
+            let Some(env) =
+                signing::open::<DiscoveryBody>(b).filter(|e| recent.insert(&e.msg_id))
+            else {
+                return false;
+            };
+            cache.apply(&env);
+            // Only room list answers say anything about how long to wait.
+            matches!(env.body, DiscoveryBody::ListRoomsRes { .. })
         })
         .await;
 
-        Ok(out)
+        Ok(cache.list())
     }
 
     pub async fn serve_discovery(
@@ -211,28 +570,51 @@ impl<'a> Discovery<'a> {
     ) -> Result<()> {
         let topic = self.transport.topic_from_name(DISCOVERY_TOPIC_NAME);
         let mut th = self.transport.join_topic(topic).await?;
+        let me = self.node_id();
+        let mut shield = ResponderShield::default();
+        // A repeated request must not count against its sender's quota twice.
+        let mut recent = RecentIds::default();
 
         loop {
-            let b = th.next().await?;
-            if let Ok(env) = serde_json::from_slice::<Envelope<DiscoveryBody>>(&b) {
-                match env.body {
-                    DiscoveryBody::ListRoomsReq => {
-                        let rooms = known_rooms();
-                        let res = DiscoveryBody::ListRoomsRes { rooms };
-                        let out = Envelope {
-                            ver: PROTOCOL_VER,
-                            kind: crate::protocol::Kind::Discovery,
-                            scope: crate::protocol::Scope::Global,
-                            room_id: None,
-                            sender_id: "server".into(),
-                            msg_id: uuid::Uuid::new_v4().to_string(),
-                            ts: now_ms(),
-                            body: res,
-                        };
-                        th.publish(&serde_json::to_vec(&out)?).await?;
+            // Nothing scheduled: wake up now and then anyway.
+            let wake = shield.next_due().unwrap_or_else(|| Instant::now() + QUOTA_WINDOW);
+            tokio::select! {
+                b = th.next() => {
+                    let Some(env) = signing::open::<DiscoveryBody>(&b?)
+                        .filter(|e| recent.insert(&e.msg_id))
+                    else {
+                        continue;
+                    };
+                    match env.body {
+                        DiscoveryBody::ListRoomsReq => {
+                            shield.request(&env, Instant::now());
+                        }
+                        DiscoveryBody::ListRoomsRes { rooms, req_id: Some(req_id) } => {
+                            shield.answered(&req_id, &rooms);
+                        }
+                        DiscoveryBody::ListRoomsRes { .. } => {}
+                        DiscoveryBody::AnnounceRoom { .. } => {}
+                        DiscoveryBody::CloseRoom { .. } => {}
+                        DiscoveryBody::Presence { .. } => {}
+                        DiscoveryBody::Vouch { .. } => {}
+                        DiscoveryBody::Rating { .. } => {}
+                        DiscoveryBody::Digest { .. } => {}
+                        DiscoveryBody::SyncReq { .. } => {}
+                    }
+                }
+                _ = tokio::time::sleep_until(wake.into()) => {
+                    for (req_id, rooms) in shield.due(Instant::now(), &known_rooms()) {
+                        let res = DiscoveryBody::ListRoomsRes { rooms, req_id: Some(req_id) };
+                        let out = make_envelope(
+                            Kind::Discovery,
+                            Scope::Global,
+                            None,
+                            me.clone(),
+                            now_ms(),
+                            res,
+                        );
+                        th.publish(&signing::to_signed_vec(&out)).await?;
                     }
-                    DiscoveryBody::AnnounceRoom { .. } => {}
-                    DiscoveryBody::ListRoomsRes { .. } => {}
                 }
             }
         }
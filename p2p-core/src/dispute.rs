@@ -0,0 +1,148 @@
+//! Disputed match results.
+//!
+//! For [`DISPUTE_WINDOW`] after a match ends, either player can flag its
+//! result (`dispute <reason>` at the game prompt). The flag goes to the room
+//! as a [`GameBody::Dispute`](crate::game::GameBody) with the player's
+//! [`Evidence`]: the moves as they logged them and the signed move envelopes
+//! they received. Both sides keep it in `<data dir>/p2p-games/disputes.json`.
+//!
+//! A disputed result does not count towards win records (see
+//! [`crate::summary`]) or ratings until it is revalidated: `debug replay` re-runs the
+//! current engine over our logged moves and the disputer's, and the result
+//! counts again when they lead to the recorded outcome.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fs, path::PathBuf, time::Duration};
+
+use crate::{
+    game::GameRegistry, matchlog::MatchRecord, protocol::now_ms, replay::Replay, session::data_dir,
+    validation,
+};
+
+/// How long after a match ends its result can be disputed.
+pub const DISPUTE_WINDOW: Duration = Duration::from_secs(60);
+
+/// What a dispute rests on, as the disputing player saw the match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Evidence {
+    /// Every move, unless a state sync skipped some.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay: Option<Replay>,
+    /// Move envelopes received from the other players, with their
+    /// signatures.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signed_moves: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeStatus {
+    /// Not revalidated yet; the result does not count.
+    Open,
+    /// The logged moves lead to the recorded result; it counts again.
+    Revalidated,
+    /// They do not; the result never counts.
+    Invalid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dispute {
+    /// Peer id of the player who flagged the result.
+    pub by: String,
+    pub reason: String,
+    /// Unix millis.
+    pub at: u64,
+    pub evidence: Evidence,
+    pub status: DisputeStatus,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DisputeBook {
+    /// Game id → dispute.
+    disputes: BTreeMap<String, Dispute>,
+}
+
+impl DisputeBook {
+    fn storage_path() -> PathBuf {
+        data_dir().join("disputes.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// `by` disputes the result of `game_id`; only the first dispute of a
+    /// match is kept. Returns whether it was new.
+    pub fn open(&mut self, game_id: &str, by: &str, reason: &str, evidence: Evidence) -> bool {
+        if self.disputes.contains_key(game_id) {
+            return false;
+        }
+        self.disputes.insert(
+            game_id.to_string(),
+            Dispute {
+                by: by.to_string(),
+                reason: reason.to_string(),
+                at: now_ms(),
+                evidence,
+                status: DisputeStatus::Open,
+            },
+        );
+        true
+    }
+
+    pub fn get(&self, game_id: &str) -> Option<&Dispute> {
+        self.disputes.get(game_id)
+    }
+
+    /// Whether the result of `game_id` counts towards win records.
+    pub fn counts(&self, game_id: &str) -> bool {
+        self.disputes
+            .get(game_id)
+            .is_none_or(|d| d.status == DisputeStatus::Revalidated)
+    }
+
+    /// Re-run the engine over the logged moves of `record` if its result is
+    /// disputed and still open. Our log must pass [`validation::validate`]
+    /// and the disputer's, when they sent one, must lead to the recorded
+    /// outcome; without our log the dispute stays open. Returns
+    /// the status, `None` when there was nothing to revalidate.
+    pub fn revalidate(
+        &mut self,
+        record: &MatchRecord,
+        registry: &GameRegistry,
+    ) -> Option<DisputeStatus> {
+        let d = self
+            .disputes
+            .get_mut(&record.game_id)
+            .filter(|d| d.status == DisputeStatus::Open)?;
+        if record.replay.is_none() {
+            return Some(DisputeStatus::Open);
+        }
+        let theirs = |r: &Replay| {
+            r.verify(registry, &record.game, record.players.len())
+                .is_ok()
+                && r.outcome.as_ref() == Some(&record.outcome)
+        };
+        let valid = validation::validate(record, registry).is_ok()
+            && d.evidence.replay.as_ref().is_none_or(theirs);
+        d.status = if valid {
+            DisputeStatus::Revalidated
+        } else {
+            DisputeStatus::Invalid
+        };
+        Some(d.status)
+    }
+}
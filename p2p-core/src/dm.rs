@@ -0,0 +1,154 @@
+//! Direct messages.
+//!
+//! Every peer listens on an inbox topic derived from its own peer id. A
+//! direct message is a [`ChatMsg`] with [`Kind::Direct`] published on the
+//! inbox of the recipient, after dialing the recipient so it travels over
+//! the 1:1 connection instead of through the global topic. Only the two
+//! peers are expected on an inbox; the text itself is not encrypted.
+//!
+//! Game invitations travel the same way: `room game invite` opens a
+//! private room, sends its ticket and the proposed game in an
+//! [`InviteBody::Invite`] and waits for the invitee to accept or decline
+//! within [`INVITE_TTL`].
+//!
+//! Direct messages are kept in the history like other chat. Grouped by the
+//! other peer they are conversations; [`ReadMarks`] remembers in
+//! `<data dir>/p2p-games/dm_read.json` how far each one was read, so front
+//! ends can show what is unread.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf, time::Duration};
+
+use crate::{
+    game::GameKind,
+    history::{HistoryEntry, HistoryScope},
+    hlc,
+    protocol::{ChatMsg, Envelope, Kind, Scope, make_envelope, now_ms},
+    session::data_dir,
+    turn::TurnOrder,
+};
+
+/// Prefix of inbox topic names; followed by the peer id.
+pub const DM_INBOX_TOPIC_PREFIX: &str = "p2p-games/dm/";
+
+/// Name of the inbox topic of `peer_id`.
+pub fn inbox_topic_name(peer_id: &str) -> String {
+    format!("{DM_INBOX_TOPIC_PREFIX}{peer_id}")
+}
+
+/// Build a direct message to `recipient`.
+pub fn make_direct(
+    sender_id: String,
+    recipient: &str,
+    text: impl Into<String>,
+) -> Envelope<ChatMsg> {
+    let mut env = make_envelope(
+        Kind::Direct,
+        Scope::Direct,
+        Some(recipient.to_string()),
+        sender_id,
+        now_ms(),
+        ChatMsg {
+            text: text.into(),
+            relay: None,
+        },
+    );
+    env.hlc = Some(hlc::now());
+    env
+}
+
+/// How long an invitation can be answered.
+pub const INVITE_TTL: Duration = Duration::from_secs(120);
+
+/// A game invitation or its answer, sent to the other peer's inbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum InviteBody {
+    /// Play `game` in the private room of `ticket`, hosted by the sender,
+    /// seated in `order`.
+    Invite {
+        invite_id: String,
+        game: GameKind,
+        order: TurnOrder,
+        /// [`crate::rooms::RoomTicket`] of the room.
+        ticket: String,
+        /// When the invitation lapses (unix millis).
+        expires: u64,
+    },
+    Accept { invite_id: String },
+    Decline { invite_id: String },
+}
+
+impl InviteBody {
+    pub fn invite_id(&self) -> &str {
+        match self {
+            Self::Invite { invite_id, .. }
+            | Self::Accept { invite_id }
+            | Self::Decline { invite_id } => invite_id,
+        }
+    }
+}
+
+/// Build an invitation, or an answer to one, for `recipient`.
+pub fn make_invite(sender_id: String, recipient: &str, body: InviteBody) -> Envelope<InviteBody> {
+    make_envelope(
+        Kind::Invite,
+        Scope::Direct,
+        Some(recipient.to_string()),
+        sender_id,
+        now_ms(),
+        body,
+    )
+}
+
+/// The other peer of a stored direct message, seen from `me`.
+pub fn conversation_peer<'a>(entry: &'a HistoryEntry, me: &str) -> Option<&'a str> {
+    if entry.scope != HistoryScope::Dm {
+        return None;
+    }
+    if entry.sender_id == me {
+        entry.room.as_deref()
+    } else {
+        Some(&entry.sender_id)
+    }
+}
+
+/// How far each conversation was read.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReadMarks {
+    /// Peer id → unix millis of the newest message read.
+    read: BTreeMap<String, u64>,
+}
+
+impl ReadMarks {
+    fn storage_path() -> PathBuf {
+        data_dir().join("dm_read.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// Everything from `peer_id` up to `ts` was read.
+    pub fn read(&mut self, peer_id: &str, ts: u64) {
+        let mark = self.read.entry(peer_id.to_string()).or_default();
+        *mark = (*mark).max(ts);
+    }
+
+    /// Whether a message from `peer_id` sent at `ts` is unread.
+    pub fn is_unread(&self, peer_id: &str, ts: u64) -> bool {
+        self.read.get(peer_id).is_none_or(|mark| ts > *mark)
+    }
+}
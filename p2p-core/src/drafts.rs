@@ -0,0 +1,99 @@
+//! Unsent input and the last sent message, per chat scope.
+//!
+//! Front ends store what is typed but not yet sent in
+//! `<data dir>/p2p-games/drafts.json` as it changes, so it survives a crash,
+//! together with our last message of each scope. That message can be sent
+//! again (`/resend`, same `msg_id`, so peers that already have it drop the
+//! copy), edited (`/edit`) or deleted (`/delete`).
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{
+    protocol::{ChatMsg, Envelope},
+    session::data_dir,
+};
+
+/// Key of a chat scope: a room name, or `None` for global chat.
+pub fn scope_key(room: Option<&str>) -> String {
+    match room {
+        Some(room) => format!("room:{room}"),
+        None => "global".to_string(),
+    }
+}
+
+/// Key of the direct message conversation with `peer_id`.
+pub fn dm_scope_key(peer_id: &str) -> String {
+    format!("dm:{peer_id}")
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Drafts {
+    /// Scope key → unsent input.
+    drafts: BTreeMap<String, String>,
+    /// Scope key → our last message there.
+    last: BTreeMap<String, Envelope<ChatMsg>>,
+}
+
+impl Drafts {
+    fn storage_path() -> PathBuf {
+        data_dir().join("drafts.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    pub fn draft(&self, scope: &str) -> &str {
+        self.drafts.get(scope).map_or("", String::as_str)
+    }
+
+    /// Remember the input of `scope`; returns whether it changed.
+    pub fn set_draft(&mut self, scope: &str, text: &str) -> bool {
+        if self.draft(scope) == text {
+            return false;
+        }
+        if text.is_empty() {
+            self.drafts.remove(scope);
+        } else {
+            self.drafts.insert(scope.to_string(), text.to_string());
+        }
+        true
+    }
+
+    /// `env` went out in `scope`: it is the last message, the draft is gone.
+    pub fn sent(&mut self, scope: &str, env: &Envelope<ChatMsg>) {
+        self.drafts.remove(scope);
+        self.last.insert(scope.to_string(), env.clone());
+    }
+
+    pub fn last(&self, scope: &str) -> Option<&Envelope<ChatMsg>> {
+        self.last.get(scope)
+    }
+
+    /// Our last message of `scope` was edited to `text`; a resend sends the
+    /// new text.
+    pub fn edited(&mut self, scope: &str, text: &str) {
+        if let Some(env) = self.last.get_mut(scope) {
+            env.body.text = text.to_string();
+        }
+    }
+
+    /// Our last message of `scope` was deleted.
+    pub fn deleted(&mut self, scope: &str) {
+        self.last.remove(scope);
+    }
+}
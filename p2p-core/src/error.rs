@@ -0,0 +1,104 @@
+//! Failures that scripts can branch on.
+//!
+//! Commands return [`ProtocolError`] for outcomes a caller may want to react
+//! to (a taken nickname, a rejected join, no network) and plain `anyhow`
+//! errors for everything else. `main` maps them to the exit codes below and,
+//! with `--errors json`, prints a [`ErrorReport`] on stderr.
+//!
+//! | code | name              |
+//! |------|-------------------|
+//! | 0    | success           |
+//! | 1    | `error` (generic) |
+//! | 2    | usage (clap)      |
+//! | 10   | `name_taken`      |
+//! | 11   | `room_full`       |
+//! | 12   | `join_rejected`   |
+//! | 13   | `not_found`       |
+//! | 14   | `no_active_room`  |
+//! | 15   | `not_host`        |
+//! | 20   | `no_connectivity` |
+//!
+//! The numbers and names are stable; new failures get new codes.
+
+use serde::Serialize;
+use thiserror::Error;
+
+/// Exit code of errors without a [`ProtocolError`].
+pub const GENERIC_EXIT: u8 = 1;
+
+/// A failure with a stable exit code; the payload is the localized message.
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+    /// The nickname or room name belongs to someone else.
+    #[error("{0}")]
+    NameTaken(String),
+    /// The room has no free seat.
+    #[error("{0}")]
+    RoomFull(String),
+    /// The room refused us (banned, or blocked by safe mode).
+    #[error("{0}")]
+    JoinRejected(String),
+    /// No room, peer or nickname by that name.
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    NoActiveRoom(String),
+    /// Only the host of the room may do that.
+    #[error("{0}")]
+    NotHost(String),
+    /// The node could not come up on the network.
+    #[error("{0}")]
+    NoConnectivity(String),
+}
+
+impl ProtocolError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::NameTaken(_) => 10,
+            Self::RoomFull(_) => 11,
+            Self::JoinRejected(_) => 12,
+            Self::NotFound(_) => 13,
+            Self::NoActiveRoom(_) => 14,
+            Self::NotHost(_) => 15,
+            Self::NoConnectivity(_) => 20,
+        }
+    }
+
+    /// Stable machine-readable name.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NameTaken(_) => "name_taken",
+            Self::RoomFull(_) => "room_full",
+            Self::JoinRejected(_) => "join_rejected",
+            Self::NotFound(_) => "not_found",
+            Self::NoActiveRoom(_) => "no_active_room",
+            Self::NotHost(_) => "not_host",
+            Self::NoConnectivity(_) => "no_connectivity",
+        }
+    }
+}
+
+/// The JSON line printed for a failed command with `--errors json`.
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub error: &'static str,
+    pub exit_code: u8,
+    pub message: String,
+}
+
+impl ErrorReport {
+    pub fn new(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<ProtocolError>() {
+            Some(e) => Self {
+                error: e.code(),
+                exit_code: e.exit_code(),
+                message: format!("{err:#}"),
+            },
+            None => Self {
+                error: "error",
+                exit_code: GENERIC_EXIT,
+                message: format!("{err:#}"),
+            },
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Fair coin flips and dice without a trusted party.
+//!
+//! Every player of a match draws a secret seed and sends its hash in a
+//! [`GameBody::RandomCommit`]. Once all commitments are in, each reveals its
+//! seed in a [`GameBody::RandomReveal`], and the XOR of the seeds is the
+//! [`SharedRandom`] value every peer arrives at. No player can steer it: a
+//! seed is fixed before any other one is known, and one honest seed is
+//! enough to make the result uniform. That needs every seed to be chosen
+//! independently of the others, so the hash covers the match, the draw and
+//! the player along with the seed: a player who copies someone else's
+//! commitment cannot open it, and a repeated commitment is refused outright
+//! (otherwise the same seed revealed twice would cancel out in the XOR).
+//!
+//! A player who dislikes the result after seeing the other seeds can only
+//! hold back its reveal, which stalls the draw instead of changing it; [`draw`] then fails after
+//! [`DRAW_TIMEOUT`] and the game decides what that means.
+//!
+//! [`FairDraw`] is the exchange as a state machine, for event loops that
+//! read the room topic themselves; [`draw`] runs it to the end on its own.
+//! Draws are told apart by a name, so a game can run one per round
+//! (`"round-3"`) without mixing them up.
+
+use anyhow::{Result, anyhow, bail};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+use transport_iroh::transport_iroh::TopicHandle;
+
+use crate::{
+    game::{GameBody, make_game},
+    t,
+    wire::Wire,
+};
+
+/// Bytes of a seed and of the result.
+pub const SEED_LEN: usize = 32;
+
+/// How long [`draw`] waits for the other players.
+pub const DRAW_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often [`draw`] repeats our last message, in case gossip lost it.
+const RESEND_EVERY: Duration = Duration::from_secs(3);
+
+type Seed = [u8; SEED_LEN];
+
+fn fresh_seed() -> Seed {
+    let mut seed = [0; SEED_LEN];
+    seed[..16].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+    seed[16..].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+    seed
+}
+
+/// Hash of `player`'s `seed` for draw `name` of `game_id`.
+fn commitment(game_id: &str, name: &str, player: &str, seed: &Seed) -> String {
+    let mut hash = Sha256::new();
+    for part in [game_id, name, player] {
+        hash.update((part.len() as u64).to_be_bytes());
+        hash.update(part);
+    }
+    hash.update(seed);
+    hex::encode(hash.finalize())
+}
+
+/// The outcome of a draw, the same on every peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharedRandom(Seed);
+
+impl SharedRandom {
+    pub fn bytes(&self) -> &[u8; SEED_LEN] {
+        &self.0
+    }
+
+    /// A number in `0..n`; `n` must not be 0.
+    pub fn below(&self, n: u64) -> u64 {
+        let mut high = [0; 16];
+        high.copy_from_slice(&self.0[..16]);
+        // The bias of the modulo is at most n / 2^128.
+        (u128::from_be_bytes(high) % u128::from(n)) as u64
+    }
+
+    pub fn coin(&self) -> bool {
+        self.0[0] & 1 == 1
+    }
+
+    /// A die with `sides` sides: `1..=sides`.
+    pub fn roll(&self, sides: u64) -> u64 {
+        self.below(sides) + 1
+    }
+
+    /// Independent value number `i` derived from this one, for games that
+    /// need more than one number per draw.
+    pub fn nth(&self, i: u64) -> Self {
+        let mut hash = Sha256::new();
+        hash.update(self.0);
+        hash.update(i.to_be_bytes());
+        Self(hash.finalize().into())
+    }
+
+    /// Shuffle `items` the same way on every peer.
+    pub fn shuffle<T>(&self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.nth(i as u64).below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// One draw among the players of a match, seen from one of them.
+#[derive(Debug, Clone)]
+pub struct FairDraw {
+    game_id: String,
+    name: String,
+    players: Vec<String>,
+    me: String,
+    seed: Seed,
+    /// Player → commitment; the first one heard counts.
+    commitments: BTreeMap<String, String>,
+    /// Player → seed checked against its commitment.
+    seeds: BTreeMap<String, Seed>,
+    /// Reveals that arrived before their commitment.
+    early: BTreeMap<String, Seed>,
+}
+
+impl FairDraw {
+    /// Draw `name` of `game_id` among `players` (peer ids), `me` included.
+    pub fn new(game_id: &str, name: &str, players: &[String], me: &str) -> Self {
+        let seed = fresh_seed();
+        let mut commitments = BTreeMap::new();
+        commitments.insert(me.to_string(), commitment(game_id, name, me, &seed));
+        Self {
+            game_id: game_id.to_string(),
+            name: name.to_string(),
+            players: players.to_vec(),
+            me: me.to_string(),
+            seed,
+            commitments,
+            seeds: BTreeMap::new(),
+            early: BTreeMap::new(),
+        }
+    }
+
+    /// Our commitment, to send when the draw starts.
+    pub fn commit(&self) -> GameBody {
+        GameBody::RandomCommit {
+            game_id: self.game_id.clone(),
+            draw: self.name.clone(),
+            commitment: self.commitments[&self.me].clone(),
+        }
+    }
+
+    /// Our seed; only to be sent once [`Self::committed`].
+    pub fn reveal(&self) -> GameBody {
+        GameBody::RandomReveal {
+            game_id: self.game_id.clone(),
+            draw: self.name.clone(),
+            seed: hex::encode(self.seed),
+        }
+    }
+
+    /// Whether every player has committed, so seeds can be revealed.
+    pub fn committed(&self) -> bool {
+        self.players
+            .iter()
+            .all(|p| self.commitments.contains_key(p))
+    }
+
+    /// Take in `body` from `sender`. Returns whether it belonged to this
+    /// draw, and fails on a seed that does not match its commitment.
+    pub fn handle(&mut self, sender: &str, body: &GameBody) -> Result<bool> {
+        if sender == self.me || !self.players.iter().any(|p| p == sender) {
+            return Ok(false);
+        }
+        match body {
+            GameBody::RandomCommit {
+                game_id,
+                draw,
+                commitment,
+            } if *game_id == self.game_id && *draw == self.name => {
+                if !self.commitments.contains_key(sender) {
+                    if self.commitments.values().any(|c| c == commitment) {
+                        bail!(t!("fair_random.copied", peer = sender));
+                    }
+                    self.commitments
+                        .insert(sender.to_string(), commitment.clone());
+                }
+                if let Some(seed) = self.early.remove(sender) {
+                    self.check(sender, seed)?;
+                }
+                Ok(true)
+            }
+            GameBody::RandomReveal {
+                game_id,
+                draw,
+                seed,
+            } if *game_id == self.game_id && *draw == self.name => {
+                let seed: Seed = hex::decode(seed)
+                    .ok()
+                    .and_then(|b| b.try_into().ok())
+                    .ok_or_else(|| anyhow!(t!("fair_random.bad_seed", peer = sender)))?;
+                if self.commitments.contains_key(sender) {
+                    self.check(sender, seed)?;
+                } else {
+                    self.early.insert(sender.to_string(), seed);
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn check(&mut self, sender: &str, seed: Seed) -> Result<()> {
+        if commitment(&self.game_id, &self.name, sender, &seed) != self.commitments[sender] {
+            bail!(t!("fair_random.bad_seed", peer = sender));
+        }
+        self.seeds.insert(sender.to_string(), seed);
+        Ok(())
+    }
+
+    /// The result, once every other player's seed is in.
+    pub fn result(&self) -> Option<SharedRandom> {
+        let mut out = self.seed;
+        for p in self.players.iter().filter(|p| **p != self.me) {
+            for (o, s) in out.iter_mut().zip(self.seeds.get(p)?) {
+                *o ^= s;
+            }
+        }
+        Some(SharedRandom(out))
+    }
+}
+
+/// Run draw `name` of `game_id` among `players` on the room topic `th` and
+/// return the result. Other traffic on the topic is skipped while it runs.
+pub async fn draw(
+    th: &mut Box<dyn TopicHandle>,
+    wire: &Wire,
+    room_id: &str,
+    game_id: &str,
+    name: &str,
+    players: &[String],
+    me: &str,
+) -> Result<SharedRandom> {
+    let mut fair = FairDraw::new(game_id, name, players, me);
+    let deadline = Instant::now() + DRAW_TIMEOUT;
+    loop {
+        let revealed = fair.committed();
+        let body = if revealed {
+            fair.reveal()
+        } else {
+            fair.commit()
+        };
+        for frame in wire.encode(&make_game(room_id, me.to_string(), body)) {
+            th.publish(&frame).await?;
+        }
+        if revealed && let Some(result) = fair.result() {
+            return Ok(result);
+        }
+        let resend = (Instant::now() + RESEND_EVERY).min(deadline);
+        while let Ok(bytes) = tokio::time::timeout_at(resend.into(), th.next()).await {
+            let Some(env) = wire.decode::<GameBody>(&bytes?) else {
+                continue;
+            };
+            if fair.handle(&env.sender_id, &env.body)? && fair.committed() != revealed {
+                break;
+            }
+            if revealed && fair.result().is_some() {
+                break;
+            }
+        }
+        if Instant::now() >= deadline {
+            bail!(t!("fair_random.timeout", draw = name));
+        }
+    }
+}
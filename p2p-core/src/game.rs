@@ -0,0 +1,521 @@
+//! Game engine interface and `Kind::Game` wire payloads.
+//!
+//! A game implements [`Game`]: its state is the value itself, moves are a
+//! game-specific serializable type, and the engine answers whose turn it is,
+//! which moves are legal, how a move changes the state, and who won.
+//!
+//! Games travel on the room topic as [`GameBody`] messages. Moves and state
+//! are carried as JSON values so peers route them without knowing the game;
+//! [`AnyGame`] turns a concrete game into an object that accepts those
+//! values, and [`GameRegistry`] creates one from the name in
+//! [`GameBody::Start`].
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Debug},
+    fs,
+    path::PathBuf,
+};
+
+use crate::{
+    dispute::Evidence,
+    protocol::{Envelope, Kind, Scope, make_envelope, now_ms},
+    rating::GameResult,
+    replay::ReplayMove,
+    session::data_dir,
+    t,
+    widgets::RenderModel,
+};
+
+/// Seat index of a player: position in the `players` of [`GameBody::Start`].
+pub type Seat = usize;
+
+/// How a game ended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Outcome {
+    Winner {
+        seat: Seat,
+    },
+    Draw,
+    /// Ended early (player left, host closed the room…).
+    Aborted {
+        reason: String,
+    },
+}
+
+/// What a game does when a player leaves mid-game, so the match never waits
+/// for someone who is gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// [`Game::eliminate`] takes the seat out; later turns skip it.
+    SkipTurns,
+    /// [`Game::eliminate`] takes the seat out and hands what it held to the
+    /// other seats.
+    Redistribute,
+    /// A bot plays the first legal move for the seat until its player is
+    /// back.
+    Bot,
+    /// The match ends: [`Game::eliminate`] may decide the result (e.g. the
+    /// other player wins), otherwise it is aborted.
+    EndGame,
+}
+
+impl DropPolicy {
+    /// Key of the policy in `game.dropped.<key>` messages.
+    pub fn key(self) -> &'static str {
+        match self {
+            DropPolicy::SkipTurns => "skip_turns",
+            DropPolicy::Redistribute => "redistribute",
+            DropPolicy::Bot => "bot",
+            DropPolicy::EndGame => "end_game",
+        }
+    }
+}
+
+/// Rules of one game. The implementing type is the full game state.
+pub trait Game: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {
+    type Move: Clone + Debug + Serialize + DeserializeOwned + Send;
+
+    /// Stable identifier used on the wire and on the command line.
+    const NAME: &'static str;
+
+    /// Version of the rules and of the move and state formats; clients with
+    /// different versions of a game cannot play it together.
+    const VERSION: u32 = 1;
+
+    /// How the game carries on when a player leaves.
+    const ON_DROP: DropPolicy = DropPolicy::EndGame;
+
+    /// A fresh game for `players` seats; errors on an unsupported count.
+    fn new(players: usize) -> Result<Self>;
+
+    /// Seat expected to move next, `None` once the game is over.
+    fn to_move(&self) -> Option<Seat>;
+
+    /// Moves `seat` may make right now (empty when it is not their turn).
+    fn legal_moves(&self, seat: Seat) -> Vec<Self::Move>;
+
+    /// Apply a move by `seat`; illegal moves are errors and change nothing.
+    fn apply(&mut self, seat: Seat, mv: &Self::Move) -> Result<()>;
+
+    /// Read a move typed by the user (e.g. `"b2"`).
+    fn parse_move(&self, input: &str) -> Result<Self::Move>;
+
+    /// Result once the game is decided.
+    fn outcome(&self) -> Option<Outcome>;
+
+    /// What to show to `viewer` (hidden information stays hidden).
+    fn render(&self, viewer: Option<Seat>) -> RenderModel;
+
+    /// Short name of a move for statistics (e.g. `B2`), if the game has
+    /// one; see [`crate::dashboard`].
+    fn describe_move(&self, _mv: &Self::Move) -> Option<String> {
+        None
+    }
+
+    /// The player of `seat` left, as handled by [`Self::ON_DROP`]: skip
+    /// its turns from now on (see [`crate::turn::Rotation`]), hand out what
+    /// it held, or decide the result. Not called for [`DropPolicy::Bot`].
+    fn eliminate(&mut self, _seat: Seat) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Game messages (room topic).
+///
+/// Tagged with `"type"` like the other bodies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum GameBody {
+    /// Host starts a game in the room.
+    Start {
+        /// Identifier of this match (e.g. `"game-<uuid>"`).
+        game_id: String,
+        /// [`Game::NAME`] of the game played.
+        game: String,
+        /// Peer ids in seat order.
+        players: Vec<String>,
+    },
+    /// A player's move; the sender is the envelope's `sender_id`.
+    Move {
+        game_id: String,
+        /// Number of moves applied before this one; detects gaps and replays.
+        seq: u64,
+        /// The game's `Move`, as JSON.
+        mv: Value,
+    },
+    /// Full state, so late joiners and peers that missed moves catch up.
+    StateSync {
+        game_id: String,
+        /// Moves applied to reach `state`.
+        seq: u64,
+        /// The serialized game.
+        state: Value,
+    },
+    /// The first seat saw the player of `seat` leave after `seq` moves;
+    /// handled as the game's [`DropPolicy`] says and counts as a move.
+    Eliminate {
+        game_id: String,
+        seq: u64,
+        seat: Seat,
+    },
+    /// The player of a seat a bot took over is back; counts as a move.
+    Rejoin {
+        game_id: String,
+        seq: u64,
+        seat: Seat,
+    },
+    /// Whose turn it is after `seq` moves, announced by the first seat
+    /// (see [`crate::turn::TurnEngine`]).
+    Turn {
+        game_id: String,
+        seq: u64,
+        to_move: Option<Seat>,
+    },
+    /// The game is over.
+    End { game_id: String, outcome: Outcome },
+    /// A player's commitment to its seed for a shared random draw; see
+    /// [`crate::fair_random`].
+    RandomCommit {
+        game_id: String,
+        /// Name of the draw within the match.
+        draw: String,
+        /// Hex SHA-256 of the seed.
+        commitment: String,
+    },
+    /// A player's seed for a draw everybody has committed to.
+    RandomReveal {
+        game_id: String,
+        draw: String,
+        /// The seed, hex.
+        seed: String,
+    },
+    /// One pass over a shared deck: the cards as the sender left them; see
+    /// [`crate::deck`].
+    DeckPass {
+        game_id: String,
+        /// Name of the deck within the match.
+        deck: String,
+        /// Passes applied before this one.
+        pass: usize,
+        /// Locked cards, hex compressed points.
+        cards: Vec<String>,
+    },
+    /// A player's keys of some positions of a deck, hex scalars.
+    DeckKeys {
+        game_id: String,
+        deck: String,
+        keys: BTreeMap<usize, String>,
+    },
+    /// A player disputes the result of a finished match; see
+    /// [`crate::dispute`].
+    Dispute {
+        game_id: String,
+        reason: String,
+        evidence: Evidence,
+    },
+    /// A player signs the result of a finished two-player match, with its
+    /// rating before it; see [`crate::rating`].
+    Attest {
+        result: GameResult,
+        rating: f64,
+    },
+}
+
+/// Build a game envelope for `room_id`.
+pub fn make_game(
+    room_id: impl Into<String>,
+    sender_id: String,
+    body: GameBody,
+) -> Envelope<GameBody> {
+    make_envelope(
+        Kind::Game,
+        Scope::Room,
+        Some(room_id.into()),
+        sender_id,
+        now_ms(),
+        body,
+    )
+}
+
+/// Fresh match identifier for [`GameBody::Start`].
+pub fn new_game_id() -> String {
+    format!("game-{}", uuid::Uuid::new_v4())
+}
+
+/// A [`Game`] driven with JSON moves and state, without knowing its type.
+pub trait AnyGame: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn on_drop(&self) -> DropPolicy;
+    fn to_move(&self) -> Option<Seat>;
+    fn legal_moves(&self, seat: Seat) -> Vec<Value>;
+    fn apply(&mut self, seat: Seat, mv: &Value) -> Result<()>;
+    fn parse_move(&self, input: &str) -> Result<Value>;
+    fn outcome(&self) -> Option<Outcome>;
+    fn render(&self, viewer: Option<Seat>) -> RenderModel;
+    fn describe_move(&self, mv: &Value) -> Option<String>;
+    fn eliminate(&mut self, seat: Seat) -> Result<()>;
+    fn state(&self) -> Value;
+    /// Replace the state with one received in [`GameBody::StateSync`].
+    fn load_state(&mut self, state: Value) -> Result<()>;
+}
+
+impl<G: Game> AnyGame for G {
+    fn name(&self) -> &'static str {
+        G::NAME
+    }
+
+    fn on_drop(&self) -> DropPolicy {
+        G::ON_DROP
+    }
+
+    fn to_move(&self) -> Option<Seat> {
+        Game::to_move(self)
+    }
+
+    fn legal_moves(&self, seat: Seat) -> Vec<Value> {
+        Game::legal_moves(self, seat)
+            .iter()
+            .map(|m| serde_json::to_value(m).unwrap())
+            .collect()
+    }
+
+    fn apply(&mut self, seat: Seat, mv: &Value) -> Result<()> {
+        let mv: G::Move = serde_json::from_value(mv.clone())
+            .map_err(|e| anyhow!("malformed {} move: {e}", G::NAME))?;
+        Game::apply(self, seat, &mv)
+    }
+
+    fn parse_move(&self, input: &str) -> Result<Value> {
+        Ok(serde_json::to_value(Game::parse_move(self, input)?).unwrap())
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        Game::outcome(self)
+    }
+
+    fn render(&self, viewer: Option<Seat>) -> RenderModel {
+        Game::render(self, viewer)
+    }
+
+    fn describe_move(&self, mv: &Value) -> Option<String> {
+        let mv: G::Move = serde_json::from_value(mv.clone()).ok()?;
+        Game::describe_move(self, &mv)
+    }
+
+    fn eliminate(&mut self, seat: Seat) -> Result<()> {
+        Game::eliminate(self, seat)
+    }
+
+    fn state(&self) -> Value {
+        serde_json::to_value(self).unwrap()
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<()> {
+        *self = serde_json::from_value(state)
+            .map_err(|e| anyhow!("malformed {} state: {e}", G::NAME))?;
+        Ok(())
+    }
+}
+
+/// The game a room is opened for, as advertised in room listings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "GameKindRepr")]
+pub struct GameKind {
+    /// [`Game::NAME`].
+    pub name: String,
+    /// Game-specific settings chosen by the host (board size, time control…).
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub settings: Value,
+}
+
+impl GameKind {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            settings: Value::Null,
+        }
+    }
+}
+
+impl fmt::Display for GameKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Older hosts advertise just the game name.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GameKindRepr {
+    Name(String),
+    Full {
+        name: String,
+        #[serde(default)]
+        settings: Value,
+    },
+}
+
+impl From<GameKindRepr> for GameKind {
+    fn from(repr: GameKindRepr) -> Self {
+        match repr {
+            GameKindRepr::Name(name) => Self::new(name),
+            GameKindRepr::Full { name, settings } => Self { name, settings },
+        }
+    }
+}
+
+enum Constructor {
+    Builtin(fn(usize) -> Result<Box<dyn AnyGame>>),
+    Plugin(&'static crate::plugin::Plugin),
+}
+
+fn construct<G: Game>(players: usize) -> Result<Box<dyn AnyGame>> {
+    Ok(Box::new(G::new(players)?))
+}
+
+/// Known games by [`Game::NAME`].
+#[derive(Default)]
+pub struct GameRegistry {
+    games: BTreeMap<&'static str, Constructor>,
+    versions: BTreeMap<&'static str, u32>,
+}
+
+impl GameRegistry {
+    /// Registry with all games shipped with the client and those of the
+    /// plugins that loaded (see [`crate::plugin`]).
+    pub fn builtin() -> Self {
+        let mut registry = Self::shipped();
+        for plugin in &crate::plugin::loaded().games {
+            registry.versions.insert(plugin.name, plugin.version);
+            registry.games.insert(plugin.name, Constructor::Plugin(plugin));
+        }
+        registry
+    }
+
+    /// Registry with the games shipped with the client only.
+    pub(crate) fn shipped() -> Self {
+        let mut registry = Self::default();
+        registry.register::<crate::tictactoe::TicTacToe>();
+        registry.register::<crate::chess::Chess>();
+        registry.register::<crate::battleship::Battleship>();
+        registry
+    }
+
+    pub fn register<G: Game>(&mut self) {
+        self.games.insert(G::NAME, Constructor::Builtin(construct::<G>));
+        self.versions.insert(G::NAME, G::VERSION);
+    }
+
+    /// [`Game::VERSION`] of `name`, if this client can play it.
+    pub fn version(&self, name: &str) -> Option<u32> {
+        self.versions.get(name).copied()
+    }
+
+    /// Every game with its version, as advertised in
+    /// [`Capabilities`](crate::protocol::Capabilities).
+    pub fn versions(&self) -> BTreeMap<String, u32> {
+        self.versions
+            .iter()
+            .map(|(name, v)| (name.to_string(), *v))
+            .collect()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.games.keys().copied()
+    }
+
+    /// Whether this client can play `name`.
+    pub fn knows(&self, name: &str) -> bool {
+        self.games.contains_key(name)
+    }
+
+    /// Start a new game of `name` for `players` seats.
+    pub fn create(&self, name: &str, players: usize) -> Result<Box<dyn AnyGame>> {
+        let new = self
+            .games
+            .get(name)
+            .ok_or_else(|| anyhow!(t!("game.unknown", game = name)))?;
+        match new {
+            Constructor::Builtin(new) => new(players),
+            Constructor::Plugin(plugin) => plugin.create(players),
+        }
+    }
+}
+
+/// A game in progress, kept on disk so it can be resumed after a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub game_id: String,
+    /// Name of the room (see [`crate::rooms::RoomRef`]) the game runs in.
+    pub room: String,
+    /// [`Game::NAME`].
+    pub game: String,
+    pub players: Vec<String>,
+    pub seq: u64,
+    pub state: Value,
+    /// Moves so far, if the match has a full [`Replay`](crate::replay::Replay).
+    #[serde(default)]
+    pub moves: Option<Vec<ReplayMove>>,
+    /// Seats a bot plays because their player left.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub bots: BTreeSet<Seat>,
+}
+
+impl SavedGame {
+    /// Announce the saved state so the room catches up on resume.
+    pub fn state_sync(&self) -> GameBody {
+        GameBody::StateSync {
+            game_id: self.game_id.clone(),
+            seq: self.seq,
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// Unfinished games, stored in `games.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SavedGames {
+    pub games: Vec<SavedGame>,
+}
+
+impl SavedGames {
+    fn storage_path() -> PathBuf {
+        data_dir().join("games.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// Insert or replace by `game_id`.
+    pub fn upsert(&mut self, game: SavedGame) {
+        self.remove(&game.game_id);
+        self.games.push(game);
+    }
+
+    /// Forget a finished or abandoned game.
+    pub fn remove(&mut self, game_id: &str) {
+        self.games.retain(|g| g.game_id != game_id);
+    }
+
+    pub fn in_room<'a>(&'a self, room: &'a str) -> impl Iterator<Item = &'a SavedGame> {
+        self.games.iter().filter(move |g| g.room == room)
+    }
+}
@@ -0,0 +1,333 @@
+//! Local message history.
+//!
+//! Received and sent chat lines are appended to
+//! `<data dir>/p2p-games/history.jsonl`, one JSON object per line. Nothing
+//! here touches the network.
+//!
+//! Retention is per scope (`[history]` in `config.toml`); expired lines and
+//! lines of forgotten peers are only dropped when [`HistoryStore::compact`]
+//! rewrites the file. Chat listeners run it on start and every
+//! [`COMPACT_EVERY`]; `history purge` runs it immediately. Edits and
+//! deletions by the sender rewrite the line in place ([`HistoryStore::apply`]);
+//! a deleted line stays as an empty tombstone. The store as a whole is kept
+//! under its quota by [`crate::storage`].
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    ops::Range,
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{
+    protocol::{ChatChange, ChatMsg, Envelope, Scope, now_ms},
+    session::data_dir,
+    storage,
+};
+
+/// How often long-running listeners compact the history.
+pub const COMPACT_EVERY: Duration = Duration::from_secs(3600);
+
+const DAY_MS: u64 = 86_400_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryScope {
+    Global,
+    Room,
+    Dm,
+}
+
+/// One stored chat line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub scope: HistoryScope,
+    /// Room id or topic for [`HistoryScope::Room`], recipient peer id for
+    /// [`HistoryScope::Dm`].
+    pub room: Option<String>,
+    pub msg_id: String,
+    pub sender_id: String,
+    /// Sender nickname if it was known when the line was stored.
+    pub sender_nick: Option<String>,
+    /// Unix millis.
+    pub ts: u64,
+    pub text: String,
+    /// The sender changed the text since.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub edited: bool,
+    /// The sender retracted the line; `text` is empty.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+}
+
+impl HistoryEntry {
+    pub fn chat(env: &Envelope<ChatMsg>, sender_nick: Option<String>) -> Self {
+        Self {
+            scope: match env.scope {
+                Scope::Global => HistoryScope::Global,
+                Scope::Room => HistoryScope::Room,
+                Scope::Direct => HistoryScope::Dm,
+            },
+            room: env.room_id.clone(),
+            msg_id: env.msg_id.clone(),
+            sender_id: env.sender_id.clone(),
+            sender_nick,
+            ts: env.ts,
+            text: env.body.text.clone(),
+            edited: false,
+            deleted: false,
+        }
+    }
+}
+
+/// Filter for [`HistoryStore::query`]; empty fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    /// Case-insensitive substring of the message text.
+    pub text: String,
+    pub scope: Option<HistoryScope>,
+    /// Room name or topic.
+    pub room: Option<String>,
+    /// Sender peer id or nickname.
+    pub peer: Option<String>,
+    /// Newest matches to return; `0` = all.
+    pub limit: usize,
+}
+
+impl HistoryQuery {
+    pub fn matches(&self, e: &HistoryEntry) -> bool {
+        self.scope.is_none_or(|s| s == e.scope)
+            && self.room.as_deref().is_none_or(|r| {
+                e.room
+                    .as_deref()
+                    .is_some_and(|er| er.eq_ignore_ascii_case(r))
+            })
+            && self.peer.as_deref().is_none_or(|p| {
+                e.sender_id == p
+                    || e.sender_nick
+                        .as_deref()
+                        .is_some_and(|n| n.eq_ignore_ascii_case(p))
+            })
+            && (self.text.is_empty() || !find_ranges(&e.text, &self.text).is_empty())
+    }
+}
+
+/// Byte ranges of case-insensitive occurrences of `needle` in `text`.
+pub fn find_ranges(text: &str, needle: &str) -> Vec<Range<usize>> {
+    let needle: Vec<char> = needle.chars().flat_map(char::to_lowercase).collect();
+    if needle.is_empty() {
+        return Vec::new();
+    }
+    let mut out = Vec::new();
+    let mut skip_until = 0;
+    for (start, _) in text.char_indices() {
+        if start < skip_until {
+            continue;
+        }
+        let mut want = needle.iter();
+        let mut found = None;
+        for (i, c) in text[start..].char_indices() {
+            if !c.to_lowercase().all(|l| want.next() == Some(&l)) {
+                break;
+            }
+            if want.len() == 0 {
+                found = Some(start + i + c.len_utf8());
+                break;
+            }
+        }
+        if let Some(end) = found {
+            out.push(start..end);
+            skip_until = end;
+        }
+    }
+    out
+}
+
+/// `[history]` section of `config.toml`: days to keep per scope, `0` = forever.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub global_days: u64,
+    pub room_days: u64,
+    pub dm_days: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            global_days: 7,
+            room_days: 30,
+            dm_days: 0,
+        }
+    }
+}
+
+impl HistoryConfig {
+    /// Retention for `scope`; `None` keeps lines forever.
+    pub fn retention(&self, scope: HistoryScope) -> Option<Duration> {
+        let days = match scope {
+            HistoryScope::Global => self.global_days,
+            HistoryScope::Room => self.room_days,
+            HistoryScope::Dm => self.dm_days,
+        };
+        (days > 0).then(|| Duration::from_millis(days * DAY_MS))
+    }
+
+    pub fn is_expired(&self, e: &HistoryEntry, now: u64) -> bool {
+        self.retention(e.scope)
+            .is_some_and(|keep| now.saturating_sub(e.ts) > keep.as_millis() as u64)
+    }
+}
+
+/// Append-only store with explicit compaction.
+pub struct HistoryStore {
+    path: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn open() -> Self {
+        Self {
+            path: data_dir().join("history.jsonl"),
+        }
+    }
+
+    pub fn append(&self, entry: &HistoryEntry) -> std::io::Result<()> {
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut line = serde_json::to_vec(entry).unwrap();
+        line.push(b'\n');
+        f.write_all(&line)
+    }
+
+    /// Make sure appended lines reached the disk (called on shutdown).
+    pub fn sync(&self) -> std::io::Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        OpenOptions::new().append(true).open(&self.path)?.sync_all()
+    }
+
+    /// All stored lines, oldest first. Unreadable lines are skipped.
+    pub fn load(&self) -> std::io::Result<Vec<HistoryEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let f = fs::File::open(&self.path)?;
+        Ok(BufReader::new(f)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|l| serde_json::from_str(&l).ok())
+            .collect())
+    }
+
+    /// Stored lines matching `q`, oldest first.
+    pub fn query(&self, q: &HistoryQuery) -> std::io::Result<Vec<HistoryEntry>> {
+        let mut hits: Vec<_> = self.load()?.into_iter().filter(|e| q.matches(e)).collect();
+        if q.limit > 0 && hits.len() > q.limit {
+            hits.drain(..hits.len() - q.limit);
+        }
+        Ok(hits)
+    }
+
+    /// Rewrite the store without expired lines and lines matching `forget`.
+    ///
+    /// Returns the number of removed lines.
+    pub fn compact(
+        &self,
+        cfg: &HistoryConfig,
+        forget: impl Fn(&HistoryEntry) -> bool,
+    ) -> std::io::Result<usize> {
+        let entries = self.load()?;
+        let now = now_ms();
+        let total = entries.len();
+        let kept: Vec<_> = entries
+            .into_iter()
+            .filter(|e| !cfg.is_expired(e, now) && !forget(e))
+            .collect();
+        let removed = total - kept.len();
+        if removed == 0 {
+            return Ok(0);
+        }
+        self.rewrite(&kept)?;
+        Ok(removed)
+    }
+
+    /// Bytes the store takes on disk.
+    pub fn bytes(&self) -> std::io::Result<u64> {
+        storage::file_len(&self.path)
+    }
+
+    /// Drop the oldest lines until the store fits `max_bytes`; see
+    /// [`crate::storage`]. Returns the number of removed lines.
+    pub fn keep_within(&self, max_bytes: u64) -> std::io::Result<usize> {
+        let entries = self.load()?;
+        let mut total: u64 = entries.iter().map(storage::line_len).sum();
+        let removed = entries
+            .iter()
+            .take_while(|e| {
+                let over = total > max_bytes;
+                total -= storage::line_len(e);
+                over
+            })
+            .count();
+        if removed > 0 {
+            self.rewrite(&entries[removed..])?;
+        }
+        Ok(removed)
+    }
+
+    /// Apply an edit or deletion to the stored line it targets, if that was
+    /// sent by the same peer. Returns whether a line changed.
+    pub fn apply(&self, change: &ChatChange) -> std::io::Result<bool> {
+        let mut entries = self.load()?;
+        let Some(e) = entries
+            .iter_mut()
+            .find(|e| e.msg_id == change.target_msg_id() && e.sender_id == change.sender_id())
+        else {
+            return Ok(false);
+        };
+        match change.new_text() {
+            Some(text) => {
+                e.text = text.to_string();
+                e.edited = true;
+            }
+            None => {
+                e.text.clear();
+                e.deleted = true;
+            }
+        }
+        self.rewrite(&entries)?;
+        Ok(true)
+    }
+
+    fn rewrite(&self, entries: &[HistoryEntry]) -> std::io::Result<()> {
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut out = Vec::new();
+        for e in entries {
+            out.extend(serde_json::to_vec(e).unwrap());
+            out.push(b'\n');
+        }
+        fs::write(&tmp, out)?;
+        fs::rename(tmp, &self.path)
+    }
+
+    /// Enforce the retention policy only.
+    pub fn enforce(&self, cfg: &HistoryConfig) -> std::io::Result<usize> {
+        self.compact(cfg, |_| false)
+    }
+
+    /// Forget everything sent by `peer` (nickname, case-insensitive, or peer id).
+    pub fn purge_peer(&self, cfg: &HistoryConfig, peer: &str) -> std::io::Result<usize> {
+        let nick = peer.to_lowercase();
+        self.compact(cfg, |e| {
+            e.sender_id == peer
+                || e.sender_nick
+                    .as_deref()
+                    .is_some_and(|n| n.to_lowercase() == nick)
+        })
+    }
+}
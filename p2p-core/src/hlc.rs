@@ -0,0 +1,155 @@
+//! Hybrid logical clock ordering for chat.
+//!
+//! `ts` is the sender's wall clock, so chat from a peer whose clock runs
+//! behind shows up before the lines it answers. Chat envelopes therefore
+//! also carry an [`Hlc`] stamp: wall-clock millis, raised to the latest stamp
+//! this node has seen, plus a counter for ties. A reply is always stamped
+//! after the message it answers, whatever the clocks say.
+//! [`Wire`](crate::wire::Wire) feeds every received stamp into the node
+//! clock.
+//!
+//! Front ends hold incoming chat in an [`OrderBuffer`] for a moment and show
+//! it in stamp order, so lines that cross in flight come out the same way on
+//! every peer.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::protocol::{Envelope, now_ms};
+
+/// Stamps further ahead of our clock are not merged, so one peer with a
+/// wrong clock cannot drag everyone's stamps into the future.
+const MAX_DRIFT_MS: u64 = 60_000;
+
+/// How long incoming chat is held for earlier lines to catch up.
+pub const ORDER_DELAY: Duration = Duration::from_millis(300);
+
+/// A hybrid logical clock stamp; orders by `wall_ms`, then `counter`.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Hlc {
+    pub wall_ms: u64,
+    pub counter: u32,
+}
+
+impl Hlc {
+    /// The least stamp after `self`; a full counter moves on to the next
+    /// millisecond instead of wrapping around.
+    fn after(self) -> Self {
+        match self.counter.checked_add(1) {
+            Some(counter) => Self { counter, ..self },
+            None => Self {
+                wall_ms: self.wall_ms.saturating_add(1),
+                counter: 0,
+            },
+        }
+    }
+}
+
+static CLOCK: Mutex<Hlc> = Mutex::new(Hlc {
+    wall_ms: 0,
+    counter: 0,
+});
+
+/// Stamp for a message sent now.
+pub fn now() -> Hlc {
+    let mut clock = CLOCK.lock().unwrap();
+    let wall = now_ms();
+    *clock = if wall > clock.wall_ms {
+        Hlc {
+            wall_ms: wall,
+            counter: 0,
+        }
+    } else {
+        clock.after()
+    };
+    *clock
+}
+
+/// Merge a received stamp, so later local stamps sort after it.
+pub fn observe(remote: Hlc) {
+    let wall = now_ms();
+    if remote.wall_ms > wall + MAX_DRIFT_MS {
+        return;
+    }
+    let mut clock = CLOCK.lock().unwrap();
+    let wall_ms = wall.max(clock.wall_ms).max(remote.wall_ms);
+    *clock = match (wall_ms == clock.wall_ms, wall_ms == remote.wall_ms) {
+        (true, true) => (*clock).max(remote).after(),
+        (true, false) => clock.after(),
+        (false, true) => remote.after(),
+        (false, false) => Hlc {
+            wall_ms,
+            counter: 0,
+        },
+    };
+}
+
+/// The stamp of `env`; envelopes of older peers are ordered by `ts`.
+pub fn stamp<T>(env: &Envelope<T>) -> Hlc {
+    env.hlc.unwrap_or(Hlc {
+        wall_ms: env.ts,
+        counter: 0,
+    })
+}
+
+/// Holds items for [`ORDER_DELAY`] and releases them in stamp order.
+pub struct OrderBuffer<T> {
+    /// (stamp, msg_id) → release time and item; the id breaks stamp ties.
+    held: BTreeMap<(Hlc, String), (Instant, T)>,
+}
+
+impl<T> Default for OrderBuffer<T> {
+    fn default() -> Self {
+        Self {
+            held: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> OrderBuffer<T> {
+    pub fn push(&mut self, stamp: Hlc, msg_id: &str, item: T, now: Instant) {
+        self.held
+            .insert((stamp, msg_id.to_string()), (now + ORDER_DELAY, item));
+    }
+
+    /// When [`due`](Self::due) next has something to release.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.held.values().map(|(at, _)| *at).min()
+    }
+
+    /// Items held long enough, plus everything stamped before them, in
+    /// stamp order.
+    pub fn due(&mut self, now: Instant) -> Vec<T> {
+        let Some(last) = self
+            .held
+            .iter()
+            .filter(|(_, (at, _))| *at <= now)
+            .map(|(key, _)| key.clone())
+            .last()
+        else {
+            return Vec::new();
+        };
+        let mut out = Vec::new();
+        while let Some(entry) = self.held.first_entry() {
+            if *entry.key() > last {
+                break;
+            }
+            out.push(entry.remove().1);
+        }
+        out
+    }
+
+    /// Everything still held, in stamp order.
+    pub fn drain(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.held)
+            .into_values()
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
@@ -0,0 +1,706 @@
+//! Localizable user-facing strings.
+//!
+//! Every line the CLI prints is looked up by a stable message key in a
+//! [`Catalog`]. English ships built in; translations are plain TOML files
+//! (`"key" = "text"`) placed at `<config dir>/p2p-games/locales/<locale>.toml`,
+//! so communities can add a language without touching the output code.
+//! Keys missing from a translation fall back to English.
+//!
+//! Placeholders use `{name}` syntax and are filled by [`tr_args`] or the
+//! [`t!`](crate::t) macro.
+
+use std::{collections::HashMap, fs, sync::OnceLock};
+
+use crate::config::Config;
+
+/// Fallback locale and the language of the built-in catalog.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Built-in English strings (message key, text).
+const EN: &[(&str, &str)] = &[
+    ("login.claiming", "Claiming nickname '{name}'…"),
+    ("login.proof_of_work", "Proof of work: {tried} of about {expected} hashes tried…"),
+    ("login.taken", "Nickname '{name}' is already taken."),
+    ("login.ok", "You are now known as {nickname}."),
+    ("login.no_name", "No nickname given: pass --name or set `nickname` in config.toml."),
+    (
+        "login.renamed",
+        "Nickname '{name}' is taken; you are now known as {nickname}.",
+    ),
+    ("logout.ok", "Released nickname '{nickname}'."),
+    ("logout.none", "You have no nickname to release."),
+    (
+        "whoami.logged_out",
+        "Not logged in. Run `p2p-games login --name <nick>` or `p2p-games setup`.",
+    ),
+    ("whoami.nickname", "Nickname: {nickname}"),
+    (
+        "identity.unreadable",
+        "Cannot read your identity key ({error}); move identity.key away to get a new peer id.",
+    ),
+    ("whoami.peer_id", "Peer id:  {peer_id}"),
+    ("whoami.guest", "Guest:    yes (identity ends with this run)"),
+    ("whoami.profile", "Profile:  {name}"),
+    ("whoami.room", "Room:     {name} ({topic}, active)"),
+    ("whoami.other_room", "          {name} ({topic})"),
+    ("whoami.no_room", "Room:     (none)"),
+    ("global.listening", "Global chat"),
+    ("room.claiming", "Claiming room name '{name}'…"),
+    ("room.name_taken", "Room name '{name}' is already taken."),
+    ("room.opened", "Room '{name}' is open."),
+    ("room.idle_closed", "Nobody came to '{name}' for {minutes} min; closing it."),
+    (
+        "room.share",
+        "Others can join with: p2p-games room join --ticket {ticket}",
+    ),
+    ("room.joined", "Joined room '{name}' ({topic})."),
+    (
+        "room.spectating",
+        "Watching room '{name}' ({topic}); you get its chat and games but take no seats.",
+    ),
+    ("room.left", "Left room '{name}'."),
+    ("game.list_title", "Available games:"),
+    ("game.plugin_failed", "Plugin {path} not loaded: {error}"),
+    ("game.unknown", "Unknown game '{game}' (see `room game list`)."),
+    ("game.bad_settings", "Game settings must be a JSON object: {error}"),
+    ("game.player_count", "{game} needs exactly {count} players."),
+    ("game.over", "The game is already over."),
+    ("game.not_your_turn", "It is not your turn."),
+    ("game.bad_move", "'{input}' is not a valid move."),
+    ("game.not_started", "No game is running yet."),
+    ("game.watching", "You are watching this game; spectators cannot move."),
+    ("game.spectator", "You joined room '{room}' as a spectator; use `room game watch`."),
+    ("game.waiting_opponent", "Waiting for an opponent in room '{room}'…"),
+    ("game.waiting_players", "Waiting for {count} players in room '{room}'…"),
+    ("game.waiting_start", "Waiting for a game to start in room '{room}'…"),
+    ("game.seat_taken", "{player} took a seat ({taken}/{seats})."),
+    ("game.dropped.skip_turns", "{player} left; their turns are skipped."),
+    ("game.dropped.redistribute", "{player} left; what they held is shared out."),
+    ("game.dropped.bot", "{player} left; a bot plays for them until they are back."),
+    ("game.dropped.end_game", "{player} left; the game ends."),
+    ("game.rejoined", "{player} is back and plays again."),
+    ("game.player_left", "{player} left"),
+    ("game.bot_playing", "A bot plays your seat until the first seat lets you back in."),
+    ("game.full", "The game in room '{room}' started without us; all seats are taken."),
+    ("game.started", "{game} started: {players}."),
+    ("game.your_turn", "Your turn; enter a move:"),
+    ("game.their_turn", "Waiting for {player} to move…"),
+    ("game.won", "You won!"),
+    ("game.lost", "{player} won."),
+    ("game.draw", "It's a draw."),
+    ("game.aborted", "Game aborted: {reason}"),
+    ("dispute.hint", "Type `dispute <reason>` within {seconds} s to flag the result."),
+    ("dispute.sent", "Result disputed; it does not count until it is revalidated."),
+    ("dispute.received", "{player} disputes the result: {reason}"),
+    ("validation.not_counted", "This result does not count towards win records: {error}"),
+    ("validation.no_replay", "moves were skipped, so the result cannot be checked"),
+    ("validation.outcome_claimed", "the claimed outcome is not where the moves lead"),
+    ("validation.bad_signature", "a signed move does not verify"),
+    ("validation.signed_move_differs", "signed move {seq} differs from the logged one"),
+    ("tictactoe.cell_taken", "That cell is already taken."),
+    ("chess.illegal", "{input} is not a legal move here."),
+    (
+        "battleship.bad_layout",
+        "Enter `random` or the bow cell and direction of each ship, longest first \
+         (e.g. `a1h c3v e5h g2v i7h`); ships must fit on the board without overlapping.",
+    ),
+    ("battleship.no_layout", "The layout you committed to is not stored on this device."),
+    ("battleship.answer_missing", "The last shot at this board has to be answered first."),
+    ("battleship.nothing_to_answer", "There is no shot to answer."),
+    ("battleship.target_missing", "Enter a cell to fire at."),
+    ("battleship.already_shot", "That cell was already fired at."),
+    ("battleship.must_reveal", "A board is sunk; the layout has to be revealed now."),
+    ("battleship.not_over", "Layouts are revealed once a board is sunk."),
+    ("battleship.wrong_phase", "That move does not fit this stage of the game."),
+    ("caps.missing_game", "{peer}'s client does not support {game}."),
+    ("caps.other_version", "{peer}'s client plays {game} v{theirs}, this one v{ours}."),
+    ("deck.not_seated", "Only the players of a match share its deck."),
+    ("deck.bad_pass", "Player {player} sent a malformed deck."),
+    ("deck.corrupt", "The card at position {position} does not open; a player cheated."),
+    ("fair_random.bad_seed", "The seed {peer} revealed does not match its commitment."),
+    ("fair_random.copied", "{peer} sent a commitment another player already made."),
+    ("fair_random.timeout", "Not every player took part in the draw '{draw}' in time."),
+    ("restore.welcome_back", "Welcome back, {nickname}."),
+    (
+        "restore.renamed",
+        "Your nickname '{name}' was taken while you were away; you are now '{nickname}'.",
+    ),
+    ("restore.rooms", "Rejoining {count} room(s)…"),
+    ("restore.games", "Resuming {count} saved game(s)."),
+    ("shutdown.leaving", "Saying goodbye to the network…"),
+    ("shutdown.incomplete", "Could not say goodbye to everyone: {error}"),
+    ("room.switched", "Active room is now '{name}'."),
+    ("room.unknown", "You are not in a room called '{name}'."),
+    (
+        "room.none_active",
+        "No active room. Open or join one first.",
+    ),
+    ("room.not_host", "Only the host of '{name}' can do that."),
+    ("room.kick_self", "You cannot kick yourself."),
+    ("room.kick_done", "Removed {peer} from '{room}'."),
+    ("room.ban_done", "Banned {peer} from '{room}'."),
+    ("room.unban_done", "Lifted the ban of {peer} in '{room}'."),
+    ("room.not_banned", "{peer} is not banned in '{room}'."),
+    ("room.kick_notice", "{peer} was removed from {room}. {reason}"),
+    ("room.kicked", "You were removed from {room}. {reason}"),
+    ("room.banned", "You are banned from {room}. {reason}"),
+    ("room.full", "Room {room} is full. {reason}"),
+    ("room.full_reason", "room full"),
+    ("room.auto_admitted", "{peer} joined {room}; accepted, {count} friend(s) vouch for them."),
+    ("room.join_request", "{peer} joined {room}; {count} friend(s) vouch for them."),
+    ("room.admitted", "Let {peer} into {room}."),
+    ("room.turned_away", "Turned {peer} away from {room}."),
+    ("room.declined", "You were not let into {room}. {reason}"),
+    ("room.declined_reason", "the host did not admit you"),
+    ("room.declined_notice", "Turned {peer} away from {room} ({policy})."),
+    ("admission.open", "open to everyone"),
+    ("admission.friends", "friends only"),
+    ("admission.vouched", "vouched peers only"),
+    ("admission.manual", "host approval"),
+    ("room.bans_title", "Bans in '{room}'"),
+    ("room.bans_entry", "{nickname}  {peer}  {reason}"),
+    ("room.bans_empty", "Nobody is banned."),
+    ("room.listing", "Looking for rooms…"),
+    (
+        "guest.welcome",
+        "Guest session as {nickname}. Nothing is kept after you quit.",
+    ),
+    (
+        "guest.no_login",
+        "Guests cannot claim a nickname; run without --guest to log in.",
+    ),
+    ("profile.invalid", "Cannot use profile {name}: {error}"),
+    ("room.list_empty", "No rooms found."),
+    ("room.list_entry", "{title}  ({details}; {room_id}, host {host})"),
+    ("room.browse_title", "Open rooms"),
+    ("room.browse_entry", "{index}. {title}  ({details})"),
+    ("room.details_game", "{game}, {players}"),
+    ("room.details_language", "{details}, in {language}"),
+    ("room.game_unsupported", "This client cannot play {game}; pick another room."),
+    ("room.browse_prompt", "Number to join, Enter to refresh, q to quit:"),
+    ("room.browse_invalid", "'{answer}' is not a room number."),
+    ("room.players", "{count} player(s)"),
+    ("room.players_of", "{count}/{max} player(s)"),
+    ("room.players_unknown", "players unknown"),
+    ("room.no_ticket", "(host too old to join from the list)"),
+    ("update.up_to_date", "You are running the latest version ({version})."),
+    ("update.available", "Version {version} is available (you have {current}): {url}"),
+    ("update.protocol_changed", "The new release speaks a newer wire protocol; older clients may stop interoperating."),
+    ("history.purged", "Removed {count} stored message(s); nothing from '{peer}' is kept locally."),
+    ("safe.global_hidden", "The global chat is not available in safe mode."),
+    ("safe.room_blocked", "Safe mode: room {room} is not on the allow-list."),
+    ("action.next_pane", "Move focus to the next pane"),
+    ("action.prev_pane", "Move focus to the previous pane"),
+    ("action.scroll_up", "Scroll up one line"),
+    ("action.scroll_down", "Scroll down one line"),
+    ("action.page_up", "Scroll up one page"),
+    ("action.page_down", "Scroll down one page"),
+    ("action.scroll_top", "Jump to the oldest message"),
+    ("action.scroll_bottom", "Jump to the newest message"),
+    ("action.search", "Search the chat"),
+    ("action.next_match", "Jump to the next older match"),
+    ("action.prev_match", "Jump to the next newer match"),
+    ("action.reply", "Reply to the selected message"),
+    ("action.copy_message", "Copy the selected message"),
+    ("action.edit_message", "Edit your selected message"),
+    ("action.delete_message", "Delete your selected message"),
+    ("action.cursor_up", "Select the entry above"),
+    ("action.cursor_down", "Select the entry below"),
+    ("action.help", "Show this help"),
+    ("action.command_palette", "Open the command palette"),
+    ("action.cancel", "Close the overlay or cancel the input"),
+    ("action.quit", "Quit"),
+    ("palette.title", "Commands"),
+    ("palette.no_match", "No command matches '{query}'."),
+    ("help.title", "Keys"),
+    (
+        "summary.chat_games",
+        "Game night in {room} is over: {games} game(s), {messages} chat message(s).",
+    ),
+    ("summary.chat_champion", "Champion: {name} with {wins} win(s)."),
+    ("summary.chat_upset", "Biggest upset: {winner} beat {loser}."),
+    ("summary.title", "Game night in {room}"),
+    ("summary.duration", "Session length: {minutes} min"),
+    ("summary.games", "Games"),
+    ("summary.no_games", "No games were finished."),
+    ("summary.won_by", "won by {name}"),
+    ("summary.no_winner", "no winner"),
+    ("summary.winners", "Winners"),
+    ("summary.upset", "Biggest upset"),
+    (
+        "summary.upset_line",
+        "{winner} beat {loser}, who had {margin} more win(s) before the game.",
+    ),
+    ("summary.chat", "Chat"),
+    ("summary.chat_stats", "{messages} message(s) from {people} people."),
+    ("summary.top_chatter", "Most talkative: {name} ({count} messages)."),
+    ("summary.saved", "Game-night summary saved to {path}."),
+    ("members.stale", "{nickname} went quiet in {room}."),
+    ("keys.title", "Key bindings"),
+    ("keys.entry", "{action}: {keys}"),
+    ("keys.unbound", "(unbound)"),
+    ("history.results", "{count} message(s) match '{query}'."),
+    ("history.empty", "No stored messages."),
+    ("dm.unknown_peer", "Don't know who '{peer}' is; use a nickname seen before or a peer id."),
+    ("dm.self", "You cannot send a direct message to yourself."),
+    ("dm.sent", "Message sent to {peer}."),
+    (
+        "invite.sent",
+        "Invited {peer} to {game}; waiting {seconds} s for an answer… (Ctrl-C to stop)",
+    ),
+    ("invite.declined", "{peer} declined the invitation."),
+    ("invite.expired", "{peer} did not answer the invitation."),
+    ("invite.received", "{peer} invites you to play {game}; answer in `p2p-games tui`."),
+    (
+        "invite.joined",
+        "Joined {room}; take your seat with `p2p-games room game play`.",
+    ),
+    ("clipboard.copied", "Copied to the clipboard."),
+    ("clipboard.failed", "Could not copy to the clipboard: {error}"),
+    ("stats.title", "Your statistics"),
+    ("stats.since", "Counting for {days} day(s); stored locally, never sent anywhere."),
+    ("stats.chat_time", "Time in chat: {minutes} min"),
+    ("stats.peers_met", "Peers met: {count}"),
+    ("stats.games_total", "Games played: {count}"),
+    ("dashboard.title", "Your {game} results"),
+    ("dashboard.title_vs", "Your {game} results against {player}"),
+    ("dashboard.none", "No counted matches yet."),
+    ("dashboard.total", "all opponents"),
+    ("dashboard.col.wins", "won"),
+    ("dashboard.col.losses", "lost"),
+    ("dashboard.col.draws", "drawn"),
+    ("dashboard.col.aborted", "aborted"),
+    ("dashboard.col.games", "games"),
+    ("dashboard.col.avg_moves", "avg moves"),
+    ("dashboard.finish.won", "won"),
+    ("dashboard.finish.lost", "lost"),
+    ("dashboard.finish.drew", "drawn"),
+    ("dashboard.streak", "Current streak: {count} {finish} in a row"),
+    ("dashboard.longest_win_streak", "Longest winning streak: {count}"),
+    ("dashboard.openings", "Favorite openings:"),
+    ("storage.title", "Storage"),
+    ("storage.history", "Chat history"),
+    ("storage.replays", "Match log"),
+    ("storage.summaries", "Summaries"),
+    ("storage.usage", "{category}: {used} of {quota}"),
+    ("storage.usage_unlimited", "{category}: {used} (no limit)"),
+    ("storage.gc_nothing", "Everything fits its quota."),
+    ("storage.gc_history", "Chat history: removed {count} old line(s), freed {freed}."),
+    ("storage.gc_replays", "Match log: dropped {count} old replay(s), freed {freed}."),
+    ("storage.gc_summaries", "Summaries: deleted {count} file(s), freed {freed}."),
+    ("canned.gg", "gg"),
+    ("canned.brb", "brb"),
+    ("canned.rematch?", "Rematch?"),
+    ("repl.ready", "Node is up. Type /help for commands, /quit to leave."),
+    ("repl.nested", "Already in the REPL."),
+    ("tui.stats_usage", "Usage: /stats <game> [nickname]"),
+    ("tui.stats_results", "Per opponent: won, lost, drawn"),
+    ("tui.stats_avg_moves", "Average length: {moves} moves"),
+    (
+        "bridge.none",
+        "No bridges configured. Add [[bridges]] entries with `a` and `b` to config.toml.",
+    ),
+    ("bridge.linked", "Bridging {a} <-> {b} (ttl {ttl})."),
+    ("bridge.unknown_room", "Room '{room}' is not joined; join it before bridging."),
+    ("bridge.from", "{origin} via {room}"),
+    ("websocket.started", "Serving this node to browser pages at {url} (keep the token private). Ctrl-C to stop."),
+    ("chat.edited", "{text} (edited)"),
+    ("chat.deleted", "{from} deleted a message."),
+    ("chat.deleted_in", "{from} deleted a message in room {room}."),
+    ("who.probing", "Looking around the network…"),
+    ("who.title", "Recently active peers"),
+    ("who.peer", "{name}, active {minutes} min ago"),
+    ("who.peer_in_room", "{name} in {room}, active {minutes} min ago"),
+    (
+        "who.count",
+        "About {online} peer(s) online, {known} known. The list is built from what this node has seen.",
+    ),
+    ("trust.unknown", "No peer has been seen with the nickname '{nickname}'."),
+    ("trust.done", "'{nickname}' is now pinned to key {peer}."),
+    ("vouch.done", "You now vouch for {peer}."),
+    ("vouch.revoked", "You no longer vouch for {peer}."),
+    ("vouch.self", "You cannot vouch for yourself."),
+    ("announce.sent", "Announcement published; clients show it for {hours} h."),
+    (
+        "announce.not_operator",
+        "Your key is not under [announcements] operators here; \
+         clients only show announcements of the operators they list.",
+    ),
+    ("announce.info", "Info"),
+    ("announce.maintenance", "Maintenance"),
+    ("announce.release", "New release"),
+    ("announce.tournament", "Tournament"),
+    ("announce.more", "  (+{count} more)"),
+    ("whois.title", "Peer {peer}"),
+    ("whois.nickname", "Nickname: {nickname}"),
+    ("whois.seen", "Last active {minutes} min ago"),
+    ("whois.seen_in_room", "Last active {minutes} min ago in {room}"),
+    ("whois.unknown", "Never seen by this node."),
+    ("whois.vouches", "Vouched for by {count} peer(s), {friends} of them your friends"),
+    ("whois.friend", " (friend)"),
+    ("whois.rating", "Rated {rating} in {game} over {games} attested match(es)"),
+    (
+        "matchmaking.seeking",
+        "Looking for a {game} opponent rated {rating} ± {range}… (Ctrl-C to stop)",
+    ),
+    ("matchmaking.paired", "Matched with {player}; playing in room {room}"),
+    ("matchmaking.cancelled", "Left the matchmaking queue."),
+    (
+        "tournament.created",
+        "Tournament {id} for {game} created. Players join with: p2p-games tournament join {ticket}",
+    ),
+    (
+        "tournament.start_hint",
+        "Press Enter to start with the players so far; it starts by itself at {players}.",
+    ),
+    ("tournament.size", "A tournament has 2 to {max} players."),
+    ("tournament.too_few", "At least two players are needed to start."),
+    ("tournament.entered", "{player} joined ({count}/{max})"),
+    ("tournament.started", "Tournament started with {players} players."),
+    ("tournament.decided", "{winner} beat {loser}"),
+    ("tournament.champion", "{player} won the tournament!"),
+    ("tournament.waiting", "Waiting for the organizer of tournament {id}… (Ctrl-C to stop)"),
+    ("tournament.joined", "You are in; waiting for the start ({count} player(s) so far)."),
+    ("tournament.not_entered", "The tournament started without you."),
+    ("tournament.eliminated", "You are out of the tournament."),
+    ("tournament.left", "Left the tournament without a result."),
+    ("tournament.unknown", "No bracket known for tournament {id}."),
+    ("tournament.none", "No tournaments yet."),
+    ("tournament.title", "{game} tournament {id}"),
+    ("tournament.round", "Round {round}"),
+    ("tournament.pairing", "{home} vs {away}"),
+    ("tournament.pairing_won", "{home} vs {away}: {winner} won"),
+    ("tournament.bye", "{player}: bye"),
+    (
+        "tournament.state.registering",
+        "{id} ({game}): {count} player(s) joined, not started",
+    ),
+    ("tournament.state.round", "{id} ({game}): round {round} of {rounds}"),
+    ("tournament.state.won", "{id} ({game}): won by {player}"),
+    ("rating.updated", "Your {game} rating: {before} → {after}"),
+    ("rating.list_title", "Your ratings"),
+    ("rating.title", "Your {game} rating: {rating}"),
+    ("rating.none", "No rated matches yet."),
+    ("rating.line", "{game}: {rating} after {games} rated match(es)"),
+    ("rating.col.won", "won"),
+    ("rating.col.drawn", "drawn"),
+    ("rating.col.lost", "lost"),
+    ("rating.col.net", "net"),
+    ("rating.recent", "Latest matches:"),
+    ("rating.change", "{before} → {after} against {player} ({opponent_rating})"),
+    (
+        "rating.published",
+        "Published your {game} rating with {games} attested match(es) behind it",
+    ),
+    ("titles.heading", "Titles"),
+    ("titles.unlocked", "{id}: {name}"),
+    ("titles.shown", "{id}: {name} (shown)"),
+    ("titles.locked", "{id}: locked, {goal}"),
+    ("titles.set", "Your title is now {name}."),
+    ("titles.cleared", "No title is shown next to your nickname any more."),
+    ("titles.not_unlocked", "'{id}' is still locked: {goal}."),
+    ("titles.unknown", "There is no title '{id}' (see `title`)."),
+    ("titles.decorated", "{nickname} [{title}]"),
+    ("language.invalid", "'{tag}' is not a language tag (e.g. de, en, pt-BR)."),
+    ("languages.none", "No languages set (e.g. `languages de en`)."),
+    ("languages.shown", "You speak: {languages}"),
+    ("languages.set", "Languages set: {languages}"),
+    ("languages.cleared", "Languages cleared."),
+    ("title.rookie", "Rookie"),
+    ("title.rookie.goal", "finish a game"),
+    ("title.regular", "Regular"),
+    ("title.regular.goal", "finish 25 games"),
+    ("title.veteran", "Veteran"),
+    ("title.veteran.goal", "finish 100 games"),
+    ("title.socialite", "Socialite"),
+    ("title.socialite.goal", "meet 50 peers"),
+    ("title.chatterbox", "Chatterbox"),
+    ("title.chatterbox.goal", "spend 10 hours in chat"),
+    ("tui.global", "Global chat"),
+    ("tui.rooms", "Chats"),
+    ("tui.public_rooms", "Public rooms"),
+    ("tui.members", "Members"),
+    ("tui.online", "Online"),
+    ("tui.input", "Message to {view}"),
+    ("tui.hint", " {help} help · {quit} quit "),
+    ("tui.help_title", "Keys"),
+    ("tui.join_title", "Join request"),
+    ("tui.dms", "Direct messages"),
+    ("tui.member_cannot_play", "{member} (no {game})"),
+    ("tui.member_spectating", "{member} (watching)"),
+    ("tui.dm_usage", "Usage: /dm <nickname or peer id>"),
+    ("tui.dm_no_edit", "Direct messages cannot be edited or deleted."),
+    (
+        "tui.join_prompt",
+        "{peer} wants to join {room}; {count} friend(s) vouch for them. \
+         Let them in? y / n",
+    ),
+    ("tui.invite_title", "Game invitation"),
+    ("tui.invite_prompt", "{peer} invites you to play {game}. Join? y / n"),
+    ("tui.edited", " (edited)"),
+    ("tui.deleted", "message deleted"),
+    ("tui.nothing_sent", "Nothing sent in this chat yet."),
+    ("tui.resent", "Sent your last message again."),
+    ("tui.nothing_picked", "Select a line in the chat pane first."),
+    ("tui.no_ticket", "{room} cannot be joined from the list; its host is too old."),
+    ("tui.palette_title", "Commands"),
+    ("tui.palette_params", "{command} also needs {params}."),
+    ("tui.run_usage", "Usage: /run <command> [args], e.g. /run room list"),
+    ("tui.run_terminal", "{command} needs a terminal of its own."),
+    ("tui.running", "Running {command}…"),
+    ("tui.run_done", "{command} finished."),
+    ("tui.run_failed", "{command} failed: {status}"),
+    ("tui.search", " /{query} · {current}/{total} "),
+    ("tui.neighbors", " {count} neighbor(s) "),
+    ("tui.isolated", " isolated: no peers "),
+    ("tui.not_yours", "Only your own messages can be edited or deleted."),
+    ("debug.tailing", "Tailing {topic} ({hex}), Ctrl-C to stop"),
+    ("debug.envelope", "── v{ver} {kind} from {sender} at {ts}, {size} bytes, {signature}"),
+    ("debug.json", "── v{ver} JSON (not an envelope), {size} bytes"),
+    ("debug.undecodable", "── undecodable frame, {size} bytes"),
+    ("debug.signed", "signature ok"),
+    ("debug.unsigned", "unsigned"),
+    ("debug.bad_signature", "BAD SIGNATURE"),
+    ("debug.bad_compression", "(compressed body could not be inflated)"),
+    ("debug.replay_ok", "ok      {game} {game_id} ({moves} moves)"),
+    ("debug.replay_failed", "FAILED  {game} {game_id}: {error}"),
+    ("debug.replay_summary", "{checked} replays checked, {failed} failed, {skipped} matches without replay."),
+    ("debug.replay_mismatch", "{count} replays no longer match the current rules."),
+    ("debug.dispute_open", "disputed {game_id}: no move log to revalidate it with"),
+    ("debug.dispute_revalidated", "disputed {game_id}: revalidated, the result counts again"),
+    ("debug.dispute_invalid", "disputed {game_id}: the moves do not lead to the recorded result"),
+    ("replay.out_of_turn", "move {index} was played by seat {seat} out of turn"),
+    ("replay.rejected", "move {index} is rejected: {error}"),
+    ("replay.outcome_differs", "recorded outcome {recorded}, replayed {replayed}"),
+    ("replay.state_differs", "the final state differs"),
+    ("replay.unfinished", "none"),
+    ("daemon.started", "Daemon running as {peer_id}; commands attach through {socket}. Ctrl-C to stop."),
+    ("daemon.running", "A daemon is already running; commands use it automatically."),
+    ("daemon.guest", "Guests cannot run the daemon: other commands would not share the identity."),
+    ("daemon.unsupported", "The daemon needs unix sockets, which this platform does not have."),
+    ("daemon.config_reloaded", "Applied the edited config.toml."),
+    ("daemon.config_rejected", "Ignoring the edited config.toml, the previous settings stay: {error}"),
+    ("daemon.config_restart", "Restart the daemon to apply {keys}."),
+    ("daemon.roles", "Serving as an archive node: {roles}."),
+    ("archive.not_found", "No archive node answered with a valid record of match {game_id}."),
+    ("archive.fetched", "Fetched match {game_id} ({game}) into {path}; check it with `debug replay --file`."),
+    ("archive.unknown_match", "No match {game_id} in your match log."),
+    ("archive.deposited", "Handed match {game_id} to the archive nodes online."),
+    ("status.title", "Status"),
+    ("status.namespace", "Network:  private namespace '{namespace}'"),
+    ("status.topic_epoch", "Topic:    {topic} rotated to epoch {epoch}"),
+    ("status.low_power", "Power:    low-power mode"),
+    ("status.safe_mode", "Profile:  safe mode (restricted)"),
+    ("status.middleware", "Wire:     {stages}"),
+    ("status.dual_emit", "Protocol: sending v1 and v{target} until {until} (unix time)"),
+    ("status.connected", "Peers:    {neighbors} neighbor(s) on the subscribed topics (process {pid})"),
+    ("status.isolated", "Peers:    none, isolated from the network (process {pid})"),
+    ("status.subsystems", "Subsystems (process {pid}):"),
+    ("status.subsystem_running", "{name}: running ({restarts} restart(s))"),
+    ("status.subsystem_restarting", "{name}: restarting after: {reason}"),
+    ("status.subsystem_failed", "{name}: failed after {restarts} restart(s): {reason}"),
+    ("status.subsystem_stopped", "{name}: stopped"),
+    ("status.bandwidth_hour", "Traffic this hour:"),
+    ("status.no_traffic", "(none)"),
+    ("status.topic_usage", "{topic}: sent {sent}, received {received}"),
+    ("status.bandwidth_day", "Last 24 hours: sent {sent}, received {received}"),
+    ("status.soft_cap", "Soft cap: {used} of {cap} this hour"),
+    ("status.throttled", "{usage} (exceeded, background traffic paused)"),
+    ("status.no_cap", "Soft cap: off"),
+    ("connectivity.isolated", "You appear isolated: no peers on any subscribed topic. Check your connectivity (`p2p-games setup` re-runs the connectivity test)."),
+    ("connectivity.recovered", "Connected to peers again."),
+    ("connectivity.bind_failed", "Could not start the network node: {error}"),
+    ("setup.title", "Welcome to P2P Games"),
+    (
+        "setup.intro",
+        "Let's get you set up: identity, connectivity and a nickname.",
+    ),
+    ("setup.starting_node", "Starting your node…"),
+    ("setup.identity", "Your peer id: {peer_id}"),
+    ("setup.connectivity_ok", "Connectivity OK (relay {relay})."),
+    (
+        "setup.connectivity_direct_only",
+        "No relay reachable; only direct connections will work.",
+    ),
+    (
+        "setup.connectivity_failed",
+        "No relay and no direct addresses; you may be offline.",
+    ),
+    ("setup.ask_nickname", "Pick a nickname:"),
+    ("setup.ask_nickname_default", "Pick a nickname [{nickname}]:"),
+    ("setup.nickname_taken", "'{name}' is already taken."),
+    ("setup.accept_suggestion", "Use '{suggestion}' instead?"),
+    ("setup.done", "All set, {nickname}!"),
+    ("setup.join_global", "Join the global chat now?"),
+    (
+        "setup.hint",
+        "Tip: run `p2p-games global listen` to join the global chat.",
+    ),
+    ("output.warning", "Warning: {text}"),
+    ("output.error", "Error: {text}"),
+    ("output.board", "board: {rows}"),
+    ("output.last_move", "last move {cell}"),
+    ("output.hand", "hand: {cards}"),
+    ("output.card_selected", "{card} (selected)"),
+    ("output.card_unplayable", "{card} (cannot be played)"),
+    (
+        "event.chat.global",
+        "{from} says in the global chat: {text}",
+    ),
+    ("event.chat.room", "{from} says in room {room}: {text}"),
+    ("event.direct", "{from} says to you: {text}"),
+    ("event.member_joined", "{nickname} joined the room."),
+    ("event.member_left", "{nickname} left the room."),
+    ("event.move", "{player} plays {cell}"),
+    (
+        "event.nick_conflict_lost",
+        "Peer {winner} holds an older claim on '{nickname}'; other peers now see the name as theirs.",
+    ),
+    (
+        "event.key_mismatch",
+        "WARNING: '{nickname}' is now claimed by key {seen}, but was first seen with key {pinned}. This may be an impersonation; run `trust {nickname}` only if you know the peer changed keys.",
+    ),
+    (
+        "nick.conflict_rename",
+        "Switch to '{suggestion}'? Answer no to keep '{nickname}' and dispute the claim.",
+    ),
+    (
+        "nick.disputed",
+        "Re-announced your claim on '{nickname}'; peers keep it yours only if it is older.",
+    ),
+];
+
+/// A resolved set of messages for one locale.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    locale: String,
+    strings: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// Catalog containing only the built-in English strings.
+    pub fn english() -> Self {
+        Self {
+            locale: DEFAULT_LOCALE.to_string(),
+            strings: EN
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Build a catalog for `locale`, layering the translation file (if any)
+    /// over English.
+    ///
+    /// Looks for `<locale>.toml` first, then the bare language (`de-CH` → `de`).
+    /// Unreadable or malformed files are ignored so a broken translation never
+    /// prevents the program from starting.
+    pub fn load(locale: &str) -> Self {
+        let mut catalog = Self::english();
+        catalog.locale = locale.to_string();
+        if locale == DEFAULT_LOCALE {
+            return catalog;
+        }
+
+        let dir = Config::dir().join("locales");
+        let lang = locale.split('-').next().unwrap_or(locale);
+        for name in [locale, lang] {
+            let Ok(s) = fs::read_to_string(dir.join(format!("{name}.toml"))) else {
+                continue;
+            };
+            if let Ok(map) = toml::from_str::<HashMap<String, String>>(&s) {
+                catalog.strings.extend(map);
+                break;
+            }
+        }
+        catalog
+    }
+
+    /// Locale this catalog was built for.
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    /// Raw message for `key`; unknown keys are returned verbatim so gaps are visible.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.strings.get(key).map(String::as_str).unwrap_or(key)
+    }
+
+    /// Message for `key` with `{name}` placeholders substituted in one pass,
+    /// so a value that itself contains `{name}` is left as it is.
+    pub fn format(&self, key: &str, args: &[(&str, &str)]) -> String {
+        let mut rest = self.get(key);
+        let mut out = String::with_capacity(rest.len());
+        while let Some(open) = rest.find('{') {
+            out.push_str(&rest[..open]);
+            let tail = &rest[open..];
+            let arg = tail.find('}').and_then(|close| {
+                let value = args.iter().find(|(name, _)| *name == &tail[1..close])?.1;
+                Some((value, close))
+            });
+            match arg {
+                Some((value, close)) => {
+                    out.push_str(value);
+                    rest = &tail[close + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &tail[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Pick the locale: explicit config value, then `LANG` (`de_CH.UTF-8` → `de-CH`),
+/// then [`DEFAULT_LOCALE`].
+pub fn resolve_locale(configured: Option<&str>) -> String {
+    if let Some(l) = configured.filter(|l| !l.is_empty()) {
+        return l.to_string();
+    }
+    std::env::var("LANG")
+        .ok()
+        .map(|l| l.split('.').next().unwrap_or_default().replace('_', "-"))
+        .filter(|l| !l.is_empty() && l != "C" && l != "POSIX")
+        .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+}
+
+/// Install the process-wide catalog. Later calls are no-ops.
+pub fn init(config: &Config) {
+    let locale = resolve_locale(config.locale.as_deref());
+    let _ = CATALOG.set(Catalog::load(&locale));
+}
+
+/// Process-wide catalog (English if [`init`] was never called).
+pub fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(Catalog::english)
+}
+
+/// Translate `key` using the process-wide catalog.
+pub fn tr(key: &str) -> String {
+    catalog().get(key).to_string()
+}
+
+/// Translate `key` and substitute `{name}` placeholders.
+pub fn tr_args(key: &str, args: &[(&str, &str)]) -> String {
+    catalog().format(key, args)
+}
+
+/// Translate a message key, optionally with named placeholder values.
+///
+/// ```ignore
+/// println!("{}", t!("global.say.stub", text = text));
+/// ```
+#[macro_export]
+macro_rules! t {
+    ($key:expr) => {
+        $crate::i18n::tr($key)
+    };
+    ($key:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::tr_args($key, &[$((stringify!($name), $value.to_string().as_str())),+])
+    };
+}
@@ -0,0 +1,379 @@
+//! Key bindings for interactive front ends.
+//!
+//! Bindings come from the `[keymap]` section of `config.toml`:
+//!
+//! ```toml
+//! [keymap]
+//! preset = "vim"            # or "default"
+//! [keymap.bindings]
+//! next_pane = ["tab", "ctrl+w"]
+//! quit = ["ctrl+q"]
+//! ```
+//!
+//! Entries in `bindings` replace the preset's keys for that action. Keys are
+//! written as chords: optional `ctrl+`, `alt+`, `shift+` modifiers followed
+//! by a character or a key name (`enter`, `esc`, `tab`, `up`, `pageup`, `f1`…).
+//!
+//! [`KeymapReloader`] re-reads the config when the file changes so edits
+//! take effect without restarting.
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr, time::SystemTime};
+
+use crate::{config::Config, t};
+
+/// Something the user can trigger with a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    NextPane,
+    PrevPane,
+    ScrollUp,
+    ScrollDown,
+    PageUp,
+    PageDown,
+    ScrollTop,
+    ScrollBottom,
+    Search,
+    NextMatch,
+    PrevMatch,
+    Reply,
+    CopyMessage,
+    EditMessage,
+    DeleteMessage,
+    CursorUp,
+    CursorDown,
+    Help,
+    CommandPalette,
+    Cancel,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: &[Action] = &[
+        Action::NextPane,
+        Action::PrevPane,
+        Action::ScrollUp,
+        Action::ScrollDown,
+        Action::PageUp,
+        Action::PageDown,
+        Action::ScrollTop,
+        Action::ScrollBottom,
+        Action::Search,
+        Action::NextMatch,
+        Action::PrevMatch,
+        Action::Reply,
+        Action::CopyMessage,
+        Action::EditMessage,
+        Action::DeleteMessage,
+        Action::CursorUp,
+        Action::CursorDown,
+        Action::Help,
+        Action::CommandPalette,
+        Action::Cancel,
+        Action::Quit,
+    ];
+
+    /// Name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::NextPane => "next_pane",
+            Action::PrevPane => "prev_pane",
+            Action::ScrollUp => "scroll_up",
+            Action::ScrollDown => "scroll_down",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::ScrollTop => "scroll_top",
+            Action::ScrollBottom => "scroll_bottom",
+            Action::Search => "search",
+            Action::NextMatch => "next_match",
+            Action::PrevMatch => "prev_match",
+            Action::Reply => "reply",
+            Action::CopyMessage => "copy_message",
+            Action::EditMessage => "edit_message",
+            Action::DeleteMessage => "delete_message",
+            Action::CursorUp => "cursor_up",
+            Action::CursorDown => "cursor_down",
+            Action::Help => "help",
+            Action::CommandPalette => "command_palette",
+            Action::Cancel => "cancel",
+            Action::Quit => "quit",
+        }
+    }
+
+    /// What the action does, for the help overlay.
+    pub fn describe(self) -> String {
+        t!(&format!("action.{}", self.name()))
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Esc,
+    Tab,
+    BackTab,
+    Backspace,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    F(u8),
+}
+
+/// A key plus modifiers, e.g. `ctrl+w`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub key: Key,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl KeyChord {
+    pub fn plain(key: Key) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            alt: false,
+            shift: false,
+        }
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut chord = KeyChord::plain(Key::Esc);
+        let mut parts: Vec<&str> = s.split('+').collect();
+        // A literal "+" key ends in an empty part.
+        let last = match parts.pop() {
+            Some("") if s.ends_with('+') => "+",
+            Some(p) => p,
+            None => bail!("empty key"),
+        };
+        for m in parts.iter().filter(|p| !p.is_empty()) {
+            match m.to_ascii_lowercase().as_str() {
+                "ctrl" => chord.ctrl = true,
+                "alt" | "meta" => chord.alt = true,
+                "shift" => chord.shift = true,
+                other => bail!("unknown modifier '{other}' in '{s}'"),
+            }
+        }
+        let lower = last.to_ascii_lowercase();
+        chord.key = match lower.as_str() {
+            "enter" | "return" => Key::Enter,
+            "esc" | "escape" => Key::Esc,
+            "tab" if chord.shift => Key::BackTab,
+            "tab" => Key::Tab,
+            "backtab" => Key::BackTab,
+            "backspace" => Key::Backspace,
+            "delete" | "del" => Key::Delete,
+            "up" => Key::Up,
+            "down" => Key::Down,
+            "left" => Key::Left,
+            "right" => Key::Right,
+            "pageup" | "pgup" => Key::PageUp,
+            "pagedown" | "pgdn" => Key::PageDown,
+            "home" => Key::Home,
+            "end" => Key::End,
+            "space" => Key::Char(' '),
+            f if f.len() > 1 && f.starts_with('f') && f[1..].parse::<u8>().is_ok() => {
+                Key::F(f[1..].parse()?)
+            }
+            _ => {
+                let mut chars = last.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Key::Char(c),
+                    _ => return Err(anyhow!("unknown key '{last}' in '{s}'")),
+                }
+            }
+        };
+        if chord.key == Key::BackTab {
+            chord.shift = false;
+        }
+        Ok(chord)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "alt+")?;
+        }
+        if self.shift {
+            write!(f, "shift+")?;
+        }
+        match self.key {
+            Key::Char(' ') => write!(f, "space"),
+            Key::Char(c) => write!(f, "{c}"),
+            Key::Enter => write!(f, "enter"),
+            Key::Esc => write!(f, "esc"),
+            Key::Tab => write!(f, "tab"),
+            Key::BackTab => write!(f, "shift+tab"),
+            Key::Backspace => write!(f, "backspace"),
+            Key::Delete => write!(f, "delete"),
+            Key::Up => write!(f, "up"),
+            Key::Down => write!(f, "down"),
+            Key::Left => write!(f, "left"),
+            Key::Right => write!(f, "right"),
+            Key::PageUp => write!(f, "pageup"),
+            Key::PageDown => write!(f, "pagedown"),
+            Key::Home => write!(f, "home"),
+            Key::End => write!(f, "end"),
+            Key::F(n) => write!(f, "f{n}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// Arrow keys, Tab and Ctrl shortcuts.
+    #[default]
+    Default,
+    /// Default plus `j`/`k` movement, `g`/`G` jumps and `ctrl+b`/`ctrl+f` paging.
+    Vim,
+}
+
+/// `[keymap]` section of `config.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeymapConfig {
+    pub preset: Preset,
+    /// Action name → keys; replaces the preset's keys for that action.
+    pub bindings: BTreeMap<String, Vec<String>>,
+}
+
+/// Resolved bindings.
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    bindings: BTreeMap<Action, Vec<KeyChord>>,
+}
+
+fn preset_keys(preset: Preset, action: Action) -> &'static [&'static str] {
+    let default: &[&str] = match action {
+        Action::NextPane => &["tab"],
+        Action::PrevPane => &["shift+tab"],
+        Action::ScrollUp => &["up"],
+        Action::ScrollDown => &["down"],
+        Action::PageUp => &["pageup"],
+        Action::PageDown => &["pagedown"],
+        Action::ScrollTop => &["home"],
+        Action::ScrollBottom => &["end"],
+        Action::Search => &["/"],
+        Action::NextMatch => &["n"],
+        Action::PrevMatch => &["N"],
+        Action::Reply => &["ctrl+r"],
+        Action::CopyMessage => &["ctrl+y"],
+        Action::EditMessage => &["ctrl+e"],
+        Action::DeleteMessage => &["ctrl+d"],
+        Action::CursorUp => &["up"],
+        Action::CursorDown => &["down"],
+        Action::Help => &["?", "f1"],
+        Action::CommandPalette => &["ctrl+p"],
+        Action::Cancel => &["esc"],
+        Action::Quit => &["ctrl+c", "ctrl+q"],
+    };
+    if preset == Preset::Default {
+        return default;
+    }
+    match action {
+        Action::NextPane => &["tab", "ctrl+w"],
+        Action::ScrollUp => &["up", "k"],
+        Action::ScrollDown => &["down", "j"],
+        Action::PageUp => &["pageup", "ctrl+b"],
+        Action::PageDown => &["pagedown", "ctrl+f"],
+        Action::ScrollTop => &["home", "g"],
+        Action::ScrollBottom => &["end", "G"],
+        Action::Reply => &["ctrl+r", "r"],
+        Action::CopyMessage => &["ctrl+y", "y"],
+        Action::CursorUp => &["up", "k"],
+        Action::CursorDown => &["down", "j"],
+        _ => default,
+    }
+}
+
+impl Keymap {
+    /// Build from config; unknown actions or unparsable keys are errors.
+    pub fn from_config(cfg: &KeymapConfig) -> Result<Self> {
+        let mut bindings = BTreeMap::new();
+        for &action in Action::ALL {
+            let keys = preset_keys(cfg.preset, action)
+                .iter()
+                .map(|k| k.parse())
+                .collect::<Result<Vec<KeyChord>>>()?;
+            bindings.insert(action, keys);
+        }
+        for (name, keys) in &cfg.bindings {
+            let action =
+                Action::from_name(name).ok_or_else(|| anyhow!("unknown keymap action '{name}'"))?;
+            let keys = keys
+                .iter()
+                .map(|k| k.parse())
+                .collect::<Result<Vec<KeyChord>>>()?;
+            bindings.insert(action, keys);
+        }
+        Ok(Self { bindings })
+    }
+
+    /// Actions bound to `chord`. Several are possible (e.g. `up` scrolls
+    /// the chat and moves the selection); the focused pane picks.
+    pub fn actions(&self, chord: &KeyChord) -> Vec<Action> {
+        self.bindings
+            .iter()
+            .filter(|(_, keys)| keys.contains(chord))
+            .map(|(a, _)| *a)
+            .collect()
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyChord] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Re-reads the keymap when `config.toml` changes on disk.
+pub struct KeymapReloader {
+    modified: Option<SystemTime>,
+}
+
+impl Default for KeymapReloader {
+    fn default() -> Self {
+        Self {
+            modified: Config::modified(),
+        }
+    }
+}
+
+impl KeymapReloader {
+    /// The new keymap if the file changed since the last call.
+    ///
+    /// A broken edit returns the error once and keeps the old bindings.
+    pub fn poll(&mut self) -> Option<Result<Keymap>> {
+        let modified = Config::modified();
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(
+            Config::load()
+                .map_err(anyhow::Error::from)
+                .and_then(|c| Keymap::from_config(&c.keymap)),
+        )
+    }
+}
@@ -0,0 +1,50 @@
+//! Languages of rooms and players.
+//!
+//! Hosts can tag the rooms they announce with the language spoken there
+//! (`room open --lang de`), and `room list --lang de` shows only rooms
+//! tagged with it. A profile can list the languages its player speaks
+//! (`languages de en`); matchmaking prefers opponents who share one.
+//!
+//! Tags are IETF language tags such as `de` or `pt-BR`. Only the primary
+//! language is compared, so `pt-BR` and `pt` match.
+
+use anyhow::{Result, bail};
+
+use crate::t;
+
+/// `tag` in canonical casing (`pt-br` → `pt-BR`), if it looks like a
+/// language tag.
+pub fn normalize(tag: &str) -> Result<String> {
+    let mut parts = tag.trim().split(['-', '_']);
+    let primary = parts.next().unwrap_or_default();
+    if !(2..=3).contains(&primary.len()) || !primary.chars().all(|c| c.is_ascii_alphabetic()) {
+        bail!(t!("language.invalid", tag = tag));
+    }
+    let mut out = primary.to_ascii_lowercase();
+    for part in parts {
+        if part.is_empty() || part.len() > 8 || !part.chars().all(|c| c.is_ascii_alphanumeric()) {
+            bail!(t!("language.invalid", tag = tag));
+        }
+        out.push('-');
+        if part.len() == 2 {
+            out.push_str(&part.to_ascii_uppercase());
+        } else {
+            out.push_str(&part.to_ascii_lowercase());
+        }
+    }
+    Ok(out)
+}
+
+fn primary(tag: &str) -> &str {
+    tag.split(['-', '_']).next().unwrap_or(tag)
+}
+
+/// Whether two tags name the same language.
+pub fn same(a: &str, b: &str) -> bool {
+    primary(a).eq_ignore_ascii_case(primary(b))
+}
+
+/// Whether two lists of tags have a language in common.
+pub fn shared(a: &[String], b: &[String]) -> bool {
+    a.iter().any(|x| b.iter().any(|y| same(x, y)))
+}
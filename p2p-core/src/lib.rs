@@ -1,4 +1,68 @@
 pub mod protocol;
 pub mod session;
 pub mod registry;
-pub mod discovery;
\ No newline at end of file
+pub mod discovery;
+pub mod config;
+pub mod i18n;
+pub mod output;
+pub mod notify;
+pub mod update;
+pub mod stats;
+pub mod bandwidth;
+pub mod power;
+pub mod namespace;
+pub mod connectivity;
+pub mod wire;
+pub mod history;
+pub mod profanity;
+pub mod safe_mode;
+pub mod rooms;
+pub mod browser;
+pub mod widgets;
+pub mod keymap;
+pub mod search;
+pub mod supervisor;
+pub mod game;
+pub mod timeouts;
+pub mod tictactoe;
+pub mod turn;
+pub mod signing;
+pub mod directory;
+pub mod bridge;
+pub mod palette;
+pub mod matchlog;
+pub mod summary;
+pub mod membership;
+pub mod middleware;
+pub mod bans;
+pub mod dm;
+pub mod error;
+pub mod replay;
+pub mod bootstrap;
+pub mod hlc;
+pub mod drafts;
+pub mod fair_random;
+pub mod vouch;
+pub mod titles;
+pub mod dispute;
+pub mod validation;
+pub mod dashboard;
+pub mod canned;
+pub mod chess;
+pub mod storage;
+pub mod commitment;
+pub mod battleship;
+pub mod deck;
+pub mod admission;
+pub mod announce;
+pub mod scheduler;
+pub mod rating;
+pub mod digest;
+pub mod matchmaking;
+pub mod tournament;
+pub mod plugin;
+pub mod language;
+pub mod archive;
+pub mod sybil;
+#[cfg(unix)]
+pub mod daemon;
@@ -0,0 +1,143 @@
+//! Results of finished matches.
+//!
+//! Every match this node took part in is appended to
+//! `<data dir>/p2p-games/matches.jsonl` when it ends, one JSON object per
+//! line. The game-night summary and win counts are computed from the
+//! results [`crate::validation`] accepts, and `debug replay` checks the
+//! recorded [`Replay`]s against the current rules. Replays of old matches
+//! are dropped when the log outgrows its quota (see [`crate::storage`]).
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use crate::{game::Outcome, replay::Replay, session::data_dir, storage};
+
+/// One finished match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchRecord {
+    pub game_id: String,
+    /// [`crate::game::Game::NAME`].
+    pub game: String,
+    /// Topic hex of the room, as used for `room_id` in room envelopes.
+    pub room: String,
+    /// Peer ids in seat order.
+    pub players: Vec<String>,
+    pub outcome: Outcome,
+    /// Moves played.
+    pub moves: u64,
+    /// Unix millis.
+    pub ended_at: u64,
+    /// Every move, if this node saw all of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay: Option<Replay>,
+    /// Move envelopes received from the other players, with their
+    /// signatures; see [`crate::validation`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signed_moves: Vec<Value>,
+    /// `replay` and `signed_moves` were dropped to save space (see
+    /// [`MatchLog::drop_replays`]) after they passed validation.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub validated: bool,
+}
+
+impl MatchRecord {
+    /// Peer id of the winner, if there is one.
+    pub fn winner(&self) -> Option<&str> {
+        match self.outcome {
+            Outcome::Winner { seat } => self.players.get(seat).map(String::as_str),
+            _ => None,
+        }
+    }
+}
+
+/// Append-only `matches.jsonl`.
+pub struct MatchLog {
+    path: PathBuf,
+}
+
+impl MatchLog {
+    pub fn open() -> Self {
+        Self::at(data_dir().join("matches.jsonl"))
+    }
+
+    /// A log in another place, e.g. recorded replays kept for CI.
+    pub fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn append(&self, record: &MatchRecord) -> std::io::Result<()> {
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut line = serde_json::to_vec(record).unwrap();
+        line.push(b'\n');
+        f.write_all(&line)
+    }
+
+    /// Bytes the log takes on disk.
+    pub fn bytes(&self) -> std::io::Result<u64> {
+        storage::file_len(&self.path)
+    }
+
+    /// Drop the replays and signed moves of the oldest matches until the
+    /// log fits `max_bytes`; the results stay. Whether a record passed
+    /// validation is decided by `valid` first and kept in
+    /// [`MatchRecord::validated`]. Returns the number of dropped replays.
+    pub fn drop_replays(
+        &self,
+        max_bytes: u64,
+        valid: impl Fn(&MatchRecord) -> bool,
+    ) -> std::io::Result<usize> {
+        let mut records = self.load()?;
+        let mut total: u64 = records.iter().map(storage::line_len).sum();
+        let mut dropped = 0;
+        for r in &mut records {
+            if total <= max_bytes {
+                break;
+            }
+            if r.replay.is_none() && r.signed_moves.is_empty() {
+                continue;
+            }
+            let before = storage::line_len(r);
+            r.validated = valid(r);
+            r.replay = None;
+            r.signed_moves.clear();
+            total = total + storage::line_len(r) - before;
+            dropped += 1;
+        }
+        if dropped > 0 {
+            self.rewrite(&records)?;
+        }
+        Ok(dropped)
+    }
+
+    fn rewrite(&self, records: &[MatchRecord]) -> std::io::Result<()> {
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut out = Vec::new();
+        for r in records {
+            out.extend(serde_json::to_vec(r).unwrap());
+            out.push(b'\n');
+        }
+        fs::write(&tmp, out)?;
+        fs::rename(tmp, &self.path)
+    }
+
+    /// All records, oldest first. Unreadable lines are skipped.
+    pub fn load(&self) -> std::io::Result<Vec<MatchRecord>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let f = fs::File::open(&self.path)?;
+        Ok(BufReader::new(f)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|l| serde_json::from_str(&l).ok())
+            .collect())
+    }
+}
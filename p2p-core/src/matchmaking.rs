@@ -0,0 +1,193 @@
+//! Finding an opponent without browsing rooms.
+//!
+//! `match <game>` puts a peer in the queue: it publishes a signed
+//! [`MatchBody::Seek`] with its rating in the game (see [`crate::rating`])
+//! on [`MATCHMAKING_TOPIC_NAME`] and repeats it every [`SEEK_REFRESH`].
+//! Every peer in the queue sees the same seekers and pairs them with the
+//! same rule ([`Queue::pairs`]): oldest seeker first, each with the unpaired
+//! seeker of the same game closest in rating, as long as the difference is
+//! within the range both accept. Seekers who speak a language in common
+//! (see [`crate::language`]) are preferred over closer ratings.
+//!
+//! Views of the queue can differ for a moment, so a pair only counts after a
+//! handshake: the peer with the smaller id hosts, opens a room on a fresh
+//! topic and sends an [`MatchBody::Offer`] until its partner answers with
+//! [`MatchBody::Accept`]. Both then leave the queue and play in the room.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::{
+    language,
+    protocol::{Envelope, Kind, Scope, make_envelope, now_ms},
+};
+
+pub const MATCHMAKING_TOPIC_NAME: &str = "p2p-matchmaking";
+
+/// How often a seeker repeats its [`MatchBody::Seek`].
+pub const SEEK_REFRESH: Duration = Duration::from_secs(10);
+
+/// Seekers not heard from for this long are dropped from the queue.
+pub const SEEK_TTL_MS: u64 = 30_000;
+
+/// Rating difference accepted when none is given.
+pub const DEFAULT_RANGE: u32 = 200;
+
+/// Messages on the matchmaking topic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MatchBody {
+    /// The sender wants to play `game` against someone rated within
+    /// `range` of its `rating`.
+    Seek {
+        game: String,
+        rating: u32,
+        range: u32,
+        /// When the sender joined the queue (unix millis); older seekers are
+        /// paired first.
+        since: u64,
+        /// Languages the sender speaks, best first; absent from older
+        /// clients.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        languages: Vec<String>,
+        /// Proof of work on the sender's key, see [`crate::sybil`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pow: Option<u64>,
+    },
+    /// The sender left the queue.
+    Cancel,
+    /// The host of a pair invites `peer_id` to the room on `topic_hex`.
+    Offer {
+        peer_id: String,
+        game: String,
+        topic_hex: String,
+    },
+    /// `peer_id`'s offer of the room on `topic_hex` is taken.
+    Accept { peer_id: String, topic_hex: String },
+}
+
+pub fn make_match(sender_id: String, body: MatchBody) -> Envelope<MatchBody> {
+    make_envelope(
+        Kind::Matchmaking,
+        Scope::Global,
+        None,
+        sender_id,
+        now_ms(),
+        body,
+    )
+}
+
+/// A peer in the queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seeker {
+    pub game: String,
+    pub rating: u32,
+    pub range: u32,
+    pub since: u64,
+    pub languages: Vec<String>,
+    /// Last [`MatchBody::Seek`] heard (unix millis).
+    pub seen: u64,
+}
+
+impl Seeker {
+    fn accepts(&self, other: &Seeker) -> bool {
+        self.game == other.game && self.rating.abs_diff(other.rating) <= self.range.min(other.range)
+    }
+}
+
+/// The queue as heard on the matchmaking topic.
+#[derive(Debug, Default)]
+pub struct Queue {
+    seekers: BTreeMap<String, Seeker>,
+}
+
+impl Queue {
+    /// Take in a signed message; unsigned ones are ignored, since anyone
+    /// could queue or cancel in someone else's name.
+    pub fn apply(&mut self, env: &Envelope<MatchBody>) {
+        if env.sig.is_none() {
+            return;
+        }
+        match &env.body {
+            MatchBody::Seek {
+                game,
+                rating,
+                range,
+                since,
+                languages,
+                ..
+            } => {
+                let seeker = Seeker {
+                    game: game.clone(),
+                    rating: *rating,
+                    range: *range,
+                    since: *since,
+                    languages: languages.clone(),
+                    seen: now_ms(),
+                };
+                self.insert(&env.sender_id, seeker);
+            }
+            MatchBody::Cancel | MatchBody::Accept { .. } => {
+                self.seekers.remove(&env.sender_id);
+            }
+            MatchBody::Offer { .. } => {}
+        }
+    }
+
+    /// Put `peer_id` in the queue, or refresh it.
+    pub fn insert(&mut self, peer_id: &str, seeker: Seeker) {
+        self.seekers.insert(peer_id.to_string(), seeker);
+    }
+
+    /// Drop seekers not heard from for [`SEEK_TTL_MS`].
+    pub fn expire(&mut self, now: u64) {
+        self.seekers
+            .retain(|_, s| now.saturating_sub(s.seen) < SEEK_TTL_MS);
+    }
+
+    /// All pairs, the same on every peer that sees the same seekers: in the
+    /// order they joined, each unpaired seeker is paired with the unpaired
+    /// one both accept that shares a language, if any, and is closest in
+    /// rating, ties going to the one that joined first.
+    pub fn pairs(&self) -> Vec<(String, String)> {
+        let mut order: Vec<(&String, &Seeker)> = self.seekers.iter().collect();
+        order.sort_by(|(a, sa), (b, sb)| (sa.since, *a).cmp(&(sb.since, *b)));
+        let mut paired: Vec<&String> = Vec::new();
+        let mut pairs = Vec::new();
+        for (i, (peer, seeker)) in order.iter().enumerate() {
+            if paired.contains(peer) {
+                continue;
+            }
+            let partner = order[i + 1..]
+                .iter()
+                .filter(|(p, s)| !paired.contains(p) && seeker.accepts(s))
+                .min_by_key(|(_, s)| {
+                    let foreign = !language::shared(&s.languages, &seeker.languages);
+                    (foreign, s.rating.abs_diff(seeker.rating))
+                });
+            if let Some((other, _)) = partner {
+                paired.extend([*peer, *other]);
+                pairs.push(((*peer).clone(), (*other).clone()));
+            }
+        }
+        pairs
+    }
+
+    /// The peer `peer_id` is paired with, if any.
+    pub fn partner(&self, peer_id: &str) -> Option<String> {
+        self.pairs().into_iter().find_map(|(a, b)| {
+            if a == peer_id {
+                Some(b)
+            } else if b == peer_id {
+                Some(a)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// Whether `me` hosts the match with `partner`.
+pub fn hosts(me: &str, partner: &str) -> bool {
+    me < partner
+}
@@ -0,0 +1,139 @@
+//! Room membership from heartbeats.
+//!
+//! Every member publishes [`RoomBody::Heartbeat`] on the room topic at the
+//! heartbeat period of its power profile. [`MemberTracker`] keeps the last
+//! sign of life of each peer; members silent for longer than
+//! `[timeouts] member_stale_ms` are marked stale and dropped, and the host
+//! broadcasts the new [`RoomBody::Members`] list whenever it changed.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::protocol::{Member, MemberRole, RoomBody, now_ms};
+
+#[derive(Debug, Clone)]
+struct Tracked {
+    nickname: String,
+    title: Option<String>,
+    role: MemberRole,
+    last_seen: u64,
+}
+
+/// Live members of one room.
+#[derive(Debug, Clone)]
+pub struct MemberTracker {
+    stale_after: u64,
+    members: BTreeMap<String, Tracked>,
+    /// The member list changed since the last [`Self::take_changed`].
+    changed: bool,
+}
+
+impl MemberTracker {
+    pub fn new(stale_after: Duration) -> Self {
+        Self {
+            stale_after: stale_after.as_millis() as u64,
+            members: BTreeMap::new(),
+            changed: false,
+        }
+    }
+
+    /// A heartbeat from `peer_id`.
+    pub fn heartbeat(
+        &mut self,
+        peer_id: &str,
+        nickname: &str,
+        title: Option<&str>,
+        role: MemberRole,
+    ) {
+        self.heartbeat_at(peer_id, nickname, title, role, now_ms());
+    }
+
+    pub fn heartbeat_at(
+        &mut self,
+        peer_id: &str,
+        nickname: &str,
+        title: Option<&str>,
+        role: MemberRole,
+        at: u64,
+    ) {
+        match self.members.get_mut(peer_id) {
+            Some(m) => {
+                if m.nickname != nickname || m.title.as_deref() != title || m.role != role {
+                    m.nickname = nickname.to_string();
+                    m.title = title.map(str::to_string);
+                    m.role = role;
+                    self.changed = true;
+                }
+                m.last_seen = m.last_seen.max(at);
+            }
+            None => {
+                self.members.insert(
+                    peer_id.to_string(),
+                    Tracked {
+                        nickname: nickname.to_string(),
+                        title: title.map(str::to_string),
+                        role,
+                        last_seen: at,
+                    },
+                );
+                self.changed = true;
+            }
+        }
+    }
+
+    /// Any other traffic from `peer_id` also counts as a sign of life, but
+    /// only heartbeats make someone a member.
+    pub fn touch(&mut self, peer_id: &str) {
+        if let Some(m) = self.members.get_mut(peer_id) {
+            m.last_seen = m.last_seen.max(now_ms());
+        }
+    }
+
+    /// `peer_id` said goodbye; returns whether it was a member.
+    pub fn left(&mut self, peer_id: &str) -> bool {
+        let was = self.members.remove(peer_id).is_some();
+        self.changed |= was;
+        was
+    }
+
+    /// Drop members not heard from within the stale timeout; returns their
+    /// nicknames.
+    pub fn sweep(&mut self, now: u64) -> Vec<String> {
+        let stale_after = self.stale_after;
+        let mut stale = Vec::new();
+        self.members.retain(|_, m| {
+            let alive = now.saturating_sub(m.last_seen) < stale_after;
+            if !alive {
+                stale.push(m.nickname.clone());
+            }
+            alive
+        });
+        self.changed |= !stale.is_empty();
+        stale
+    }
+
+    /// Whether the list changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub fn members(&self) -> Vec<Member> {
+        self.members
+            .iter()
+            .map(|(peer_id, m)| Member {
+                peer_id: peer_id.clone(),
+                nickname: m.nickname.clone(),
+                title: m.title.clone(),
+                role: m.role,
+            })
+            .collect()
+    }
+
+    /// The `Members` update the host sends for `room_id`.
+    pub fn update(&self, room_id: &str, host_id: &str) -> RoomBody {
+        RoomBody::Members {
+            room_id: room_id.to_string(),
+            host_id: host_id.to_string(),
+            members: self.members(),
+        }
+    }
+}
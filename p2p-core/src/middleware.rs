@@ -0,0 +1,292 @@
+//! Envelope middleware on the send and receive paths.
+//!
+//! A [`Pipeline`] is an ordered list of [`Middleware`] stages working on
+//! the JSON form of an envelope. Outgoing envelopes pass the stages first
+//! to last, incoming ones last to first, so each stage undoes on receive
+//! what it did on send and sees the envelope in the same shape both ways.
+//! The built-in stages are chosen in `config.toml`:
+//!
+//! ```toml
+//! [protocol]
+//! middleware = ["dedup", "sign", "compress"]
+//! ```
+//!
+//! which signs, then compresses on send, and decompresses, verifies, then
+//! drops duplicates on receive. Further stages (e.g. room encryption) are
+//! added in code with [`Pipeline::push`].
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use crate::signing;
+
+/// How many recent `msg_id`s [`RecentIds`] remembers by default.
+const RECENT_IDS: usize = 512;
+
+/// Bodies shorter than this (as JSON) are not worth compressing.
+const COMPRESS_MIN_LEN: usize = 256;
+
+/// Largest body a compressed envelope may inflate to.
+const MAX_BODY_LEN: usize = 1 << 20;
+
+/// One stage of a [`Pipeline`].
+pub trait Middleware: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Transform an outgoing envelope.
+    fn send(&self, _env: &mut Value) {}
+
+    /// Transform an incoming envelope; `false` drops it.
+    fn receive(&self, _env: &mut Value) -> bool {
+        true
+    }
+
+    /// A received envelope was decoded into the payload the caller asked
+    /// for. Envelopes tried as another payload type first are seen by
+    /// [`receive`](Self::receive) more than once, but delivered only once.
+    fn delivered(&self, _env: &Value) {}
+}
+
+/// Built-in stages, as named in `[protocol] middleware`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// Drop envelopes whose `msg_id` was delivered before.
+    Dedup,
+    /// Sign with the node key, verify signatures ([`crate::signing`]).
+    Sign,
+    /// Deflate large bodies. Every peer must run it; off by default.
+    Compress,
+}
+
+impl Stage {
+    /// `dedup`, then `sign`: duplicates are only dropped once verified.
+    pub fn defaults() -> Vec<Self> {
+        vec![Self::Dedup, Self::Sign]
+    }
+
+    fn build(self) -> Box<dyn Middleware> {
+        match self {
+            Self::Dedup => Box::new(Dedup::default()),
+            Self::Sign => Box::new(Sign),
+            Self::Compress => Box::new(Compress),
+        }
+    }
+}
+
+/// Ordered middleware stages.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Middleware>>,
+}
+
+impl Pipeline {
+    pub fn new(stages: &[Stage]) -> Self {
+        Self {
+            stages: stages.iter().map(|s| s.build()).collect(),
+        }
+    }
+
+    /// Append a stage; it runs last on send and first on receive.
+    pub fn push(&mut self, stage: Box<dyn Middleware>) {
+        self.stages.push(stage);
+    }
+
+    /// Stage names in send order.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|s| s.name()).collect()
+    }
+
+    pub fn send(&self, env: &mut Value) {
+        for stage in &self.stages {
+            stage.send(env);
+        }
+    }
+
+    pub fn receive(&self, env: &mut Value) -> bool {
+        self.stages.iter().rev().all(|stage| stage.receive(env))
+    }
+
+    pub fn delivered(&self, env: &Value) {
+        for stage in &self.stages {
+            stage.delivered(env);
+        }
+    }
+
+    /// [`Self::receive`] without the `dedup` stage, for readers that keep
+    /// their own [`RecentIds`].
+    pub fn receive_undeduped(&self, env: &mut Value) -> bool {
+        self.stages
+            .iter()
+            .rev()
+            .filter(|stage| stage.name() != "dedup")
+            .all(|stage| stage.receive(env))
+    }
+
+    /// [`Self::delivered`] without the `dedup` stage.
+    pub fn delivered_undeduped(&self, env: &Value) {
+        for stage in self.stages.iter().filter(|stage| stage.name() != "dedup") {
+            stage.delivered(env);
+        }
+    }
+}
+
+/// See [`Stage::Sign`].
+pub struct Sign;
+
+impl Middleware for Sign {
+    fn name(&self) -> &'static str {
+        "sign"
+    }
+
+    fn send(&self, env: &mut Value) {
+        signing::sign(env);
+    }
+
+    fn receive(&self, env: &mut Value) -> bool {
+        signing::accept(env)
+    }
+}
+
+/// Bounded set of recently seen `msg_id`s; the id seen least recently is
+/// forgotten first.
+///
+/// [`Dedup`] keeps one per [`Wire`](crate::wire::Wire); code reading a topic
+/// with [`signing::open`] keeps its own, so every subsystem still sees each
+/// message, but only once.
+pub struct RecentIds {
+    capacity: usize,
+    /// Id → tick of its latest sighting.
+    seen: HashMap<String, u64>,
+    /// Sightings, oldest first; entries older than `seen` are stale.
+    order: VecDeque<(String, u64)>,
+    tick: u64,
+}
+
+impl Default for RecentIds {
+    fn default() -> Self {
+        Self::new(RECENT_IDS)
+    }
+}
+
+impl RecentIds {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.seen.contains_key(id)
+    }
+
+    /// Record a sighting of `id`; returns whether it is new.
+    pub fn insert(&mut self, id: &str) -> bool {
+        self.tick += 1;
+        let new = self.seen.insert(id.to_string(), self.tick).is_none();
+        self.order.push_back((id.to_string(), self.tick));
+        while self.seen.len() > self.capacity {
+            let Some((old, tick)) = self.order.pop_front() else {
+                break;
+            };
+            if self.seen.get(&old) == Some(&tick) {
+                self.seen.remove(&old);
+            }
+        }
+        // Ids seen over and over leave stale sightings behind.
+        if self.order.len() > 2 * self.capacity {
+            let seen = &self.seen;
+            self.order.retain(|(id, tick)| seen.get(id) == Some(tick));
+        }
+        new
+    }
+}
+
+/// See [`Stage::Dedup`].
+#[derive(Default)]
+pub struct Dedup {
+    recent: Mutex<RecentIds>,
+}
+
+impl Middleware for Dedup {
+    fn name(&self) -> &'static str {
+        "dedup"
+    }
+
+    fn receive(&self, env: &mut Value) -> bool {
+        let Some(id) = env["msg_id"].as_str() else {
+            return true;
+        };
+        let mut recent = self.recent.lock().unwrap();
+        if recent.contains(id) {
+            // A repeat keeps the id fresh.
+            recent.insert(id);
+            return false;
+        }
+        true
+    }
+
+    fn delivered(&self, env: &Value) {
+        if let Some(id) = env["msg_id"].as_str() {
+            self.recent.lock().unwrap().insert(id);
+        }
+    }
+}
+
+/// See [`Stage::Compress`]. A compressed envelope carries `body_z`
+/// (base64 of the deflated body JSON) instead of `body`.
+pub struct Compress;
+
+impl Middleware for Compress {
+    fn name(&self) -> &'static str {
+        "compress"
+    }
+
+    fn send(&self, env: &mut Value) {
+        let Some(fields) = env.as_object_mut() else {
+            return;
+        };
+        let Some(body) = fields.get("body") else {
+            return;
+        };
+        let json = serde_json::to_vec(body).expect("serialize body");
+        if json.len() < COMPRESS_MIN_LEN {
+            return;
+        }
+        let packed = BASE64.encode(miniz_oxide::deflate::compress_to_vec(&json, 6));
+        if packed.len() >= json.len() {
+            return;
+        }
+        fields.remove("body");
+        fields.insert("body_z".into(), packed.into());
+    }
+
+    fn receive(&self, env: &mut Value) -> bool {
+        let Some(fields) = env.as_object_mut() else {
+            return true;
+        };
+        let Some(packed) = fields.remove("body_z") else {
+            return true;
+        };
+        let body = packed
+            .as_str()
+            .and_then(|s| BASE64.decode(s).ok())
+            .and_then(|b| miniz_oxide::inflate::decompress_to_vec_with_limit(&b, MAX_BODY_LEN).ok())
+            .and_then(|json| serde_json::from_slice::<Value>(&json).ok());
+        match body {
+            Some(body) => {
+                fields.insert("body".into(), body);
+                true
+            }
+            None => false,
+        }
+    }
+}
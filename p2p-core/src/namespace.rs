@@ -0,0 +1,227 @@
+//! Topic namespaces for private networks.
+//!
+//! Every topic id derived from a name (global chat, registries, discovery,
+//! rooms) is computed from `"<namespace>/<name>"`. Peers with a different
+//! `namespace` in their config therefore never share a topic, so a company or
+//! a group of friends can run an isolated network on the same binaries.
+//! Topics given explicitly as hex are used as-is.
+//!
+//! A well-known topic that gets flooded or abandoned can be rotated: every
+//! named topic has an epoch, 0 unless `config.toml` says otherwise, and
+//! epoch `n > 0` derives the id from `"<namespace>/<name>#<n>"`:
+//!
+//! ```toml
+//! [[topics.rotate]]
+//! name = "p2p-global-chat"
+//! epoch = 1
+//! since = 1767225600      # unix seconds; peers switch together
+//! overlap_hours = 72      # default
+//! ```
+//!
+//! Before `since` the previous epoch stays in use, so a rotation can be
+//! announced ahead of time. For `overlap_hours` after it, joining the topic
+//! joins both epochs: messages go out on both and come in from both, so
+//! peers that have not updated their config yet still hear and are heard.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use transport_iroh::transport_iroh::{
+    GossipTransport, NeighborCount, NodeAddr, TopicHandle, TopicId,
+};
+
+use crate::{protocol::now_ms, rooms::TopicFeed};
+
+/// Namespace of the public network.
+pub const DEFAULT_NAMESPACE: &str = "public";
+
+/// Fully qualified name a topic id is derived from.
+pub fn qualified_topic_name(namespace: &str, name: &str) -> String {
+    format!("{namespace}/{name}")
+}
+
+/// Name the id of `name` in `epoch` is derived from; epoch 0 is the
+/// original topic.
+pub fn epoch_topic_name(namespace: &str, name: &str, epoch: u32) -> String {
+    match epoch {
+        0 => qualified_topic_name(namespace, name),
+        n => format!("{}#{n}", qualified_topic_name(namespace, name)),
+    }
+}
+
+/// `[topics]` section of `config.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicEpochs {
+    /// Rotated topics (`[[topics.rotate]]`).
+    pub rotate: Vec<TopicRotation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicRotation {
+    /// Topic name, like `"p2p-discovery"`.
+    pub name: String,
+    pub epoch: u32,
+    /// When the epoch begins (unix seconds).
+    #[serde(default)]
+    pub since: u64,
+    /// How long the previous epoch is joined as well.
+    #[serde(default = "default_overlap_hours")]
+    pub overlap_hours: u64,
+}
+
+fn default_overlap_hours() -> u64 {
+    72
+}
+
+impl TopicRotation {
+    fn starts_ms(&self) -> u64 {
+        self.since.saturating_mul(1000)
+    }
+
+    fn overlap_ends_ms(&self) -> u64 {
+        self.starts_ms() + self.overlap_hours * 3_600_000
+    }
+}
+
+impl TopicEpochs {
+    /// The epoch of `name` at `now` (unix millis).
+    pub fn epoch(&self, name: &str, now: u64) -> u32 {
+        self.rotate
+            .iter()
+            .filter(|r| r.name == name)
+            .map(|r| {
+                if now >= r.starts_ms() {
+                    r.epoch
+                } else {
+                    r.epoch.saturating_sub(1)
+                }
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// [`GossipTransport`] decorator that scopes named topics to a namespace.
+pub struct NamespacedTransport<T> {
+    inner: T,
+    namespace: String,
+    epochs: TopicEpochs,
+}
+
+impl<T: GossipTransport> NamespacedTransport<T> {
+    pub fn new(inner: T, namespace: impl Into<String>) -> Self {
+        Self {
+            inner,
+            namespace: namespace.into(),
+            epochs: TopicEpochs::default(),
+        }
+    }
+
+    /// Derive named topics in the epochs of `epochs`.
+    pub fn with_epochs(mut self, epochs: TopicEpochs) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    fn topic_at(&self, name: &str, epoch: u32) -> TopicId {
+        self.inner
+            .topic_from_name(&epoch_topic_name(&self.namespace, name, epoch))
+    }
+
+    /// The previous epoch of `topic`, if `topic` is a rotated topic still in
+    /// its overlap at `now`.
+    fn overlapping(&self, topic: &TopicId, now: u64) -> Option<TopicId> {
+        self.epochs.rotate.iter().find_map(|r| {
+            let current = r.epoch > 0
+                && now >= r.starts_ms()
+                && now < r.overlap_ends_ms()
+                && self.topic_at(&r.name, r.epoch) == *topic;
+            current.then(|| self.topic_at(&r.name, r.epoch - 1))
+        })
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Topic of `name` in another namespace (used by bridges).
+    pub fn topic_in(&self, namespace: &str, name: &str) -> TopicId {
+        self.inner
+            .topic_from_name(&qualified_topic_name(namespace, name))
+    }
+}
+
+#[async_trait]
+impl<T: GossipTransport> GossipTransport for NamespacedTransport<T> {
+    fn node_addr(&self) -> &NodeAddr {
+        self.inner.node_addr()
+    }
+
+    async fn connect(&self, peer: &NodeAddr) -> Result<()> {
+        self.inner.connect(peer).await
+    }
+
+    async fn join_topic(&self, topic: TopicId) -> Result<Box<dyn TopicHandle>> {
+        let Some(previous) = self.overlapping(&topic, now_ms()) else {
+            return self.inner.join_topic(topic).await;
+        };
+        let current = self.inner.join_topic(topic).await?;
+        let old = self.inner.join_topic(previous).await?;
+        let neighbors = current.neighbors();
+        let mut feed = TopicFeed::default();
+        feed.add(None, self.inner.join_topic(topic).await?);
+        feed.add(None, self.inner.join_topic(previous).await?);
+        Ok(Box::new(OverlapHandle {
+            send: [current, old],
+            feed,
+            neighbors,
+        }))
+    }
+
+    fn topic_from_name(&self, name: &str) -> TopicId {
+        self.topic_at(name, self.epochs.epoch(name, now_ms()))
+    }
+
+    fn topic_from_hex(&self, hex: &str) -> Result<TopicId> {
+        self.inner.topic_from_hex(hex)
+    }
+
+    fn topic_to_hex(&self, topic: &TopicId) -> String {
+        self.inner.topic_to_hex(topic)
+    }
+
+    fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr> {
+        self.inner.parse_node_id_addr(s)
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+/// A rotated topic during its overlap: both epochs as one topic.
+struct OverlapHandle {
+    /// Current epoch first.
+    send: [Box<dyn TopicHandle>; 2],
+    feed: TopicFeed,
+    neighbors: Option<NeighborCount>,
+}
+
+#[async_trait]
+impl TopicHandle for OverlapHandle {
+    async fn publish(&self, bytes: &[u8]) -> Result<()> {
+        for th in &self.send {
+            th.publish(bytes).await?;
+        }
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Result<Vec<u8>> {
+        Ok(self.feed.next().await?.1)
+    }
+
+    fn neighbors(&self) -> Option<NeighborCount> {
+        self.neighbors.clone()
+    }
+}
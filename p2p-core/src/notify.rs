@@ -0,0 +1,160 @@
+//! Audible notifications for events the user should not miss.
+//!
+//! Each [`NotifyEvent`] maps to an [`Alert`] configured in the `[notifications]`
+//! section of `config.toml`:
+//!
+//! ```toml
+//! [notifications]
+//! mention = "bell"
+//! dm = "bell"
+//! game_move = "off"
+//! your_turn = { sound = "/home/me/sounds/ding.ogg" }
+//! ```
+//!
+//! Sound files are played by spawning the first available system player
+//! (`paplay`, `aplay`, `afplay`); if none can be started we fall back to the
+//! terminal bell.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, RwLock},
+};
+
+/// External players tried in order for [`Alert::Sound`].
+const SOUND_PLAYERS: &[&str] = &["paplay", "aplay", "afplay"];
+
+/// Events that can trigger an alert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyEvent {
+    /// Someone wrote `@<your nickname>` in a chat.
+    Mention,
+    /// A direct message arrived.
+    DirectMessage,
+    /// Any move was played in a game you take part in.
+    GameMove,
+    /// It is now your turn.
+    YourTurn,
+}
+
+/// What to do when an event fires.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Alert {
+    /// Stay silent.
+    #[default]
+    Off,
+    /// Ring the terminal bell (`BEL`).
+    Bell,
+    /// Play a sound file.
+    Sound(PathBuf),
+}
+
+/// Per-event alert configuration (`[notifications]` in `config.toml`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub mention: Alert,
+    pub dm: Alert,
+    pub game_move: Alert,
+    pub your_turn: Alert,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            mention: Alert::Bell,
+            dm: Alert::Bell,
+            game_move: Alert::Off,
+            your_turn: Alert::Bell,
+        }
+    }
+}
+
+impl NotifyConfig {
+    pub fn alert_for(&self, ev: NotifyEvent) -> &Alert {
+        match ev {
+            NotifyEvent::Mention => &self.mention,
+            NotifyEvent::DirectMessage => &self.dm,
+            NotifyEvent::GameMove => &self.game_move,
+            NotifyEvent::YourTurn => &self.your_turn,
+        }
+    }
+}
+
+/// Fires alerts according to a [`NotifyConfig`]; clones share it.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    config: Arc<RwLock<NotifyConfig>>,
+}
+
+impl Notifier {
+    pub fn new(config: NotifyConfig) -> Self {
+        Self {
+            config: Arc::new(RwLock::new(config)),
+        }
+    }
+
+    /// Use `config` from now on.
+    pub fn reconfigure(&self, config: NotifyConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Trigger the configured alert for `ev` (never blocks on playback).
+    pub fn notify(&self, ev: NotifyEvent) {
+        let alert = self.config.read().unwrap().alert_for(ev).clone();
+        match &alert {
+            Alert::Off => {}
+            Alert::Bell => ring_bell(),
+            Alert::Sound(path) => {
+                if !play_sound(path) {
+                    ring_bell();
+                }
+            }
+        }
+    }
+
+    /// Fire [`NotifyEvent::Mention`] if `text` mentions `@nickname` (case-insensitive).
+    pub fn check_mention(&self, text: &str, nickname: &str) {
+        if mentions(text, nickname) {
+            self.notify(NotifyEvent::Mention);
+        }
+    }
+}
+
+/// Whether `text` contains `@nickname` as a whole word (case-insensitive).
+pub fn mentions(text: &str, nickname: &str) -> bool {
+    if nickname.is_empty() {
+        return false;
+    }
+    let needle = format!("@{}", nickname.to_lowercase());
+    let text = text.to_lowercase();
+    text.match_indices(&needle).any(|(i, _)| {
+        text[i + needle.len()..]
+            .chars()
+            .next()
+            .is_none_or(|c| !(c.is_alphanumeric() || c == '-' || c == '_'))
+    })
+}
+
+fn ring_bell() {
+    let mut stdout = std::io::stdout();
+    let _ = stdout.write_all(b"\x07");
+    let _ = stdout.flush();
+}
+
+fn play_sound(path: &Path) -> bool {
+    SOUND_PLAYERS.iter().any(|player| {
+        Command::new(player)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            // Reap the player in the background so it never lingers as a zombie.
+            .map(|mut child| std::thread::spawn(move || child.wait()))
+            .is_ok()
+    })
+}
@@ -0,0 +1,575 @@
+//! Terminal output styling.
+//!
+//! [`OutputMode::Rich`] uses ANSI colors, box-drawing characters and spinners.
+//! [`OutputMode::Plain`] is the accessibility / screen-reader mode: no escape
+//! codes, no drawing characters, no animation; boards are described as linear
+//! text (`X plays B2; board: X.. / .O. / ...`) and events are announced as full
+//! sentences.
+//!
+//! With `--json` ([`Output::with_json`]) events and notices are printed as one
+//! JSON object per line instead, so scripts can follow a running command;
+//! anything else renders as in plain mode.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{IsTerminal, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    t,
+    widgets::{GridModel, HandModel, RenderModel, ScoreTable},
+};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+
+const SPINNER_FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// How output is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Colors, box-drawing and spinners.
+    Rich,
+    /// Linear, decoration-free text for screen readers and logs.
+    Plain,
+}
+
+/// Something that happened and should be announced to the user.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UiEvent<'a> {
+    /// A chat line; `room` is `None` for the global chat.
+    Chat {
+        from: &'a str,
+        text: &'a str,
+        room: Option<&'a str>,
+    },
+    /// A direct message to us.
+    Direct { from: &'a str, text: &'a str },
+    /// A member joined the current room.
+    MemberJoined { nickname: &'a str },
+    /// A member left the current room.
+    MemberLeft { nickname: &'a str },
+    /// A player placed a mark on a grid board (zero-based coordinates).
+    Move {
+        player: char,
+        row: usize,
+        col: usize,
+    },
+    /// A claim that beats ours took our nickname away.
+    NickConflictLost {
+        nickname: &'a str,
+        winner: &'a str,
+    },
+    /// A nickname is claimed by another key than the one first seen with it.
+    KeyMismatch {
+        nickname: &'a str,
+        pinned: &'a str,
+        seen: &'a str,
+    },
+}
+
+/// When rich output uses ANSI colors (`color` in `config.toml`, `--color`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// When stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Renders user-facing text according to the selected [`OutputMode`].
+#[derive(Debug, Clone, Copy)]
+pub struct Output {
+    mode: OutputMode,
+    json: bool,
+    /// Rich mode only; boxes and spinners stay without colors.
+    colors: bool,
+}
+
+impl Output {
+    pub fn new(plain: bool) -> Self {
+        Self {
+            mode: if plain {
+                OutputMode::Plain
+            } else {
+                OutputMode::Rich
+            },
+            json: false,
+            colors: true,
+        }
+    }
+
+    pub fn with_colors(self, colors: bool) -> Self {
+        Self { colors, ..self }
+    }
+
+    /// Print events and notices as JSON lines; implies plain mode.
+    pub fn with_json(self, json: bool) -> Self {
+        if json {
+            Self { mode: OutputMode::Plain, json, ..self }
+        } else {
+            self
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.json
+    }
+
+    pub fn mode(&self) -> OutputMode {
+        self.mode
+    }
+
+    pub fn is_plain(&self) -> bool {
+        self.mode == OutputMode::Plain
+    }
+
+    /// Rich output as is, or without its colors when they are off.
+    fn styled(&self, s: String) -> String {
+        if self.colors { s } else { strip_colors(&s) }
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        match self.mode {
+            OutputMode::Rich if self.colors => format!("{color}{text}{RESET}"),
+            _ => text.to_string(),
+        }
+    }
+
+    /// Section title: boxed in rich mode, a plain `Title:` line otherwise.
+    pub fn heading(&self, title: &str) -> String {
+        if self.json {
+            return notice("heading", title);
+        }
+        match self.mode {
+            OutputMode::Rich => {
+                let bar = "─".repeat(title.chars().count() + 2);
+                self.styled(format!("┌{bar}┐\n│ {BOLD}{title}{RESET} │\n└{bar}┘"))
+            }
+            OutputMode::Plain => format!("{title}:"),
+        }
+    }
+
+    /// A status line without emphasis; a notice in JSON mode.
+    pub fn info(&self, text: &str) -> String {
+        if self.json {
+            return notice("info", text);
+        }
+        text.to_string()
+    }
+
+    pub fn success(&self, text: &str) -> String {
+        if self.json {
+            return notice("success", text);
+        }
+        self.paint(GREEN, text)
+    }
+
+    pub fn warn(&self, text: &str) -> String {
+        if self.json {
+            return notice("warn", text);
+        }
+        match self.mode {
+            OutputMode::Rich => self.paint(YELLOW, text),
+            OutputMode::Plain => t!("output.warning", text = text),
+        }
+    }
+
+    pub fn error(&self, text: &str) -> String {
+        if self.json {
+            return notice("error", text);
+        }
+        match self.mode {
+            OutputMode::Rich => self.paint(RED, text),
+            OutputMode::Plain => t!("output.error", text = text),
+        }
+    }
+
+    /// One-line announcement of an event; verbose sentences in plain mode.
+    pub fn event(&self, ev: &UiEvent<'_>) -> String {
+        if self.json {
+            return serde_json::to_string(ev).unwrap_or_default();
+        }
+        let line = match (self.mode, ev) {
+            (OutputMode::Rich, UiEvent::Chat { from, text, room }) => match room {
+                Some(room) => format!("{DIM}[{room}]{RESET} {CYAN}{from}{RESET}: {text}"),
+                None => format!("{CYAN}{from}{RESET}: {text}"),
+            },
+            (OutputMode::Plain, UiEvent::Chat { from, text, room }) => match room {
+                Some(room) => t!("event.chat.room", from = from, room = room, text = text),
+                None => t!("event.chat.global", from = from, text = text),
+            },
+            (OutputMode::Rich, UiEvent::Direct { from, text }) => {
+                format!("{YELLOW}✉ {from}{RESET}: {text}")
+            }
+            (OutputMode::Plain, UiEvent::Direct { from, text }) => {
+                t!("event.direct", from = from, text = text)
+            }
+            (OutputMode::Rich, UiEvent::MemberJoined { nickname }) => {
+                format!("{GREEN}+ {nickname}{RESET}")
+            }
+            (OutputMode::Plain, UiEvent::MemberJoined { nickname }) => {
+                t!("event.member_joined", nickname = nickname)
+            }
+            (OutputMode::Rich, UiEvent::MemberLeft { nickname }) => {
+                format!("{DIM}- {nickname}{RESET}")
+            }
+            (OutputMode::Plain, UiEvent::MemberLeft { nickname }) => {
+                t!("event.member_left", nickname = nickname)
+            }
+            (OutputMode::Rich, UiEvent::NickConflictLost { nickname, winner }) => format!(
+                "{BOLD}{RED}! {}{RESET}",
+                t!("event.nick_conflict_lost", nickname = nickname, winner = winner)
+            ),
+            (OutputMode::Plain, UiEvent::NickConflictLost { nickname, winner }) => {
+                t!("event.nick_conflict_lost", nickname = nickname, winner = winner)
+            }
+            (OutputMode::Rich, UiEvent::KeyMismatch { nickname, pinned, seen }) => format!(
+                "{BOLD}{RED}!! {}{RESET}",
+                t!("event.key_mismatch", nickname = nickname, pinned = pinned, seen = seen)
+            ),
+            (OutputMode::Plain, UiEvent::KeyMismatch { nickname, pinned, seen }) => {
+                t!("event.key_mismatch", nickname = nickname, pinned = pinned, seen = seen)
+            }
+            (_, UiEvent::Move { player, row, col }) => {
+                t!("event.move", player = player, cell = cell_name(*row, *col))
+            }
+        };
+        self.styled(line)
+    }
+
+    /// Render a grid board (`None` = empty cell).
+    pub fn board(&self, cells: &[Vec<Option<char>>]) -> String {
+        self.grid(&GridModel {
+            cells: cells.to_vec(),
+            highlight: None,
+        })
+    }
+
+    /// Draw any [`RenderModel`].
+    pub fn render(&self, model: &RenderModel) -> String {
+        match model {
+            RenderModel::Grid(g) => self.grid(g),
+            RenderModel::Hand(h) => self.hand(h),
+            RenderModel::Scores(s) => self.score_table(s),
+            RenderModel::Stack(parts) => parts
+                .iter()
+                .map(|p| self.render(p))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        }
+    }
+
+    /// Grid board.
+    ///
+    /// Rich mode draws a labelled box; plain mode produces a single line such as
+    /// `board: X.. / .O. / ...` (rows top to bottom, `.` for empty).
+    pub fn grid(&self, grid: &GridModel) -> String {
+        let cells = &grid.cells;
+        match self.mode {
+            OutputMode::Plain => {
+                let rows: Vec<String> = cells
+                    .iter()
+                    .map(|row| row.iter().map(|c| c.unwrap_or('.')).collect())
+                    .collect();
+                let mut line = t!("output.board", rows = rows.join(" / "));
+                if let Some((r, c)) = grid.highlight {
+                    line = format!("{line}; {}", t!("output.last_move", cell = cell_name(r, c)));
+                }
+                line
+            }
+            OutputMode::Rich => {
+                let width = cells.first().map(Vec::len).unwrap_or(0);
+                let letters: String = (0..width)
+                    .map(|c| format!(" {} ", column_letter(c)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let edge =
+                    |l: &str, m: &str, r: &str| format!("   {l}{}{r}", vec!["───"; width].join(m));
+                let mut out = vec![format!("    {letters}"), edge("┌", "┬", "┐")];
+                for (i, row) in cells.iter().enumerate() {
+                    let line: Vec<String> = row
+                        .iter()
+                        .enumerate()
+                        .map(|(j, c)| {
+                            let piece = format!(" {} ", c.unwrap_or(' '));
+                            if grid.highlight == Some((i, j)) {
+                                format!("{BOLD}{YELLOW}{piece}{RESET}")
+                            } else {
+                                piece
+                            }
+                        })
+                        .collect();
+                    out.push(format!("{:>2} │{}│", i + 1, line.join("│")));
+                    if i + 1 < cells.len() {
+                        out.push(edge("├", "┼", "┤"));
+                    }
+                }
+                out.push(edge("└", "┴", "┘"));
+                self.styled(out.join("\n"))
+            }
+        }
+    }
+
+    /// A hand of cards, numbered from 1 for move entry.
+    ///
+    /// Rich mode shows `[10♥]` boxes with the selection highlighted and
+    /// unplayable cards dimmed; plain mode lists them in one sentence.
+    pub fn hand(&self, hand: &HandModel) -> String {
+        match self.mode {
+            OutputMode::Plain => {
+                let cards: Vec<String> = hand
+                    .cards
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        let mut s = format!("{} {}", i + 1, c.label);
+                        if hand.selected == Some(i) {
+                            s = t!("output.card_selected", card = s);
+                        } else if !c.playable {
+                            s = t!("output.card_unplayable", card = s);
+                        }
+                        s
+                    })
+                    .collect();
+                t!("output.hand", cards = cards.join(", "))
+            }
+            OutputMode::Rich => {
+                let mut faces = Vec::new();
+                let mut numbers = Vec::new();
+                for (i, c) in hand.cards.iter().enumerate() {
+                    let face = format!("[{}]", c.label);
+                    let w = face.chars().count();
+                    numbers.push(format!("{:^w$}", i + 1));
+                    faces.push(if hand.selected == Some(i) {
+                        format!("{BOLD}{YELLOW}{face}{RESET}")
+                    } else if !c.playable {
+                        format!("{DIM}{face}{RESET}")
+                    } else {
+                        face
+                    });
+                }
+                self.styled(format!("{}\n{}", faces.join(" "), numbers.join(" ")))
+            }
+        }
+    }
+
+    /// Score table with one row per player.
+    ///
+    /// Plain mode reads each row as `player: column value, …`.
+    pub fn score_table(&self, table: &ScoreTable) -> String {
+        match self.mode {
+            OutputMode::Plain => table
+                .rows
+                .iter()
+                .map(|row| {
+                    let values: Vec<String> = table
+                        .columns
+                        .iter()
+                        .zip(&row.values)
+                        .map(|(c, v)| format!("{c} {v}"))
+                        .collect();
+                    format!("{}: {}", row.player, values.join(", "))
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            OutputMode::Rich => {
+                let name_w = table
+                    .rows
+                    .iter()
+                    .map(|r| r.player.chars().count())
+                    .max()
+                    .unwrap_or(0);
+                let widths: Vec<usize> = table
+                    .columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        table
+                            .rows
+                            .iter()
+                            .filter_map(|r| r.values.get(i))
+                            .map(|v| v.chars().count())
+                            .chain([c.chars().count()])
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect();
+                let header: Vec<String> = table
+                    .columns
+                    .iter()
+                    .zip(&widths)
+                    .map(|(c, w)| format!("{c:>w$}"))
+                    .collect();
+                let total = name_w + widths.iter().map(|w| w + 3).sum::<usize>();
+                let mut out = vec![
+                    format!("{BOLD}{:name_w$} │ {}{RESET}", "", header.join(" │ ")),
+                    "─".repeat(total + 1),
+                ];
+                for row in &table.rows {
+                    let cells: Vec<String> = widths
+                        .iter()
+                        .enumerate()
+                        .map(|(i, w)| format!("{:>w$}", row.values.get(i).map_or("", |v| v)))
+                        .collect();
+                    let line = format!("{:name_w$} │ {}", row.player, cells.join(" │ "));
+                    out.push(if row.highlight {
+                        format!("{BOLD}{line}{RESET}")
+                    } else {
+                        line
+                    });
+                }
+                self.styled(out.join("\n"))
+            }
+        }
+    }
+
+    /// Emphasize byte `ranges` of `text` (search hits).
+    ///
+    /// Plain mode leaves the text as is; the result count is announced instead.
+    pub fn highlight(&self, text: &str, ranges: &[std::ops::Range<usize>]) -> String {
+        if self.is_plain() || !self.colors {
+            return text.to_string();
+        }
+        let mut out = String::with_capacity(text.len());
+        let mut pos = 0;
+        for r in ranges {
+            out.push_str(&text[pos..r.start]);
+            out.push_str(&format!("{BOLD}{YELLOW}{}{RESET}", &text[r.clone()]));
+            pos = r.end;
+        }
+        out.push_str(&text[pos..]);
+        out
+    }
+
+    /// Horizontal bar chart of labelled values.
+    ///
+    /// Plain mode lists `label: value` lines instead of drawing bars.
+    pub fn bar_chart(&self, rows: &[(String, u64)]) -> String {
+        const WIDTH: u64 = 30;
+        let max = rows.iter().map(|(_, v)| *v).max().unwrap_or(0).max(1);
+        let label_w = rows
+            .iter()
+            .map(|(l, _)| l.chars().count())
+            .max()
+            .unwrap_or(0);
+        rows.iter()
+            .map(|(label, value)| match self.mode {
+                OutputMode::Plain => format!("{label}: {value}"),
+                OutputMode::Rich => {
+                    let len = (value * WIDTH).div_ceil(max) as usize;
+                    let bar = "█".repeat(len);
+                    self.styled(format!("{label:<label_w$} {CYAN}{bar}{RESET} {value}"))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Show progress while waiting (e.g. for registry claims).
+    ///
+    /// Rich mode animates a spinner on stderr until the returned guard is
+    /// dropped; plain mode prints the label once.
+    pub fn waiting(&self, label: &str) -> Spinner {
+        match self.mode {
+            OutputMode::Plain => {
+                eprintln!("{label}");
+                Spinner {
+                    stop: Arc::new(AtomicBool::new(true)),
+                    handle: None,
+                }
+            }
+            OutputMode::Rich => {
+                let stop = Arc::new(AtomicBool::new(false));
+                let flag = stop.clone();
+                let label = label.to_string();
+                let handle = thread::spawn(move || {
+                    let mut stderr = std::io::stderr();
+                    for frame in SPINNER_FRAMES.iter().cycle() {
+                        if flag.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        let _ = write!(stderr, "\r{frame} {label}");
+                        let _ = stderr.flush();
+                        thread::sleep(Duration::from_millis(80));
+                    }
+                    let _ = write!(stderr, "\r\x1b[2K");
+                    let _ = stderr.flush();
+                });
+                Spinner {
+                    stop,
+                    handle: Some(handle),
+                }
+            }
+        }
+    }
+}
+
+/// `{"type":"notice","level":…,"text":…}` for JSON mode.
+fn notice(level: &str, text: &str) -> String {
+    serde_json::json!({ "type": "notice", "level": level, "text": text }).to_string()
+}
+
+/// `s` without ANSI color codes.
+fn strip_colors(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find("\x1b[") {
+        out.push_str(&rest[..i]);
+        rest = rest[i..].split_once('m').map_or("", |(_, after)| after);
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Guard returned by [`Output::waiting`]; stops the spinner when dropped.
+pub struct Spinner {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
+
+/// Column letter for a zero-based column index (`0` → `A`).
+pub fn column_letter(col: usize) -> char {
+    (b'A' + (col % 26) as u8) as char
+}
+
+/// Spoken-style cell name for zero-based coordinates (`(1, 1)` → `B2`).
+pub fn cell_name(row: usize, col: usize) -> String {
+    format!("{}{}", column_letter(col), row + 1)
+}
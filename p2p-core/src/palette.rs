@@ -0,0 +1,210 @@
+//! Help overlay and command palette for the TUI.
+//!
+//! [`help_entries`] lists every [`Action`] with its keys and a description
+//! for the `?` overlay. [`CommandPalette`] (Ctrl-P) offers every CLI
+//! operation, taken from the clap definition of [`AppCli`] so it never falls
+//! behind the command line: the user types a few letters, the best fuzzy
+//! matches are listed, and the chosen command comes back as arguments for
+//! [`AppCli`] with the remaining parameters to fill in.
+
+use clap::CommandFactory;
+
+use crate::{
+    keymap::{Action, Keymap},
+    protocol::AppCli,
+};
+
+/// One line of the help overlay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelpEntry {
+    pub action: Action,
+    /// Bound keys, comma separated (empty when unbound).
+    pub keys: String,
+    pub description: String,
+}
+
+/// Everything the `?` overlay shows, in [`Action::ALL`] order.
+pub fn help_entries(keymap: &Keymap) -> Vec<HelpEntry> {
+    Action::ALL
+        .iter()
+        .map(|&action| HelpEntry {
+            action,
+            keys: keymap
+                .keys(action)
+                .iter()
+                .map(|k| k.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            description: action.describe(),
+        })
+        .collect()
+}
+
+/// A CLI operation offered by the palette.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteCommand {
+    /// Subcommand path, e.g. `["room", "join"]`.
+    pub path: Vec<String>,
+    /// One-line help from the CLI definition.
+    pub about: String,
+    /// Required arguments still to be filled in, e.g. `["<TEXT>"]`.
+    pub params: Vec<String>,
+}
+
+impl PaletteCommand {
+    /// `room join`.
+    pub fn title(&self) -> String {
+        self.path.join(" ")
+    }
+
+    /// Arguments for [`AppCli`] once the parameters are filled in.
+    pub fn argv(&self, params: &[String]) -> Vec<String> {
+        std::iter::once("p2p-games".to_string())
+            .chain(self.path.iter().cloned())
+            .chain(params.iter().cloned())
+            .collect()
+    }
+}
+
+fn collect(cmd: &clap::Command, path: &mut Vec<String>, out: &mut Vec<PaletteCommand>) {
+    let subs: Vec<_> = cmd
+        .get_subcommands()
+        .filter(|s| s.get_name() != "help")
+        .collect();
+    if subs.is_empty() {
+        out.push(PaletteCommand {
+            path: path.clone(),
+            about: cmd.get_about().map(|a| a.to_string()).unwrap_or_default(),
+            params: cmd
+                .get_arguments()
+                .filter(|a| a.is_required_set() && !a.is_global_set())
+                .map(|a| match a.get_long() {
+                    Some(long) => format!("--{long} <{}>", a.get_id().as_str().to_uppercase()),
+                    None => format!("<{}>", a.get_id().as_str().to_uppercase()),
+                })
+                .collect(),
+        });
+    }
+    for sub in subs {
+        path.push(sub.get_name().to_string());
+        collect(sub, path, out);
+        path.pop();
+    }
+}
+
+/// All leaf subcommands of the CLI.
+pub fn all_commands() -> Vec<PaletteCommand> {
+    let mut out = Vec::new();
+    collect(&AppCli::command(), &mut Vec::new(), &mut out);
+    out
+}
+
+/// Fuzzy score of `query` against `candidate`: every query character must
+/// appear in order; consecutive runs and matches at word starts score
+/// higher. `None` if it doesn't match.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<u32> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut prev: Option<usize> = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = pos + candidate[pos..].iter().position(|&c| c == q)?;
+        score += 1;
+        if prev.is_some_and(|p| p + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        prev = Some(found);
+        pos = found + 1;
+    }
+    Some(score)
+}
+
+/// State of the Ctrl-P palette.
+#[derive(Debug, Clone)]
+pub struct CommandPalette {
+    commands: Vec<PaletteCommand>,
+    query: String,
+    /// Indices into `commands`, best match first.
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+impl Default for CommandPalette {
+    fn default() -> Self {
+        Self::new(all_commands())
+    }
+}
+
+impl CommandPalette {
+    pub fn new(commands: Vec<PaletteCommand>) -> Self {
+        let matches = (0..commands.len()).collect();
+        Self {
+            commands,
+            query: String::new(),
+            matches,
+            selected: 0,
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn set_query(&mut self, query: &str) {
+        self.query = query.to_string();
+        let mut scored: Vec<(u32, usize)> = self
+            .commands
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| {
+                let title = fuzzy_score(&self.query, &c.title());
+                let about = fuzzy_score(&self.query, &c.about);
+                // The name counts more than the description.
+                title.map(|s| s * 2).max(about).map(|s| (s, i))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+    }
+
+    pub fn push_char(&mut self, c: char) {
+        let q = format!("{}{c}", self.query);
+        self.set_query(&q);
+    }
+
+    pub fn pop_char(&mut self) {
+        let mut q = self.query.clone();
+        q.pop();
+        self.set_query(&q);
+    }
+
+    /// Matching commands, best first.
+    pub fn matches(&self) -> impl Iterator<Item = &PaletteCommand> {
+        self.matches.iter().map(|&i| &self.commands[i])
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + 1) % self.matches.len();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + self.matches.len() - 1) % self.matches.len();
+        }
+    }
+
+    /// Position of the highlighted entry in [`matches`](Self::matches).
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected(&self) -> Option<&PaletteCommand> {
+        self.matches.get(self.selected).map(|&i| &self.commands[i])
+    }
+}
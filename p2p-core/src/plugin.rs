@@ -0,0 +1,361 @@
+//! Third-party games as WebAssembly modules.
+//!
+//! Every `.wasm` file in [`plugins_dir`] that speaks the guest ABI below is
+//! registered by [`GameRegistry::builtin`](crate::game::GameRegistry) next
+//! to the games shipped with the client, and can be played in rooms like
+//! them. Modules run in an interpreter without imports, so a plugin can
+//! only compute: no files, no network, no clock. Every call may burn at most
+//! [`FUEL_PER_CALL`] instructions.
+//!
+//! # Guest ABI
+//!
+//! All data crosses as UTF-8 JSON in the module's exported `memory`. The
+//! host asks the guest for room with `alloc(len: i32) -> i32` and writes its
+//! arguments there; the guest answers with an `i64` holding the offset of
+//! its reply in the high 32 bits and the length in the low ones. Replies are
+//! `{"ok": …}` or `{"err": "<message>"}`.
+//!
+//! | export | arguments | `ok` |
+//! |---|---|---|
+//! | `meta() -> i64` | | `{"name", "version"}` |
+//! | `init(players: i32) -> i64` | | a [`Status`] |
+//! | `validate_move(state, seat, move) -> i64` | | anything |
+//! | `apply_move(state, seat, move) -> i64` | | a [`Status`] |
+//! | `render(state, viewer) -> i64` | `viewer` is -1 for spectators | a grid |
+//!
+//! `state` and `move` are each passed as an `(offset: i32, len: i32)` pair.
+//! A move is the text the player typed, as a JSON string. A grid is
+//! `{"cells": [["X", null, …], …], "highlight": [row, col] | null}`. An
+//! optional `dealloc(offset: i32, len: i32)` export is called for every
+//! buffer once the host is done with it.
+//!
+//! A plugin game ends the match when a player leaves
+//! ([`DropPolicy::EndGame`]); bots cannot play it.
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+use wasmi::{Config, Engine, Instance, Linker, Memory, Module, Store};
+
+use crate::{
+    game::{AnyGame, DropPolicy, GameRegistry, Outcome, Seat},
+    widgets::{GridModel, RenderModel},
+};
+
+/// Instructions a single call into a plugin may execute.
+pub const FUEL_PER_CALL: u64 = 50_000_000;
+
+/// Where plugins are looked for: `<data dir>/p2p-games/plugins`, shared by
+/// all profiles.
+pub fn plugins_dir() -> PathBuf {
+    let mut path = dirs::data_local_dir().unwrap_or(std::env::temp_dir());
+    path.push("p2p-games");
+    path.push("plugins");
+    path
+}
+
+/// What `init` and `apply_move` return.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    /// The guest's game state, passed back on every later call.
+    pub state: Value,
+    pub to_move: Option<Seat>,
+    pub outcome: Option<Outcome>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Reply<T> {
+    Ok(T),
+    Err(String),
+}
+
+#[derive(Deserialize)]
+struct Meta {
+    name: String,
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct Grid {
+    cells: Vec<Vec<Option<char>>>,
+    #[serde(default)]
+    highlight: Option<(usize, usize)>,
+}
+
+/// A loaded plugin, ready to start games.
+pub struct Plugin {
+    pub name: &'static str,
+    pub version: u32,
+    pub path: PathBuf,
+    engine: Engine,
+    module: Module,
+}
+
+/// Plugins found in [`plugins_dir`], and the files that were skipped with
+/// the reason.
+#[derive(Default)]
+pub struct Plugins {
+    pub games: Vec<Plugin>,
+    pub errors: Vec<(PathBuf, String)>,
+}
+
+/// The plugins of this process, loaded on first use.
+pub fn loaded() -> &'static Plugins {
+    static PLUGINS: OnceLock<Plugins> = OnceLock::new();
+    PLUGINS.get_or_init(|| {
+        let shipped: Vec<&str> = GameRegistry::shipped().names().collect();
+        load_dir(&plugins_dir(), &shipped)
+    })
+}
+
+/// Load every `.wasm` file in `dir`; names in `taken` are refused.
+pub fn load_dir(dir: &Path, taken: &[&str]) -> Plugins {
+    let mut plugins = Plugins::default();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return plugins;
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "wasm"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        match Plugin::load(&path) {
+            Ok(p) if taken.contains(&p.name) || plugins.games.iter().any(|g| g.name == p.name) => {
+                let error = format!("a game named {} is already known", p.name);
+                plugins.errors.push((path, error));
+            }
+            Ok(p) => plugins.games.push(p),
+            Err(e) => plugins.errors.push((path, e.to_string())),
+        }
+    }
+    plugins
+}
+
+impl Plugin {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &bytes).map_err(|e| anyhow!("{e}"))?;
+        let mut guest = Guest::new(&engine, &module)?;
+        let meta: Meta = guest.call("meta", &[], &[])?;
+        if meta.name.is_empty() || meta.name.contains(char::is_whitespace) {
+            bail!("bad game name {:?}", meta.name);
+        }
+        Ok(Self {
+            // Plugins are loaded once per process; see `loaded`.
+            name: Box::leak(meta.name.into_boxed_str()),
+            version: meta.version,
+            path: path.to_path_buf(),
+            engine,
+            module,
+        })
+    }
+
+    /// A new game for `players` seats.
+    pub fn create(&self, players: usize) -> Result<Box<dyn AnyGame>> {
+        let mut guest = Guest::new(&self.engine, &self.module)?;
+        let status: Status = guest.call("init", &[players as i32], &[])?;
+        Ok(Box::new(PluginGame {
+            name: self.name,
+            guest: Arc::new(Mutex::new(guest)),
+            status,
+        }))
+    }
+}
+
+/// An instance of a plugin module.
+struct Guest {
+    store: Store<()>,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl Guest {
+    fn new(engine: &Engine, module: &Module) -> Result<Self> {
+        let mut store = Store::new(engine, ());
+        store.set_fuel(FUEL_PER_CALL).map_err(|e| anyhow!("{e}"))?;
+        let instance = Linker::<()>::new(engine)
+            .instantiate(&mut store, module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(|e| anyhow!("{e}"))?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| anyhow!("the module exports no memory"))?;
+        Ok(Self {
+            store,
+            instance,
+            memory,
+        })
+    }
+
+    /// Copy `bytes` into guest memory; returns `(offset, len)`.
+    fn put(&mut self, bytes: &[u8]) -> Result<(i32, i32)> {
+        let alloc = self
+            .instance
+            .get_typed_func::<i32, i32>(&self.store, "alloc")
+            .map_err(|e| anyhow!("{e}"))?;
+        let len = bytes.len() as i32;
+        let offset = alloc
+            .call(&mut self.store, len)
+            .map_err(|e| anyhow!("{e}"))?;
+        self.memory
+            .write(&mut self.store, offset as u32 as usize, bytes)
+            .map_err(|e| anyhow!("{e}"))?;
+        Ok((offset, len))
+    }
+
+    fn free(&mut self, (offset, len): (i32, i32)) {
+        if let Ok(dealloc) = self
+            .instance
+            .get_typed_func::<(i32, i32), ()>(&self.store, "dealloc")
+        {
+            let _ = dealloc.call(&mut self.store, (offset, len));
+        }
+    }
+
+    /// Call `export` with the `ints` followed by the `(offset, len)` of each
+    /// of `blobs`, and decode its reply.
+    fn call<T: DeserializeOwned>(
+        &mut self,
+        export: &str,
+        ints: &[i32],
+        blobs: &[&[u8]],
+    ) -> Result<T> {
+        self.store
+            .set_fuel(FUEL_PER_CALL)
+            .map_err(|e| anyhow!("{e}"))?;
+        let func = self
+            .instance
+            .get_func(&self.store, export)
+            .ok_or_else(|| anyhow!("the module does not export {export}"))?;
+        let mut args: Vec<wasmi::Val> = Vec::new();
+        let mut buffers = Vec::new();
+        // `validate_move` and `apply_move` take the seat between state and
+        // move, `render` the viewer after the state.
+        let mut ints = ints.iter();
+        for (i, blob) in blobs.iter().enumerate() {
+            let (offset, len) = self.put(blob)?;
+            buffers.push((offset, len));
+            args.extend([wasmi::Val::I32(offset), wasmi::Val::I32(len)]);
+            if i == 0
+                && let Some(n) = ints.next()
+            {
+                args.push(wasmi::Val::I32(*n));
+            }
+        }
+        args.extend(ints.map(|n| wasmi::Val::I32(*n)));
+        let mut result = [wasmi::Val::I64(0)];
+        let called = func.call(&mut self.store, &args, &mut result);
+        for buffer in buffers {
+            self.free(buffer);
+        }
+        called.map_err(|e| anyhow!("{export} failed: {e}"))?;
+        let wasmi::Val::I64(packed) = result[0] else {
+            bail!("{export} returned no i64");
+        };
+        let (offset, len) = ((packed as u64 >> 32) as usize, packed as u32 as usize);
+        let mut reply = vec![0; len];
+        self.memory
+            .read(&self.store, offset, &mut reply)
+            .map_err(|e| anyhow!("{e}"))?;
+        self.free((offset as i32, len as i32));
+        match serde_json::from_slice::<Reply<T>>(&reply) {
+            Ok(Reply::Ok(value)) => Ok(value),
+            Ok(Reply::Err(message)) => bail!(message),
+            Err(e) => bail!("malformed reply from {export}: {e}"),
+        }
+    }
+}
+
+/// A running plugin game.
+struct PluginGame {
+    name: &'static str,
+    guest: Arc<Mutex<Guest>>,
+    status: Status,
+}
+
+impl PluginGame {
+    fn state_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.status.state).unwrap()
+    }
+}
+
+impl AnyGame for PluginGame {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn on_drop(&self) -> DropPolicy {
+        DropPolicy::EndGame
+    }
+
+    fn to_move(&self) -> Option<Seat> {
+        self.status.to_move
+    }
+
+    fn legal_moves(&self, _seat: Seat) -> Vec<Value> {
+        Vec::new()
+    }
+
+    fn apply(&mut self, seat: Seat, mv: &Value) -> Result<()> {
+        let mv = serde_json::to_vec(mv)?;
+        let state = self.state_bytes();
+        let mut guest = self.guest.lock().unwrap();
+        guest.call::<Value>("validate_move", &[seat as i32], &[&state, &mv])?;
+        self.status = guest.call("apply_move", &[seat as i32], &[&state, &mv])?;
+        Ok(())
+    }
+
+    fn parse_move(&self, input: &str) -> Result<Value> {
+        Ok(json!(input.trim()))
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        self.status.outcome.clone()
+    }
+
+    fn render(&self, viewer: Option<Seat>) -> RenderModel {
+        let viewer = viewer.map_or(-1, |s| s as i32);
+        let state = self.state_bytes();
+        let grid: Result<Grid> = self
+            .guest
+            .lock()
+            .unwrap()
+            .call("render", &[viewer], &[&state]);
+        match grid {
+            Ok(grid) => RenderModel::Grid(GridModel {
+                cells: grid.cells,
+                highlight: grid.highlight,
+            }),
+            Err(_) => RenderModel::Grid(GridModel::default()),
+        }
+    }
+
+    fn describe_move(&self, mv: &Value) -> Option<String> {
+        mv.as_str().map(str::to_string)
+    }
+
+    fn eliminate(&mut self, _seat: Seat) -> Result<()> {
+        Ok(())
+    }
+
+    fn state(&self) -> Value {
+        serde_json::to_value(&self.status).unwrap()
+    }
+
+    fn load_state(&mut self, state: Value) -> Result<()> {
+        self.status = serde_json::from_value(state)
+            .map_err(|e| anyhow!("malformed {} state: {e}", self.name))?;
+        Ok(())
+    }
+}
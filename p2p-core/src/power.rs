@@ -0,0 +1,226 @@
+//! Power profiles.
+//!
+//! [`PowerProfile::LowPower`] (`--low-power` / `low_power = true`) trades
+//! latency for fewer radio wakeups, for laptops on battery and future mobile
+//! builds:
+//! - periodic traffic (heartbeats, re-announces, claim renewals) uses the
+//!   longer [`Intervals`] of the profile,
+//! - outgoing publishes are queued per topic and released together on fixed
+//!   window boundaries by [`BatchedTransport`],
+//! - only the active room stays subscribed ([`PowerProfile::keep_inactive_rooms`]).
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+use transport_iroh::transport_iroh::{
+    GossipTransport, NeighborCount, NodeAddr, TopicHandle, TopicId,
+};
+
+use crate::protocol::now_ms;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PowerProfile {
+    #[default]
+    Normal,
+    LowPower,
+}
+
+/// Timing knobs that depend on the power profile.
+#[derive(Debug, Clone, Copy)]
+pub struct Intervals {
+    /// Room member heartbeat period.
+    pub heartbeat: Duration,
+    /// Room re-announcement period on the discovery topic.
+    pub reannounce: Duration,
+    /// Nickname claim renewal period.
+    pub claim_renewal: Duration,
+    /// Publish batching window; `None` sends immediately.
+    pub publish_batch: Option<Duration>,
+}
+
+impl PowerProfile {
+    pub fn from_flag(low_power: bool) -> Self {
+        if low_power {
+            Self::LowPower
+        } else {
+            Self::Normal
+        }
+    }
+
+    pub fn intervals(self) -> Intervals {
+        match self {
+            Self::Normal => Intervals {
+                heartbeat: Duration::from_secs(15),
+                reannounce: Duration::from_secs(30),
+                claim_renewal: Duration::from_secs(60),
+                publish_batch: None,
+            },
+            Self::LowPower => Intervals {
+                heartbeat: Duration::from_secs(60),
+                reannounce: Duration::from_secs(120),
+                claim_renewal: Duration::from_secs(300),
+                publish_batch: Some(Duration::from_secs(2)),
+            },
+        }
+    }
+
+    /// Whether subscriptions to rooms other than the active one are kept open.
+    pub fn keep_inactive_rooms(self) -> bool {
+        self == Self::Normal
+    }
+}
+
+/// Time left until the next multiple of `window` in wall-clock time.
+///
+/// Aligning to wall-clock boundaries makes publishes from independent tasks
+/// leave in the same burst.
+pub fn until_next_window(window: Duration) -> Duration {
+    let window_ms = window.as_millis().max(1) as u64;
+    Duration::from_millis(window_ms - now_ms() % window_ms)
+}
+
+/// [`GossipTransport`] decorator that delays publishes to batch windows.
+pub struct BatchedTransport<T> {
+    inner: T,
+    window: Option<Duration>,
+}
+
+impl<T: GossipTransport> BatchedTransport<T> {
+    pub fn new(inner: T, profile: PowerProfile) -> Self {
+        Self {
+            inner,
+            window: profile.intervals().publish_batch,
+        }
+    }
+}
+
+#[async_trait]
+impl<T: GossipTransport> GossipTransport for BatchedTransport<T> {
+    fn node_addr(&self) -> &NodeAddr {
+        self.inner.node_addr()
+    }
+
+    async fn connect(&self, peer: &NodeAddr) -> Result<()> {
+        self.inner.connect(peer).await
+    }
+
+    async fn join_topic(&self, topic: TopicId) -> Result<Box<dyn TopicHandle>> {
+        let inner = self.inner.join_topic(topic).await?;
+        Ok(match self.window {
+            None => inner,
+            Some(window) => Box::new(BatchedTopic::new(inner, window)),
+        })
+    }
+
+    fn topic_from_name(&self, name: &str) -> TopicId {
+        self.inner.topic_from_name(name)
+    }
+
+    fn topic_from_hex(&self, hex: &str) -> Result<TopicId> {
+        self.inner.topic_from_hex(hex)
+    }
+
+    fn topic_to_hex(&self, topic: &TopicId) -> String {
+        self.inner.topic_to_hex(topic)
+    }
+
+    fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr> {
+        self.inner.parse_node_id_addr(s)
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+/// A frame waiting for the next window, and who to tell how it went.
+type Queued = (Vec<u8>, oneshot::Sender<Result<()>>);
+
+/// Topic handle whose publishes are queued and flushed together once per
+/// window by one task. The task owns the inner handle and passes received
+/// frames on; it ends when the handle is dropped.
+struct BatchedTopic {
+    queue: mpsc::UnboundedSender<Queued>,
+    received: mpsc::UnboundedReceiver<Result<Vec<u8>>>,
+    neighbors: Option<NeighborCount>,
+}
+
+impl BatchedTopic {
+    fn new(inner: Box<dyn TopicHandle>, window: Duration) -> Self {
+        let (queue, queued) = mpsc::unbounded_channel();
+        let (deliver, received) = mpsc::unbounded_channel();
+        let neighbors = inner.neighbors();
+        tokio::spawn(batch(inner, window, queued, deliver));
+        Self {
+            queue,
+            received,
+            neighbors,
+        }
+    }
+}
+
+async fn batch(
+    mut inner: Box<dyn TopicHandle>,
+    window: Duration,
+    mut queued: mpsc::UnboundedReceiver<Queued>,
+    deliver: mpsc::UnboundedSender<Result<Vec<u8>>>,
+) {
+    let mut pending = Vec::new();
+    let mut due: Option<Instant> = None;
+    loop {
+        let flush = async {
+            match due {
+                Some(at) => tokio::time::sleep_until(at).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            frame = queued.recv() => match frame {
+                Some(frame) => {
+                    due.get_or_insert_with(|| Instant::now() + until_next_window(window));
+                    pending.push(frame);
+                }
+                None => break,
+            },
+            () = flush => {
+                for (bytes, done) in pending.drain(..) {
+                    let _ = done.send(inner.publish(&bytes).await);
+                }
+                due = None;
+            }
+            frame = inner.next() => {
+                let closed = frame.is_err();
+                if deliver.send(frame).is_err() || closed {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl TopicHandle for BatchedTopic {
+    /// Returns once the window the frame was queued for has been flushed.
+    async fn publish(&self, bytes: &[u8]) -> Result<()> {
+        let (done, flushed) = oneshot::channel();
+        self.queue
+            .send((bytes.to_vec(), done))
+            .map_err(|_| anyhow!("topic closed"))?;
+        flushed.await.map_err(|_| anyhow!("topic closed"))?
+    }
+
+    async fn next(&mut self) -> Result<Vec<u8>> {
+        self.received
+            .recv()
+            .await
+            .unwrap_or_else(|| Err(anyhow!("topic closed")))
+    }
+
+    fn neighbors(&self) -> Option<NeighborCount> {
+        self.neighbors.clone()
+    }
+}
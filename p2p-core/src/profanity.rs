@@ -0,0 +1,72 @@
+//! Profanity filter for displayed chat text.
+//!
+//! Matching is per word and case-insensitive; hits are masked with `*` of
+//! the same length so the line keeps its shape. Only what is *shown* is
+//! filtered, stored history keeps the original text.
+
+/// Built-in list; extended by `blocked_words` in config and safe mode.
+const DEFAULT_WORDS: &[&str] = &[
+    "arse",
+    "ass",
+    "asshole",
+    "bastard",
+    "bitch",
+    "bollocks",
+    "crap",
+    "cunt",
+    "damn",
+    "dick",
+    "fuck",
+    "fucking",
+    "motherfucker",
+    "piss",
+    "prick",
+    "shit",
+    "slut",
+    "twat",
+    "wanker",
+    "whore",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct ProfanityFilter {
+    words: Vec<String>,
+}
+
+impl ProfanityFilter {
+    /// Built-in list plus `extra` words.
+    pub fn new(extra: &[String]) -> Self {
+        let mut words: Vec<String> = DEFAULT_WORDS.iter().map(|w| w.to_string()).collect();
+        words.extend(extra.iter().map(|w| w.to_lowercase()));
+        Self { words }
+    }
+
+    fn is_blocked(&self, word: &str) -> bool {
+        self.words.contains(&word.to_lowercase())
+    }
+
+    /// `text` with every blocked word masked.
+    pub fn clean(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut word = String::new();
+        for c in text.chars() {
+            if c.is_alphanumeric() {
+                word.push(c);
+                continue;
+            }
+            self.push_word(&mut out, &mut word);
+            out.push(c);
+        }
+        self.push_word(&mut out, &mut word);
+        out
+    }
+
+    fn push_word(&self, out: &mut String, word: &mut String) {
+        if self.is_blocked(word) {
+            out.extend(std::iter::repeat_n('*', word.chars().count()));
+        } else {
+            out.push_str(word);
+        }
+        word.clear();
+    }
+}
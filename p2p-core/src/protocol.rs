@@ -12,11 +12,28 @@
 //!   happens at the application layer.
 //!
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use uuid::Uuid;
 
+use crate::{
+    admission::AdmissionPolicy,
+    archive::Role,
+    config::TransportKind,
+    digest::{SyncTable, TableDigest},
+    game::GameKind,
+    hlc::{self, Hlc},
+    output::ColorChoice,
+    turn::TurnOrder,
+};
+
 // ======================================================================
 // Constants
 // ======================================================================
@@ -40,12 +57,26 @@ pub const NAME_REGISTRY_TOPIC_NAME: &str = "p2p-name-registry";
 pub enum Kind {
     /// Discovery: announce/list rooms in the global topic.
     Discovery,
-    /// Room control: join/ack/members/leave/close inside a room topic.
+    /// Room control: join/ack/members/heartbeat/kick/leave/close inside a room topic.
     Room,
     /// Chat messages (global or room-scoped).
     Chat,
-    /// Reserved for game lifecycle and gameplay messages (start/cmd/state/events).
+    /// Game lifecycle and gameplay messages, see [`crate::game::GameBody`].
     Game,
+    /// Direct messages between two peers, see [`crate::dm`].
+    Direct,
+    /// New text for an earlier chat message, see [`ChatEdit`].
+    Edit,
+    /// Retraction of an earlier chat message, see [`ChatDelete`].
+    Delete,
+    /// Operator banner on the announcements topic, see [`crate::announce`].
+    Announcement,
+    /// Queue and pairing messages, see [`crate::matchmaking`].
+    Matchmaking,
+    /// Bracket, entry and result messages, see [`crate::tournament`].
+    Tournament,
+    /// Game invitations and their answers, see [`crate::dm::InviteBody`].
+    Invite,
 }
 
 /// Logical broadcast scope of a message.
@@ -56,6 +87,8 @@ pub enum Scope {
     Global,
     /// Sent/received on a **room** topic (only members of a lobby/game).
     Room,
+    /// Sent to the inbox topic of a single peer.
+    Direct,
 }
 
 /// Common envelope for **all** messages.
@@ -68,9 +101,10 @@ pub struct Envelope<T> {
     pub ver: u16,
     /// Message category (for dispatching).
     pub kind: Kind,
-    /// Logical scope: global or room.
+    /// Logical scope: global, room or direct.
     pub scope: Scope,
-    /// Target room when `scope == Scope::Room`; `None` for global messages.
+    /// Target room when `scope == Scope::Room`, recipient peer id when
+    /// `scope == Scope::Direct`; `None` for global messages.
     pub room_id: Option<String>,
     /// Human-readable peer/node id (e.g., hex/base32 of iroh `NodeId`).
     pub sender_id: String,
@@ -79,8 +113,18 @@ pub struct Envelope<T> {
     /// Monotonic timestamp (e.g., unix millis or a Lamport counter).
     /// Used for UI ordering and basic replay protection.
     pub ts: u64,
+    /// Hybrid logical clock stamp of chat messages; see [`crate::hlc`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hlc: Option<Hlc>,
     /// The actual message payload.
     pub body: T,
+    /// Ed25519 signature (hex) over the canonical envelope; see
+    /// [`crate::signing`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sig: Option<String>,
+    /// Signer's public key (hex); must match `sender_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pubkey: Option<String>,
 }
 
 // ======================================================================
@@ -94,6 +138,84 @@ pub struct Envelope<T> {
 pub struct ChatMsg {
     /// Text content of the chat message.
     pub text: String,
+    /// Set when a bridge mirrored the message from another room.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay: Option<Relay>,
+}
+
+/// New text for one of the sender's earlier chat messages.
+///
+/// Only the sender of the target may edit it, and only signed edits are
+/// applied (see [`crate::wire::Wire::decode_change`]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatEdit {
+    pub target_msg_id: String,
+    pub new_text: String,
+}
+
+/// Retracts one of the sender's earlier chat messages; same rules as
+/// [`ChatEdit`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatDelete {
+    pub target_msg_id: String,
+}
+
+/// A received [`ChatEdit`] or [`ChatDelete`].
+#[derive(Debug, Clone)]
+pub enum ChatChange {
+    Edit(Envelope<ChatEdit>),
+    Delete(Envelope<ChatDelete>),
+}
+
+impl ChatChange {
+    pub fn target_msg_id(&self) -> &str {
+        match self {
+            Self::Edit(env) => &env.body.target_msg_id,
+            Self::Delete(env) => &env.body.target_msg_id,
+        }
+    }
+
+    pub fn sender_id(&self) -> &str {
+        match self {
+            Self::Edit(env) => &env.sender_id,
+            Self::Delete(env) => &env.sender_id,
+        }
+    }
+
+    /// The new text, `None` for a deletion.
+    pub fn new_text(&self) -> Option<&str> {
+        match self {
+            Self::Edit(env) => Some(&env.body.new_text),
+            Self::Delete(_) => None,
+        }
+    }
+
+    pub fn stamp(&self) -> Hlc {
+        match self {
+            Self::Edit(env) => hlc::stamp(env),
+            Self::Delete(env) => hlc::stamp(env),
+        }
+    }
+
+    pub fn msg_id(&self) -> &str {
+        match self {
+            Self::Edit(env) => &env.msg_id,
+            Self::Delete(env) => &env.msg_id,
+        }
+    }
+}
+
+/// Origin of a chat message mirrored by a bridge (see [`crate::bridge`]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Relay {
+    /// Peer that wrote the message.
+    pub origin_id: String,
+    /// Bridge end the message was first seen on.
+    pub origin_room: String,
+    /// `msg_id` of the original envelope.
+    pub origin_msg_id: String,
+    /// Bridges crossed so far.
+    pub hops: u8,
 }
 
 /// Discovery messages (global topic).
@@ -112,6 +234,21 @@ pub enum DiscoveryBody {
         host_id: String,
         /// Creation time (unix millis).
         created_at: u64,
+        /// Player cap set by the host; absent if unlimited or from older hosts.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_players: Option<u32>,
+        /// Players in the room when announced, host included.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        current_players: Option<u32>,
+        /// Language spoken in the room (see [`crate::language`]).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+        /// Join ticket, so listeners can join without asking for the list.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ticket: Option<String>,
+        /// Game played in the room.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        game: Option<GameKind>,
     },
     /// Ask peers to respond with the rooms they currently know/host.
     ListRoomsReq,
@@ -119,6 +256,53 @@ pub enum DiscoveryBody {
     ListRoomsRes {
         /// Summaries suitable for a lobby list UI.
         rooms: Vec<RoomSummary>,
+        /// `msg_id` of the answered [`DiscoveryBody::ListRoomsReq`]; absent from older peers.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        req_id: Option<String>,
+    },
+    /// Host closed the room; drop it from room lists.
+    CloseRoom {
+        /// Room identifier.
+        room_id: String,
+    },
+    /// A peer came online or is going away.
+    Presence {
+        /// Peer/node identifier.
+        peer_id: String,
+        /// `false` when the peer shuts down.
+        online: bool,
+    },
+    /// The sender vouches for a peer it knows, see [`crate::vouch`].
+    Vouch {
+        /// Peer vouched for.
+        peer_id: String,
+        /// Take an earlier vouch back.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        revoke: bool,
+    },
+    /// The sender's Elo rating in a game with the attestations behind it,
+    /// see [`crate::rating`]; long histories come in several parts.
+    Rating {
+        game: String,
+        rating: u32,
+        /// Rated matches in total.
+        games: usize,
+        /// Index of the first attestation of this part.
+        first: usize,
+        /// Signed [`crate::game::GameBody::Attest`] envelopes of opponents.
+        attestations: Vec<Value>,
+    },
+    /// Compact digest of one of the sender's tables, see [`crate::digest`].
+    Digest {
+        table: SyncTable,
+        digest: TableDigest,
+    },
+    /// Ask `peer_id` for its entries of `table` in `buckets`, after its
+    /// digest differed from ours there.
+    SyncReq {
+        peer_id: String,
+        table: SyncTable,
+        buckets: Vec<u8>,
     },
 }
 
@@ -133,6 +317,22 @@ pub struct RoomSummary {
     pub host_id: String,
     /// Last time this room was observed/announced (unix millis).
     pub last_seen: u64,
+    /// Join ticket (see [`crate::rooms::RoomTicket`]); absent from older hosts.
+    #[serde(default)]
+    pub ticket: Option<String>,
+    /// Players currently seen in the room, as counted by the host.
+    #[serde(default, alias = "players")]
+    pub current_players: Option<u32>,
+    /// Player cap set by the host; joins beyond it are turned away.
+    #[serde(default)]
+    pub max_players: Option<u32>,
+    /// Game played in the room and its settings; clients that do not
+    /// implement it should not join.
+    #[serde(default)]
+    pub game: Option<GameKind>,
+    /// Language spoken in the room (see [`crate::language`]).
+    #[serde(default)]
+    pub language: Option<String>,
 }
 
 /// Room control messages (room topic).
@@ -147,6 +347,10 @@ pub enum RoomBody {
         room_id: String,
         /// Desired display name inside the room.
         nickname: String,
+        /// Whether the peer comes to play or only to watch; absent from
+        /// older clients.
+        #[serde(default, skip_serializing_if = "MemberRole::is_player")]
+        role: MemberRole,
     },
     /// Acknowledge a join attempt (accept/reject). Sent by the host.
     JoinAck {
@@ -156,6 +360,16 @@ pub enum RoomBody {
         accept: bool,
         /// Optional reason if rejected.
         reason: Option<String>,
+        /// Peer the answer is for; absent from older hosts.
+        #[serde(default)]
+        peer_id: Option<String>,
+        /// Rejected because the room is at its player cap, not a ban.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        full: bool,
+        /// Rejected by the host's admission policy (see
+        /// [`crate::admission`]), not a ban.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        declined: bool,
     },
     /// Canonical member list broadcast by the host after changes.
     Members {
@@ -166,6 +380,31 @@ pub enum RoomBody {
         /// Current members (peer id + nickname).
         members: Vec<Member>,
     },
+    /// Periodic sign of life from a member, see [`crate::membership`].
+    Heartbeat {
+        /// Room id.
+        room_id: String,
+        /// Display name inside the room.
+        nickname: String,
+        /// Cosmetic title picked by the member, see [`crate::titles`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        /// The role the member joined with.
+        #[serde(default, skip_serializing_if = "MemberRole::is_player")]
+        role: MemberRole,
+    },
+    /// Removal of a member by the host; with `ban` set the peer may not
+    /// come back (see [`crate::bans`]).
+    Kick {
+        /// Room id.
+        room_id: String,
+        /// Peer being removed.
+        peer_id: String,
+        /// Optional reason shown to the room.
+        reason: Option<String>,
+        #[serde(default)]
+        ban: bool,
+    },
     /// Voluntary leave notification from a peer.
     Leave {
         /// Room id.
@@ -185,18 +424,96 @@ pub struct Member {
     pub peer_id: String,
     /// Display name inside the room.
     pub nickname: String,
+    /// Cosmetic title from the member's heartbeats.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "MemberRole::is_player")]
+    pub role: MemberRole,
+}
+
+/// What a member came to a room for.
+///
+/// Spectators get the room's chat and the state of its games like everybody
+/// else, but hosts never seat them, so they cannot send moves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberRole {
+    #[default]
+    Player,
+    Spectator,
+}
+
+impl MemberRole {
+    pub fn is_player(&self) -> bool {
+        *self == MemberRole::Player
+    }
+}
+
+/// Wire versions a peer can decode, advertised on the topics it listens to.
+///
+/// Always sent in the `ver = 1` format so every peer can read it; old peers
+/// simply fail to decode it as any payload they know and drop it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Supported envelope versions, ascending.
+    pub versions: Vec<u16>,
+    /// Games the client can play, with their [`Game::VERSION`]; absent from
+    /// older clients.
+    ///
+    /// [`Game::VERSION`]: crate::game::Game::VERSION
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub games: Option<BTreeMap<String, u32>>,
 }
 
 /// Nickname claim broadcast on the name-registry topic.
 ///
 /// The network reaches a deterministic decision about the owner by applying
 /// [`name_claim_wins`] consistently on all peers.
+///
+/// A claim is a lease: the owner broadcasts it again before `expires_at`
+/// (unix millis), and once that passes without renewal the name is free.
+/// Claims of older peers carry no expiry and hold until replaced.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NameClaim {
     pub nick_lower: String,
     pub nickname: String,
     pub owner_peer_id: String,
     pub since_ts: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Proof of work on the owner's key, see [`crate::sybil`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pow: Option<u64>,
+}
+
+impl NameClaim {
+    /// Whether the lease ran out by `now`.
+    pub fn expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|t| t <= now)
+    }
+
+    /// Release of this claim, sent now.
+    pub fn release(&self) -> NameRelease {
+        NameRelease {
+            nick_lower: self.nick_lower.clone(),
+            owner_peer_id: self.owner_peer_id.clone(),
+            since_ts: self.since_ts,
+            released_at: now_ms(),
+        }
+    }
+}
+
+/// Owner giving up a nickname (`logout`), sent on the name-registry topic.
+///
+/// It frees only the claim made at `since_ts`, so a late release cannot
+/// undo a newer claim of the same owner, and only when signed by the owner.
+/// `released_at` also tells it apart from a [`NameClaim`] on the wire.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameRelease {
+    pub nick_lower: String,
+    pub owner_peer_id: String,
+    pub since_ts: u64,
+    pub released_at: u64,
 }
 
 // ======================================================================
@@ -242,7 +559,10 @@ pub fn make_envelope<T>(
         sender_id,
         msg_id: Uuid::new_v4().to_string(),
         ts,
+        hlc: None,
         body,
+        sig: None,
+        pubkey: None,
     }
 }
 
@@ -260,14 +580,19 @@ pub fn name_claim_wins(a_owner: &str, a_ts: u64, b_owner: &str, b_ts: u64) -> bo
 
 /// Build a global chat envelope (ready for serialization and send).
 pub fn make_chat_global(sender_id: String, text: impl Into<String>) -> Envelope<ChatMsg> {
-    make_envelope(
+    let mut env = make_envelope(
         Kind::Chat,
         Scope::Global,
         None,
         sender_id,
         now_ms(),
-        ChatMsg { text: text.into() },
-    )
+        ChatMsg {
+            text: text.into(),
+            relay: None,
+        },
+    );
+    env.hlc = Some(hlc::now());
+    env
 }
 
 /// Build a room-scoped chat envelope (ready for serialization and send).
@@ -276,14 +601,55 @@ pub fn make_chat_room(
     sender_id: String,
     text: impl Into<String>,
 ) -> Envelope<ChatMsg> {
-    make_envelope(
+    let mut env = make_envelope(
         Kind::Chat,
         Scope::Room,
         Some(room_id.into()),
         sender_id,
         now_ms(),
-        ChatMsg { text: text.into() },
-    )
+        ChatMsg {
+            text: text.into(),
+            relay: None,
+        },
+    );
+    env.hlc = Some(hlc::now());
+    env
+}
+
+/// Build an edit of our chat message `original`, in the same scope.
+pub fn make_chat_edit(
+    original: &Envelope<ChatMsg>,
+    new_text: impl Into<String>,
+) -> Envelope<ChatEdit> {
+    let mut env = make_envelope(
+        Kind::Edit,
+        original.scope,
+        original.room_id.clone(),
+        original.sender_id.clone(),
+        now_ms(),
+        ChatEdit {
+            target_msg_id: original.msg_id.clone(),
+            new_text: new_text.into(),
+        },
+    );
+    env.hlc = Some(hlc::now());
+    env
+}
+
+/// Build the retraction of our chat message `original`.
+pub fn make_chat_delete(original: &Envelope<ChatMsg>) -> Envelope<ChatDelete> {
+    let mut env = make_envelope(
+        Kind::Delete,
+        original.scope,
+        original.room_id.clone(),
+        original.sender_id.clone(),
+        now_ms(),
+        ChatDelete {
+            target_msg_id: original.msg_id.clone(),
+        },
+    );
+    env.hlc = Some(hlc::now());
+    env
 }
 
 // ======================================================================
@@ -294,41 +660,248 @@ pub fn make_chat_room(
 #[derive(Parser, Debug)]
 #[command(name = "p2p-games", about = "P2P games over gossip")]
 pub struct AppCli {
+    /// Screen-reader friendly output: no colors, box-drawing or spinners.
+    #[arg(long, global = true, default_value_t = false)]
+    pub plain: bool,
+
+    /// Print results as JSON (one object per line for running commands).
+    #[arg(long, global = true, default_value_t = false)]
+    pub json: bool,
+
+    /// Low-power mode: longer periodic intervals, batched publishes, only the active room subscribed.
+    #[arg(long, global = true, default_value_t = false)]
+    pub low_power: bool,
+
+    /// Guest mode: throwaway identity and nickname for this run only; nothing is claimed or saved.
+    #[arg(long, global = true, default_value_t = false)]
+    pub guest: bool,
+
+    /// Player profile to use: its own identity, nickname and rooms. Without it the default profile is used.
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// When to color output; overrides `color` in `config.toml`.
+    #[arg(long, global = true, value_enum, value_name = "WHEN")]
+    pub color: Option<ColorChoice>,
+
+    /// Relay server to use instead of n0's, may be repeated; overrides
+    /// `relay_url` and `[relays] urls` in `config.toml`.
+    #[arg(long, global = true, value_name = "URL")]
+    pub relay_url: Vec<String>,
+
+    /// Send all iroh traffic through the relays, never directly.
+    #[arg(long, global = true, default_value_t = false, conflicts_with = "no_relay")]
+    pub relay_only: bool,
+
+    /// Use no relays; only direct connections work.
+    #[arg(long, global = true, default_value_t = false)]
+    pub no_relay: bool,
+
+    /// Neither publish our address to nor look nodes up in n0's DNS discovery.
+    #[arg(long, global = true, default_value_t = false)]
+    pub no_n0_discovery: bool,
+
+    /// Neither announce ourselves nor look for nodes on the local network (mDNS).
+    #[arg(long, global = true, default_value_t = false)]
+    pub no_local_discovery: bool,
+
+    /// Network transport; `tcp` for networks that block UDP,
+    /// `libp2p` to join libp2p networks. Overrides `transport` in `config.toml`.
+    #[arg(long, global = true, value_enum)]
+    pub transport: Option<TransportKind>,
+
+    /// How to report a failed command on stderr; see the exit codes in `p2p_core::error`.
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Text)]
+    pub errors: ErrorFormat,
+
     #[command(subcommand)]
     pub command: Command,
 }
 
+/// Format of the error printed when a command fails.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// `Error: <message>` for people.
+    Text,
+    /// One JSON object with `error`, `exit_code` and `message` for scripts.
+    Json,
+}
+
 /// High-level commands exposed to the user.
 #[derive(Subcommand, Debug)]
 pub enum Command {
+    /// Interactive first-run setup (nickname, identity, connectivity check).
+    Setup,
     /// Claim a unique nickname in the P2P network.
     Login {
-        /// Desired nickname.
+        /// Desired nickname; defaults to `nickname` in `config.toml`.
         #[arg(long)]
-        name: String,
-        /// If set, do not auto-rename on conflict (exit non-zero instead).
+        name: Option<String>,
+        /// If set, do not auto-rename on conflict (exit with `name_taken` instead).
         #[arg(long, default_value_t = false)]
         no_auto: bool,
-        /// Wait time (ms) to collect registry claims.
-        #[arg(long, default_value_t = 1200)]
-        wait_ms: u64,
+        /// Wait time (ms) to collect registry claims; overrides
+        /// `[timeouts] claim_wait_ms`.
+        #[arg(long)]
+        wait_ms: Option<u64>,
     },
+    /// Give up your nickname so others can claim it.
+    Logout,
     /// Print your node address (share with peers to enable direct connections).
-    Addr,
+    Addr {
+        /// Also copy it to the clipboard.
+        #[arg(long, default_value_t = false)]
+        copy: bool,
+    },
     /// Global chat (fixed topic).
     Global {
         /// Global subcommand (listen/say).
         #[command(subcommand)]
         sub: GlobalCmd,
     },
-    /// Room operations (you can have at most one active room).
+    /// Room operations (you can be in several rooms; one of them is active).
     Room {
-        /// Room subcommand (open/join/leave/say).
+        /// Room subcommand (open/join/leave/switch/say/list/browse/game).
         #[command(subcommand)]
         sub: RoomCmd,
     },
+    /// Send a direct message to a peer.
+    Dm {
+        /// Nickname or peer id of the recipient.
+        peer: String,
+        text: String,
+    },
+    /// Publish a network-wide banner; only shown by clients that list your
+    /// key under `[announcements] operators`.
+    Announce {
+        text: String,
+        /// What the banner is about.
+        #[arg(long, value_enum, default_value_t = crate::announce::AnnouncementKind::Info)]
+        kind: crate::announce::AnnouncementKind,
+        /// How long clients show it.
+        #[arg(long, default_value_t = 24)]
+        hours: u64,
+    },
+    /// Full-screen client: chat, rooms and members in one view.
+    Tui,
+    /// Keep one node up and read commands line by line (`/join foo`, `/say hi`, `/list`).
+    Repl,
+    /// Keep a node running in the background; other commands use it.
+    Daemon {
+        /// Also serve others as an archive node: `archive` for every role,
+        /// or single roles; repeatable (default: `[archive] roles`).
+        #[arg(long = "role", value_enum)]
+        roles: Vec<Role>,
+    },
+    /// Fetch match records from archive nodes, or deposit yours there.
+    Archive {
+        #[command(subcommand)]
+        sub: ArchiveCmd,
+    },
     /// Show local identity / session information.
     Whoami,
+    /// Check the release feed for a newer version (never installs anything).
+    Update,
+    /// Show local usage statistics (kept on this machine only), or your
+    /// results in one game.
+    Stats {
+        /// Game to show results, openings and streaks for.
+        game: Option<String>,
+        /// Only matches against this player (nickname or peer id).
+        #[arg(long, requires = "game")]
+        vs: Option<String>,
+    },
+    /// List peers that were recently active (approximate).
+    Who,
+    /// Accept the key a nickname is now claimed with, after a key warning.
+    Trust {
+        /// Nickname to re-pin to the peer last seen with it.
+        nickname: String,
+    },
+    /// Vouch for a peer you know; vouches are public and signed.
+    Vouch {
+        /// Nickname or peer id.
+        peer: String,
+        /// Take an earlier vouch back.
+        #[arg(long, default_value_t = false)]
+        revoke: bool,
+    },
+    /// Show what is known about a peer, including who vouches for it.
+    Whois {
+        /// Nickname or peer id.
+        peer: String,
+    },
+    /// Wait for an opponent of about your rating and play them in a new
+    /// room.
+    Match {
+        /// Game to play.
+        game: String,
+        /// Largest rating difference to accept.
+        #[arg(long, default_value_t = crate::matchmaking::DEFAULT_RANGE)]
+        range: u32,
+    },
+    /// Organize or play in a single-elimination tournament.
+    Tournament {
+        /// Tournament subcommand (create/join/status).
+        #[command(subcommand)]
+        sub: TournamentCmd,
+    },
+    /// Show your Elo ratings, or your rating history in one game.
+    Rating {
+        /// Game to show the history of.
+        game: Option<String>,
+        /// Publish the rating with the signed results behind it.
+        #[arg(long, default_value_t = false, requires = "game")]
+        publish: bool,
+    },
+    /// List the titles your achievements unlocked, or pick one to show
+    /// next to your nickname.
+    Title {
+        /// Title to show.
+        id: Option<String>,
+        /// Stop showing a title.
+        #[arg(long, default_value_t = false, conflicts_with = "id")]
+        clear: bool,
+    },
+    /// Show the languages you speak, or set them, best first; matchmaking
+    /// prefers opponents who share one.
+    Languages {
+        /// Language tags (e.g. `de`, `en`).
+        tags: Vec<String>,
+        /// Forget the languages set.
+        #[arg(long, default_value_t = false, conflicts_with = "tags")]
+        clear: bool,
+    },
+    /// Show node status: identity, active room and bandwidth usage.
+    Status,
+    /// Mirror chat between the rooms configured in `[[bridges]]`.
+    Bridge,
+    /// Let a browser page use this node over WebSocket (chat and games).
+    Websocket {
+        /// Address to accept connections on; keep it on loopback.
+        #[arg(long, default_value = "127.0.0.1:7448")]
+        listen: SocketAddr,
+    },
+    /// Show the effective key bindings (`[keymap]` in the config file).
+    Keys,
+    /// Manage the local message history.
+    History {
+        /// History subcommand (purge/search).
+        #[command(subcommand)]
+        sub: HistoryCmd,
+    },
+    /// Show or free the disk space used by history, replays and summaries.
+    Storage {
+        /// Storage subcommand (status/gc).
+        #[command(subcommand)]
+        sub: StorageCmd,
+    },
+    /// Developer tools.
+    Debug {
+        /// Debug subcommand (tail/replay).
+        #[command(subcommand)]
+        sub: DebugCmd,
+    },
 }
 
 /// Subcommands for the global chat.
@@ -338,26 +911,234 @@ pub enum GlobalCmd {
     Listen,
     /// Send a message to the global chat.
     Say { text: String },
+    /// Show stored global chat messages.
+    History {
+        /// How many of the newest messages to show.
+        #[arg(long, default_value_t = 20)]
+        last: usize,
+    },
+}
+
+/// Subcommands for tournaments.
+#[derive(Subcommand, Debug)]
+pub enum TournamentCmd {
+    /// Organize a tournament and print the ticket players join with.
+    Create {
+        /// Game to play.
+        game: String,
+        /// Start by itself once this many players joined (2 to 16).
+        #[arg(long, default_value_t = 8)]
+        players: usize,
+    },
+    /// Enter a tournament and play your matches as the bracket advances.
+    Join {
+        /// Ticket printed by `tournament create`.
+        ticket: String,
+    },
+    /// Show the bracket of a tournament, or list the ones you took part in.
+    Status {
+        /// Ticket of the tournament.
+        ticket: Option<String>,
+    },
+}
+
+/// Subcommands for archive nodes.
+#[derive(Subcommand, Debug)]
+pub enum ArchiveCmd {
+    /// Ask archive nodes for the record of a match; it is saved for
+    /// `debug replay --file`.
+    Fetch {
+        /// Game id of the match.
+        game_id: String,
+    },
+    /// Hand the record of a match you played to archive nodes.
+    Deposit {
+        /// Game id of the match, from `matches.jsonl`.
+        game_id: String,
+    },
+}
+
+/// Subcommands for the local message history.
+#[derive(Subcommand, Debug)]
+pub enum HistoryCmd {
+    /// Delete every stored message from a peer (nickname or peer id).
+    Purge {
+        #[arg(long)]
+        peer: String,
+    },
+    /// Search stored messages (case-insensitive).
+    Search {
+        query: String,
+        /// Only this room (name or topic).
+        #[arg(long)]
+        room: Option<String>,
+        /// Only messages from this peer (nickname or peer id).
+        #[arg(long)]
+        peer: Option<String>,
+        /// Show at most this many (newest) results.
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+}
+
+/// Subcommands for local storage.
+#[derive(Subcommand, Debug)]
+pub enum StorageCmd {
+    /// Show the space each category uses and its quota (`[storage]`).
+    Status,
+    /// Evict the least recently used data until every category fits.
+    Gc,
+}
+
+/// Subcommands for debugging.
+#[derive(Subcommand, Debug)]
+pub enum DebugCmd {
+    /// Print every frame published on a topic, decoded where possible.
+    Tail {
+        /// Topic name (e.g. `p2p-name-registry`) or 64-digit topic hex.
+        topic: String,
+    },
+    /// Play recorded matches again and check the current rules agree.
+    Replay {
+        /// Match log to check (default: the matches this node played).
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
 }
 
 /// Subcommands for room handling.
 #[derive(Subcommand, Debug)]
 pub enum RoomCmd {
     /// Open a room by name (becomes your active room).
-    Open { name: String },
+    Open {
+        name: String,
+        /// Game to advertise with the room.
+        #[arg(long)]
+        game: Option<String>,
+        /// Settings of that game as a JSON object.
+        #[arg(long, requires = "game")]
+        settings: Option<String>,
+        /// Copy the join ticket to the clipboard.
+        #[arg(long, default_value_t = false)]
+        copy: bool,
+        /// Turn join requests away once this many players (host included)
+        /// are in the room.
+        #[arg(long)]
+        max_players: Option<u32>,
+        /// Who may join (default: `admission` in the config file).
+        #[arg(long, value_enum)]
+        admit: Option<AdmissionPolicy>,
+        /// Language spoken in the room (e.g. `de`, `pt-BR`).
+        #[arg(long)]
+        lang: Option<String>,
+        /// Close the room after this many minutes with nobody else in it;
+        /// 0 keeps it open (default: `room_idle_minutes` in the config file).
+        #[arg(long, value_name = "MINUTES")]
+        idle_close: Option<u64>,
+    },
     /// Join a room via host node address & topic hex (becomes active room).
     Join {
+        /// Join ticket printed by `Open` (replaces `--addr` and `--topic`).
+        #[arg(long, conflicts_with_all = ["addr", "topic"])]
+        ticket: Option<String>,
         /// Host node address string (e.g., from `Addr` or `Open` output).
-        #[arg(long)]
-        addr: String,
+        #[arg(long, required_unless_present = "ticket")]
+        addr: Option<String>,
         /// Topic hex (32-byte topic id as hex; e.g., from `Open` output).
+        #[arg(long, required_unless_present = "ticket")]
+        topic: Option<String>,
+        /// Local name for the room (defaults to the start of the topic hex).
         #[arg(long)]
-        topic: String,
+        name: Option<String>,
+        /// Join as a spectator: follow chat and games, never take a seat.
+        #[arg(long, default_value_t = false)]
+        spectate: bool,
     },
-    /// Leave the currently active room.
-    Leave,
+    /// Leave a room (the active one if no name is given).
+    Leave { name: Option<String> },
+    /// Make another joined room the active one.
+    Switch { name: String },
     /// Say a line into the currently active room.
     Say { text: String },
+    /// Remove a member from the active room you host.
+    Kick {
+        /// Peer id or nickname.
+        peer: String,
+        #[arg(long)]
+        reason: Option<String>,
+        /// Also ban the peer from the room.
+        #[arg(long, default_value_t = false)]
+        ban: bool,
+    },
+    /// Ban a peer from the active room you host (same as `kick --ban`).
+    Ban {
+        /// Peer id or nickname.
+        peer: String,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Lift a ban in the active room you host.
+    Unban {
+        /// Peer id or nickname.
+        peer: String,
+    },
+    /// Show the bans of the active room.
+    Bans,
     /// List known/open rooms announced on the network.
+    List {
+        /// Only rooms tagged with this language.
+        #[arg(long)]
+        lang: Option<String>,
+    },
+    /// Browse open rooms and join one by picking it from the list.
+    Browse,
+    /// Play a game in the active room.
+    Game {
+        /// Game subcommand (start/play/list).
+        #[command(subcommand)]
+        sub: GameCmd,
+    },
+}
+
+/// Subcommands for games in the active room.
+#[derive(Subcommand, Debug)]
+pub enum GameCmd {
+    /// Start a game and wait for the other players to take their seats.
+    Start {
+        /// Game to play (see `room game list`).
+        game: String,
+        /// Only accept this peer id as opponent; repeat for several
+        /// (default: the first to show up).
+        #[arg(long)]
+        vs: Vec<String>,
+        /// Seats, yours included.
+        #[arg(long, default_value_t = 2)]
+        players: usize,
+        /// Seat order, and so who moves first.
+        #[arg(long, value_enum, default_value_t = TurnOrder::Clockwise)]
+        order: TurnOrder,
+        /// Seed for `--order seeded`.
+        #[arg(long, required_if_eq("order", "seeded"))]
+        seed: Option<u64>,
+    },
+    /// Invite a peer to a game in a new private room and host it once
+    /// they accept.
+    Invite {
+        /// Nickname or peer id.
+        peer: String,
+        /// Game to play (see `room game list`).
+        game: String,
+        /// Settings of that game as a JSON object.
+        #[arg(long)]
+        settings: Option<String>,
+        /// Seat order, and so who moves first.
+        #[arg(long, value_enum, default_value_t = TurnOrder::Clockwise)]
+        order: TurnOrder,
+    },
+    /// Take your seat in a game started by another member of the room.
+    Play,
+    /// Follow the game in the active room without taking a seat.
+    Watch,
+    /// Games this client can play.
     List,
 }
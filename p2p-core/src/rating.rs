@@ -0,0 +1,380 @@
+//! Elo ratings from attested results.
+//!
+//! When a two-player match ends, both players sign a
+//! [`GameBody::Attest`]: the [`GameResult`] as they saw it and their own
+//! rating in the game before it. The opponent's attestation is what counts
+//! for us, kept in `<data dir>/p2p-games/ratings.json` once the dispute
+//! window is over and it agrees with our own view of the match. Replaying
+//! the attestations in order gives our rating and its history per opponent:
+//! every match moves it by [`K_FACTOR`] times the score minus the expected
+//! score against the rating the opponent signed.
+//!
+//! Only matches whose result counts are replayed: the moves in our match log
+//! must lead to it and it must not be disputed ([`validation::counts`]). A
+//! signed rating is therefore only used for opponents we actually played
+//! the match against, move by move; a key signing results of matches that
+//! never happened moves nothing.
+//!
+//! `rating <game> --publish` sends the rating with the attestations behind
+//! it on the discovery topic ([`DiscoveryBody::Rating`], in parts that fit a
+//! gossip message). Other peers replay them the same way and only keep the
+//! rating if it comes out the same. A peer can still leave out matches it
+//! lost; what it cannot do is claim results its opponents did not sign.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::PathBuf,
+};
+
+use crate::{
+    dispute::DisputeBook,
+    game::{GameBody, GameRegistry, Outcome},
+    matchlog::MatchLog,
+    protocol::{DiscoveryBody, Envelope, now_ms},
+    session::data_dir,
+    signing, validation,
+};
+
+/// Rating before the first rated match.
+pub const INITIAL_RATING: f64 = 1200.0;
+
+/// Largest change one match can make.
+pub const K_FACTOR: f64 = 32.0;
+
+/// Attestations per [`DiscoveryBody::Rating`] message.
+const PER_MESSAGE: usize = 4;
+
+/// The result of a two-player match as both players sign it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameResult {
+    pub game_id: String,
+    /// [`crate::game::Game::NAME`].
+    pub game: String,
+    /// Peer ids in seat order.
+    pub players: Vec<String>,
+    pub outcome: Outcome,
+}
+
+impl GameResult {
+    /// Points of `peer_id`: 1 for a win, ½ for a draw, 0 for a loss; `None`
+    /// for aborted matches and for non-players.
+    pub fn score(&self, peer_id: &str) -> Option<f64> {
+        let seat = self.players.iter().position(|p| p == peer_id)?;
+        match self.outcome {
+            Outcome::Winner { seat: w } if w == seat => Some(1.0),
+            Outcome::Winner { .. } => Some(0.0),
+            Outcome::Draw => Some(0.5),
+            Outcome::Aborted { .. } => None,
+        }
+    }
+
+    /// Whether the match can be rated: two players and a winner among
+    /// them, or a draw.
+    pub fn rated(&self) -> bool {
+        self.players.len() == 2
+            && match self.outcome {
+                Outcome::Winner { seat } => seat < self.players.len(),
+                Outcome::Draw => true,
+                Outcome::Aborted { .. } => false,
+            }
+    }
+}
+
+/// A checked [`GameBody::Attest`].
+#[derive(Debug, Clone)]
+pub struct Attestation {
+    /// The player who signed it.
+    pub signer: String,
+    /// When it was signed (unix millis); orders the matches.
+    pub ts: u64,
+    pub result: GameResult,
+    /// The signer's rating in the game before the match.
+    pub rating: f64,
+}
+
+/// The attestation in a received envelope: validly signed by a player of a
+/// match that can be rated.
+pub fn open(signed: &Value) -> Option<Attestation> {
+    if !signed["sig"].is_string() || !signing::accept(signed) {
+        return None;
+    }
+    let env: Envelope<GameBody> = serde_json::from_value(signed.clone()).ok()?;
+    let GameBody::Attest { result, rating } = env.body else {
+        return None;
+    };
+    if !result.rated() || !result.players.contains(&env.sender_id) || !rating.is_finite() {
+        return None;
+    }
+    Some(Attestation {
+        signer: env.sender_id,
+        ts: env.ts,
+        result,
+        rating,
+    })
+}
+
+/// Expected score of a player rated `rating` against one rated `opponent`.
+pub fn expected(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+/// One rated match of ours.
+#[derive(Debug, Clone, Serialize)]
+pub struct RatingChange {
+    pub game_id: String,
+    pub opponent: String,
+    pub opponent_rating: f64,
+    pub score: f64,
+    pub before: f64,
+    pub after: f64,
+    pub at: u64,
+}
+
+/// The history of `peer_id` in `game` from the attestations of its
+/// opponents, oldest first. Matches attested twice count once.
+pub fn replay<'a>(
+    peer_id: &str,
+    game: &str,
+    attestations: impl IntoIterator<Item = &'a Attestation>,
+) -> Vec<RatingChange> {
+    let mut seen = BTreeSet::new();
+    let mut rated: Vec<_> = attestations
+        .into_iter()
+        .filter(|a| a.signer != peer_id && a.result.game == game)
+        .filter(|a| seen.insert(a.result.game_id.clone()))
+        .filter_map(|a| Some((a, a.result.score(peer_id)?)))
+        .collect();
+    rated.sort_by(|(a, _), (b, _)| (a.ts, &a.result.game_id).cmp(&(b.ts, &b.result.game_id)));
+    let mut rating = INITIAL_RATING;
+    rated
+        .into_iter()
+        .map(|(a, score)| {
+            let before = rating;
+            rating += K_FACTOR * (score - expected(before, a.rating));
+            RatingChange {
+                game_id: a.result.game_id.clone(),
+                opponent: a.signer.clone(),
+                opponent_rating: a.rating,
+                score,
+                before,
+                after: rating,
+                at: a.ts,
+            }
+        })
+        .collect()
+}
+
+/// Rating after `changes`.
+pub fn current(changes: &[RatingChange]) -> f64 {
+    changes.last().map_or(INITIAL_RATING, |c| c.after)
+}
+
+/// A rating another peer published and we checked.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Published {
+    pub rating: u32,
+    /// Rated matches behind it.
+    pub games: usize,
+    /// When we checked it (unix millis).
+    pub at: u64,
+}
+
+/// Parts of a published rating still coming in.
+#[derive(Debug, Clone, Default)]
+struct Pending {
+    rating: u32,
+    parts: Vec<Option<Value>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RatingBook {
+    /// `game_id` → the opponent's signed attestation.
+    attestations: BTreeMap<String, Value>,
+    /// Peer → game → its published rating.
+    published: BTreeMap<String, BTreeMap<String, Published>>,
+    #[serde(skip)]
+    pending: BTreeMap<(String, String), Pending>,
+    /// Ids of the logged matches whose result counts, read on load.
+    #[serde(skip)]
+    counted: BTreeSet<String>,
+}
+
+/// Ids of the matches in our log whose result counts.
+fn counted_matches() -> BTreeSet<String> {
+    let registry = GameRegistry::builtin();
+    let disputes = DisputeBook::load().unwrap_or_default();
+    MatchLog::open()
+        .load()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|m| validation::counts(m, &registry, &disputes))
+        .map(|m| m.game_id)
+        .collect()
+}
+
+impl RatingBook {
+    fn storage_path() -> PathBuf {
+        data_dir().join("ratings.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        let mut book: Self = if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+        } else {
+            Self::default()
+        };
+        book.counted = counted_matches();
+        Ok(book)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// Keep the opponent's `signed` attestation of the match we saw as
+    /// `ours`; returns whether it was new and agrees with it.
+    pub fn record(&mut self, signed: Value, me: &str, ours: &GameResult) -> bool {
+        let agrees = open(&signed).is_some_and(|a| {
+            a.signer != me && a.result == *ours && ours.players.iter().any(|p| p == me)
+        });
+        agrees && !self.attestations.contains_key(&ours.game_id) && {
+            self.attestations.insert(ours.game_id.clone(), signed);
+            true
+        }
+    }
+
+    /// The newest attestation `opponent` signed for a match of `game`
+    /// since `since` (unix millis).
+    pub fn latest_from(&self, opponent: &str, game: &str, since: u64) -> Option<&Value> {
+        self.attestations
+            .values()
+            .filter_map(|signed| Some((signed, open(signed)?)))
+            .filter(|(_, a)| a.signer == opponent && a.result.game == game && a.ts >= since)
+            .max_by_key(|(_, a)| a.ts)
+            .map(|(signed, _)| signed)
+    }
+
+    /// Attestations of the matches whose result counts.
+    fn opened(&self) -> Vec<Attestation> {
+        self.attestations
+            .iter()
+            .filter(|(game_id, _)| self.counted.contains(*game_id))
+            .filter_map(|(_, signed)| open(signed))
+            .collect()
+    }
+
+    /// Games we have rated matches in.
+    pub fn games(&self) -> BTreeSet<String> {
+        self.opened().into_iter().map(|a| a.result.game).collect()
+    }
+
+    /// Our history in `game`, oldest first.
+    pub fn changes(&self, me: &str, game: &str) -> Vec<RatingChange> {
+        replay(me, game, &self.opened())
+    }
+
+    /// Our rating in `game`.
+    pub fn rating(&self, me: &str, game: &str) -> f64 {
+        current(&self.changes(me, game))
+    }
+
+    /// Our rating in `game` with its attestations, as the discovery
+    /// messages that publish it.
+    pub fn summary(&self, me: &str, game: &str) -> Vec<DiscoveryBody> {
+        let changes = self.changes(me, game);
+        let rating = current(&changes).round() as u32;
+        let signed: Vec<Value> = changes
+            .iter()
+            .filter_map(|c| self.attestations.get(&c.game_id).cloned())
+            .collect();
+        let games = signed.len();
+        let mut parts: Vec<DiscoveryBody> = signed
+            .chunks(PER_MESSAGE)
+            .enumerate()
+            .map(|(i, chunk)| DiscoveryBody::Rating {
+                game: game.to_string(),
+                rating,
+                games,
+                first: i * PER_MESSAGE,
+                attestations: chunk.to_vec(),
+            })
+            .collect();
+        if parts.is_empty() {
+            parts.push(DiscoveryBody::Rating {
+                game: game.to_string(),
+                rating,
+                games,
+                first: 0,
+                attestations: Vec::new(),
+            });
+        }
+        parts
+    }
+
+    /// Take in a part of a published rating; returns whether it completed
+    /// one that checks out.
+    pub fn apply(&mut self, env: &Envelope<DiscoveryBody>) -> bool {
+        let DiscoveryBody::Rating {
+            game,
+            rating,
+            games,
+            first,
+            attestations,
+        } = &env.body
+        else {
+            return false;
+        };
+        if env.sig.is_none() || first + attestations.len() > *games {
+            return false;
+        }
+        let key = (env.sender_id.clone(), game.clone());
+        let pending = self.pending.entry(key.clone()).or_default();
+        if pending.rating != *rating || pending.parts.len() != *games {
+            *pending = Pending {
+                rating: *rating,
+                parts: vec![None; *games],
+            };
+        }
+        for (slot, signed) in pending.parts[*first..].iter_mut().zip(attestations) {
+            *slot = Some(signed.clone());
+        }
+        if pending.parts.iter().any(Option::is_none) {
+            return false;
+        }
+        let Some(pending) = self.pending.remove(&key) else {
+            return false;
+        };
+        let opened: Option<Vec<Attestation>> = pending.parts.iter().flatten().map(open).collect();
+        let Some(opened) = opened else {
+            return false;
+        };
+        let changes = replay(&env.sender_id, game, &opened);
+        if changes.len() != *games || current(&changes).round() as u32 != *rating {
+            return false;
+        }
+        self.published
+            .entry(env.sender_id.clone())
+            .or_default()
+            .insert(
+                game.clone(),
+                Published {
+                    rating: *rating,
+                    games: *games,
+                    at: now_ms(),
+                },
+            );
+        true
+    }
+
+    /// Ratings `peer_id` published and we checked, by game.
+    pub fn published(&self, peer_id: &str) -> Option<&BTreeMap<String, Published>> {
+        self.published.get(peer_id)
+    }
+}
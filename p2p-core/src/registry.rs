@@ -1,28 +1,46 @@
 use anyhow::Result;
-use tokio::time::{timeout, Duration};
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::Serialize;
 
 use crate::protocol::{
-    Envelope, NameClaim, NAME_REGISTRY_TOPIC_NAME, now_ms, name_claim_wins,
+    Envelope, NameClaim, NameRelease, NAME_REGISTRY_TOPIC_NAME, now_ms, name_claim_wins,
 };
-use transport_iroh::transport_iroh::GossipTransport;
+use crate::digest::{Digestible, bucket_of};
+use crate::directory::PeerDirectory;
+use crate::middleware::RecentIds;
+use crate::signing;
+use crate::sybil::{Proof, SybilConfig};
+use crate::vouch::VouchBook;
+use crate::timeouts::{collect, retry, Timeouts};
+use transport_iroh::transport_iroh::{GossipTransport, TopicHandle};
 
 #[derive(Debug, Default, Clone)]
 pub struct NameTable {
     owners: BTreeMap<String, (String, u64, String)>,
+    /// Lease end of the owning claim; names without one are held until a
+    /// winning claim replaces them.
+    expires: BTreeMap<String, u64>,
 }
 
 impl NameTable {
     pub fn apply(&mut self, c: &NameClaim) {
+        let now = now_ms();
+        self.gc(now);
+        if c.expired(now) {
+            return;
+        }
         match self.owners.get(&c.nick_lower) {
             None => {
                 self.owners.insert(c.nick_lower.clone(), (c.owner_peer_id.clone(), c.since_ts, c.nickname.clone()));
+                self.lease(c);
             }
             Some((owner, since, casing)) => {
                 if *owner == c.owner_peer_id {
                     if &c.nickname != casing {
                         self.owners.insert(c.nick_lower.clone(), (owner.clone(), *since, c.nickname.clone()));
                     }
+                    self.lease(c);
                 } else {
                     let win_new = name_claim_wins(&c.owner_peer_id, c.since_ts, owner, *since);
                     let(w_owner, w_ts, w_name) = if win_new {
@@ -31,75 +49,378 @@ impl NameTable {
                         (owner.clone(), *since, casing.clone())
                     };
                     self.owners.insert(c.nick_lower.clone(), (w_owner, w_ts, w_name));
+                    if win_new {
+                        self.lease(c);
+                    }
                 }
             }
         }
     }
 
+    /// Free the name of a released claim; only signed releases of the
+    /// current owner's claim count.
+    pub fn release(&mut self, env: &Envelope<NameRelease>) {
+        let r = &env.body;
+        if env.sig.is_none() || env.sender_id != r.owner_peer_id {
+            return;
+        }
+        if self.owners.get(&r.nick_lower).is_some_and(|(owner, since, _)| {
+            *owner == r.owner_peer_id && *since == r.since_ts
+        }) {
+            self.owners.remove(&r.nick_lower);
+            self.expires.remove(&r.nick_lower);
+        }
+    }
+
+    /// `c` owns its name now; remember when its lease runs out.
+    fn lease(&mut self, c: &NameClaim) {
+        match c.expires_at {
+            Some(t) => self.expires.insert(c.nick_lower.clone(), t),
+            None => self.expires.remove(&c.nick_lower),
+        };
+    }
+
+    /// Drop names whose lease ran out by `now`, so they can be claimed again.
+    pub fn gc(&mut self, now: u64) {
+        let expired: Vec<String> = self.expires.iter()
+            .filter(|(_, t)| **t <= now)
+            .map(|(nick, _)| nick.clone())
+            .collect();
+        for nick in expired {
+            self.expires.remove(&nick);
+            self.owners.remove(&nick);
+        }
+    }
+
     pub fn owner(&self, nick_lower: &str) -> Option<&(String, u64, String)> {
-        self.owners.get(nick_lower)
+        let expired = self.expires.get(nick_lower).is_some_and(|t| *t <= now_ms());
+        self.owners.get(nick_lower).filter(|_| !expired)
     }
 }
 
 pub struct NameRegistry<'a> {
         transport: &'a dyn GossipTransport,
+        timeouts: Timeouts,
+        sybil: SybilConfig,
     }
 
     impl<'a> NameRegistry<'a> {
-        pub fn new(transport: &'a dyn GossipTransport) -> Self {
+        pub fn new(transport: &'a dyn GossipTransport, timeouts: Timeouts) -> Self {
             Self {
-                transport
+                transport,
+                timeouts,
+                sybil: SybilConfig::default(),
             }
         }
 
-        pub async fn claim_unique(&self, desired: &str, my_peer_id: &str, wait_ms: u64) -> Result<(String, bool)> {
-            let topic = self.transport.topic_from_name(NAME_REGISTRY_TOPIC_NAME);
-            let mut th = self.transport.join_topic(topic).await?;
+        /// Ignore claims of peers that are not established by `sybil` when
+        /// claiming, and send our proof of work of its difficulty.
+        pub fn with_sybil(mut self, sybil: &SybilConfig) -> Self {
+            self.sybil = sybil.clone();
+            self
+        }
+
+        pub async fn claim_unique(&self, desired: &str, my_peer_id: &str) -> Result<(String, bool)> {
+            self.claim_unique_at(desired, my_peer_id, now_ms()).await
+        }
 
+        /// Like [`claim_unique`](Self::claim_unique) with an explicit claim
+        /// time, which callers keep to recognise later conflicts (see
+        /// [`NickGuard`]).
+        pub async fn claim_unique_at(&self, desired: &str, my_peer_id: &str, since_ts: u64) -> Result<(String, bool)> {
             let claim = NameClaim {
                 nick_lower: desired.to_lowercase(),
                 nickname: desired.to_string(),
                 owner_peer_id: my_peer_id.to_string(),
-                since_ts: now_ms(),
+                since_ts,
+                expires_at: Some(now_ms() + self.timeouts.nick_lease_ms),
+                pow: Proof::ensure(my_peer_id, self.sybil.pow_bits, |_| {}).await,
             };
 
+            let mut th = self.publish_claim(&claim).await?;
+
+            let mut table = NameTable::default();
+            table.apply(&claim);
+
+            let mut recent = RecentIds::default();
+            let mut directory = PeerDirectory::load().unwrap_or_default();
+            let vouches = VouchBook::load().unwrap_or_default();
+            let window = self.timeouts.window(self.timeouts.claim_wait(), &*th);
+            collect(&mut th, window, |b| {
+                if let Some(env) = signing::open::<NameRelease>(b)
+                    .filter(|e| recent.insert(&e.msg_id))
+                {
+                    table.release(&env);
+                    return true;
+                }
+                let Some(env) = signing::open::<NameClaim>(b)
+                    .filter(|e| recent.insert(&e.msg_id))
+                else {
+                    return false;
+                };
+                let owner = &env.body.owner_peer_id;
+                directory.seen(owner, None);
+                directory.proof(owner, env.body.pow);
+                if owner != my_peer_id && !self.sybil.established(owner, &directory, &vouches) {
+                    return false;
+                }
+                table.apply(&env.body);
+                true
+            }).await;
+
+            if let Some((owner, _, name)) = table.owner(&desired.to_lowercase())
+                && owner == my_peer_id
+            {
+                return Ok((name.clone(), true))
+            }
+
+            Ok((suggest_nickname(desired, my_peer_id), false))
+        }
+
+        /// Broadcast `claim` again, e.g. to dispute a conflicting claim.
+        pub async fn publish_claim(&self, claim: &NameClaim) -> Result<Box<dyn TopicHandle>> {
+            self.publish(&claim.owner_peer_id, claim.since_ts, claim.clone()).await
+        }
+
+        /// Broadcast that we give up a nickname.
+        pub async fn publish_release(&self, release: &NameRelease) -> Result<()> {
+            self.publish(&release.owner_peer_id, release.released_at, release.clone()).await?;
+            Ok(())
+        }
+
+        async fn publish<T: Serialize>(&self, sender_id: &str, ts: u64, body: T) -> Result<Box<dyn TopicHandle>> {
+            let topic = self.transport.topic_from_name(NAME_REGISTRY_TOPIC_NAME);
             let env = Envelope {
                 ver: crate::protocol::PROTOCOL_VER,
                 kind: crate::protocol::Kind::Room,
                 scope: crate::protocol::Scope::Global,
                 room_id: None,
-                sender_id: my_peer_id.to_string(),
+                sender_id: sender_id.to_string(),
                 msg_id: uuid::Uuid::new_v4().to_string(),
-                ts: claim.since_ts,
-                body: claim.clone(),
+                ts,
+                hlc: None,
+                body,
+                sig: None,
+                pubkey: None,
             };
 
-            let bytes = serde_json::to_vec(&env)?;
-            th.publish(&bytes).await?;
+            let bytes = &signing::to_signed_vec(&env);
+            retry(&self.timeouts.retry, || async move {
+                let th = self.transport.join_topic(topic).await?;
+                th.publish(bytes).await?;
+                Ok(th)
+            }).await
+        }
+    }
 
-            let mut table = NameTable::default();
-            table.apply(&claim);
+/// Fallback nickname when `desired` is taken: `<desired>-<peer id prefix>`.
+pub fn suggest_nickname(desired: &str, my_peer_id: &str) -> String {
+    let suffix = &my_peer_id[..6.min(my_peer_id.len())];
+    format!("{}-{}", desired, suffix)
+}
 
-            let _ = timeout(Duration::from_millis(wait_ms), async {
-                loop {
-                    match th.next().await {
-                        Ok(b) => {
-                            if let Ok(env) = serde_json::from_slice::<Envelope<NameClaim>> (&b) {
-                                table.apply(&env.body);
-                            }
-                        }
-                        Err(_) => break,
-                    }
-                }
-            }).await;
+/// A remote claim beat the nickname we are using.
+#[derive(Debug, Clone)]
+pub struct NickConflictLost {
+    pub nickname: String,
+    pub winner_peer_id: String,
+    pub winner_since: u64,
+    /// Name to fall back to, as [`NameRegistry::claim_unique`] would pick.
+    pub suggestion: String,
+}
 
-            if let Some((owner, _, name)) = table.owner(&desired.to_lowercase()) {
-                if owner == my_peer_id {
-                    return Ok((name.clone(), true))
+/// Watches registry traffic for claims that beat ours.
+///
+/// Every peer settles conflicts with [`name_claim_wins`], so once a winning
+/// claim shows up the rest of the network considers the name taken even if
+/// we won it at login (clock skew, healed partition).
+#[derive(Debug, Clone)]
+pub struct NickGuard {
+    mine: NameClaim,
+    lost: bool,
+}
+
+impl NickGuard {
+    pub fn new(mine: NameClaim) -> Self {
+        Self { mine, lost: false }
+    }
+
+    /// Our claim, to re-broadcast when disputing.
+    pub fn claim(&self) -> &NameClaim {
+        &self.mine
+    }
+
+    /// Our claim with its lease extended by `lease` from now, to broadcast
+    /// periodically (and when disputing) so the name stays ours.
+    pub fn renew(&mut self, lease: Duration) -> &NameClaim {
+        self.mine.expires_at = Some(now_ms() + lease.as_millis() as u64);
+        &self.mine
+    }
+
+    /// Reports the first claim that beats ours; later ones are ignored.
+    /// Expired claims are not rivals.
+    pub fn observe(&mut self, c: &NameClaim) -> Option<NickConflictLost> {
+        if self.lost
+            || c.expired(now_ms())
+            || c.nick_lower != self.mine.nick_lower
+            || c.owner_peer_id == self.mine.owner_peer_id
+            || !name_claim_wins(&c.owner_peer_id, c.since_ts, &self.mine.owner_peer_id, self.mine.since_ts)
+        {
+            return None;
+        }
+        self.lost = true;
+        Some(NickConflictLost {
+            nickname: self.mine.nickname.clone(),
+            winner_peer_id: c.owner_peer_id.clone(),
+            winner_since: c.since_ts,
+            suggestion: suggest_nickname(&self.mine.nickname, &self.mine.owner_peer_id),
+        })
+    }
+    /// Whether `c` is a rival claim on our nickname that loses against
+    /// ours, i.e. one to answer by re-broadcasting our claim.
+    pub fn defends(&self, c: &NameClaim) -> bool {
+        !self.lost
+            && !c.expired(now_ms())
+            && c.nick_lower == self.mine.nick_lower
+            && c.owner_peer_id != self.mine.owner_peer_id
+            && name_claim_wins(&self.mine.owner_peer_id, self.mine.since_ts, &c.owner_peer_id, c.since_ts)
+    }
+}
+
+/// Minimum time between two re-broadcasts of the same cached claim.
+const DEFEND_COOLDOWN_MS: u64 = 5_000;
+
+#[derive(Debug, Clone)]
+struct CachedClaim {
+    claim: NameClaim,
+    /// The claim as received, with the owner's signature.
+    bytes: Vec<u8>,
+    /// Last activity of the owner (unix millis).
+    owner_seen: u64,
+    /// Last time we re-broadcast it.
+    defended: u64,
+}
+
+/// Signed nickname claims of other peers, defended on their behalf.
+///
+/// Every listener keeps the winning signed claim per nickname. When a rival
+/// claim shows up that loses against a cached one, the cached claim is sent
+/// again unchanged, so the claimant sees it within its claim wait and picks
+/// another name. This goes on while the owner is active and for a grace
+/// period (`[timeouts] nick_grace_ms`) after it was last seen or said
+/// goodbye, so a crash and restart doesn't let someone take the name, but
+/// never past the end of the claim's lease.
+#[derive(Debug, Clone)]
+pub struct ClaimCache {
+    grace_ms: u64,
+    claims: BTreeMap<String, CachedClaim>,
+}
+
+impl ClaimCache {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            grace_ms: grace.as_millis() as u64,
+            claims: BTreeMap::new(),
+        }
+    }
+
+    /// Record a claim received as `bytes`; returns the cached claim to
+    /// re-broadcast when `env` lost against it.
+    pub fn observe(&mut self, env: &Envelope<NameClaim>, bytes: &[u8]) -> Option<Vec<u8>> {
+        let now = now_ms();
+        let c = &env.body;
+        self.owner_seen_at(&c.owner_peer_id, now);
+        if c.expired(now) {
+            return None;
+        }
+        let grace_ms = self.grace_ms;
+        if let Some(cached) = self.claims.get_mut(&c.nick_lower)
+            && now.saturating_sub(cached.owner_seen) < grace_ms
+            && !cached.claim.expired(now)
+        {
+            let mine = &cached.claim;
+            if mine.owner_peer_id == c.owner_peer_id {
+                // Same claim again, likely renewed: keep the latest lease.
+                if c.since_ts == mine.since_ts {
+                    cached.claim = c.clone();
+                    cached.bytes = bytes.to_vec();
+                }
+                return None;
+            }
+            if name_claim_wins(&mine.owner_peer_id, mine.since_ts, &c.owner_peer_id, c.since_ts) {
+                if now.saturating_sub(cached.defended) < DEFEND_COOLDOWN_MS {
+                    return None;
                 }
+                cached.defended = now;
+                return Some(cached.bytes.clone());
             }
+        }
+        // Only claims signed by their owner can be replayed for them.
+        if env.sig.is_some() && env.sender_id == c.owner_peer_id {
+            self.claims.insert(c.nick_lower.clone(), CachedClaim {
+                claim: c.clone(),
+                bytes: bytes.to_vec(),
+                owner_seen: now,
+                defended: 0,
+            });
+        }
+        None
+    }
 
-            let suffix = &my_peer_id[..6.min(my_peer_id.len())];
-            Ok((format!("{}-{}", desired, suffix), false))
+    /// Stop defending a claim its owner released.
+    pub fn release(&mut self, env: &Envelope<NameRelease>) {
+        let r = &env.body;
+        if env.sig.is_none() || env.sender_id != r.owner_peer_id {
+            return;
         }
-    }
\ No newline at end of file
+        if self.claims.get(&r.nick_lower).is_some_and(|c| {
+            c.claim.owner_peer_id == r.owner_peer_id && c.claim.since_ts == r.since_ts
+        }) {
+            self.claims.remove(&r.nick_lower);
+        }
+    }
+
+    /// `peer_id` was active just now.
+    pub fn owner_seen(&mut self, peer_id: &str) {
+        self.owner_seen_at(peer_id, now_ms());
+    }
+
+    fn owner_seen_at(&mut self, peer_id: &str, at: u64) {
+        for cached in self.claims.values_mut().filter(|c| c.claim.owner_peer_id == peer_id) {
+            cached.owner_seen = cached.owner_seen.max(at);
+        }
+    }
+
+    /// Frames of the claims in digest `buckets`, to answer a
+    /// [`crate::protocol::DiscoveryBody::SyncReq`].
+    pub fn in_buckets(&self, buckets: &[u8]) -> Vec<Vec<u8>> {
+        self.claims
+            .iter()
+            .filter(|(nick, c)| !c.claim.expired(now_ms()) && buckets.contains(&bucket_of(nick)))
+            .map(|(_, c)| c.bytes.clone())
+            .collect()
+    }
+
+    /// Drop claims whose grace period or lease is over.
+    pub fn expire(&mut self) {
+        let now = now_ms();
+        let grace_ms = self.grace_ms;
+        self.claims.retain(|_, c| {
+            now.saturating_sub(c.owner_seen) < grace_ms && !c.claim.expired(now)
+        });
+    }
+}
+
+impl Digestible for ClaimCache {
+    fn entries(&self) -> Vec<(String, String)> {
+        let now = now_ms();
+        self.claims
+            .iter()
+            .filter(|(_, c)| !c.claim.expired(now))
+            .map(|(nick, c)| {
+                let value = format!("{}\0{}", c.claim.owner_peer_id, c.claim.since_ts);
+                (nick.clone(), value)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,100 @@
+//! Recorded matches, played again through the current engines.
+//!
+//! [`TurnEngine`](crate::turn::TurnEngine) keeps every move it applies, and a
+//! finished match is logged with that [`Replay`] (see
+//! [`MatchRecord`](crate::matchlog::MatchRecord)). [`Replay::verify`] applies
+//! the moves to a fresh game from today's [`GameRegistry`] and checks that it
+//! ends in the same state with the same outcome, so a rules change that
+//! would make peers on different versions disagree about a running game
+//! shows up before release: `cargo test` replays the recorded matches in
+//! `p2p-core/tests/replays/`, and `p2p-games debug replay` those of a match
+//! log.
+//!
+//! A match that took over a [`GameBody::StateSync`](crate::game::GameBody)
+//! skipped moves and has no replay.
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    game::{DropPolicy, GameRegistry, Outcome, Seat},
+    t,
+};
+
+/// A player leaving or coming back, recorded in place of a move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeatChange {
+    /// Handled as the game's [`DropPolicy`](crate::game::DropPolicy) says.
+    Dropped,
+    /// Took the seat back from the bot.
+    Rejoined,
+}
+
+/// One applied move.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayMove {
+    pub seat: Seat,
+    pub mv: Value,
+    /// Set when the seat's player left or came back instead of moving
+    /// (`mv` is null).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<SeatChange>,
+}
+
+/// The moves of a match and where they led.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    /// In the order they were applied.
+    pub moves: Vec<ReplayMove>,
+    /// Game state after the last move.
+    pub state: Value,
+    /// What the game reported after the last move (`None` if unfinished).
+    pub outcome: Option<Outcome>,
+}
+
+impl Replay {
+    /// Play the moves on a new `game` for `players` seats and compare the
+    /// result with the recording.
+    pub fn verify(&self, registry: &GameRegistry, game: &str, players: usize) -> Result<()> {
+        let mut g = registry.create(game, players)?;
+        for (i, m) in self.moves.iter().enumerate() {
+            let index = i + 1;
+            match (m.change, g.on_drop()) {
+                (None, _) => {}
+                // Bots play ordinary moves; only the seat's player changes.
+                (Some(SeatChange::Rejoined), _) | (Some(_), DropPolicy::Bot) => continue,
+                (Some(SeatChange::Dropped), _) => {
+                    g.eliminate(m.seat)
+                        .map_err(|e| anyhow!(t!("replay.rejected", index = index, error = e)))?;
+                    continue;
+                }
+            }
+            if g.to_move() != Some(m.seat) {
+                bail!(t!("replay.out_of_turn", index = index, seat = m.seat));
+            }
+            g.apply(m.seat, &m.mv)
+                .map_err(|e| anyhow!(t!("replay.rejected", index = index, error = e)))?;
+        }
+        let outcome = g.outcome();
+        if outcome != self.outcome {
+            bail!(t!(
+                "replay.outcome_differs",
+                recorded = describe(self.outcome.as_ref()),
+                replayed = describe(outcome.as_ref())
+            ));
+        }
+        if g.state() != self.state {
+            bail!(t!("replay.state_differs"));
+        }
+        Ok(())
+    }
+}
+
+fn describe(outcome: Option<&Outcome>) -> String {
+    match outcome {
+        Some(o) => serde_json::to_string(o).unwrap_or_default(),
+        None => t!("replay.unfinished"),
+    }
+}
@@ -0,0 +1,255 @@
+//! Membership in several rooms at once.
+//!
+//! [`RoomManager`] is the persisted list of joined rooms plus a pointer to
+//! the *active* one, which is where `room say` goes. [`TopicFeed`] merges the
+//! live subscriptions of several rooms into one stream so a listener can
+//! follow all of them. [`RoomTicket`] bundles everything needed to join a
+//! room into one string, and [`RoomPresence`] counts who is around.
+//! [`IdleTimer`] tells a host when a room it opened has been left empty.
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use transport_iroh::transport_iroh::{NeighborCount, TopicHandle};
+
+use crate::{
+    admission::AdmissionPolicy,
+    protocol::{MemberRole, now_ms},
+};
+
+const TICKET_PREFIX: &str = "p2pg:";
+
+/// Host address and topic of a room, as one copyable string
+/// (`p2pg:<topic hex>@<host node id>`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomTicket {
+    pub topic_hex: String,
+    pub host_addr: String,
+}
+
+impl fmt::Display for RoomTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{TICKET_PREFIX}{}@{}", self.topic_hex, self.host_addr)
+    }
+}
+
+impl FromStr for RoomTicket {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (topic_hex, host_addr) = s
+            .trim()
+            .strip_prefix(TICKET_PREFIX)
+            .and_then(|rest| rest.split_once('@'))
+            .ok_or_else(|| anyhow!("not a room ticket: {s}"))?;
+        if topic_hex.is_empty() || host_addr.is_empty() {
+            bail!("incomplete room ticket: {s}");
+        }
+        Ok(Self {
+            topic_hex: topic_hex.to_string(),
+            host_addr: host_addr.to_string(),
+        })
+    }
+}
+
+/// Peers recently seen per room; cheap to clone.
+#[derive(Clone, Default)]
+pub struct RoomPresence {
+    seen: Arc<Mutex<HashMap<String, HashMap<String, u64>>>>,
+}
+
+impl RoomPresence {
+    pub fn seen(&self, room: &str, peer_id: &str) {
+        let mut seen = self.seen.lock().unwrap();
+        seen.entry(room.to_string())
+            .or_default()
+            .insert(peer_id.to_string(), now_ms());
+    }
+
+    /// Distinct peers seen in `room` within `window`.
+    pub fn count(&self, room: &str, window: Duration) -> u32 {
+        let since = now_ms().saturating_sub(window.as_millis() as u64);
+        let seen = self.seen.lock().unwrap();
+        seen.get(room).map_or(0, |peers| {
+            peers.values().filter(|t| **t >= since).count() as u32
+        })
+    }
+}
+
+/// Minutes a hosted room may stay empty before it is closed, unless
+/// configured otherwise.
+pub const DEFAULT_IDLE_MINUTES: u64 = 60;
+
+/// Host-side clock of how long a room has had nobody but the host in it,
+/// so forgotten rooms get closed instead of being announced forever.
+#[derive(Debug, Clone)]
+pub struct IdleTimer {
+    after: Duration,
+    empty_since: Option<Instant>,
+}
+
+impl IdleTimer {
+    /// Close after `after` without company; the room counts as empty from
+    /// `now`, when it opened.
+    pub fn new(after: Duration, now: Instant) -> Self {
+        Self {
+            after,
+            empty_since: Some(now),
+        }
+    }
+
+    /// Note how many peers other than the host are around; true once the
+    /// room has been empty for the whole period.
+    pub fn observe(&mut self, others: u32, now: Instant) -> bool {
+        if others > 0 {
+            self.empty_since = None;
+            return false;
+        }
+        let since = *self.empty_since.get_or_insert(now);
+        now.duration_since(since) >= self.after
+    }
+}
+
+/// A joined room as remembered across invocations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomRef {
+    /// Display name; unique within the manager (case-insensitive).
+    pub name: String,
+    pub topic_hex: String,
+    /// Host address to dial before subscribing.
+    pub host_addr: Option<String>,
+    /// Player cap of a room we host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_players: Option<u32>,
+    /// Who may join a room we host; unset for the configured default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admission: Option<AdmissionPolicy>,
+    /// Whether we joined to play or only to watch.
+    #[serde(default, skip_serializing_if = "MemberRole::is_player")]
+    pub role: MemberRole,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoomManager {
+    rooms: Vec<RoomRef>,
+    active: Option<String>,
+}
+
+impl RoomManager {
+    /// Add (or refresh) a room and make it the active one.
+    pub fn join(&mut self, room: RoomRef) {
+        self.rooms
+            .retain(|r| !r.name.eq_ignore_ascii_case(&room.name) && r.topic_hex != room.topic_hex);
+        self.active = Some(room.name.clone());
+        self.rooms.push(room);
+    }
+
+    /// Remove a room; if it was active, the most recently joined one takes over.
+    pub fn leave(&mut self, name: &str) -> Option<RoomRef> {
+        let idx = self.position(name)?;
+        let room = self.rooms.remove(idx);
+        if self.active.as_deref() == Some(room.name.as_str()) {
+            self.active = self.rooms.last().map(|r| r.name.clone());
+        }
+        Some(room)
+    }
+
+    pub fn switch(&mut self, name: &str) -> Result<&RoomRef> {
+        let idx = self
+            .position(name)
+            .ok_or_else(|| anyhow!("not a member of room '{name}'"))?;
+        self.active = Some(self.rooms[idx].name.clone());
+        Ok(&self.rooms[idx])
+    }
+
+    pub fn active(&self) -> Option<&RoomRef> {
+        self.active.as_deref().and_then(|name| self.get(name))
+    }
+
+    /// Look up by name (case-insensitive) or topic hex.
+    pub fn get(&self, name: &str) -> Option<&RoomRef> {
+        self.position(name).map(|i| &self.rooms[i])
+    }
+
+    pub fn rooms(&self) -> &[RoomRef] {
+        &self.rooms
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.rooms
+            .iter()
+            .position(|r| r.name.eq_ignore_ascii_case(name) || r.topic_hex == name)
+    }
+}
+
+/// Several topic subscriptions merged into one labelled stream.
+pub struct TopicFeed {
+    tx: mpsc::Sender<(Option<String>, Result<Vec<u8>>)>,
+    rx: mpsc::Receiver<(Option<String>, Result<Vec<u8>>)>,
+    neighbors: Vec<NeighborCount>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Default for TopicFeed {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::channel(64);
+        Self {
+            tx,
+            rx,
+            neighbors: Vec::new(),
+            tasks: Vec::new(),
+        }
+    }
+}
+
+impl TopicFeed {
+    /// Start forwarding messages of `th`, tagged with `label`.
+    pub fn add(&mut self, label: Option<String>, mut th: Box<dyn TopicHandle>) {
+        if let Some(n) = th.neighbors() {
+            self.neighbors.push(n);
+        }
+        let tx = self.tx.clone();
+        self.tasks.push(tokio::spawn(async move {
+            loop {
+                let res = th.next().await;
+                let failed = res.is_err();
+                if tx.send((label.clone(), res)).await.is_err() || failed {
+                    break;
+                }
+            }
+        }));
+    }
+
+    /// Next message from any subscription; the first closed topic ends the feed.
+    pub async fn next(&mut self) -> Result<(Option<String>, Vec<u8>)> {
+        if self.tasks.is_empty() {
+            bail!("no subscriptions");
+        }
+        let (label, res) = self.rx.recv().await.expect("sender kept by the feed");
+        Ok((label, res?))
+    }
+
+    /// Neighbor counters of all subscriptions that expose one.
+    pub fn neighbors(&self) -> &[NeighborCount] {
+        &self.neighbors
+    }
+}
+
+impl Drop for TopicFeed {
+    fn drop(&mut self) {
+        for t in &self.tasks {
+            t.abort();
+        }
+    }
+}
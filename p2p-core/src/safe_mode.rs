@@ -0,0 +1,83 @@
+//! Restricted ("safe") profile for children's accounts.
+//!
+//! Safe mode is not part of `config.toml`: the user it restricts could simply
+//! edit that. It is read from a system-wide settings file that only an
+//! administrator can write:
+//! - Unix: `/etc/p2p-games/safe-mode.toml`
+//! - Windows: `%ProgramData%\p2p-games\safe-mode.toml`
+//!
+//! When the file enables it:
+//! - direct messages are only accepted from `friends`,
+//! - the profanity filter is always on,
+//! - the global chat is hidden,
+//! - rooms can only be joined if listed in `allowed_rooms`.
+//!
+//! A file that exists but cannot be read or parsed keeps safe mode **on** with empty
+//! lists, so breaking the file never lifts the restrictions.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+/// Contents of the protected settings file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafeMode {
+    pub enabled: bool,
+    /// Peer ids or nicknames allowed to send direct messages.
+    pub friends: Vec<String>,
+    /// Room names or topic ids (hex) that may be joined.
+    pub allowed_rooms: Vec<String>,
+    /// Words added to the profanity filter.
+    pub blocked_words: Vec<String>,
+}
+
+impl SafeMode {
+    /// Location of the protected settings file.
+    pub fn settings_path() -> PathBuf {
+        #[cfg(windows)]
+        let base = std::env::var_os("ProgramData")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"));
+        #[cfg(not(windows))]
+        let base = PathBuf::from("/etc");
+        base.join("p2p-games").join("safe-mode.toml")
+    }
+
+    /// Read the protected settings; no file means no restrictions.
+    pub fn load() -> Self {
+        let path = Self::settings_path();
+        let locked = Self {
+            enabled: true,
+            ..Self::default()
+        };
+        match fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s).unwrap_or(locked),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(_) => locked,
+        }
+    }
+
+    pub fn hides_global_chat(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn forces_profanity_filter(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn allows_dm_from(&self, peer_id: &str, nickname: Option<&str>) -> bool {
+        !self.enabled
+            || self
+                .friends
+                .iter()
+                .any(|f| f == peer_id || nickname.is_some_and(|n| f.eq_ignore_ascii_case(n)))
+    }
+
+    /// Whether the room with `topic_hex` (and `name`, if known) may be joined.
+    pub fn allows_room(&self, topic_hex: &str, name: Option<&str>) -> bool {
+        !self.enabled
+            || self.allowed_rooms.iter().any(|r| {
+                r.eq_ignore_ascii_case(topic_hex) || name.is_some_and(|n| r.eq_ignore_ascii_case(n))
+            })
+    }
+}
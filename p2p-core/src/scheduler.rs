@@ -0,0 +1,176 @@
+//! Periodic broadcasts under one rate budget.
+//!
+//! Heartbeats, room re-announces and nickname renewals each run on their own
+//! period. Left to plain intervals they fire in lockstep, and a node in many
+//! rooms publishes a burst on every topic at once, on every peer at the same
+//! moment. A [`Scheduler`] runs the periodic tasks of one loop instead:
+//! - every period is stretched or shortened by up to
+//!   [`SchedulerConfig::jitter_percent`], so tasks and peers drift apart,
+//! - every run first takes its publishes from the [`Budget`] the whole node
+//!   shares (`[scheduler] max_per_minute`); while it is spent, due tasks
+//!   wait instead of publishing,
+//! - a task that fell a whole period behind runs once, not once per period
+//!   it missed.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// `[scheduler]` section of `config.toml`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    /// Periodic publishes per minute for the whole node; 0 disables the limit.
+    pub max_per_minute: u32,
+    /// Publishes that may go out at once after a quiet spell.
+    pub burst: u32,
+    /// How far, in percent, a period may stray from its nominal length.
+    pub jitter_percent: u8,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_per_minute: 120,
+            burst: 20,
+            jitter_percent: 10,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+    per_sec: f64,
+    burst: f64,
+}
+
+/// Token bucket of periodic publishes; cheap to clone, clones share it.
+#[derive(Clone)]
+pub struct Budget {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl Budget {
+    pub fn new(config: &SchedulerConfig) -> Self {
+        let burst = f64::from(config.burst.max(1));
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: burst,
+                at: Instant::now(),
+                per_sec: f64::from(config.max_per_minute) / 60.0,
+                burst,
+            })),
+        }
+    }
+
+    /// Apply new limits; publishes already taken stay taken.
+    pub fn reconfigure(&self, config: &SchedulerConfig) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.per_sec = f64::from(config.max_per_minute) / 60.0;
+        bucket.burst = f64::from(config.burst.max(1));
+        bucket.tokens = bucket.tokens.min(bucket.burst);
+    }
+
+    /// Take `cost` publishes if they fit now, or tell how long until they do.
+    fn try_take(&self, cost: u32) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        if bucket.per_sec == 0.0 {
+            return Ok(());
+        }
+        // More than a burst would never fit; it waits for a full bucket.
+        let cost = f64::from(cost).min(bucket.burst);
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.at).as_secs_f64() * bucket.per_sec;
+        bucket.tokens = (bucket.tokens + refill).min(bucket.burst);
+        bucket.at = now;
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (cost - bucket.tokens) / bucket.per_sec,
+            ))
+        }
+    }
+
+    /// Wait until `cost` publishes fit and take them.
+    pub async fn take(&self, cost: u32) {
+        while let Err(wait) = self.try_take(cost) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+struct Task<T> {
+    id: T,
+    every: Duration,
+    /// Publishes per run.
+    cost: u32,
+    due: Instant,
+}
+
+/// The periodic tasks of one event loop, identified by `T`.
+pub struct Scheduler<T> {
+    budget: Budget,
+    jitter_percent: u8,
+    tasks: Vec<Task<T>>,
+}
+
+impl<T: Clone + PartialEq> Scheduler<T> {
+    pub fn new(budget: Budget, config: &SchedulerConfig) -> Self {
+        Self {
+            budget,
+            jitter_percent: config.jitter_percent.min(100),
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Run `id` every `every` with `cost` publishes per run, the first time
+    /// at `first`; an `id` already scheduled is replaced.
+    pub fn every_from(&mut self, id: T, every: Duration, cost: u32, first: Instant) {
+        self.tasks.retain(|t| t.id != id);
+        self.tasks.push(Task {
+            id,
+            every,
+            cost,
+            due: first,
+        });
+    }
+
+    /// Run `id` every `every`, starting now.
+    pub fn every(&mut self, id: T, every: Duration, cost: u32) {
+        self.every_from(id, every, cost, Instant::now());
+    }
+
+    /// `every` stretched or shortened by up to the configured jitter.
+    fn jittered(&self, every: Duration) -> Duration {
+        let spread = every.as_millis() as u64 * u64::from(self.jitter_percent) / 100;
+        if spread == 0 {
+            return every;
+        }
+        let offset = uuid::Uuid::new_v4().as_u64_pair().0 % (2 * spread + 1);
+        every + Duration::from_millis(offset) - Duration::from_millis(spread)
+    }
+
+    /// Wait for the next due task and its publishes in the budget, and
+    /// return it. Never returns without tasks; safe to use in `select!`.
+    pub async fn next(&mut self) -> T {
+        let Some(i) = (0..self.tasks.len()).min_by_key(|i| self.tasks[*i].due) else {
+            return std::future::pending().await;
+        };
+        tokio::time::sleep_until(self.tasks[i].due.into()).await;
+        self.budget.take(self.tasks[i].cost).await;
+        let now = Instant::now();
+        let every = self.jittered(self.tasks[i].every);
+        let task = &mut self.tasks[i];
+        task.due = if task.due + every > now {
+            task.due + every
+        } else {
+            now + every
+        };
+        task.id.clone()
+    }
+}
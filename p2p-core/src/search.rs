@@ -0,0 +1,100 @@
+//! Incremental scrollback search.
+//!
+//! State behind `/`-style search in a chat pane: every keystroke updates the
+//! query, matches are recomputed over the pane's lines (in-memory lines plus
+//! whatever was loaded from the [`HistoryStore`](crate::history::HistoryStore)),
+//! and `n`/`N`-style navigation moves the current hit, which the pane scrolls
+//! to and highlights.
+
+use std::ops::Range;
+
+use crate::history::find_ranges;
+
+#[derive(Debug, Clone, Default)]
+pub struct ScrollbackSearch {
+    query: String,
+    /// Indices of matching lines, oldest first.
+    hits: Vec<usize>,
+    /// Position in `hits` of the current result.
+    current: Option<usize>,
+}
+
+impl ScrollbackSearch {
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.query.is_empty()
+    }
+
+    /// Replace the query and recompute hits; the current result becomes the
+    /// newest match, as searching usually starts from the bottom.
+    pub fn set_query(&mut self, query: &str, lines: &[&str]) {
+        self.query = query.to_string();
+        self.rescan(lines);
+        self.current = self.hits.len().checked_sub(1);
+    }
+
+    pub fn push_char(&mut self, c: char, lines: &[&str]) {
+        let q = format!("{}{c}", self.query);
+        self.set_query(&q, lines);
+    }
+
+    pub fn pop_char(&mut self, lines: &[&str]) {
+        let mut q = self.query.clone();
+        q.pop();
+        self.set_query(&q, lines);
+    }
+
+    /// Recompute hits after lines were added, keeping the current line.
+    pub fn rescan(&mut self, lines: &[&str]) {
+        let line = self.current_line();
+        self.hits = if self.query.is_empty() {
+            Vec::new()
+        } else {
+            lines
+                .iter()
+                .enumerate()
+                .filter(|(_, text)| !find_ranges(text, &self.query).is_empty())
+                .map(|(i, _)| i)
+                .collect()
+        };
+        self.current = line
+            .and_then(|l| self.hits.iter().position(|h| *h == l))
+            .or(self.hits.len().checked_sub(1));
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Index of the line to jump to.
+    pub fn current_line(&self) -> Option<usize> {
+        self.current.map(|c| self.hits[c])
+    }
+
+    /// Move to the next older match (wraps around).
+    pub fn older(&mut self) -> Option<usize> {
+        let n = self.hits.len();
+        self.current = self.current.map(|c| (c + n - 1) % n);
+        self.current_line()
+    }
+
+    /// Move to the next newer match (wraps around).
+    pub fn newer(&mut self) -> Option<usize> {
+        let n = self.hits.len();
+        self.current = self.current.map(|c| (c + 1) % n);
+        self.current_line()
+    }
+
+    /// `(current, total)` for a "3/7" indicator; current is one-based.
+    pub fn position(&self) -> (usize, usize) {
+        (self.current.map_or(0, |c| c + 1), self.hits.len())
+    }
+
+    /// Byte ranges to highlight in `text`.
+    pub fn highlights(&self, text: &str) -> Vec<Range<usize>> {
+        find_ranges(text, &self.query)
+    }
+}
@@ -1,35 +1,198 @@
+//! Who we are on this machine: the session state and the node key.
+//!
+//! Everything a player keeps (identity, session, match log, history, …)
+//! lives in [`data_dir`]. Named profiles (`--profile work`) get a directory
+//! of their own under `profiles/`, so one machine can run several
+//! independent players; without `--profile` the top-level directory is used,
+//! as before profiles existed. `config.toml` is shared by all profiles.
+
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+use uuid::Uuid;
+
+use crate::{
+    protocol::{MemberRole, NameClaim},
+    rooms::{RoomManager, RoomRef},
+    sybil::Proof,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SessionState {
     pub peer_id: String,
     pub nickname: String,
-    pub current_room_topic_hex: Option<String>,
-    pub current_room_host_addr: Option<String>,
+    /// `since_ts` of our registry claim on `nickname`; 0 when unclaimed.
+    #[serde(default)]
+    pub nick_since: u64,
+    /// Joined rooms and the active one.
+    #[serde(default)]
+    pub rooms: RoomManager,
+    /// Cosmetic title shown next to our nickname, see [`crate::titles`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Languages we speak, best first (see [`crate::language`]).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+    /// Single-room layout of older versions, migrated into `rooms` on load.
+    #[serde(default, rename = "current_room_topic_hex", skip_serializing)]
+    legacy_room_topic_hex: Option<String>,
+    #[serde(default, rename = "current_room_host_addr", skip_serializing)]
+    legacy_room_host_addr: Option<String>,
+    /// Guest session (`--guest`): throwaway identity, never written to disk.
+    #[serde(skip)]
+    pub guest: bool,
+}
+
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+static DATA_ROOT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Keep everything under `root` instead of `<data dir>/p2p-games`, `None`
+/// to go back to it. The integration tests point it at a temp directory.
+pub fn set_data_root(root: Option<PathBuf>) {
+    *DATA_ROOT.lock().unwrap() = root;
+}
+
+/// Use the profile `name` for this run; call before anything touches
+/// [`data_dir`]. Names are letters, digits, `-` and `_`.
+pub fn set_profile(name: &str) -> io::Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "profile names are letters, digits, '-' and '_'",
+        ));
+    }
+    PROFILE
+        .set(name.to_string())
+        .map_err(|_| io::Error::other("profile already set"))
+}
+
+/// The profile of this run, `None` for the default one.
+pub fn profile() -> Option<&'static str> {
+    PROFILE.get().map(String::as_str)
+}
+
+/// Per-user data directory (`<data dir>/p2p-games`, or
+/// `<data dir>/p2p-games/profiles/<name>` for a named profile), created on
+/// demand.
+pub fn data_dir() -> PathBuf {
+    let root = DATA_ROOT.lock().unwrap().clone();
+    let mut path = root.unwrap_or_else(|| {
+        let base = dirs::data_local_dir().unwrap_or(std::env::temp_dir());
+        base.join("p2p-games")
+    });
+    if let Some(name) = profile() {
+        path.push("profiles");
+        path.push(name);
+    }
+    let _ = fs::create_dir_all(&path);
+    path
+}
+
+fn identity_path() -> PathBuf {
+    data_dir().join("identity.key")
+}
+
+/// Node key saved by [`save_identity`]; `None` before the first run. A key
+/// file that cannot be read is an error, not a reason to start over under a
+/// new peer id (and lose our nickname claims).
+pub fn load_identity() -> io::Result<Option<[u8; 32]>> {
+    let hex_key = match fs::read_to_string(identity_path()) {
+        Ok(k) => k,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|k| k.try_into().ok())
+        .map(Some)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a 32-byte hex key"))
+}
+
+/// Keep the node key so the peer id stays the same across runs. Only we can
+/// ever read it: the file is created with mode 0600 and then moved into
+/// place, so a crash never leaves a half-written key behind.
+pub fn save_identity(secret: &[u8; 32]) -> io::Result<()> {
+    let path = identity_path();
+    let tmp = path.with_extension("key.tmp");
+    // The mode only applies to new files.
+    let _ = fs::remove_file(&tmp);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp)?;
+    file.write_all(hex::encode(secret).as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp, &path)
 }
 
 impl SessionState {
+    /// A fresh guest session with a nickname like `guest-3f9a`.
+    pub fn guest() -> Self {
+        let suffix = Uuid::new_v4().simple().to_string();
+        Self {
+            nickname: format!("guest-{}", &suffix[..4]),
+            guest: true,
+            ..Self::default()
+        }
+    }
+
+    /// Our registry claim on the current nickname, if we hold one.
+    pub fn nick_claim(&self) -> Option<NameClaim> {
+        (self.nick_since != 0 && !self.nickname.is_empty()).then(|| NameClaim {
+            nick_lower: self.nickname.to_lowercase(),
+            nickname: self.nickname.clone(),
+            owner_peer_id: self.peer_id.clone(),
+            since_ts: self.nick_since,
+            // Set by `NickGuard::renew` whenever the claim goes out.
+            expires_at: None,
+            pow: Proof::of(&self.peer_id),
+        })
+    }
+
     fn storage_path() -> PathBuf {
-        let mut path = dirs::data_local_dir().unwrap_or(std::env::temp_dir());
-        path.push("p2p-games");
-        let _ = fs::create_dir_all(&path);
-        path.push("session.json");
-        path
+        data_dir().join("session.json")
     }
 
     pub fn load() -> std::io::Result<Self> {
         let path = Self::storage_path();
         if path.exists() {
             let b = fs::read(path)?;
-            Ok(serde_json::from_slice(&b)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?)
+            let mut state: Self = serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            if let Some(topic_hex) = state.legacy_room_topic_hex.take() {
+                state.rooms.join(RoomRef {
+                    name: topic_hex.clone(),
+                    topic_hex,
+                    host_addr: state.legacy_room_host_addr.take(),
+                    max_players: None,
+                    admission: None,
+                    role: MemberRole::Player,
+                });
+            }
+            Ok(state)
         } else {
             Ok(Self::default())
         }
     }
 
+    /// Persist the session; a no-op for guests.
     pub fn save(&self) -> std::io::Result<()> {
+        if self.guest {
+            return Ok(());
+        }
         let json = serde_json::to_vec_pretty(self).unwrap();
         fs::write(Self::storage_path(), json)
     }
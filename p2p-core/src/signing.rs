@@ -0,0 +1,128 @@
+//! Envelope signatures.
+//!
+//! Envelopes we publish under our own peer id carry `pubkey` (our node key,
+//! hex) and `sig`: an Ed25519 signature over the canonical envelope, which
+//! is its JSON with keys sorted and without `ver`, `sig` and `pubkey` (`ver`
+//! is left out so dual-emitted copies share one signature). The iroh peer id
+//! is the hex public key, so a receiver checks that `pubkey` matches
+//! `sender_id` and that the signature verifies; forged envelopes are
+//! dropped.
+//!
+//! Unsigned envelopes from older peers are accepted unless
+//! `[protocol] require_signatures = true`, and never under a `sender_id` we
+//! have already seen sign: dropping `sig` does not make a forgery pass.
+//! [`Wire`](crate::wire::Wire) checks every envelope it decodes, with or
+//! without the `sign` middleware stage.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::protocol::Envelope;
+
+static KEY: OnceLock<SigningKey> = OnceLock::new();
+static REQUIRE: AtomicBool = AtomicBool::new(false);
+
+/// Sender ids seen with a valid signature.
+static SIGNERS: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+
+fn signers() -> std::sync::MutexGuard<'static, HashSet<String>> {
+    SIGNERS.get_or_init(Mutex::default).lock().unwrap()
+}
+
+/// Sign outgoing envelopes with the node key `secret` from now on.
+pub fn init(secret: &[u8; 32], require_signatures: bool) {
+    let _ = KEY.set(SigningKey::from_bytes(secret));
+    REQUIRE.store(require_signatures, Ordering::Relaxed);
+}
+
+/// Hex public key of the node key, once [`init`] ran.
+pub fn public_key() -> Option<String> {
+    KEY.get().map(|k| hex::encode(k.verifying_key().as_bytes()))
+}
+
+fn canonical(v: &Value) -> Vec<u8> {
+    let mut v = v.clone();
+    if let Some(fields) = v.as_object_mut() {
+        for k in ["ver", "sig", "pubkey"] {
+            fields.remove(k);
+        }
+    }
+    // `serde_json::Map` keeps keys sorted, which makes this canonical.
+    serde_json::to_vec(&v).expect("serialize envelope")
+}
+
+/// Add `pubkey` and `sig` to a serialized envelope sent under our own id.
+///
+/// Envelopes with another `sender_id` (e.g. anonymous discovery requests)
+/// and envelopes built before [`init`] stay unsigned.
+pub fn sign(v: &mut Value) {
+    let (Some(key), Some(pubkey)) = (KEY.get(), public_key()) else {
+        return;
+    };
+    if v["sender_id"].as_str() != Some(pubkey.as_str()) {
+        return;
+    }
+    let sig = key.sign(&canonical(v));
+    v["pubkey"] = pubkey.into();
+    v["sig"] = hex::encode(sig.to_bytes()).into();
+}
+
+/// JSON of `env`, signed when possible.
+pub fn to_signed_vec<T: Serialize>(env: &Envelope<T>) -> Vec<u8> {
+    let mut v = serde_json::to_value(env).expect("serialize envelope");
+    sign(&mut v);
+    serde_json::to_vec(&v).expect("serialize envelope")
+}
+
+/// Whether the signature of a received envelope checks out.
+fn verify(v: &Value, pubkey: &str, sig: &str) -> bool {
+    if v["sender_id"].as_str() != Some(pubkey) {
+        return false;
+    }
+    let key = hex::decode(pubkey)
+        .ok()
+        .and_then(|b| <[u8; 32]>::try_from(b).ok())
+        .and_then(|b| VerifyingKey::from_bytes(&b).ok());
+    let sig = hex::decode(sig)
+        .ok()
+        .and_then(|b| <[u8; 64]>::try_from(b).ok())
+        .map(|b| Signature::from_bytes(&b));
+    match (key, sig) {
+        (Some(key), Some(sig)) => key.verify_strict(&canonical(v), &sig).is_ok(),
+        _ => false,
+    }
+}
+
+/// Whether a received envelope may be processed: validly signed, or
+/// unsigned while signatures are not required and its sender never signed.
+pub fn accept(v: &Value) -> bool {
+    let sender = v["sender_id"].as_str().unwrap_or_default();
+    match (v["pubkey"].as_str(), v["sig"].as_str()) {
+        (Some(pubkey), Some(sig)) => {
+            let valid = verify(v, pubkey, sig);
+            if valid && !signers().contains(sender) {
+                signers().insert(sender.to_string());
+            }
+            valid
+        }
+        (None, None) => !REQUIRE.load(Ordering::Relaxed) && !signers().contains(sender),
+        _ => false,
+    }
+}
+
+/// Parse a bare JSON envelope and check its signature.
+pub fn open<T: DeserializeOwned>(bytes: &[u8]) -> Option<Envelope<T>> {
+    let v: Value = serde_json::from_slice(bytes).ok()?;
+    if !accept(&v) {
+        return None;
+    }
+    serde_json::from_value(v).ok()
+}
@@ -0,0 +1,69 @@
+//! Local usage statistics.
+//!
+//! Counters live in `<data dir>/p2p-games/stats.json` and are **never**
+//! transmitted: nothing in this module touches the network. They exist purely
+//! for the `stats` command.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::PathBuf,
+};
+
+use crate::{protocol::now_ms, session::data_dir};
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct LocalStats {
+    /// When counting started (unix millis).
+    pub since: u64,
+    /// Finished games per game type.
+    pub games_played: BTreeMap<String, u64>,
+    /// Time spent with a chat open (millis).
+    pub chat_ms: u64,
+    /// Distinct peer ids seen sending messages.
+    pub peers_met: BTreeSet<String>,
+}
+
+impl LocalStats {
+    fn storage_path() -> PathBuf {
+        data_dir().join("stats.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self {
+                since: now_ms(),
+                ..Self::default()
+            })
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    pub fn record_game(&mut self, game: &str) {
+        *self.games_played.entry(game.to_string()).or_default() += 1;
+    }
+
+    pub fn add_chat_time(&mut self, ms: u64) {
+        self.chat_ms += ms;
+    }
+
+    /// Returns `true` if the peer was not seen before.
+    pub fn meet_peer(&mut self, peer_id: &str) -> bool {
+        self.peers_met.insert(peer_id.to_string())
+    }
+
+    pub fn total_games(&self) -> u64 {
+        self.games_played.values().sum()
+    }
+}
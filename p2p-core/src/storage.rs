@@ -0,0 +1,205 @@
+//! Disk quotas for what piles up in the data directory.
+//!
+//! Chat history, match replays and game-night summaries grow as long as the
+//! client is used. `[storage]` in `config.toml` caps each of them:
+//!
+//! ```toml
+//! [storage]
+//! history_mb = 50      # 0 = no limit
+//! replays_mb = 100
+//! summaries_mb = 10
+//! ```
+//!
+//! [`gc`] evicts the least recently used data of a category until it fits:
+//! the oldest chat lines, the replays of the oldest matches (their results
+//! stay, see [`MatchLog::drop_replays`]) and the summaries not opened for
+//! the longest time. Chat listeners run it together with history
+//! compaction; `storage gc` runs it right away and `storage status` shows
+//! what each category uses.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{game::GameRegistry, history::HistoryStore, matchlog::MatchLog, summary, validation};
+
+const MIB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    History,
+    Replays,
+    Summaries,
+}
+
+impl Category {
+    pub const ALL: [Category; 3] = [Category::History, Category::Replays, Category::Summaries];
+
+    /// Key of the category in `storage.<key>` messages.
+    pub fn key(self) -> &'static str {
+        match self {
+            Category::History => "history",
+            Category::Replays => "replays",
+            Category::Summaries => "summaries",
+        }
+    }
+
+    /// Bytes the category takes on disk.
+    pub fn used(self) -> io::Result<u64> {
+        match self {
+            Category::History => HistoryStore::open().bytes(),
+            Category::Replays => MatchLog::open().bytes(),
+            Category::Summaries => Ok(summaries()?.iter().map(|(_, len, _)| len).sum()),
+        }
+    }
+}
+
+/// `[storage]` section of `config.toml`: MiB per category, `0` = no limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub history_mb: u64,
+    pub replays_mb: u64,
+    pub summaries_mb: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            history_mb: 50,
+            replays_mb: 100,
+            summaries_mb: 10,
+        }
+    }
+}
+
+impl StorageConfig {
+    /// Quota of `category` in bytes; `None` means no limit.
+    pub fn quota(&self, category: Category) -> Option<u64> {
+        let mb = match category {
+            Category::History => self.history_mb,
+            Category::Replays => self.replays_mb,
+            Category::Summaries => self.summaries_mb,
+        };
+        (mb > 0).then_some(mb * MIB)
+    }
+}
+
+/// Space one category takes.
+#[derive(Debug, Clone, Serialize)]
+pub struct Usage {
+    pub category: Category,
+    pub bytes: u64,
+    pub quota: Option<u64>,
+}
+
+impl Usage {
+    pub fn over_quota(&self) -> bool {
+        self.quota.is_some_and(|q| self.bytes > q)
+    }
+}
+
+/// What [`gc`] removed from one category.
+#[derive(Debug, Clone, Serialize)]
+pub struct Evicted {
+    pub category: Category,
+    /// Chat lines, replays or summary files.
+    pub items: usize,
+    pub freed: u64,
+}
+
+pub fn usage(cfg: &StorageConfig) -> io::Result<Vec<Usage>> {
+    Category::ALL
+        .into_iter()
+        .map(|category| {
+            Ok(Usage {
+                category,
+                bytes: category.used()?,
+                quota: cfg.quota(category),
+            })
+        })
+        .collect()
+}
+
+/// Evict data until every category fits its quota. Categories where
+/// nothing had to go are left out.
+pub fn gc(cfg: &StorageConfig) -> io::Result<Vec<Evicted>> {
+    let mut evicted = Vec::new();
+    for category in Category::ALL {
+        let Some(quota) = cfg.quota(category) else {
+            continue;
+        };
+        let before = category.used()?;
+        if before <= quota {
+            continue;
+        }
+        let items = match category {
+            Category::History => HistoryStore::open().keep_within(quota)?,
+            Category::Replays => {
+                let registry = GameRegistry::builtin();
+                MatchLog::open()
+                    .drop_replays(quota, |r| validation::validate(r, &registry).is_ok())?
+            }
+            Category::Summaries => trim_summaries(quota)?,
+        };
+        evicted.push(Evicted {
+            category,
+            items,
+            freed: before.saturating_sub(category.used()?),
+        });
+    }
+    Ok(evicted)
+}
+
+/// Length of the file at `path`, `0` if there is none.
+pub(crate) fn file_len(path: &Path) -> io::Result<u64> {
+    match fs::metadata(path) {
+        Ok(meta) => Ok(meta.len()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// Size of `entry` as a line of a JSONL store.
+pub(crate) fn line_len<T: Serialize>(entry: &T) -> u64 {
+    serde_json::to_vec(entry).unwrap().len() as u64 + 1
+}
+
+/// Saved summaries with their size and last use, least recently used first.
+fn summaries() -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let dir = summary::dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_file() {
+            let used = meta.accessed().or_else(|_| meta.modified())?;
+            files.push((entry.path(), meta.len(), used));
+        }
+    }
+    files.sort_by_key(|(_, _, used)| *used);
+    Ok(files)
+}
+
+/// Delete the least recently used summaries until they fit `quota`.
+fn trim_summaries(quota: u64) -> io::Result<usize> {
+    let files = summaries()?;
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    let mut removed = 0;
+    for (path, len, _) in files {
+        if total <= quota {
+            break;
+        }
+        fs::remove_file(path)?;
+        total -= len;
+        removed += 1;
+    }
+    Ok(removed)
+}
@@ -0,0 +1,231 @@
+//! Game-night summary of a room session.
+//!
+//! When a room session ends, [`GameNight::build`] assembles what happened
+//! in the room since it started from the [`MatchLog`](crate::matchlog) and
+//! the chat [`HistoryStore`](crate::history): games played, winners, the
+//! biggest upset and chat statistics; results that do not count (disputed,
+//! or rejected by [`crate::validation`]) have no winner. The host posts [`GameNight::chat_lines`]
+//! to the room; everyone keeps [`GameNight::to_markdown`] under
+//! `<data dir>/p2p-games/summaries/`.
+
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{history::HistoryEntry, matchlog::MatchRecord, protocol::now_ms, session::data_dir, t};
+
+/// Where [`GameNight::save`] puts the reports.
+pub fn dir() -> PathBuf {
+    data_dir().join("summaries")
+}
+
+/// A win against someone with a better record before the match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upset {
+    pub game_id: String,
+    pub winner: String,
+    pub loser: String,
+    /// Previous wins of the loser minus those of the winner.
+    pub margin: u32,
+}
+
+/// A match of the night, with display names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayedMatch {
+    pub game: String,
+    pub moves: u64,
+    /// `None` for draws and aborted games.
+    pub winner: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GameNight {
+    /// Room name.
+    pub room: String,
+    /// Session start and end (unix millis).
+    pub since: u64,
+    pub until: u64,
+    /// Matches finished in the room during the session, oldest first.
+    pub matches: Vec<PlayedMatch>,
+    /// Display name → wins, for players with at least one win.
+    pub winners: BTreeMap<String, u32>,
+    pub upset: Option<Upset>,
+    /// Chat lines in the room during the session.
+    pub messages: usize,
+    /// Display name → lines sent.
+    pub chatters: BTreeMap<String, usize>,
+}
+
+impl GameNight {
+    /// Summarize room `topic_hex` (shown as `room`) since `since`.
+    ///
+    /// `all_matches` is the whole match log, so upsets can weigh records
+    /// from earlier nights; `counts` says whether a result counts towards
+    /// win records, and `name` turns a peer id into a display name.
+    pub fn build(
+        room: &str,
+        topic_hex: &str,
+        since: u64,
+        all_matches: &[MatchRecord],
+        counts: impl Fn(&MatchRecord) -> bool,
+        history: &[HistoryEntry],
+        name: impl Fn(&str) -> String,
+    ) -> Self {
+        let mut wins: BTreeMap<&str, u32> = BTreeMap::new();
+        let mut matches = Vec::new();
+        let mut winners = BTreeMap::new();
+        let mut upset: Option<Upset> = None;
+        for m in all_matches {
+            let tonight = m.room == topic_hex && m.ended_at >= since;
+            let winner = m.winner().filter(|_| counts(m));
+            if tonight {
+                matches.push(PlayedMatch {
+                    game: m.game.clone(),
+                    moves: m.moves,
+                    winner: winner.map(&name),
+                });
+            }
+            let Some(winner) = winner else {
+                continue;
+            };
+            if tonight {
+                *winners.entry(name(winner)).or_insert(0) += 1;
+                let before = wins.get(winner).copied().unwrap_or(0);
+                for loser in m.players.iter().filter(|p| *p != winner) {
+                    let margin = wins.get(loser.as_str()).copied().unwrap_or(0);
+                    let margin = margin.saturating_sub(before);
+                    if margin > 0 && upset.as_ref().is_none_or(|u| margin > u.margin) {
+                        upset = Some(Upset {
+                            game_id: m.game_id.clone(),
+                            winner: name(winner),
+                            loser: name(loser),
+                            margin,
+                        });
+                    }
+                }
+            }
+            *wins.entry(winner).or_insert(0) += 1;
+        }
+
+        let mut messages = 0;
+        let mut chatters = BTreeMap::new();
+        for e in history
+            .iter()
+            .filter(|e| e.room.as_deref() == Some(topic_hex) && e.ts >= since)
+        {
+            messages += 1;
+            let who = e.sender_nick.clone().unwrap_or_else(|| name(&e.sender_id));
+            *chatters.entry(who).or_insert(0) += 1;
+        }
+
+        Self {
+            room: room.to_string(),
+            since,
+            until: now_ms(),
+            matches,
+            winners,
+            upset,
+            messages,
+            chatters,
+        }
+    }
+
+    /// Nothing worth reporting happened.
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty() && self.messages == 0
+    }
+
+    fn champion(&self) -> Option<(&String, &u32)> {
+        self.winners.iter().max_by_key(|(_, n)| **n)
+    }
+
+    fn top_chatter(&self) -> Option<(&String, &usize)> {
+        self.chatters.iter().max_by_key(|(_, n)| **n)
+    }
+
+    /// Short version posted to the room.
+    pub fn chat_lines(&self) -> Vec<String> {
+        let mut lines = vec![t!(
+            "summary.chat_games",
+            room = self.room,
+            games = self.matches.len(),
+            messages = self.messages
+        )];
+        if let Some((who, n)) = self.champion() {
+            lines.push(t!("summary.chat_champion", name = who, wins = n));
+        }
+        if let Some(u) = &self.upset {
+            lines.push(t!("summary.chat_upset", winner = u.winner, loser = u.loser));
+        }
+        lines
+    }
+
+    /// Full report.
+    pub fn to_markdown(&self) -> String {
+        let minutes = self.until.saturating_sub(self.since) / 60_000;
+        let mut md = format!(
+            "# {}\n\n{}\n\n## {}\n\n",
+            t!("summary.title", room = self.room),
+            t!("summary.duration", minutes = minutes),
+            t!("summary.games")
+        );
+        if self.matches.is_empty() {
+            md.push_str(&format!("{}\n", t!("summary.no_games")));
+        }
+        for m in &self.matches {
+            let result = match &m.winner {
+                Some(w) => t!("summary.won_by", name = w),
+                None => t!("summary.no_winner"),
+            };
+            md.push_str(&format!("- {} ({} moves): {}\n", m.game, m.moves, result));
+        }
+        if !self.winners.is_empty() {
+            md.push_str(&format!("\n## {}\n\n", t!("summary.winners")));
+            let mut winners: Vec<_> = self.winners.iter().collect();
+            winners.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            for (who, n) in winners {
+                md.push_str(&format!("- {who}: {n}\n"));
+            }
+        }
+        if let Some(u) = &self.upset {
+            md.push_str(&format!(
+                "\n## {}\n\n{}\n",
+                t!("summary.upset"),
+                t!(
+                    "summary.upset_line",
+                    winner = u.winner,
+                    loser = u.loser,
+                    margin = u.margin
+                )
+            ));
+        }
+        md.push_str(&format!(
+            "\n## {}\n\n{}\n",
+            t!("summary.chat"),
+            t!(
+                "summary.chat_stats",
+                messages = self.messages,
+                people = self.chatters.len()
+            )
+        ));
+        if let Some((who, n)) = self.top_chatter() {
+            md.push_str(&format!(
+                "{}\n",
+                t!("summary.top_chatter", name = who, count = n)
+            ));
+        }
+        md
+    }
+
+    /// Write the markdown report; returns its path.
+    pub fn save(&self) -> std::io::Result<PathBuf> {
+        let dir = dir();
+        fs::create_dir_all(&dir)?;
+        let slug: String = self
+            .room
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect();
+        let path = dir.join(format!("{slug}-{}.md", self.until));
+        fs::write(&path, self.to_markdown())?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,260 @@
+//! Supervision of long-running subsystems.
+//!
+//! Each subsystem (registry keeper, discovery responder, room host, game
+//! session, …) runs in its own task. A panic or error in one of them is
+//! caught, recorded, and the subsystem is restarted according to its
+//! [`RestartPolicy`] instead of taking down the node.
+//!
+//! Health is mirrored to `<data dir>/p2p-games/health/<pid>.json`, one file
+//! per process, so `status`, run from another process, can show every
+//! running node.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    future::Future,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+
+use crate::{protocol::now_ms, session::data_dir};
+
+/// A snapshot older than this is from a node that is no longer running.
+pub const HEALTH_STALE_MS: u64 = 90_000;
+
+const HEARTBEAT: Duration = Duration::from_secs(30);
+
+/// How often a subsystem may be restarted.
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    /// Restarts allowed within `window`; one more marks it failed.
+    pub max_restarts: u32,
+    pub window: Duration,
+    /// Delay before the first restart; doubles on each further one.
+    pub backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            window: Duration::from_secs(60),
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum Health {
+    Running,
+    /// Crashed and is about to be started again.
+    Restarting {
+        reason: String,
+    },
+    /// Gave up after too many restarts.
+    Failed {
+        reason: String,
+    },
+    /// Returned normally.
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubsystemHealth {
+    pub health: Health,
+    /// Restarts since the subsystem was first started.
+    pub restarts: u32,
+    /// Last crash reason, kept after a successful restart.
+    pub last_error: Option<String>,
+}
+
+/// Persisted health of all subsystems of one node.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// The process the node runs in.
+    #[serde(default)]
+    pub pid: u32,
+    /// Unix millis of the last write.
+    pub updated_at: u64,
+    pub subsystems: BTreeMap<String, SubsystemHealth>,
+}
+
+impl HealthReport {
+    fn storage_dir() -> PathBuf {
+        data_dir().join("health")
+    }
+
+    fn storage_path(pid: u32) -> PathBuf {
+        Self::storage_dir().join(format!("{pid}.json"))
+    }
+
+    /// Reports of the nodes still running, by pid. Stale reports, left by
+    /// nodes that did not shut down cleanly, are removed.
+    pub fn load_live() -> Vec<Self> {
+        let Ok(entries) = fs::read_dir(Self::storage_dir()) else {
+            return Vec::new();
+        };
+        let mut live = Vec::new();
+        for path in entries.flatten().map(|e| e.path()) {
+            // A report being rewritten may not parse; it is skipped this time.
+            let Some(report) = fs::read(&path)
+                .ok()
+                .and_then(|b| serde_json::from_slice::<Self>(&b).ok())
+            else {
+                continue;
+            };
+            if report.is_live() {
+                live.push(report);
+            } else {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        live.sort_by_key(|r| r.pid);
+        live
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::create_dir_all(Self::storage_dir())?;
+        fs::write(Self::storage_path(self.pid), json)
+    }
+
+    /// Whether a node is still updating this report.
+    pub fn is_live(&self) -> bool {
+        now_ms().saturating_sub(self.updated_at) < HEALTH_STALE_MS
+    }
+}
+
+type Shared = Arc<Mutex<HealthReport>>;
+
+fn update(shared: &Shared, name: &str, f: impl FnOnce(&mut SubsystemHealth)) {
+    let mut report = shared.lock().unwrap();
+    let entry = report
+        .subsystems
+        .entry(name.to_string())
+        .or_insert(SubsystemHealth {
+            health: Health::Running,
+            restarts: 0,
+            last_error: None,
+        });
+    f(entry);
+    report.updated_at = now_ms();
+    let _ = report.save();
+}
+
+/// Runs subsystems and restarts them when they crash.
+pub struct Supervisor {
+    report: Shared,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        let report: Shared = Arc::new(Mutex::new(HealthReport {
+            pid: std::process::id(),
+            ..HealthReport::default()
+        }));
+        let beat = report.clone();
+        let heartbeat = tokio::spawn(async move {
+            let mut tick = tokio::time::interval(HEARTBEAT);
+            loop {
+                tick.tick().await;
+                let mut r = beat.lock().unwrap();
+                r.updated_at = now_ms();
+                let _ = r.save();
+            }
+        });
+        Self {
+            report,
+            tasks: vec![heartbeat],
+        }
+    }
+
+    /// Start `name`; `start` is called again for every restart.
+    pub fn spawn<F, Fut>(&mut self, name: &str, policy: RestartPolicy, start: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let name = name.to_string();
+        let report = self.report.clone();
+        self.tasks.push(tokio::spawn(async move {
+            let mut crashes: VecDeque<Instant> = VecDeque::new();
+            let mut backoff = policy.backoff;
+            loop {
+                update(&report, &name, |h| h.health = Health::Running);
+                let reason = match tokio::spawn(start()).await {
+                    Ok(Ok(())) => {
+                        update(&report, &name, |h| h.health = Health::Stopped);
+                        return;
+                    }
+                    Ok(Err(e)) => format!("{e:#}"),
+                    Err(e) if e.is_panic() => panic_message(e.into_panic()),
+                    Err(_) => return,
+                };
+
+                let now = Instant::now();
+                crashes.push_back(now);
+                while crashes
+                    .front()
+                    .is_some_and(|t| now.duration_since(*t) > policy.window)
+                {
+                    crashes.pop_front();
+                }
+                if crashes.len() as u32 > policy.max_restarts {
+                    update(&report, &name, |h| {
+                        h.health = Health::Failed {
+                            reason: reason.clone(),
+                        };
+                        h.last_error = Some(reason.clone());
+                    });
+                    return;
+                }
+                update(&report, &name, |h| {
+                    h.health = Health::Restarting {
+                        reason: reason.clone(),
+                    };
+                    h.restarts += 1;
+                    h.last_error = Some(reason.clone());
+                });
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(policy.window);
+            }
+        }));
+    }
+
+    /// Current health of all subsystems.
+    pub fn report(&self) -> HealthReport {
+        self.report.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        for t in &self.tasks {
+            t.abort();
+        }
+        let pid = self.report.lock().unwrap().pid;
+        let _ = fs::remove_file(HealthReport::storage_path(pid));
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    let msg = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".into());
+    format!("panicked: {msg}")
+}
@@ -0,0 +1,188 @@
+//! What a peer must show before it counts.
+//!
+//! Keys cost nothing, so one attacker can bring a fresh one for every name
+//! claim or seek. `[sybil]` in `config.toml` sets the bar a peer has to pass
+//! to be *established* (everything is off by default):
+//!
+//! ```toml
+//! [sybil]
+//! min_identity_age_hours = 24   # first seen in our peer directory that long ago
+//! pow_bits = 16                 # proof of work on the peer id
+//! vouches = 1                   # vouchers that pass the two checks above
+//! ```
+//!
+//! Name claims of peers that are not established are ignored in name races
+//! (`login`, and the nickname we defend), and they are left out of our
+//! matchmaking queue. Our own key always counts.
+//!
+//! The proof of work is a nonce such that SHA-256 of
+//! `p2p-games/pow/<peer id>/<nonce>` starts with `pow_bits` zero bits. We
+//! find ours once per key and difficulty on a blocking thread, keep it in
+//! `<data dir>/p2p-games/pow.json` and send it with our name claims and
+//! seeks; the [`PeerDirectory`] remembers those of other peers.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::{directory::PeerDirectory, protocol::now_ms, session::data_dir, vouch::VouchBook};
+
+/// The hardest proof of work we look for.
+pub const MAX_POW_BITS: u32 = 32;
+
+/// Nonces tried between two progress reports of [`Proof::ensure`].
+pub const PROGRESS_EVERY: u64 = 1 << 20;
+
+const HOUR_MS: u64 = 3_600_000;
+
+/// `[sybil]` section of `config.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SybilConfig {
+    /// Hours since we first saw a peer before it counts.
+    pub min_identity_age_hours: u64,
+    /// Leading zero bits of the proof of work on its peer id.
+    pub pow_bits: u32,
+    /// Vouches it needs from peers passing the age and work checks.
+    pub vouches: usize,
+}
+
+impl SybilConfig {
+    pub fn is_off(&self) -> bool {
+        self.min_identity_age_hours == 0 && self.pow_bits == 0 && self.vouches == 0
+    }
+
+    /// Whether `peer_id` passes the bar, by what `directory` and `vouches`
+    /// know of it.
+    pub fn established(
+        &self,
+        peer_id: &str,
+        directory: &PeerDirectory,
+        vouches: &VouchBook,
+    ) -> bool {
+        if self.is_off() {
+            return true;
+        }
+        let vouched = vouches
+            .vouchers(peer_id)
+            .into_iter()
+            .filter(|v| self.aged_and_worked(v, directory))
+            .count();
+        self.aged_and_worked(peer_id, directory) && vouched >= self.vouches
+    }
+
+    fn aged_and_worked(&self, peer_id: &str, directory: &PeerDirectory) -> bool {
+        let Some(entry) = directory.peers.get(peer_id) else {
+            return self.min_identity_age_hours == 0 && self.pow_bits == 0;
+        };
+        let age = now_ms().saturating_sub(entry.first_seen);
+        age >= self.min_identity_age_hours * HOUR_MS
+            && (self.pow_bits == 0 || entry.pow.is_some_and(|n| work(peer_id, n) >= self.pow_bits))
+    }
+}
+
+/// Leading zero bits of the proof of work `nonce` for `peer_id`.
+pub fn work(peer_id: &str, nonce: u64) -> u32 {
+    let hash = Sha256::digest(format!("p2p-games/pow/{peer_id}/{nonce}").as_bytes());
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Our proof of work, kept in `pow.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Proof {
+    pub peer_id: String,
+    pub nonce: u64,
+}
+
+impl Proof {
+    fn storage_path() -> PathBuf {
+        data_dir().join("pow.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// The nonce we found for `peer_id`, if any, to send along.
+    pub fn of(peer_id: &str) -> Option<u64> {
+        Self::load()
+            .ok()
+            .filter(|p| !peer_id.is_empty() && p.peer_id == peer_id)
+            .map(|p| p.nonce)
+    }
+
+    /// A nonce for `peer_id` with at least `bits` of work (at most
+    /// [`MAX_POW_BITS`]), found now unless the stored one is good enough.
+    ///
+    /// The search runs on a blocking thread and calls `progress` with the
+    /// number of nonces tried every [`PROGRESS_EVERY`]; about `2^bits` are
+    /// needed. Dropping the future stops it.
+    pub async fn ensure(
+        peer_id: &str,
+        bits: u32,
+        progress: impl Fn(u64) + Send + 'static,
+    ) -> Option<u64> {
+        let bits = bits.min(MAX_POW_BITS);
+        if let Some(nonce) = Self::of(peer_id).filter(|n| work(peer_id, *n) >= bits) {
+            return Some(nonce);
+        }
+        if bits == 0 {
+            return None;
+        }
+        let stop = StopOnDrop(Arc::default());
+        let (id, flag) = (peer_id.to_string(), stop.0.clone());
+        let nonce = tokio::task::spawn_blocking(move || {
+            (0..).find(|&n| {
+                if n > 0 && n % PROGRESS_EVERY == 0 {
+                    progress(n);
+                }
+                flag.load(Ordering::Relaxed) || work(&id, n) >= bits
+            })
+        })
+        .await
+        .ok()
+        .flatten()
+        .filter(|n| work(peer_id, *n) >= bits)?;
+        let proof = Self {
+            peer_id: peer_id.to_string(),
+            nonce,
+        };
+        let _ = proof.save();
+        Some(nonce)
+    }
+}
+
+/// Tells the search of [`Proof::ensure`] to give up once its caller is gone.
+struct StopOnDrop(Arc<AtomicBool>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,148 @@
+//! Tic-tac-toe, the reference [`Game`].
+//!
+//! Seat 0 plays `X` and moves first, seat 1 plays `O`. Cells are entered as
+//! on the drawn board (`a1` … `c3`, column letter first) or as `1`–`9` in
+//! reading order. A player who leaves forfeits.
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::{DropPolicy, Game, Outcome, Seat},
+    output::cell_name,
+    t,
+    widgets::{GridModel, RenderModel},
+};
+
+const SIZE: usize = 3;
+
+const LINES: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+/// Mark drawn for each seat.
+const MARKS: [char; 2] = ['X', 'O'];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TicTacToe {
+    /// Row-major cells holding the seat that marked them.
+    cells: [Option<Seat>; SIZE * SIZE],
+    turn: Seat,
+    last: Option<usize>,
+    /// Seat that left the game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    forfeit: Option<Seat>,
+}
+
+/// Mark a cell (row-major index `0..9`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Place {
+    pub cell: usize,
+}
+
+impl TicTacToe {
+    fn winner(&self) -> Option<Seat> {
+        LINES.iter().find_map(|[a, b, c]| {
+            let s = self.cells[*a]?;
+            (self.cells[*b] == Some(s) && self.cells[*c] == Some(s)).then_some(s)
+        })
+    }
+}
+
+impl Game for TicTacToe {
+    type Move = Place;
+
+    const NAME: &'static str = "tictactoe";
+
+    const ON_DROP: DropPolicy = DropPolicy::EndGame;
+
+    fn new(players: usize) -> Result<Self> {
+        if players != 2 {
+            bail!(t!("game.player_count", game = Self::NAME, count = 2));
+        }
+        Ok(Self::default())
+    }
+
+    fn to_move(&self) -> Option<Seat> {
+        self.outcome().is_none().then_some(self.turn)
+    }
+
+    fn legal_moves(&self, seat: Seat) -> Vec<Place> {
+        if Game::to_move(self) != Some(seat) {
+            return Vec::new();
+        }
+        (0..SIZE * SIZE)
+            .filter(|c| self.cells[*c].is_none())
+            .map(|cell| Place { cell })
+            .collect()
+    }
+
+    fn apply(&mut self, seat: Seat, mv: &Place) -> Result<()> {
+        match Game::to_move(self) {
+            None => bail!(t!("game.over")),
+            Some(s) if s != seat => bail!(t!("game.not_your_turn")),
+            Some(_) => {}
+        }
+        match self.cells.get(mv.cell) {
+            None => bail!(t!("game.bad_move", input = mv.cell)),
+            Some(Some(_)) => bail!(t!("tictactoe.cell_taken")),
+            Some(None) => {}
+        }
+        self.cells[mv.cell] = Some(seat);
+        self.last = Some(mv.cell);
+        self.turn = 1 - seat;
+        Ok(())
+    }
+
+    fn parse_move(&self, input: &str) -> Result<Place> {
+        let input = input.trim().to_ascii_lowercase();
+        let cell = match input.as_bytes() {
+            [d @ b'1'..=b'9'] => (d - b'1') as usize,
+            [col @ b'a'..=b'c', row @ b'1'..=b'3'] => {
+                (row - b'1') as usize * SIZE + (col - b'a') as usize
+            }
+            _ => bail!(t!("game.bad_move", input = input)),
+        };
+        Ok(Place { cell })
+    }
+
+    fn describe_move(&self, mv: &Place) -> Option<String> {
+        Some(cell_name(mv.cell / SIZE, mv.cell % SIZE))
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        if let Some(seat) = self.forfeit {
+            Some(Outcome::Winner { seat: 1 - seat })
+        } else if let Some(seat) = self.winner() {
+            Some(Outcome::Winner { seat })
+        } else if self.cells.iter().all(Option::is_some) {
+            Some(Outcome::Draw)
+        } else {
+            None
+        }
+    }
+
+    fn render(&self, _viewer: Option<Seat>) -> RenderModel {
+        let cells = self
+            .cells
+            .chunks(SIZE)
+            .map(|row| row.iter().map(|c| c.map(|s| MARKS[s])).collect())
+            .collect();
+        RenderModel::Grid(GridModel {
+            cells,
+            highlight: self.last.map(|c| (c / SIZE, c % SIZE)),
+        })
+    }
+
+    fn eliminate(&mut self, seat: Seat) -> Result<()> {
+        self.forfeit = Some(seat);
+        Ok(())
+    }
+}
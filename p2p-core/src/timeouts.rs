@@ -0,0 +1,254 @@
+//! Network wait times and retries.
+//!
+//! All waits that used to be hard-coded per command come from the
+//! `[timeouts]` section of `config.toml`:
+//!
+//! ```toml
+//! [timeouts]
+//! claim_wait_ms = 1200
+//! list_rooms_ms = 2000
+//! [timeouts.retry]
+//! attempts = 5
+//! ```
+//!
+//! [`retry`] re-runs fallible network steps (joining a topic, publishing,
+//! connecting to a host) with exponential backoff.
+//!
+//! Claim and room list collection runs in a [`WaitWindow`]: it starts
+//! between `min_wait_ms` and the configured wait depending on how many
+//! neighbors could answer, and is extended while answers keep arriving, up
+//! to [`STRETCH`] times the configured wait.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+use transport_iroh::transport_iroh::TopicHandle;
+
+/// Neighbors at which a window starts at the full configured wait.
+const FULL_WAIT_NEIGHBORS: usize = 4;
+/// Longest a window stretches, as a multiple of the configured wait.
+pub const STRETCH: u32 = 3;
+
+/// `[timeouts]` section of `config.toml`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeouts {
+    /// Time to collect competing nickname and room name claims.
+    pub claim_wait_ms: u64,
+    /// Time to wait for the host to acknowledge a join request.
+    pub join_ack_ms: u64,
+    /// Time to collect room list responses.
+    pub list_rooms_ms: u64,
+    /// Time to wait for a game move to be acknowledged.
+    pub move_ack_ms: u64,
+    /// Silence after which a room member counts as gone.
+    pub member_stale_ms: u64,
+    /// How long peers keep defending the nickname of an owner that went
+    /// quiet or offline.
+    pub nick_grace_ms: u64,
+    /// Lifetime of a nickname claim; the owner renews it well before (see
+    /// the power profile's `claim_renewal`), after that the name is free.
+    pub nick_lease_ms: u64,
+    /// Time to wait for one `[[bootstrap]]` peer to answer.
+    pub bootstrap_ms: u64,
+    /// Fit claim and room list waits to the network (see [`WaitWindow`]);
+    /// `false` waits exactly `claim_wait_ms` and `list_rooms_ms`.
+    pub adaptive: bool,
+    /// Shortest adaptive wait, used when no neighbor could answer.
+    pub min_wait_ms: u64,
+    pub retry: RetryPolicy,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            claim_wait_ms: 1200,
+            join_ack_ms: 3000,
+            list_rooms_ms: 1200,
+            move_ack_ms: 2000,
+            member_stale_ms: 45_000,
+            nick_grace_ms: 10 * 60_000,
+            nick_lease_ms: 15 * 60_000,
+            bootstrap_ms: 1500,
+            adaptive: true,
+            min_wait_ms: 250,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn claim_wait(&self) -> Duration {
+        Duration::from_millis(self.claim_wait_ms)
+    }
+
+    pub fn join_ack(&self) -> Duration {
+        Duration::from_millis(self.join_ack_ms)
+    }
+
+    pub fn list_rooms(&self) -> Duration {
+        Duration::from_millis(self.list_rooms_ms)
+    }
+
+    pub fn move_ack(&self) -> Duration {
+        Duration::from_millis(self.move_ack_ms)
+    }
+
+    pub fn member_stale(&self) -> Duration {
+        Duration::from_millis(self.member_stale_ms)
+    }
+
+    pub fn nick_grace(&self) -> Duration {
+        Duration::from_millis(self.nick_grace_ms)
+    }
+
+    pub fn nick_lease(&self) -> Duration {
+        Duration::from_millis(self.nick_lease_ms)
+    }
+
+    pub fn bootstrap(&self) -> Duration {
+        Duration::from_millis(self.bootstrap_ms)
+    }
+
+    /// Window for collecting answers on `th`, around the configured `wait`.
+    pub fn window(&self, wait: Duration, th: &dyn TopicHandle) -> WaitWindow {
+        if !self.adaptive {
+            return WaitWindow::fixed(wait);
+        }
+        let floor = Duration::from_millis(self.min_wait_ms).min(wait);
+        let neighbors = th.neighbors().map(|n| n.get());
+        WaitWindow::new(floor, wait, neighbors)
+    }
+}
+
+/// Deadline of a collection window that follows the traffic it sees.
+#[derive(Debug, Clone)]
+pub struct WaitWindow {
+    deadline: Instant,
+    /// Hard end of the window.
+    limit: Instant,
+    floor: Duration,
+    wait: Duration,
+    last: Option<Instant>,
+    /// Smoothed time between answers.
+    gap: Option<Duration>,
+}
+
+impl WaitWindow {
+    /// Starts at `floor` with no neighbors, at `wait` with
+    /// [`FULL_WAIT_NEIGHBORS`] or more (or an unknown count), in between
+    /// otherwise.
+    pub fn new(floor: Duration, wait: Duration, neighbors: Option<usize>) -> Self {
+        let now = Instant::now();
+        let start = match neighbors {
+            None => wait,
+            Some(n) => {
+                let share = n.min(FULL_WAIT_NEIGHBORS) as u32;
+                floor + (wait - floor) * share / FULL_WAIT_NEIGHBORS as u32
+            }
+        };
+        Self {
+            deadline: now + start,
+            limit: now + wait * STRETCH,
+            floor,
+            wait,
+            last: None,
+            gap: None,
+        }
+    }
+
+    /// Exactly `wait`, whatever arrives.
+    pub fn fixed(wait: Duration) -> Self {
+        let deadline = Instant::now() + wait;
+        Self {
+            deadline,
+            limit: deadline,
+            floor: wait,
+            wait,
+            last: None,
+            gap: None,
+        }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// An answer arrived at `now`: keep the window open for about two of
+    /// the usual gaps between answers, in case more are on their way.
+    pub fn arrived(&mut self, now: Instant) {
+        if let Some(last) = self.last {
+            let gap = now.saturating_duration_since(last);
+            self.gap = Some(match self.gap {
+                Some(avg) => (avg * 3 + gap) / 4,
+                None => gap,
+            });
+        }
+        self.last = Some(now);
+        let settle = self
+            .gap
+            .map_or(self.floor, |g| (g * 2).clamp(self.floor, self.wait));
+        self.deadline = self.deadline.max(now + settle).min(self.limit);
+    }
+}
+
+/// Pass frames from `th` to `on` until `window` closes; `on` returns
+/// whether the frame was an answer, which keeps the window open longer.
+pub async fn collect(
+    th: &mut Box<dyn TopicHandle>,
+    mut window: WaitWindow,
+    mut on: impl FnMut(&[u8]) -> bool,
+) {
+    while let Ok(Ok(b)) = tokio::time::timeout_at(window.deadline().into(), th.next()).await {
+        if on(&b) {
+            window.arrived(Instant::now());
+        }
+    }
+}
+
+/// `[timeouts.retry]`: how often a failed network step is tried again.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total tries, including the first one.
+    pub attempts: u32,
+    /// Delay before the second try; doubles after every failure.
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            initial_backoff_ms: 250,
+            max_backoff_ms: 2000,
+        }
+    }
+}
+
+/// Run `op` until it succeeds or `policy.attempts` are used up; the last
+/// error is returned.
+pub async fn retry<T, F, Fut>(policy: &RetryPolicy, mut op: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = Duration::from_millis(policy.initial_backoff_ms);
+    let max = Duration::from_millis(policy.max_backoff_ms);
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt >= policy.attempts => return Err(e),
+            Err(_) => {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(max);
+                attempt += 1;
+            }
+        }
+    }
+}
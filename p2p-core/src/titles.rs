@@ -0,0 +1,71 @@
+//! Cosmetic titles unlocked by achievements.
+//!
+//! Achievements are milestones in the [`LocalStats`]; each one unlocks a
+//! title the player can pick with `title <id>` to be shown next to their
+//! nickname in member lists. The picked title travels in room heartbeats
+//! and changes nothing about play. It is claimed by its sender, not proven:
+//! peers only check that the title exists, so it is a badge, not a
+//! credential.
+
+use crate::{stats::LocalStats, t};
+
+/// Chat time after which [`ACHIEVEMENTS`] grants the chatterbox title.
+const CHATTERBOX_MS: u64 = 10 * 3_600_000;
+
+/// A milestone and the title it unlocks; `title.<id>` and
+/// `title.<id>.goal` are its i18n keys.
+pub struct Achievement {
+    pub id: &'static str,
+    unlocked: fn(&LocalStats) -> bool,
+}
+
+impl Achievement {
+    pub fn is_unlocked(&self, stats: &LocalStats) -> bool {
+        (self.unlocked)(stats)
+    }
+
+    /// Display name of the title.
+    pub fn name(&self) -> String {
+        t!(&format!("title.{}", self.id))
+    }
+
+    /// What it takes to unlock the title.
+    pub fn goal(&self) -> String {
+        t!(&format!("title.{}.goal", self.id))
+    }
+}
+
+pub const ACHIEVEMENTS: &[Achievement] = &[
+    Achievement {
+        id: "rookie",
+        unlocked: |s| s.total_games() >= 1,
+    },
+    Achievement {
+        id: "regular",
+        unlocked: |s| s.total_games() >= 25,
+    },
+    Achievement {
+        id: "veteran",
+        unlocked: |s| s.total_games() >= 100,
+    },
+    Achievement {
+        id: "socialite",
+        unlocked: |s| s.peers_met.len() >= 50,
+    },
+    Achievement {
+        id: "chatterbox",
+        unlocked: |s| s.chat_ms >= CHATTERBOX_MS,
+    },
+];
+
+pub fn find(id: &str) -> Option<&'static Achievement> {
+    ACHIEVEMENTS.iter().find(|a| a.id == id)
+}
+
+/// `nickname` with the title `id` next to it; unknown titles are left out.
+pub fn decorate(nickname: &str, id: Option<&str>) -> String {
+    match id.and_then(find) {
+        Some(a) => t!("titles.decorated", nickname = nickname, title = a.name()),
+        None => nickname.to_string(),
+    }
+}
@@ -0,0 +1,381 @@
+//! Single-elimination tournaments.
+//!
+//! The organizer runs `tournament create <game>` and hands out the
+//! [`TournamentTicket`] it prints. Players join with it: they send a signed
+//! [`TournamentBody::Join`] on the tournament topic until the organizer's
+//! [`Bracket`] lists them. When the organizer starts the tournament, the
+//! entrants are seeded by the rating they joined with, best first, so the
+//! best seeds meet last and get the byes of an incomplete field.
+//!
+//! Every pairing of a round plays in a room of its own on a topic derived
+//! from the tournament, the round and the pairing ([`room_topic_name`]),
+//! hosted by the better seed. The result feeds back as the attestation the
+//! players signed for their ratings (see [`crate::rating`]): a player sends
+//! its opponent's in a [`TournamentBody::Result`] together with the moves
+//! of the match. The organizer only takes the loser's word for a win: the
+//! attestation must belong to the pairing, be signed by the loser after the
+//! round started, and the moves must lead to the result
+//! ([`crate::validation::validate`]). Then it marks the winner. A drawn
+//! match is played again. Once every pairing of a round has
+//! a winner, the winners are paired for the next round, until one is left.
+//!
+//! The organizer publishes the whole bracket, signed, whenever it changes
+//! and every [`BRACKET_EVERY`]; peers only take brackets from the organizer
+//! named in the ticket.
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, fs, path::PathBuf, str::FromStr, time::Duration};
+
+use crate::{
+    dispute::Evidence,
+    game::{GameRegistry, Outcome},
+    matchlog::MatchRecord,
+    protocol::{Envelope, Kind, Scope, make_envelope, now_ms},
+    rating::Attestation,
+    session::data_dir,
+    validation,
+};
+
+const TICKET_PREFIX: &str = "p2pt:";
+
+/// Most entrants; a bracket of this many still fits a gossip message.
+pub const MAX_PLAYERS: usize = 16;
+
+/// How often the organizer repeats the bracket, and players what they
+/// have to say.
+pub const BRACKET_EVERY: Duration = Duration::from_secs(5);
+
+/// Tournament id and organizer, as one copyable string
+/// (`p2pt:<id>@<organizer peer id>`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TournamentTicket {
+    pub id: String,
+    pub organizer: String,
+}
+
+impl TournamentTicket {
+    /// Name of the topic the tournament is run on.
+    pub fn topic_name(&self) -> String {
+        format!("tournament-{}", self.id)
+    }
+}
+
+impl fmt::Display for TournamentTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{TICKET_PREFIX}{}@{}", self.id, self.organizer)
+    }
+}
+
+impl FromStr for TournamentTicket {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (id, organizer) = s
+            .trim()
+            .strip_prefix(TICKET_PREFIX)
+            .and_then(|rest| rest.split_once('@'))
+            .ok_or_else(|| anyhow!("not a tournament ticket: {s}"))?;
+        if id.is_empty() || organizer.is_empty() {
+            bail!("incomplete tournament ticket: {s}");
+        }
+        Ok(Self {
+            id: id.to_string(),
+            organizer: organizer.to_string(),
+        })
+    }
+}
+
+/// Name of the topic the pairing `index` of `round` plays on.
+pub fn room_topic_name(id: &str, round: usize, index: usize) -> String {
+    format!("tournament-{id}-{round}-{index}")
+}
+
+/// Messages on a tournament topic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TournamentBody {
+    /// The sender wants to play; `rating` is its rating in the game and
+    /// only decides its seed.
+    Join { rating: u32 },
+    /// The organizer's bracket.
+    Bracket { bracket: Bracket },
+    /// The opponent's signed [`crate::game::GameBody::Attest`] of the
+    /// sender's latest match in the tournament, with the moves as the sender
+    /// saw them.
+    Result {
+        attestation: Value,
+        #[serde(default)]
+        evidence: Evidence,
+    },
+}
+
+pub fn make_tournament(sender_id: String, body: TournamentBody) -> Envelope<TournamentBody> {
+    make_envelope(
+        Kind::Tournament,
+        Scope::Global,
+        None,
+        sender_id,
+        now_ms(),
+        body,
+    )
+}
+
+/// Two players of a round, or one with a bye.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pairing {
+    /// The better seed; hosts the match.
+    pub home: String,
+    /// `None` for a bye.
+    pub away: Option<String>,
+    pub winner: Option<String>,
+}
+
+impl Pairing {
+    fn has(&self, peer_id: &str) -> bool {
+        self.home == peer_id || self.away.as_deref() == Some(peer_id)
+    }
+
+    /// The other player of `peer_id`'s pairing.
+    pub fn opponent(&self, peer_id: &str) -> Option<&str> {
+        if self.home == peer_id {
+            self.away.as_deref()
+        } else {
+            self.has(peer_id).then_some(self.home.as_str())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Round {
+    /// When the round started (unix millis); results signed earlier do not
+    /// count.
+    pub started: u64,
+    pub pairings: Vec<Pairing>,
+}
+
+/// Slots of seeds `0..size` (a power of two) in the first round, so that
+/// seeds meet as late as they can: `0 7 3 4 1 6 2 5` for eight.
+fn slots(size: usize) -> Vec<usize> {
+    let mut order = vec![0];
+    while order.len() < size {
+        let n = order.len() * 2;
+        order = order.iter().flat_map(|&s| [s, n - 1 - s]).collect();
+    }
+    order
+}
+
+/// A tournament as its organizer publishes it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bracket {
+    pub id: String,
+    pub game: String,
+    pub organizer: String,
+    /// Entrants; best seed first once started.
+    pub players: Vec<String>,
+    /// Rounds played so far; empty while players are still joining.
+    pub rounds: Vec<Round>,
+}
+
+impl Bracket {
+    pub fn new(ticket: &TournamentTicket, game: &str) -> Self {
+        Self {
+            id: ticket.id.clone(),
+            game: game.to_string(),
+            organizer: ticket.organizer.clone(),
+            players: Vec::new(),
+            rounds: Vec::new(),
+        }
+    }
+
+    pub fn started(&self) -> bool {
+        !self.rounds.is_empty()
+    }
+
+    /// Seed the entrants (peer id → rating) and pair them for the first
+    /// round.
+    pub fn start(&mut self, entrants: &BTreeMap<String, u32>, now: u64) {
+        let mut seeded: Vec<(&String, &u32)> = entrants.iter().collect();
+        seeded.sort_by(|(a, ra), (b, rb)| rb.cmp(ra).then(a.cmp(b)));
+        self.players = seeded.into_iter().map(|(p, _)| p.clone()).collect();
+        let order = slots(self.players.len().next_power_of_two());
+        let pairings = order
+            .chunks(2)
+            .map(|pair| {
+                let (home, away) = (pair[0].min(pair[1]), pair[0].max(pair[1]));
+                let home = self.players[home].clone();
+                let away = self.players.get(away).cloned();
+                Pairing {
+                    winner: away.is_none().then(|| home.clone()),
+                    home,
+                    away,
+                }
+            })
+            .collect();
+        self.rounds = vec![Round {
+            started: now,
+            pairings,
+        }];
+        self.advance(now);
+    }
+
+    /// The round being played, with its number.
+    pub fn current(&self) -> Option<(usize, &Round)> {
+        self.rounds.iter().enumerate().next_back()
+    }
+
+    pub fn champion(&self) -> Option<&str> {
+        match &self.current()?.1.pairings[..] {
+            [final_] => final_.winner.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// `peer_id`'s pairing in the current round, with the round and its
+    /// index.
+    pub fn pairing_of(&self, peer_id: &str) -> Option<(usize, usize, &Pairing)> {
+        let (round, r) = self.current()?;
+        let (index, pairing) = r
+            .pairings
+            .iter()
+            .enumerate()
+            .find(|(_, p)| p.has(peer_id))?;
+        Some((round, index, pairing))
+    }
+
+    /// Whether `peer_id` lost a match.
+    pub fn eliminated(&self, peer_id: &str) -> bool {
+        self.rounds
+            .iter()
+            .flat_map(|r| &r.pairings)
+            .any(|p| p.has(peer_id) && p.winner.as_deref().is_some_and(|w| w != peer_id))
+    }
+
+    /// Take in the loser's attestation of a match of the current round and
+    /// the moves behind it; returns whether it decided a pairing.
+    pub fn report(
+        &mut self,
+        attestation: &Attestation,
+        evidence: &Evidence,
+        registry: &GameRegistry,
+        now: u64,
+    ) -> bool {
+        let result = &attestation.result;
+        let Outcome::Winner { seat } = result.outcome else {
+            return false;
+        };
+        let Some(round) = self.rounds.last_mut() else {
+            return false;
+        };
+        if result.game != self.game || attestation.ts < round.started {
+            return false;
+        }
+        let [a, b] = &result.players[..] else {
+            return false;
+        };
+        let Some(winner) = result.players.get(seat) else {
+            return false;
+        };
+        // Only the loser can concede a match.
+        if a == b || attestation.signer == *winner || !result.players.contains(&attestation.signer)
+        {
+            return false;
+        }
+        let pairing = round
+            .pairings
+            .iter_mut()
+            .find(|p| p.winner.is_none() && p.has(a) && p.has(b));
+        let Some(pairing) = pairing else {
+            return false;
+        };
+        let record = MatchRecord {
+            game_id: result.game_id.clone(),
+            game: result.game.clone(),
+            room: String::new(),
+            players: result.players.clone(),
+            outcome: result.outcome.clone(),
+            moves: evidence.replay.as_ref().map_or(0, |r| r.moves.len() as u64),
+            ended_at: attestation.ts,
+            replay: evidence.replay.clone(),
+            signed_moves: evidence.signed_moves.clone(),
+            validated: false,
+        };
+        if validation::validate(&record, registry).is_err() {
+            return false;
+        }
+        pairing.winner = Some(winner.clone());
+        self.advance(now);
+        true
+    }
+
+    /// Pair the winners for the next round once the current one is done.
+    fn advance(&mut self, now: u64) {
+        let Some((_, round)) = self.current() else {
+            return;
+        };
+        if round.pairings.len() < 2 || round.pairings.iter().any(|p| p.winner.is_none()) {
+            return;
+        }
+        let seed = |p: &String| self.players.iter().position(|q| q == p);
+        let pairings = round
+            .pairings
+            .chunks(2)
+            .map(|pair| {
+                let mut winners: Vec<String> =
+                    pair.iter().filter_map(|p| p.winner.clone()).collect();
+                winners.sort_by_key(seed);
+                Pairing {
+                    home: winners[0].clone(),
+                    away: winners.get(1).cloned(),
+                    winner: None,
+                }
+            })
+            .collect();
+        self.rounds.push(Round {
+            started: now,
+            pairings,
+        });
+    }
+}
+
+/// Brackets we organized or played in, by tournament id, kept in
+/// `<data dir>/p2p-games/tournaments.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Tournaments {
+    brackets: BTreeMap<String, Bracket>,
+}
+
+impl Tournaments {
+    fn storage_path() -> PathBuf {
+        data_dir().join("tournaments.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    pub fn upsert(&mut self, bracket: &Bracket) {
+        self.brackets.insert(bracket.id.clone(), bracket.clone());
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Bracket> {
+        self.brackets.get(id)
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &Bracket> {
+        self.brackets.values()
+    }
+}
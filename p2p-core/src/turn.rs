@@ -0,0 +1,450 @@
+//! Turn order shared by all turn-based games.
+//!
+//! [`TurnEngine`] wraps a running [`AnyGame`] with the match bookkeeping
+//! every game needs: seat assignment, the move counter, and the check that a
+//! [`GameBody::Move`] comes from the seat whose turn it is before the game
+//! sees it. Games only implement their rules.
+//!
+//! The first seat is authoritative: after each applied move it broadcasts a
+//! [`GameBody::Turn`] so the other seats can detect that they diverged and
+//! resync.
+//!
+//! Players who leave do not stall the match: the first seat announces the
+//! drop ([`GameBody::Eliminate`]) and every peer handles it the same way,
+//! as the game's [`DropPolicy`] says. Under [`DropPolicy::Bot`] the first
+//! seat moves for the seat until its player is back ([`GameBody::Rejoin`]).
+//!
+//! Matches seat any number of players. [`TurnOrder`] decides the seat order
+//! when a match starts; games with more than two seats pass the turn round
+//! that order with a [`Rotation`], which skips eliminated seats.
+
+use anyhow::{Result, anyhow, bail};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+use crate::{
+    game::{AnyGame, DropPolicy, GameBody, GameRegistry, Outcome, SavedGame, Seat},
+    replay::{Replay, ReplayMove, SeatChange},
+    t,
+};
+
+/// How the players of a new match are seated; seat 0 moves first.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TurnOrder {
+    /// Host first, then in the order players took a seat.
+    #[default]
+    Clockwise,
+    /// Shuffled anew for every match.
+    Random,
+    /// Shuffled by a seed; the same seed and players give the same order.
+    Seeded,
+}
+
+impl TurnOrder {
+    /// `players` (host first, then in the order they showed up) in seat
+    /// order. `seed` is only used by [`TurnOrder::Seeded`].
+    pub fn arrange(self, mut players: Vec<String>, seed: Option<u64>) -> Vec<String> {
+        let seed = match self {
+            TurnOrder::Clockwise => return players,
+            TurnOrder::Random => uuid::Uuid::new_v4().as_u64_pair().0,
+            TurnOrder::Seeded => {
+                // Independent of who showed up first.
+                players.sort();
+                seed.unwrap_or_default()
+            }
+        };
+        shuffle(&mut players, seed);
+        players
+    }
+}
+
+/// Fisher–Yates driven by splitmix64, so a seed shuffles the same way on
+/// every platform and version.
+fn shuffle<T>(items: &mut [T], mut seed: u64) {
+    for i in (1..items.len()).rev() {
+        seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        items.swap(i, (z % (i as u64 + 1)) as usize);
+    }
+}
+
+/// Whose turn it is when turns go round the seats in order, skipping the
+/// eliminated ones. Kept in the state of games with more than two seats.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rotation {
+    seats: usize,
+    current: Seat,
+    #[serde(default)]
+    out: BTreeSet<Seat>,
+}
+
+impl Rotation {
+    pub fn new(seats: usize) -> Self {
+        Self {
+            seats,
+            ..Self::default()
+        }
+    }
+
+    pub fn current(&self) -> Seat {
+        self.current
+    }
+
+    pub fn is_out(&self, seat: Seat) -> bool {
+        self.out.contains(&seat)
+    }
+
+    /// Seats still playing, in seat order.
+    pub fn active(&self) -> impl Iterator<Item = Seat> + '_ {
+        (0..self.seats).filter(|s| !self.is_out(*s))
+    }
+
+    /// Pass the turn to the next seat still playing.
+    pub fn advance(&mut self) {
+        for step in 1..=self.seats {
+            let next = (self.current + step) % self.seats;
+            if !self.is_out(next) {
+                self.current = next;
+                return;
+            }
+        }
+    }
+
+    /// Take `seat` out; if it was its turn, the next seat moves.
+    pub fn eliminate(&mut self, seat: Seat) {
+        self.out.insert(seat);
+        if self.current == seat {
+            self.advance();
+        }
+    }
+}
+
+/// What happened to a move received from the room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// Applied; the game advanced by one move.
+    Applied,
+    /// Already applied (duplicate or replay); nothing to do.
+    Stale,
+    /// The sender is ahead of us or out of turn; answer with
+    /// [`TurnEngine::state_sync`].
+    Resync,
+    /// Not a player of this match, or the game refused the move.
+    Rejected(String),
+}
+
+/// One match: players in seat order, moves applied so far, and the game.
+pub struct TurnEngine {
+    game_id: String,
+    players: Vec<String>,
+    seq: u64,
+    game: Box<dyn AnyGame>,
+    /// Applied moves; `None` once a state sync skipped some.
+    moves: Option<Vec<ReplayMove>>,
+    /// Seats played by the first seat's bot.
+    bots: BTreeSet<Seat>,
+    /// Why the match ended early, under [`DropPolicy::EndGame`].
+    aborted: Option<String>,
+}
+
+impl TurnEngine {
+    pub fn new(game_id: String, players: Vec<String>, game: Box<dyn AnyGame>) -> Self {
+        Self {
+            game_id,
+            players,
+            seq: 0,
+            game,
+            moves: Some(Vec::new()),
+            bots: BTreeSet::new(),
+            aborted: None,
+        }
+    }
+
+    /// Rebuild a match from its save.
+    pub fn resume(registry: &GameRegistry, saved: &SavedGame) -> Result<Self> {
+        let mut game = registry.create(&saved.game, saved.players.len())?;
+        game.load_state(saved.state.clone())?;
+        Ok(Self {
+            game_id: saved.game_id.clone(),
+            players: saved.players.clone(),
+            seq: saved.seq,
+            game,
+            moves: saved.moves.clone(),
+            bots: saved.bots.clone(),
+            aborted: None,
+        })
+    }
+
+    pub fn game_id(&self) -> &str {
+        &self.game_id
+    }
+
+    pub fn players(&self) -> &[String] {
+        &self.players
+    }
+
+    /// Moves applied so far.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn game(&self) -> &dyn AnyGame {
+        &*self.game
+    }
+
+    pub fn seat_of(&self, peer_id: &str) -> Option<Seat> {
+        self.players.iter().position(|p| p == peer_id)
+    }
+
+    /// Peer whose turn it is, `None` once the game is over.
+    pub fn to_move(&self) -> Option<&str> {
+        self.game
+            .to_move()
+            .and_then(|s| self.players.get(s))
+            .map(String::as_str)
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.game.outcome().or_else(|| {
+            self.aborted
+                .clone()
+                .map(|reason| Outcome::Aborted { reason })
+        })
+    }
+
+    /// Whether a bot plays `seat` because its player left.
+    pub fn is_bot(&self, seat: Seat) -> bool {
+        self.bots.contains(&seat)
+    }
+
+    /// Make a move for `seat`, typed by the user; returns the message to
+    /// broadcast.
+    pub fn play(&mut self, seat: Seat, input: &str) -> Result<GameBody> {
+        if self.is_bot(seat) {
+            bail!(t!("game.bot_playing"));
+        }
+        if self.game.to_move() != Some(seat) || self.aborted.is_some() {
+            bail!(t!("game.not_your_turn"));
+        }
+        let mv = self.game.parse_move(input)?;
+        self.apply_move(seat, mv)
+    }
+
+    /// Move for the bot seat whose turn it is, if any; the first seat
+    /// broadcasts the returned message. The bot plays the first legal move,
+    /// so every peer would have picked the same one.
+    pub fn play_bot(&mut self) -> Option<GameBody> {
+        let seat = self.game.to_move().filter(|s| self.is_bot(*s))?;
+        if self.aborted.is_some() {
+            return None;
+        }
+        let mv = self.game.legal_moves(seat).into_iter().next()?;
+        self.apply_move(seat, mv).ok()
+    }
+
+    fn apply_move(&mut self, seat: Seat, mv: Value) -> Result<GameBody> {
+        self.game.apply(seat, &mv)?;
+        self.applied(ReplayMove {
+            seat,
+            mv: mv.clone(),
+            change: None,
+        });
+        Ok(GameBody::Move {
+            game_id: self.game_id.clone(),
+            seq: self.seq - 1,
+            mv,
+        })
+    }
+
+    /// Check and apply a [`GameBody::Move`] sent by `sender`.
+    pub fn receive(&mut self, sender: &str, seq: u64, mv: &Value) -> Verdict {
+        if seq < self.seq {
+            return Verdict::Stale;
+        }
+        let Some(mut seat) = self.seat_of(sender) else {
+            return Verdict::Rejected(format!("{sender} is not playing"));
+        };
+        // The first seat moves for bot seats.
+        if let Some(s) = self.game.to_move()
+            && self.is_bot(s)
+            && self.is_authority(sender)
+        {
+            seat = s;
+        }
+        if seq > self.seq || self.game.to_move() != Some(seat) || self.aborted.is_some() {
+            return Verdict::Resync;
+        }
+        match self.apply_move(seat, mv.clone()) {
+            Ok(_) => Verdict::Applied,
+            Err(e) => Verdict::Rejected(e.to_string()),
+        }
+    }
+
+    /// The player of `seat` left: handle it as the game's [`DropPolicy`]
+    /// says; returns the message to broadcast.
+    pub fn eliminate(&mut self, seat: Seat) -> Result<GameBody> {
+        self.change_seat(seat, SeatChange::Dropped)?;
+        Ok(GameBody::Eliminate {
+            game_id: self.game_id.clone(),
+            seq: self.seq - 1,
+            seat,
+        })
+    }
+
+    /// The player of a bot seat is back and takes it over again; returns
+    /// the message to broadcast.
+    pub fn rejoin(&mut self, seat: Seat) -> Result<GameBody> {
+        self.change_seat(seat, SeatChange::Rejoined)?;
+        Ok(GameBody::Rejoin {
+            game_id: self.game_id.clone(),
+            seq: self.seq - 1,
+            seat,
+        })
+    }
+
+    /// Check and apply a [`GameBody::Eliminate`] or [`GameBody::Rejoin`]
+    /// sent by `sender`.
+    pub fn receive_seat_change(
+        &mut self,
+        sender: &str,
+        seq: u64,
+        seat: Seat,
+        change: SeatChange,
+    ) -> Verdict {
+        if seq < self.seq {
+            return Verdict::Stale;
+        }
+        if !self.is_authority(sender) {
+            return Verdict::Rejected(format!("{sender} does not run this match"));
+        }
+        if seq > self.seq {
+            return Verdict::Resync;
+        }
+        match self.change_seat(seat, change) {
+            Ok(()) => Verdict::Applied,
+            Err(e) => Verdict::Rejected(e.to_string()),
+        }
+    }
+
+    fn change_seat(&mut self, seat: Seat, change: SeatChange) -> Result<()> {
+        let Some(player) = self.players.get(seat) else {
+            bail!("no seat {seat}");
+        };
+        if self.outcome().is_some() {
+            bail!(t!("game.over"));
+        }
+        match (change, self.game.on_drop()) {
+            (SeatChange::Rejoined, _) => {
+                if !self.bots.remove(&seat) {
+                    bail!("seat {seat} is not played by a bot");
+                }
+            }
+            (SeatChange::Dropped, DropPolicy::Bot) => {
+                self.bots.insert(seat);
+            }
+            (SeatChange::Dropped, DropPolicy::EndGame) => {
+                let reason = t!("game.player_left", player = player);
+                self.game.eliminate(seat)?;
+                if self.game.outcome().is_none() {
+                    self.aborted = Some(reason);
+                }
+            }
+            (SeatChange::Dropped, DropPolicy::SkipTurns | DropPolicy::Redistribute) => {
+                self.game.eliminate(seat)?
+            }
+        }
+        self.applied(ReplayMove {
+            seat,
+            mv: Value::Null,
+            change: Some(change),
+        });
+        Ok(())
+    }
+
+    fn applied(&mut self, mv: ReplayMove) {
+        self.seq += 1;
+        if let Some(moves) = &mut self.moves {
+            moves.push(mv);
+        }
+    }
+
+    /// Every move of the match so far, unless a state sync skipped some.
+    pub fn replay(&self) -> Option<Replay> {
+        Some(Replay {
+            moves: self.moves.clone()?,
+            state: self.game.state(),
+            outcome: self.game.outcome(),
+        })
+    }
+
+    /// Whether `peer_id` announces turns for this match.
+    pub fn is_authority(&self, peer_id: &str) -> bool {
+        self.players.first().is_some_and(|p| p == peer_id)
+    }
+
+    /// The authoritative turn transition after the last applied move.
+    pub fn turn(&self) -> GameBody {
+        GameBody::Turn {
+            game_id: self.game_id.clone(),
+            seq: self.seq,
+            to_move: self.game.to_move(),
+        }
+    }
+
+    /// Whether a [`GameBody::Turn`] agrees with our state. A turn for a
+    /// later move means we missed something; either way a mismatch is
+    /// settled by exchanging [`state_sync`](Self::state_sync).
+    pub fn agrees(&self, seq: u64, to_move: Option<Seat>) -> bool {
+        seq < self.seq || (seq == self.seq && to_move == self.game.to_move())
+    }
+
+    pub fn start(&self) -> GameBody {
+        GameBody::Start {
+            game_id: self.game_id.clone(),
+            game: self.game.name().to_string(),
+            players: self.players.clone(),
+        }
+    }
+
+    pub fn state_sync(&self) -> GameBody {
+        GameBody::StateSync {
+            game_id: self.game_id.clone(),
+            seq: self.seq,
+            state: self.game.state(),
+        }
+    }
+
+    /// Take over a newer state from [`GameBody::StateSync`]; returns whether
+    /// it was newer than ours.
+    pub fn load_sync(&mut self, seq: u64, state: Value) -> Result<bool> {
+        if seq <= self.seq {
+            return Ok(false);
+        }
+        self.game
+            .load_state(state)
+            .map_err(|e| anyhow!("{}: {e}", self.game_id))?;
+        self.seq = seq;
+        self.moves = None;
+        Ok(true)
+    }
+
+    /// Snapshot for `games.json`; `room` is the room name.
+    pub fn saved(&self, room: &str) -> SavedGame {
+        SavedGame {
+            game_id: self.game_id.clone(),
+            room: room.to_string(),
+            game: self.game.name().to_string(),
+            players: self.players.clone(),
+            seq: self.seq,
+            state: self.game.state(),
+            moves: self.moves.clone(),
+            bots: self.bots.clone(),
+        }
+    }
+}
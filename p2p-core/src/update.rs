@@ -0,0 +1,144 @@
+//! Opt-in check for newer releases.
+//!
+//! The release feed serves a [`SignedManifest`]: the JSON-encoded
+//! [`ReleaseManifest`] together with an Ed25519 signature by the release key.
+//! A manifest is only trusted if the signature verifies against the public key
+//! pinned in the config. We compare versions and tell the user; nothing is ever
+//! downloaded or installed automatically.
+
+use anyhow::{Context, Result, anyhow, bail};
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf, time::Duration};
+
+use crate::{protocol::now_ms, session::data_dir};
+
+/// HTTP timeout for fetching the manifest.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `[update]` section of `config.toml`. Disabled unless explicitly enabled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdateConfig {
+    /// Check the feed automatically on startup (at most once per `interval_hours`).
+    pub enabled: bool,
+    /// URL of the signed release manifest.
+    pub manifest_url: String,
+    /// Hex-encoded Ed25519 public key of the release signer.
+    pub public_key: String,
+    /// Minimum time between automatic checks.
+    pub interval_hours: u64,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            manifest_url: String::new(),
+            public_key: String::new(),
+            interval_hours: 24,
+        }
+    }
+}
+
+/// Description of the latest release.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReleaseManifest {
+    /// Release version (`major.minor.patch`).
+    pub version: String,
+    /// Wire protocol version spoken by that release.
+    pub protocol_ver: u16,
+    /// Where to download it (shown to the user, never fetched).
+    pub url: String,
+    /// Optional release notes.
+    pub notes: Option<String>,
+}
+
+/// Manifest as published on the feed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedManifest {
+    /// JSON-encoded [`ReleaseManifest`] exactly as signed.
+    pub manifest: String,
+    /// Hex-encoded Ed25519 signature over `manifest`.
+    pub signature: String,
+}
+
+/// Outcome of a check.
+#[derive(Debug, Clone)]
+pub enum UpdateStatus {
+    UpToDate,
+    Available(ReleaseManifest),
+}
+
+/// Verify `signed` against the hex-encoded `public_key` and decode it.
+pub fn verify_manifest(signed: &SignedManifest, public_key: &str) -> Result<ReleaseManifest> {
+    let key: [u8; 32] = hex::decode(public_key)?
+        .try_into()
+        .map_err(|_| anyhow!("release public key must be 32 bytes"))?;
+    let key = VerifyingKey::from_bytes(&key)?;
+    let sig: [u8; 64] = hex::decode(&signed.signature)?
+        .try_into()
+        .map_err(|_| anyhow!("manifest signature must be 64 bytes"))?;
+    key.verify_strict(signed.manifest.as_bytes(), &Signature::from_bytes(&sig))
+        .context("release manifest signature is invalid")?;
+    Ok(serde_json::from_str(&signed.manifest)?)
+}
+
+/// Whether `candidate` is a strictly newer `major.minor.patch` than `current`.
+///
+/// Pre-release / build suffixes are ignored; unparsable parts count as `0`.
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    fn parts(v: &str) -> [u64; 3] {
+        let core = v
+            .trim_start_matches('v')
+            .split(['-', '+'])
+            .next()
+            .unwrap_or("");
+        let mut out = [0; 3];
+        for (slot, p) in out.iter_mut().zip(core.split('.')) {
+            *slot = p.parse().unwrap_or(0);
+        }
+        out
+    }
+    parts(candidate) > parts(current)
+}
+
+/// Fetch and verify the manifest, then compare it against `current_version`.
+pub async fn check(cfg: &UpdateConfig, current_version: &str) -> Result<UpdateStatus> {
+    if cfg.manifest_url.is_empty() || cfg.public_key.is_empty() {
+        bail!("update check needs `manifest_url` and `public_key` in [update]");
+    }
+    let signed: SignedManifest = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()?
+        .get(&cfg.manifest_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let manifest = verify_manifest(&signed, &cfg.public_key)?;
+    let _ = fs::write(stamp_path(), now_ms().to_string());
+
+    if is_newer(&manifest.version, current_version) {
+        Ok(UpdateStatus::Available(manifest))
+    } else {
+        Ok(UpdateStatus::UpToDate)
+    }
+}
+
+/// Whether an automatic check is enabled and due.
+pub fn due(cfg: &UpdateConfig) -> bool {
+    if !cfg.enabled {
+        return false;
+    }
+    let last: u64 = fs::read_to_string(stamp_path())
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0);
+    now_ms().saturating_sub(last) >= cfg.interval_hours * 3_600_000
+}
+
+fn stamp_path() -> PathBuf {
+    data_dir().join("update_check")
+}
@@ -0,0 +1,71 @@
+//! Match results checked against the rules before they count.
+//!
+//! Any client can claim any outcome in a [`GameBody::End`]; two colluding
+//! clients can even agree on one. A result therefore only counts towards
+//! win records when [`validate`] accepts it: the recorded moves are played
+//! again through today's engine and must lead to the claimed outcome, and
+//! every signed move envelope received from the other players must match
+//! the move recorded for its seat and sequence number. Matches without a
+//! full move log (they took over a state sync) cannot be checked and do not
+//! count.
+
+use anyhow::{Result, bail};
+use serde_json::Value;
+
+use crate::{
+    dispute::DisputeBook,
+    game::{GameBody, GameRegistry},
+    matchlog::MatchRecord,
+    protocol::Envelope,
+    signing, t,
+};
+
+/// Whether the result of `record` counts towards win records: it passes
+/// [`validate`] and is not disputed (see [`crate::dispute`]).
+pub fn counts(record: &MatchRecord, registry: &GameRegistry, disputes: &DisputeBook) -> bool {
+    disputes.counts(&record.game_id) && validate(record, registry).is_ok()
+}
+
+/// Check the claimed outcome of `record` against its logged moves. A record
+/// whose replay was dropped for space passes if it passed before.
+pub fn validate(record: &MatchRecord, registry: &GameRegistry) -> Result<()> {
+    let Some(replay) = &record.replay else {
+        if record.validated {
+            return Ok(());
+        }
+        bail!(t!("validation.no_replay"));
+    };
+    if replay.outcome.as_ref() != Some(&record.outcome) {
+        bail!(t!("validation.outcome_claimed"));
+    }
+    replay.verify(registry, &record.game, record.players.len())?;
+    for signed in &record.signed_moves {
+        check_signed(record, signed)?;
+    }
+    Ok(())
+}
+
+/// A signed move envelope must be validly signed by a player of the match
+/// and agree with the move logged at its `seq`.
+fn check_signed(record: &MatchRecord, signed: &Value) -> Result<()> {
+    let env = (signed["sig"].is_string() && signing::accept(signed))
+        .then(|| serde_json::from_value::<Envelope<GameBody>>(signed.clone()).ok())
+        .flatten();
+    let Some(env) = env else {
+        bail!(t!("validation.bad_signature"));
+    };
+    let GameBody::Move { game_id, seq, mv } = env.body else {
+        bail!(t!("validation.bad_signature"));
+    };
+    let seat = record.players.iter().position(|p| *p == env.sender_id);
+    let logged = record
+        .replay
+        .as_ref()
+        .and_then(|r| r.moves.get(usize::try_from(seq).ok()?));
+    let agrees = game_id == record.game_id
+        && logged.is_some_and(|m| Some(m.seat) == seat && m.change.is_none() && m.mv == mv);
+    if !agrees {
+        bail!(t!("validation.signed_move_differs", seq = seq));
+    }
+    Ok(())
+}
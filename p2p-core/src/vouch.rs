@@ -0,0 +1,140 @@
+//! Vouches: peers speaking up for each other.
+//!
+//! A peer vouches for someone it knows with a signed
+//! [`DiscoveryBody::Vouch`] on the discovery topic, and takes it back the
+//! same way. Every node keeps the vouches it hears in
+//! `<data dir>/p2p-games/vouches.json`; `whois` shows them. A host can let
+//! peers vouched for by enough of its friends into its rooms without
+//! looking at each join (`[vouch]` in `config.toml`):
+//!
+//! ```toml
+//! [vouch]
+//! friends = ["3ac1…", "alice"]   # peer ids, or nicknames pinned in the directory
+//! auto_admit = 2                 # friends' vouches needed to auto-accept a join
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{
+    directory::PeerDirectory,
+    protocol::{DiscoveryBody, Envelope},
+    session::data_dir,
+};
+
+/// `[vouch]` section of `config.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VouchConfig {
+    /// Peers whose vouches count for admission.
+    pub friends: Vec<String>,
+    /// Vouches from friends after which a join request to a room we host is
+    /// accepted right away; unset to look at every join.
+    pub auto_admit: Option<usize>,
+}
+
+impl VouchConfig {
+    /// Whether `peer_id` is one of our friends. A friend listed by nickname
+    /// is the key that nickname is pinned to, not whoever uses it now.
+    pub fn is_friend(&self, peer_id: &str, directory: &PeerDirectory) -> bool {
+        self.friends.iter().any(|f| {
+            f == peer_id
+                || directory
+                    .pins
+                    .get(&f.to_lowercase())
+                    .is_some_and(|p| p == peer_id)
+        })
+    }
+}
+
+/// Latest vouch or revocation of one voucher.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Given {
+    at: u64,
+    active: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VouchBook {
+    /// Peer vouched for → voucher → latest word.
+    vouches: BTreeMap<String, BTreeMap<String, Given>>,
+}
+
+impl VouchBook {
+    fn storage_path() -> PathBuf {
+        data_dir().join("vouches.json")
+    }
+
+    pub fn load() -> std::io::Result<Self> {
+        let path = Self::storage_path();
+        if path.exists() {
+            let b = fs::read(path)?;
+            serde_json::from_slice(&b)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).unwrap();
+        fs::write(Self::storage_path(), json)
+    }
+
+    /// `voucher` vouched for `peer_id` at `at` (or revoked it); older news
+    /// and vouches for oneself are ignored. Returns whether anything changed.
+    pub fn record(&mut self, voucher: &str, peer_id: &str, at: u64, active: bool) -> bool {
+        if voucher == peer_id {
+            return false;
+        }
+        let vouchers = self.vouches.entry(peer_id.to_string()).or_default();
+        if let Some(given) = vouchers.get(voucher)
+            && (at < given.at || given.active == active)
+        {
+            return false;
+        }
+        vouchers.insert(voucher.to_string(), Given { at, active });
+        true
+    }
+
+    /// Record a vouch heard on the discovery topic. Only signed ones count;
+    /// anybody could send an unsigned vouch in a friend's name.
+    pub fn apply(&mut self, env: &Envelope<DiscoveryBody>) -> bool {
+        match &env.body {
+            DiscoveryBody::Vouch { peer_id, revoke } if env.sig.is_some() => {
+                self.record(&env.sender_id, peer_id, env.ts, !revoke)
+            }
+            _ => false,
+        }
+    }
+
+    /// Peers currently vouching for `peer_id`.
+    pub fn vouchers(&self, peer_id: &str) -> Vec<&str> {
+        self.vouches.get(peer_id).map_or_else(Vec::new, |v| {
+            v.iter()
+                .filter(|(_, g)| g.active)
+                .map(|(voucher, _)| voucher.as_str())
+                .collect()
+        })
+    }
+
+    /// How many of our friends vouch for `peer_id`.
+    pub fn from_friends(
+        &self,
+        peer_id: &str,
+        config: &VouchConfig,
+        directory: &PeerDirectory,
+    ) -> usize {
+        self.vouchers(peer_id)
+            .into_iter()
+            .filter(|v| config.is_friend(v, directory))
+            .count()
+    }
+
+    /// Whether a join of `peer_id` is accepted without the host looking.
+    pub fn admits(&self, peer_id: &str, config: &VouchConfig, directory: &PeerDirectory) -> bool {
+        config
+            .auto_admit
+            .is_some_and(|n| self.from_friends(peer_id, config, directory) >= n)
+    }
+}
@@ -0,0 +1,63 @@
+//! Render models for game UIs.
+//!
+//! Games describe *what* to show with a [`RenderModel`]; front ends decide
+//! *how*. [`Output::render`](crate::output::Output::render) draws it for the
+//! terminal (rich or plain), so all games share one look and can be tested by
+//! comparing models instead of ASCII art.
+
+/// Anything a game can put on screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderModel {
+    Grid(GridModel),
+    Hand(HandModel),
+    Scores(ScoreTable),
+    /// Several parts shown top to bottom (e.g. board, then scores).
+    Stack(Vec<RenderModel>),
+}
+
+/// Rectangular board with single-character pieces (`None` = empty).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GridModel {
+    pub cells: Vec<Vec<Option<char>>>,
+    /// Cell to emphasize, usually the last move (row, col).
+    pub highlight: Option<(usize, usize)>,
+}
+
+impl GridModel {
+    pub fn empty(rows: usize, cols: usize) -> Self {
+        Self {
+            cells: vec![vec![None; cols]; rows],
+            highlight: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Card {
+    /// Short face text, e.g. `"10♥"`.
+    pub label: String,
+    /// Whether the card may be played right now.
+    pub playable: bool,
+}
+
+/// The local player's cards.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandModel {
+    pub cards: Vec<Card>,
+    pub selected: Option<usize>,
+}
+
+/// Score sheet: one row per player, one column per round/category.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScoreTable {
+    pub columns: Vec<String>,
+    pub rows: Vec<ScoreRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScoreRow {
+    pub player: String,
+    pub values: Vec<String>,
+    /// Whose turn it is / who leads; emphasized when drawn.
+    pub highlight: bool,
+}
@@ -0,0 +1,323 @@
+//! Wire formats and the migration between them.
+//!
+//! Two envelope encodings exist:
+//! - `ver = 1`: the bare JSON envelope,
+//! - `ver = 2`: a one-byte version frame (`0x02`) followed by the JSON
+//!   envelope, so later encodings can be told apart without parsing.
+//!
+//! Version 1 peers cannot read version 2 frames. To move the network without
+//! a flag day, `[protocol] target_ver = 2` together with `dual_emit_until`
+//! makes [`Wire::encode`] emit every envelope in both formats until the
+//! deadline, or until every peer we have heard from advertised version 2
+//! through a [`Capabilities`] message or a version 2 frame. [`Wire::decode`]
+//! always accepts both formats and drops the second copy of a dual-emitted
+//! envelope by `msg_id`.
+//!
+//! Both directions go through the [`Pipeline`] of `[protocol] middleware`
+//! (signatures, deduplication, compression).
+//!
+//! The [`Capabilities`] announcements also list the games a peer's client
+//! plays; [`Wire::game_support`] tells front ends whom not to offer a game.
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use crate::{
+    game::GameRegistry,
+    hlc,
+    middleware::{Middleware, Pipeline, RecentIds, Stage},
+    protocol::{
+        Capabilities, ChatChange, ChatDelete, ChatEdit, Envelope, Kind, PROTOCOL_VER, Scope,
+        make_envelope, now_ms,
+    },
+    signing, t,
+};
+
+/// Envelope versions this build can decode.
+pub const SUPPORTED_VERS: &[u16] = &[1, 2];
+
+/// `[protocol]` section of `config.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProtocolConfig {
+    /// Envelope version to migrate to.
+    pub target_ver: u16,
+    /// Until this unix time (seconds), also emit `ver = 1` copies when
+    /// `target_ver` is newer. `None` switches over immediately.
+    pub dual_emit_until: Option<u64>,
+    /// Drop unsigned envelopes instead of accepting them from older peers.
+    pub require_signatures: bool,
+    /// Envelope middleware in send order, see [`crate::middleware`].
+    pub middleware: Vec<Stage>,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            target_ver: PROTOCOL_VER,
+            dual_emit_until: None,
+            require_signatures: false,
+            middleware: Stage::defaults(),
+        }
+    }
+}
+
+impl ProtocolConfig {
+    fn target(&self) -> u16 {
+        if SUPPORTED_VERS.contains(&self.target_ver) {
+            self.target_ver
+        } else {
+            PROTOCOL_VER
+        }
+    }
+
+    /// Whether the dual-emit window is still open.
+    pub fn in_transition(&self) -> bool {
+        self.target() > PROTOCOL_VER
+            && self
+                .dual_emit_until
+                .is_some_and(|until| now_ms() / 1000 < until)
+    }
+}
+
+/// Split a received frame into its version and JSON part.
+///
+/// Returns `None` for versions not in [`SUPPORTED_VERS`].
+pub fn parse_frame(bytes: &[u8]) -> Option<(u16, &[u8])> {
+    match bytes.first()? {
+        b'{' => Some((1, bytes)),
+        2 => Some((2, &bytes[1..])),
+        _ => None,
+    }
+}
+
+/// Whether a peer's client can play a game, as far as we know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameSupport {
+    Yes,
+    /// No game list heard from the peer (an older client, or none yet).
+    Unknown,
+    Missing,
+    /// The peer plays this other version of the game.
+    OtherVersion(u32),
+}
+
+impl GameSupport {
+    /// Why `peer` cannot play version `version` of `game`, for the user;
+    /// `None` unless it is known that it cannot.
+    pub fn problem(self, peer: &str, game: &str, version: u32) -> Option<String> {
+        match self {
+            GameSupport::Yes | GameSupport::Unknown => None,
+            GameSupport::Missing => Some(t!("caps.missing_game", peer = peer, game = game)),
+            GameSupport::OtherVersion(theirs) => Some(t!(
+                "caps.other_version",
+                peer = peer,
+                game = game,
+                theirs = theirs,
+                ours = version
+            )),
+        }
+    }
+}
+
+/// Version-aware encoder/decoder shared by everything that publishes.
+pub struct Wire {
+    cfg: ProtocolConfig,
+    pipeline: Pipeline,
+    /// Highest version each peer has shown it understands.
+    peers: Mutex<HashMap<String, u16>>,
+    /// Games each peer announced it plays, with their versions.
+    games: Mutex<HashMap<String, BTreeMap<String, u32>>>,
+}
+
+impl Wire {
+    pub fn new(cfg: &ProtocolConfig) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            pipeline: Pipeline::new(&cfg.middleware),
+            peers: Mutex::new(HashMap::new()),
+            games: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &ProtocolConfig {
+        &self.cfg
+    }
+
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    /// Add a middleware stage after the configured ones.
+    pub fn push_middleware(&mut self, stage: Box<dyn Middleware>) {
+        self.pipeline.push(stage);
+    }
+
+    /// Versions the next envelope is emitted in.
+    ///
+    /// During the transition both formats are sent unless every known peer
+    /// supports the target; with no known peer we stay conservative.
+    pub fn emit_versions(&self) -> Vec<u16> {
+        let target = self.cfg.target();
+        if !self.cfg.in_transition() {
+            return vec![target];
+        }
+        let peers = self.peers.lock().unwrap();
+        if !peers.is_empty() && peers.values().all(|v| *v >= target) {
+            vec![target]
+        } else {
+            vec![PROTOCOL_VER, target]
+        }
+    }
+
+    /// One encoded frame per version from [`Wire::emit_versions`].
+    pub fn encode<T: Serialize>(&self, env: &Envelope<T>) -> Vec<Vec<u8>> {
+        self.emit_versions()
+            .into_iter()
+            .map(|ver| self.encode_as(ver, env))
+            .collect()
+    }
+
+    /// Serialize an envelope in the given wire version.
+    pub fn encode_as<T: Serialize>(&self, ver: u16, env: &Envelope<T>) -> Vec<u8> {
+        let mut v = serde_json::to_value(env).expect("serialize envelope");
+        v["ver"] = ver.into();
+        self.pipeline.send(&mut v);
+        let json = serde_json::to_vec(&v).expect("serialize envelope");
+        match ver {
+            2 => {
+                let mut out = vec![2];
+                out.extend(json);
+                out
+            }
+            _ => json,
+        }
+    }
+
+    /// Decode a frame of any supported version.
+    ///
+    /// Capability announcements are consumed here and never returned, as are
+    /// envelopes dropped by the middleware (bad signature, already seen in
+    /// the other format, ...).
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Option<Envelope<T>> {
+        self.decode_inner(bytes, None)
+    }
+
+    /// [`Wire::decode`] for a reader that must see every message even when
+    /// another reader of this wire got it first (e.g. one of several
+    /// WebSocket clients): repeats are dropped by `recent` instead of the
+    /// shared `dedup` stage.
+    pub fn decode_own<T: DeserializeOwned>(
+        &self,
+        bytes: &[u8],
+        recent: &mut RecentIds,
+    ) -> Option<Envelope<T>> {
+        self.decode_inner(bytes, Some(recent))
+    }
+
+    fn decode_inner<T: DeserializeOwned>(
+        &self,
+        bytes: &[u8],
+        recent: Option<&mut RecentIds>,
+    ) -> Option<Envelope<T>> {
+        let (ver, json) = parse_frame(bytes)?;
+        let mut v: Value = serde_json::from_slice(json).ok()?;
+        let accepted = match &recent {
+            Some(recent) => {
+                let repeat = v["msg_id"].as_str().is_some_and(|id| recent.contains(id));
+                !repeat && self.pipeline.receive_undeduped(&mut v)
+            }
+            None => self.pipeline.receive(&mut v),
+        };
+        // The `sign` stage already checked it; without it, still drop
+        // forgeries and honour `require_signatures`.
+        let signed = self.pipeline.names().contains(&"sign") || signing::accept(&v);
+        if !accepted || !signed {
+            return None;
+        }
+        if let Ok(env) = Envelope::<Capabilities>::deserialize(&v) {
+            let max = env.body.versions.iter().copied().max().unwrap_or(ver);
+            self.learn(&env.sender_id, max);
+            if let Some(games) = env.body.games {
+                self.games.lock().unwrap().insert(env.sender_id, games);
+            }
+            return None;
+        }
+        let env = Envelope::<T>::deserialize(&v).ok()?;
+        self.learn(&env.sender_id, ver);
+        if let Some(stamp) = env.hlc {
+            hlc::observe(stamp);
+        }
+        match recent {
+            Some(recent) => {
+                recent.insert(&env.msg_id);
+                self.pipeline.delivered_undeduped(&v);
+            }
+            None => self.pipeline.delivered(&v),
+        }
+        Some(env)
+    }
+
+    /// Decode a [`ChatEdit`] or [`ChatDelete`].
+    ///
+    /// Changes must be signed and checked by the `sign` stage: anyone can put
+    /// someone else's id into `sender_id`, and only the sender of a message
+    /// may change it.
+    pub fn decode_change(&self, bytes: &[u8]) -> Option<ChatChange> {
+        if !self.pipeline.names().contains(&"sign") {
+            return None;
+        }
+        // Look at the kind first; most frames are not changes.
+        let (_, json) = parse_frame(bytes)?;
+        let v: Value = serde_json::from_slice(json).ok()?;
+        let change = match v["kind"].as_str()? {
+            "EDIT" => ChatChange::Edit(self.decode::<ChatEdit>(bytes)?),
+            "DELETE" => ChatChange::Delete(self.decode::<ChatDelete>(bytes)?),
+            _ => return None,
+        };
+        let signed = match &change {
+            ChatChange::Edit(env) => env.sig.is_some(),
+            ChatChange::Delete(env) => env.sig.is_some(),
+        };
+        signed.then_some(change)
+    }
+
+    /// Our capability announcement, always in the `ver = 1` format.
+    pub fn capabilities(&self, sender_id: String) -> Vec<u8> {
+        let env = make_envelope(
+            Kind::Discovery,
+            Scope::Global,
+            None,
+            sender_id,
+            now_ms(),
+            Capabilities {
+                versions: SUPPORTED_VERS.to_vec(),
+                games: Some(GameRegistry::builtin().versions()),
+            },
+        );
+        self.encode_as(1, &env)
+    }
+
+    /// Whether `peer_id` can play version `version` of `game`.
+    pub fn game_support(&self, peer_id: &str, game: &str, version: u32) -> GameSupport {
+        let games = self.games.lock().unwrap();
+        let Some(games) = games.get(peer_id) else {
+            return GameSupport::Unknown;
+        };
+        match games.get(game) {
+            Some(v) if *v == version => GameSupport::Yes,
+            Some(v) => GameSupport::OtherVersion(*v),
+            None => GameSupport::Missing,
+        }
+    }
+
+    fn learn(&self, peer_id: &str, ver: u16) {
+        let mut peers = self.peers.lock().unwrap();
+        let known = peers.entry(peer_id.to_string()).or_default();
+        *known = (*known).max(ver);
+    }
+}
@@ -0,0 +1,31 @@
+//! A throwaway data directory per test.
+
+use p2p_core::session;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tempfile::TempDir;
+
+/// The data directory is process-wide, so tests holding one take turns.
+static TURN: Mutex<()> = Mutex::new(());
+
+/// Points [`session::data_dir`] at a fresh temp directory until dropped,
+/// keeping the user's own peers, plugins and logs out of the test.
+pub struct DataDir {
+    _dir: TempDir,
+    _turn: MutexGuard<'static, ()>,
+}
+
+pub fn isolate() -> DataDir {
+    let turn = TURN.lock().unwrap_or_else(PoisonError::into_inner);
+    let dir = TempDir::new().unwrap();
+    session::set_data_root(Some(dir.path().to_path_buf()));
+    DataDir {
+        _dir: dir,
+        _turn: turn,
+    }
+}
+
+impl Drop for DataDir {
+    fn drop(&mut self) {
+        session::set_data_root(None);
+    }
+}
@@ -0,0 +1,72 @@
+//! Helpers shared by the integration tests.
+
+pub mod data_dir;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::Barrier;
+use transport_iroh::{
+    memory::{MemoryBroker, MemoryTransport},
+    transport_iroh::{GossipTransport, NodeAddr, TopicHandle, TopicId},
+};
+
+/// A node whose topic joins return only once every node of its group has
+/// joined as well, so nobody publishes into a topic before the others
+/// listen. Each node of a group must join exactly once.
+pub struct Gated {
+    inner: MemoryTransport,
+    gate: Arc<Barrier>,
+}
+
+impl Gated {
+    pub fn group<const N: usize>(broker: &MemoryBroker) -> [Self; N] {
+        let gate = Arc::new(Barrier::new(N));
+        std::array::from_fn(|_| Self {
+            inner: broker.node(),
+            gate: gate.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl GossipTransport for Gated {
+    fn node_addr(&self) -> &NodeAddr {
+        self.inner.node_addr()
+    }
+
+    async fn connect(&self, peer: &NodeAddr) -> Result<()> {
+        self.inner.connect(peer).await
+    }
+
+    async fn join_topic(&self, topic: TopicId) -> Result<Box<dyn TopicHandle>> {
+        let th = self.inner.join_topic(topic).await?;
+        self.gate.wait().await;
+        Ok(th)
+    }
+
+    fn topic_from_name(&self, name: &str) -> TopicId {
+        self.inner.topic_from_name(name)
+    }
+
+    fn topic_from_hex(&self, hex: &str) -> Result<TopicId> {
+        self.inner.topic_from_hex(hex)
+    }
+
+    fn topic_to_hex(&self, topic: &TopicId) -> String {
+        self.inner.topic_to_hex(topic)
+    }
+
+    fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr> {
+        self.inner.parse_node_id_addr(s)
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+}
+
+/// Peer id of `node`.
+pub fn id(node: &dyn GossipTransport) -> String {
+    node.node_addr().node_id.to_string()
+}
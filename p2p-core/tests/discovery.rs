@@ -0,0 +1,96 @@
+//! Room discovery between nodes on an in-memory network.
+
+mod common;
+
+use common::{Gated, data_dir::isolate, id};
+use p2p_core::{discovery::Discovery, protocol::RoomSummary, timeouts::Timeouts};
+use std::time::Duration;
+use transport_iroh::{memory::MemoryBroker, transport_iroh::GossipTransport};
+
+fn timeouts() -> Timeouts {
+    Timeouts {
+        claim_wait_ms: 300,
+        list_rooms_ms: 600,
+        ..Timeouts::default()
+    }
+}
+
+fn room(host: &dyn GossipTransport, title: &str) -> RoomSummary {
+    RoomSummary {
+        room_id: format!("room-{title}"),
+        title: title.to_string(),
+        host_id: id(host),
+        last_seen: 0,
+        ticket: None,
+        current_players: Some(1),
+        max_players: Some(2),
+        game: None,
+        language: None,
+    }
+}
+
+fn titles(rooms: &[RoomSummary]) -> Vec<&str> {
+    rooms.iter().map(|r| r.title.as_str()).collect()
+}
+
+#[tokio::test]
+async fn list_rooms_is_answered_by_every_host() {
+    let _data = isolate();
+    let broker = MemoryBroker::new();
+    let (a, b, c) = (broker.node(), broker.node(), broker.node());
+    let (chess, go) = (room(&a, "chess"), room(&b, "go"));
+    let serve_a = Discovery::new(&a, timeouts()).serve_discovery(move || vec![chess.clone()]);
+    let serve_b = Discovery::new(&b, timeouts()).serve_discovery(move || vec![go.clone()]);
+    let browser = Discovery::new(&c, timeouts());
+    // Polled in order, so both responders listen before the request goes out.
+    let rooms = tokio::select! {
+        biased;
+        res = serve_a => panic!("responder stopped: {res:?}"),
+        res = serve_b => panic!("responder stopped: {res:?}"),
+        rooms = browser.list_rooms() => rooms.unwrap(),
+    };
+    assert_eq!(titles(&rooms), ["chess", "go"]);
+}
+
+#[tokio::test]
+async fn announced_rooms_show_up_while_listing() {
+    let _data = isolate();
+    let broker = MemoryBroker::new();
+    let (host, browser) = (broker.node(), broker.node());
+    let tetris = room(&host, "tetris");
+    let announce = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Discovery::new(&host, timeouts()).announce_room(&tetris, 1).await
+    };
+    let browser = Discovery::new(&browser, timeouts());
+    let (rooms, announced) = tokio::join!(browser.list_rooms(), announce);
+    announced.unwrap();
+    let rooms = rooms.unwrap();
+    assert_eq!(titles(&rooms), ["tetris"]);
+    assert_eq!(rooms[0].host_id, tetris.host_id);
+}
+
+#[tokio::test]
+async fn nobody_answers_on_an_empty_network() {
+    let _data = isolate();
+    let broker = MemoryBroker::new();
+    let lonely = broker.node();
+    let rooms = Discovery::new(&lonely, timeouts()).list_rooms().await.unwrap();
+    assert!(rooms.is_empty());
+}
+
+#[tokio::test]
+async fn contested_room_name_goes_to_one_claimant() {
+    let _data = isolate();
+    let broker = MemoryBroker::new();
+    let [a, b] = Gated::group(&broker);
+    let (disc_a, disc_b) = (Discovery::new(&a, timeouts()), Discovery::new(&b, timeouts()));
+    let (id_a, id_b) = (id(&a), id(&b));
+    let (res_a, res_b) = tokio::join!(
+        disc_a.claim_room_name("Lobby", &id_a),
+        disc_b.claim_room_name("lobby", &id_b),
+    );
+    let ((room_a, won_a), (room_b, won_b)) = (res_a.unwrap(), res_b.unwrap());
+    assert_ne!(won_a, won_b);
+    assert_ne!(room_a, room_b);
+}
@@ -0,0 +1,67 @@
+//! Nickname claims between nodes on an in-memory network.
+
+mod common;
+
+use common::{Gated, data_dir::isolate, id};
+use p2p_core::{
+    protocol::now_ms,
+    registry::{NameRegistry, suggest_nickname},
+    timeouts::Timeouts,
+};
+use transport_iroh::memory::MemoryBroker;
+
+fn timeouts() -> Timeouts {
+    Timeouts {
+        claim_wait_ms: 300,
+        ..Timeouts::default()
+    }
+}
+
+#[tokio::test]
+async fn free_name_is_granted() {
+    let _data = isolate();
+    let broker = MemoryBroker::new();
+    let a = broker.node();
+    let claimed = NameRegistry::new(&a, timeouts()).claim_unique("alice", &id(&a)).await;
+    assert_eq!(claimed.unwrap(), ("alice".to_string(), true));
+}
+
+#[tokio::test]
+async fn earlier_claim_wins_a_race() {
+    let _data = isolate();
+    let broker = MemoryBroker::new();
+    let [a, b, c] = Gated::group(&broker);
+    let (reg_a, reg_b, reg_c) = (
+        NameRegistry::new(&a, timeouts()),
+        NameRegistry::new(&b, timeouts()),
+        NameRegistry::new(&c, timeouts()),
+    );
+    let (id_a, id_b, id_c) = (id(&a), id(&b), id(&c));
+    let since = now_ms();
+    let (res_a, res_b, res_c) = tokio::join!(
+        reg_a.claim_unique_at("Alice", &id_a, since),
+        reg_b.claim_unique_at("alice", &id_b, since + 1),
+        reg_c.claim_unique_at("carol", &id_c, since + 2),
+    );
+    assert_eq!(res_a.unwrap(), ("Alice".to_string(), true));
+    let fallback = suggest_nickname("alice", &id_b);
+    assert_eq!(res_b.unwrap(), (fallback, false));
+    assert_eq!(res_c.unwrap(), ("carol".to_string(), true));
+}
+
+#[tokio::test]
+async fn tie_goes_to_the_lower_peer_id() {
+    let _data = isolate();
+    let broker = MemoryBroker::new();
+    let [a, b] = Gated::group(&broker);
+    let (reg_a, reg_b) = (NameRegistry::new(&a, timeouts()), NameRegistry::new(&b, timeouts()));
+    let (id_a, id_b) = (id(&a), id(&b));
+    let since = now_ms();
+    let (res_a, res_b) = tokio::join!(
+        reg_a.claim_unique_at("bob", &id_a, since),
+        reg_b.claim_unique_at("bob", &id_b, since),
+    );
+    let (won_a, won_b) = (res_a.unwrap().1, res_b.unwrap().1);
+    assert_ne!(won_a, won_b);
+    assert_eq!(won_a, id_a < id_b);
+}
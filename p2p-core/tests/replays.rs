@@ -0,0 +1,71 @@
+//! Recorded matches under `tests/replays/`, played again through today's
+//! game engines.
+//!
+//! Each file is a match log (`<game>.jsonl`, one [`MatchRecord`] per line)
+//! of games played with an earlier version. A rules change that makes one of
+//! them end differently would split peers on different versions in a running
+//! game; if the change is intended, bump the game's `VERSION` and record the
+//! file anew.
+
+#[path = "common/data_dir.rs"]
+mod data_dir;
+
+use data_dir::isolate;
+use p2p_core::{
+    game::GameRegistry,
+    matchlog::{MatchLog, MatchRecord},
+};
+use std::path::{Path, PathBuf};
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/replays")
+}
+
+fn records(game: &str) -> Vec<MatchRecord> {
+    let path = fixtures().join(format!("{game}.jsonl"));
+    let records = MatchLog::at(path).load().unwrap();
+    assert!(!records.is_empty(), "no recorded {game} matches");
+    records
+}
+
+/// Plugins are loaded from the data directory; load them from an empty one.
+fn registry() -> GameRegistry {
+    let _data = isolate();
+    GameRegistry::builtin()
+}
+
+fn replay_all(game: &str) {
+    let registry = registry();
+    for record in records(game) {
+        assert_eq!(record.game, game, "{} is in the wrong file", record.game_id);
+        let replay = record.replay.as_ref().expect("recorded with its moves");
+        assert_eq!(replay.outcome.as_ref(), Some(&record.outcome), "{}", record.game_id);
+        assert_eq!(replay.moves.len() as u64, record.moves, "{}", record.game_id);
+        if let Err(e) = replay.verify(&registry, game, record.players.len()) {
+            panic!("{}: {e:#}", record.game_id);
+        }
+    }
+}
+
+#[test]
+fn tictactoe() {
+    replay_all("tictactoe");
+}
+
+#[test]
+fn chess() {
+    replay_all("chess");
+}
+
+#[test]
+fn battleship() {
+    replay_all("battleship");
+}
+
+#[test]
+fn every_game_has_recordings() {
+    let registry = registry();
+    for game in registry.names() {
+        assert!(!records(game).is_empty());
+    }
+}
@@ -1 +1,2 @@
-pub mod transport_iroh;
\ No newline at end of file
+pub mod memory;
+pub mod transport_iroh;
@@ -0,0 +1,197 @@
+//! In-process gossip for tests and offline development.
+//!
+//! A [`MemoryBroker`] stands in for the network: every [`MemoryTransport`]
+//! created from the same broker is a node, and a frame published on a topic
+//! reaches every other node subscribed to it. As with iroh-gossip, a node
+//! does not receive its own frames. The neighbor count of a topic is the
+//! number of other nodes subscribed to it.
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use iroh::{PublicKey, SecretKey};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::transport_iroh::{
+    self as iroh_t, GossipTransport, NeighborCount, NodeAddr, TopicHandle, TopicId,
+};
+
+/// Frames buffered per topic; a subscriber that falls further behind skips
+/// the oldest ones, like a gossip peer that lagged.
+const TOPIC_CAPACITY: usize = 1024;
+
+type Frame = (PublicKey, Vec<u8>);
+
+struct Topic {
+    tx: broadcast::Sender<Frame>,
+    /// Subscription id → node and its neighbor counter.
+    subs: HashMap<u64, (PublicKey, NeighborCount)>,
+}
+
+impl Topic {
+    fn recount(&self) {
+        for (node, neighbors) in self.subs.values() {
+            let others: HashSet<_> = self
+                .subs
+                .values()
+                .map(|(n, _)| n)
+                .filter(|n| *n != node)
+                .collect();
+            neighbors.set(others.len());
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    topics: HashMap<TopicId, Topic>,
+    next_sub: u64,
+}
+
+/// The shared "network" of in-memory nodes; cheap to clone.
+#[derive(Clone, Default)]
+pub struct MemoryBroker(Arc<Mutex<Inner>>);
+
+impl MemoryBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new node with a random key.
+    pub fn node(&self) -> MemoryTransport {
+        let secret = SecretKey::from_bytes(&rand::random());
+        MemoryTransport {
+            addr: NodeAddr::from(secret.public()),
+            secret,
+            broker: self.clone(),
+        }
+    }
+
+    fn subscribe(&self, topic: TopicId, node: PublicKey) -> MemoryTopic {
+        let mut inner = self.0.lock().unwrap();
+        inner.next_sub += 1;
+        let sub = inner.next_sub;
+        let t = inner.topics.entry(topic).or_insert_with(|| Topic {
+            tx: broadcast::channel(TOPIC_CAPACITY).0,
+            subs: HashMap::new(),
+        });
+        let neighbors = NeighborCount::default();
+        t.subs.insert(sub, (node, neighbors.clone()));
+        t.recount();
+        MemoryTopic {
+            broker: self.clone(),
+            topic,
+            sub,
+            node,
+            tx: t.tx.clone(),
+            rx: t.tx.subscribe(),
+            neighbors,
+        }
+    }
+
+    fn unsubscribe(&self, topic: &TopicId, sub: u64) {
+        let mut inner = self.0.lock().unwrap();
+        let Some(t) = inner.topics.get_mut(topic) else {
+            return;
+        };
+        t.subs.remove(&sub);
+        if t.subs.is_empty() {
+            inner.topics.remove(topic);
+        } else {
+            t.recount();
+        }
+    }
+}
+
+/// One node on a [`MemoryBroker`].
+pub struct MemoryTransport {
+    addr: NodeAddr,
+    secret: SecretKey,
+    broker: MemoryBroker,
+}
+
+impl MemoryTransport {
+    /// Key of this node, e.g. for signing envelopes as it.
+    pub fn secret_key(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+}
+
+#[async_trait]
+impl GossipTransport for MemoryTransport {
+    fn node_addr(&self) -> &NodeAddr {
+        &self.addr
+    }
+
+    /// Every node already reaches every other one.
+    async fn connect(&self, _peer: &NodeAddr) -> Result<()> {
+        Ok(())
+    }
+
+    async fn join_topic(&self, topic: TopicId) -> Result<Box<dyn TopicHandle>> {
+        Ok(Box::new(self.broker.subscribe(topic, self.addr.node_id)))
+    }
+
+    fn topic_from_name(&self, name: &str) -> TopicId {
+        iroh_t::topic_from_name(name)
+    }
+
+    fn topic_from_hex(&self, hex: &str) -> Result<TopicId> {
+        iroh_t::topic_from_hex(hex)
+    }
+
+    fn topic_to_hex(&self, topic: &TopicId) -> String {
+        iroh_t::topic_to_hex(topic)
+    }
+
+    fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr> {
+        iroh_t::parse_node_id_addr(s)
+    }
+
+    /// Subscriptions end when their handles are dropped.
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct MemoryTopic {
+    broker: MemoryBroker,
+    topic: TopicId,
+    sub: u64,
+    node: PublicKey,
+    tx: broadcast::Sender<Frame>,
+    rx: broadcast::Receiver<Frame>,
+    neighbors: NeighborCount,
+}
+
+#[async_trait]
+impl TopicHandle for MemoryTopic {
+    async fn publish(&self, bytes: &[u8]) -> Result<()> {
+        // Our own receiver keeps the channel open, so this cannot fail.
+        let _ = self.tx.send((self.node, bytes.to_vec()));
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Result<Vec<u8>> {
+        loop {
+            match self.rx.recv().await {
+                Ok((from, bytes)) if from != self.node => return Ok(bytes),
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => bail!("topic closed"),
+            }
+        }
+    }
+
+    fn neighbors(&self) -> Option<NeighborCount> {
+        Some(self.neighbors.clone())
+    }
+}
+
+impl Drop for MemoryTopic {
+    fn drop(&mut self) {
+        self.broker.unsubscribe(&self.topic, self.sub);
+    }
+}
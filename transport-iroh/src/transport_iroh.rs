@@ -1,21 +1,58 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures_util::StreamExt;
-use iroh::{protocol::Router, Endpoint, NodeAddr, PublicKey, Watcher};
+use iroh::{
+    discovery::{mdns::{self, MdnsDiscovery}, DiscoveryEvent},
+    protocol::Router,
+    Endpoint, PublicKey, RelayMode, RelayUrl, SecretKey, Watcher,
+};
 use iroh_gossip::{
-    api::{Event, GossipTopic, Message},
+    api::{Event, GossipReceiver, GossipSender, Message},
     net::Gossip,
-    proto::TopicId,
     ALPN,
 };
-use std::{str::FromStr, sync::Arc};
-use tokio::sync::Mutex;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+pub use iroh::NodeAddr;
+pub use iroh_gossip::proto::TopicId;
+
+/// How long joining a topic waits for the nodes found on the local network,
+/// so a message published right away is not lost.
+const LOCAL_JOIN_WAIT: Duration = Duration::from_secs(3);
+
+/// Live number of direct gossip neighbors on a topic; cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct NeighborCount(Arc<AtomicUsize>);
+
+impl NeighborCount {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, n: usize) {
+        self.0.store(n, Ordering::Relaxed);
+    }
+}
 
 #[async_trait]
 pub trait TopicHandle: Send + Sync {
     async fn publish(&self, bytes: &[u8]) -> Result<()>;
     async fn next(&mut self) -> Result<Vec<u8>>;
+    /// Neighbor counter for this topic, if the transport tracks neighbors.
+    fn neighbors(&self) -> Option<NeighborCount> {
+        None
+    }
 }
 
 #[async_trait]
@@ -27,28 +64,242 @@ pub trait GossipTransport: Send + Sync {
     fn topic_from_hex(&self, hex: &str) -> Result<TopicId>;
     fn topic_to_hex(&self, topic: &TopicId) -> String;
     fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr>;
+    /// Leave all topics and close connections; the transport is unusable afterwards.
+    async fn shutdown(&self) -> Result<()>;
+}
+
+/// Topic id of a topic name (BLAKE3 of the name).
+pub fn topic_from_name(name: &str) -> TopicId {
+    let h = blake3::hash(name.as_bytes());
+    TopicId::from_bytes(*h.as_bytes())
+}
+
+pub fn topic_from_hex(hex: &str) -> Result<TopicId> {
+    let bytes = hex::decode(hex)?;
+    let arr: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow!("topic hex must decode to 32 bytes"))?;
+    Ok(TopicId::from_bytes(arr))
+}
+
+pub fn topic_to_hex(topic: &TopicId) -> String {
+    hex::encode(topic.as_bytes())
+}
+
+/// Address of a node known only by its id.
+pub fn parse_node_id_addr(s: &str) -> Result<NodeAddr> {
+    let pk = PublicKey::from_str(s)?;
+    Ok(NodeAddr::from(pk))
+}
+
+/// Shared transports, e.g. one endpoint serving several front ends.
+#[async_trait]
+impl<T: GossipTransport + ?Sized> GossipTransport for Arc<T> {
+    fn node_addr(&self) -> &NodeAddr {
+        (**self).node_addr()
+    }
+
+    async fn connect(&self, peer: &NodeAddr) -> Result<()> {
+        (**self).connect(peer).await
+    }
+
+    async fn join_topic(&self, topic: TopicId) -> Result<Box<dyn TopicHandle>> {
+        (**self).join_topic(topic).await
+    }
+
+    fn topic_from_name(&self, name: &str) -> TopicId {
+        (**self).topic_from_name(name)
+    }
+
+    fn topic_from_hex(&self, hex: &str) -> Result<TopicId> {
+        (**self).topic_from_hex(hex)
+    }
+
+    fn topic_to_hex(&self, topic: &TopicId) -> String {
+        (**self).topic_to_hex(topic)
+    }
+
+    fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr> {
+        (**self).parse_node_id_addr(s)
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        (**self).shutdown().await
+    }
+}
+
+/// Which relay servers an [`IrohTransport`] uses.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Relays {
+    /// n0's public relays.
+    #[default]
+    Default,
+    /// These relays instead of n0's, e.g. self-hosted ones.
+    Custom(Vec<String>),
+    /// None at all; only direct connections work.
+    Disabled,
+}
+
+/// How an [`IrohTransport`] reaches other nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrohOptions {
+    pub relays: Relays,
+    /// Send all traffic through the relays. The UDP sockets are bound to
+    /// loopback, so no direct path to another machine can form.
+    pub relay_only: bool,
+    /// Publish our address to n0's DNS discovery and look nodes up there.
+    pub n0_discovery: bool,
+    /// Announce ourselves over mDNS and find nodes on the same subnet, which
+    /// then bootstrap every topic we join; no relay or n0 service needed.
+    pub local_discovery: bool,
+}
+
+impl Default for IrohOptions {
+    fn default() -> Self {
+        Self {
+            relays: Relays::Default,
+            relay_only: false,
+            n0_discovery: true,
+            local_discovery: true,
+        }
+    }
+}
+
+impl IrohOptions {
+    /// Reject combinations that cannot reach anyone.
+    pub fn check(&self) -> Result<()> {
+        match &self.relays {
+            Relays::Disabled if self.relay_only => {
+                bail!("relay-only mode needs relays, but they are disabled")
+            }
+            Relays::Custom(urls) if urls.is_empty() => bail!("no relay urls given"),
+            Relays::Custom(urls) => {
+                for url in urls {
+                    url.parse::<RelayUrl>()
+                        .map_err(|e| anyhow!("relay url {url}: {e}"))?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 pub struct IrohTransport {
     endpoint: Endpoint,
     gossip: Gossip,
-    _router: Router,
+    router: Router,
     addr: NodeAddr,
+    local: Arc<Mutex<LocalPeers>>,
+    watcher: JoinHandle<()>,
+}
+
+/// Senders of the topics we are on, by handle; a handle removes its entry
+/// when dropped.
+type LocalTopics = Arc<std::sync::Mutex<BTreeMap<u64, GossipSender>>>;
+
+/// Nodes found on the local network, and the topics they are asked to join.
+#[derive(Default)]
+struct LocalPeers {
+    nodes: BTreeSet<PublicKey>,
+    topics: LocalTopics,
+    next_topic: u64,
+}
+
+impl LocalPeers {
+    /// Follow mDNS results: every node found joins the topics we are on.
+    async fn watch(local: Arc<Mutex<Self>>, endpoint: Endpoint) {
+        let mut events = endpoint.discovery_stream();
+        while let Some(ev) = events.next().await {
+            let node = match ev {
+                Ok(DiscoveryEvent::Discovered(item)) if item.provenance() == mdns::NAME => {
+                    item.node_id()
+                }
+                Ok(DiscoveryEvent::Expired(node)) => {
+                    local.lock().await.nodes.remove(&node);
+                    continue;
+                }
+                _ => continue,
+            };
+            let (topics, senders) = {
+                let mut local = local.lock().await;
+                if !local.nodes.insert(node) {
+                    continue;
+                }
+                let senders = local.topics.lock().unwrap().clone();
+                (local.topics.clone(), senders)
+            };
+            tracing::debug!("found {} on the local network", node.fmt_short());
+            // Topics that were left refuse the join and are dropped.
+            for (id, sender) in senders {
+                if sender.join_peers(vec![node]).await.is_err() {
+                    topics.lock().unwrap().remove(&id);
+                }
+            }
+        }
+    }
 }
 
 impl IrohTransport {
-    pub async fn new() -> Result<Self> {
-        let endpoint = Endpoint::builder().discovery_n0().bind().await?;
+    /// Bind with a freshly generated node key.
+    pub async fn new(options: &IrohOptions) -> Result<Self> {
+        Self::bind(Self::builder(options)?).await
+    }
+
+    /// Bind with a fixed node key so the node id survives restarts.
+    pub async fn with_secret_key(secret: [u8; 32], options: &IrohOptions) -> Result<Self> {
+        Self::bind(Self::builder(options)?.secret_key(SecretKey::from_bytes(&secret))).await
+    }
+
+    fn builder(options: &IrohOptions) -> Result<iroh::endpoint::Builder> {
+        options.check()?;
+        let mut builder = Endpoint::builder();
+        if options.n0_discovery {
+            builder = builder.discovery_n0();
+        }
+        if options.local_discovery {
+            builder = builder.add_discovery(MdnsDiscovery::builder());
+        }
+        if options.relay_only {
+            builder = builder
+                .bind_addr_v4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+                .bind_addr_v6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 0, 0, 0));
+        }
+        let mode = match &options.relays {
+            Relays::Default => RelayMode::Default,
+            Relays::Disabled => RelayMode::Disabled,
+            Relays::Custom(urls) => {
+                let urls = urls
+                    .iter()
+                    .map(|url| url.parse::<RelayUrl>())
+                    .collect::<Result<_, _>>()?;
+                RelayMode::Custom(urls)
+            }
+        };
+        Ok(builder.relay_mode(mode))
+    }
+
+    /// The node key, for persisting the identity.
+    pub fn secret_key(&self) -> [u8; 32] {
+        self.endpoint.secret_key().to_bytes()
+    }
+
+    async fn bind(builder: iroh::endpoint::Builder) -> Result<Self> {
+        let endpoint = builder.bind().await?;
         let gossip = Gossip::builder().spawn(endpoint.clone());
         let router = Router::builder(endpoint.clone())
             .accept(ALPN, gossip.clone())
             .spawn();
         let addr = endpoint.node_addr().initialized().await;
+        let local = Arc::new(Mutex::new(LocalPeers::default()));
+        let watcher = tokio::spawn(LocalPeers::watch(local.clone(), endpoint.clone()));
         Ok(Self {
             endpoint,
             gossip,
-            _router: router,
+            router,
             addr,
+            local,
+            watcher,
         })
     }
 }
@@ -65,55 +316,92 @@ impl GossipTransport for IrohTransport {
     }
 
     async fn join_topic(&self, topic: TopicId) -> Result<Box<dyn TopicHandle>> {
-        let topic = self.gossip.subscribe(topic, vec![]).await?;
+        let (sender, mut receiver, on_lan, local) = {
+            let mut local = self.local.lock().await;
+            let peers: Vec<_> = local.nodes.iter().copied().collect();
+            let on_lan = !peers.is_empty();
+            let (sender, receiver) = self.gossip.subscribe(topic, peers).await?.split();
+            let id = local.next_topic;
+            local.next_topic += 1;
+            local.topics.lock().unwrap().insert(id, sender.clone());
+            (sender, receiver, on_lan, (id, local.topics.clone()))
+        };
+        if on_lan {
+            let _ = tokio::time::timeout(LOCAL_JOIN_WAIT, receiver.joined()).await;
+        }
+        let neighbors = NeighborCount::default();
+        neighbors.set(receiver.neighbors().count());
         Ok(Box::new(IrohTopic {
-            topic: Arc::new(Mutex::new(topic)),
+            sender,
+            receiver: Arc::new(Mutex::new(receiver)),
+            neighbors,
+            local,
         }))
     }
 
     fn topic_from_name(&self, name: &str) -> TopicId {
-        let h = blake3::hash(name.as_bytes());
-        TopicId::from_bytes(*h.as_bytes())
+        topic_from_name(name)
     }
 
     fn topic_from_hex(&self, hex: &str) -> Result<TopicId> {
-        let bytes = hex::decode(hex)?;
-        let arr: [u8; 32] = bytes
-            .try_into()
-            .map_err(|_| anyhow!("topic hex must decode to 32 bytes"))?;
-        Ok(TopicId::from_bytes(arr))
+        topic_from_hex(hex)
     }
 
     fn topic_to_hex(&self, topic: &TopicId) -> String {
-        hex::encode(topic.as_bytes())
+        topic_to_hex(topic)
     }
 
     fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr> {
-        let pk = PublicKey::from_str(s)?;
-        Ok(NodeAddr::from(pk))
+        parse_node_id_addr(s)
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.watcher.abort();
+        self.router.shutdown().await?;
+        Ok(())
     }
 }
 
 struct IrohTopic {
-    topic: Arc<Mutex<GossipTopic>>,
+    sender: GossipSender,
+    receiver: Arc<Mutex<GossipReceiver>>,
+    neighbors: NeighborCount,
+    /// Our entry in [`LocalPeers::topics`].
+    local: (u64, LocalTopics),
+}
+
+impl Drop for IrohTopic {
+    fn drop(&mut self) {
+        let (id, topics) = &self.local;
+        topics.lock().unwrap().remove(id);
+    }
 }
 
 #[async_trait]
 impl TopicHandle for IrohTopic {
     async fn publish(&self, bytes: &[u8]) -> Result<()> {
-        let mut topic = self.topic.lock().await;
-        topic.broadcast(Bytes::copy_from_slice(bytes)).await?;
+        self.sender.broadcast(Bytes::copy_from_slice(bytes)).await?;
         Ok(())
     }
 
     async fn next(&mut self) -> Result<Vec<u8>> {
-        let mut topic = self.topic.lock().await;
-        while let Some(ev) = topic.next().await {
+        let mut receiver = self.receiver.lock().await;
+        while let Some(ev) = receiver.next().await {
             match ev? {
                 Event::Received(Message { content, .. }) => return Ok(content.to_vec()),
+                Event::NeighborUp(_) => {
+                    self.neighbors.set(self.neighbors.get() + 1);
+                }
+                Event::NeighborDown(_) => {
+                    self.neighbors.set(self.neighbors.get().saturating_sub(1));
+                }
                 _ => continue,
             }
         }
         Err(anyhow!("gossip topic closed"))
     }
+
+    fn neighbors(&self) -> Option<NeighborCount> {
+        Some(self.neighbors.clone())
+    }
 }
@@ -0,0 +1 @@
+pub mod transport_libp2p;
@@ -0,0 +1,428 @@
+//! Gossip over libp2p gossipsub, to take part in libp2p-based networks.
+//!
+//! The node runs a libp2p swarm on TCP with noise and yamux and the
+//! gossipsub behaviour, with messages signed by the node key. That key is
+//! our usual identity, so the node id is the same as on iroh and the libp2p
+//! peer id is derived from it ([`peer_id_of`]). A topic is the gossipsub
+//! topic named after the topic id in hex.
+//!
+//! The swarm lives in a task of its own; the transport and its topic
+//! handles talk to it through a command channel. Addresses are written
+//! `<node id>@<host:port>` or `<node id>@<multiaddr>` (see [`parse_addr`]);
+//! a node known only by its id is reached through the mesh.
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use iroh::{PublicKey, SecretKey};
+use libp2p::{
+    Multiaddr, PeerId, Swarm, SwarmBuilder,
+    gossipsub::{self, IdentTopic, MessageAuthenticity, PublishError, TopicHash},
+    identity::{self, Keypair},
+    multiaddr::Protocol,
+    noise,
+    swarm::{SwarmEvent, dial_opts::DialOpts},
+    tcp, yamux,
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc, oneshot,
+};
+use transport_iroh::transport_iroh::{
+    self as iroh_t, GossipTransport, NeighborCount, NodeAddr, TopicHandle, TopicId,
+};
+
+/// Port listened on when none is configured.
+pub const DEFAULT_PORT: u16 = 4001;
+
+/// How long listen addresses are collected when binding.
+const LISTEN_SETTLE: Duration = Duration::from_millis(200);
+
+const DIAL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Frames buffered per topic; a subscriber that falls further behind skips
+/// the oldest ones.
+const TOPIC_CAPACITY: usize = 1024;
+
+/// Connections without traffic are kept this long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The libp2p peer id of a node.
+pub fn peer_id_of(node_id: &PublicKey) -> Result<PeerId> {
+    let key = identity::ed25519::PublicKey::try_from_bytes(node_id.as_bytes())?;
+    Ok(identity::PublicKey::from(key).to_peer_id())
+}
+
+fn multiaddr_of(addr: SocketAddr) -> Multiaddr {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) => Protocol::Ip4(ip),
+        IpAddr::V6(ip) => Protocol::Ip6(ip),
+    };
+    Multiaddr::empty().with(ip).with(Protocol::Tcp(addr.port()))
+}
+
+fn socket_addr_of(addr: &Multiaddr) -> Option<SocketAddr> {
+    let mut ip = None;
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::Ip4(v4) => ip = Some(IpAddr::V4(v4)),
+            Protocol::Ip6(v6) => ip = Some(IpAddr::V6(v6)),
+            Protocol::Tcp(port) => return ip.map(|ip| SocketAddr::new(ip, port)),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `<node id>` or `<node id>@<address>[,<address>…]`, each address either
+/// `host:port` or a TCP multiaddr such as `/ip4/192.0.2.7/tcp/4001`.
+pub fn parse_addr(s: &str) -> Result<NodeAddr> {
+    let s = s.trim();
+    let (id, addrs) = s.split_once('@').unwrap_or((s, ""));
+    let node_id = PublicKey::from_str(id)?;
+    let mut direct = Vec::new();
+    for a in addrs.split(',').filter(|a| !a.is_empty()) {
+        if a.starts_with('/') {
+            let multiaddr: Multiaddr = a.parse()?;
+            direct
+                .push(socket_addr_of(&multiaddr).ok_or_else(|| anyhow!("not a TCP address: {a}"))?);
+        } else {
+            match a.parse::<SocketAddr>() {
+                Ok(addr) => direct.push(addr),
+                Err(_) => direct.extend(a.to_socket_addrs()?),
+            }
+        }
+    }
+    Ok(NodeAddr::new(node_id).with_direct_addresses(direct))
+}
+
+enum Command {
+    Dial {
+        peer: PeerId,
+        addrs: Vec<Multiaddr>,
+        done: oneshot::Sender<Result<()>>,
+    },
+    Subscribe(IdentTopic),
+    Unsubscribe(IdentTopic),
+    Publish {
+        topic: IdentTopic,
+        data: Vec<u8>,
+        done: oneshot::Sender<Result<()>>,
+    },
+    Shutdown,
+}
+
+/// A topic we are subscribed to.
+struct Local {
+    tx: broadcast::Sender<Vec<u8>>,
+    subs: usize,
+    neighbors: NeighborCount,
+}
+
+type Topics = Arc<Mutex<HashMap<TopicHash, Local>>>;
+
+fn ident_topic(topic: &TopicId) -> IdentTopic {
+    IdentTopic::new(iroh_t::topic_to_hex(topic))
+}
+
+/// Set the neighbor count of every topic we are in to the peers
+/// subscribed to it.
+fn recount(swarm: &Swarm<gossipsub::Behaviour>, topics: &Topics) {
+    let topics = topics.lock().unwrap();
+    for (hash, local) in topics.iter() {
+        let n = swarm
+            .behaviour()
+            .all_peers()
+            .filter(|(_, subscribed)| subscribed.contains(&hash))
+            .count();
+        local.neighbors.set(n);
+    }
+}
+
+/// Run the swarm until shut down.
+async fn drive(
+    mut swarm: Swarm<gossipsub::Behaviour>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    topics: Topics,
+) {
+    let mut dialing: HashMap<PeerId, Vec<oneshot::Sender<Result<()>>>> = HashMap::new();
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                None | Some(Command::Shutdown) => break,
+                Some(Command::Dial { peer, addrs, done }) => {
+                    if swarm.is_connected(&peer) {
+                        let _ = done.send(Ok(()));
+                        continue;
+                    }
+                    match swarm.dial(DialOpts::peer_id(peer).addresses(addrs).build()) {
+                        Ok(()) => dialing.entry(peer).or_default().push(done),
+                        Err(e) => {
+                            let _ = done.send(Err(anyhow!("dialing {peer}: {e}")));
+                        }
+                    }
+                }
+                Some(Command::Subscribe(topic)) => {
+                    if let Err(e) = swarm.behaviour_mut().subscribe(&topic) {
+                        tracing::warn!("gossipsub subscribe: {e:?}");
+                    }
+                    recount(&swarm, &topics);
+                }
+                Some(Command::Unsubscribe(topic)) => {
+                    let _ = swarm.behaviour_mut().unsubscribe(&topic);
+                }
+                Some(Command::Publish { topic, data, done }) => {
+                    // Like iroh-gossip, publishing with nobody around is not
+                    // an error; the message is just not delivered.
+                    let res = match swarm.behaviour_mut().publish(topic, data) {
+                        Ok(_) | Err(PublishError::InsufficientPeers) => Ok(()),
+                        Err(e) => Err(anyhow!("gossipsub publish: {e}")),
+                    };
+                    let _ = done.send(res);
+                }
+            },
+            event = swarm.select_next_some() => match event {
+                SwarmEvent::Behaviour(gossipsub::Event::Message { message, .. }) => {
+                    if let Some(local) = topics.lock().unwrap().get(&message.topic) {
+                        let _ = local.tx.send(message.data);
+                    }
+                }
+                SwarmEvent::Behaviour(
+                    gossipsub::Event::Subscribed { .. } | gossipsub::Event::Unsubscribed { .. },
+                )
+                | SwarmEvent::ConnectionClosed { .. } => recount(&swarm, &topics),
+                SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                    for done in dialing.remove(&peer_id).into_iter().flatten() {
+                        let _ = done.send(Ok(()));
+                    }
+                }
+                SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), error, .. } => {
+                    for done in dialing.remove(&peer_id).into_iter().flatten() {
+                        let _ = done.send(Err(anyhow!("dialing {peer_id}: {error}")));
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+/// A node of a gossipsub network.
+pub struct Libp2pTransport {
+    secret: SecretKey,
+    addr: NodeAddr,
+    commands: mpsc::UnboundedSender<Command>,
+    topics: Topics,
+}
+
+impl Libp2pTransport {
+    /// Listen on `listen` with a freshly generated node key.
+    pub async fn new(listen: SocketAddr) -> Result<Self> {
+        Self::with_secret_key(rand::random(), listen).await
+    }
+
+    /// Listen on `listen` with a fixed node key so the node id survives
+    /// restarts.
+    pub async fn with_secret_key(secret: [u8; 32], listen: SocketAddr) -> Result<Self> {
+        let keypair = Keypair::ed25519_from_bytes(secret)?;
+        let config = gossipsub::ConfigBuilder::default()
+            .build()
+            .map_err(|e| anyhow!("gossipsub config: {e}"))?;
+        let gossipsub =
+            gossipsub::Behaviour::new(MessageAuthenticity::Signed(keypair.clone()), config)
+                .map_err(|e| anyhow!("gossipsub: {e}"))?;
+        let mut swarm = SwarmBuilder::with_existing_identity(keypair)
+            .with_tokio()
+            .with_tcp(
+                tcp::Config::default(),
+                noise::Config::new,
+                yamux::Config::default,
+            )?
+            .with_behaviour(|_| gossipsub)?
+            .with_swarm_config(|c| c.with_idle_connection_timeout(IDLE_TIMEOUT))
+            .build();
+        swarm.listen_on(multiaddr_of(listen))?;
+        let mut direct = Vec::new();
+        loop {
+            let event = if direct.is_empty() {
+                swarm.select_next_some().await
+            } else {
+                match tokio::time::timeout(LISTEN_SETTLE, swarm.select_next_some()).await {
+                    Ok(event) => event,
+                    Err(_) => break,
+                }
+            };
+            match event {
+                SwarmEvent::NewListenAddr { address, .. } => {
+                    direct.extend(socket_addr_of(&address))
+                }
+                SwarmEvent::ListenerError { error, .. } => bail!("listening on {listen}: {error}"),
+                SwarmEvent::ListenerClosed {
+                    reason: Err(error), ..
+                } => bail!("listening on {listen}: {error}"),
+                _ => {}
+            }
+        }
+        // Loopback is no use to anyone else, unless it is all there is.
+        if direct.iter().any(|a| !a.ip().is_loopback()) {
+            direct.retain(|a| !a.ip().is_loopback());
+        }
+        let secret = SecretKey::from_bytes(&secret);
+        let (commands, rx) = mpsc::unbounded_channel();
+        let topics = Topics::default();
+        tokio::spawn(drive(swarm, rx, topics.clone()));
+        Ok(Self {
+            addr: NodeAddr::new(secret.public()).with_direct_addresses(direct),
+            secret,
+            commands,
+            topics,
+        })
+    }
+
+    /// The node key, for persisting the identity.
+    pub fn secret_key(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| anyhow!("libp2p swarm stopped"))
+    }
+}
+
+#[async_trait]
+impl GossipTransport for Libp2pTransport {
+    fn node_addr(&self) -> &NodeAddr {
+        &self.addr
+    }
+
+    /// Dial `peer` at its addresses. A peer without any is left to the mesh.
+    async fn connect(&self, peer: &NodeAddr) -> Result<()> {
+        let addrs: Vec<Multiaddr> = peer.direct_addresses().copied().map(multiaddr_of).collect();
+        if addrs.is_empty() {
+            return Ok(());
+        }
+        let (done, dialed) = oneshot::channel();
+        self.send(Command::Dial {
+            peer: peer_id_of(&peer.node_id)?,
+            addrs,
+            done,
+        })?;
+        tokio::time::timeout(DIAL_TIMEOUT, dialed)
+            .await
+            .map_err(|_| anyhow!("timed out dialing {}", peer.node_id))?
+            .map_err(|_| anyhow!("libp2p swarm stopped"))?
+    }
+
+    async fn join_topic(&self, topic: TopicId) -> Result<Box<dyn TopicHandle>> {
+        let ident = ident_topic(&topic);
+        let (rx, neighbors, first) = {
+            let mut topics = self.topics.lock().unwrap();
+            let local = topics.entry(ident.hash()).or_insert_with(|| Local {
+                tx: broadcast::channel(TOPIC_CAPACITY).0,
+                subs: 0,
+                neighbors: NeighborCount::default(),
+            });
+            local.subs += 1;
+            (
+                local.tx.subscribe(),
+                local.neighbors.clone(),
+                local.subs == 1,
+            )
+        };
+        if first {
+            self.send(Command::Subscribe(ident.clone()))?;
+        }
+        Ok(Box::new(Libp2pTopic {
+            commands: self.commands.clone(),
+            topics: self.topics.clone(),
+            topic: ident,
+            rx,
+            neighbors,
+        }))
+    }
+
+    fn topic_from_name(&self, name: &str) -> TopicId {
+        iroh_t::topic_from_name(name)
+    }
+
+    fn topic_from_hex(&self, hex: &str) -> Result<TopicId> {
+        iroh_t::topic_from_hex(hex)
+    }
+
+    fn topic_to_hex(&self, topic: &TopicId) -> String {
+        iroh_t::topic_to_hex(topic)
+    }
+
+    fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr> {
+        parse_addr(s)
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        let _ = self.commands.send(Command::Shutdown);
+        Ok(())
+    }
+}
+
+struct Libp2pTopic {
+    commands: mpsc::UnboundedSender<Command>,
+    topics: Topics,
+    topic: IdentTopic,
+    rx: broadcast::Receiver<Vec<u8>>,
+    neighbors: NeighborCount,
+}
+
+#[async_trait]
+impl TopicHandle for Libp2pTopic {
+    async fn publish(&self, bytes: &[u8]) -> Result<()> {
+        let (done, published) = oneshot::channel();
+        let command = Command::Publish {
+            topic: self.topic.clone(),
+            data: bytes.to_vec(),
+            done,
+        };
+        self.commands
+            .send(command)
+            .map_err(|_| anyhow!("libp2p swarm stopped"))?;
+        published
+            .await
+            .map_err(|_| anyhow!("libp2p swarm stopped"))?
+    }
+
+    async fn next(&mut self) -> Result<Vec<u8>> {
+        loop {
+            match self.rx.recv().await {
+                Ok(bytes) => return Ok(bytes),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => bail!("topic closed"),
+            }
+        }
+    }
+
+    fn neighbors(&self) -> Option<NeighborCount> {
+        Some(self.neighbors.clone())
+    }
+}
+
+impl Drop for Libp2pTopic {
+    fn drop(&mut self) {
+        let mut topics = self.topics.lock().unwrap();
+        let hash = self.topic.hash();
+        let Some(local) = topics.get_mut(&hash) else {
+            return;
+        };
+        local.subs -= 1;
+        if local.subs == 0 {
+            topics.remove(&hash);
+            let _ = self.commands.send(Command::Unsubscribe(self.topic.clone()));
+        }
+    }
+}
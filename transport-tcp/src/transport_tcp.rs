@@ -0,0 +1,687 @@
+//! Gossip over plain TCP, for networks that block iroh's UDP/QUIC.
+//!
+//! Every node listens on a TCP port ([`DEFAULT_PORT`] unless configured)
+//! and keeps a connection to each peer it dialed or was dialed by. Frames
+//! are length-prefixed: a big-endian `u32`, then a kind byte and the
+//! payload. A connection opens with a handshake in which each side proves
+//! it holds the key of the node id it claims by signing the other side's
+//! nonce, so node ids are the same as on iroh and envelope signatures keep
+//! working.
+//!
+//! There is no routing: a published message gets a random id and is flooded
+//! to every connected peer, which delivers it to its own subscribers and
+//! passes it on to its other peers, dropping ids it has seen before. Peers
+//! flood the addresses of the peers they know in the same way, and nodes
+//! dial the ones they did not know until they have [`MAX_PEERS`]
+//! connections, so a network joined through one bootstrap peer fills in by
+//! itself.
+//!
+//! Addresses are written `<node id>@<host:port>` (see [`parse_addr`]); a
+//! node known only by its id is reached through the peers already
+//! connected.
+
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use iroh::{PublicKey, SecretKey};
+use iroh_base::Signature;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpListener, TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    task::JoinHandle,
+};
+use transport_iroh::transport_iroh::{
+    self as iroh_t, GossipTransport, NeighborCount, NodeAddr, TopicHandle, TopicId,
+};
+
+/// Port listened on when none is configured.
+pub const DEFAULT_PORT: u16 = 7447;
+
+/// Connections a node opens by itself from addresses it heard of; peers
+/// dialing in are always accepted.
+pub const MAX_PEERS: usize = 16;
+
+/// Largest frame accepted from a peer.
+const MAX_FRAME: usize = 1 << 20;
+
+const DIAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Frames buffered per topic; a subscriber that falls further behind skips
+/// the oldest ones.
+const TOPIC_CAPACITY: usize = 1024;
+
+/// Message ids remembered to stop the flood.
+const SEEN_CAPACITY: usize = 16_384;
+
+/// Signed with the peer's nonce, so a handshake signature means nothing
+/// anywhere else.
+const HANDSHAKE_CONTEXT: &[u8] = b"p2p-games tcp handshake";
+
+const HELLO: u8 = 0;
+const PROOF: u8 = 1;
+const PEERS: u8 = 2;
+const SUBSCRIBE: u8 = 3;
+const UNSUBSCRIBE: u8 = 4;
+const MESSAGE: u8 = 5;
+
+enum Frame {
+    /// First frame both ways: who we are, the nonce to sign and the port we
+    /// listen on.
+    Hello {
+        node_id: PublicKey,
+        nonce: [u8; 32],
+        port: u16,
+    },
+    /// Signature of the other side's nonce.
+    Proof([u8; 64]),
+    /// Addresses of known nodes, as [`format_addr`] writes them.
+    Peers(Vec<String>),
+    Subscribe(TopicId),
+    Unsubscribe(TopicId),
+    Message {
+        topic: TopicId,
+        id: [u8; 16],
+        data: Vec<u8>,
+    },
+}
+
+impl Frame {
+    fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        match self {
+            Frame::Hello {
+                node_id,
+                nonce,
+                port,
+            } => {
+                body.push(HELLO);
+                body.extend_from_slice(node_id.as_bytes());
+                body.extend_from_slice(nonce);
+                body.extend_from_slice(&port.to_be_bytes());
+            }
+            Frame::Proof(sig) => {
+                body.push(PROOF);
+                body.extend_from_slice(sig);
+            }
+            Frame::Peers(peers) => {
+                body.push(PEERS);
+                body.extend(serde_json::to_vec(peers).unwrap());
+            }
+            Frame::Subscribe(topic) => {
+                body.push(SUBSCRIBE);
+                body.extend_from_slice(topic.as_bytes());
+            }
+            Frame::Unsubscribe(topic) => {
+                body.push(UNSUBSCRIBE);
+                body.extend_from_slice(topic.as_bytes());
+            }
+            Frame::Message { topic, id, data } => {
+                body.push(MESSAGE);
+                body.extend_from_slice(topic.as_bytes());
+                body.extend_from_slice(id);
+                body.extend_from_slice(data);
+            }
+        }
+        let mut frame = (body.len() as u32).to_be_bytes().to_vec();
+        frame.extend(body);
+        frame
+    }
+
+    fn decode(body: &[u8]) -> Result<Self> {
+        let (&kind, rest) = body.split_first().ok_or_else(|| anyhow!("empty frame"))?;
+        let topic = |b: &[u8]| -> Result<TopicId> {
+            let bytes: [u8; 32] = b.try_into()?;
+            Ok(TopicId::from_bytes(bytes))
+        };
+        Ok(match kind {
+            HELLO if rest.len() == 66 => Frame::Hello {
+                node_id: PublicKey::try_from(&rest[..32])?,
+                nonce: rest[32..64].try_into()?,
+                port: u16::from_be_bytes([rest[64], rest[65]]),
+            },
+            PROOF => Frame::Proof(rest.try_into()?),
+            PEERS => Frame::Peers(serde_json::from_slice(rest)?),
+            SUBSCRIBE => Frame::Subscribe(topic(rest)?),
+            UNSUBSCRIBE => Frame::Unsubscribe(topic(rest)?),
+            MESSAGE if rest.len() >= 48 => Frame::Message {
+                topic: topic(&rest[..32])?,
+                id: rest[32..48].try_into()?,
+                data: rest[48..].to_vec(),
+            },
+            _ => bail!("malformed frame of kind {kind}"),
+        })
+    }
+}
+
+async fn read_frame(r: &mut OwnedReadHalf) -> Result<Frame> {
+    let len = r.read_u32().await? as usize;
+    if len > MAX_FRAME {
+        bail!("frame of {len} bytes");
+    }
+    let mut body = vec![0; len];
+    r.read_exact(&mut body).await?;
+    Frame::decode(&body)
+}
+
+/// `<node id>` or `<node id>@<host:port>[,<host:port>…]`.
+pub fn parse_addr(s: &str) -> Result<NodeAddr> {
+    let s = s.trim();
+    let (id, addrs) = s.split_once('@').unwrap_or((s, ""));
+    let node_id = PublicKey::from_str(id)?;
+    let mut direct = Vec::new();
+    for a in addrs.split(',').filter(|a| !a.is_empty()) {
+        match a.parse::<SocketAddr>() {
+            Ok(addr) => direct.push(addr),
+            Err(_) => direct.extend(a.to_socket_addrs()?),
+        }
+    }
+    Ok(NodeAddr::new(node_id).with_direct_addresses(direct))
+}
+
+/// `addr` the way [`parse_addr`] reads it.
+pub fn format_addr(addr: &NodeAddr) -> String {
+    let direct: Vec<String> = addr.direct_addresses().map(|a| a.to_string()).collect();
+    if direct.is_empty() {
+        addr.node_id.to_string()
+    } else {
+        format!("{}@{}", addr.node_id, direct.join(","))
+    }
+}
+
+/// Address of the interface outgoing traffic leaves through. Connecting a
+/// UDP socket sends nothing; it only picks the route.
+fn outbound_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+    socket.local_addr().ok().map(|a| a.ip())
+}
+
+struct Peer {
+    /// Encoded frames for the writer task.
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    /// Topics the peer is subscribed to.
+    topics: HashSet<TopicId>,
+    reader: JoinHandle<()>,
+    /// Tells this connection from a later one to the same peer.
+    conn: u64,
+    /// Whether the node with the smaller id dialed; that connection wins
+    /// when two nodes dial each other at once.
+    dialed_by_lower: bool,
+}
+
+/// A topic we are subscribed to.
+struct Local {
+    tx: broadcast::Sender<Vec<u8>>,
+    subs: usize,
+    neighbors: NeighborCount,
+}
+
+#[derive(Default)]
+struct State {
+    peers: HashMap<PublicKey, Peer>,
+    /// Addresses nodes listen on, as heard.
+    known: HashMap<PublicKey, SocketAddr>,
+    topics: HashMap<TopicId, Local>,
+    seen: HashSet<[u8; 16]>,
+    seen_order: VecDeque<[u8; 16]>,
+    next_conn: u64,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl State {
+    /// Remember `id`; false if it was seen before.
+    fn first_sight(&mut self, id: [u8; 16]) -> bool {
+        if !self.seen.insert(id) {
+            return false;
+        }
+        self.seen_order.push_back(id);
+        if self.seen_order.len() > SEEN_CAPACITY
+            && let Some(old) = self.seen_order.pop_front()
+        {
+            self.seen.remove(&old);
+        }
+        true
+    }
+
+    fn recount(&self, topic: &TopicId) {
+        if let Some(local) = self.topics.get(topic) {
+            let n = self
+                .peers
+                .values()
+                .filter(|p| p.topics.contains(topic))
+                .count();
+            local.neighbors.set(n);
+        }
+    }
+
+    fn recount_all(&self) {
+        for topic in self.topics.keys() {
+            self.recount(topic);
+        }
+    }
+
+    /// Send `frame` to every peer but `except`.
+    fn broadcast(&self, frame: &Frame, except: Option<&PublicKey>) {
+        let bytes = frame.encode();
+        for (node_id, peer) in &self.peers {
+            if Some(node_id) != except {
+                let _ = peer.tx.send(bytes.clone());
+            }
+        }
+    }
+
+    fn peer_list(&self) -> Vec<String> {
+        self.known
+            .iter()
+            .map(|(node_id, addr)| {
+                format_addr(&NodeAddr::new(*node_id).with_direct_addresses([*addr]))
+            })
+            .collect()
+    }
+}
+
+struct Shared {
+    secret: SecretKey,
+    addr: NodeAddr,
+    port: u16,
+    state: Mutex<State>,
+}
+
+impl Shared {
+    async fn dial(self: &Arc<Self>, addr: SocketAddr) -> Result<PublicKey> {
+        let dialing = async {
+            let stream = TcpStream::connect(addr).await?;
+            self.handshake(stream, true).await
+        };
+        tokio::time::timeout(DIAL_TIMEOUT, dialing)
+            .await
+            .map_err(|_| anyhow!("timed out dialing {addr}"))?
+    }
+
+    async fn handshake(self: &Arc<Self>, stream: TcpStream, outbound: bool) -> Result<PublicKey> {
+        let remote = stream.peer_addr()?;
+        let (mut r, mut w) = stream.into_split();
+        let nonce: [u8; 32] = rand::random();
+        let hello = Frame::Hello {
+            node_id: self.addr.node_id,
+            nonce,
+            port: self.port,
+        };
+        w.write_all(&hello.encode()).await?;
+        let Frame::Hello {
+            node_id,
+            nonce: theirs,
+            port,
+        } = read_frame(&mut r).await?
+        else {
+            bail!("{remote} did not say hello");
+        };
+        if node_id == self.addr.node_id {
+            bail!("{remote} is ourselves");
+        }
+        let proof = self.secret.sign(&[HANDSHAKE_CONTEXT, &theirs].concat());
+        w.write_all(&Frame::Proof(proof.to_bytes()).encode())
+            .await?;
+        let Frame::Proof(sig) = read_frame(&mut r).await? else {
+            bail!("{remote} sent no proof");
+        };
+        node_id
+            .verify(
+                &[HANDSHAKE_CONTEXT, &nonce].concat(),
+                &Signature::from_bytes(&sig),
+            )
+            .map_err(|_| anyhow!("{remote} is not {node_id}"))?;
+        self.attach(node_id, SocketAddr::new(remote.ip(), port), outbound, r, w);
+        Ok(node_id)
+    }
+
+    /// Take a connection that passed the handshake into service.
+    fn attach(
+        self: &Arc<Self>,
+        node_id: PublicKey,
+        listens_on: SocketAddr,
+        outbound: bool,
+        r: OwnedReadHalf,
+        mut w: OwnedWriteHalf,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let dialed_by_lower = outbound == (self.addr.node_id < node_id);
+        if let Some(old) = state.peers.get(&node_id)
+            && (old.dialed_by_lower || !dialed_by_lower)
+        {
+            return;
+        }
+        let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+        tokio::spawn(async move {
+            while let Some(bytes) = rx.recv().await {
+                if w.write_all(&bytes).await.is_err() {
+                    break;
+                }
+            }
+        });
+        for topic in state.topics.keys() {
+            let _ = tx.send(Frame::Subscribe(*topic).encode());
+        }
+        state.next_conn += 1;
+        let conn = state.next_conn;
+        let shared = self.clone();
+        let reader = tokio::spawn(async move { shared.read_loop(node_id, conn, r).await });
+        let peer = Peer {
+            tx,
+            topics: HashSet::new(),
+            reader,
+            conn,
+            dialed_by_lower,
+        };
+        if let Some(old) = state.peers.insert(node_id, peer) {
+            old.reader.abort();
+            state.recount_all();
+        }
+        state.known.insert(node_id, listens_on);
+        let peers = Frame::Peers(state.peer_list());
+        state.broadcast(&peers, None);
+        tracing::debug!("tcp peer {node_id} connected");
+    }
+
+    async fn read_loop(self: Arc<Self>, node_id: PublicKey, conn: u64, mut r: OwnedReadHalf) {
+        loop {
+            let frame = match read_frame(&mut r).await {
+                Ok(frame) => frame,
+                Err(e) => {
+                    tracing::debug!("tcp peer {node_id} gone: {e:#}");
+                    break;
+                }
+            };
+            let mut state = self.state.lock().unwrap();
+            match frame {
+                Frame::Subscribe(topic) => {
+                    if let Some(peer) = state.peers.get_mut(&node_id) {
+                        peer.topics.insert(topic);
+                    }
+                    state.recount(&topic);
+                }
+                Frame::Unsubscribe(topic) => {
+                    if let Some(peer) = state.peers.get_mut(&node_id) {
+                        peer.topics.remove(&topic);
+                    }
+                    state.recount(&topic);
+                }
+                Frame::Peers(list) => {
+                    drop(state);
+                    self.discover(list);
+                }
+                Frame::Message { topic, id, data } => {
+                    if !state.first_sight(id) {
+                        continue;
+                    }
+                    if let Some(local) = state.topics.get(&topic) {
+                        let _ = local.tx.send(data.clone());
+                    }
+                    state.broadcast(&Frame::Message { topic, id, data }, Some(&node_id));
+                }
+                Frame::Hello { .. } | Frame::Proof(_) => break,
+            }
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.peers.get(&node_id).is_some_and(|p| p.conn == conn) {
+            state.peers.remove(&node_id);
+            state.recount_all();
+        }
+    }
+
+    /// Learn the addresses in a peer list and dial the nodes that are new
+    /// to us while we have room.
+    fn discover(self: &Arc<Self>, list: Vec<String>) {
+        let mut dial = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            for entry in list {
+                let Ok(addr) = entry.parse::<NodeAddrText>() else {
+                    continue;
+                };
+                let (node_id, listens_on) = (addr.0, addr.1);
+                if node_id == self.addr.node_id || state.known.contains_key(&node_id) {
+                    continue;
+                }
+                state.known.insert(node_id, listens_on);
+                if !state.peers.contains_key(&node_id) && state.peers.len() + dial.len() < MAX_PEERS
+                {
+                    dial.push(listens_on);
+                }
+            }
+        }
+        for addr in dial {
+            let shared = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = shared.dial(addr).await {
+                    tracing::debug!("could not dial {addr}: {e:#}");
+                }
+            });
+        }
+    }
+}
+
+/// One `<node id>@<ip:port>` of a peer list; no name lookups for what
+/// peers send.
+struct NodeAddrText(PublicKey, SocketAddr);
+
+impl FromStr for NodeAddrText {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (id, addr) = s
+            .split_once('@')
+            .ok_or_else(|| anyhow!("no address in {s}"))?;
+        Ok(Self(PublicKey::from_str(id)?, addr.parse()?))
+    }
+}
+
+/// A node of the TCP network.
+pub struct TcpTransport {
+    shared: Arc<Shared>,
+}
+
+impl TcpTransport {
+    /// Listen on `listen` with a freshly generated node key.
+    pub async fn new(listen: SocketAddr) -> Result<Self> {
+        Self::with_secret_key(rand::random(), listen).await
+    }
+
+    /// Listen on `listen` with a fixed node key so the node id survives
+    /// restarts. If the port is taken (e.g. by another of our processes), a
+    /// random one is used.
+    pub async fn with_secret_key(secret: [u8; 32], listen: SocketAddr) -> Result<Self> {
+        let secret = SecretKey::from_bytes(&secret);
+        let listener = match TcpListener::bind(listen).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::warn!("cannot listen on {listen}: {e}; using a random port");
+                TcpListener::bind(SocketAddr::new(listen.ip(), 0)).await?
+            }
+        };
+        let local = listener.local_addr()?;
+        let advertised = match local.ip() {
+            ip if ip.is_unspecified() => SocketAddr::new(outbound_ip().unwrap_or(ip), local.port()),
+            _ => local,
+        };
+        let shared = Arc::new(Shared {
+            addr: NodeAddr::new(secret.public()).with_direct_addresses([advertised]),
+            secret,
+            port: local.port(),
+            state: Mutex::default(),
+        });
+        let accepting = shared.clone();
+        let listener = tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        tracing::debug!("tcp accept failed: {e}");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let shared = accepting.clone();
+                tokio::spawn(async move {
+                    let accepted =
+                        tokio::time::timeout(DIAL_TIMEOUT, shared.handshake(stream, false));
+                    if let Ok(Err(e)) = accepted.await {
+                        tracing::debug!("tcp handshake failed: {e:#}");
+                    }
+                });
+            }
+        });
+        shared.state.lock().unwrap().listener = Some(listener);
+        Ok(Self { shared })
+    }
+
+    /// The node key, for persisting the identity.
+    pub fn secret_key(&self) -> [u8; 32] {
+        self.shared.secret.to_bytes()
+    }
+}
+
+#[async_trait]
+impl GossipTransport for TcpTransport {
+    fn node_addr(&self) -> &NodeAddr {
+        &self.shared.addr
+    }
+
+    /// Dial `peer` at its addresses, or at the one heard of. A peer without
+    /// a known address is left to the flood through the peers we have.
+    async fn connect(&self, peer: &NodeAddr) -> Result<()> {
+        let mut addrs: Vec<SocketAddr> = peer.direct_addresses().copied().collect();
+        {
+            let state = self.shared.state.lock().unwrap();
+            if state.peers.contains_key(&peer.node_id) {
+                return Ok(());
+            }
+            addrs.extend(state.known.get(&peer.node_id));
+        }
+        let mut error = None;
+        for addr in addrs {
+            match self.shared.dial(addr).await {
+                Ok(node_id) if node_id == peer.node_id => return Ok(()),
+                Ok(node_id) => error = Some(anyhow!("{addr} is {node_id}, not {}", peer.node_id)),
+                Err(e) => error = Some(e),
+            }
+        }
+        error.map_or(Ok(()), Err)
+    }
+
+    async fn join_topic(&self, topic: TopicId) -> Result<Box<dyn TopicHandle>> {
+        let mut state = self.shared.state.lock().unwrap();
+        let local = state.topics.entry(topic).or_insert_with(|| Local {
+            tx: broadcast::channel(TOPIC_CAPACITY).0,
+            subs: 0,
+            neighbors: NeighborCount::default(),
+        });
+        local.subs += 1;
+        let (rx, neighbors, first) = (
+            local.tx.subscribe(),
+            local.neighbors.clone(),
+            local.subs == 1,
+        );
+        if first {
+            state.broadcast(&Frame::Subscribe(topic), None);
+        }
+        state.recount(&topic);
+        Ok(Box::new(TcpTopic {
+            shared: self.shared.clone(),
+            topic,
+            rx,
+            neighbors,
+        }))
+    }
+
+    fn topic_from_name(&self, name: &str) -> TopicId {
+        iroh_t::topic_from_name(name)
+    }
+
+    fn topic_from_hex(&self, hex: &str) -> Result<TopicId> {
+        iroh_t::topic_from_hex(hex)
+    }
+
+    fn topic_to_hex(&self, topic: &TopicId) -> String {
+        iroh_t::topic_to_hex(topic)
+    }
+
+    fn parse_node_id_addr(&self, s: &str) -> Result<NodeAddr> {
+        parse_addr(s)
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(listener) = state.listener.take() {
+            listener.abort();
+        }
+        for (_, peer) in state.peers.drain() {
+            peer.reader.abort();
+        }
+        Ok(())
+    }
+}
+
+struct TcpTopic {
+    shared: Arc<Shared>,
+    topic: TopicId,
+    rx: broadcast::Receiver<Vec<u8>>,
+    neighbors: NeighborCount,
+}
+
+#[async_trait]
+impl TopicHandle for TcpTopic {
+    async fn publish(&self, bytes: &[u8]) -> Result<()> {
+        let id: [u8; 16] = rand::random();
+        let mut state = self.shared.state.lock().unwrap();
+        state.first_sight(id);
+        let frame = Frame::Message {
+            topic: self.topic,
+            id,
+            data: bytes.to_vec(),
+        };
+        state.broadcast(&frame, None);
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Result<Vec<u8>> {
+        loop {
+            match self.rx.recv().await {
+                Ok(bytes) => return Ok(bytes),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => bail!("topic closed"),
+            }
+        }
+    }
+
+    fn neighbors(&self) -> Option<NeighborCount> {
+        Some(self.neighbors.clone())
+    }
+}
+
+impl Drop for TcpTopic {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        let Some(local) = state.topics.get_mut(&self.topic) else {
+            return;
+        };
+        local.subs -= 1;
+        if local.subs == 0 {
+            state.topics.remove(&self.topic);
+            state.broadcast(&Frame::Unsubscribe(self.topic), None);
+        }
+    }
+}